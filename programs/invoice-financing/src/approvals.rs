@@ -0,0 +1,179 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::Discriminator;
+
+use crate::governance::{authorize, AuthorityPath, Lane};
+use crate::{instruction, ErrorCode, GlobalState};
+
+// An optional M-of-N approver set in front of the most sensitive authority
+// instructions: config updates, pausing, insurance pool withdrawals, oracle
+// rotation, recovery of stray funds, payout custody, destination registration,
+// unclaimed repayment sweeps and changes to the set itself. With no approvers
+// they run on the authority keypair alone, as before. Once a set is configured
+// the keypair can no longer run them directly. An approver proposes the exact
+// instruction, approvers sign off until the threshold is met within the approval
+// window, and execute_admin_action replays it through a self-CPI signed by the
+// approvals PDA ["admin_approvals"], which the guarded instruction then accepts
+// as the authority. The governance account keeps its own path: once governance is
+// enabled, governed instructions still require it.
+#[constant]
+pub const APPROVALS_SEED: &[u8] = b"admin_approvals";
+
+pub const MAX_ADMIN_APPROVERS: usize = 5;
+
+// Longest a proposal may wait for approvals
+pub const MAX_APPROVAL_WINDOW_SECS: i64 = 30 * 86_400;
+
+// Bounds on a proposed instruction; the data includes its discriminator.
+// configure_payout_processor takes the most accounts.
+pub const MAX_ADMIN_ACTION_ACCOUNTS: usize = 12;
+pub const MAX_ADMIN_ACTION_DATA_LEN: usize = 256;
+
+// update_config and set_admin_approvers carry the largest arguments
+const _: () = assert!(8 + crate::ProtocolConfig::SIZE <= MAX_ADMIN_ACTION_DATA_LEN);
+const _: () = assert!(8 + (4 + 32 * MAX_ADMIN_APPROVERS) + 1 + 8 <= MAX_ADMIN_ACTION_DATA_LEN);
+
+// Instructions the approver set guards, by discriminator
+pub const GUARDED_INSTRUCTIONS: [[u8; 8]; 13] = [
+    instruction::UpdateConfig::DISCRIMINATOR,
+    instruction::Pause::DISCRIMINATOR,
+    instruction::Unpause::DISCRIMINATOR,
+    instruction::ProposePoolWithdrawal::DISCRIMINATOR,
+    instruction::ExecutePoolWithdrawal::DISCRIMINATOR,
+    instruction::SetOracleAuthority::DISCRIMINATOR,
+    instruction::SetAdminApprovers::DISCRIMINATOR,
+    instruction::RecoverForeignTokens::DISCRIMINATOR,
+    instruction::RecoverExcessLamports::DISCRIMINATOR,
+    instruction::ConfigurePayoutProcessor::DISCRIMINATOR,
+    instruction::ApplyPayoutCustody::DISCRIMINATOR,
+    instruction::RegisterDestination::DISCRIMINATOR,
+    instruction::SweepUnclaimedRepayment::DISCRIMINATOR,
+];
+
+// One account of a proposed instruction, as the self-CPI passes it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct AdminAccountMeta {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl From<&AdminAccountMeta> for AccountMeta {
+    fn from(meta: &AdminAccountMeta) -> Self {
+        AccountMeta { pubkey: meta.pubkey, is_signer: meta.is_signer, is_writable: meta.is_writable }
+    }
+}
+
+pub fn approvals_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[APPROVALS_SEED], &crate::ID)
+}
+
+// Handler check for a guarded instruction: `authorize`, and once an approver set
+// is configured, the keypair acting alone is refused
+pub fn authorize_guarded(state: &Account<GlobalState>, signer: &AccountInfo, lane: Lane) -> Result<AuthorityPath> {
+    let path = authorize(state, signer, lane)?;
+    if state.approvers_configured() {
+        require!(path != AuthorityPath::Direct, ErrorCode::ApprovalRequired);
+    }
+    Ok(path)
+}
+
+// Whether `data` calls one of the guarded instructions
+pub fn is_guarded(data: &[u8]) -> bool {
+    data.len() >= 8 && GUARDED_INSTRUCTIONS.iter().any(|discriminator| data[..8] == discriminator[..])
+}
+
+// An approver set is distinct keys with a threshold they can meet, or empty with a
+// zero threshold to hand the guarded instructions back to the authority
+pub fn is_valid_approver_set(approvers: &[Pubkey], threshold: u8, window_secs: i64) -> bool {
+    if approvers.is_empty() {
+        return threshold == 0;
+    }
+    let distinct = approvers.iter().enumerate().all(|(index, approver)| !approvers[..index].contains(approver));
+    distinct
+        && approvers.len() <= MAX_ADMIN_APPROVERS
+        && (1..=approvers.len()).contains(&(threshold as usize))
+        && (1..=MAX_APPROVAL_WINDOW_SECS).contains(&window_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approver_sets_need_distinct_keys_and_a_reachable_threshold() {
+        let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let day = 86_400;
+
+        assert!(is_valid_approver_set(&[], 0, 0));
+        assert!(!is_valid_approver_set(&[], 1, day));
+        assert!(is_valid_approver_set(&[a, b, c], 2, day));
+        assert!(is_valid_approver_set(&[a, b, c], 3, MAX_APPROVAL_WINDOW_SECS));
+        assert!(!is_valid_approver_set(&[a, b, c], 0, day));
+        assert!(!is_valid_approver_set(&[a, b, c], 4, day));
+        assert!(!is_valid_approver_set(&[a, b, a], 2, day));
+        assert!(!is_valid_approver_set(&[a, b], 1, 0));
+        assert!(!is_valid_approver_set(&[a, b], 1, MAX_APPROVAL_WINDOW_SECS + 1));
+
+        let six: Vec<Pubkey> = (0..MAX_ADMIN_APPROVERS + 1).map(|_| Pubkey::new_unique()).collect();
+        assert!(!is_valid_approver_set(&six, 2, day));
+    }
+
+    #[test]
+    fn only_the_guarded_instructions_can_be_proposed() {
+        assert!(is_guarded(&instruction::Pause::DISCRIMINATOR));
+        assert!(is_guarded(&[&instruction::UpdateConfig::DISCRIMINATOR[..], &[0; 16]].concat()));
+        assert!(is_guarded(&instruction::ConfigurePayoutProcessor::DISCRIMINATOR));
+        assert!(is_guarded(&instruction::ApplyPayoutCustody::DISCRIMINATOR));
+        assert!(is_guarded(&instruction::RegisterDestination::DISCRIMINATOR));
+        assert!(is_guarded(&instruction::SweepUnclaimedRepayment::DISCRIMINATOR));
+        assert!(!is_guarded(&instruction::RequestDestinationRemoval::DISCRIMINATOR));
+        assert!(!is_guarded(&instruction::SetRetentionPeriod::DISCRIMINATOR));
+        assert!(!is_guarded(&instruction::CancelPoolWithdrawal::DISCRIMINATOR));
+        assert!(!is_guarded(&instruction::Pause::DISCRIMINATOR[..7]));
+        assert!(!is_guarded(&[]));
+    }
+
+    #[test]
+    fn the_largest_guarded_instructions_fit_a_proposal() {
+        use anchor_lang::ToAccountMetas;
+
+        let key = Pubkey::new_unique();
+        let configure = crate::accounts::ConfigurePayoutProcessor {
+            payout_processor: key,
+            outbox_escrow: key,
+            outbox_authority: key,
+            custody: key,
+            allowlist: key,
+            usdc_mint: key,
+            global_state: key,
+            admin_log: key,
+            authority: key,
+            payer: key,
+            token_program: key,
+            system_program: key,
+        };
+        let recover = crate::accounts::RecoverForeignTokens {
+            global_state: key,
+            admin_log: key,
+            source: key,
+            destination: key,
+            allowlist: key,
+            owner: key,
+            payout_processor: Some(key),
+            investor_balance: Some(key),
+            invoice: Some(key),
+            authority: key,
+            token_program: key,
+        };
+        assert!(configure.to_account_metas(None).len() <= MAX_ADMIN_ACTION_ACCOUNTS);
+        assert!(recover.to_account_metas(None).len() <= MAX_ADMIN_ACTION_ACCOUNTS);
+    }
+
+    #[test]
+    fn approvals_address_is_a_program_pda() {
+        let (address, bump) = approvals_address();
+        assert!(!address.is_on_curve());
+        assert_eq!(Pubkey::create_program_address(&[APPROVALS_SEED, &[bump]], &crate::ID).unwrap(), address);
+    }
+}
@@ -0,0 +1,92 @@
+use anchor_lang::prelude::*;
+
+use crate::{BlacklistEntry, ErrorCode};
+
+// A wallet barred from new business after a confirmed case has an entry at
+// ["blacklist", wallet]. Nothing lives there until the wallet is first
+// blacklisted, so every instruction that starts something new takes the address
+// unchecked and reads whatever is there; a party cannot leave it out.
+#[constant]
+pub const BLACKLIST_SEED: &[u8] = b"blacklist";
+
+// What the party is barred from: listing invoices as a business, or funding and
+// buying positions as an investor
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum BlacklistRole {
+    Business,
+    Investor,
+}
+
+// Why a party was blacklisted or cleared, for the audit trail. Same stability rule
+// as the listing rejection reasons: append new ones at the end.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default, InitSpace)]
+pub enum BlacklistReason {
+    #[default]
+    ConfirmedFraud,
+    SanctionsMatch,
+    FailedVerification,
+    AbusiveActivity,
+    // Clearing
+    AppealUpheld,
+    BlacklistedInError,
+}
+
+impl BlacklistReason {
+    // Reasons for lifting a blacklisting; the rest are for imposing one
+    pub fn is_clearing(self) -> bool {
+        matches!(self, Self::AppealUpheld | Self::BlacklistedInError)
+    }
+}
+
+// Refuse a party whose entry bars it from `role`; `entry` is the account at the
+// party's blacklist address, empty unless it was ever blacklisted
+pub fn require_not_blacklisted(entry: &AccountInfo, role: BlacklistRole) -> Result<()> {
+    if entry.owner != &crate::ID || entry.data_is_empty() {
+        return Ok(());
+    }
+    let entry = BlacklistEntry::try_deserialize(&mut &entry.try_borrow_data()?[..])?;
+    require!(!entry.bars(role), ErrorCode::PartyBlacklisted);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_barred_role_is_refused() {
+        let key = crate::pda::find_blacklist_address(&Pubkey::new_unique()).0;
+        let check = |owner: &Pubkey, data: &mut [u8], role| {
+            let mut lamports = 1_000_000;
+            let info = AccountInfo::new(&key, false, false, &mut lamports, data, owner, false, 0);
+            require_not_blacklisted(&info, role)
+        };
+
+        // Never blacklisted: the address holds nothing
+        assert!(check(&anchor_lang::system_program::ID, &mut [], BlacklistRole::Investor).is_ok());
+
+        let mut entry = BlacklistEntry { investor: true, ..BlacklistEntry::default() };
+        let mut data = Vec::new();
+        entry.try_serialize(&mut data).unwrap();
+        assert_eq!(
+            check(&crate::ID, &mut data, BlacklistRole::Investor).unwrap_err(),
+            error!(ErrorCode::PartyBlacklisted)
+        );
+        assert!(check(&crate::ID, &mut data, BlacklistRole::Business).is_ok());
+
+        // Cleared, the entry stays behind as a record and bars nothing
+        entry.set(BlacklistRole::Investor, false);
+        let mut data = Vec::new();
+        entry.try_serialize(&mut data).unwrap();
+        assert!(check(&crate::ID, &mut data, BlacklistRole::Investor).is_ok());
+    }
+
+    #[test]
+    fn codes_are_stable() {
+        assert_eq!(BlacklistRole::Investor.try_to_vec().unwrap(), vec![1]);
+        assert_eq!(BlacklistReason::AbusiveActivity.try_to_vec().unwrap(), vec![3]);
+        assert_eq!(BlacklistReason::BlacklistedInError.try_to_vec().unwrap(), vec![5]);
+        assert!(BlacklistReason::AppealUpheld.is_clearing());
+        assert!(!BlacklistReason::ConfirmedFraud.is_clearing());
+    }
+}
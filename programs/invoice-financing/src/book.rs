@@ -0,0 +1,198 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+
+use crate::schemas::BookLeaf;
+
+// The book commitment is the root of a fixed-depth binary merkle tree over book
+// leaves (schemas::BookLeaf) in nonce order, empty positions padded with the
+// root of an empty subtree. The crank only keeps the frontier, the last left
+// sibling at each level, so pages can be folded in one at a time.
+//
+// Leaves and nodes hash with distinct prefixes, so an inner node can never be
+// passed off as a leaf:
+//
+//   leaf = sha256(0x00 || leaf bytes)
+//   node = sha256(0x01 || left || right)
+pub const BOOK_TREE_DEPTH: usize = 20;
+pub const BOOK_MAX_LEAVES: u64 = 1 << BOOK_TREE_DEPTH;
+
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+pub fn leaf_hash(leaf: &BookLeaf) -> [u8; 32] {
+    hashv(&[LEAF_PREFIX, &leaf.to_bytes()]).to_bytes()
+}
+
+pub fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    hashv(&[NODE_PREFIX, left, right]).to_bytes()
+}
+
+// Root of an empty subtree of each height
+fn empty_roots() -> [[u8; 32]; BOOK_TREE_DEPTH] {
+    let mut roots = [[0u8; 32]; BOOK_TREE_DEPTH];
+    for height in 1..BOOK_TREE_DEPTH {
+        roots[height] = node_hash(&roots[height - 1], &roots[height - 1]);
+    }
+    roots
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct BookFrontier {
+    pub leaf_count: u64,
+    pub branches: [[u8; 32]; BOOK_TREE_DEPTH],
+}
+
+impl Default for BookFrontier {
+    fn default() -> Self {
+        Self { leaf_count: 0, branches: [[0u8; 32]; BOOK_TREE_DEPTH] }
+    }
+}
+
+impl BookFrontier {
+    pub const SIZE: usize = 8 + 32 * BOOK_TREE_DEPTH;
+
+    pub fn append(&mut self, leaf: [u8; 32]) -> Result<()> {
+        require!(self.leaf_count < BOOK_MAX_LEAVES, crate::ErrorCode::BookFull);
+        let mut node = leaf;
+        let mut index = self.leaf_count;
+        for branch in self.branches.iter_mut() {
+            if index & 1 == 0 {
+                *branch = node;
+                break;
+            }
+            node = node_hash(branch, &node);
+            index >>= 1;
+        }
+        self.leaf_count += 1;
+        Ok(())
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        let empty = empty_roots();
+        let mut node = [0u8; 32];
+        let mut size = self.leaf_count;
+        for (branch, empty) in self.branches.iter().zip(empty.iter()) {
+            node = if size & 1 == 1 {
+                node_hash(branch, &node)
+            } else {
+                node_hash(&node, empty)
+            };
+            size >>= 1;
+        }
+        node
+    }
+}
+
+// Position of a leaf and its sibling at each level, bottom up
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct BookProof {
+    pub index: u64,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+// Check that `leaf` sits at `proof.index` under a committed root
+#[cfg(any(test, feature = "client"))]
+pub fn verify_book_proof(root: &[u8; 32], leaf: &BookLeaf, proof: &BookProof) -> bool {
+    if proof.siblings.len() != BOOK_TREE_DEPTH || proof.index >= BOOK_MAX_LEAVES {
+        return false;
+    }
+    let mut node = leaf_hash(leaf);
+    for (height, sibling) in proof.siblings.iter().enumerate() {
+        node = if (proof.index >> height) & 1 == 0 {
+            node_hash(&node, sibling)
+        } else {
+            node_hash(sibling, &node)
+        };
+    }
+    node == *root
+}
+
+// Whole tree rebuilt from the leaves of a commitment, for issuing proofs
+#[cfg(any(test, feature = "client"))]
+pub struct BookTree {
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+#[cfg(any(test, feature = "client"))]
+impl BookTree {
+    pub fn new(leaves: &[BookLeaf]) -> Self {
+        let empty = empty_roots();
+        let mut levels = vec![leaves.iter().map(leaf_hash).collect::<Vec<_>>()];
+        for height in 0..BOOK_TREE_DEPTH {
+            let below = &levels[height];
+            let level = below
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], pair.get(1).unwrap_or(&empty[height])))
+                .collect();
+            levels.push(level);
+        }
+        Self { levels }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.levels[BOOK_TREE_DEPTH].first().copied().unwrap_or_else(|| BookFrontier::default().root())
+    }
+
+    pub fn proof(&self, index: u64) -> BookProof {
+        let empty = empty_roots();
+        let siblings = (0..BOOK_TREE_DEPTH)
+            .map(|height| {
+                let sibling = ((index >> height) ^ 1) as usize;
+                self.levels[height].get(sibling).copied().unwrap_or(empty[height])
+            })
+            .collect();
+        BookProof { index, siblings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InvoiceStatus;
+
+    fn leaf(nonce: u64) -> BookLeaf {
+        BookLeaf {
+            nonce,
+            invoice: Pubkey::new_unique(),
+            business_owner: Pubkey::new_unique(),
+            investor: Pubkey::default(),
+            status: InvoiceStatus::PendingFunding,
+            amount: nonce * 1_000_000,
+            funded_amount: 0,
+            due_date: 1_702_000_000,
+            remaining_balance: 0,
+            amount_repaid: 0,
+            funding_date: None,
+            repayment_date: None,
+            final_repayment_amount: None,
+            insurance_payout: None,
+            defaulted_at: None,
+        }
+    }
+
+    #[test]
+    fn frontier_root_matches_the_full_tree() {
+        let leaves: Vec<BookLeaf> = (1..=7).map(leaf).collect();
+        let mut frontier = BookFrontier::default();
+        assert_eq!(frontier.root(), BookTree::new(&[]).root());
+
+        for (count, next) in leaves.iter().enumerate() {
+            frontier.append(leaf_hash(next)).unwrap();
+            assert_eq!(frontier.root(), BookTree::new(&leaves[..=count]).root());
+        }
+    }
+
+    #[test]
+    fn proofs_bind_leaf_and_position() {
+        let leaves: Vec<BookLeaf> = (1..=5).map(leaf).collect();
+        let tree = BookTree::new(&leaves);
+        let root = tree.root();
+
+        assert!(verify_book_proof(&root, &leaves[3], &tree.proof(3)));
+        assert!(!verify_book_proof(&root, &leaves[3], &tree.proof(2)));
+        assert!(!verify_book_proof(&root, &leaf(4), &tree.proof(3)));
+        let mut truncated = tree.proof(3);
+        truncated.siblings.pop();
+        assert!(!verify_book_proof(&root, &leaves[3], &truncated));
+    }
+}
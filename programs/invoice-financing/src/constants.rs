@@ -1,4 +1,158 @@
 use anchor_lang::prelude::*;
 
+use crate::{BusinessProfile, Debtor, GlobalState, InvestorBalance, InvestorStats, Invoice, PositionListing};
+
+// Seeds, limits and account sizes clients derive addresses and validate input
+// with. Everything here is #[constant], so it is in the IDL as well; seeds that
+// belong to a module stay there and are re-exported below. Anchor leaves usize
+// constants out of the IDL, so sizes and lengths are given as u64 or u32.
+
+// Protocol singletons
+#[constant]
+pub const GLOBAL_STATE_SEED: &[u8] = b"global_state";
+#[constant]
+pub const ADMIN_LOG_SEED: &[u8] = b"admin_log";
+#[constant]
+pub const PARAM_HISTORY_SEED: &[u8] = b"param_history";
+#[constant]
+pub const MICRO_TIER_SEED: &[u8] = b"micro_tier";
+#[constant]
+pub const CRANK_TREASURY_SEED: &[u8] = b"crank_treasury";
+// Owns the token accounts registered on the treasury allowlist
+#[constant]
+pub const TREASURY_AUTHORITY_SEED: &[u8] = b"treasury_authority";
+#[constant]
+pub const BOOK_COMMITMENT_SEED: &[u8] = b"book_commitment";
+#[constant]
+pub const EXPERIMENT_SEED: &[u8] = b"experiment";
+#[constant]
+pub const ALLOWLIST_SEED: &[u8] = b"allowlist";
+#[constant]
+pub const ADMIN_PROPOSAL_SEED: &[u8] = b"admin_proposal";
+// Signs the self-CPIs events go out through with cpi-events; #[event_cpi] fixes it
+#[constant]
+pub const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+
+// Insurance pool: the USDC vault, the PDA that owns it, LP positions and the
+// pending surplus withdrawal
+#[constant]
+pub const INSURANCE_POOL_SEED: &[u8] = b"insurance_pool";
+#[constant]
+pub const INSURANCE_POOL_AUTHORITY_SEED: &[u8] = b"insurance_pool_authority";
+#[constant]
+pub const INSURANCE_LP_SEED: &[u8] = b"insurance_lp";
+#[constant]
+pub const POOL_WITHDRAWAL_SEED: &[u8] = b"pool_withdrawal";
+
+// Off-ramp payouts
+#[constant]
+pub const PAYOUT_PROCESSOR_SEED: &[u8] = b"payout_processor";
+#[constant]
+pub const OUTBOX_SEED: &[u8] = b"outbox";
+#[constant]
+pub const OUTBOX_ESCROW_SEED: &[u8] = b"outbox_escrow";
+#[constant]
+pub const OUTBOX_AUTHORITY_SEED: &[u8] = b"outbox_authority";
+
+// Invoice PDAs are [INVOICE_SEED, business_owner, invoice_id (u64 LE)], with ids
+// counted per business in its [BUSINESS_PROFILE_SEED, business_owner] profile
+#[constant]
+pub const INVOICE_SEED: &[u8] = b"invoice";
+#[constant]
+pub const BUSINESS_PROFILE_SEED: &[u8] = b"business_profile";
+#[constant]
+pub const INVOICE_VAULT_SEED: &[u8] = b"invoice_vault";
+#[constant]
+pub const INVOICE_AUDIT_SEED: &[u8] = b"invoice_audit";
+#[constant]
+pub const FUNDING_SHARE_SEED: &[u8] = b"funding_share";
+#[constant]
+pub const POSITION_LISTING_SEED: &[u8] = b"position_listing";
+#[constant]
+pub const BUNDLE_SEED: &[u8] = b"bundle";
+#[constant]
+pub const DEBTOR_SEED: &[u8] = b"debtor";
+#[constant]
+pub const COLLECTIONS_SEED: &[u8] = b"collections";
+#[constant]
+pub const COLLECTIONS_AGENCY_SEED: &[u8] = b"collections_agency";
+#[constant]
+pub const ORIGINATOR_STATS_SEED: &[u8] = b"originator_stats";
+
+// Per-investor accounts; the whitelist entry predates the naming scheme
+#[constant]
+pub const INVESTOR_WHITELIST_SEED: &[u8] = b"investor";
+#[constant]
+pub const INVESTOR_STATS_SEED: &[u8] = b"investor_stats";
+#[constant]
+pub const INVESTOR_BALANCE_SEED: &[u8] = b"investor_balance";
+#[constant]
+pub const INVESTOR_CUSTODY_SEED: &[u8] = b"investor_custody";
+#[constant]
+pub const PORTFOLIO_SEED: &[u8] = b"portfolio";
+#[constant]
+pub const PAIR_LEDGER_SEED: &[u8] = b"pair_ledger";
+#[constant]
+pub const AUTO_INVEST_MANDATE_SEED: &[u8] = b"auto_invest_mandate";
+
+pub use crate::approvals::APPROVALS_SEED;
+pub use crate::blacklist::BLACKLIST_SEED;
+pub use crate::pool::{INVESTMENT_POOL_SEED, POOL_AUTHORITY_SEED, POOL_POSITION_SEED, POOL_SHARES_SEED};
+pub use crate::receipt::RECEIPT_SEED;
+
+// Invoices commit to their document by sha256 and point at an encrypted copy of it
+// off-chain; the pointer is at most this long
+#[constant]
+pub const MAX_DEBTOR_INFO_URI_LEN: u32 = 64;
+
+// Economic parameters a fresh deployment starts with; update_config changes them.
+// Funded invoices may be repaid until the grace period after their due date, then
+// default; late fees run at 0.05% of outstanding principal per day.
+#[constant]
+pub const DEFAULT_MAX_INVOICE_AMOUNT: u64 = 10_000_000_000; // 10k USDC
+#[constant]
+pub const DEFAULT_MAX_TERM_DAYS: u16 = 365;
+#[constant]
+pub const DEFAULT_GRACE_PERIOD_DAYS: u16 = 30;
+#[constant]
+pub const DEFAULT_LATE_FEE_BPS_PER_DAY: u16 = 5;
+// Least premium an insured invoice pays, changed by set_min_insurance_premium
+#[constant]
+pub const DEFAULT_MIN_INSURANCE_PREMIUM: u64 = 100_000; // 0.10 USDC
+
+// Bounds update_config and set_min_insurance_premium accept
+#[constant]
+pub const MAX_CONFIG_INVOICE_AMOUNT: u64 = 1_000_000_000_000; // 1M USDC
+#[constant]
+pub const MAX_CONFIG_TERM_DAYS: u16 = 730;
+#[constant]
+pub const MAX_CONFIG_GRACE_PERIOD_DAYS: u16 = 180;
+#[constant]
+pub const MAX_CONFIG_LATE_FEE_BPS_PER_DAY: u16 = 100;
+#[constant]
+pub const MAX_MIN_INSURANCE_PREMIUM: u64 = 100_000_000; // 100 USDC
+// Bound set_escrow_compensation accepts: at most 0.1% of the principal a day
+#[constant]
+pub const MAX_ESCROW_COMPENSATION_BPS_PER_DAY: u16 = 10;
+
+// Share of face value a factored invoice may be advanced against, in bps
+#[constant]
+pub const MIN_ADVANCE_RATE_BPS: u16 = 5_000;
+#[constant]
+pub const MAX_ADVANCE_RATE_BPS: u16 = 9_900;
+
+// Allocated sizes, discriminator included, for rent estimates and dataSize filters
+#[constant]
+pub const GLOBAL_STATE_SIZE: u64 = GlobalState::SIZE as u64;
+#[constant]
+pub const INVOICE_SIZE: u64 = Invoice::SIZE as u64;
+#[constant]
+pub const BUSINESS_PROFILE_SIZE: u64 = BusinessProfile::SIZE as u64;
+#[constant]
+pub const DEBTOR_SIZE: u64 = Debtor::SIZE as u64;
+#[constant]
+pub const INVESTOR_STATS_SIZE: u64 = InvestorStats::SIZE as u64;
+#[constant]
+pub const INVESTOR_BALANCE_SIZE: u64 = InvestorBalance::SIZE as u64;
 #[constant]
-pub const SEED: &str = "anchor";
+pub const POSITION_LISTING_SIZE: u64 = PositionListing::SIZE as u64;
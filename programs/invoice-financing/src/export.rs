@@ -0,0 +1,244 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::MAX_RETURN_DATA;
+
+use crate::constants::INVOICE_AUDIT_SEED;
+use crate::pda::find_invoice_address;
+use crate::{load_invoice, AdminActionCode, BusinessProfile, BusinessStats, ErrorCode, InvoiceAuditLog, InvoiceStatus};
+
+// Layout version of BusinessHistoryPage; bump it with any change to the layout
+pub const EXPORT_VERSION: u8 = 1;
+
+// Invoices accepted per export page. Each takes two remaining accounts, the
+// invoice and its audit log, and a page holds fewer once return data runs out.
+pub const MAX_EXPORT_PAGE_INVOICES: usize = 8;
+
+// One page of a business's records from export_business_history, shaped for
+// conversion to CSV or JSON off-chain. Invoices come in id order from `cursor`,
+// one record per id so gaps cannot go unnoticed; the profile snapshot comes with
+// the first page only.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct BusinessHistoryPage {
+    pub version: u8,
+    pub business: Pubkey,
+    pub as_of: i64,
+    pub profile: Option<ProfileSnapshot>,
+    pub cursor: u64,
+    pub records: Vec<SettlementRecord>,
+    // Where the next page starts; None on the final page
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct ProfileSnapshot {
+    pub stats: BusinessStats,
+    pub credit_score: u16,
+    // Months in the reputation window with a default or late repayment, newest first
+    pub reputation: Vec<ReputationSnapshot>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ReputationSnapshot {
+    pub months_ago: u8,
+    pub defaults: u8,
+    pub late_repayments: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct SettlementRecord {
+    pub invoice_id: u64,
+    // None once the invoice account is closed: cancelled, or closed after settling
+    pub invoice: Option<InvoiceRecord>,
+    // Privileged actions taken on the invoice, oldest first; the audit log keeps
+    // the last few, so the total may run ahead of them
+    pub admin_actions_total: u64,
+    pub admin_actions: Vec<ActionRecord>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct InvoiceRecord {
+    pub status: InvoiceStatus,
+    pub amount: u64,
+    pub funded_amount: u64,
+    pub amount_repaid: u64,
+    pub created_at: i64,
+    pub due_date: i64,
+    pub funding_date: Option<i64>,
+    pub settled_at: Option<i64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ActionRecord {
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+    pub amount: Option<u64>,
+}
+
+impl BusinessHistoryPage {
+    pub fn start(profile: &BusinessProfile, cursor: u64, as_of: i64) -> Self {
+        let snapshot = (cursor == 1).then(|| ProfileSnapshot {
+            stats: profile.stats(),
+            credit_score: profile.credit_score,
+            reputation: profile
+                .history
+                .buckets(as_of)
+                .filter(|(_, defaults, late)| *defaults > 0 || *late > 0)
+                .map(|(age, defaults, late_repayments)| ReputationSnapshot {
+                    months_ago: age as u8,
+                    defaults,
+                    late_repayments,
+                })
+                .collect(),
+        });
+        Self {
+            version: EXPORT_VERSION,
+            business: profile.business_owner,
+            as_of,
+            profile: snapshot,
+            cursor,
+            records: Vec::new(),
+            next_cursor: None,
+        }
+    }
+
+    // Add the next invoice's record unless the page, with room left for the next
+    // cursor, would no longer fit in return data. The first record always goes
+    // in, so every page makes progress; one alone always fits.
+    pub fn push(&mut self, record: SettlementRecord) -> bool {
+        let size = serialized_len(self) + serialized_len(&record) + 8;
+        if size > MAX_RETURN_DATA && !self.records.is_empty() {
+            return false;
+        }
+        self.records.push(record);
+        true
+    }
+
+    // Point past the last record, unless it was the business's last invoice
+    pub fn finish(&mut self, invoices_created: u64) {
+        let next = self.cursor + self.records.len() as u64;
+        self.next_cursor = (next <= invoices_created).then_some(next);
+    }
+}
+
+fn serialized_len<T: AnchorSerialize>(value: &T) -> usize {
+    value.try_to_vec().map_or(usize::MAX, |data| data.len())
+}
+
+// The record of invoice `invoice_id` of `business`, read from the invoice and
+// audit log accounts at their addresses; either may be empty
+pub fn read_settlement_record(
+    business: &Pubkey,
+    invoice_id: u64,
+    invoice_info: &AccountInfo,
+    audit_info: &AccountInfo,
+) -> Result<SettlementRecord> {
+    let (invoice_address, _) = find_invoice_address(invoice_id, business);
+    require_keys_eq!(invoice_info.key(), invoice_address, ErrorCode::InvalidInvoiceAccount);
+    let (audit_address, _) =
+        Pubkey::find_program_address(&[INVOICE_AUDIT_SEED, invoice_address.as_ref()], &crate::ID);
+    require_keys_eq!(audit_info.key(), audit_address, ErrorCode::InvalidInvoiceAccount);
+
+    let invoice = if invoice_info.data_is_empty() {
+        None
+    } else {
+        let invoice = load_invoice(invoice_info)?;
+        Some(InvoiceRecord {
+            status: invoice.status,
+            amount: invoice.amount,
+            funded_amount: invoice.funded_amount,
+            amount_repaid: invoice.amount_repaid,
+            created_at: invoice.created_at,
+            due_date: invoice.due_date,
+            funding_date: invoice.funding_date,
+            settled_at: invoice.settled_at(),
+        })
+    };
+
+    let (admin_actions_total, admin_actions) = if audit_info.data_is_empty() {
+        (0, Vec::new())
+    } else {
+        require_keys_eq!(*audit_info.owner, crate::ID, ErrorCode::InvalidInvoiceAccount);
+        let log = InvoiceAuditLog::try_deserialize(&mut &audit_info.try_borrow_data()?[..])?;
+        let actions = log
+            .chronological()
+            .iter()
+            .map(|entry| ActionRecord {
+                action: entry.action,
+                timestamp: entry.timestamp,
+                amount: entry.amount,
+            })
+            .collect();
+        (log.total_actions, actions)
+    };
+
+    Ok(SettlementRecord {
+        invoice_id,
+        invoice,
+        admin_actions_total,
+        admin_actions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(invoice_id: u64, actions: usize) -> SettlementRecord {
+        SettlementRecord {
+            invoice_id,
+            invoice: Some(InvoiceRecord {
+                status: InvoiceStatus::Repaid,
+                amount: 10_000_000,
+                funded_amount: 9_500_000,
+                amount_repaid: 10_000_000,
+                created_at: 1_700_000_000,
+                due_date: 1_702_592_000,
+                funding_date: Some(1_700_086_400),
+                settled_at: Some(1_702_000_000),
+            }),
+            admin_actions_total: actions as u64,
+            admin_actions: vec![
+                ActionRecord {
+                    action: AdminActionCode::PersonalDataErased,
+                    timestamp: 1_700_000_000,
+                    amount: Some(1),
+                };
+                actions
+            ],
+        }
+    }
+
+    #[test]
+    fn a_page_stops_where_return_data_runs_out() {
+        let profile = BusinessProfile { invoices_created: 20, ..BusinessProfile::default() };
+        let mut page = BusinessHistoryPage::start(&profile, 1, 1_705_000_000);
+        assert!(page.profile.is_some());
+
+        let mut invoice_id = 1;
+        while page.push(record(invoice_id, InvoiceAuditLog::MAX_ENTRIES)) {
+            invoice_id += 1;
+        }
+        page.finish(profile.invoices_created);
+
+        assert!(!page.records.is_empty() && page.records.len() < MAX_EXPORT_PAGE_INVOICES);
+        assert_eq!(page.next_cursor, Some(invoice_id));
+        assert!(page.try_to_vec().unwrap().len() <= MAX_RETURN_DATA);
+    }
+
+    #[test]
+    fn the_last_page_is_marked_complete() {
+        let profile = BusinessProfile { invoices_created: 10, ..BusinessProfile::default() };
+        let mut page = BusinessHistoryPage::start(&profile, 9, 1_705_000_000);
+        assert!(page.profile.is_none());
+        assert!(page.push(record(9, 0)));
+        page.finish(profile.invoices_created);
+        assert_eq!(page.next_cursor, Some(10));
+
+        assert!(page.push(SettlementRecord { invoice: None, ..record(10, 0) }));
+        page.finish(profile.invoices_created);
+        assert_eq!(page.next_cursor, None);
+
+        let mut past_the_end = BusinessHistoryPage::start(&profile, 11, 1_705_000_000);
+        past_the_end.finish(profile.invoices_created);
+        assert_eq!(past_the_end.next_cursor, None);
+    }
+}
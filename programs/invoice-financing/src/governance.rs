@@ -0,0 +1,81 @@
+use anchor_lang::prelude::*;
+
+use crate::approvals::approvals_address;
+use crate::{ErrorCode, GlobalState};
+
+// The protocol authority is on its way to a DAO. Until then privileged instructions
+// accept either the authority keypair or the SPL Governance account that governs
+// the global state. SPL Governance executes a passed proposal by signing, through
+// invoke_signed, as that account: a PDA of the governance program at
+// ["account-governance", realm, global_state], owned by the governance program.
+//
+// Every privileged instruction declares a lane. Fast-lane instructions (pausing,
+// unpausing, freezing outflows, per-invoice operations) stay with the keypair so
+// an emergency never waits on a vote. Once governance_enabled is set, governed
+// instructions (parameter changes, role grants, treasury movements) only run when
+// the governance account signs. The flag itself only changes through a governed,
+// timelocked proposal. approvals.rs adds a third signer, the approvals PDA, for
+// instructions an approver set has signed off on.
+pub const GOVERNANCE_SEED: &[u8] = b"account-governance";
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub enum Lane {
+    Fast,
+    Governed,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub enum AuthorityPath {
+    Direct,
+    Governance,
+    Approvers,
+}
+
+pub fn governance_address(governance_program: &Pubkey, realm: &Pubkey, global_state: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[GOVERNANCE_SEED, realm.as_ref(), global_state.as_ref()], governance_program).0
+}
+
+// How `signer` speaks for the protocol, if it does at all
+pub fn authority_path(state: &Account<GlobalState>, signer: &AccountInfo) -> Option<AuthorityPath> {
+    if signer.key() == state.authority {
+        return Some(AuthorityPath::Direct);
+    }
+    if state.approvers_configured() && signer.key() == approvals_address().0 {
+        return Some(AuthorityPath::Approvers);
+    }
+    let program = state.governance_program?;
+    let is_governance = *signer.owner == program
+        && signer.key() == governance_address(&program, &state.governance_realm, &state.key());
+    is_governance.then_some(AuthorityPath::Governance)
+}
+
+// Account constraint: the signer is the authority keypair or the governance account
+pub fn is_authority(state: &Account<GlobalState>, signer: &AccountInfo) -> bool {
+    authority_path(state, signer).is_some()
+}
+
+// Handler check: the signer may run an instruction of this lane
+pub fn authorize(state: &Account<GlobalState>, signer: &AccountInfo, lane: Lane) -> Result<AuthorityPath> {
+    let path = authority_path(state, signer).ok_or(ErrorCode::Unauthorized)?;
+    if lane == Lane::Governed && state.governance_enabled {
+        require!(path == AuthorityPath::Governance, ErrorCode::GovernanceRequired);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn governance_account_is_bound_to_program_realm_and_state() {
+        let (program, realm, state) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let account = governance_address(&program, &realm, &state);
+
+        assert_eq!(account, governance_address(&program, &realm, &state));
+        assert!(!account.is_on_curve());
+        assert_ne!(account, governance_address(&Pubkey::new_unique(), &realm, &state));
+        assert_ne!(account, governance_address(&program, &Pubkey::new_unique(), &state));
+        assert_ne!(account, governance_address(&program, &realm, &Pubkey::new_unique()));
+    }
+}
@@ -1,8 +1,115 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
+use anchor_lang::InstructionData;
+use anchor_lang::Discriminator;
+use anchor_lang::solana_program::program::MAX_RETURN_DATA;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::spl_token::instruction::AuthorityType;
+use anchor_spl::token::spl_token;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer, MintTo, Burn, SetAuthority, Approve, Revoke, SyncNative, CloseAccount};
+
+pub mod approvals;
+pub mod blacklist;
+pub mod book;
+pub mod constants;
+pub mod export;
+pub mod governance;
+pub mod listing;
+pub mod pda;
+pub mod pool;
+pub mod pricing;
+pub mod receipt;
+pub mod review;
+pub mod risk;
+pub mod schemas;
+pub mod signature;
+pub mod vault;
+
+use approvals::{approvals_address, authorize_guarded, AdminAccountMeta, MAX_ADMIN_APPROVERS};
+use blacklist::{require_not_blacklisted, BlacklistReason, BlacklistRole};
+use book::{leaf_hash, BookFrontier};
+use export::{read_settlement_record, BusinessHistoryPage, MAX_EXPORT_PAGE_INVOICES};
+pub use constants::*;
+use governance::{authority_path, authorize, governance_address, is_authority, AuthorityPath, Lane};
+use program::InvoiceFinancing;
+use pool::{invoke_as_pool, redemption_value, require_vault_destination, shares_for_deposit};
+use pricing::{price_invoice, term_days, term_yield, CoverageTiers, PremiumSchedule, PricingInputs, Rounding};
+use receipt::holds_receipt;
+use review::{listing_problems, ListingDraft, ListingProblem, RejectionReason, RemediationHint};
+use risk::{calculate_enhanced_risk, CreditHistory, IndustryCode, ListingRisk, ReputationHistory, RiskConfig, RiskParams};
+use schemas::BookLeaf;
+use signature::{verify_credit_attestation, CreditAttestation};
+use vault::{is_foreign, require_no_delegate, require_sound_vault, ProgramVault, VaultIntegrity};
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
+// Account layout versions. Instructions refuse an Invoice or GlobalState on any
+// other version; migrate_invoice and migrate_global_state bring older ones up.
+// Layouts change by appending fields, which migration fills with defaults; the
+// one exception is Invoice version 3, which moved status up into the fixed header.
+// Invoice versions skip 8 to 200, which read as an unversioned invoice's text
+// length, so the one after 7 is 201.
+pub const INVOICE_VERSION: u8 = 201;
+pub const GLOBAL_STATE_VERSION: u8 = 7;
+
+// Offsets into invoice account data, discriminator included, for getProgramAccounts
+// memcmp filters. Everything before debtor_info_uri has a fixed size, so these
+// hold for every invoice on the current version:
+//
+//   offset  len  field
+//        0    8  discriminator
+//        8    8  invoice_id (u64)
+//       16   32  business_owner
+//       48   32  investor (default key while unfunded)
+//       80    8  amount (u64)
+//       88    8  funded_amount (u64)
+//       96    8  due_date (i64)
+//      104    1  version (u8, INVOICE_VERSION)
+//      105    1  status (u8, InvoiceStatus discriminant)
+//      106   32  debtor_info_hash
+//      138       debtor_info_uri and the rest, variable length
+pub const INVOICE_BUSINESS_OWNER_OFFSET: usize = 8 + 8;
+pub const INVOICE_INVESTOR_OFFSET: usize = INVOICE_BUSINESS_OWNER_OFFSET + 32;
+pub const INVOICE_DUE_DATE_OFFSET: usize = INVOICE_INVESTOR_OFFSET + 32 + 8 + 8;
+pub const INVOICE_VERSION_OFFSET: usize = INVOICE_DUE_DATE_OFFSET + 8;
+pub const INVOICE_STATUS_OFFSET: usize = INVOICE_VERSION_OFFSET + 1;
+
+// Secondary stablecoins GlobalState can approve alongside the primary mint
+pub const MAX_APPROVED_MINTS: usize = 4;
+
+// Longest a funding can wait in escrow for the business to accept it
+pub const MAX_FUNDING_ACCEPTANCE_WINDOW: i64 = 7 * 86_400;
+
+// How long a repaid invoice's vault waits for its receipt holder before the
+// remainder can be swept to the insurance pool (two years)
+pub const UNCLAIMED_REPAYMENT_SWEEP_SECS: i64 = 2 * 365 * 86_400;
+
+// Unless the business sets its own, funding closes a day before the due date and
+// at most 30 days after listing
+pub const FUNDING_DEADLINE_MARGIN_SECS: i64 = 86_400;
+pub const DEFAULT_FUNDING_WINDOW_SECS: i64 = 30 * 86_400;
+
+// Least time between two ping_overdue calls on the same invoice
+pub const OVERDUE_PING_INTERVAL_SECS: i64 = 86_400;
+
+// Ceiling on the originator fee cap governance can set
+pub const MAX_ORIGINATOR_FEE_BPS: u16 = 1_000;
+
+// EventAuthority of an #[event_cpi] context, for emit_indexed
+#[cfg(feature = "cpi-events")]
+macro_rules! event_authority {
+    ($ctx:expr) => {
+        EventAuthority { info: &$ctx.accounts.event_authority, bump: $ctx.bumps.event_authority }
+    };
+}
+
+#[cfg(not(feature = "cpi-events"))]
+macro_rules! event_authority {
+    ($ctx:expr) => {
+        EventAuthority(std::marker::PhantomData)
+    };
+}
+
 #[program]
 pub mod invoice_financing {
     use super::*;
@@ -16,648 +123,19988 @@ pub mod invoice_financing {
         global_state.authority = ctx.accounts.authority.key();
         global_state.usdc_mint = ctx.accounts.usdc_mint.key();
         global_state.bump = ctx.bumps.global_state;
-        
+        global_state.version = GLOBAL_STATE_VERSION;
+        global_state.retention_period_secs = DEFAULT_RETENTION_PERIOD_SECS;
+        global_state.min_interest_bps = DEFAULT_MIN_INTEREST_BPS;
+        global_state.min_insurance_premium = DEFAULT_MIN_INSURANCE_PREMIUM;
+        global_state.health_thresholds = DEFAULT_HEALTH_THRESHOLDS;
+        global_state.config = ProtocolConfig::default();
+        global_state.risk_params = RiskParams::DEFAULT;
+
+        emit_bounded(GlobalStateInitialized {
+            global_state: global_state.key(),
+            authority: global_state.authority,
+            usdc_mint: global_state.usdc_mint,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
         msg!("Global state initialized with authority: {}", global_state.authority);
         Ok(())
     }
 
+    // Create the insurance pool token account, owned by its own authority PDA.
+    // Runs once, after initialize and before the first invoice is funded.
+    pub fn initialize_insurance_pool(ctx: Context<InitializeInsurancePool>) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        global_state.insurance_pool_bump = ctx.bumps.insurance_pool_account;
+        global_state.insurance_pool_authority_bump = ctx.bumps.insurance_pool_authority;
+        require_sound_vault(
+            &ctx.accounts.insurance_pool_account,
+            &ctx.accounts.insurance_pool_authority.key(),
+        )?;
+
+        emit_bounded(InsurancePoolInitialized {
+            pool: ctx.accounts.insurance_pool_account.key(),
+            authority: ctx.accounts.insurance_pool_authority.key(),
+            action: AdminActionCode::InsurancePoolInitialized,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Insurance pool initialized at {}", ctx.accounts.insurance_pool_account.key());
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::InsurancePoolInitialized,
+            None,
+        )
+    }
+
     // Create a new invoice for financing
+    #[allow(clippy::too_many_arguments)]
     pub fn create_invoice(
         ctx: Context<CreateInvoice>,
-        invoice_id: u64,
         amount: u64,
         due_date: i64,
-        debtor_info: String,
+        debtor_info_hash: [u8; 32],
+        debtor_info_uri: Option<String>,
+        offramp_requested: bool,
+        partial_funding: bool,
+        industry: IndustryCode,
+        credit_attestation: Option<CreditAttestation>,
+        debtor_id: [u8; 32],
+        debtor_wallet: Option<Pubkey>,
+        funding_deadline: Option<i64>,
+        origination: Option<Origination>,
+        advance_rate_bps: Option<u16>,
     ) -> Result<()> {
+        require!(debtor_id != [0u8; 32], ErrorCode::InvalidDebtorId);
+        require_not_blacklisted(&ctx.accounts.business_blacklist, BlacklistRole::Business)?;
         let invoice = &mut ctx.accounts.invoice;
         let global_state = &mut ctx.accounts.global_state;
 
-        // Comprehensive validation
-        require!(amount > 0, ErrorCode::InvalidAmount);
-        require!(amount <= 10_000_000_000, ErrorCode::AmountTooLarge); // Max 10k USDC
-        require!(due_date > Clock::get()?.unix_timestamp, ErrorCode::InvalidDueDate);
-        require!(due_date <= Clock::get()?.unix_timestamp + 365 * 24 * 3600, ErrorCode::DueDateTooFar); // Max 1 year
-        require!(debtor_info.len() <= 200, ErrorCode::DebtorInfoTooLong);
-        require!(debtor_info.len() >= 10, ErrorCode::DebtorInfoTooShort);
+        let config = global_state.config;
+        let invoice_created_at = Clock::get()?.unix_timestamp;
 
-        // Enhanced risk calculation
-        let risk_assessment = calculate_enhanced_risk(
+        // Same checks validate_listing reports in full; here the first one fails
+        let draft = ListingDraft {
             amount,
             due_date,
-            &ctx.accounts.business_owner.key(),
-            &global_state
+            debtor_info_hash,
+            debtor_info_uri: debtor_info_uri.as_deref(),
+            offramp_requested,
+            partial_funding,
+            self_acknowledged: debtor_wallet == Some(ctx.accounts.business_owner.key()),
+        };
+        let creation_paused = global_state.require_not_paused(PAUSE_CREATE).is_err();
+
+        // Invoices are denominated in an approved stablecoin, the primary mint by
+        // default. Only the primary mint is insured, since the pool holds it alone,
+        // and only it flows through partial funding and off-ramp payouts.
+        let mint = ctx.accounts.mint.as_ref().map_or(global_state.usdc_mint, |mint| mint.key());
+        require!(global_state.is_approved_mint(&mint), ErrorCode::MintNotApproved);
+        let insured = mint == global_state.usdc_mint;
+        require!(insured || !(offramp_requested || partial_funding), ErrorCode::PrimaryMintOnly);
+        let business_unverified = global_state.business_unverified(Some(&ctx.accounts.business_profile));
+        let open_invoice_limit_reached = global_state.open_invoice_limit_reached(Some(&ctx.accounts.business_profile));
+        if let Some(problem) = listing_problems(
+            &draft,
+            &config,
+            creation_paused,
+            business_unverified,
+            open_invoice_limit_reached,
+            invoice_created_at,
+        )
+        .first()
+        {
+            return Err(problem.reason.error().into());
+        }
+        // A factoring advance comes from one investor funding the invoice outright
+        require!(advance_rate_bps.is_none() || !partial_funding, ErrorCode::FactoringUnsupported);
+        invoice.amount = amount;
+        invoice.set_advance_rate(advance_rate_bps)?;
+
+        // The id was fixed by the invoice PDA the context derived; advance the counter past it
+        let business_profile = &mut ctx.accounts.business_profile;
+        let invoice_id = business_profile.next_invoice_id();
+        business_profile.business_owner = ctx.accounts.business_owner.key();
+        business_profile.bump = ctx.bumps.business_profile;
+        business_profile.invoices_created = invoice_id;
+        business_profile.open_invoices =
+            business_profile.open_invoices.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        // An oracle-attested bureau score, checked against the oracle's Ed25519
+        // signature in the instruction before this one
+        let attested_score = match credit_attestation {
+            Some(attestation) => {
+                let oracle = global_state.oracle_authority.ok_or(ErrorCode::OracleNotConfigured)?;
+                let instructions = ctx.accounts.instructions.as_ref().ok_or(ErrorCode::InvalidInstructionsSysvar)?;
+                Some(verify_credit_attestation(
+                    instructions,
+                    &oracle,
+                    global_state.cluster_id,
+                    ctx.accounts.business_owner.key(),
+                    &attestation,
+                    &mut business_profile.credit_attestation_nonce,
+                    invoice_created_at,
+                )?)
+            }
+            None => None,
+        };
+
+        // Micro-tier invoices skip the risk model for a flat score and premium
+        let micro_tier = ctx.accounts.micro_tier.as_deref().filter(|tier| tier.applies(amount));
+
+        // Price the invoice under an arm of the running pricing experiment, if any
+        // (micro-tier pricing is fixed and stays out of experiments)
+        let running = global_state.experiment_in_force(invoice_created_at).filter(|_| micro_tier.is_none());
+        let experiment = running_experiment(running, ctx.accounts.experiment.as_mut())?;
+        let experiment_terms =
+            experiment.as_ref().map(|experiment| experiment.terms(&ctx.accounts.business_owner.key(), invoice_id));
+        let quote = quote_listing(
+            &ListingRisk { amount, due_date, industry, attested_score },
+            invoice.principal(),
+            &ctx.accounts.business_profile,
+            global_state,
+            micro_tier,
+            experiment_terms,
+            invoice_created_at,
         )?;
-        
-        // Calculate insurance premium based on risk
-        let insurance_premium = (amount * risk_assessment.risk_score as u64) / 1000;
+        let risk_assessment = quote.risk_assessment;
+        let pricing = &quote.pricing;
+        let insurance_premium = if insured { pricing.insurance_premium } else { 0 };
+
+        invoice.experiment = match experiment {
+            Some(experiment) => {
+                let experiment_key = experiment.key();
+                Some(experiment.assign(experiment_key, pricing))
+            }
+            None => None,
+        };
 
         // Set invoice data
         invoice.invoice_id = invoice_id;
         invoice.business_owner = ctx.accounts.business_owner.key();
-        invoice.amount = amount;
         invoice.due_date = due_date;
-        invoice.debtor_info = debtor_info;
+        invoice.funding_deadline =
+            funding_deadline.unwrap_or_else(|| Invoice::default_funding_deadline(invoice_created_at, due_date));
+        require!(
+            invoice.funding_deadline > invoice_created_at && invoice.funding_deadline <= due_date,
+            ErrorCode::InvalidFundingDeadline
+        );
+        invoice.version = INVOICE_VERSION;
+        invoice.debtor_info_hash = debtor_info_hash;
+        invoice.debtor_info_uri = debtor_info_uri;
         invoice.status = InvoiceStatus::PendingFunding;
-        invoice.risk_score = risk_assessment.risk_score;
-        invoice.insurance_premium = insurance_premium;
-        invoice.created_at = Clock::get()?.unix_timestamp;
+        invoice.apply_quote(&quote, insured, invoice_created_at)?;
+        invoice.param_versions.created = global_state.param_version;
+        invoice.mint = mint;
+        invoice.created_at = invoice_created_at;
         invoice.funded_amount = 0;
         invoice.investor = Pubkey::default();
+        invoice.rent_payer = ctx.accounts.payer.key();
         invoice.bump = ctx.bumps.invoice;
 
         // Additional risk factors
-        invoice.industry_risk = risk_assessment.industry_risk;
-        invoice.credit_score = risk_assessment.estimated_credit_score;
-        invoice.payment_terms_days = ((due_date - Clock::get()?.unix_timestamp) / 86400) as u16;
+        invoice.industry = industry;
+        invoice.debtor_wallet = debtor_wallet;
+
+        // Under the business-pays model the premium goes to the pool now, and
+        // funding only moves the principal
+        if global_state.business_pays_premium && insurance_premium > 0 {
+            let business_token = ctx.accounts.business_token_account.as_ref().ok_or(ErrorCode::BusinessAccountMissing)?;
+            let pool = ctx.accounts.insurance_pool_account.as_ref().ok_or(ErrorCode::InsurancePoolAccountMissing)?;
+            let token_program = ctx.accounts.token_program.as_ref().ok_or(ErrorCode::InsurancePoolAccountMissing)?;
+            require!(business_token.amount >= insurance_premium, ErrorCode::InsufficientFunds);
+            token::transfer(
+                CpiContext::new(
+                    token_program.to_account_info(),
+                    Transfer {
+                        from: business_token.to_account_info(),
+                        to: pool.to_account_info(),
+                        authority: ctx.accounts.business_owner.to_account_info(),
+                    },
+                ),
+                insurance_premium,
+            )?;
+            global_state.collect_premium(insurance_premium)?;
+            invoice.premium_prepaid = true;
+        }
+
+        // First reference to a debtor opens its registry entry
+        let debtor = &mut ctx.accounts.debtor;
+        debtor.debtor_id = debtor_id;
+        debtor.bump = ctx.bumps.debtor;
+        debtor.invoices_listed = debtor.invoices_listed.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        invoice.debtor = debtor.key();
+        invoice.offramp_requested = offramp_requested;
+        invoice.partial_funding = partial_funding;
+        invoice.contributor_count = 0;
+        invoice.distributable_amount = 0;
+
+        // A platform listing on the business's behalf takes its fee out of the
+        // funding. Originated invoices are funded outright, so partial funding and
+        // off-ramp payouts are off the table for them.
+        if let Some(origination) = origination {
+            require!(!offramp_requested && !partial_funding, ErrorCode::OriginationUnsupported);
+            require!(origination.fee_bps <= global_state.originator_fee_cap_bps, ErrorCode::OriginatorFeeTooHigh);
+            let stats = ctx.accounts.originator_stats.as_mut().ok_or(ErrorCode::OriginatorNotRegistered)?;
+            require_keys_eq!(stats.originator, origination.originator, ErrorCode::OriginatorNotRegistered);
+            stats.invoices_originated = stats.invoices_originated.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+            invoice.originator = Some(origination.originator);
+            invoice.originator_fee_bps = origination.fee_bps;
+            invoice.originator_fee_from_principal = global_state.originator_fee_from_principal;
+        }
 
         // Update global state
-        global_state.total_invoices += 1;
+        global_state.total_invoices = global_state.total_invoices.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        global_state.invoices_created = global_state.invoices_created.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        invoice.book_nonce = global_state.invoices_created;
 
-        emit!(InvoiceCreated {
-            invoice_id,
-            business_owner: ctx.accounts.business_owner.key(),
-            amount,
-            risk_score: risk_assessment.risk_score,
-            insurance_premium,
-            estimated_yield: risk_assessment.estimated_yield,
-        });
+        if invoice.micro_tier {
+            emit_bounded(MicroInvoiceCreated {
+                invoice_id,
+                business_owner: ctx.accounts.business_owner.key(),
+                amount,
+                industry,
+                debtor: invoice.debtor,
+                mint,
+                risk_score: risk_assessment.risk_score,
+                insurance_premium,
+                invoice: invoice.key(),
+                investor: invoice.investor,
+                timestamp: invoice_created_at,
+                funding_mode: invoice.funding_mode(),
+                advance_amount: invoice.advance_amount,
+                remaining_daily_capacity: quote.remaining_daily_capacity,
+            });
+        } else {
+            emit_indexed(event_authority!(ctx), InvoiceCreated {
+                invoice_id,
+                business_owner: ctx.accounts.business_owner.key(),
+                amount,
+                industry,
+                debtor: invoice.debtor,
+                mint,
+                risk_score: risk_assessment.risk_score,
+                insurance_premium,
+                estimated_yield: pricing.estimated_yield_bps,
+                invoice: invoice.key(),
+                investor: invoice.investor,
+                timestamp: invoice_created_at,
+                funding_mode: invoice.funding_mode(),
+                advance_amount: invoice.advance_amount,
+                remaining_daily_capacity: quote.remaining_daily_capacity,
+            })?;
+        }
 
         msg!("Invoice {} created successfully with risk score: {}", invoice_id, risk_assessment.risk_score);
         Ok(())
     }
 
-    // Fund an invoice (investor provides capital)
-    pub fn fund_invoice(
-        ctx: Context<FundInvoice>,
-        amount: u64,
-    ) -> Result<()> {
+    // Withdraw an unfunded invoice; the account is closed and its rent returned
+    pub fn cancel_invoice(ctx: Context<CancelInvoice>) -> Result<()> {
         let invoice = &mut ctx.accounts.invoice;
         let global_state = &mut ctx.accounts.global_state;
 
-        // Enhanced validation
-        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
-        require!(amount == invoice.amount, ErrorCode::InvalidFundingAmount); // Must fund full amount
         require!(
-            ctx.accounts.investor_token_account.amount >= amount + invoice.insurance_premium,
-            ErrorCode::InsufficientFunds
+            matches!(
+                invoice.status,
+                InvoiceStatus::PendingFunding | InvoiceStatus::Delisted | InvoiceStatus::Expired
+            ),
+            ErrorCode::InvoiceNotAvailable
         );
+        require!(invoice.funded_amount == 0, ErrorCode::InvoiceHasContributions);
+        require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
 
-        // Calculate total cost (principal + insurance premium)
-        let _total_cost = amount + invoice.insurance_premium;
+        // A premium the business prepaid at listing comes back out of the pool
+        if invoice.premium_prepaid {
+            let business_token = ctx.accounts.business_token_account.as_ref().ok_or(ErrorCode::BusinessAccountMissing)?;
+            let pool = ctx.accounts.insurance_pool_account.as_ref().ok_or(ErrorCode::InsurancePoolAccountMissing)?;
+            let pool_authority =
+                ctx.accounts.insurance_pool_authority.as_ref().ok_or(ErrorCode::InsurancePoolAccountMissing)?;
+            let token_program = ctx.accounts.token_program.as_ref().ok_or(ErrorCode::InsurancePoolAccountMissing)?;
+            require_no_delegate(pool)?;
+            let seeds = &[INSURANCE_POOL_AUTHORITY_SEED, &[global_state.insurance_pool_authority_bump]];
+            let signer_seeds = &[&seeds[..]];
+            token::transfer(
+                CpiContext::new_with_signer(
+                    token_program.to_account_info(),
+                    Transfer {
+                        from: pool.to_account_info(),
+                        to: business_token.to_account_info(),
+                        authority: pool_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                invoice.insurance_premium,
+            )?;
+            global_state.refund_premium(invoice.insurance_premium)?;
+        }
 
-        // Transfer principal from investor to business owner
-        let transfer_principal_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.investor_token_account.to_account_info(),
-                to: ctx.accounts.business_token_account.to_account_info(),
-                authority: ctx.accounts.investor.to_account_info(),
-            },
-        );
-        token::transfer(transfer_principal_ctx, amount)?;
+        global_state.total_invoices = global_state.total_invoices.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+        // Delisted and expired invoices already left the open count
+        if invoice.status == InvoiceStatus::PendingFunding {
+            ctx.accounts.business_profile.close_invoice();
+        }
+        invoice.transition(InvoiceStatus::Cancelled)?;
 
-        // Transfer insurance premium to insurance pool
-        let transfer_premium_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.investor_token_account.to_account_info(),
-                to: ctx.accounts.insurance_pool_account.to_account_info(),
-                authority: ctx.accounts.investor.to_account_info(),
-            },
-        );
-        token::transfer(transfer_premium_ctx, invoice.insurance_premium)?;
+        emit_bounded(InvoiceCancelled {
+            invoice_id: invoice.invoice_id,
+            business_owner: invoice.business_owner,
+            invoice: invoice.key(),
+            investor: invoice.investor,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-        // Update invoice state
-        invoice.status = InvoiceStatus::Funded;
-        invoice.funded_amount = amount;
-        invoice.investor = ctx.accounts.investor.key();
-        invoice.funding_date = Some(Clock::get()?.unix_timestamp);
+        msg!("Invoice {} cancelled by {}", invoice.invoice_id, invoice.business_owner);
+        Ok(())
+    }
 
-        // Calculate expected return (risk-based yield)
-        let expected_return = amount + ((amount * invoice.risk_score as u64) / 500); // 2x risk score as APR
-        invoice.expected_return = Some(expected_return);
+    // Anyone may retire a listing nobody funded by its deadline. Contributors to a
+    // partially funded one withdraw as before; the business can cancel it to
+    // recover the rent and list the receivable again.
+    pub fn expire_invoice(ctx: Context<ExpireInvoice>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let current_time = Clock::get()?.unix_timestamp;
 
-        // Update global state
-        global_state.total_funded += amount;
-        global_state.insurance_pool_balance += invoice.insurance_premium;
+        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+        require!(!invoice.funding_open(current_time), ErrorCode::FundingWindowOpen);
+        require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+        invoice.transition(InvoiceStatus::Expired)?;
+        ctx.accounts.business_profile.close_invoice();
 
-        emit!(InvoiceFunded {
+        emit_bounded(InvoiceExpired {
             invoice_id: invoice.invoice_id,
-            investor: ctx.accounts.investor.key(),
-            amount,
-            insurance_premium: invoice.insurance_premium,
-            expected_return,
+            funding_deadline: invoice.funding_closes_at(),
+            funded_amount: invoice.funded_amount,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
         });
 
-        msg!("Invoice {} funded by {} for {} USDC", invoice.invoice_id, ctx.accounts.investor.key(), amount);
+        msg!("Invoice {} expired unfunded", invoice.invoice_id);
         Ok(())
     }
 
-    // Repay invoice when debtor pays
-    pub fn repay_invoice(ctx: Context<RepayInvoice>, repayment_amount: u64) -> Result<()> {
+    // Anyone may signal that a funded invoice is past due, at most once a day per
+    // invoice, so keepers and notification services get a push-style trigger. The
+    // event carries the late fee accrued so far without folding it into the
+    // invoice; repayment still does that.
+    pub fn ping_overdue(ctx: Context<PingOverdue>) -> Result<()> {
         let invoice = &mut ctx.accounts.invoice;
+        let current_time = Clock::get()?.unix_timestamp;
 
-        require!(invoice.status == InvoiceStatus::Funded, ErrorCode::InvoiceNotFunded);
-        require!(repayment_amount >= invoice.funded_amount, ErrorCode::InsufficientRepayment);
+        require!(invoice.status.is_repayable(), ErrorCode::InvoiceNotFunded);
+        require!(current_time > invoice.due_date, ErrorCode::InvoiceNotOverdue);
+        require!(invoice.overdue_ping_due(current_time), ErrorCode::OverduePingTooSoon);
+        let (late_fee, days_overdue) =
+            calculate_late_fee(invoice, current_time, ctx.accounts.global_state.config.late_fee_bps_per_day)?;
+        invoice.last_overdue_ping = Some(current_time);
 
-        // Allow repayment up to 30 days after due date (grace period)
-        let grace_period = 30 * 86400; // 30 days
+        emit_bounded(InvoiceOverdue {
+            invoice_id: invoice.invoice_id,
+            due_date: invoice.due_date,
+            days_overdue: days_overdue as u64,
+            accrued_late_fee: late_fee,
+            remaining_balance: invoice.remaining_balance,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Invoice {} is {} days overdue", invoice.invoice_id, days_overdue);
+        Ok(())
+    }
+
+    // Close a repaid invoice once the retention period has lapsed, returning its
+    // rent to the wallet that paid it at listing. The closing event carries the
+    // invoice's economics for off-chain archives. Nothing owed to investors may
+    // still sit in the invoice vault.
+    pub fn close_invoice(ctx: Context<CloseInvoice>) -> Result<()> {
+        let invoice = &ctx.accounts.invoice;
+        let global_state = &ctx.accounts.global_state;
         let current_time = Clock::get()?.unix_timestamp;
-        
-        // Check if within grace period
-        let is_late = current_time > invoice.due_date;
-        let days_overdue = if is_late {
-            (current_time - invoice.due_date) / 86400
-        } else {
-            0
-        };
 
+        let settled_at = invoice.closable_at().ok_or(ErrorCode::InvoiceNotClosable)?;
         require!(
-            current_time <= invoice.due_date + grace_period,
-            ErrorCode::RepaymentPeriodExpired
+            current_time >= settled_at + global_state.retention_period_secs,
+            ErrorCode::RetentionPeriodActive
+        );
+        // Bundle settlement reads its constituents
+        require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+        // A receipt left unredeemed still has a claim on the vault, unless what it
+        // was owed has been swept to the insurance pool
+        require!(
+            invoice.live_receipt().is_none() || invoice.unclaimed_swept > 0,
+            ErrorCode::InvoicePositionOpen
         );
+        if invoice.partial_funding {
+            // Pro rata claims round down, leaving under a unit per contributor behind
+            let vault = ctx.accounts.invoice_vault.as_ref().ok_or(ErrorCode::InvoiceVaultMissing)?;
+            require!(vault.amount < invoice.contributor_count as u64, ErrorCode::InvoicePositionOpen);
+        }
 
-        // Calculate late fees if applicable
-        let mut total_repayment = repayment_amount;
-        let mut late_fee = 0u64;
-        
-        if is_late {
-            late_fee = (invoice.funded_amount * days_overdue as u64 * 5) / 10000; // 0.05% per day
-            total_repayment += late_fee;
+        emit_bounded(InvoiceClosed {
+            invoice_id: invoice.invoice_id,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            mint: invoice.mint,
+            status: invoice.status,
+            amount: invoice.amount,
+            funded_amount: invoice.funded_amount,
+            amount_repaid: invoice.amount_repaid,
+            interest_paid: invoice.interest_paid,
+            late_fee: invoice.late_fee.unwrap_or(0),
+            insurance_premium: invoice.insurance_premium,
+            insurance_payout: invoice.insurance_payout.unwrap_or(0),
+            recovered_amount: invoice.recovered_amount,
+            created_at: invoice.created_at,
+            funding_date: invoice.funding_date,
+            settled_at,
+            closed_at: current_time,
+        });
+
+        msg!("Invoice {} closed by {}", invoice.invoice_id, invoice.business_owner);
+        Ok(())
+    }
+
+    // Dry-run create_invoice's checks and report every problem with the listing,
+    // each with a remediation hint, instead of failing on the first (view function)
+    #[allow(clippy::too_many_arguments)]
+    pub fn validate_listing(
+        ctx: Context<ValidateListing>,
+        amount: u64,
+        due_date: i64,
+        debtor_info_hash: [u8; 32],
+        debtor_info_uri: Option<String>,
+        offramp_requested: bool,
+        partial_funding: bool,
+        debtor_wallet: Option<Pubkey>,
+    ) -> Result<Vec<ListingProblem>> {
+        let global_state = &ctx.accounts.global_state;
+        let business_owner = ctx.accounts.business_profile.as_ref().map(|profile| profile.business_owner);
+        let draft = ListingDraft {
+            amount,
+            due_date,
+            debtor_info_hash,
+            debtor_info_uri: debtor_info_uri.as_deref(),
+            offramp_requested,
+            partial_funding,
+            self_acknowledged: debtor_wallet.is_some() && debtor_wallet == business_owner,
+        };
+        Ok(listing_problems(
+            &draft,
+            &global_state.config,
+            global_state.require_not_paused(PAUSE_CREATE).is_err(),
+            global_state.business_unverified(ctx.accounts.business_profile.as_deref()),
+            global_state.open_invoice_limit_reached(ctx.accounts.business_profile.as_deref()),
+            Clock::get()?.unix_timestamp,
+        ))
+    }
+
+    // Submit (or update) the business's KYC details. Any change after verification
+    // sends the profile back for review.
+    pub fn register_business(
+        ctx: Context<RegisterBusiness>,
+        jurisdiction: [u8; 2],
+        industry_code: u32,
+        document_hash: [u8; 32],
+    ) -> Result<()> {
+        require!(jurisdiction.iter().all(u8::is_ascii_uppercase), ErrorCode::InvalidJurisdiction);
+        require!(document_hash != [0u8; 32], ErrorCode::BusinessNotRegistered);
+
+        let profile = &mut ctx.accounts.business_profile;
+        let changed = profile.jurisdiction != jurisdiction
+            || profile.industry_code != industry_code
+            || profile.document_hash != document_hash;
+        if changed && profile.verified {
+            profile.verified = false;
+            profile.verified_at = None;
         }
+        profile.business_owner = ctx.accounts.business_owner.key();
+        profile.bump = ctx.bumps.business_profile;
+        profile.jurisdiction = jurisdiction;
+        profile.industry_code = industry_code;
+        profile.document_hash = document_hash;
 
-        require!(
-            ctx.accounts.business_token_account.amount >= total_repayment,
-            ErrorCode::InsufficientRepaymentFunds
-        );
+        emit_bounded(BusinessRegistered {
+            business_owner: profile.business_owner,
+            jurisdiction,
+            industry_code,
+            document_hash,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-        // Transfer repayment from business owner to investor
-        let transfer_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.business_token_account.to_account_info(),
-                to: ctx.accounts.investor_token_account.to_account_info(),
-                authority: ctx.accounts.business_owner.to_account_info(),
-            },
-        );
-        token::transfer(transfer_ctx, total_repayment)?;
+        msg!("Business {} registered for verification", profile.business_owner);
+        Ok(())
+    }
 
-        invoice.status = InvoiceStatus::Repaid;
-        invoice.repayment_date = Some(current_time);
-        invoice.final_repayment_amount = Some(total_repayment);
-        invoice.late_fee = Some(late_fee);
+    // Open the stats account a platform's originated invoices are tallied in; an
+    // invoice can only name an originator that has one
+    pub fn register_originator(ctx: Context<RegisterOriginator>) -> Result<()> {
+        let stats = &mut ctx.accounts.originator_stats;
+        stats.originator = ctx.accounts.originator.key();
+        stats.bump = ctx.bumps.originator_stats;
 
-        emit!(InvoiceRepaid {
-            invoice_id: invoice.invoice_id,
-            amount: total_repayment,
-            late_fee,
-            days_overdue: days_overdue as u16,
+        emit_bounded(OriginatorRegistered {
+            originator: stats.originator,
+            timestamp: Clock::get()?.unix_timestamp,
         });
 
-        msg!("Invoice {} repaid: {} USDC (late fee: {})", invoice.invoice_id, total_repayment, late_fee);
+        msg!("Originator {} registered", stats.originator);
         Ok(())
     }
 
-    // Claim insurance if invoice defaults
-    pub fn claim_insurance(ctx: Context<ClaimInsurance>) -> Result<()> {
-        let invoice = &mut ctx.accounts.invoice;
-        let global_state = &mut ctx.accounts.global_state;
+    // Record the outcome of KYC review of the documents hashing to `document_hash`,
+    // with the credit score underwriting found
+    pub fn verify_business(
+        ctx: Context<VerifyBusiness>,
+        business_owner: Pubkey,
+        document_hash: [u8; 32],
+        credit_score: u16,
+        verified: bool,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let profile = &mut ctx.accounts.business_profile;
+        require!(profile.document_hash != [0u8; 32], ErrorCode::BusinessNotRegistered);
+        require!(profile.document_hash == document_hash, ErrorCode::BusinessProfileChanged);
+        require!((300..=850).contains(&credit_score), ErrorCode::InvalidCreditScore);
 
-        require!(invoice.status == InvoiceStatus::Funded, ErrorCode::InvoiceNotFunded);
-        require!(
-            ctx.accounts.investor.key() == invoice.investor,
-            ErrorCode::UnauthorizedInsuranceClaim
-        );
+        profile.verified = verified;
+        profile.verified_at = if verified { Some(Clock::get()?.unix_timestamp) } else { None };
+        profile.credit_score = credit_score;
 
-        // Must wait 30 days after due date to claim
-        let claim_eligible_date = invoice.due_date + (30 * 86400);
-        require!(
-            Clock::get()?.unix_timestamp > claim_eligible_date,
-            ErrorCode::NotEligibleForClaim
-        );
+        emit_bounded(BusinessVerificationSet {
+            business_owner,
+            verified,
+            credit_score,
+            action: AdminActionCode::BusinessVerificationSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-        // Calculate insurance payout based on risk tier
-        let coverage_percentage = match invoice.risk_score {
-            0..=20 => 90,   // Low risk: 90% coverage
-            21..=35 => 80,  // Medium risk: 80% coverage
-            36..=50 => 70,  // High risk: 70% coverage
-            _ => 60,        // Very high risk: 60% coverage
-        };
+        msg!("Business {} verification set to {}", business_owner, verified);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::BusinessVerificationSet,
+            None,
+        )
+    }
 
-        let insurance_payout = (invoice.funded_amount * coverage_percentage) / 100;
-        
-        // Ensure insurance pool has sufficient funds
-        require!(
-            ctx.accounts.insurance_pool_account.amount >= insurance_payout,
-            ErrorCode::InsufficientInsurancePool
-        );
+    // Restrict listing to verified businesses, or open it to anyone
+    pub fn set_require_business_verification(ctx: Context<UpdateGlobalState>, enabled: bool) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        ctx.accounts.global_state.require_business_verification = enabled;
 
-        // Transfer insurance payout to investor
-        let seeds = &[b"insurance_pool".as_ref(), &[global_state.bump]];
-        let signer_seeds = &[&seeds[..]];
+        emit_bounded(BusinessVerificationRequirementSet {
+            enabled,
+            action: AdminActionCode::BusinessVerificationRequirementSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
-        let transfer_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.insurance_pool_account.to_account_info(),
-                to: ctx.accounts.investor_token_account.to_account_info(),
-                authority: ctx.accounts.insurance_pool_authority.to_account_info(),
-            },
-            signer_seeds,
-        );
-        token::transfer(transfer_ctx, insurance_payout)?;
+        msg!("Business verification {}", if enabled { "required" } else { "not required" });
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::BusinessVerificationRequirementSet,
+            None,
+        )
+    }
 
-        invoice.status = InvoiceStatus::Defaulted;
-        invoice.insurance_claim_date = Some(Clock::get()?.unix_timestamp);
-        invoice.insurance_payout = Some(insurance_payout);
-        
-        global_state.insurance_pool_balance -= insurance_payout;
+    // Take an unfunded listing off the marketplace after review. The reason is
+    // recorded on the invoice so the business knows what to fix; it can then cancel
+    // and resubmit.
+    pub fn delist_invoice(ctx: Context<DelistInvoice>, reason: RejectionReason) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Fast)?;
+        let invoice = &mut ctx.accounts.invoice;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(reason.is_review_reason(), ErrorCode::NotAReviewReason);
+        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+        require!(invoice.funded_amount == 0, ErrorCode::InvoiceHasContributions);
+        require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+
+        invoice.transition(InvoiceStatus::Delisted)?;
+        invoice.rejection_reason = Some(reason);
+        ctx.accounts.business_profile.close_invoice();
+
+        let audit_log = &mut ctx.accounts.invoice_audit_log;
+        audit_log.invoice = invoice.key();
+        audit_log.bump = ctx.bumps.invoice_audit_log;
+        audit_log.record(AdminAction {
+            actor: ctx.accounts.authority.key(),
+            role: AdminRole::ProtocolAuthority,
+            action: AdminActionCode::InvoiceDelisted,
+            timestamp: current_time,
+            amount: None,
+        });
 
-        emit!(InsuranceClaimed {
+        emit_bounded(InvoiceDelisted {
             invoice_id: invoice.invoice_id,
+            business_owner: invoice.business_owner,
+            reason,
+            hint: reason.hint(),
+            action: AdminActionCode::InvoiceDelisted,
+            invoice: invoice.key(),
             investor: invoice.investor,
-            payout_amount: insurance_payout,
-            coverage_percentage,
+            timestamp: current_time,
         });
 
-        msg!("Insurance claimed for invoice {}: {} USDC ({}% coverage)", 
-             invoice.invoice_id, insurance_payout, coverage_percentage);
+        msg!("Invoice {} delisted: {:?}", invoice.invoice_id, reason);
         Ok(())
     }
 
-    // Get invoice details (view function)
-    pub fn get_invoice_details(ctx: Context<GetInvoiceDetails>) -> Result<InvoiceDetails> {
-        let invoice = &ctx.accounts.invoice;
-        
-        Ok(InvoiceDetails {
+    // The debtor named at listing confirms it owes the invoice. Under a penalized
+    // policy that takes the listing's risk points off and reprices it, which also
+    // means any published listing proof has to be published again.
+    pub fn acknowledge_invoice(ctx: Context<AcknowledgeInvoice>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &ctx.accounts.global_state;
+        let debtor_wallet = ctx.accounts.debtor_wallet.key();
+
+        require!(invoice.debtor_wallet == Some(debtor_wallet), ErrorCode::DebtorWalletMismatch);
+        require!(!invoice.debtor_acknowledged, ErrorCode::InvoiceAlreadyAcknowledged);
+        // The price is fixed once capital or a bundle depends on it
+        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+        require!(invoice.funded_amount == 0, ErrorCode::InvoiceHasContributions);
+        require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+
+        let insured = invoice.mint == global_state.usdc_mint;
+        invoice.record_acknowledgment(
+            global_state.config.coverage,
+            global_state.min_insurance_premium,
+            insured,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        emit_bounded(InvoiceAcknowledged {
             invoice_id: invoice.invoice_id,
             business_owner: invoice.business_owner,
-            investor: invoice.investor,
-            amount: invoice.amount,
-            funded_amount: invoice.funded_amount,
-            due_date: invoice.due_date,
-            status: invoice.status,
+            debtor_wallet,
             risk_score: invoice.risk_score,
             insurance_premium: invoice.insurance_premium,
-            created_at: invoice.created_at,
-            funding_date: invoice.funding_date,
-            repayment_date: invoice.repayment_date,
-            expected_return: invoice.expected_return,
-        })
+            invoice: invoice.key(),
+            investor: invoice.investor,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Invoice {} acknowledged by debtor {}", invoice.invoice_id, debtor_wallet);
+        Ok(())
     }
-}
 
-// Enhanced risk calculation with multiple factors
-fn calculate_enhanced_risk(
-    amount: u64,
-    due_date: i64,
-    business_owner: &Pubkey,
-    _global_state: &GlobalState,
-) -> Result<RiskAssessment> {
-    let current_time = Clock::get()?.unix_timestamp;
-    let days_to_due = (due_date - current_time) / 86400;
-    
-    let mut risk_score = 10u8; // Base risk score
-    
-    // Amount-based risk (higher amounts = higher risk)
-    risk_score += match amount {
-        0..=10_000_000 => 5,      // $10 or less: +5
-        10_000_001..=50_000_000 => 10,   // $10-50: +10
-        50_000_001..=100_000_000 => 15,  // $50-100: +15
-        100_000_001..=500_000_000 => 25, // $100-500: +25
-        _ => 35,                          // $500+: +35
-    };
-    
-    // Duration-based risk (shorter terms = higher risk)
-    risk_score += match days_to_due {
-        0..=7 => 20,     // 1 week or less: very risky
-        8..=14 => 15,    // 2 weeks: high risk
-        15..=30 => 10,   // 1 month: medium risk
-        31..=60 => 5,    // 2 months: low additional risk
-        61..=90 => 2,    // 3 months: minimal additional risk
-        _ => 0,          // Longer terms: no additional risk
-    };
-    
-    // Mock business credit assessment (in production, this would use external APIs)
-    let business_hash = business_owner.to_bytes();
-    let pseudo_credit_score = ((business_hash[0] as u16) * 3 + 600) % 850; // Mock score 600-850
-    
-    risk_score += match pseudo_credit_score {
-        800..=850 => 0,   // Excellent credit: no additional risk
-        750..=799 => 2,   // Good credit: minimal risk
-        700..=749 => 5,   // Fair credit: some risk
-        650..=699 => 10,  // Poor credit: higher risk
-        _ => 15,          // Very poor credit: significant risk
-    };
-    
-    // Industry risk (mock - based on debtor info length as proxy)
-    let industry_risk = 5u8; // Default medium industry risk
-    risk_score += industry_risk;
-    
-    // Cap risk score at 50 (5% premium max)
-    risk_score = std::cmp::min(risk_score, 50);
-    
-    // Calculate estimated yield for investor
-    let base_yield_bps = 500; // 5% base yield
-    let risk_premium_bps = (risk_score as u16) * 20; // Additional yield based on risk
-    let estimated_yield = base_yield_bps + risk_premium_bps;
-    
-    Ok(RiskAssessment {
-        risk_score,
-        industry_risk,
-        estimated_credit_score: pseudo_credit_score,
-        estimated_yield,
-    })
-}
+    // Business: correct an unfunded invoice's amount, due date or debtor document.
+    // The new terms are checked and priced as a fresh listing's would be, and a
+    // debtor acknowledgment of the old ones no longer counts. fund_invoice takes
+    // the amount and risk score the investor saw, so funding on stale terms fails.
+    pub fn amend_invoice(
+        ctx: Context<AmendInvoice>,
+        amount: u64,
+        due_date: i64,
+        debtor_info_hash: [u8; 32],
+        debtor_info_uri: Option<String>,
+        credit_attestation: Option<CreditAttestation>,
+    ) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
 
-// Account structures
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = GlobalState::SIZE,
-        seeds = [b"global_state"],
-        bump
-    )]
-    pub global_state: Account<'info, GlobalState>,
-    
+        // The terms are fixed once capital or a bundle depends on them
+        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+        require!(invoice.funded_amount == 0, ErrorCode::InvoiceHasContributions);
+        require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+        require!(invoice.funding_open(current_time), ErrorCode::FundingWindowClosed);
+        // A prepaid premium bought cover for the old terms; cancel and relist instead
+        require!(!invoice.premium_prepaid, ErrorCode::InvoicePremiumPrepaid);
+        // The funding deadline stands, and may not fall after the new due date
+        require!(invoice.funding_deadline <= due_date, ErrorCode::InvalidFundingDeadline);
+
+        let draft = ListingDraft {
+            amount,
+            due_date,
+            debtor_info_hash,
+            debtor_info_uri: debtor_info_uri.as_deref(),
+            offramp_requested: invoice.offramp_requested,
+            partial_funding: invoice.partial_funding,
+            self_acknowledged: invoice.debtor_wallet == Some(invoice.business_owner),
+        };
+        let creation_paused = global_state.require_not_paused(PAUSE_CREATE).is_err();
+        let business_unverified = global_state.business_unverified(Some(&ctx.accounts.business_profile));
+        // The invoice is already counted among the business's open ones
+        if let Some(problem) =
+            listing_problems(&draft, &global_state.config, creation_paused, business_unverified, false, current_time)
+                .first()
+        {
+            return Err(problem.reason.error().into());
+        }
+
+        let business_profile = &mut ctx.accounts.business_profile;
+        let attested_score = match credit_attestation {
+            Some(attestation) => {
+                let oracle = global_state.oracle_authority.ok_or(ErrorCode::OracleNotConfigured)?;
+                let instructions = ctx.accounts.instructions.as_ref().ok_or(ErrorCode::InvalidInstructionsSysvar)?;
+                Some(verify_credit_attestation(
+                    instructions,
+                    &oracle,
+                    global_state.cluster_id,
+                    ctx.accounts.business_owner.key(),
+                    &attestation,
+                    &mut business_profile.credit_attestation_nonce,
+                    current_time,
+                )?)
+            }
+            None => None,
+        };
+
+        // An invoice in a pricing experiment leaves the arm it was counted in, and
+        // is priced into the running experiment like a new listing. One left over
+        // from an earlier experiment takes no part in the running one, as its
+        // account is the one passed.
+        let micro_tier = ctx.accounts.micro_tier.as_deref().filter(|tier| tier.applies(amount));
+        let previous = invoice.experiment.take();
+        if let Some(assignment) = &previous {
+            let account = ctx.accounts.experiment.as_mut().ok_or(ErrorCode::ExperimentAccountMissing)?;
+            require_keys_eq!(account.key(), assignment.experiment, ErrorCode::ExperimentMismatch);
+            let arm = account.arm_mut(assignment.arm);
+            arm.assigned = arm.assigned.saturating_sub(1);
+        }
+        let running = global_state.experiment_in_force(current_time).filter(|key| {
+            micro_tier.is_none() && previous.as_ref().is_none_or(|assignment| assignment.experiment == *key)
+        });
+        let experiment = running_experiment(running, ctx.accounts.experiment.as_mut())?;
+        let experiment_terms =
+            experiment.as_ref().map(|experiment| experiment.terms(&invoice.business_owner, invoice.invoice_id));
+        // A factored invoice keeps its advance rate on the new face value
+        let advance_rate_bps = Some(invoice.advance_rate_bps).filter(|rate| *rate > 0);
+        let old_amount = invoice.amount;
+        invoice.amount = amount;
+        invoice.set_advance_rate(advance_rate_bps)?;
+        let quote = quote_listing(
+            &ListingRisk { amount, due_date, industry: invoice.industry, attested_score },
+            invoice.principal(),
+            &ctx.accounts.business_profile,
+            global_state,
+            micro_tier,
+            experiment_terms,
+            current_time,
+        )?;
+        invoice.experiment = match experiment {
+            Some(experiment) => {
+                let experiment_key = experiment.key();
+                Some(experiment.assign(experiment_key, &quote.pricing))
+            }
+            None => None,
+        };
+
+        let old_due_date = invoice.due_date;
+        let old_debtor_info_hash = invoice.debtor_info_hash;
+        let old_risk_score = invoice.risk_score;
+        let old_insurance_premium = invoice.insurance_premium;
+        let insured = invoice.mint == global_state.usdc_mint;
+        invoice.due_date = due_date;
+        invoice.debtor_info_hash = debtor_info_hash;
+        invoice.debtor_info_uri = debtor_info_uri;
+        invoice.debtor_acknowledged = false;
+        invoice.debtor_acknowledged_at = None;
+        invoice.apply_quote(&quote, insured, current_time)?;
+        invoice.param_versions.created = global_state.param_version;
+
+        emit_bounded(InvoiceAmended {
+            invoice_id: invoice.invoice_id,
+            old_amount,
+            amount,
+            old_due_date,
+            due_date,
+            old_debtor_info_hash,
+            debtor_info_hash,
+            old_risk_score,
+            risk_score: invoice.risk_score,
+            old_insurance_premium,
+            insurance_premium: invoice.insurance_premium,
+            payment_terms_days: invoice.payment_terms_days,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+            remaining_daily_capacity: quote.remaining_daily_capacity,
+        });
+
+        msg!("Invoice {} amended with risk score: {}", invoice.invoice_id, invoice.risk_score);
+        Ok(())
+    }
+
+    // Fund an invoice (investor provides capital)
+    // With `from_balance` the principal and premium are drawn from the investor's
+    // pre-deposited custody balance instead of their wallet
+    // `max_premium` bounds the insurance premium the investor accepts, so a quote
+    // taken in simulation cannot be silently repriced before execution
+    pub fn fund_invoice(
+        ctx: Context<FundInvoice>,
+        amount: u64,
+        from_balance: bool,
+        max_premium: u64,
+        expected_risk_score: u8,
+        expected_due_date: i64,
+    ) -> Result<()> {
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+
+        // Enhanced validation
+        global_state.require_not_paused(PAUSE_FUND)?;
+        require_not_blacklisted(&ctx.accounts.investor_blacklist, BlacklistRole::Investor)?;
+        require_not_blacklisted(&ctx.accounts.business_blacklist, BlacklistRole::Business)?;
+        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+        require!(!invoice.partial_funding, ErrorCode::PartialFundingInvoice);
+        require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+        require!(amount == invoice.principal(), ErrorCode::InvalidFundingAmount); // Must fund full amount
+        // Terms the investor saw, which an amendment or acknowledgment may have moved
+        require!(expected_risk_score == invoice.risk_score, ErrorCode::InvoiceTermsChanged);
+        require!(expected_due_date == invoice.due_date, ErrorCode::InvoiceTermsChanged);
+        // Nothing on top of the principal when the business prepaid the premium
+        let premium = invoice.investor_premium();
+        require!(premium <= max_premium, ErrorCode::SlippageExceeded);
+        global_state.require_acknowledged(invoice)?;
+        // Custody balances are held in the primary mint
+        require!(!from_balance || invoice.mint == global_state.usdc_mint, ErrorCode::PrimaryMintOnly);
+        // The originator is paid from the investor's wallet as the principal moves
+        require!(invoice.originator.is_none() || !from_balance, ErrorCode::OriginationUnsupported);
+        let originator_fee = invoice.originator_fee();
+        if !from_balance {
+            let total_cost = amount
+                .checked_add(premium)
+                .and_then(|cost| cost.checked_add(invoice.investor_originator_fee()))
+                .ok_or(ErrorCode::MathOverflow)?;
+            require!(
+                ctx.accounts.investor_token_account.amount >= total_cost,
+                ErrorCode::InsufficientFunds
+            );
+        }
+
+        admit_funding(
+            global_state,
+            invoice,
+            &mut ctx.accounts.investor_stats,
+            &mut ctx.accounts.business_profile,
+            ctx.accounts.debtor.as_deref_mut(),
+            ctx.accounts.investor_whitelist.as_deref(),
+            amount,
+        )?;
+
+        // With an acceptance window the funding waits in the invoice vault until the
+        // business accepts it; off-ramp payouts already wait in the processor escrow
+        let escrowed = global_state.funding_acceptance_window > 0 && !invoice.offramp_requested;
+        require!(invoice.originator.is_none() || !escrowed, ErrorCode::OriginationUnsupported);
+        if escrowed {
+            require_sound_vault(&ctx.accounts.invoice_vault, &invoice.key())?;
+        }
+
+        // Transfer principal from investor to business owner, into the invoice vault
+        // pending acceptance, or into the outbox escrow when the business asked for a
+        // fiat off-ramp payout
+        let principal_destination = if escrowed {
+            ctx.accounts.invoice_vault.to_account_info()
+        } else if invoice.offramp_requested {
+            let processor = ctx.accounts.payout_processor.as_ref().ok_or(ErrorCode::OutboxAccountsMissing)?;
+            let escrow = ctx.accounts.outbox_escrow.as_ref().ok_or(ErrorCode::OutboxAccountsMissing)?;
+            require_keys_eq!(escrow.key(), processor.escrow, ErrorCode::InvalidOutboxEscrow);
+            require_no_delegate(escrow)?;
+            escrow.to_account_info()
+        } else {
+            ctx.accounts.business_token_account.to_account_info()
+        };
+
+        if from_balance {
+            // One transfer out of custody; the premium stays behind, owed to the pool
+            // until swept, so it only moves in the balance's accounting here
+            let balance = ctx.accounts.investor_balance.as_mut().ok_or(ErrorCode::InvestorBalanceMissing)?;
+            let custody = ctx.accounts.investor_custody.as_ref().ok_or(ErrorCode::InvestorBalanceMissing)?;
+            require_keys_eq!(custody.key(), balance.custody, ErrorCode::InvalidCustodyAccount);
+            require_no_delegate(custody)?;
+            balance.draw(amount, premium)?;
+
+            let investor_key = ctx.accounts.investor.key();
+            let seeds = &[INVESTOR_BALANCE_SEED, investor_key.as_ref(), &[balance.bump]];
+            let signer_seeds = &[&seeds[..]];
+            let transfer_principal_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: custody.to_account_info(),
+                    to: principal_destination,
+                    authority: balance.to_account_info(),
+                },
+                signer_seeds,
+            );
+            token::transfer(transfer_principal_ctx, amount)?;
+        } else {
+            let transfer_principal_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.investor_token_account.to_account_info(),
+                    to: principal_destination,
+                    authority: ctx.accounts.investor.to_account_info(),
+                },
+            );
+            token::transfer(transfer_principal_ctx, invoice.business_proceeds())?;
+
+            if let Some(originator) = invoice.originator {
+                let originator_account =
+                    ctx.accounts.originator_token_account.as_ref().ok_or(ErrorCode::OriginatorAccountsMissing)?;
+                require_keys_eq!(originator_account.owner, originator, ErrorCode::TokenOwnerMismatch);
+                if originator_fee > 0 {
+                    let transfer_fee_ctx = CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: ctx.accounts.investor_token_account.to_account_info(),
+                            to: originator_account.to_account_info(),
+                            authority: ctx.accounts.investor.to_account_info(),
+                        },
+                    );
+                    token::transfer(transfer_fee_ctx, originator_fee)?;
+                }
+            }
+
+            // Transfer insurance premium to insurance pool, or alongside the principal
+            // into escrow; invoices in other mints are uninsured and carry none
+            if premium > 0 {
+                let premium_destination = if escrowed {
+                    ctx.accounts.invoice_vault.to_account_info()
+                } else {
+                    ctx.accounts.insurance_pool_account.to_account_info()
+                };
+                let transfer_premium_ctx = CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.investor_token_account.to_account_info(),
+                        to: premium_destination,
+                        authority: ctx.accounts.investor.to_account_info(),
+                    },
+                );
+                token::transfer(transfer_premium_ctx, premium)?;
+                if !escrowed {
+                    global_state.collect_premium(premium)?;
+                }
+            }
+        }
+
+        let funded_at = Clock::get()?.unix_timestamp;
+        mark_funded(invoice, global_state, ctx.accounts.investor.key(), amount, escrowed, from_balance, funded_at)?;
+
+        if let Some(originator) = invoice.originator {
+            let stats = ctx.accounts.originator_stats.as_mut().ok_or(ErrorCode::OriginatorAccountsMissing)?;
+            require_keys_eq!(stats.originator, originator, ErrorCode::OriginatorNotRegistered);
+            stats.record_funding(amount, originator_fee, funded_at)?;
+            invoice.originator_fee_paid = originator_fee;
+        }
+
+        let investor_stats = &mut ctx.accounts.investor_stats;
+        investor_stats.investor = invoice.investor;
+        investor_stats.bump = ctx.bumps.investor_stats;
+        investor_stats.deployed_capital = investor_stats.deployed_capital.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        let portfolio = &mut ctx.accounts.portfolio;
+        portfolio.investor = invoice.investor;
+        portfolio.bump = ctx.bumps.portfolio;
+
+        let pair_ledger = &mut ctx.accounts.pair_ledger;
+        pair_ledger.business = invoice.business_owner;
+        pair_ledger.investor = invoice.investor;
+        pair_ledger.bump = ctx.bumps.pair_ledger;
+        // An escrowed funding joins the pair's history and the portfolio, and gets its
+        // receipt, once accepted
+        if !escrowed {
+            pair_ledger.record_funding(invoice.invoice_id, amount, funded_at)?;
+            invoice.book_portfolio(portfolio, premium)?;
+
+            // Repayments collect in the invoice vault for the receipt holder
+            require_sound_vault(&ctx.accounts.invoice_vault, &invoice.key())?;
+            mint_position_receipt(
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.receipt_mint.to_account_info(),
+                ctx.accounts.investor_receipt.to_account_info(),
+                invoice,
+                invoice_info,
+            )?;
+        }
+
+        // Expected return, fixed by mark_funded from the APR and the term left
+        let expected_return = invoice.expected_return.unwrap_or(amount);
+
+        record_experiment_outcome(invoice, ctx.accounts.experiment.as_mut(), ExperimentOutcome::Funded)?;
+
+        if invoice.offramp_requested {
+            let processor = ctx.accounts.payout_processor.as_mut().ok_or(ErrorCode::OutboxAccountsMissing)?;
+            let page = ctx.accounts.outbox_page.as_mut().ok_or(ErrorCode::OutboxAccountsMissing)?;
+            let index = append_outbox_entry(
+                processor,
+                page,
+                OutboxEntry {
+                    invoice_id: invoice.invoice_id,
+                    beneficiary: invoice.business_owner,
+                    amount,
+                    currency_mint: global_state.usdc_mint,
+                    kind: OutboxEntryKind::Disbursement,
+                    status: OutboxEntryStatus::Pending,
+                    queued_at: Clock::get()?.unix_timestamp,
+                    retries: 0,
+                    reference_hash: [0u8; 32],
+                    processed_at: None,
+                },
+            )?;
+
+            emit_bounded(OutboxEntryAppended {
+                index,
+                invoice_id: invoice.invoice_id,
+                beneficiary: invoice.business_owner,
+                amount,
+                kind: OutboxEntryKind::Disbursement,
+                invoice: invoice.key(),
+                business_owner: invoice.business_owner,
+                investor: invoice.investor,
+                timestamp: funded_at,
+            });
+        }
+
+        // Update global state
+        global_state.total_funded = global_state.total_funded.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        global_state.require_coverage_capacity()?;
+
+        if let Some(escrow) = invoice.escrow {
+            emit_bounded(FundingEscrowed {
+                invoice_id: invoice.invoice_id,
+                investor: ctx.accounts.investor.key(),
+                amount,
+                insurance_premium: invoice.insurance_premium,
+                deadline: escrow.deadline,
+                invoice: invoice.key(),
+                business_owner: invoice.business_owner,
+                timestamp: funded_at,
+            });
+            msg!("Invoice {} funding escrowed until {}", invoice.invoice_id, escrow.deadline);
+            return Ok(());
+        }
+
+        emit_indexed(event_authority!(ctx), InvoiceFunded {
+            invoice_id: invoice.invoice_id,
+            investor: ctx.accounts.investor.key(),
+            amount,
+            insurance_premium: invoice.insurance_premium,
+            expected_return,
+            apr_bps: invoice.apr_bps,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: funded_at,
+            coverage_utilization_bps: global_state.coverage_utilization_bps(),
+            originator_fee: invoice.originator_fee_paid,
+            funding_mode: invoice.funding_mode(),
+            face_value: invoice.amount,
+        })?;
+
+        msg!("Invoice {} funded by {} for {} USDC", invoice.invoice_id, ctx.accounts.investor.key(), amount);
+        Ok(())
+    }
+
+    // fund_invoice for an invoice denominated in wrapped SOL, paid in lamports. The
+    // investor's associated wSOL account, which they open beforehand, is topped up
+    // with whatever the principal, premium and originator fee need beyond what it
+    // already holds, then funds the invoice as usual. Other mints are refused.
+    pub fn fund_invoice_with_sol(
+        ctx: Context<FundInvoice>,
+        amount: u64,
+        max_premium: u64,
+        expected_risk_score: u8,
+        expected_due_date: i64,
+    ) -> Result<()> {
+        let invoice = &ctx.accounts.invoice;
+        require_keys_eq!(invoice.mint, spl_token::native_mint::ID, ErrorCode::NativeMintOnly);
+
+        let total_cost = amount
+            .checked_add(invoice.investor_premium())
+            .and_then(|cost| cost.checked_add(invoice.investor_originator_fee()))
+            .ok_or(ErrorCode::MathOverflow)?;
+        let shortfall = total_cost.saturating_sub(ctx.accounts.investor_token_account.amount);
+        if shortfall > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.investor.to_account_info(),
+                        to: ctx.accounts.investor_token_account.to_account_info(),
+                    },
+                ),
+                shortfall,
+            )?;
+            token::sync_native(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                SyncNative { account: ctx.accounts.investor_token_account.to_account_info() },
+            ))?;
+            ctx.accounts.investor_token_account.reload()?;
+            msg!("Wrapped {} lamports for invoice {}", shortfall, invoice.invoice_id);
+        }
+
+        fund_invoice(ctx, amount, false, max_premium, expected_risk_score, expected_due_date)
+    }
+
+    // The business takes an escrowed funding before its deadline: the principal is
+    // released to them, the premium to the insurance pool, and the investor's
+    // position receipt is minted
+    pub fn accept_funding(ctx: Context<AcceptFunding>) -> Result<()> {
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(invoice.status == InvoiceStatus::FundedPendingAcceptance, ErrorCode::FundingNotEscrowed);
+        let escrow = invoice.escrow.ok_or(ErrorCode::FundingNotEscrowed)?;
+        require!(current_time <= escrow.deadline, ErrorCode::AcceptanceWindowClosed);
+        require_no_delegate(&ctx.accounts.invoice_vault)?;
+
+        let invoice_id_bytes = invoice.invoice_id.to_le_bytes();
+        let seeds = &[INVOICE_SEED, invoice.business_owner.as_ref(), invoice_id_bytes.as_ref(), &[invoice.bump]];
+        let signer_seeds = &[&seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.invoice_vault.to_account_info(),
+                    to: ctx.accounts.business_token_account.to_account_info(),
+                    authority: invoice_info.clone(),
+                },
+                signer_seeds,
+            ),
+            invoice.funded_amount,
+        )?;
+        // Custody-funded premiums never left custody; they are swept from there
+        if escrow.premium > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.invoice_vault.to_account_info(),
+                        to: ctx.accounts.insurance_pool_account.to_account_info(),
+                        authority: invoice_info.clone(),
+                    },
+                    signer_seeds,
+                ),
+                escrow.premium,
+            )?;
+            global_state.collect_premium(escrow.premium)?;
+        }
+
+        mint_position_receipt(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.receipt_mint.to_account_info(),
+            ctx.accounts.investor_receipt.to_account_info(),
+            invoice,
+            invoice_info,
+        )?;
+
+        invoice.transition(InvoiceStatus::Funded)?;
+        invoice.released_at = Some(current_time);
+        invoice.escrow = None;
+        sync_insured_exposure(invoice, global_state)?;
+        global_state.require_coverage_capacity()?;
+        ctx.accounts.pair_ledger.record_funding(invoice.invoice_id, invoice.funded_amount, current_time)?;
+        if let Some(portfolio) = ctx.accounts.portfolio.as_deref_mut() {
+            invoice.book_portfolio(portfolio, escrow.premium)?;
+        }
+
+        emit_bounded(FundingAccepted {
+            invoice_id: invoice.invoice_id,
+            investor: invoice.investor,
+            amount: invoice.funded_amount,
+            insurance_premium: invoice.insurance_premium,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: current_time,
+        });
+
+        msg!("Invoice {} funding accepted", invoice.invoice_id);
+        Ok(())
+    }
+
+    // Once the acceptance deadline passes without the business accepting, the
+    // investor takes the escrowed principal and premium back and the invoice
+    // returns to the marketplace. The treasury adds escrow compensation for the
+    // window the capital sat out, as far as the treasury account passed holds it;
+    // an investor passing none forgoes it.
+    pub fn reclaim_escrow(ctx: Context<ReclaimEscrow>) -> Result<()> {
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(invoice.status == InvoiceStatus::FundedPendingAcceptance, ErrorCode::FundingNotEscrowed);
+        let escrow = invoice.escrow.ok_or(ErrorCode::FundingNotEscrowed)?;
+        require!(current_time > escrow.deadline, ErrorCode::AcceptanceWindowOpen);
+        require_no_delegate(&ctx.accounts.invoice_vault)?;
+
+        // Only a treasury account active on the treasury allowlist pays
+        let owed = escrow.compensation(
+            invoice.funded_amount,
+            invoice.funding_date.unwrap_or(escrow.deadline),
+            global_state.escrow_compensation_bps_per_day,
+        )?;
+        let treasury = match (
+            ctx.accounts.treasury.as_ref(),
+            ctx.accounts.treasury_allowlist.as_ref(),
+            ctx.accounts.treasury_authority.as_ref(),
+            ctx.bumps.treasury_authority,
+        ) {
+            (Some(treasury), Some(allowlist), Some(authority), Some(bump)) if owed > 0 => {
+                require_allowlisted_destination(allowlist, &treasury.key(), current_time)?;
+                require_keys_eq!(treasury.mint, invoice.mint, ErrorCode::TokenMintMismatch);
+                require_keys_eq!(treasury.owner, authority.key(), ErrorCode::TokenOwnerMismatch);
+                Some((treasury.to_account_info(), authority.to_account_info(), bump, owed.min(treasury.amount)))
+            }
+            _ => None,
+        };
+        let compensation = treasury.as_ref().map_or(0, |(_, _, _, amount)| *amount);
+
+        // Custody-funded capital goes back into the balance it was drawn from,
+        // premium and compensation included; wallet-funded capital back to the wallet
+        let refund = invoice.funded_amount.checked_add(escrow.premium).ok_or(ErrorCode::MathOverflow)?;
+        let destination = if escrow.from_balance {
+            let balance = ctx.accounts.investor_balance.as_mut().ok_or(ErrorCode::InvestorBalanceMissing)?;
+            let custody = ctx.accounts.investor_custody.as_ref().ok_or(ErrorCode::InvestorBalanceMissing)?;
+            require_keys_eq!(custody.key(), balance.custody, ErrorCode::InvalidCustodyAccount);
+            balance.refund(invoice.funded_amount, invoice.investor_premium())?;
+            balance.available = balance.available.checked_add(compensation).ok_or(ErrorCode::MathOverflow)?;
+            custody.to_account_info()
+        } else {
+            let wallet = ctx.accounts.investor_token_account.as_ref().ok_or(ErrorCode::InvestorAccountMissing)?;
+            wallet.to_account_info()
+        };
+        let invoice_id_bytes = invoice.invoice_id.to_le_bytes();
+        let seeds = &[INVOICE_SEED, invoice.business_owner.as_ref(), invoice_id_bytes.as_ref(), &[invoice.bump]];
+        let signer_seeds = &[&seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.invoice_vault.to_account_info(),
+                    to: destination.clone(),
+                    authority: invoice_info,
+                },
+                signer_seeds,
+            ),
+            refund,
+        )?;
+        if let Some((treasury, authority, bump, amount)) = treasury.filter(|(_, _, _, amount)| *amount > 0) {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer { from: treasury, to: destination, authority },
+                    &[&[TREASURY_AUTHORITY_SEED, &[bump]]],
+                ),
+                amount,
+            )?;
+        }
+
+        // Undo what the funding counted against caps and totals
+        let principal = invoice.funded_amount;
+        if let Some(debtor) = invoice.booked_debtor(ctx.accounts.debtor.as_deref_mut())? {
+            debtor.unbook(principal);
+        }
+        invoice.release_exposure(
+            Some(&mut ctx.accounts.investor_stats),
+            &mut ctx.accounts.business_profile,
+            principal,
+        )?;
+        ctx.accounts.business_profile.unrecord_funding(principal);
+        let investor_stats = &mut ctx.accounts.investor_stats;
+        investor_stats.deployed_capital = investor_stats.deployed_capital.saturating_sub(principal);
+        global_state.total_funded = global_state.total_funded.saturating_sub(principal);
+
+        let investor = invoice.investor;
+        invoice.transition(InvoiceStatus::PendingFunding)?;
+        invoice.investor = Pubkey::default();
+        invoice.funded_amount = 0;
+        invoice.remaining_balance = 0;
+        invoice.funding_date = None;
+        invoice.exposure_booked = false;
+        invoice.escrow = None;
+        sync_insured_exposure(invoice, global_state)?;
+
+        emit_bounded(EscrowReclaimed {
+            invoice_id: invoice.invoice_id,
+            investor,
+            amount: principal,
+            insurance_premium: escrow.premium,
+            compensation,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: current_time,
+        });
+
+        msg!("Invoice {} escrow reclaimed by {}", invoice.invoice_id, investor);
+        Ok(())
+    }
+
+    // Contribute part of the face value of a partial-funding invoice. Funds sit in the
+    // invoice vault until the face value is reached, then principal goes to the business
+    // and the premium to the insurance pool.
+    pub fn contribute_funding(ctx: Context<ContributeFunding>, amount: u64, max_premium: u64) -> Result<()> {
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let share = &mut ctx.accounts.funding_share;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        global_state.require_not_paused(PAUSE_FUND)?;
+        require_not_blacklisted(&ctx.accounts.investor_blacklist, BlacklistRole::Investor)?;
+        require_not_blacklisted(&ctx.accounts.business_blacklist, BlacklistRole::Business)?;
+        require!(invoice.partial_funding, ErrorCode::PartialFundingNotEnabled);
+        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+        require!(invoice.funding_open(current_time), ErrorCode::FundingWindowClosed);
+        require!(amount > 0, ErrorCode::InvalidFundingAmount);
+        global_state.require_acknowledged(invoice)?;
+        global_state.require_whitelisted(ctx.accounts.investor_whitelist.as_deref(), current_time)?;
+
+        // An over-subscribed last contribution is trimmed to what is still open
+        let contribution = amount.min(invoice.amount - invoice.funded_amount);
+        let funded_after = invoice.funded_amount.checked_add(contribution).ok_or(ErrorCode::MathOverflow)?;
+        let share_after = share.amount.checked_add(contribution).ok_or(ErrorCode::MathOverflow)?;
+        let premium = invoice.investor_premium();
+        let premium_share = pro_rata(premium, funded_after, invoice.amount) - pro_rata(premium, invoice.funded_amount, invoice.amount);
+        require!(premium_share <= max_premium, ErrorCode::SlippageExceeded);
+
+        let outlay = contribution.checked_add(premium_share).ok_or(ErrorCode::MathOverflow)?;
+        require!(ctx.accounts.investor_token_account.amount >= outlay, ErrorCode::InsufficientFunds);
+
+        let investor_stats = &mut ctx.accounts.investor_stats;
+        investor_stats.require_retail_guardrails(global_state.retail_guardrails, share_after, invoice.risk_score)?;
+        investor_stats.investor = ctx.accounts.investor.key();
+        investor_stats.bump = ctx.bumps.investor_stats;
+        investor_stats.deployed_capital = investor_stats.deployed_capital.checked_add(contribution).ok_or(ErrorCode::MathOverflow)?;
+
+        global_state.record_daily_funding(contribution, current_time)?;
+        require_sound_vault(&ctx.accounts.invoice_vault, &invoice.key())?;
+
+        // Exposure is booked once the invoice is fully funded, but no contribution
+        // goes into an invoice whose debtor has no room left for it
+        let debtor = invoice.booked_debtor(ctx.accounts.debtor.as_deref_mut())?;
+        if let Some(debtor) = &debtor {
+            debtor.require_capacity(invoice.amount, global_state.debtor_exposure_cap)?;
+        }
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.investor_token_account.to_account_info(),
+                to: ctx.accounts.invoice_vault.to_account_info(),
+                authority: ctx.accounts.investor.to_account_info(),
+            },
+        );
+        token::transfer(transfer_ctx, outlay)?;
+
+        // A repeat contribution tops up the investor's existing share
+        if share.amount == 0 {
+            share.invoice = invoice.key();
+            share.investor = ctx.accounts.investor.key();
+            share.bump = ctx.bumps.funding_share;
+            invoice.contributor_count += 1;
+        }
+        share.amount = share_after;
+        share.premium_paid = share.premium_paid.checked_add(premium_share).ok_or(ErrorCode::MathOverflow)?;
+        invoice.funded_amount = funded_after;
+
+        emit_bounded(FundingContributed {
+            invoice_id: invoice.invoice_id,
+            investor: share.investor,
+            amount: contribution,
+            premium: premium_share,
+            funded_amount: funded_after,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: current_time,
+        });
+
+        if funded_after < invoice.amount {
+            msg!("Invoice {} partially funded: {}/{} USDC", invoice.invoice_id, funded_after, invoice.amount);
+            return Ok(());
+        }
+
+        // Fully funded: release principal and premium from the vault
+        let invoice_id_bytes = invoice.invoice_id.to_le_bytes();
+        let seeds = &[INVOICE_SEED, invoice.business_owner.as_ref(), invoice_id_bytes.as_ref(), &[invoice.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_principal_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.invoice_vault.to_account_info(),
+                to: ctx.accounts.business_token_account.to_account_info(),
+                authority: invoice_info.clone(),
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_principal_ctx, invoice.amount)?;
+
+        let transfer_premium_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.invoice_vault.to_account_info(),
+                to: ctx.accounts.insurance_pool_account.to_account_info(),
+                authority: invoice_info,
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_premium_ctx, premium)?;
+
+        invoice.transition(InvoiceStatus::Funded)?;
+        invoice.param_versions.funded = global_state.param_version;
+        invoice.remaining_balance = invoice.amount;
+        invoice.funding_date = Some(current_time);
+        invoice.fix_term_yield(current_time)?;
+        invoice.released_at = Some(current_time);
+        sync_insured_exposure(invoice, global_state)?;
+        if let Some(debtor) = debtor {
+            debtor.book_funding(invoice.amount, global_state.debtor_exposure_cap)?;
+        }
+        ctx.accounts.business_profile.record_funding(1, invoice.amount)?;
+        let expected_return = invoice.expected_return.unwrap_or(invoice.amount);
+
+        record_experiment_outcome(invoice, ctx.accounts.experiment.as_mut(), ExperimentOutcome::Funded)?;
+
+        global_state.total_funded = global_state.total_funded.checked_add(invoice.amount).ok_or(ErrorCode::MathOverflow)?;
+        global_state.collect_premium(premium)?;
+        global_state.require_coverage_capacity()?;
+
+        emit_indexed(event_authority!(ctx), InvoiceFunded {
+            invoice_id: invoice.invoice_id,
+            investor: invoice.investor,
+            amount: invoice.amount,
+            insurance_premium: invoice.insurance_premium,
+            expected_return,
+            apr_bps: invoice.apr_bps,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: current_time,
+            coverage_utilization_bps: global_state.coverage_utilization_bps(),
+            originator_fee: invoice.originator_fee_paid,
+            funding_mode: invoice.funding_mode(),
+            face_value: invoice.amount,
+        })?;
+
+        msg!("Invoice {} fully funded by {} investors", invoice.invoice_id, invoice.contributor_count);
+        Ok(())
+    }
+
+    // Refund a contribution (principal and premium share) to an invoice that never
+    // reached its face value before its funding deadline
+    pub fn withdraw_contribution(ctx: Context<WithdrawContribution>) -> Result<()> {
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let invoice = &mut ctx.accounts.invoice;
+        let share = &ctx.accounts.funding_share;
+
+        require!(
+            matches!(invoice.status, InvoiceStatus::PendingFunding | InvoiceStatus::Expired),
+            ErrorCode::InvoiceNotAvailable
+        );
+        require!(
+            !invoice.funding_open(Clock::get()?.unix_timestamp),
+            ErrorCode::FundingWindowOpen
+        );
+        // The first withdrawal after the deadline retires the listing
+        if invoice.status == InvoiceStatus::PendingFunding {
+            invoice.transition(InvoiceStatus::Expired)?;
+            ctx.accounts.business_profile.close_invoice();
+        }
+
+        let refund = share.amount + share.premium_paid;
+        require_no_delegate(&ctx.accounts.invoice_vault)?;
+        let invoice_id_bytes = invoice.invoice_id.to_le_bytes();
+        let seeds = &[INVOICE_SEED, invoice.business_owner.as_ref(), invoice_id_bytes.as_ref(), &[invoice.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.invoice_vault.to_account_info(),
+                to: ctx.accounts.investor_token_account.to_account_info(),
+                authority: invoice_info,
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_ctx, refund)?;
+
+        invoice.funded_amount -= share.amount;
+        invoice.contributor_count -= 1;
+        let investor_stats = &mut ctx.accounts.investor_stats;
+        investor_stats.deployed_capital = investor_stats.deployed_capital.saturating_sub(share.amount);
+
+        emit_bounded(ContributionWithdrawn {
+            invoice_id: invoice.invoice_id,
+            investor: share.investor,
+            amount: share.amount,
+            premium_refunded: share.premium_paid,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Contribution of {} USDC withdrawn from invoice {}", share.amount, invoice.invoice_id);
+        Ok(())
+    }
+
+    // Pull an investor's pro-rata cut of everything paid into the invoice vault so far
+    // by repayments or insurance
+    pub fn claim_share_repayment(ctx: Context<ClaimShareRepayment>) -> Result<()> {
+        let invoice = &ctx.accounts.invoice;
+        let share = &mut ctx.accounts.funding_share;
+
+        require!(
+            matches!(
+                invoice.status,
+                InvoiceStatus::PartiallyRepaid
+                    | InvoiceStatus::Disputed
+                    | InvoiceStatus::Repaid
+                    | InvoiceStatus::Defaulted
+                    | InvoiceStatus::RepaidAfterDefault
+            ),
+            ErrorCode::NothingToClaim
+        );
+
+        let entitled = pro_rata(invoice.distributable_amount, share.amount, invoice.funded_amount);
+        let payout = entitled - share.claimed;
+        require!(payout > 0, ErrorCode::NothingToClaim);
+        require_no_delegate(&ctx.accounts.invoice_vault)?;
+
+        let invoice_id_bytes = invoice.invoice_id.to_le_bytes();
+        let seeds = &[INVOICE_SEED, invoice.business_owner.as_ref(), invoice_id_bytes.as_ref(), &[invoice.bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.invoice_vault.to_account_info(),
+                to: ctx.accounts.investor_token_account.to_account_info(),
+                authority: invoice.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_ctx, payout)?;
+
+        share.claimed = entitled;
+
+        emit_bounded(ShareRepaymentClaimed {
+            invoice_id: invoice.invoice_id,
+            investor: share.investor,
+            amount: payout,
+            total_claimed: share.claimed,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("{} claimed {} USDC from invoice {}", share.investor, payout, invoice.invoice_id);
+        Ok(())
+    }
+
+    // Move USDC into the investor's program-custodied balance for approval-free funding
+    pub fn deposit_balance(ctx: Context<DepositBalance>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require_sound_vault(&ctx.accounts.investor_custody, &ctx.accounts.investor_balance.key())?;
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.investor_token_account.to_account_info(),
+                to: ctx.accounts.investor_custody.to_account_info(),
+                authority: ctx.accounts.investor.to_account_info(),
+            },
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        let balance = &mut ctx.accounts.investor_balance;
+        balance.investor = ctx.accounts.investor.key();
+        balance.custody = ctx.accounts.investor_custody.key();
+        balance.bump = ctx.bumps.investor_balance;
+        balance.available += amount;
+
+        emit_bounded(BalanceDeposited {
+            investor: balance.investor,
+            amount,
+            available: balance.available,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("{} deposited {} USDC into custody", balance.investor, amount);
+        Ok(())
+    }
+
+    // Return idle custody funds to the investor; premiums owed to the pool stay put
+    pub fn withdraw_balance(ctx: Context<WithdrawBalance>, amount: u64) -> Result<()> {
+        let balance = &mut ctx.accounts.investor_balance;
+        balance.withdraw(amount)?;
+        require_no_delegate(&ctx.accounts.investor_custody)?;
+
+        let investor_key = ctx.accounts.investor.key();
+        let seeds = &[INVESTOR_BALANCE_SEED, investor_key.as_ref(), &[balance.bump]];
+        let signer_seeds = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.investor_custody.to_account_info(),
+                to: ctx.accounts.investor_token_account.to_account_info(),
+                authority: balance.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        emit_bounded(BalanceWithdrawn {
+            investor: balance.investor,
+            amount,
+            available: balance.available,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("{} withdrew {} USDC from custody", balance.investor, amount);
+        Ok(())
+    }
+
+    // Move premiums owed by a custody balance into the insurance pool (permissionless crank)
+    pub fn sweep_balance_premium(ctx: Context<SweepBalancePremium>) -> Result<()> {
+        let balance = &mut ctx.accounts.investor_balance;
+        let amount = balance.premium_owed;
+        require!(amount > 0, ErrorCode::NothingToClaim);
+        require_no_delegate(&ctx.accounts.investor_custody)?;
+
+        let seeds = &[INVESTOR_BALANCE_SEED, balance.investor.as_ref(), &[balance.bump]];
+        let signer_seeds = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.investor_custody.to_account_info(),
+                to: ctx.accounts.insurance_pool_account.to_account_info(),
+                authority: balance.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        balance.premium_owed = 0;
+        ctx.accounts.global_state.collect_premium(amount)?;
+
+        emit_bounded(BalancePremiumSwept {
+            investor: balance.investor,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Swept {} USDC of premiums from {}'s custody", amount, balance.investor);
+        Ok(())
+    }
+
+    // Reconcile a custody balance's accounting against its token account (view function)
+    pub fn audit_investor_balance(ctx: Context<AuditInvestorBalance>) -> Result<InvestorBalanceAudit> {
+        let balance = &ctx.accounts.investor_balance;
+        let custody_amount = ctx.accounts.investor_custody.amount;
+
+        Ok(InvestorBalanceAudit {
+            investor: balance.investor,
+            available: balance.available,
+            premium_owed: balance.premium_owed,
+            custody_amount,
+            reconciled: balance.reconciles(custody_amount),
+        })
+    }
+
+    // Re-check a program-controlled token account on demand (permissionless): owner,
+    // delegate and close authority, plus its balance wherever the program tracks one.
+    // The report is emitted for monitors as well as returned.
+    pub fn verify_vault_integrity(
+        ctx: Context<VerifyVaultIntegrity>,
+        kind: ProgramVault,
+    ) -> Result<VaultIntegrityReport> {
+        let vault = &ctx.accounts.vault;
+        let global_state = &ctx.accounts.global_state;
+
+        let (expected_address, authority, expected_amount) = match kind {
+            ProgramVault::InsurancePool => {
+                let address = Pubkey::create_program_address(
+                    &[INSURANCE_POOL_SEED, &[global_state.insurance_pool_bump]],
+                    ctx.program_id,
+                )
+                .map_err(|_| ErrorCode::VaultAddressMismatch)?;
+                let authority = Pubkey::create_program_address(
+                    &[INSURANCE_POOL_AUTHORITY_SEED, &[global_state.insurance_pool_authority_bump]],
+                    ctx.program_id,
+                )
+                .map_err(|_| ErrorCode::VaultAddressMismatch)?;
+                (address, authority, Some(global_state.insurance_pool_balance))
+            }
+            ProgramVault::OutboxEscrow => {
+                let processor = ctx.accounts.payout_processor.as_ref().ok_or(ErrorCode::VaultContextMissing)?;
+                let authority = Pubkey::create_program_address(
+                    &[OUTBOX_AUTHORITY_SEED, &[processor.authority_bump]],
+                    ctx.program_id,
+                )
+                .map_err(|_| ErrorCode::VaultAddressMismatch)?;
+                // Pending entries are spread over outbox pages, so there is no single total
+                (processor.escrow, authority, None)
+            }
+            ProgramVault::InvestorCustody => {
+                let balance = ctx.accounts.investor_balance.as_ref().ok_or(ErrorCode::VaultContextMissing)?;
+                let expected = balance.available.checked_add(balance.premium_owed).ok_or(ErrorCode::MathOverflow)?;
+                (balance.custody, balance.key(), Some(expected))
+            }
+            ProgramVault::InvoiceVault => {
+                let invoice = ctx.accounts.invoice.as_ref().ok_or(ErrorCode::VaultContextMissing)?;
+                let (address, _) =
+                    Pubkey::find_program_address(&[INVOICE_VAULT_SEED, invoice.key().as_ref()], ctx.program_id);
+                // Contributions, refunds and claims are tracked per share, not per vault
+                (address, invoice.key(), None)
+            }
+        };
+        require_keys_eq!(vault.key(), expected_address, ErrorCode::VaultAddressMismatch);
+
+        let integrity = VaultIntegrity::of(vault, &authority);
+        let report = VaultIntegrityReport {
+            vault: vault.key(),
+            kind,
+            integrity,
+            amount: vault.amount,
+            expected_amount,
+            sound: integrity.is_sound() && expected_amount.unwrap_or(vault.amount) == vault.amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        };
+        emit_bounded(report.clone());
+
+        msg!("Vault {} integrity: {}", report.vault, if report.sound { "sound" } else { "FAILED" });
+        Ok(report)
+    }
+
+    // Repay invoice when debtor pays
+    // With `max_total` set the invoice is paid off at whatever it owes on execution,
+    // late fees accrued since the quote included, provided that stays within max_total
+    pub fn repay_invoice(ctx: Context<RepayInvoice>, repayment_amount: u64, max_total: Option<u64>) -> Result<()> {
+        let invoice_key = ctx.accounts.invoice.key();
+        let invoice = &mut ctx.accounts.invoice;
+
+        require!(
+            invoice.status.is_repayable(),
+            ErrorCode::InvoiceNotFunded
+        );
+        require!(repayment_amount > 0, ErrorCode::InvalidAmount);
+        // Bundled invoices are repaid through their bundle
+        require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+
+        // Allow repayment up to 30 days after due date (grace period)
+        let current_time = Clock::get()?.unix_timestamp;
+        
+        // Check if within grace period
+        let global_state = &ctx.accounts.global_state;
+        require!(
+            current_time <= invoice.due_date + global_state.config.grace_period_secs(),
+            ErrorCode::RepaymentPeriodExpired
+        );
+
+        // Bring interest and late fees up to date; payments settle late fees, then
+        // interest, then principal
+        let accrual = invoice.accrue_charges(
+            current_time,
+            global_state.min_interest_bps,
+            global_state.config.late_fee_bps_per_day,
+        )?;
+        let repayment_amount = repayment_within(repayment_amount, accrual.outstanding, max_total)?;
+
+        require!(
+            ctx.accounts.business_token_account.amount >= repayment_amount,
+            ErrorCode::InsufficientRepaymentFunds
+        );
+
+        // Transfer repayment from business owner to investor, or into the invoice
+        // vault for the share holders of a partially funded invoice or the holder
+        // of the position receipt
+        let to_vault = invoice.partial_funding || invoice.live_receipt().is_some();
+        let repayment_destination = if to_vault {
+            let vault = ctx.accounts.invoice_vault.as_ref().ok_or(ErrorCode::InvoiceVaultMissing)?;
+            require_no_delegate(vault)?;
+            vault.to_account_info()
+        } else {
+            ctx.accounts.investor_token_account.as_ref().ok_or(ErrorCode::InvestorAccountMissing)?.to_account_info()
+        };
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.business_token_account.to_account_info(),
+                to: repayment_destination,
+                authority: ctx.accounts.business_owner.to_account_info(),
+            },
+        );
+        token::transfer(transfer_ctx, repayment_amount)?;
+        if to_vault {
+            invoice.distributable_amount =
+                invoice.distributable_amount.checked_add(repayment_amount).ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        let business_profile = &mut ctx.accounts.business_profile;
+        business_profile.business_owner = invoice.business_owner;
+        business_profile.bump = ctx.bumps.business_profile;
+        record_repayment(
+            invoice,
+            invoice_key,
+            &mut ctx.accounts.global_state,
+            business_profile,
+            ctx.accounts.investor_stats.as_deref_mut(),
+            ctx.accounts.pair_ledger.as_deref_mut(),
+            ctx.accounts.portfolio.as_deref_mut(),
+            ctx.accounts.debtor.as_deref_mut(),
+            ctx.accounts.experiment.as_mut(),
+            repayment_amount,
+            accrual.days_overdue,
+            current_time,
+            event_authority!(ctx),
+        )
+    }
+
+    // Pay off several of the signer's invoices in one transaction, each in full with
+    // interest and late fees brought up to date, within `max_total` altogether.
+    // remaining_accounts starts with `count` pairs of an invoice and the account
+    // repay_invoice would pay it into: its vault, or the investor's token account.
+    // After them come the debtor entries, investor stats, pair ledgers and
+    // portfolios the invoices touch, each once, in any order. Invoices in an
+    // experiment are repaid one at a time. Stops at the first invoice that cannot
+    // be settled, logging its index.
+    pub fn repay_invoices_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RepayInvoicesBatch<'info>>,
+        count: u8,
+        max_total: u64,
+    ) -> Result<()> {
+        let count = count as usize;
+        require!(count > 0 && count <= MAX_REPAYMENT_BATCH, ErrorCode::RepaymentBatchTooLarge);
+        require!(ctx.remaining_accounts.len() >= 2 * count, ErrorCode::InvalidBatchAccount);
+        let (item_accounts, book_accounts) = ctx.remaining_accounts.split_at(2 * count);
+        let business_owner = ctx.accounts.business_owner.key();
+        let mint = ctx.accounts.business_token_account.mint;
+        let current_time = Clock::get()?.unix_timestamp;
+        let failed_at = |index: usize| {
+            move |err: Error| {
+                msg!("Batch repayment stopped at invoice {}", index);
+                err
+            }
+        };
+
+        // Bring every invoice up to date before any money moves
+        let mut items = Vec::with_capacity(count);
+        for (index, pair) in item_accounts.chunks(2).enumerate() {
+            let global_state = &ctx.accounts.global_state;
+            let item = (|| -> Result<_> {
+                let (invoice_info, destination_info) = (&pair[0], &pair[1]);
+                let repeated = item_accounts[..2 * index].iter().step_by(2).any(|earlier| earlier.key == invoice_info.key);
+                require!(!repeated, ErrorCode::InvalidBatchAccount);
+                let mut invoice = load_invoice(invoice_info)?;
+                require_keys_eq!(invoice.business_owner, business_owner, ErrorCode::RepayerNotBusinessOwner);
+                require_keys_eq!(invoice.mint, mint, ErrorCode::TokenMintMismatch);
+                require!(
+                    invoice.status.is_repayable(),
+                    ErrorCode::InvoiceNotFunded
+                );
+                require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+                // The batch takes no experiment accounts to record the outcome in
+                require!(invoice.experiment.is_none(), ErrorCode::InvoiceInExperiment);
+                require!(
+                    current_time <= invoice.due_date + global_state.config.grace_period_secs(),
+                    ErrorCode::RepaymentPeriodExpired
+                );
+                let accrual = invoice.accrue_charges(
+                    current_time,
+                    global_state.min_interest_bps,
+                    global_state.config.late_fee_bps_per_day,
+                )?;
+
+                let to_vault = invoice.partial_funding || invoice.live_receipt().is_some();
+                let destination = Account::<TokenAccount>::try_from(destination_info)?;
+                if to_vault {
+                    let vault = Pubkey::find_program_address(&[INVOICE_VAULT_SEED, invoice_info.key.as_ref()], &crate::ID).0;
+                    require_keys_eq!(destination.key(), vault, ErrorCode::InvoiceVaultMissing);
+                    require_no_delegate(&destination)?;
+                } else {
+                    require_keys_eq!(destination.mint, invoice.mint, ErrorCode::TokenMintMismatch);
+                    require_keys_eq!(destination.owner, invoice.investor, ErrorCode::RepaymentDestinationMismatch);
+                }
+                Ok((invoice_info, destination_info, invoice, accrual, to_vault))
+            })()
+            .map_err(failed_at(index))?;
+            items.push(item);
+        }
+
+        let total = items
+            .iter()
+            .try_fold(0u64, |total, (_, _, _, accrual, _)| total.checked_add(accrual.outstanding))
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(total <= max_total, ErrorCode::SlippageExceeded);
+        require!(ctx.accounts.business_token_account.amount >= total, ErrorCode::InsufficientRepaymentFunds);
+
+        let mut books = RepaymentBooks::load(book_accounts)?;
+        let business_profile = &mut ctx.accounts.business_profile;
+        business_profile.business_owner = business_owner;
+        business_profile.bump = ctx.bumps.business_profile;
+        for (index, (invoice_info, destination_info, mut invoice, accrual, to_vault)) in items.into_iter().enumerate() {
+            (|| -> Result<()> {
+                let amount = accrual.outstanding;
+                token::transfer(
+                    CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: ctx.accounts.business_token_account.to_account_info(),
+                            to: destination_info.clone(),
+                            authority: ctx.accounts.business_owner.to_account_info(),
+                        },
+                    ),
+                    amount,
+                )?;
+                if to_vault {
+                    invoice.distributable_amount =
+                        invoice.distributable_amount.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+                }
+                let (debtor, investor_stats, pair_ledger, portfolio) = books.entries_for(&invoice)?;
+                record_repayment(
+                    &mut invoice,
+                    invoice_info.key(),
+                    &mut ctx.accounts.global_state,
+                    business_profile,
+                    investor_stats,
+                    pair_ledger,
+                    portfolio,
+                    debtor,
+                    None,
+                    amount,
+                    accrual.days_overdue,
+                    current_time,
+                    event_authority!(ctx),
+                )?;
+                store_invoice(invoice_info, &invoice)
+            })()
+            .map_err(failed_at(index))?;
+        }
+        books.store()?;
+
+        msg!("Batch of {} invoices repaid: {} USDC", count, total);
+        Ok(())
+    }
+
+    // The business or the position holder reports that the debtor disputes the
+    // invoice. Late fees stop accruing, and the invoice cannot be defaulted or
+    // claimed on, until the dispute is resolved.
+    pub fn raise_dispute(ctx: Context<RaiseDispute>, reason_hash: [u8; 32]) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &ctx.accounts.global_state;
+        let party = ctx.accounts.party.key();
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(!invoice.dispute_active(), ErrorCode::InvoiceDisputed);
+        require!(
+            invoice.status.is_repayable(),
+            ErrorCode::InvoiceNotFunded
+        );
+        require!(
+            party == invoice.business_owner
+                || invoice.is_position_holder(&party, ctx.accounts.investor_receipt.as_deref()),
+            ErrorCode::Unauthorized
+        );
+
+        // Fees owed for the days before the dispute stay owed
+        invoice.accrue_charges(current_time, global_state.min_interest_bps, global_state.config.late_fee_bps_per_day)?;
+        invoice.dispute = Some(Dispute {
+            raised_by: party,
+            reason_hash,
+            raised_at: current_time,
+            resolved_at: None,
+            resolution: None,
+        });
+        invoice.transition(InvoiceStatus::Disputed)?;
+
+        emit_bounded(DisputeRaised {
+            invoice_id: invoice.invoice_id,
+            raised_by: party,
+            reason_hash,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Invoice {} disputed by {}", invoice.invoice_id, party);
+        Ok(())
+    }
+
+    // The authority or the dispute arbiter settles a dispute: dismiss it, write the
+    // principal owed down, or default the invoice
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>, resolution: DisputeResolution) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let resolver = ctx.accounts.resolver.key();
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(invoice.dispute_active(), ErrorCode::NoActiveDispute);
+        // Repaid in full while disputed: only dismissing is left
+        require!(
+            resolution == DisputeResolution::Dismissed
+                || invoice.status.is_repayable(),
+            ErrorCode::InvoiceNotFunded
+        );
+        // Days overdue during the dispute are marked accrued before fees resume
+        invoice.accrue_charges(current_time, global_state.min_interest_bps, global_state.config.late_fee_bps_per_day)?;
+        let dispute = invoice.dispute.as_mut().ok_or(ErrorCode::NoActiveDispute)?;
+        dispute.resolved_at = Some(current_time);
+        dispute.resolution = Some(resolution);
+
+        // Repaid in full while disputed, the invoice has already settled
+        if invoice.status == InvoiceStatus::Disputed && resolution != DisputeResolution::Defaulted {
+            let resumed = if invoice.amount_repaid > 0 { InvoiceStatus::PartiallyRepaid } else { InvoiceStatus::Funded };
+            invoice.transition(resumed)?;
+        }
+        match resolution {
+            DisputeResolution::Dismissed => {}
+            DisputeResolution::Adjusted { remaining_balance } => {
+                require!(
+                    remaining_balance > 0 && remaining_balance < invoice.remaining_balance,
+                    ErrorCode::InvalidDisputeAdjustment
+                );
+                let written_down = invoice.remaining_balance - remaining_balance;
+                if let Some(debtor) = invoice.booked_debtor(ctx.accounts.debtor.as_deref_mut())? {
+                    debtor.release(written_down);
+                }
+                invoice.release_exposure(
+                    ctx.accounts.investor_stats.as_deref_mut(),
+                    &mut ctx.accounts.business_profile,
+                    written_down,
+                )?;
+                if let Some(portfolio) = invoice.booked_portfolio(ctx.accounts.portfolio.as_deref_mut())? {
+                    portfolio.record_write_off(written_down)?;
+                }
+                invoice.remaining_balance = remaining_balance;
+                sync_insured_exposure(invoice, global_state)?;
+            }
+            DisputeResolution::Defaulted => {
+                default_invoice(
+                    invoice,
+                    global_state,
+                    &mut ctx.accounts.business_profile,
+                    ctx.accounts.debtor.as_deref_mut(),
+                    ctx.accounts.investor_stats.as_deref_mut(),
+                    ctx.accounts.experiment.as_mut(),
+                    ctx.accounts.pair_ledger.as_deref_mut(),
+                    ctx.accounts.portfolio.as_deref_mut(),
+                    current_time,
+                )?;
+            }
+        }
+
+        let role = match authority_path(global_state, ctx.accounts.resolver.as_ref()) {
+            Some(AuthorityPath::Direct) => AdminRole::ProtocolAuthority,
+            Some(AuthorityPath::Governance) => AdminRole::Governance,
+            Some(AuthorityPath::Approvers) => AdminRole::Approver,
+            None => AdminRole::DisputeArbiter,
+        };
+        let audit_log = &mut ctx.accounts.invoice_audit_log;
+        audit_log.invoice = invoice.key();
+        audit_log.bump = ctx.bumps.invoice_audit_log;
+        audit_log.record(AdminAction {
+            actor: resolver,
+            role,
+            action: AdminActionCode::DisputeResolved,
+            timestamp: current_time,
+            amount: Some(invoice.remaining_balance),
+        });
+
+        emit_bounded(DisputeResolved {
+            invoice_id: invoice.invoice_id,
+            resolver,
+            resolution,
+            remaining_balance: invoice.remaining_balance,
+            action: AdminActionCode::DisputeResolved,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Invoice {} dispute resolved: {:?}", invoice.invoice_id, resolution);
+        Ok(())
+    }
+
+    // Push the due date out after renegotiated terms; business owner and investor
+    // both sign. One extension per invoice, at most MAX_DUE_DATE_EXTENSION_SECS.
+    pub fn extend_due_date(ctx: Context<ExtendDueDate>, new_due_date: i64) -> Result<()> {
+        require!(
+            ctx.accounts
+                .invoice
+                .is_position_holder(&ctx.accounts.investor.key(), ctx.accounts.investor_receipt.as_deref()),
+            ErrorCode::UnauthorizedInvestor
+        );
+        let invoice = &mut ctx.accounts.invoice;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(
+            invoice.status.is_repayable(),
+            ErrorCode::InvoiceNotFunded
+        );
+        require!(!invoice.partial_funding, ErrorCode::PartialFundingInvoice);
+        require!(invoice.original_due_date.is_none(), ErrorCode::DueDateAlreadyExtended);
+        let global_state = &ctx.accounts.global_state;
+        require!(
+            current_time <= invoice.due_date + global_state.config.grace_period_secs(),
+            ErrorCode::RepaymentPeriodExpired
+        );
+        require!(
+            new_due_date > invoice.due_date && new_due_date > current_time,
+            ErrorCode::InvalidDueDate
+        );
+        require!(
+            new_due_date - invoice.due_date <= MAX_DUE_DATE_EXTENSION_SECS,
+            ErrorCode::ExtensionTooLong
+        );
+
+        // Settle fees accrued under the old terms, then restart the late fee clock
+        invoice.accrue_charges(current_time, global_state.min_interest_bps, global_state.config.late_fee_bps_per_day)?;
+        invoice.late_fee_days_accrued = 0;
+
+        let old_due_date = invoice.due_date;
+        invoice.original_due_date = Some(old_due_date);
+        invoice.due_date = new_due_date;
+        invoice.payment_terms_days = ((new_due_date - invoice.created_at) / 86400) as u16;
+
+        emit_bounded(DueDateExtended {
+            invoice_id: invoice.invoice_id,
+            old_due_date,
+            new_due_date,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Invoice {} due date extended from {} to {}", invoice.invoice_id, old_due_date, new_due_date);
+        Ok(())
+    }
+
+    // Permissionless crank: flip an invoice to Defaulted once the grace period after
+    // its due date has passed without full repayment
+    pub fn mark_defaulted(ctx: Context<MarkDefaulted>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(
+            invoice.status.is_repayable(),
+            ErrorCode::InvoiceNotFunded
+        );
+        require!(
+            current_time > invoice.due_date + global_state.config.grace_period_secs(),
+            ErrorCode::GracePeriodActive
+        );
+        // A disputed invoice waits for the resolution
+        require!(!invoice.dispute_active(), ErrorCode::InvoiceDisputed);
+
+        let days_overdue = default_invoice(
+            invoice,
+            global_state,
+            &mut ctx.accounts.business_profile,
+            ctx.accounts.debtor.as_deref_mut(),
+            ctx.accounts.investor_stats.as_deref_mut(),
+            ctx.accounts.experiment.as_mut(),
+            ctx.accounts.pair_ledger.as_deref_mut(),
+            ctx.accounts.portfolio.as_deref_mut(),
+            current_time,
+        )?;
+
+        msg!("Invoice {} defaulted, {} days overdue", invoice.invoice_id, days_overdue);
+        Ok(())
+    }
+
+    // Claim insurance on a defaulted invoice
+    pub fn claim_insurance(ctx: Context<ClaimInsurance>) -> Result<()> {
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+
+        global_state.require_not_paused(PAUSE_CLAIM)?;
+        require_keys_eq!(invoice.mint, global_state.usdc_mint, ErrorCode::PrimaryMintOnly);
+        require!(invoice.status == InvoiceStatus::Defaulted, ErrorCode::InvoiceNotDefaulted);
+        require!(!invoice.dispute_active(), ErrorCode::InvoiceDisputed);
+        require!(invoice.insurance_payout.is_none(), ErrorCode::InsuranceAlreadyClaimed);
+        // Any share holder may trigger the claim on a partially funded invoice
+        let claimant_is_investor = if invoice.partial_funding {
+            matches!(&ctx.accounts.funding_share, Some(share) if share.amount > 0)
+        } else {
+            invoice.is_position_holder(&ctx.accounts.investor.key(), ctx.accounts.investor_receipt.as_deref())
+        };
+        require!(claimant_is_investor, ErrorCode::UnauthorizedInsuranceClaim);
+
+        // Only the principal still unpaid after any partial repayments is covered
+        let coverage_percentage = invoice.coverage_percentage as u64;
+        let entitlement = invoice.insured_coverage();
+
+        // An underfunded pool pays each claim the same fraction of what it is owed;
+        // the rest stays outstanding for claim_remaining_insurance
+        let pool_available = ctx.accounts.insurance_pool_account.amount.min(global_state.insurance_pool_balance);
+        let insurance_payout = insurance_payout_share(entitlement, pool_available, global_state.claims_outstanding);
+        require!(insurance_payout > 0 || entitlement == 0, ErrorCode::InsufficientInsurancePool);
+
+        // Transfer insurance payout to investor, or into the invoice vault for the
+        // share holders of a partially funded invoice
+        let payout_destination = if invoice.partial_funding {
+            let vault = ctx.accounts.invoice_vault.as_ref().ok_or(ErrorCode::InvoiceVaultMissing)?;
+            require_no_delegate(vault)?;
+            vault.to_account_info()
+        } else {
+            ctx.accounts.investor_token_account.to_account_info()
+        };
+        require_no_delegate(&ctx.accounts.insurance_pool_account)?;
+        let seeds = &[INSURANCE_POOL_AUTHORITY_SEED, &[global_state.insurance_pool_authority_bump]];
+        let signer_seeds = &[&seeds[..]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.insurance_pool_account.to_account_info(),
+                to: payout_destination,
+                authority: ctx.accounts.insurance_pool_authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_ctx, insurance_payout)?;
+        if invoice.partial_funding {
+            invoice.distributable_amount =
+                invoice.distributable_amount.checked_add(insurance_payout).ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        let claimed_at = Clock::get()?.unix_timestamp;
+        if let Some(ledger) = ctx.accounts.pair_ledger.as_mut().filter(|_| !invoice.partial_funding) {
+            ledger.record_recovery(invoice.invoice_id, SettlementKind::InsurancePayout, insurance_payout, claimed_at)?;
+        }
+        // A receipt that changed hands off-program leaves the recorded investor's
+        // portfolio behind: the payout, and any recoveries after it, are the holder's
+        if ctx.accounts.investor.key() == invoice.investor {
+            if let Some(portfolio) = invoice.booked_portfolio(ctx.accounts.portfolio.as_deref_mut())? {
+                portfolio.record_recovery(insurance_payout)?;
+            }
+        } else {
+            invoice.portfolio_booked = false;
+        }
+
+        // The claim settles the receipt: the holder also takes whatever repayments
+        // reached the vault before the default, the receipt is burned, and they
+        // become the recorded investor for any recoveries that follow
+        if let Some(receipt_mint) = invoice.live_receipt() {
+            let holder = ctx.accounts.investor.key();
+            if invoice.distributable_amount > 0 {
+                let vault = ctx.accounts.invoice_vault.as_ref().ok_or(ErrorCode::InvoiceVaultMissing)?;
+                require_no_delegate(vault)?;
+                let invoice_id_bytes = invoice.invoice_id.to_le_bytes();
+                let seeds = &[INVOICE_SEED, invoice.business_owner.as_ref(), invoice_id_bytes.as_ref(), &[invoice.bump]];
+                let signer_seeds = &[&seeds[..]];
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: vault.to_account_info(),
+                            to: ctx.accounts.investor_token_account.to_account_info(),
+                            authority: invoice_info,
+                        },
+                        signer_seeds,
+                    ),
+                    invoice.distributable_amount,
+                )?;
+                emit_bounded(ReceiptRedeemed {
+                    invoice_id: invoice.invoice_id,
+                    holder,
+                    amount: invoice.distributable_amount,
+                    invoice: invoice.key(),
+                    business_owner: invoice.business_owner,
+                    investor: invoice.investor,
+                    timestamp: claimed_at,
+                });
+                invoice.distributable_amount = 0;
+            }
+            let mint = ctx.accounts.receipt_mint.as_ref().ok_or(ErrorCode::InvalidPositionReceipt)?;
+            let receipt = ctx.accounts.investor_receipt.as_ref().ok_or(ErrorCode::InvalidPositionReceipt)?;
+            require_keys_eq!(mint.key(), receipt_mint, ErrorCode::InvalidPositionReceipt);
+            token::burn(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Burn {
+                        mint: mint.to_account_info(),
+                        from: receipt.to_account_info(),
+                        authority: ctx.accounts.investor.to_account_info(),
+                    },
+                ),
+                1,
+            )?;
+            invoice.receipt_redeemed = true;
+            invoice.investor = holder;
+            emit_bounded(ReceiptBurned {
+                invoice_id: invoice.invoice_id,
+                mint: receipt_mint,
+                holder,
+                invoice: invoice.key(),
+                business_owner: invoice.business_owner,
+                investor: invoice.investor,
+                timestamp: claimed_at,
+            });
+        }
+
+        invoice.insurance_claim_date = Some(claimed_at);
+        invoice.insurance_payout = Some(insurance_payout);
+        invoice.insurance_payout_outstanding = entitlement - insurance_payout;
+        sync_insured_exposure(invoice, global_state)?;
+
+        global_state.pay_insurance(insurance_payout)?;
+        // Defaults from before the queue was counted are not in it
+        global_state.pending_claims = global_state.pending_claims.saturating_sub(1);
+
+        emit_indexed(event_authority!(ctx), InsuranceClaimed {
+            invoice_id: invoice.invoice_id,
+            investor: invoice.investor,
+            payout_amount: insurance_payout,
+            coverage_percentage,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: claimed_at,
+            kind: InsurancePayoutKind::of(invoice.insurance_payout_outstanding),
+            payout_outstanding: invoice.insurance_payout_outstanding,
+        })?;
+
+        msg!("Insurance claimed for invoice {}: {} USDC ({}% coverage), {} outstanding",
+             invoice.invoice_id, insurance_payout, coverage_percentage, invoice.insurance_payout_outstanding);
+        Ok(())
+    }
+
+    // Pay down what a partial insurance payout left outstanding, once premiums have
+    // replenished the pool. Anyone may send it: the money only goes to the recorded
+    // investor, or the invoice vault for share holders, and the claim takes the same
+    // pro rata slice of the pool a first claim would.
+    pub fn claim_remaining_insurance(ctx: Context<ClaimRemainingInsurance>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        global_state.require_not_paused(PAUSE_CLAIM)?;
+        require!(invoice.status == InvoiceStatus::Defaulted, ErrorCode::InvoiceNotDefaulted);
+        require!(!invoice.dispute_active(), ErrorCode::InvoiceDisputed);
+        let owed = invoice.insurance_payout_outstanding;
+        require!(owed > 0, ErrorCode::NoInsuranceOutstanding);
+
+        let pool_available = ctx.accounts.insurance_pool_account.amount.min(global_state.insurance_pool_balance);
+        let payout = insurance_payout_share(owed, pool_available, global_state.claims_outstanding);
+        require!(payout > 0, ErrorCode::InsufficientInsurancePool);
+
+        let destination = if invoice.partial_funding {
+            let vault = ctx.accounts.invoice_vault.as_ref().ok_or(ErrorCode::InvoiceVaultMissing)?;
+            require_no_delegate(vault)?;
+            vault.to_account_info()
+        } else {
+            let investor_token = ctx.accounts.investor_token_account.as_ref().ok_or(ErrorCode::InvestorAccountMissing)?;
+            investor_token.to_account_info()
+        };
+        require_no_delegate(&ctx.accounts.insurance_pool_account)?;
+        let seeds = &[INSURANCE_POOL_AUTHORITY_SEED, &[global_state.insurance_pool_authority_bump]];
+        let signer_seeds = &[&seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.insurance_pool_account.to_account_info(),
+                    to: destination,
+                    authority: ctx.accounts.insurance_pool_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            payout,
+        )?;
+        if invoice.partial_funding {
+            invoice.distributable_amount = invoice.distributable_amount.checked_add(payout).ok_or(ErrorCode::MathOverflow)?;
+        } else if let Some(ledger) = ctx.accounts.pair_ledger.as_mut() {
+            ledger.record_recovery(invoice.invoice_id, SettlementKind::InsurancePayout, payout, current_time)?;
+        }
+        if let Some(portfolio) = invoice.booked_portfolio(ctx.accounts.portfolio.as_deref_mut())? {
+            portfolio.record_recovery(payout)?;
+        }
+
+        invoice.insurance_payout = Some(invoice.insurance_payout.unwrap_or(0).checked_add(payout).ok_or(ErrorCode::MathOverflow)?);
+        invoice.insurance_payout_outstanding = owed - payout;
+        sync_insured_exposure(invoice, global_state)?;
+        global_state.pay_insurance(payout)?;
+
+        emit_bounded(InsuranceRemainderPaid {
+            invoice_id: invoice.invoice_id,
+            investor: invoice.investor,
+            payout_amount: payout,
+            payout_outstanding: invoice.insurance_payout_outstanding,
+            kind: InsurancePayoutKind::of(invoice.insurance_payout_outstanding),
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: current_time,
+        });
+
+        msg!("Insurance shortfall paid on invoice {}: {} USDC, {} outstanding",
+             invoice.invoice_id, payout, invoice.insurance_payout_outstanding);
+        Ok(())
+    }
+
+    // Provide liquidity to the insurance pool in exchange for pool shares
+    pub fn deposit_insurance_liquidity(ctx: Context<DepositInsuranceLiquidity>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let global_state = &mut ctx.accounts.global_state;
+
+        // The first deposit sets shares 1:1 with USDC; later ones buy in at the
+        // current pool value, so premiums earned and claims paid accrue to holders
+        let shares = if global_state.total_lp_shares == 0 {
+            amount
+        } else {
+            pro_rata(global_state.total_lp_shares, amount, global_state.insurance_pool_balance)
+        };
+        require!(shares > 0, ErrorCode::InvalidAmount);
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.provider_token_account.to_account_info(),
+                to: ctx.accounts.insurance_pool_account.to_account_info(),
+                authority: ctx.accounts.provider.to_account_info(),
+            },
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        let position = &mut ctx.accounts.lp_position;
+        position.provider = ctx.accounts.provider.key();
+        position.bump = ctx.bumps.lp_position;
+        position.shares += shares;
+        global_state.total_lp_shares += shares;
+        global_state.insurance_pool_balance += amount;
+
+        emit_bounded(InsuranceLiquidityDeposited {
+            provider: position.provider,
+            amount,
+            shares,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("{} deposited {} USDC into the insurance pool for {} shares", position.provider, amount, shares);
+        Ok(())
+    }
+
+    // First step of an LP withdrawal: lock in how many shares to burn. The shares keep
+    // bearing losses through the cooldown, so an LP cannot dodge a default they see coming.
+    pub fn request_insurance_withdrawal(ctx: Context<RequestInsuranceWithdrawal>, shares: u64) -> Result<()> {
+        let position = &mut ctx.accounts.lp_position;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(shares > 0 && shares <= position.shares, ErrorCode::InsufficientLpShares);
+        require!(position.pending_withdrawal_shares == 0, ErrorCode::WithdrawalAlreadyRequested);
+
+        let available_at = current_time + LP_WITHDRAWAL_COOLDOWN_SECS;
+        position.pending_withdrawal_shares = shares;
+        position.withdrawal_available_at = available_at;
+
+        emit_bounded(InsuranceWithdrawalRequested {
+            provider: position.provider,
+            shares,
+            available_at,
+            timestamp: current_time,
+        });
+
+        msg!("{} requested withdrawal of {} shares, available at {}", position.provider, shares, available_at);
+        Ok(())
+    }
+
+    // Burn the requested shares for their current value once the cooldown is over, as
+    // long as the pool still covers all committed insurance afterwards
+    pub fn withdraw_insurance_liquidity(ctx: Context<WithdrawInsuranceLiquidity>) -> Result<()> {
+        let position = &mut ctx.accounts.lp_position;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let shares = position.pending_withdrawal_shares;
+        require!(shares > 0, ErrorCode::WithdrawalNotRequested);
+        require!(current_time >= position.withdrawal_available_at, ErrorCode::TimelockNotElapsed);
+
+        let amount = pro_rata(global_state.insurance_pool_balance, shares, global_state.total_lp_shares);
+        require!(
+            global_state.insurance_pool_balance - amount >= global_state.insured_exposure,
+            ErrorCode::PoolUtilizationLimit
+        );
+        require_no_delegate(&ctx.accounts.insurance_pool_account)?;
+
+        let seeds = &[INSURANCE_POOL_AUTHORITY_SEED, &[global_state.insurance_pool_authority_bump]];
+        let signer_seeds = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.insurance_pool_account.to_account_info(),
+                to: ctx.accounts.provider_token_account.to_account_info(),
+                authority: ctx.accounts.insurance_pool_authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        position.shares -= shares;
+        position.pending_withdrawal_shares = 0;
+        global_state.total_lp_shares -= shares;
+        global_state.insurance_pool_balance -= amount;
+
+        emit_bounded(InsuranceLiquidityWithdrawn {
+            provider: position.provider,
+            shares,
+            amount,
+            timestamp: current_time,
+        });
+
+        msg!("{} withdrew {} USDC from the insurance pool", position.provider, amount);
+        Ok(())
+    }
+
+    // Propose moving surplus premiums out of the insurance pool; executable only after
+    // the timelock, giving LPs and investors time to react
+    pub fn propose_pool_withdrawal(ctx: Context<ProposePoolWithdrawal>, amount: u64) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(
+            ctx.accounts.destination.mint == global_state.usdc_mint,
+            ErrorCode::InvalidDestinationMint
+        );
+        require!(amount <= global_state.pool_surplus(), ErrorCode::PoolUtilizationLimit);
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.amount = amount;
+        proposal.destination = ctx.accounts.destination.key();
+        proposal.proposed_at = current_time;
+        proposal.executable_at = current_time + POOL_WITHDRAWAL_TIMELOCK_SECS;
+        proposal.bump = ctx.bumps.proposal;
+
+        emit_bounded(PoolWithdrawalProposed {
+            amount,
+            destination: proposal.destination,
+            executable_at: proposal.executable_at,
+            action: AdminActionCode::PoolWithdrawalProposed,
+            timestamp: current_time,
+        });
+
+        msg!("Pool withdrawal of {} USDC proposed, executable at {}", amount, proposal.executable_at);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::PoolWithdrawalProposed,
+            Some(amount),
+        )
+    }
+
+    // Carry out a proposed pool withdrawal once its timelock has elapsed. The cap is
+    // checked again since insured exposure may have grown in the meantime.
+    pub fn execute_pool_withdrawal(ctx: Context<ExecutePoolWithdrawal>) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let proposal = &ctx.accounts.proposal;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+        let amount = proposal.amount;
+
+        require!(current_time >= proposal.executable_at, ErrorCode::TimelockNotElapsed);
+        require!(amount <= global_state.pool_surplus(), ErrorCode::PoolUtilizationLimit);
+        require_allowlisted_destination(&ctx.accounts.allowlist, &proposal.destination, current_time)?;
+        require_no_delegate(&ctx.accounts.insurance_pool_account)?;
+
+        let seeds = &[INSURANCE_POOL_AUTHORITY_SEED, &[global_state.insurance_pool_authority_bump]];
+        let signer_seeds = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.insurance_pool_account.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: ctx.accounts.insurance_pool_authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        global_state.insurance_pool_balance -= amount;
+
+        emit_bounded(PoolWithdrawalExecuted {
+            amount,
+            destination: proposal.destination,
+            action: AdminActionCode::PoolWithdrawalExecuted,
+            timestamp: current_time,
+        });
+
+        msg!("Pool withdrawal of {} USDC to {} executed", amount, proposal.destination);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::PoolWithdrawalExecuted,
+            Some(amount),
+        )
+    }
+
+    // Drop a pending pool withdrawal proposal
+    pub fn cancel_pool_withdrawal(ctx: Context<CancelPoolWithdrawal>) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Fast)?;
+        let proposal = &ctx.accounts.proposal;
+
+        emit_bounded(PoolWithdrawalCancelled {
+            amount: proposal.amount,
+            destination: proposal.destination,
+            action: AdminActionCode::PoolWithdrawalCancelled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Pool withdrawal of {} USDC cancelled", proposal.amount);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::PoolWithdrawalCancelled,
+            Some(proposal.amount),
+        )
+    }
+
+    // Move tokens someone sent to one of the program's PDAs by mistake out to
+    // `destination`, which must be active on the lost-and-found allowlist. `kind`
+    // names the PDA owning `source` as in verify_vault_integrity, with the account
+    // locating it. Tokens in any invoice mint, and the vault of that kind itself,
+    // are refused, so insured and invested funds never leave this way.
+    pub fn recover_foreign_tokens(ctx: Context<RecoverForeignTokens>, kind: ProgramVault, amount: u64) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &ctx.accounts.global_state;
+        let source = &ctx.accounts.source;
+        require!(amount > 0 && amount <= source.amount, ErrorCode::InvalidAmount);
+
+        // The PDA that owns the source, the seeds it signs with, and the vault the
+        // program tracks under it
+        let (seeds, tracked): (Vec<Vec<u8>>, Option<(Pubkey, Pubkey)>) = match kind {
+            ProgramVault::InsurancePool => {
+                let pool = Pubkey::create_program_address(&[INSURANCE_POOL_SEED, &[global_state.insurance_pool_bump]], ctx.program_id)
+                    .map_err(|_| ErrorCode::VaultAddressMismatch)?;
+                (
+                    vec![INSURANCE_POOL_AUTHORITY_SEED.to_vec(), vec![global_state.insurance_pool_authority_bump]],
+                    Some((pool, global_state.usdc_mint)),
+                )
+            }
+            ProgramVault::OutboxEscrow => {
+                let processor = ctx.accounts.payout_processor.as_ref().ok_or(ErrorCode::VaultContextMissing)?;
+                (vec![OUTBOX_AUTHORITY_SEED.to_vec(), vec![processor.authority_bump]], Some((processor.escrow, global_state.usdc_mint)))
+            }
+            ProgramVault::InvestorCustody => {
+                let balance = ctx.accounts.investor_balance.as_ref().ok_or(ErrorCode::VaultContextMissing)?;
+                (
+                    vec![INVESTOR_BALANCE_SEED.to_vec(), balance.investor.to_bytes().to_vec(), vec![balance.bump]],
+                    Some((balance.custody, global_state.usdc_mint)),
+                )
+            }
+            ProgramVault::InvoiceVault => {
+                let invoice = ctx.accounts.invoice.as_ref().ok_or(ErrorCode::VaultContextMissing)?;
+                let (vault, _) = Pubkey::find_program_address(&[INVOICE_VAULT_SEED, invoice.key().as_ref()], ctx.program_id);
+                (
+                    vec![
+                        INVOICE_SEED.to_vec(),
+                        invoice.business_owner.to_bytes().to_vec(),
+                        invoice.invoice_id.to_le_bytes().to_vec(),
+                        vec![invoice.bump],
+                    ],
+                    Some((vault, invoice.mint)),
+                )
+            }
+        };
+        let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+        let owner = Pubkey::create_program_address(&seeds, ctx.program_id).map_err(|_| ErrorCode::VaultAddressMismatch)?;
+        require_keys_eq!(ctx.accounts.owner.key(), owner, ErrorCode::VaultAddressMismatch);
+        require_keys_eq!(source.owner, owner, ErrorCode::TokenOwnerMismatch);
+        require!(
+            is_foreign(
+                &source.mint,
+                &source.key(),
+                global_state.is_approved_mint(&source.mint),
+                tracked.as_ref().map(|(vault, mint)| (vault, mint)),
+            ),
+            ErrorCode::RecoveryProtected
+        );
+
+        let current_time = Clock::get()?.unix_timestamp;
+        require_allowlisted_destination(&ctx.accounts.allowlist, &ctx.accounts.destination.key(), current_time)?;
+
+        let signer = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: source.to_account_info(),
+                to: ctx.accounts.destination.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        emit_bounded(ForeignTokensRecovered {
+            kind,
+            source: source.key(),
+            mint: source.mint,
+            amount,
+            destination: ctx.accounts.destination.key(),
+            action: AdminActionCode::ForeignTokensRecovered,
+            timestamp: current_time,
+        });
+
+        msg!("Recovered {} of mint {} to {}", amount, source.mint, ctx.accounts.destination.key());
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::ForeignTokensRecovered,
+            Some(amount),
+        )
+    }
+
+    // Send lamports a program-owned account holds above its rent exemption, from a
+    // stray SOL transfer, to `destination`, which must be active on the lost-and-found
+    // allowlist. The account keeps exactly its rent. The crank treasury is refused,
+    // since its surplus is what pays crank rewards.
+    pub fn recover_excess_lamports(ctx: Context<RecoverExcessLamports>) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let account = &ctx.accounts.account;
+        let (treasury, _) = Pubkey::find_program_address(&[CRANK_TREASURY_SEED], ctx.program_id);
+        require_keys_neq!(account.key(), treasury, ErrorCode::RecoveryProtected);
+
+        let rent = Rent::get()?.minimum_balance(account.data_len());
+        let amount = account.lamports().saturating_sub(rent);
+        require!(amount > 0, ErrorCode::NothingToRecover);
+        let current_time = Clock::get()?.unix_timestamp;
+        require_allowlisted_destination(&ctx.accounts.allowlist, &ctx.accounts.destination.key(), current_time)?;
+        **account.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.destination.try_borrow_mut_lamports()? += amount;
+
+        emit_bounded(ExcessLamportsRecovered {
+            account: account.key(),
+            amount,
+            destination: ctx.accounts.destination.key(),
+            action: AdminActionCode::ExcessLamportsRecovered,
+            timestamp: current_time,
+        });
+
+        msg!("Recovered {} lamports above rent from {}", amount, account.key());
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::ExcessLamportsRecovered,
+            Some(amount),
+        )
+    }
+
+    // Get invoice details (view function). The details go out as return data, so
+    // clients simulate the instruction and decode them (`.view()` in the TS client)
+    pub fn get_invoice_details(ctx: Context<GetInvoiceDetails>) -> Result<InvoiceDetails> {
+        Ok(InvoiceDetails::from(&*ctx.accounts.invoice))
+    }
+
+    // Canonical snapshot of a live listing's key terms for partner sites to embed
+    // (view function); see listing.rs for the byte layout
+    pub fn get_listing_proof(ctx: Context<GetListingProof>) -> Result<ListingSnapshot> {
+        let invoice = &ctx.accounts.invoice;
+        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+
+        let terms = listing::ListingTerms::of(invoice.key(), invoice);
+        let terms_hash = terms.hash();
+        let proof = listing::ListingProof {
+            terms,
+            slot: Clock::get()?.slot,
+        };
+
+        Ok(ListingSnapshot {
+            proof: proof.to_bytes(),
+            terms_hash,
+            matches_published: invoice.published_listing_hash.map(|published| published == terms_hash),
+        })
+    }
+
+    // Anchor the hash of the listing's current terms on the invoice, so material
+    // built from an older proof can be recognised as stale
+    pub fn publish_listing_proof(ctx: Context<PublishListingProof>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+
+        let terms_hash = listing::ListingTerms::of(invoice.key(), invoice).hash();
+        invoice.published_listing_hash = Some(terms_hash);
+
+        emit_bounded(ListingProofPublished {
+            invoice_id: invoice.invoice_id,
+            terms_hash,
+            slot: Clock::get()?.slot,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Listing proof published for invoice {}", invoice.invoice_id);
+        Ok(())
+    }
+
+    // Get details for a watchlist of invoices passed as remaining accounts (view function)
+    pub fn get_invoices_details_batch(ctx: Context<GetInvoicesDetailsBatch>) -> Result<InvoiceBatch> {
+        let count = ctx.remaining_accounts.len();
+        require!(count <= MAX_DETAILS_BATCH, ErrorCode::TooManyInvoices);
+
+        // Full details only while the whole response fits in return data
+        let mode = if InvoiceBatch::full_size(count) <= MAX_RETURN_DATA {
+            BatchMode::Full
+        } else {
+            BatchMode::Compact
+        };
+
+        let entries = ctx
+            .remaining_accounts
+            .iter()
+            .map(|info| match read_batch_invoice(info) {
+                Ok(invoice) => match mode {
+                    BatchMode::Full => InvoiceBatchEntry::Full(InvoiceDetails::from(&invoice)),
+                    BatchMode::Compact => InvoiceBatchEntry::Compact(InvoiceSummary::from(&invoice)),
+                },
+                Err(status) => InvoiceBatchEntry::Unavailable(status),
+            })
+            .collect();
+
+        Ok(InvoiceBatch { mode, entries })
+    }
+
+    // Erase debtor personal data from a settled invoice once the retention period lapsed
+    pub fn erase_personal_data(ctx: Context<ErasePersonalData>) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &ctx.accounts.global_state;
+        let signer = ctx.accounts.signer.key();
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(
+            signer == invoice.business_owner || signer == global_state.authority,
+            ErrorCode::Unauthorized
+        );
+        require!(!invoice.erased, ErrorCode::AlreadyErased);
+        let settled_at = invoice.settled_at().ok_or(ErrorCode::InvoiceNotTerminal)?;
+        require!(
+            current_time >= settled_at + global_state.retention_period_secs,
+            ErrorCode::RetentionPeriodActive
+        );
+
+        let content_hash = invoice.erase_personal_data();
+
+        let audit_log = &mut ctx.accounts.invoice_audit_log;
+        audit_log.invoice = invoice.key();
+        audit_log.bump = ctx.bumps.invoice_audit_log;
+        audit_log.record(AdminAction {
+            actor: signer,
+            role: if signer == global_state.authority {
+                AdminRole::ProtocolAuthority
+            } else {
+                AdminRole::BusinessOwner
+            },
+            action: AdminActionCode::PersonalDataErased,
+            timestamp: current_time,
+            amount: None,
+        });
+
+        emit_bounded(PersonalDataErased {
+            invoice_id: invoice.invoice_id,
+            business_owner: invoice.business_owner,
+            erased_by: signer,
+            content_hash,
+            erased_at: current_time,
+            action: AdminActionCode::PersonalDataErased,
+            invoice: invoice.key(),
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Personal data erased from invoice {}", invoice.invoice_id);
+        Ok(())
+    }
+
+    // Whether `preimage` is the document the invoice committed to (view function).
+    // Documents too large for a transaction are checked off-chain against the same
+    // sha256, as Invoice::debtor_info_matches does.
+    pub fn verify_debtor_info(ctx: Context<VerifyDebtorInfo>, preimage: Vec<u8>) -> Result<bool> {
+        Ok(ctx.accounts.invoice.debtor_info_matches(&preimage))
+    }
+
+    // Bring an invoice on an older layout up to INVOICE_VERSION; a no-op on one that
+    // is current. Anyone may crank it, as it only rewrites what the account holds:
+    // rent a smaller layout frees goes back to the business, and the payer tops up
+    // a larger one.
+    pub fn migrate_invoice(ctx: Context<MigrateInvoice>) -> Result<()> {
+        let info = ctx.accounts.invoice.to_account_info();
+        let (from_version, invoice) = match migrate_invoice_data(&info.try_borrow_data()?)? {
+            Some(migrated) => migrated,
+            None => {
+                msg!("Invoice already at version {}", INVOICE_VERSION);
+                return Ok(());
+            }
+        };
+        require_keys_eq!(invoice.business_owner, ctx.accounts.business_owner.key(), ErrorCode::InvoiceOwnerMismatch);
+
+        let previous_size = info.data_len();
+        let rent_refunded = resize_for_migration(
+            &info,
+            Invoice::SIZE,
+            &ctx.accounts.business_owner.to_account_info(),
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+        store_invoice(&info, &invoice)?;
+
+        emit_bounded(InvoiceMigrated {
+            invoice_id: invoice.invoice_id,
+            from_version,
+            version: INVOICE_VERSION,
+            debtor_info_hash: invoice.debtor_info_hash,
+            previous_size: previous_size as u32,
+            size: Invoice::SIZE as u32,
+            rent_refunded,
+            invoice: info.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Invoice {} migrated to version {}", invoice.invoice_id, INVOICE_VERSION);
+        Ok(())
+    }
+
+    // Bring the global state up to GLOBAL_STATE_VERSION, filling in defaults for the
+    // fields it gained; a no-op once it is current. Permissionless like
+    // migrate_invoice, with the authority getting back any rent freed.
+    pub fn migrate_global_state(ctx: Context<MigrateGlobalState>) -> Result<()> {
+        let info = ctx.accounts.global_state.to_account_info();
+        let previous_size = info.data_len();
+        let (from_version, global_state) = match migrate_global_state_data(&info.try_borrow_data()?)? {
+            Some(migrated) => migrated,
+            None => {
+                msg!("Global state already at version {}", GLOBAL_STATE_VERSION);
+                return Ok(());
+            }
+        };
+        require_keys_eq!(global_state.authority, ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+
+        let rent_refunded = resize_for_migration(
+            &info,
+            GlobalState::SIZE,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+        global_state.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        emit_bounded(GlobalStateMigrated {
+            from_version,
+            version: GLOBAL_STATE_VERSION,
+            previous_size: previous_size as u32,
+            size: GlobalState::SIZE as u32,
+            rent_refunded,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Global state migrated to version {}", GLOBAL_STATE_VERSION);
+        Ok(())
+    }
+
+    // Tighten the daily funding cap immediately, or schedule an increase behind the timelock
+    pub fn set_daily_funding_cap(ctx: Context<UpdateGovernedParams>, new_cap: u64) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+        let old_cap = global_state.daily_funding_cap;
+
+        if is_cap_tightening(old_cap, new_cap) {
+            global_state.daily_funding_cap = new_cap;
+            global_state.pending_daily_funding_cap = None;
+
+            emit_bounded(DailyFundingCapUpdated {
+                old_cap,
+                new_cap,
+                action: AdminActionCode::DailyFundingCapSet,
+                timestamp: current_time,
+            });
+            msg!("Daily funding cap tightened from {} to {}", old_cap, new_cap);
+            record_param_changes(
+                global_state,
+                &mut ctx.accounts.param_history,
+                ctx.accounts.authority.key(),
+                param_diff(&[(ParamId::DailyFundingCap, old_cap)], &[(ParamId::DailyFundingCap, new_cap)]),
+            )?;
+        } else {
+            let effective_at = current_time + PARAMETER_TIMELOCK_SECS;
+            global_state.pending_daily_funding_cap = Some(new_cap);
+            global_state.pending_daily_funding_cap_at = effective_at;
+
+            emit_bounded(DailyFundingCapIncreaseProposed {
+                old_cap,
+                new_cap,
+                effective_at,
+                action: AdminActionCode::DailyFundingCapSet,
+                timestamp: current_time,
+            });
+            msg!("Daily funding cap increase to {} scheduled for {}", new_cap, effective_at);
+        }
+
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::DailyFundingCapSet,
+            Some(new_cap),
+        )
+    }
+
+    // Apply a scheduled daily cap increase once its timelock has elapsed
+    pub fn apply_daily_funding_cap(ctx: Context<UpdateGovernedParams>) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let new_cap = global_state
+            .pending_daily_funding_cap
+            .ok_or(ErrorCode::NoPendingChange)?;
+        require!(
+            current_time >= global_state.pending_daily_funding_cap_at,
+            ErrorCode::TimelockNotElapsed
+        );
+
+        let old_cap = global_state.daily_funding_cap;
+        global_state.daily_funding_cap = new_cap;
+        global_state.pending_daily_funding_cap = None;
+
+        emit_bounded(DailyFundingCapUpdated {
+            old_cap,
+            new_cap,
+            action: AdminActionCode::DailyFundingCapApplied,
+            timestamp: current_time,
+        });
+        msg!("Daily funding cap raised from {} to {}", old_cap, new_cap);
+        record_param_changes(
+            global_state,
+            &mut ctx.accounts.param_history,
+            ctx.accounts.authority.key(),
+            param_diff(&[(ParamId::DailyFundingCap, old_cap)], &[(ParamId::DailyFundingCap, new_cap)]),
+        )?;
+
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::DailyFundingCapApplied,
+            Some(new_cap),
+        )
+    }
+
+    // Get today's funding capacity under the daily cap (view function)
+    pub fn get_daily_funding_capacity(ctx: Context<GetDailyFundingCapacity>) -> Result<DailyFundingCapacity> {
+        let global_state = &ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+        let day_start = utc_day_start(current_time);
+
+        Ok(DailyFundingCapacity {
+            daily_funding_cap: global_state.daily_funding_cap,
+            funded_today: if global_state.day_start_ts == day_start { global_state.funded_today } else { 0 },
+            remaining: global_state.remaining_daily_capacity(current_time),
+            resets_at: day_start + 86400,
+        })
+    }
+
+    // One-call protocol health probe for monitoring (view function). The pool token
+    // account is optional; without it the balance mirror is reported as unverified.
+    pub fn get_health(ctx: Context<GetHealth>) -> Result<HealthReport> {
+        let current_time = Clock::get()?.unix_timestamp;
+        let pool_token_balance = ctx.accounts.insurance_pool_account.as_ref().map(|pool| pool.amount);
+
+        Ok(assess_health(&ctx.accounts.global_state, pool_token_balance, current_time))
+    }
+
+    // Protocol totals, the pool's tracked balance against its token account,
+    // coverage utilization and today's funding headroom, for monitoring in one
+    // simulated call (view function)
+    pub fn get_global_stats(ctx: Context<GetGlobalStats>) -> Result<GlobalStats> {
+        let pool_token_balance = ctx.accounts.insurance_pool_account.as_ref().map(|pool| pool.amount);
+        Ok(ctx.accounts.global_state.stats(pool_token_balance, Clock::get()?.unix_timestamp))
+    }
+
+    // Stop new activity of the given kinds (PAUSE_* bits). Repayments are never paused.
+    pub fn pause(ctx: Context<UpdateGlobalState>, flags: u8) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Fast)?;
+        let new_flags = ctx.accounts.global_state.paused | flags;
+        set_pause_flags(ctx, new_flags, AdminActionCode::Paused)
+    }
+
+    // Resume the given kinds of activity
+    pub fn unpause(ctx: Context<UpdateGlobalState>, flags: u8) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Fast)?;
+        let new_flags = ctx.accounts.global_state.paused & !flags;
+        set_pause_flags(ctx, new_flags, AdminActionCode::Unpaused)
+    }
+
+    // Name the SPL Governance realm whose governance account may act as the authority
+    pub fn configure_governance(
+        ctx: Context<UpdateGlobalState>,
+        governance_program: Pubkey,
+        realm: Pubkey,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        global_state.governance_program = Some(governance_program);
+        global_state.governance_realm = realm;
+
+        let governance_account = governance_address(&governance_program, &realm, &global_state.key());
+        emit_bounded(GovernanceConfigured {
+            governance_program,
+            realm,
+            governance_account,
+            action: AdminActionCode::GovernanceConfigured,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Governance account set to {}", governance_account);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::GovernanceConfigured,
+            None,
+        )
+    }
+
+    // Schedule governed instructions to require (or stop requiring) the governance account
+    pub fn propose_governance_enabled(ctx: Context<UpdateGlobalState>, enabled: bool) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        if enabled {
+            require!(global_state.governance_program.is_some(), ErrorCode::GovernanceNotConfigured);
+        }
+
+        let effective_at = Clock::get()?.unix_timestamp + PARAMETER_TIMELOCK_SECS;
+        global_state.pending_governance_enabled = Some(enabled);
+        global_state.pending_governance_enabled_at = effective_at;
+
+        emit_bounded(GovernanceEnablementProposed {
+            enabled,
+            effective_at,
+            action: AdminActionCode::GovernanceEnablementProposed,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Governance {} scheduled for {}", if enabled { "enablement" } else { "disablement" }, effective_at);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::GovernanceEnablementProposed,
+            None,
+        )
+    }
+
+    // Apply a scheduled governance enablement once its timelock has elapsed
+    pub fn apply_governance_enabled(ctx: Context<UpdateGlobalState>) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+
+        let enabled = global_state
+            .pending_governance_enabled
+            .ok_or(ErrorCode::NoPendingChange)?;
+        require!(
+            Clock::get()?.unix_timestamp >= global_state.pending_governance_enabled_at,
+            ErrorCode::TimelockNotElapsed
+        );
+        global_state.governance_enabled = enabled;
+        global_state.pending_governance_enabled = None;
+
+        emit_bounded(GovernanceEnablementApplied {
+            enabled,
+            action: AdminActionCode::GovernanceEnablementApplied,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Governance {}", if enabled { "enabled" } else { "disabled" });
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::GovernanceEnablementApplied,
+            None,
+        )
+    }
+
+    // Put the guarded instructions behind `threshold` of `approvers`, whose proposals
+    // stay open for `window_secs`; an empty set with a zero threshold hands them
+    // back to the authority. Proposals raised under the previous set are void.
+    pub fn set_admin_approvers(
+        ctx: Context<UpdateGlobalState>,
+        approvers: Vec<Pubkey>,
+        threshold: u8,
+        window_secs: i64,
+    ) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(
+            approvals::is_valid_approver_set(&approvers, threshold, window_secs),
+            ErrorCode::InvalidApproverSet
+        );
+        let global_state = &mut ctx.accounts.global_state;
+        global_state.admin_approvers = approvers.clone();
+        global_state.approval_threshold = threshold;
+        global_state.approval_window_secs = window_secs;
+        global_state.approver_set_nonce = global_state.approver_set_nonce.wrapping_add(1);
+
+        emit_bounded(AdminApproversSet {
+            approvers,
+            threshold,
+            window_secs,
+            action: AdminActionCode::AdminApproversSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Admin approver set: {} of {}", threshold, global_state.admin_approvers.len());
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::AdminApproversSet,
+            None,
+        )
+    }
+
+    // Propose a guarded instruction, given as the accounts and data the self-CPI in
+    // execute_admin_action will pass it, with the approvals PDA as its authority.
+    // The proposer must be an approver and counts as the first approval.
+    pub fn propose_admin_action(
+        ctx: Context<ProposeAdminAction>,
+        accounts: Vec<AdminAccountMeta>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        require!(
+            approvals::is_guarded(&data)
+                && data.len() <= approvals::MAX_ADMIN_ACTION_DATA_LEN
+                && accounts.len() <= approvals::MAX_ADMIN_ACTION_ACCOUNTS,
+            ErrorCode::InvalidAdminAction
+        );
+        let global_state = &mut ctx.accounts.global_state;
+        let proposer = ctx.accounts.proposer.key();
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.proposal_id = global_state.admin_proposal_count;
+        proposal.proposer = proposer;
+        proposal.approver_set_nonce = global_state.approver_set_nonce;
+        proposal.accounts = accounts;
+        proposal.data = data;
+        proposal.proposed_at = current_time;
+        proposal.expires_at = current_time + global_state.approval_window_secs;
+        proposal.bump = ctx.bumps.proposal;
+        let approvals = proposal.approve(global_state, &proposer, current_time)?;
+        global_state.admin_proposal_count += 1;
+
+        emit_bounded(AdminActionProposed {
+            proposal_id: proposal.proposal_id,
+            proposer,
+            instruction: proposal.instruction(),
+            data_hash: anchor_lang::solana_program::hash::hash(&proposal.data).to_bytes(),
+            expires_at: proposal.expires_at,
+            approvals,
+            threshold: global_state.approval_threshold,
+            action: AdminActionCode::AdminActionProposed,
+            timestamp: current_time,
+        });
+
+        msg!("Admin action {} proposed, expires at {}", proposal.proposal_id, proposal.expires_at);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            proposer,
+            AdminActionCode::AdminActionProposed,
+            None,
+        )
+    }
+
+    // Sign off on a pending proposal as one of the approvers
+    pub fn approve_admin_action(ctx: Context<ApproveAdminAction>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        let proposal = &mut ctx.accounts.proposal;
+        let approver = ctx.accounts.approver.key();
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let approvals = proposal.approve(global_state, &approver, current_time)?;
+
+        emit_bounded(AdminActionApproved {
+            proposal_id: proposal.proposal_id,
+            approver,
+            approvals,
+            threshold: global_state.approval_threshold,
+            action: AdminActionCode::AdminActionApproved,
+            timestamp: current_time,
+        });
+
+        msg!("Admin action {} approved by {} ({} of {})", proposal.proposal_id, approver, approvals, global_state.approval_threshold);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            approver,
+            AdminActionCode::AdminActionApproved,
+            None,
+        )
+    }
+
+    // Run an approved proposal: a self-CPI of its instruction signed by the approvals
+    // PDA, with its accounts passed as remaining accounts. Any approver may execute
+    // once the threshold is met; the proposal closes back to its proposer.
+    pub fn execute_admin_action<'info>(ctx: Context<'_, '_, 'info, 'info, ExecuteAdminAction<'info>>) -> Result<()> {
+        use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+
+        let current_time = Clock::get()?.unix_timestamp;
+        let executor = ctx.accounts.executor.key();
+        let proposal = &ctx.accounts.proposal;
+        require!(ctx.accounts.global_state.approver_index(&executor).is_some(), ErrorCode::NotAnApprover);
+        proposal.require_executable(&ctx.accounts.global_state, current_time)?;
+
+        let ix = Instruction {
+            program_id: crate::ID,
+            accounts: proposal.accounts.iter().map(AccountMeta::from).collect(),
+            data: proposal.data.clone(),
+        };
+        let mut infos = ctx.remaining_accounts.to_vec();
+        infos.push(ctx.accounts.approvals.to_account_info());
+        anchor_lang::solana_program::program::invoke_signed(
+            &ix,
+            &infos,
+            &[&[APPROVALS_SEED, &[ctx.bumps.approvals]]],
+        )?;
+
+        // The instruction wrote to the global state and admin log itself; pick up
+        // its changes before recording the execution on top of them
+        ctx.accounts.global_state.reload()?;
+        ctx.accounts.admin_log.reload()?;
+
+        emit_bounded(AdminActionExecuted {
+            proposal_id: proposal.proposal_id,
+            executor,
+            instruction: proposal.instruction(),
+            approvals: proposal.approval_count(),
+            action: AdminActionCode::AdminActionExecuted,
+            timestamp: current_time,
+        });
+
+        // Logged as the approvals PDA, which acted; the instruction may have changed
+        // the set the executor belonged to
+        msg!("Admin action {} executed by {}", proposal.proposal_id, executor);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.approvals.key(),
+            AdminActionCode::AdminActionExecuted,
+            None,
+        )
+    }
+
+    // Withdraw a proposal, returning its rent to the proposer: the proposer may at
+    // any time, anyone once it has expired or the approver set has changed. Nothing
+    // changes in the protocol, so only the event records it.
+    pub fn cancel_admin_action(ctx: Context<CancelAdminAction>) -> Result<()> {
+        let proposal = &ctx.accounts.proposal;
+        let canceller = ctx.accounts.canceller.key();
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(
+            canceller == proposal.proposer
+                || current_time >= proposal.expires_at
+                || proposal.approver_set_nonce != ctx.accounts.global_state.approver_set_nonce,
+            ErrorCode::Unauthorized
+        );
+
+        emit_bounded(AdminActionCancelled {
+            proposal_id: proposal.proposal_id,
+            cancelled_by: canceller,
+            action: AdminActionCode::AdminActionCancelled,
+            timestamp: current_time,
+        });
+
+        msg!("Admin action {} cancelled", proposal.proposal_id);
+        Ok(())
+    }
+
+    // Tune the thresholds get_health grades against
+    pub fn set_health_thresholds(ctx: Context<UpdateGlobalState>, thresholds: HealthThresholds) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(thresholds.is_valid(), ErrorCode::InvalidHealthThresholds);
+        ctx.accounts.global_state.health_thresholds = thresholds;
+
+        msg!("Health thresholds updated");
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::HealthThresholdsSet,
+            None,
+        )
+    }
+
+    // Cap the funded principal outstanding against any one debtor, across every
+    // business listing against it; 0 lifts the cap. Exposure already over a
+    // lowered cap stays, but no more is funded until it comes back under.
+    pub fn set_debtor_exposure_cap(ctx: Context<UpdateGlobalState>, cap: u64) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        let previous = global_state.debtor_exposure_cap;
+        global_state.debtor_exposure_cap = cap;
+
+        emit_bounded(DebtorExposureCapSet {
+            previous,
+            cap,
+            action: AdminActionCode::DebtorExposureCapSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Debtor exposure cap set to {}", cap);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::DebtorExposureCapSet,
+            Some(cap),
+        )
+    }
+
+    // Cap the funded principal outstanding per investor and per business; 0 lifts
+    // a cap. Like the debtor cap, lowering one only stops new funding.
+    pub fn set_exposure_caps(ctx: Context<UpdateGlobalState>, investor_cap: u64, business_cap: u64) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        let previous_investor_cap = global_state.investor_exposure_cap;
+        let previous_business_cap = global_state.business_exposure_cap;
+        global_state.investor_exposure_cap = investor_cap;
+        global_state.business_exposure_cap = business_cap;
+
+        emit_bounded(ExposureCapsSet {
+            previous_investor_cap,
+            previous_business_cap,
+            investor_cap,
+            business_cap,
+            action: AdminActionCode::ExposureCapsSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Exposure caps set: {} per investor, {} per business", investor_cap, business_cap);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::ExposureCapsSet,
+            None,
+        )
+    }
+
+    // Who pays the insurance premium on invoices listed from now on: the business
+    // into the pool at listing, or the investor on top of the principal at funding
+    pub fn set_premium_payer(ctx: Context<UpdateGlobalState>, business_pays: bool) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        global_state.business_pays_premium = business_pays;
+
+        emit_bounded(PremiumPayerSet {
+            business_pays,
+            action: AdminActionCode::PremiumPayerSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Insurance premium paid by the {}", if business_pays { "business" } else { "investor" });
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::PremiumPayerSet,
+            None,
+        )
+    }
+
+    // Cap the coverage the pool sells at a multiple of its balance, in bps (0 = no
+    // cap). Fundings that would take insured exposure past it are rejected.
+    pub fn set_coverage_capacity(ctx: Context<UpdateGlobalState>, capacity_bps: u32) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(capacity_bps == 0 || capacity_bps >= 10_000, ErrorCode::InvalidCoverageCapacity);
+        let global_state = &mut ctx.accounts.global_state;
+        let previous = global_state.coverage_capacity_bps;
+        global_state.coverage_capacity_bps = capacity_bps;
+
+        emit_bounded(CoverageCapacitySet {
+            previous,
+            capacity_bps,
+            utilization_bps: global_state.coverage_utilization_bps(),
+            action: AdminActionCode::CoverageCapacitySet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Coverage capacity set to {} bps of the pool", capacity_bps);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::CoverageCapacitySet,
+            None,
+        )
+    }
+
+    // Cap the fee an invoice may promise its originator, and whether that fee comes
+    // out of the business's principal or on top of the investor's cost. Applies to
+    // invoices listed after it is set.
+    pub fn set_originator_fee_policy(ctx: Context<UpdateGlobalState>, cap_bps: u16, from_principal: bool) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(cap_bps <= MAX_ORIGINATOR_FEE_BPS, ErrorCode::InvalidOriginatorFeeCap);
+        let global_state = &mut ctx.accounts.global_state;
+        let previous_cap_bps = global_state.originator_fee_cap_bps;
+        global_state.originator_fee_cap_bps = cap_bps;
+        global_state.originator_fee_from_principal = from_principal;
+
+        emit_bounded(OriginatorFeePolicySet {
+            previous_cap_bps,
+            cap_bps,
+            from_principal,
+            action: AdminActionCode::OriginatorFeePolicySet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Originator fees capped at {} bps, paid by the {}",
+            cap_bps,
+            if from_principal { "business" } else { "investor" }
+        );
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::OriginatorFeePolicySet,
+            None,
+        )
+    }
+
+    // Whether funding waits on the debtor acknowledging an invoice, or prices its
+    // absence in. Applies to invoices listed (penalty) or funded (requirement) after.
+    pub fn set_acknowledgment_policy(ctx: Context<UpdateGlobalState>, policy: AcknowledgmentPolicy) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        require!(policy.is_valid(&global_state.risk_params), ErrorCode::InvalidAcknowledgmentPolicy);
+        let previous = global_state.acknowledgment_policy;
+        global_state.acknowledgment_policy = policy;
+
+        emit_bounded(AcknowledgmentPolicySet {
+            previous,
+            policy,
+            action: AdminActionCode::AcknowledgmentPolicySet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Acknowledgment policy set to {:?}", policy);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::AcknowledgmentPolicySet,
+            None,
+        )
+    }
+
+    // Key that may resolve invoice disputes besides the authority; None leaves
+    // disputes to the authority alone
+    pub fn set_dispute_arbiter(ctx: Context<UpdateGlobalState>, arbiter: Option<Pubkey>) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        let previous = global_state.dispute_arbiter;
+        global_state.dispute_arbiter = arbiter;
+
+        emit_bounded(DisputeArbiterSet {
+            previous,
+            arbiter,
+            action: AdminActionCode::DisputeArbiterSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Dispute arbiter set to {:?}", arbiter);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::DisputeArbiterSet,
+            None,
+        )
+    }
+
+    // How long a business has to accept an escrowed funding before the investor
+    // may reclaim it. Zero releases funding straight to the business.
+    pub fn set_funding_acceptance_window(ctx: Context<UpdateGlobalState>, window_secs: i64) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(
+            (0..=MAX_FUNDING_ACCEPTANCE_WINDOW).contains(&window_secs),
+            ErrorCode::InvalidAcceptanceWindow
+        );
+        let global_state = &mut ctx.accounts.global_state;
+        let previous_window_secs = global_state.funding_acceptance_window;
+        global_state.funding_acceptance_window = window_secs;
+
+        emit_bounded(FundingAcceptanceWindowSet {
+            previous_window_secs,
+            window_secs,
+            action: AdminActionCode::FundingAcceptanceWindowSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Funding acceptance window set to {}s", window_secs);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::FundingAcceptanceWindowSet,
+            Some(window_secs as u64),
+        )
+    }
+
+    // Approve a stablecoin for new invoices, or withdraw approval. Amounts across
+    // the protocol are read at the primary mint's precision, so a secondary mint
+    // must match its decimals.
+    pub fn set_mint_approval(ctx: Context<SetMintApproval>, approved: bool) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let mint = ctx.accounts.mint.key();
+        require!(
+            !approved || ctx.accounts.mint.decimals == ctx.accounts.primary_mint.decimals,
+            ErrorCode::MintDecimalsMismatch
+        );
+        let global_state = &mut ctx.accounts.global_state;
+        global_state.set_mint_approval(mint, approved)?;
+
+        emit_bounded(MintApprovalSet {
+            mint,
+            approved,
+            action: AdminActionCode::MintApprovalSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Mint {} approval set to {}", mint, approved);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::MintApprovalSet,
+            None,
+        )
+    }
+
+    // Retune the weights of the listing-time risk score; listed invoices keep
+    // the score they were priced at
+    pub fn set_risk_params(ctx: Context<UpdateGlobalState>, params: RiskParams) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(params.is_valid(), ErrorCode::InvalidRiskParams);
+        let global_state = &mut ctx.accounts.global_state;
+        let old_params = global_state.risk_params;
+        global_state.risk_params = params;
+
+        emit_bounded(RiskParamsUpdated {
+            old_params,
+            new_params: params,
+            action: AdminActionCode::RiskParamsUpdated,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Risk params updated");
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::RiskParamsUpdated,
+            None,
+        )
+    }
+
+    // Replace the protocol's economic parameters within their sane ranges
+    pub fn update_config(ctx: Context<UpdateGovernedParams>, config: ProtocolConfig) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(config.is_valid(), ErrorCode::InvalidConfig);
+        let global_state = &mut ctx.accounts.global_state;
+        let old_config = global_state.config;
+        global_state.config = config;
+        record_param_changes(
+            global_state,
+            &mut ctx.accounts.param_history,
+            ctx.accounts.authority.key(),
+            param_diff(&old_config.param_values(), &config.param_values()),
+        )?;
+
+        emit_bounded(ConfigUpdated {
+            old_config,
+            new_config: config,
+            action: AdminActionCode::ConfigUpdated,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Protocol config updated");
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::ConfigUpdated,
+            None,
+        )
+    }
+
+    // Set how long settled invoices keep personal data before it may be erased
+    pub fn set_retention_period(ctx: Context<UpdateGlobalState>, retention_period_secs: i64) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(retention_period_secs >= 0, ErrorCode::InvalidRetentionPeriod);
+        ctx.accounts.global_state.retention_period_secs = retention_period_secs;
+
+        msg!("Retention period set to {} seconds", retention_period_secs);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::RetentionPeriodSet,
+            None,
+        )
+    }
+
+    // Set the share of the yield component owed however early an invoice is repaid
+    pub fn set_min_interest_bps(ctx: Context<UpdateGovernedParams>, min_interest_bps: u16) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(min_interest_bps <= 10_000, ErrorCode::InvalidMinInterest);
+        let old_min_interest_bps = ctx.accounts.global_state.min_interest_bps;
+        ctx.accounts.global_state.min_interest_bps = min_interest_bps;
+        record_param_changes(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.param_history,
+            ctx.accounts.authority.key(),
+            param_diff(
+                &[(ParamId::MinInterestBps, old_min_interest_bps as u64)],
+                &[(ParamId::MinInterestBps, min_interest_bps as u64)],
+            ),
+        )?;
+
+        msg!("Minimum interest set to {} bps of the yield", min_interest_bps);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::MinInterestSet,
+            None,
+        )
+    }
+
+    // Set what the treasury pays an investor per day of an acceptance window the
+    // business let run out, in bps of the principal; up to
+    // MAX_ESCROW_COMPENSATION_BPS_PER_DAY
+    pub fn set_escrow_compensation(ctx: Context<UpdateGovernedParams>, bps_per_day: u16) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(bps_per_day <= MAX_ESCROW_COMPENSATION_BPS_PER_DAY, ErrorCode::InvalidEscrowCompensation);
+        let old_bps_per_day = ctx.accounts.global_state.escrow_compensation_bps_per_day;
+        ctx.accounts.global_state.escrow_compensation_bps_per_day = bps_per_day;
+        record_param_changes(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.param_history,
+            ctx.accounts.authority.key(),
+            param_diff(
+                &[(ParamId::EscrowCompensationBpsPerDay, old_bps_per_day as u64)],
+                &[(ParamId::EscrowCompensationBpsPerDay, bps_per_day as u64)],
+            ),
+        )?;
+
+        msg!("Escrow compensation set to {} bps a day", bps_per_day);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::EscrowCompensationSet,
+            Some(bps_per_day as u64),
+        )
+    }
+
+    // Set the least premium an insured invoice pays; invoices already listed keep
+    // the premium they were priced at
+    pub fn set_min_insurance_premium(ctx: Context<UpdateGlobalState>, min_insurance_premium: u64) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(min_insurance_premium <= MAX_MIN_INSURANCE_PREMIUM, ErrorCode::InvalidMinInsurancePremium);
+        ctx.accounts.global_state.min_insurance_premium = min_insurance_premium;
+
+        msg!("Minimum insurance premium set to {}", min_insurance_premium);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::MinInsurancePremiumSet,
+            Some(min_insurance_premium),
+        )
+    }
+
+    // Set how many invoices a business may have open at once (0 = no cap). A
+    // business already over a lowered cap keeps its invoices and lists again once
+    // enough of them settle.
+    pub fn set_max_open_invoices_per_business(ctx: Context<UpdateGlobalState>, max_open_invoices: u32) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        ctx.accounts.global_state.max_open_invoices_per_business = max_open_invoices;
+
+        msg!("Open invoices per business capped at {}", max_open_invoices);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::MaxOpenInvoicesSet,
+            Some(max_open_invoices as u64),
+        )
+    }
+
+    // Turn the first-time investor diversification limits on or off
+    pub fn set_retail_guardrails(ctx: Context<UpdateGlobalState>, enabled: bool) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        ctx.accounts.global_state.retail_guardrails = enabled;
+
+        msg!("Retail guardrails {}", if enabled { "enabled" } else { "disabled" });
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::RetailGuardrailsSet,
+            None,
+        )
+    }
+
+    // Record (or revoke) a professional investor attestation after KYC review
+    pub fn set_professional_attestation(
+        ctx: Context<SetProfessionalAttestation>,
+        investor: Pubkey,
+        professional: bool,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let investor_stats = &mut ctx.accounts.investor_stats;
+        investor_stats.investor = investor;
+        investor_stats.professional = professional;
+        investor_stats.bump = ctx.bumps.investor_stats;
+
+        emit_bounded(ProfessionalAttestationSet {
+            investor,
+            professional,
+            action: AdminActionCode::ProfessionalAttestationSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Professional attestation for {} set to {}", investor, professional);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::ProfessionalAttestationSet,
+            None,
+        )
+    }
+
+    // Approve an investor for funding after accreditation review, optionally until `expires_at`
+    pub fn approve_investor(ctx: Context<ApproveInvestor>, investor: Pubkey, expires_at: Option<i64>) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(
+            !matches!(expires_at, Some(expires_at) if expires_at <= current_time),
+            ErrorCode::InvalidWhitelistExpiry
+        );
+
+        let entry = &mut ctx.accounts.investor_whitelist;
+        entry.investor = investor;
+        entry.approved = true;
+        entry.expires_at = expires_at;
+        entry.updated_at = current_time;
+        entry.bump = ctx.bumps.investor_whitelist;
+
+        emit_bounded(InvestorApproved {
+            investor,
+            expires_at,
+            action: AdminActionCode::InvestorApproved,
+            timestamp: current_time,
+        });
+
+        msg!("Investor {} approved", investor);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::InvestorApproved,
+            expires_at.map(|expires_at| expires_at as u64),
+        )
+    }
+
+    // Withdraw an investor's approval; positions already held are unaffected
+    pub fn revoke_investor(ctx: Context<RevokeInvestor>) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Fast)?;
+        let entry = &mut ctx.accounts.investor_whitelist;
+        entry.approved = false;
+        entry.updated_at = Clock::get()?.unix_timestamp;
+
+        emit_bounded(InvestorRevoked {
+            investor: entry.investor,
+            action: AdminActionCode::InvestorRevoked,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Investor {} revoked", entry.investor);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::InvestorRevoked,
+            None,
+        )
+    }
+
+    // Bar a wallet from listing invoices (Business) or from funding and buying
+    // positions (Investor) after a confirmed case, without pausing anyone else.
+    // What it already holds keeps settling: repayments, claims, recoveries and
+    // redemptions are untouched, so its counterparties are not trapped.
+    pub fn blacklist(
+        ctx: Context<BlacklistParty>,
+        party: Pubkey,
+        role: BlacklistRole,
+        reason: BlacklistReason,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Fast)?;
+        require!(!reason.is_clearing(), ErrorCode::InvalidBlacklistReason);
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let entry = &mut ctx.accounts.blacklist_entry;
+        entry.party = party;
+        entry.set(role, true);
+        entry.reason = reason;
+        entry.updated_at = current_time;
+        entry.bump = ctx.bumps.blacklist_entry;
+
+        emit_bounded(PartyBlacklisted {
+            party,
+            role,
+            reason,
+            action: AdminActionCode::PartyBlacklisted,
+            timestamp: current_time,
+        });
+
+        msg!("{} blacklisted as {:?}: {:?}", party, role, reason);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::PartyBlacklisted,
+            Some(reason as u64),
+        )
+    }
+
+    // Lift a party's blacklisting in one role; the entry stays as a record
+    pub fn unblacklist(ctx: Context<UnblacklistParty>, role: BlacklistRole, reason: BlacklistReason) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(reason.is_clearing(), ErrorCode::InvalidBlacklistReason);
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let entry = &mut ctx.accounts.blacklist_entry;
+        entry.set(role, false);
+        entry.reason = reason;
+        entry.updated_at = current_time;
+
+        emit_bounded(PartyUnblacklisted {
+            party: entry.party,
+            role,
+            reason,
+            action: AdminActionCode::PartyUnblacklisted,
+            timestamp: current_time,
+        });
+
+        msg!("{} no longer blacklisted as {:?}: {:?}", entry.party, role, reason);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::PartyUnblacklisted,
+            Some(reason as u64),
+        )
+    }
+
+    // Restrict funding to whitelisted investors, or open it to anyone
+    pub fn set_require_whitelist(ctx: Context<UpdateGlobalState>, enabled: bool) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        ctx.accounts.global_state.require_whitelist = enabled;
+
+        emit_bounded(WhitelistRequirementSet {
+            enabled,
+            action: AdminActionCode::WhitelistRequirementSet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Investor whitelist {}", if enabled { "required" } else { "not required" });
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::WhitelistRequirementSet,
+            None,
+        )
+    }
+
+    // Accounts-receivable aging report over a page of the business's invoices,
+    // each passed once (view function)
+    pub fn get_business_aging_report(
+        ctx: Context<GetBusinessAgingReport>,
+        business_owner: Pubkey,
+    ) -> Result<AgingReport> {
+        require!(
+            ctx.remaining_accounts.len() <= MAX_AGING_REPORT_INVOICES,
+            ErrorCode::TooManyInvoices
+        );
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let mut report = AgingReport {
+            business_owner,
+            as_of: current_time,
+            ..AgingReport::default()
+        };
+
+        let accounts = ctx.remaining_accounts;
+        for (index, info) in accounts.iter().enumerate() {
+            // Each invoice once, or a copy would be counted twice
+            let repeated = accounts[..index].iter().any(|earlier| earlier.key() == info.key());
+            require!(!repeated, ErrorCode::InvalidBatchAccount);
+            let invoice = load_invoice(info)?;
+            require_keys_eq!(invoice.business_owner, business_owner, ErrorCode::InvoiceOwnerMismatch);
+            report.add(&invoice, current_time, ctx.accounts.global_state.config.late_fee_bps_per_day)?;
+        }
+
+        Ok(report)
+    }
+
+    // A page of a business's records for export (view function): its invoices
+    // with ids from `cursor` on, passed as remaining accounts in id order, each
+    // followed by its audit log address; closed invoices and missing logs are
+    // passed all the same. Start from 1 and page on from next_cursor until it
+    // comes back None.
+    pub fn export_business_history(
+        ctx: Context<ExportBusinessHistory>,
+        business_owner: Pubkey,
+        cursor: u64,
+    ) -> Result<BusinessHistoryPage> {
+        let accounts = ctx.remaining_accounts;
+        require!(accounts.len() <= 2 * MAX_EXPORT_PAGE_INVOICES, ErrorCode::TooManyInvoices);
+        require!(accounts.len().is_multiple_of(2), ErrorCode::InvalidExportPage);
+        let profile = &ctx.accounts.business_profile;
+        let last = profile.invoices_created;
+        let invoices = (accounts.len() / 2) as u64;
+        // The page must stay within the business's invoices, and take at least one
+        // while any remain so that every page moves the cursor on
+        require!(cursor >= 1 && cursor <= last + 1, ErrorCode::InvalidExportPage);
+        require!(cursor - 1 + invoices <= last, ErrorCode::InvalidExportPage);
+        require!(invoices > 0 || cursor > last, ErrorCode::InvalidExportPage);
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let mut page = BusinessHistoryPage::start(profile, cursor, current_time);
+        for (invoice_id, pair) in (cursor..).zip(accounts.chunks_exact(2)) {
+            let record = read_settlement_record(&business_owner, invoice_id, &pair[0], &pair[1])?;
+            if !page.push(record) {
+                break;
+            }
+        }
+        page.finish(last);
+
+        emit_bounded(ExportRequested {
+            business: business_owner,
+            cursor,
+            records: page.records.len() as u8,
+            complete: page.next_cursor.is_none(),
+            timestamp: current_time,
+        });
+
+        Ok(page)
+    }
+
+    // Permissionless crank: fold a page of invoices, in increasing book nonce order,
+    // into the open book commitment; the final page fixes its merkle root. Each
+    // commitment covers the invoices created before its first page; cancelled
+    // invoices are closed, so their nonces are simply absent.
+    pub fn commit_book_root(ctx: Context<CommitBookRoot>, final_page: bool) -> Result<()> {
+        require!(
+            ctx.remaining_accounts.len() <= MAX_BOOK_PAGE_INVOICES,
+            ErrorCode::TooManyInvoices
+        );
+        let global_state = &mut ctx.accounts.global_state;
+        let commitment = &mut ctx.accounts.book_commitment;
+        let clock = Clock::get()?;
+
+        if !commitment.opened {
+            require!(
+                global_state.book_commitment_count == 0
+                    || clock.unix_timestamp >= global_state.last_book_commit_at + BOOK_COMMIT_INTERVAL_SECS,
+                ErrorCode::BookCommitTooSoon
+            );
+            commitment.index = global_state.book_commitment_count;
+            commitment.through_nonce = global_state.invoices_created;
+            commitment.opened_slot = clock.slot;
+            commitment.opened = true;
+            commitment.bump = ctx.bumps.book_commitment;
+        }
+
+        for info in ctx.remaining_accounts {
+            let invoice = load_invoice(info)?;
+            require!(
+                invoice.book_nonce > commitment.last_nonce && invoice.book_nonce <= commitment.through_nonce,
+                ErrorCode::BookNonceOutOfOrder
+            );
+            commitment.frontier.append(leaf_hash(&BookLeaf::of(info.key(), &invoice)))?;
+            if commitment.first_nonce == 0 {
+                commitment.first_nonce = invoice.book_nonce;
+            }
+            commitment.last_nonce = invoice.book_nonce;
+        }
+
+        // Paid while the crank treasury has lamports above its rent-exempt floor
+        let treasury = ctx.accounts.crank_treasury.to_account_info();
+        let surplus = treasury.lamports().saturating_sub(Rent::get()?.minimum_balance(treasury.data_len()));
+        let reward = (ctx.remaining_accounts.len() as u64 * BOOK_CRANK_REWARD_PER_LEAF).min(surplus);
+        if reward > 0 {
+            **treasury.try_borrow_mut_lamports()? -= reward;
+            **ctx.accounts.cranker.to_account_info().try_borrow_mut_lamports()? += reward;
+        }
+
+        if final_page {
+            commitment.root = commitment.frontier.root();
+            commitment.committed_slot = clock.slot;
+            commitment.committed_at = clock.unix_timestamp;
+            commitment.finalized = true;
+            global_state.book_commitment_count += 1;
+            global_state.last_book_commit_at = clock.unix_timestamp;
+
+            emit_bounded(BookRootCommitted {
+                index: commitment.index,
+                root: commitment.root,
+                first_nonce: commitment.first_nonce,
+                last_nonce: commitment.last_nonce,
+                leaf_count: commitment.frontier.leaf_count,
+                slot: clock.slot,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+            msg!(
+                "Book commitment {} over nonces {}..={} ({} invoices)",
+                commitment.index,
+                commitment.first_nonce,
+                commitment.last_nonce,
+                commitment.frontier.leaf_count
+            );
+        }
+        Ok(())
+    }
+
+    // Register a named destination for a vault (active after the timelock): a USDC
+    // token account, or for the lost-and-found vault any account recoveries pay into
+    pub fn register_destination(
+        ctx: Context<RegisterDestination>,
+        vault: VaultKind,
+        name: [u8; 32],
+    ) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let allowlist = &mut ctx.accounts.allowlist;
+        let destination = ctx.accounts.destination.key();
+        let current_time = Clock::get()?.unix_timestamp;
+
+        // Recoveries pay out in whatever mint was stranded, or in lamports
+        if vault != VaultKind::LostAndFound {
+            let info = &ctx.accounts.destination;
+            require_keys_eq!(*info.owner, token::ID, ErrorCode::InvalidDestinationMint);
+            let token_account = TokenAccount::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            require!(
+                token_account.mint == ctx.accounts.global_state.usdc_mint,
+                ErrorCode::InvalidDestinationMint
+            );
+        }
+        require!(
+            allowlist.find(&destination).is_none(),
+            ErrorCode::DestinationAlreadyRegistered
+        );
+        require!(
+            allowlist.destinations.len() < DestinationAllowlist::MAX_DESTINATIONS,
+            ErrorCode::AllowlistFull
+        );
+
+        allowlist.vault = vault;
+        allowlist.bump = ctx.bumps.allowlist;
+
+        let active_at = current_time + ALLOWLIST_TIMELOCK_SECS;
+        allowlist.destinations.push(AllowlistedDestination {
+            name,
+            token_account: destination,
+            active_at,
+            removable_at: None,
+        });
+
+        emit_bounded(DestinationRegistered {
+            vault,
+            name,
+            token_account: destination,
+            active_at,
+            action: AdminActionCode::DestinationRegistered,
+            timestamp: current_time,
+        });
+
+        msg!("Destination {} registered for {:?} vault, active at {}", destination, vault, active_at);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::DestinationRegistered,
+            None,
+        )
+    }
+
+    // Start the timelocked removal of an allowlisted destination
+    pub fn request_destination_removal(
+        ctx: Context<UpdateDestinationAllowlist>,
+        vault: VaultKind,
+        token_account: Pubkey,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let allowlist = &mut ctx.accounts.allowlist;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let index = allowlist
+            .find(&token_account)
+            .ok_or(ErrorCode::DestinationNotFound)?;
+        let removable_at = current_time + ALLOWLIST_TIMELOCK_SECS;
+        allowlist.destinations[index].removable_at = Some(removable_at);
+
+        emit_bounded(DestinationRemovalRequested {
+            vault,
+            token_account,
+            removable_at,
+            action: AdminActionCode::DestinationRemovalRequested,
+            timestamp: current_time,
+        });
+
+        msg!("Removal of destination {} from {:?} vault requested", token_account, vault);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::DestinationRemovalRequested,
+            None,
+        )
+    }
+
+    // Remove a destination once its removal timelock has elapsed
+    pub fn execute_destination_removal(
+        ctx: Context<UpdateDestinationAllowlist>,
+        vault: VaultKind,
+        token_account: Pubkey,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let allowlist = &mut ctx.accounts.allowlist;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let index = allowlist
+            .find(&token_account)
+            .ok_or(ErrorCode::DestinationNotFound)?;
+        let removable_at = allowlist.destinations[index]
+            .removable_at
+            .ok_or(ErrorCode::RemovalNotRequested)?;
+        require!(current_time >= removable_at, ErrorCode::TimelockNotElapsed);
+
+        allowlist.destinations.remove(index);
+
+        emit_bounded(DestinationRemoved {
+            vault,
+            token_account,
+            action: AdminActionCode::DestinationRemoved,
+            timestamp: current_time,
+        });
+
+        msg!("Destination {} removed from {:?} vault", token_account, vault);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::DestinationRemoved,
+            None,
+        )
+    }
+
+    // Emergency freeze: clear every destination of a vault, blocking all outflows immediately
+    pub fn freeze_destination_allowlist(
+        ctx: Context<UpdateDestinationAllowlist>,
+        vault: VaultKind,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Fast)?;
+        let allowlist = &mut ctx.accounts.allowlist;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let cleared = allowlist.destinations.len() as u8;
+        allowlist.destinations.clear();
+        allowlist.frozen_at = Some(current_time);
+
+        emit_bounded(DestinationAllowlistFrozen {
+            vault,
+            cleared,
+            frozen_at: current_time,
+            action: AdminActionCode::DestinationAllowlistFrozen,
+            timestamp: current_time,
+        });
+
+        msg!("{:?} vault allowlist frozen, {} destinations cleared", vault, cleared);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::DestinationAllowlistFrozen,
+            None,
+        )
+    }
+
+    // Register (or update) the fiat payout processor and its custody account, which
+    // must be on the payout custody allowlist. The first custody account takes effect
+    // at once; a different one later waits out the timelock for apply_payout_custody.
+    pub fn configure_payout_processor(
+        ctx: Context<ConfigurePayoutProcessor>,
+        processor: Pubkey,
+        ack_timeout_secs: i64,
+    ) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let payout_processor = &mut ctx.accounts.payout_processor;
+        let current_time = Clock::get()?.unix_timestamp;
+        let custody = ctx.accounts.custody.key();
+
+        require!(ack_timeout_secs > 0, ErrorCode::InvalidAckTimeout);
+        require!(
+            ctx.accounts.custody.mint == ctx.accounts.global_state.usdc_mint,
+            ErrorCode::InvalidDestinationMint
+        );
+        require!(
+            ctx.accounts.allowlist.is_registered(&custody, current_time),
+            ErrorCode::DestinationNotAllowlisted
+        );
+
+        require_sound_vault(&ctx.accounts.outbox_escrow, &ctx.accounts.outbox_authority.key())?;
+
+        if let Some(effective_at) = payout_processor.stage_custody(custody, current_time) {
+            emit_bounded(PayoutCustodyChangeProposed {
+                old_custody: payout_processor.custody,
+                new_custody: custody,
+                effective_at,
+                action: AdminActionCode::PayoutProcessorConfigured,
+                timestamp: current_time,
+            });
+            msg!("Payout custody change to {} scheduled for {}", custody, effective_at);
+        }
+
+        payout_processor.processor = processor;
+        payout_processor.escrow = ctx.accounts.outbox_escrow.key();
+        payout_processor.ack_timeout_secs = ack_timeout_secs;
+        payout_processor.bump = ctx.bumps.payout_processor;
+        payout_processor.authority_bump = ctx.bumps.outbox_authority;
+
+        emit_bounded(PayoutProcessorConfigured {
+            processor,
+            custody: payout_processor.custody,
+            ack_timeout_secs,
+            action: AdminActionCode::PayoutProcessorConfigured,
+            timestamp: current_time,
+        });
+
+        msg!("Payout processor set to {}", processor);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::PayoutProcessorConfigured,
+            None,
+        )
+    }
+
+    // Hand payouts to a scheduled custody account once its timelock has elapsed; by
+    // then it must be active on the payout custody allowlist
+    pub fn apply_payout_custody(ctx: Context<ApplyPayoutCustody>) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let current_time = Clock::get()?.unix_timestamp;
+        let old_custody = ctx.accounts.payout_processor.custody;
+        let new_custody = ctx.accounts.payout_processor.apply_pending_custody(&ctx.accounts.allowlist, current_time)?;
+
+        emit_bounded(PayoutCustodyChanged {
+            old_custody,
+            new_custody,
+            action: AdminActionCode::PayoutCustodyApplied,
+            timestamp: current_time,
+        });
+
+        msg!("Payout custody moved from {} to {}", old_custody, new_custody);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::PayoutCustodyApplied,
+            None,
+        )
+    }
+
+    // Open the outbox page that the next appended entry will land in
+    pub fn open_outbox_page(ctx: Context<OpenOutboxPage>, page: u32) -> Result<()> {
+        require!(
+            page as u64 == ctx.accounts.payout_processor.total_entries / OutboxPage::MAX_ENTRIES as u64,
+            ErrorCode::OutboxPageMismatch
+        );
+
+        let outbox_page = &mut ctx.accounts.outbox_page;
+        outbox_page.page = page;
+        outbox_page.entries = Vec::new();
+        outbox_page.bump = ctx.bumps.outbox_page;
+
+        msg!("Outbox page {} opened", page);
+        Ok(())
+    }
+
+    // Open the admin log page that the next protocol-level admin action will land in
+    pub fn open_admin_log_page(ctx: Context<OpenAdminLogPage>, page: u32) -> Result<()> {
+        require!(
+            page as u64 == ctx.accounts.global_state.admin_action_count / AdminActionLog::MAX_ENTRIES as u64,
+            ErrorCode::AdminLogPageMismatch
+        );
+
+        let admin_log = &mut ctx.accounts.admin_log;
+        admin_log.page = page;
+        admin_log.entries = Vec::new();
+        admin_log.bump = ctx.bumps.admin_log;
+
+        msg!("Admin log page {} opened", page);
+        Ok(())
+    }
+
+    // Open the param history page that the next parameter change will land in
+    pub fn open_param_history_page(ctx: Context<OpenParamHistoryPage>, page: u32) -> Result<()> {
+        require!(
+            page == ctx.accounts.global_state.param_version / ParamHistory::MAX_ENTRIES as u32,
+            ErrorCode::ParamHistoryPageMismatch
+        );
+
+        let param_history = &mut ctx.accounts.param_history;
+        param_history.page = page;
+        param_history.entries = Vec::new();
+        param_history.bump = ctx.bumps.param_history;
+
+        msg!("Param history page {} opened", page);
+        Ok(())
+    }
+
+    // Value a governed parameter had at `timestamp` and the parameter-set version then
+    // in force (view function). Every param history page goes in remaining_accounts,
+    // oldest first.
+    pub fn get_param_at(ctx: Context<GetParamAt>, param: ParamId, timestamp: i64) -> Result<ParamAt> {
+        let global_state = &ctx.accounts.global_state;
+        let entries: Vec<ParamSetChange> = load_param_history(ctx.remaining_accounts, global_state.param_version)?
+            .into_iter()
+            .flat_map(|page| page.entries)
+            .collect();
+        Ok(param_at(&entries, param, timestamp, global_state.param_value(param)))
+    }
+
+    // Protocol-level admin actions recorded on one admin log page, oldest first (view function)
+    pub fn get_admin_action_log(ctx: Context<GetAdminActionLog>) -> Result<Vec<AdminAction>> {
+        Ok(ctx.accounts.admin_log.entries.clone())
+    }
+
+    // The last privileged actions taken on an invoice, oldest first (view function)
+    pub fn get_invoice_audit_trail(ctx: Context<GetInvoiceAuditTrail>) -> Result<Vec<AdminAction>> {
+        Ok(ctx.accounts.invoice_audit_log.chronological())
+    }
+
+    // Bilateral statement between a business and an investor across every invoice
+    // the investor funded outright (view function)
+    pub fn get_pair_statement(
+        ctx: Context<GetPairStatement>,
+        _business: Pubkey,
+        _investor: Pubkey,
+    ) -> Result<PairStatement> {
+        Ok(ctx.accounts.pair_ledger.statement())
+    }
+
+    // A business's track record: invoices created, funded and settled, volume and
+    // what is outstanding (view function)
+    pub fn get_business_stats(ctx: Context<GetBusinessStats>, _business: Pubkey) -> Result<BusinessStats> {
+        Ok(ctx.accounts.business_profile.stats())
+    }
+
+    // An investor's totals across the invoices it funded outright (view function)
+    pub fn get_investor_portfolio(ctx: Context<GetInvestorPortfolio>, _investor: Pubkey) -> Result<PortfolioStatement> {
+        Ok(ctx.accounts.portfolio.statement())
+    }
+
+    // Close a pair ledger with nothing outstanding; both parties must agree, and the
+    // rent goes back to the investor who paid it
+    pub fn close_pair_ledger(ctx: Context<ClosePairLedger>) -> Result<()> {
+        let ledger = &ctx.accounts.pair_ledger;
+        require!(ledger.open_invoices == 0, ErrorCode::PairExposureOutstanding);
+
+        emit_bounded(PairLedgerClosed {
+            business: ledger.business,
+            investor: ledger.investor,
+            invoices_financed: ledger.invoices_financed,
+            total_financed: ledger.total_financed,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Pair ledger {} / {} closed", ledger.business, ledger.investor);
+        Ok(())
+    }
+
+    // Collect what repayments have paid into the invoice vault by presenting the
+    // position receipt. Once the invoice is repaid in full the receipt is burned
+    // and the holder becomes the recorded investor.
+    pub fn redeem_receipt(ctx: Context<RedeemReceipt>) -> Result<()> {
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let invoice = &mut ctx.accounts.invoice;
+        let holder = ctx.accounts.holder.key();
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let receipt_mint = invoice.live_receipt().ok_or(ErrorCode::NoPositionReceipt)?;
+        require_keys_eq!(ctx.accounts.receipt_mint.key(), receipt_mint, ErrorCode::NoPositionReceipt);
+        require!(
+            invoice.is_position_holder(&holder, Some(&ctx.accounts.holder_receipt)),
+            ErrorCode::InvalidPositionReceipt
+        );
+        let settled = matches!(invoice.status, InvoiceStatus::Repaid | InvoiceStatus::RepaidAfterDefault);
+        let payout = invoice.distributable_amount;
+        require!(payout > 0 || settled, ErrorCode::NothingToClaim);
+
+        if payout > 0 {
+            require_no_delegate(&ctx.accounts.invoice_vault)?;
+            let invoice_id_bytes = invoice.invoice_id.to_le_bytes();
+            let seeds = &[INVOICE_SEED, invoice.business_owner.as_ref(), invoice_id_bytes.as_ref(), &[invoice.bump]];
+            let signer_seeds = &[&seeds[..]];
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.invoice_vault.to_account_info(),
+                        to: ctx.accounts.holder_token_account.to_account_info(),
+                        authority: invoice_info,
+                    },
+                    signer_seeds,
+                ),
+                payout,
+            )?;
+            invoice.distributable_amount = 0;
+            emit_bounded(ReceiptRedeemed {
+                invoice_id: invoice.invoice_id,
+                holder,
+                amount: payout,
+                invoice: invoice.key(),
+                business_owner: invoice.business_owner,
+                investor: invoice.investor,
+                timestamp: current_time,
+            });
+        }
+
+        if settled {
+            token::burn(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Burn {
+                        mint: ctx.accounts.receipt_mint.to_account_info(),
+                        from: ctx.accounts.holder_receipt.to_account_info(),
+                        authority: ctx.accounts.holder.to_account_info(),
+                    },
+                ),
+                1,
+            )?;
+            invoice.receipt_redeemed = true;
+            if holder != invoice.investor {
+                invoice.portfolio_booked = false;
+            }
+            invoice.investor = holder;
+            emit_bounded(ReceiptBurned {
+                invoice_id: invoice.invoice_id,
+                mint: receipt_mint,
+                holder,
+                invoice: invoice.key(),
+                business_owner: invoice.business_owner,
+                investor: invoice.investor,
+                timestamp: current_time,
+            });
+        }
+
+        msg!("{} redeemed {} USDC from invoice {}", holder, payout, invoice.invoice_id);
+        Ok(())
+    }
+
+    // redeem_receipt for a holder who wants SOL back: the payout lands in their wSOL
+    // account as usual, which is then closed to unwrap it, rent and any other wSOL
+    // it held included, into the holder's wallet
+    pub fn redeem_receipt_to_sol(ctx: Context<RedeemReceipt>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.invoice_vault.mint, spl_token::native_mint::ID, ErrorCode::NativeMintOnly);
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let holder_token_account = ctx.accounts.holder_token_account.to_account_info();
+        let holder = ctx.accounts.holder.to_account_info();
+
+        redeem_receipt(ctx)?;
+
+        let unwrapped = holder_token_account.lamports();
+        token::close_account(CpiContext::new(
+            token_program,
+            CloseAccount {
+                account: holder_token_account,
+                destination: holder.clone(),
+                authority: holder.clone(),
+            },
+        ))?;
+        msg!("Unwrapped {} lamports to {}", unwrapped, holder.key());
+        Ok(())
+    }
+
+    // Move repayments nobody redeemed within UNCLAIMED_REPAYMENT_SWEEP_SECS of the
+    // final repayment from the invoice vault to the insurance pool. The receipt can
+    // still be redeemed afterwards, for nothing, to close out the position. The
+    // pool holds only the primary mint, so other mints are never swept.
+    pub fn sweep_unclaimed_repayment(ctx: Context<SweepUnclaimedRepayment>) -> Result<()> {
+        let path = authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require_keys_eq!(invoice.mint, global_state.usdc_mint, ErrorCode::PrimaryMintOnly);
+        require!(invoice.unclaimed_sweep_open(current_time), ErrorCode::UnclaimedSweepNotOpen);
+        let amount = invoice.distributable_amount;
+
+        require_no_delegate(&ctx.accounts.invoice_vault)?;
+        let invoice_id_bytes = invoice.invoice_id.to_le_bytes();
+        let seeds = &[INVOICE_SEED, invoice.business_owner.as_ref(), invoice_id_bytes.as_ref(), &[invoice.bump]];
+        let signer_seeds = &[&seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.invoice_vault.to_account_info(),
+                    to: ctx.accounts.insurance_pool_account.to_account_info(),
+                    authority: invoice_info,
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+        invoice.distributable_amount = 0;
+        invoice.unclaimed_swept = amount;
+        global_state.insurance_pool_balance =
+            global_state.insurance_pool_balance.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        let audit_log = &mut ctx.accounts.invoice_audit_log;
+        audit_log.invoice = invoice.key();
+        audit_log.bump = ctx.bumps.invoice_audit_log;
+        audit_log.record(AdminAction {
+            actor: ctx.accounts.authority.key(),
+            role: match path {
+                AuthorityPath::Direct => AdminRole::ProtocolAuthority,
+                AuthorityPath::Governance => AdminRole::Governance,
+                AuthorityPath::Approvers => AdminRole::Approver,
+            },
+            action: AdminActionCode::UnclaimedRepaymentSwept,
+            timestamp: current_time,
+            amount: Some(amount),
+        });
+
+        emit_bounded(UnclaimedRepaymentSwept {
+            invoice_id: invoice.invoice_id,
+            amount,
+            action: AdminActionCode::UnclaimedRepaymentSwept,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Swept {} unclaimed from invoice {} to the insurance pool", amount, invoice.invoice_id);
+        Ok(())
+    }
+
+    // Offer a funded position for sale at `ask_price`; one listing per invoice. A
+    // receipt stays in the seller's wallet, with the listing approved to move it.
+    pub fn list_position(ctx: Context<ListPosition>, ask_price: u64) -> Result<()> {
+        let invoice = &ctx.accounts.invoice;
+        require!(
+            invoice.is_position_holder(&ctx.accounts.seller.key(), ctx.accounts.seller_receipt.as_deref()),
+            ErrorCode::Unauthorized
+        );
+        require!(invoice.position_transferable(), ErrorCode::PositionNotTransferable);
+        require!(ask_price > 0, ErrorCode::InvalidAmount);
+
+        if let (Some(_), Some(receipt)) = (invoice.live_receipt(), ctx.accounts.seller_receipt.as_ref()) {
+            token::approve(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Approve {
+                        to: receipt.to_account_info(),
+                        delegate: ctx.accounts.listing.to_account_info(),
+                        authority: ctx.accounts.seller.to_account_info(),
+                    },
+                ),
+                1,
+            )?;
+        }
+
+        let listing = &mut ctx.accounts.listing;
+        listing.invoice = invoice.key();
+        listing.seller = ctx.accounts.seller.key();
+        listing.ask_price = ask_price;
+        listing.listed_at = Clock::get()?.unix_timestamp;
+        listing.bump = ctx.bumps.listing;
+
+        emit_bounded(PositionListed {
+            invoice_id: invoice.invoice_id,
+            seller: listing.seller,
+            ask_price,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: listing.listed_at,
+        });
+        Ok(())
+    }
+
+    // Withdraw a listing; the rent goes back to the seller, and the listing's
+    // approval over a receipt is revoked
+    pub fn cancel_position_listing(ctx: Context<CancelPositionListing>) -> Result<()> {
+        let listing = ctx.accounts.listing.key();
+        if let Some(receipt) = ctx.accounts.seller_receipt.as_ref().filter(|r| r.delegate == COption::Some(listing)) {
+            token::revoke(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Revoke {
+                    source: receipt.to_account_info(),
+                    authority: ctx.accounts.seller.to_account_info(),
+                },
+            ))?;
+        }
+        Ok(())
+    }
+
+    // Buy a listed position: the buyer pays the asking price straight to the seller
+    // and becomes the invoice's investor, so repayments and any insurance claim go
+    // to them from here on. `max_price` guards against the listing being replaced
+    // at a higher price before this lands.
+    pub fn transfer_position(ctx: Context<TransferPosition>, max_price: u64) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let listing = &ctx.accounts.listing;
+        let global_state = &ctx.accounts.global_state;
+
+        global_state.require_not_paused(PAUSE_FUND)?;
+        require_not_blacklisted(&ctx.accounts.buyer_blacklist, BlacklistRole::Investor)?;
+        require!(invoice.position_transferable(), ErrorCode::PositionNotTransferable);
+        let seller_holds = match invoice.live_receipt() {
+            Some(mint) => matches!(
+                ctx.accounts.seller_receipt.as_deref(),
+                Some(receipt) if holds_receipt(receipt, &mint, &listing.seller) && receipt.delegate == COption::Some(listing.key())
+            ),
+            None => invoice.investor == listing.seller,
+        };
+        require!(seller_holds, ErrorCode::PositionListingStale);
+        require_keys_neq!(ctx.accounts.buyer.key(), listing.seller, ErrorCode::PositionNotTransferable);
+        require!(listing.ask_price <= max_price, ErrorCode::SlippageExceeded);
+        global_state.require_whitelisted(ctx.accounts.buyer_whitelist.as_deref(), Clock::get()?.unix_timestamp)?;
+        require!(
+            ctx.accounts.buyer_token_account.amount >= listing.ask_price,
+            ErrorCode::InsufficientFunds
+        );
+
+        // The buyer takes on the remaining principal, so retail limits apply to it
+        let investor_stats = &mut ctx.accounts.investor_stats;
+        investor_stats.require_retail_guardrails(
+            global_state.retail_guardrails,
+            invoice.remaining_balance,
+            invoice.risk_score,
+        )?;
+
+        let transfer_price_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.seller_token_account.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            },
+        );
+        token::transfer(transfer_price_ctx, listing.ask_price)?;
+
+        // The receipt follows the sale, moved under the listing's approval
+        if let Some(mint) = invoice.live_receipt() {
+            let seller_receipt = ctx.accounts.seller_receipt.as_ref().ok_or(ErrorCode::PositionListingStale)?;
+            let buyer_receipt = ctx.accounts.buyer_receipt.as_ref().ok_or(ErrorCode::InvalidPositionReceipt)?;
+            require!(
+                buyer_receipt.mint == mint && buyer_receipt.owner == ctx.accounts.buyer.key(),
+                ErrorCode::InvalidPositionReceipt
+            );
+            let invoice_key = invoice.key();
+            let seeds = &[POSITION_LISTING_SEED, invoice_key.as_ref(), &[listing.bump]];
+            let signer_seeds = &[&seeds[..]];
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: seller_receipt.to_account_info(),
+                        to: buyer_receipt.to_account_info(),
+                        authority: listing.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                1,
+            )?;
+        }
+
+        let seller = listing.seller;
+        let buyer = ctx.accounts.buyer.key();
+        // The seller's portfolio realizes the sale; one whose receipt changed hands
+        // off-program stopped following the position then
+        if invoice.investor == seller {
+            if let Some(portfolio) = invoice.booked_portfolio(ctx.accounts.seller_portfolio.as_deref_mut())? {
+                portfolio.record_sale(invoice.remaining_balance, listing.ask_price)?;
+            }
+        }
+        invoice.portfolio_booked = false;
+        invoice.investor = buyer;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        investor_stats.investor = buyer;
+        investor_stats.bump = ctx.bumps.investor_stats;
+        investor_stats.deployed_capital = investor_stats
+            .deployed_capital
+            .checked_add(invoice.remaining_balance)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        // The outstanding principal moves from the seller's exposure to the buyer's
+        if invoice.exposure_booked {
+            let seller_stats = ctx.accounts.seller_stats.as_mut().ok_or(ErrorCode::InvestorStatsMissing)?;
+            seller_stats.outstanding = seller_stats.outstanding.saturating_sub(invoice.remaining_balance);
+            book_outstanding(
+                &mut investor_stats.outstanding,
+                invoice.remaining_balance,
+                global_state.investor_exposure_cap,
+                ErrorCode::InvestorExposureExceeded,
+            )?;
+        }
+
+        // The seller's ledger is missing for positions funded before ledgers existed
+        if let Some(seller_ledger) = ctx.accounts.seller_ledger.as_mut() {
+            seller_ledger.record_sale(invoice.invoice_id, invoice.remaining_balance, listing.ask_price, current_time)?;
+        }
+        let buyer_ledger = &mut ctx.accounts.buyer_ledger;
+        buyer_ledger.business = invoice.business_owner;
+        buyer_ledger.investor = buyer;
+        buyer_ledger.bump = ctx.bumps.buyer_ledger;
+        buyer_ledger.record_purchase(invoice.invoice_id, invoice.remaining_balance, listing.ask_price, current_time)?;
+
+        emit_bounded(PositionTransferred {
+            invoice_id: invoice.invoice_id,
+            from: seller,
+            to: buyer,
+            price: listing.ask_price,
+            remaining_balance: invoice.remaining_balance,
+            expected_return: invoice.expected_return.unwrap_or(invoice.amount).saturating_sub(invoice.amount_repaid),
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Invoice {} position sold by {} to {} for {} USDC", invoice.invoice_id, seller, buyer, listing.ask_price);
+        Ok(())
+    }
+
+    // Processor confirms an off-ramp payout; escrowed funds move to its custody
+    // account, which must be active on the payout custody allowlist
+    pub fn ack_outbox_entry(
+        ctx: Context<AckOutboxEntry>,
+        index: u64,
+        reference_hash: [u8; 32],
+    ) -> Result<()> {
+        let payout_processor = &ctx.accounts.payout_processor;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let entry = ctx.accounts.outbox_page.entry_mut(index)?;
+        require!(entry.status == OutboxEntryStatus::Pending, ErrorCode::OutboxEntryNotPending);
+        require!(
+            entry.invoice_id == ctx.accounts.invoice.invoice_id && entry.beneficiary == ctx.accounts.invoice.business_owner,
+            ErrorCode::OutboxInvoiceMismatch
+        );
+        let amount = entry.amount;
+        require_no_delegate(&ctx.accounts.outbox_escrow)?;
+        require_allowlisted_destination(&ctx.accounts.allowlist, &ctx.accounts.custody.key(), current_time)?;
+
+        let seeds = &[OUTBOX_AUTHORITY_SEED, &[payout_processor.authority_bump]];
+        let signer_seeds = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.outbox_escrow.to_account_info(),
+                to: ctx.accounts.custody.to_account_info(),
+                authority: ctx.accounts.outbox_authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        let entry = ctx.accounts.outbox_page.entry_mut(index)?;
+        entry.status = OutboxEntryStatus::Processed;
+        entry.reference_hash = reference_hash;
+        entry.processed_at = Some(current_time);
+        ctx.accounts.invoice.released_at = Some(current_time);
+
+        emit_bounded(OutboxEntryAcked {
+            index,
+            invoice_id: entry.invoice_id,
+            amount,
+            reference_hash,
+            invoice: ctx.accounts.invoice.key(),
+            business_owner: ctx.accounts.invoice.business_owner,
+            investor: ctx.accounts.invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Outbox entry {} processed", index);
+        Ok(())
+    }
+
+    // Re-flag an unacked entry for another delivery attempt once the ack timeout lapsed
+    pub fn retry_outbox_entry(ctx: Context<RetryOutboxEntry>, index: u64) -> Result<()> {
+        let payout_processor = &ctx.accounts.payout_processor;
+        let signer = ctx.accounts.signer.key();
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let entry = ctx.accounts.outbox_page.entry_mut(index)?;
+        require!(entry.status == OutboxEntryStatus::Pending, ErrorCode::OutboxEntryNotPending);
+        require!(
+            signer == payout_processor.processor || signer == entry.beneficiary,
+            ErrorCode::Unauthorized
+        );
+        require!(
+            entry.invoice_id == ctx.accounts.invoice.invoice_id && entry.beneficiary == ctx.accounts.invoice.business_owner,
+            ErrorCode::OutboxInvoiceMismatch
+        );
+        require!(
+            current_time >= entry.queued_at + payout_processor.ack_timeout_secs,
+            ErrorCode::OutboxEntryNotTimedOut
+        );
+
+        entry.queued_at = current_time;
+        entry.retries += 1;
+
+        emit_bounded(OutboxEntryRetried {
+            index,
+            invoice_id: entry.invoice_id,
+            retries: entry.retries,
+            invoice: ctx.accounts.invoice.key(),
+            business_owner: ctx.accounts.invoice.business_owner,
+            investor: ctx.accounts.invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Outbox entry {} re-queued (retry {})", index, entry.retries);
+        Ok(())
+    }
+
+    // Business gives up on a timed-out off-ramp payout and takes the funds on-chain
+    pub fn cancel_outbox_entry(ctx: Context<CancelOutboxEntry>, index: u64) -> Result<()> {
+        let payout_processor = &ctx.accounts.payout_processor;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let entry = ctx.accounts.outbox_page.entry_mut(index)?;
+        require!(entry.status == OutboxEntryStatus::Pending, ErrorCode::OutboxEntryNotPending);
+        require!(
+            entry.beneficiary == ctx.accounts.business_owner.key(),
+            ErrorCode::Unauthorized
+        );
+        require!(
+            entry.invoice_id == ctx.accounts.invoice.invoice_id && entry.beneficiary == ctx.accounts.invoice.business_owner,
+            ErrorCode::OutboxInvoiceMismatch
+        );
+        require!(
+            current_time >= entry.queued_at + payout_processor.ack_timeout_secs,
+            ErrorCode::OutboxEntryNotTimedOut
+        );
+        let amount = entry.amount;
+        require_no_delegate(&ctx.accounts.outbox_escrow)?;
+
+        let seeds = &[OUTBOX_AUTHORITY_SEED, &[payout_processor.authority_bump]];
+        let signer_seeds = &[&seeds[..]];
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.outbox_escrow.to_account_info(),
+                to: ctx.accounts.business_token_account.to_account_info(),
+                authority: ctx.accounts.outbox_authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        let entry = ctx.accounts.outbox_page.entry_mut(index)?;
+        entry.status = OutboxEntryStatus::Cancelled;
+        entry.processed_at = Some(current_time);
+        ctx.accounts.invoice.released_at = Some(current_time);
+
+        emit_bounded(OutboxEntryCancelled {
+            index,
+            invoice_id: entry.invoice_id,
+            amount,
+            invoice: ctx.accounts.invoice.key(),
+            business_owner: ctx.accounts.invoice.business_owner,
+            investor: ctx.accounts.invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Outbox entry {} cancelled, {} returned to business", index, amount);
+        Ok(())
+    }
+
+    // Configure the fee-free micro-invoice tier used to onboard freelancers
+    pub fn configure_micro_tier(
+        ctx: Context<ConfigureMicroTier>,
+        max_amount: u64,
+        risk_score: u8,
+        premium_bps: u16,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(
+            max_amount > 0 && max_amount <= MICRO_TIER_MAX_AMOUNT,
+            ErrorCode::InvalidMicroTierConfig
+        );
+        require!(risk_score <= 50, ErrorCode::InvalidMicroTierConfig);
+        require!(premium_bps <= 10_000, ErrorCode::InvalidMicroTierConfig);
+
+        let micro_tier = &mut ctx.accounts.micro_tier;
+        micro_tier.max_amount = max_amount;
+        micro_tier.risk_score = risk_score;
+        micro_tier.premium_bps = premium_bps;
+        micro_tier.bump = ctx.bumps.micro_tier;
+
+        emit_bounded(MicroTierConfigured {
+            max_amount,
+            risk_score,
+            premium_bps,
+            action: AdminActionCode::MicroTierConfigured,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Micro tier set: up to {} USDC at risk score {}", max_amount, risk_score);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::MicroTierConfigured,
+            Some(max_amount),
+        )
+    }
+
+    // Set the cluster discriminator (genesis hash) bound into every signed message
+    pub fn set_cluster_id(ctx: Context<UpdateGlobalState>, cluster_id: [u8; 32]) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        global_state.cluster_id = cluster_id;
+
+        msg!("Cluster discriminator updated");
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::ClusterIdSet,
+            None,
+        )
+    }
+
+    // Rotate the key whose credit attestations create_invoice accepts, or stop
+    // accepting them with None
+    pub fn set_oracle_authority(ctx: Context<UpdateGlobalState>, oracle_authority: Option<Pubkey>) -> Result<()> {
+        authorize_guarded(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let global_state = &mut ctx.accounts.global_state;
+        let previous = global_state.oracle_authority;
+        global_state.oracle_authority = oracle_authority;
+
+        emit_bounded(OracleAuthoritySet {
+            previous,
+            oracle_authority,
+            action: AdminActionCode::OracleAuthoritySet,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Credit oracle set to {:?}", oracle_authority);
+        record_admin_action(
+            global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::OracleAuthoritySet,
+            None,
+        )
+    }
+
+    // Schedule a premium pricing experiment (starts no earlier than the timelock)
+    pub fn create_experiment(
+        ctx: Context<CreateExperiment>,
+        experiment_id: u64,
+        premium_bps_per_risk_point: u16,
+        traffic_bps: u16,
+        starts_at: i64,
+        ends_at: i64,
+        max_premium_delta_bps: u16,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        let experiment = &mut ctx.accounts.experiment;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(premium_bps_per_risk_point > 0, ErrorCode::InvalidExperimentParams);
+        require!(traffic_bps <= 10_000, ErrorCode::InvalidExperimentParams);
+        require!(ends_at > starts_at, ErrorCode::InvalidExperimentParams);
+        require!(
+            starts_at >= current_time + EXPERIMENT_TIMELOCK_SECS,
+            ErrorCode::TimelockNotElapsed
+        );
+        // One experiment at a time: listings are held to the one recorded here
+        let global_state = &mut ctx.accounts.global_state;
+        require!(
+            global_state.pricing_experiment.is_none() || global_state.pricing_experiment_ends_at <= starts_at,
+            ErrorCode::ExperimentOverlap
+        );
+        global_state.pricing_experiment = Some(experiment.key());
+        global_state.pricing_experiment_starts_at = starts_at;
+        global_state.pricing_experiment_ends_at = ends_at;
+
+        experiment.experiment_id = experiment_id;
+        experiment.premium_bps_per_risk_point = premium_bps_per_risk_point;
+        experiment.traffic_bps = traffic_bps;
+        experiment.starts_at = starts_at;
+        experiment.ends_at = ends_at;
+        experiment.max_premium_delta_bps = max_premium_delta_bps;
+        experiment.control = ArmCounters::default();
+        experiment.treatment = ArmCounters::default();
+        experiment.bump = ctx.bumps.experiment;
+
+        emit_bounded(ExperimentCreated {
+            experiment_id,
+            premium_bps_per_risk_point,
+            traffic_bps,
+            starts_at,
+            ends_at,
+            max_premium_delta_bps,
+            action: AdminActionCode::ExperimentCreated,
+            timestamp: current_time,
+        });
+
+        msg!("Experiment {} scheduled from {} to {}", experiment_id, starts_at, ends_at);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::ExperimentCreated,
+            None,
+        )
+    }
+
+    // Get per-arm outcome counters of an experiment (view function)
+    pub fn get_experiment_results(ctx: Context<GetExperimentResults>) -> Result<ExperimentResults> {
+        let experiment = &ctx.accounts.experiment;
+
+        Ok(ExperimentResults {
+            experiment_id: experiment.experiment_id,
+            traffic_bps: experiment.traffic_bps,
+            control: experiment.control,
+            treatment: experiment.treatment,
+        })
+    }
+
+    // Approve a collections agency and its fee arrangement
+    pub fn register_collections_agency(
+        ctx: Context<RegisterCollectionsAgency>,
+        agency: Pubkey,
+        fee_bps: u16,
+    ) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Governed)?;
+        require!(fee_bps <= MAX_COLLECTIONS_FEE_BPS, ErrorCode::InvalidCollectionsFee);
+
+        let collections_agency = &mut ctx.accounts.collections_agency;
+        collections_agency.authority = agency;
+        collections_agency.fee_bps = fee_bps;
+        collections_agency.bump = ctx.bumps.collections_agency;
+
+        emit_bounded(CollectionsAgencyRegistered {
+            agency,
+            fee_bps,
+            action: AdminActionCode::CollectionsAgencyRegistered,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Collections agency {} registered at {} bps", agency, fee_bps);
+        record_admin_action(
+            &mut ctx.accounts.global_state,
+            &mut ctx.accounts.admin_log,
+            ctx.accounts.authority.key(),
+            AdminActionCode::CollectionsAgencyRegistered,
+            None,
+        )
+    }
+
+    // Refer a defaulted invoice to a registered collections agency
+    pub fn assign_collections(ctx: Context<AssignCollections>) -> Result<()> {
+        authorize(&ctx.accounts.global_state, ctx.accounts.authority.as_ref(), Lane::Fast)?;
+        let invoice = &ctx.accounts.invoice;
+        let agency = &mut ctx.accounts.collections_agency;
+        let assignment = &mut ctx.accounts.collections_assignment;
+
+        require!(invoice.status == InvoiceStatus::Defaulted, ErrorCode::InvoiceNotDefaulted);
+
+        assignment.invoice = invoice.key();
+        assignment.agency = agency.key();
+        assignment.fee_bps = agency.fee_bps;
+        assignment.assigned_at = Clock::get()?.unix_timestamp;
+        assignment.active = true;
+        assignment.bump = ctx.bumps.collections_assignment;
+        agency.assignments += 1;
+
+        let audit_log = &mut ctx.accounts.invoice_audit_log;
+        audit_log.invoice = invoice.key();
+        audit_log.bump = ctx.bumps.invoice_audit_log;
+        audit_log.record(AdminAction {
+            actor: ctx.accounts.authority.key(),
+            role: AdminRole::ProtocolAuthority,
+            action: AdminActionCode::CollectionsAssigned,
+            timestamp: assignment.assigned_at,
+            amount: None,
+        });
+
+        emit_bounded(CollectionsAssigned {
+            invoice_id: invoice.invoice_id,
+            agency: agency.authority,
+            fee_bps: agency.fee_bps,
+            action: AdminActionCode::CollectionsAssigned,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Invoice {} assigned to collections agency {}", invoice.invoice_id, agency.authority);
+        Ok(())
+    }
+
+    // Pay money recovered on a defaulted invoice through recovery_waterfall: the
+    // agency's fee slice while an assignment is active, then the insurance pool up to
+    // what it paid out, then the investor up to the uncovered principal and yield,
+    // and any excess back to the business
+    pub fn remit_recovery(ctx: Context<RemitRecovery>, amount: u64) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(invoice.status == InvoiceStatus::Defaulted, ErrorCode::InvoiceNotDefaulted);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require_keys_eq!(invoice.mint, global_state.usdc_mint, ErrorCode::PrimaryMintOnly);
+
+        let split = distribute_recovery(
+            invoice,
+            global_state,
+            RecoveryAccounts {
+                token_program: ctx.accounts.token_program.to_account_info(),
+                source: ctx.accounts.payer_token_account.to_account_info(),
+                payer: ctx.accounts.payer.to_account_info(),
+                insurance_pool_account: &ctx.accounts.insurance_pool_account,
+                collections_assignment: ctx.accounts.collections_assignment.as_mut(),
+                collections_agency: ctx.accounts.collections_agency.as_mut(),
+                agency_token_account: ctx.accounts.agency_token_account.as_ref(),
+                investor_token_account: ctx.accounts.investor_token_account.as_ref(),
+                invoice_vault: ctx.accounts.invoice_vault.as_ref(),
+                business_token_account: ctx.accounts.business_token_account.as_ref(),
+                pair_ledger: ctx.accounts.pair_ledger.as_mut(),
+                portfolio: ctx.accounts.portfolio.as_mut(),
+            },
+            amount,
+            current_time,
+        )?;
+
+        emit_bounded(RecoveryRemitted {
+            invoice_id: invoice.invoice_id,
+            amount,
+            agency_fee: split.agency_fee,
+            to_pool: split.pool,
+            to_investor: split.investor,
+            to_business: split.business,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Recovered {} USDC on invoice {}", amount, invoice.invoice_id);
+        Ok(())
+    }
+
+    // The business, or the authority on its behalf, pays in what it recovered from the
+    // debtor after default, split as in remit_recovery: an active collections agency's
+    // fee, then the insurance pool up to what it paid out, then the investor up to the
+    // principal the insurance left uncovered plus the contractual yield; any excess
+    // goes back to the business. Settlements can arrive in several deposits, and the
+    // one that makes both whole leaves the invoice RepaidAfterDefault.
+    pub fn settle_recovery(ctx: Context<SettleRecovery>, amount: u64) -> Result<()> {
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(invoice.status == InvoiceStatus::Defaulted, ErrorCode::InvoiceNotDefaulted);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        let split = distribute_recovery(
+            invoice,
+            global_state,
+            RecoveryAccounts {
+                token_program: ctx.accounts.token_program.to_account_info(),
+                source: ctx.accounts.settler_token_account.to_account_info(),
+                payer: ctx.accounts.settler.to_account_info(),
+                insurance_pool_account: &ctx.accounts.insurance_pool_account,
+                collections_assignment: ctx.accounts.collections_assignment.as_mut(),
+                collections_agency: ctx.accounts.collections_agency.as_mut(),
+                agency_token_account: ctx.accounts.agency_token_account.as_ref(),
+                investor_token_account: ctx.accounts.investor_token_account.as_ref(),
+                invoice_vault: ctx.accounts.invoice_vault.as_ref(),
+                business_token_account: ctx.accounts.business_token_account.as_ref(),
+                pair_ledger: ctx.accounts.pair_ledger.as_mut(),
+                portfolio: ctx.accounts.portfolio.as_mut(),
+            },
+            amount,
+            current_time,
+        )?;
+
+        emit_bounded(RecoverySettled {
+            invoice_id: invoice.invoice_id,
+            settled_by: ctx.accounts.settler.key(),
+            amount,
+            agency_fee: split.agency_fee,
+            to_pool: split.pool,
+            to_investor: split.investor,
+            to_business: split.business,
+            recovered_amount: invoice.recovered_amount,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!(
+            "Settled {} recovered on invoice {}: {} to pool, {} to investor, {} to business",
+            amount,
+            invoice.invoice_id,
+            split.pool,
+            split.investor,
+            split.business
+        );
+        Ok(())
+    }
+
+    // Agency formally closes pursuit of a debt it could not collect
+    pub fn report_uncollectible(ctx: Context<ReportUncollectible>) -> Result<()> {
+        let invoice = &ctx.accounts.invoice;
+        let agency = &mut ctx.accounts.collections_agency;
+        let assignment = &mut ctx.accounts.collections_assignment;
+
+        require!(assignment.active, ErrorCode::CollectionsNotActive);
+
+        let current_time = Clock::get()?.unix_timestamp;
+        assignment.active = false;
+        assignment.closed_at = Some(current_time);
+        agency.uncollectible_count += 1;
+
+        ctx.accounts.invoice_audit_log.record(AdminAction {
+            actor: agency.authority,
+            role: AdminRole::CollectionsAgency,
+            action: AdminActionCode::ReportedUncollectible,
+            timestamp: current_time,
+            amount: Some(assignment.recovered),
+        });
+
+        emit_bounded(DebtReportedUncollectible {
+            invoice_id: invoice.invoice_id,
+            business_owner: invoice.business_owner,
+            agency: agency.authority,
+            recovered: assignment.recovered,
+            action: AdminActionCode::ReportedUncollectible,
+            invoice: invoice.key(),
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Invoice {} reported uncollectible by {}", invoice.invoice_id, agency.authority);
+        Ok(())
+    }
+
+    // Track record of a collections agency (view function)
+    pub fn get_collections_agency_stats(ctx: Context<GetCollectionsAgencyStats>) -> Result<CollectionsAgencyStats> {
+        let agency = &ctx.accounts.collections_agency;
+
+        Ok(CollectionsAgencyStats {
+            agency: agency.authority,
+            fee_bps: agency.fee_bps,
+            assignments: agency.assignments,
+            recovered_total: agency.recovered_total,
+            fees_earned: agency.fees_earned,
+            uncollectible_count: agency.uncollectible_count,
+        })
+    }
+
+    // Bundle 2-10 of the owner's unfunded invoices (passed as writable remaining
+    // accounts) into one fundable unit priced on their amount-weighted risk. The
+    // invoices are locked against individual funding while bundled.
+    pub fn create_bundle(ctx: Context<CreateBundle>, bundle_id: u64, same_debtor: bool) -> Result<()> {
+        ctx.accounts.global_state.require_not_paused(PAUSE_CREATE)?;
+        let bundle_key = ctx.accounts.bundle.key();
+        let business_owner = ctx.accounts.business_owner.key();
+        let count = ctx.remaining_accounts.len();
+        require!((MIN_BUNDLE_SIZE..=MAX_BUNDLE_SIZE).contains(&count), ErrorCode::InvalidBundleSize);
+
+        let mut constituents: Vec<BundleConstituent> = Vec::with_capacity(count);
+        let mut risks = Vec::with_capacity(count);
+        let mut terms = Vec::with_capacity(count);
+        let mut debtor: Option<Pubkey> = None;
+        let current_time = Clock::get()?.unix_timestamp;
+        for info in ctx.remaining_accounts {
+            require!(
+                constituents.iter().all(|constituent| constituent.invoice != info.key()),
+                ErrorCode::BundleConstituentMismatch
+            );
+            let mut invoice = load_invoice(info)?;
+            require_keys_eq!(invoice.business_owner, business_owner, ErrorCode::InvoiceOwnerMismatch);
+            require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+            require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+            require!(
+                !invoice.partial_funding
+                    && !invoice.offramp_requested
+                    && !invoice.premium_prepaid
+                    && invoice.funding_mode() == FundingMode::Par
+                    && invoice.originator.is_none()
+                    && invoice.mint == ctx.accounts.global_state.usdc_mint,
+                ErrorCode::BundleConstituentIneligible
+            );
+            // Same debtor registry entry; invoices from before the registry have none
+            if same_debtor {
+                require!(invoice.debtor != Pubkey::default(), ErrorCode::BundleDebtorMismatch);
+                match debtor {
+                    Some(debtor) => require_keys_eq!(debtor, invoice.debtor, ErrorCode::BundleDebtorMismatch),
+                    None => debtor = Some(invoice.debtor),
+                }
+            }
+
+            risks.push((invoice.amount, invoice.risk_assessment()));
+            terms.push((invoice.amount, term_days(current_time, invoice.due_date)));
+            constituents.push(BundleConstituent {
+                invoice: info.key(),
+                invoice_id: invoice.invoice_id,
+                amount: invoice.amount,
+            });
+            invoice.bundle = Some(bundle_key);
+            store_invoice(info, &invoice)?;
+        }
+
+        let amount = constituents.iter().map(|constituent| constituent.amount).sum();
+        let risk = blend_risk(&risks);
+        let coverage = ctx.accounts.global_state.config.coverage;
+        // Quoted over the amount-weighted term; funding refixes each constituent's own
+        let term = blend_term(&terms);
+        let min_premium = ctx.accounts.global_state.min_insurance_premium;
+        let inputs = PricingInputs::new(amount, risk, PremiumSchedule::RiskScaled, coverage, term);
+        let pricing = price_invoice(&inputs.with_min_premium(min_premium))?;
+
+        let bundle = &mut ctx.accounts.bundle;
+        bundle.bundle_id = bundle_id;
+        bundle.business_owner = business_owner;
+        bundle.status = BundleStatus::Open;
+        bundle.constituents = constituents;
+        bundle.amount = amount;
+        bundle.risk_score = risk.risk_score;
+        bundle.insurance_premium = pricing.insurance_premium;
+        bundle.expected_return = pricing.expected_return(amount)?;
+        bundle.pricing_version = pricing.version;
+        bundle.coverage_percentage = pricing.coverage_percentage as u8;
+        bundle.created_at = current_time;
+        bundle.bump = ctx.bumps.bundle;
+
+        emit_bounded(BundleCreated {
+            bundle_id,
+            business_owner,
+            invoice_ids: bundle.invoice_ids(),
+            amount,
+            risk_score: risk.risk_score,
+            insurance_premium: pricing.insurance_premium,
+            bundle: bundle.key(),
+            investor: bundle.investor,
+            timestamp: current_time,
+        });
+
+        msg!("Bundle {} created from {} invoices, risk score {}", bundle_id, count, risk.risk_score);
+        Ok(())
+    }
+
+    // Dissolve a bundle nobody has funded yet, releasing its invoices
+    pub fn unbundle(ctx: Context<Unbundle>) -> Result<()> {
+        let bundle = &ctx.accounts.bundle;
+        require!(bundle.status == BundleStatus::Open, ErrorCode::BundleNotOpen);
+
+        for (info, mut invoice) in bundle.load_constituents(ctx.remaining_accounts)? {
+            invoice.bundle = None;
+            store_invoice(info, &invoice)?;
+        }
+
+        emit_bounded(BundleDissolved {
+            bundle_id: bundle.bundle_id,
+            business_owner: bundle.business_owner,
+            invoice_ids: bundle.invoice_ids(),
+            bundle: bundle.key(),
+            investor: bundle.investor,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Bundle {} dissolved", bundle.bundle_id);
+        Ok(())
+    }
+
+    // Fund a whole bundle: one principal transfer to the business, one premium to the
+    // insurance pool. Each constituent becomes a funded invoice owned by the investor,
+    // carrying its pro-rata share of the bundle's premium and yield.
+    pub fn fund_bundle(ctx: Context<FundBundle>, max_premium: u64) -> Result<()> {
+        let bundle = &mut ctx.accounts.bundle;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        global_state.require_not_paused(PAUSE_FUND)?;
+        require_not_blacklisted(&ctx.accounts.investor_blacklist, BlacklistRole::Investor)?;
+        require_not_blacklisted(&ctx.accounts.business_blacklist, BlacklistRole::Business)?;
+        require!(bundle.status == BundleStatus::Open, ErrorCode::BundleNotOpen);
+        require!(bundle.insurance_premium <= max_premium, ErrorCode::SlippageExceeded);
+        global_state.require_whitelisted(ctx.accounts.investor_whitelist.as_deref(), current_time)?;
+        let amount = bundle.amount;
+        require!(
+            ctx.accounts.investor_token_account.amount >= amount + bundle.insurance_premium,
+            ErrorCode::InsufficientFunds
+        );
+
+        let investor_stats = &mut ctx.accounts.investor_stats;
+        investor_stats.require_retail_guardrails(global_state.retail_guardrails, amount, bundle.risk_score)?;
+        global_state.book_exposure(investor_stats, &mut ctx.accounts.business_profile, amount)?;
+        ctx.accounts.business_profile.record_funding(bundle.constituents.len() as u64, amount)?;
+        global_state.record_daily_funding(amount, current_time)?;
+
+        let transfer_principal_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.investor_token_account.to_account_info(),
+                to: ctx.accounts.business_token_account.to_account_info(),
+                authority: ctx.accounts.investor.to_account_info(),
+            },
+        );
+        token::transfer(transfer_principal_ctx, amount)?;
+
+        let transfer_premium_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.investor_token_account.to_account_info(),
+                to: ctx.accounts.insurance_pool_account.to_account_info(),
+                authority: ctx.accounts.investor.to_account_info(),
+            },
+        );
+        token::transfer(transfer_premium_ctx, bundle.insurance_premium)?;
+        global_state.collect_premium(bundle.insurance_premium)?;
+
+        let investor = ctx.accounts.investor.key();
+        let amounts: Vec<u64> = bundle.constituents.iter().map(|constituent| constituent.amount).collect();
+        let premiums = allocate_pro_rata(bundle.insurance_premium, &amounts);
+        let yields = allocate_pro_rata(bundle.expected_return - amount, &amounts);
+        let mut expected_return = 0u64;
+        let (invoice_accounts, debtor_accounts) = bundle.split_accounts(ctx.remaining_accounts);
+        let mut debtors = DebtorBook::load(debtor_accounts)?;
+        for (index, (info, mut invoice)) in bundle.load_constituents(invoice_accounts)?.into_iter().enumerate() {
+            require!(invoice.funding_open(current_time), ErrorCode::FundingWindowClosed);
+            require!(invoice.debtor_wallet != Some(investor), ErrorCode::DebtorWalletIsParty);
+            global_state.require_acknowledged(&invoice)?;
+            if let Some(debtor) = debtors.booked_debtor(&invoice)? {
+                debtor.book_funding(invoice.amount, global_state.debtor_exposure_cap)?;
+            }
+            invoice.transition(InvoiceStatus::Funded)?;
+            invoice.param_versions.funded = global_state.param_version;
+            invoice.investor = investor;
+            invoice.funded_amount = invoice.amount;
+            invoice.remaining_balance = invoice.amount;
+            invoice.funding_date = Some(current_time);
+            invoice.released_at = Some(current_time);
+            invoice.risk_score = bundle.risk_score;
+            invoice.insurance_premium = premiums[index];
+            invoice.premium_bps = bundle.risk_score as u16 * pricing::CONTROL_PREMIUM_BPS_PER_RISK_POINT;
+            invoice.expected_return = Some(invoice.amount + yields[index]);
+            invoice.pricing_version = bundle.pricing_version;
+            invoice.apr_bps = pricing::apr_bps(bundle.risk_score);
+            invoice.fix_term_yield(current_time)?;
+            expected_return = expected_return
+                .checked_add(invoice.expected_return.unwrap_or(invoice.amount))
+                .ok_or(ErrorCode::MathOverflow)?;
+            invoice.coverage_percentage = bundle.coverage_percentage;
+            invoice.exposure_booked = true;
+            sync_insured_exposure(&mut invoice, global_state)?;
+            store_invoice(info, &invoice)?;
+        }
+        debtors.store()?;
+        global_state.require_coverage_capacity()?;
+
+        bundle.status = BundleStatus::Funded;
+        bundle.expected_return = expected_return;
+        bundle.investor = investor;
+        bundle.funding_date = Some(current_time);
+
+        investor_stats.investor = investor;
+        investor_stats.bump = ctx.bumps.investor_stats;
+        investor_stats.deployed_capital = investor_stats.deployed_capital.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        global_state.total_funded += amount;
+
+        emit_bounded(BundleFunded {
+            bundle_id: bundle.bundle_id,
+            investor,
+            invoice_ids: bundle.invoice_ids(),
+            amount,
+            insurance_premium: bundle.insurance_premium,
+            expected_return: bundle.expected_return,
+            bundle: bundle.key(),
+            business_owner: bundle.business_owner,
+            timestamp: current_time,
+        });
+
+        msg!("Bundle {} funded by {} for {} USDC", bundle.bundle_id, investor, amount);
+        Ok(())
+    }
+
+    // Repay a funded bundle. The payment is split across constituents still being
+    // repaid in proportion to what each owes; defaulted constituents are settled
+    // through their own insurance claim and recoveries instead.
+    pub fn repay_bundle(ctx: Context<RepayBundle>, repayment_amount: u64, max_total: Option<u64>) -> Result<()> {
+        let bundle = &mut ctx.accounts.bundle;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        require!(bundle.status == BundleStatus::Funded, ErrorCode::BundleNotFunded);
+        require!(repayment_amount > 0, ErrorCode::InvalidAmount);
+
+        let (invoice_accounts, debtor_accounts) = bundle.split_accounts(ctx.remaining_accounts);
+        let (infos, mut invoices): (Vec<_>, Vec<_>) = bundle.load_constituents(invoice_accounts)?.into_iter().unzip();
+        let was_repaid: Vec<bool> = invoices.iter().map(|invoice| invoice.status == InvoiceStatus::Repaid).collect();
+        let balances_before: Vec<u64> = invoices.iter().map(|invoice| invoice.remaining_balance).collect();
+        let allocations = apply_bundle_repayment(&mut invoices, repayment_amount, max_total, current_time, global_state)?;
+        let mut debtors = DebtorBook::load(debtor_accounts)?;
+        for (invoice, before) in invoices.iter().zip(balances_before) {
+            let principal = before - invoice.remaining_balance;
+            if let Some(debtor) = debtors.booked_debtor(invoice)? {
+                debtor.release(principal);
+            }
+            invoice.release_exposure(
+                ctx.accounts.investor_stats.as_deref_mut(),
+                &mut ctx.accounts.business_profile,
+                principal,
+            )?;
+        }
+        debtors.store()?;
+        let repayment_amount: u64 = allocations.iter().sum();
+        require!(
+            ctx.accounts.business_token_account.amount >= repayment_amount,
+            ErrorCode::InsufficientRepaymentFunds
+        );
+
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.business_token_account.to_account_info(),
+                to: ctx.accounts.investor_token_account.to_account_info(),
+                authority: ctx.accounts.business_owner.to_account_info(),
+            },
+        );
+        token::transfer(transfer_ctx, repayment_amount)?;
+
+        let business_profile = &mut ctx.accounts.business_profile;
+        business_profile.business_owner = bundle.business_owner;
+        business_profile.bump = ctx.bumps.business_profile;
+        for ((info, invoice), was_repaid) in infos.iter().zip(invoices.iter_mut()).zip(was_repaid) {
+            sync_insured_exposure(invoice, global_state)?;
+            store_invoice(info, invoice)?;
+            // Each constituent settled this time counts as one repayment, late or not
+            if !was_repaid && invoice.status == InvoiceStatus::Repaid {
+                let late = (current_time - invoice.due_date) / 86400 > 0;
+                if late {
+                    business_profile.history.record_late_repayment(current_time);
+                }
+                business_profile.credit_history.record_repayment(late, invoice.funded_amount);
+                business_profile.close_invoice();
+            }
+        }
+        bundle.amount_repaid += repayment_amount;
+
+        let constituents = infos
+            .iter()
+            .zip(&invoices)
+            .zip(allocations)
+            .map(|((info, invoice), amount)| {
+                Ok(ConstituentRepayment {
+                    invoice_id: invoice.invoice_id,
+                    amount,
+                    remaining_balance: invoice.outstanding_balance()?,
+                    invoice: info.key(),
+                    status: invoice.status,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        emit_bounded(BundleRepaymentAllocated {
+            bundle_id: bundle.bundle_id,
+            amount: repayment_amount,
+            allocations: constituents,
+            bundle: bundle.key(),
+            business_owner: bundle.business_owner,
+            investor: bundle.investor,
+            timestamp: current_time,
+        });
+
+        if bundle_settled(&invoices) {
+            settle_bundle_account(bundle, current_time);
+        }
+
+        msg!("Bundle {} repaid {} USDC", bundle.bundle_id, repayment_amount);
+        Ok(())
+    }
+
+    // Permissionless crank: close out a funded bundle once every constituent is
+    // repaid or defaulted, e.g. when the last one was settled by mark_defaulted
+    pub fn settle_bundle(ctx: Context<SettleBundle>) -> Result<()> {
+        let bundle = &mut ctx.accounts.bundle;
+        require!(bundle.status == BundleStatus::Funded, ErrorCode::BundleNotFunded);
+
+        let invoices: Vec<Invoice> = bundle
+            .load_constituents(ctx.remaining_accounts)?
+            .into_iter()
+            .map(|(_, invoice)| invoice)
+            .collect();
+        require!(bundle_settled(&invoices), ErrorCode::BundleNotSettled);
+
+        settle_bundle_account(bundle, Clock::get()?.unix_timestamp);
+        Ok(())
+    }
+
+    // Open an investment pool managed by the signer, see pool.rs
+    pub fn create_investment_pool(ctx: Context<CreateInvestmentPool>, max_risk_score: u8) -> Result<()> {
+        let pool_key = ctx.accounts.pool.key();
+        let pool = &mut ctx.accounts.pool;
+        pool.manager = ctx.accounts.manager.key();
+        pool.mint = ctx.accounts.usdc_mint.key();
+        pool.share_mint = ctx.accounts.share_mint.key();
+        pool.vault = ctx.accounts.vault.key();
+        pool.max_risk_score = max_risk_score;
+        pool.deployed = 0;
+        pool.open_positions = 0;
+        pool.invoices_funded = 0;
+        pool.authority_bump = ctx.bumps.pool_authority;
+        pool.bump = ctx.bumps.pool;
+
+        emit_bounded(InvestmentPoolCreated {
+            pool: pool_key,
+            manager: pool.manager,
+            share_mint: pool.share_mint,
+            vault: pool.vault,
+            max_risk_score,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Investment pool {} opened by {}", pool_key, pool.manager);
+        Ok(())
+    }
+
+    // Deposit into a pool for shares at its current net asset value
+    pub fn deposit_to_pool(ctx: Context<DepositToPool>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        let pool = &ctx.accounts.pool;
+        let pool_key = pool.key();
+        let nav = pool.nav(ctx.accounts.vault.amount)?;
+        let shares = shares_for_deposit(amount, nav, ctx.accounts.share_mint.supply)?;
+        require!(shares > 0, ErrorCode::InvalidAmount);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.depositor_token_account.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.depositor.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        let bump = [pool.authority_bump];
+        let seeds: &[&[u8]] = &[POOL_AUTHORITY_SEED, pool_key.as_ref(), &bump];
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    to: ctx.accounts.depositor_shares.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[seeds],
+            ),
+            shares,
+        )?;
+
+        emit_bounded(PoolDeposited {
+            pool: pool_key,
+            depositor: ctx.accounts.depositor.key(),
+            amount,
+            shares,
+            nav,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("{} deposited {} USDC into pool {} for {} shares", ctx.accounts.depositor.key(), amount, pool_key, shares);
+        Ok(())
+    }
+
+    // Burn shares for their slice of the pool's net asset value, paid from the
+    // vault; fails while too much of the pool is out in invoices
+    pub fn redeem_from_pool(ctx: Context<RedeemFromPool>, shares: u64) -> Result<()> {
+        require!(shares > 0, ErrorCode::InvalidAmount);
+        let pool = &ctx.accounts.pool;
+        let pool_key = pool.key();
+        let nav = pool.nav(ctx.accounts.vault.amount)?;
+        let amount = redemption_value(shares, nav, ctx.accounts.share_mint.supply);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(ctx.accounts.vault.amount >= amount, ErrorCode::PoolIlliquid);
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    from: ctx.accounts.holder_shares.to_account_info(),
+                    authority: ctx.accounts.holder.to_account_info(),
+                },
+            ),
+            shares,
+        )?;
+        let bump = [pool.authority_bump];
+        let seeds: &[&[u8]] = &[POOL_AUTHORITY_SEED, pool_key.as_ref(), &bump];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault.to_account_info(),
+                    to: ctx.accounts.holder_token_account.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[seeds],
+            ),
+            amount,
+        )?;
+
+        emit_bounded(PoolRedeemed {
+            pool: pool_key,
+            holder: ctx.accounts.holder.key(),
+            shares,
+            amount,
+            nav,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("{} redeemed {} shares of pool {} for {} USDC", ctx.accounts.holder.key(), shares, pool_key, amount);
+        Ok(())
+    }
+
+    // Manager: fund an invoice in full out of the pool vault. The remaining
+    // accounts are fund_invoice's, with the pool authority as the investor and the
+    // vault as its token account; the pool authority pays the rent of what funding
+    // opens and the manager tops it back up afterwards.
+    pub fn pool_fund_invoice<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PoolFundInvoice<'info>>,
+        max_premium: u64,
+    ) -> Result<()> {
+        let pool_key = ctx.accounts.pool.key();
+        let pool_authority = ctx.accounts.pool_authority.key();
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let inner = ctx.remaining_accounts;
+
+        let listed = load_invoice(&invoice_info)?;
+        require_keys_eq!(listed.mint, ctx.accounts.pool.mint, ErrorCode::TokenMintMismatch);
+        require!(listed.risk_score <= ctx.accounts.pool.max_risk_score, ErrorCode::PoolMandateExceeded);
+        require!(inner.first().map(|info| info.key()) == Some(invoice_info.key()), ErrorCode::InvalidInvoiceAccount);
+        require_vault_destination(inner, &pool_authority, &ctx.accounts.pool.mint, &ctx.accounts.pool.vault)?;
+
+        let float = ctx.accounts.pool_authority.lamports();
+        let data = instruction::FundInvoice {
+            amount: listed.principal(),
+            from_balance: false,
+            max_premium,
+            expected_risk_score: listed.risk_score,
+            expected_due_date: listed.due_date,
+        }
+        .data();
+        let bump = [ctx.accounts.pool.authority_bump];
+        let seeds: &[&[u8]] = &[POOL_AUTHORITY_SEED, pool_key.as_ref(), &bump];
+        invoke_as_pool(data, inner, &ctx.accounts.invoice_financing_program, &pool_authority, &[seeds])?;
+
+        let funded = load_invoice(&invoice_info)?;
+        require_keys_eq!(funded.investor, pool_authority, ErrorCode::Unauthorized);
+        let cost = funded
+            .funded_amount
+            .checked_add(funded.investor_premium())
+            .and_then(|cost| cost.checked_add(funded.investor_originator_fee()))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let spent = float.saturating_sub(ctx.accounts.pool_authority.lamports());
+        if spent > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.manager.to_account_info(),
+                        to: ctx.accounts.pool_authority.to_account_info(),
+                    },
+                ),
+                spent,
+            )?;
+        }
+
+        let position = &mut ctx.accounts.position;
+        position.pool = pool_key;
+        position.invoice = invoice_info.key();
+        position.cost = cost;
+        position.opened_at = Clock::get()?.unix_timestamp;
+        position.bump = ctx.bumps.position;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.deployed = pool.deployed.checked_add(cost).ok_or(ErrorCode::MathOverflow)?;
+        pool.open_positions = pool.open_positions.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        pool.invoices_funded = pool.invoices_funded.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        emit_bounded(PoolInvoiceFunded {
+            pool: pool_key,
+            invoice_id: funded.invoice_id,
+            cost,
+            invoice: invoice_info.key(),
+            business_owner: funded.business_owner,
+            investor: funded.investor,
+            timestamp: position.opened_at,
+        });
+        msg!("Pool {} funded invoice {} at a cost of {} USDC", pool_key, funded.invoice_id, cost);
+        Ok(())
+    }
+
+    // Permissionless crank: bring a settled pool position home. A repaid invoice's
+    // receipt is redeemed, a defaulted one's insurance claimed, and an escrowed
+    // funding past its acceptance deadline reclaimed, whichever applies; the
+    // remaining accounts are that instruction's, as for pool_fund_invoice.
+    pub fn settle_pool_position<'info>(ctx: Context<'_, '_, 'info, 'info, SettlePoolPosition<'info>>) -> Result<()> {
+        let pool_key = ctx.accounts.pool.key();
+        let pool_authority = ctx.accounts.pool_authority.key();
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let inner = ctx.remaining_accounts;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        let invoice = load_invoice(&invoice_info)?;
+        let data = match invoice.status {
+            InvoiceStatus::Repaid | InvoiceStatus::RepaidAfterDefault => instruction::RedeemReceipt {}.data(),
+            InvoiceStatus::Defaulted => instruction::ClaimInsurance {}.data(),
+            InvoiceStatus::FundedPendingAcceptance
+                if invoice.escrow.is_some_and(|escrow| current_time > escrow.deadline) =>
+            {
+                instruction::ReclaimEscrow {}.data()
+            }
+            _ => return Err(ErrorCode::PoolPositionOpen.into()),
+        };
+        require!(inner.first().map(|info| info.key()) == Some(invoice_info.key()), ErrorCode::InvalidInvoiceAccount);
+        require_vault_destination(inner, &pool_authority, &ctx.accounts.pool.mint, &ctx.accounts.pool.vault)?;
+
+        let before = ctx.accounts.vault.amount;
+        let bump = [ctx.accounts.pool.authority_bump];
+        let seeds: &[&[u8]] = &[POOL_AUTHORITY_SEED, pool_key.as_ref(), &bump];
+        invoke_as_pool(data, inner, &ctx.accounts.invoice_financing_program, &pool_authority, &[seeds])?;
+        ctx.accounts.vault.reload()?;
+        let proceeds = ctx.accounts.vault.amount.saturating_sub(before);
+
+        let cost = ctx.accounts.position.cost;
+        let pool = &mut ctx.accounts.pool;
+        pool.deployed = pool.deployed.checked_sub(cost).ok_or(ErrorCode::MathOverflow)?;
+        pool.open_positions = pool.open_positions.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+
+        emit_bounded(PoolPositionSettled {
+            pool: pool_key,
+            invoice_id: invoice.invoice_id,
+            cost,
+            proceeds,
+            invoice: invoice_info.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+        msg!("Pool {} settled invoice {}: cost {}, proceeds {}", pool_key, invoice.invoice_id, cost, proceeds);
+        Ok(())
+    }
+
+    // Pre-commit capital to invoices matching `rules`. The mandate is approved as
+    // delegate over `allowance` of the investor's USDC account; the investor can
+    // top that up later with a plain SPL approve to the mandate.
+    pub fn create_auto_invest_mandate(
+        ctx: Context<CreateAutoInvestMandate>,
+        rules: MandateRules,
+        allowance: u64,
+    ) -> Result<()> {
+        require!(rules.is_valid(), ErrorCode::InvalidMandateRules);
+        token::approve(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Approve {
+                    to: ctx.accounts.investor_token_account.to_account_info(),
+                    delegate: ctx.accounts.mandate.to_account_info(),
+                    authority: ctx.accounts.investor.to_account_info(),
+                },
+            ),
+            allowance,
+        )?;
+
+        let mandate = &mut ctx.accounts.mandate;
+        mandate.investor = ctx.accounts.investor.key();
+        mandate.token_account = ctx.accounts.investor_token_account.key();
+        mandate.rules = rules;
+        mandate.paused = false;
+        mandate.invoices_funded = 0;
+        mandate.total_funded = 0;
+        mandate.created_at = Clock::get()?.unix_timestamp;
+        mandate.bump = ctx.bumps.mandate;
+
+        emit_bounded(AutoInvestMandateCreated {
+            investor: mandate.investor,
+            rules,
+            allowance,
+            timestamp: mandate.created_at,
+        });
+        msg!("Auto-invest mandate opened for {} with {} USDC allowance", mandate.investor, allowance);
+        Ok(())
+    }
+
+    // Stop or resume matching without giving up the allowance
+    pub fn set_auto_invest_paused(ctx: Context<ManageAutoInvestMandate>, paused: bool) -> Result<()> {
+        let mandate = &mut ctx.accounts.mandate;
+        mandate.paused = paused;
+        emit_bounded(AutoInvestMandatePaused {
+            investor: mandate.investor,
+            paused,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Close the mandate, returning its rent, and revoke its allowance unless the
+    // investor has since approved someone else
+    pub fn revoke_auto_invest_mandate(ctx: Context<RevokeAutoInvestMandate>) -> Result<()> {
+        let mandate = &ctx.accounts.mandate;
+        if ctx.accounts.investor_token_account.delegate == COption::Some(mandate.key()) {
+            token::revoke(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Revoke {
+                    source: ctx.accounts.investor_token_account.to_account_info(),
+                    authority: ctx.accounts.investor.to_account_info(),
+                },
+            ))?;
+        }
+        emit_bounded(AutoInvestMandateRevoked {
+            investor: mandate.investor,
+            invoices_funded: mandate.invoices_funded,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Permissionless crank: fund a pending invoice in full for a mandate's investor
+    // when it matches the mandate, out of the mandate's allowance. The investor goes
+    // through every check funding by hand does; whoever cranks pays the rent of
+    // what funding opens.
+    pub fn execute_auto_invest(ctx: Context<ExecuteAutoInvest>) -> Result<()> {
+        let invoice_info = ctx.accounts.invoice.to_account_info();
+        let mandate_info = ctx.accounts.mandate.to_account_info();
+        let mandate = &mut ctx.accounts.mandate;
+        let invoice = &mut ctx.accounts.invoice;
+        let global_state = &mut ctx.accounts.global_state;
+        let current_time = Clock::get()?.unix_timestamp;
+
+        global_state.require_not_paused(PAUSE_FUND)?;
+        require_not_blacklisted(&ctx.accounts.investor_blacklist, BlacklistRole::Investor)?;
+        require_not_blacklisted(&ctx.accounts.business_blacklist, BlacklistRole::Business)?;
+        require!(!mandate.paused, ErrorCode::AutoInvestPaused);
+        require!(invoice.status == InvoiceStatus::PendingFunding, ErrorCode::InvoiceNotAvailable);
+        require!(!invoice.partial_funding, ErrorCode::PartialFundingInvoice);
+        require!(invoice.bundle.is_none(), ErrorCode::InvoiceBundled);
+        require!(invoice.originator.is_none(), ErrorCode::OriginationUnsupported);
+        require!(
+            mandate.rules.matches(invoice, current_time) && invoice.mint == ctx.accounts.investor_token_account.mint,
+            ErrorCode::AutoInvestMismatch
+        );
+        global_state.require_acknowledged(invoice)?;
+
+        let amount = invoice.principal();
+        let premium = invoice.investor_premium();
+        let total_cost = amount.checked_add(premium).ok_or(ErrorCode::MathOverflow)?;
+        let source = &ctx.accounts.investor_token_account;
+        require!(
+            source.delegate == COption::Some(mandate.key()) && source.delegated_amount >= total_cost,
+            ErrorCode::AutoInvestAllowanceExhausted
+        );
+        require!(source.amount >= total_cost, ErrorCode::InsufficientFunds);
+        let outstanding = ctx.accounts.investor_stats.outstanding.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        require!(outstanding <= mandate.rules.exposure_cap, ErrorCode::AutoInvestExposureExceeded);
+
+        admit_funding(
+            global_state,
+            invoice,
+            &mut ctx.accounts.investor_stats,
+            &mut ctx.accounts.business_profile,
+            ctx.accounts.debtor.as_deref_mut(),
+            ctx.accounts.investor_whitelist.as_deref(),
+            amount,
+        )?;
+
+        // Off-ramp invoices never match, so the principal goes to the business or
+        // into escrow pending acceptance
+        let escrowed = global_state.funding_acceptance_window > 0;
+        require_sound_vault(&ctx.accounts.invoice_vault, &invoice.key())?;
+        let investor = mandate.investor;
+        let seeds = &[AUTO_INVEST_MANDATE_SEED, investor.as_ref(), &[mandate.bump]];
+        let signer_seeds = &[&seeds[..]];
+        let principal_destination = if escrowed {
+            ctx.accounts.invoice_vault.to_account_info()
+        } else {
+            ctx.accounts.business_token_account.to_account_info()
+        };
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.investor_token_account.to_account_info(),
+                    to: principal_destination,
+                    authority: mandate_info.clone(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+        if premium > 0 {
+            let premium_destination = if escrowed {
+                ctx.accounts.invoice_vault.to_account_info()
+            } else {
+                ctx.accounts.insurance_pool_account.to_account_info()
+            };
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.investor_token_account.to_account_info(),
+                        to: premium_destination,
+                        authority: mandate_info,
+                    },
+                    signer_seeds,
+                ),
+                premium,
+            )?;
+            if !escrowed {
+                global_state.collect_premium(premium)?;
+            }
+        }
+
+        mark_funded(invoice, global_state, investor, amount, escrowed, false, current_time)?;
+
+        let investor_stats = &mut ctx.accounts.investor_stats;
+        investor_stats.investor = investor;
+        investor_stats.bump = ctx.bumps.investor_stats;
+        investor_stats.deployed_capital = investor_stats.deployed_capital.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        let pair_ledger = &mut ctx.accounts.pair_ledger;
+        pair_ledger.business = invoice.business_owner;
+        pair_ledger.investor = investor;
+        pair_ledger.bump = ctx.bumps.pair_ledger;
+        if !escrowed {
+            pair_ledger.record_funding(invoice.invoice_id, amount, current_time)?;
+            mint_position_receipt(
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.receipt_mint.to_account_info(),
+                ctx.accounts.investor_receipt.to_account_info(),
+                invoice,
+                invoice_info,
+            )?;
+        }
+        record_experiment_outcome(invoice, ctx.accounts.experiment.as_mut(), ExperimentOutcome::Funded)?;
+        global_state.total_funded = global_state.total_funded.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        global_state.require_coverage_capacity()?;
+
+        mandate.invoices_funded = mandate.invoices_funded.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        mandate.total_funded = mandate.total_funded.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        match invoice.escrow {
+            Some(escrow) => emit_bounded(FundingEscrowed {
+                invoice_id: invoice.invoice_id,
+                investor,
+                amount,
+                insurance_premium: invoice.insurance_premium,
+                deadline: escrow.deadline,
+                invoice: invoice.key(),
+                business_owner: invoice.business_owner,
+                timestamp: current_time,
+            }),
+            None => emit_indexed(event_authority!(ctx), InvoiceFunded {
+                invoice_id: invoice.invoice_id,
+                investor,
+                amount,
+                insurance_premium: invoice.insurance_premium,
+                expected_return: invoice.expected_return.unwrap_or(amount),
+                apr_bps: invoice.apr_bps,
+                invoice: invoice.key(),
+                business_owner: invoice.business_owner,
+                timestamp: current_time,
+                coverage_utilization_bps: global_state.coverage_utilization_bps(),
+                originator_fee: invoice.originator_fee_paid,
+                funding_mode: invoice.funding_mode(),
+                face_value: invoice.amount,
+            })?,
+        }
+        emit_bounded(AutoInvestExecuted {
+            investor,
+            invoice_id: invoice.invoice_id,
+            amount,
+            premium,
+            executed_by: ctx.accounts.cranker.key(),
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            timestamp: current_time,
+        });
+
+        msg!("Invoice {} funded for {} by auto-invest mandate", invoice.invoice_id, investor);
+        Ok(())
+    }
+}
+
+// Bundles hold between 2 and 10 invoices
+pub const MIN_BUNDLE_SIZE: usize = 2;
+pub const MAX_BUNDLE_SIZE: usize = 10;
+
+// Split `amount` across entries in proportion to `weights`, rounding dust onto the
+// earliest entries that still have room. Callers keep `amount` within the weights' sum.
+pub fn allocate_pro_rata(amount: u64, weights: &[u64]) -> Vec<u64> {
+    let total = weights.iter().sum();
+    let mut allocations: Vec<u64> = weights.iter().map(|weight| pro_rata(amount, *weight, total)).collect();
+    let mut dust = amount - allocations.iter().sum::<u64>();
+    for (allocation, weight) in allocations.iter_mut().zip(weights) {
+        let extra = dust.min(weight - *allocation);
+        *allocation += extra;
+        dust -= extra;
+    }
+    allocations
+}
+
+// Amount-weighted risk of a bundle. The score rounds up so blending never prices a
+// basket below its constituents' average; industry risk takes the worst constituent.
+pub fn blend_risk(constituents: &[(u64, RiskAssessment)]) -> RiskAssessment {
+    let total: u128 = constituents.iter().map(|(amount, _)| *amount as u128).sum();
+    let weighted = |score: fn(&RiskAssessment) -> u16| -> u16 {
+        let sum: u128 = constituents.iter().map(|(amount, risk)| *amount as u128 * score(risk) as u128).sum();
+        if total == 0 { 0 } else { sum.div_ceil(total) as u16 }
+    };
+    RiskAssessment {
+        risk_score: weighted(|risk| risk.risk_score as u16) as u8,
+        industry_risk: constituents.iter().map(|(_, risk)| risk.industry_risk).max().unwrap_or_default(),
+        estimated_credit_score: weighted(|risk| risk.estimated_credit_score),
+    }
+}
+
+// Amount-weighted term of a bundle's constituents, in days, rounded up like blend_risk
+pub fn blend_term(constituents: &[(u64, u64)]) -> u64 {
+    let total: u128 = constituents.iter().map(|(amount, _)| *amount as u128).sum();
+    let sum: u128 = constituents.iter().map(|(amount, days)| *amount as u128 * *days as u128).sum();
+    if total == 0 { 0 } else { sum.div_ceil(total) as u64 }
+}
+
+// What a repayment actually pays once charges are brought up to date: the requested
+// amount, or with `max_total` the whole of `owed` as long as it stays within the bound
+pub fn repayment_within(requested: u64, owed: u64, max_total: Option<u64>) -> Result<u64> {
+    match max_total {
+        Some(max_total) => {
+            require!(owed <= max_total, ErrorCode::SlippageExceeded);
+            Ok(owed)
+        }
+        None => {
+            require!(requested <= owed, ErrorCode::RepaymentExceedsBalance);
+            Ok(requested)
+        }
+    }
+}
+
+// Accrue charges on the constituents still being repaid and split the payment across
+// them by what each owes. Defaulted constituents, and any past the grace period, get
+// nothing. Returns the amount applied to each constituent.
+pub fn apply_bundle_repayment(
+    invoices: &mut [Invoice],
+    amount: u64,
+    max_total: Option<u64>,
+    current_time: i64,
+    global_state: &mut GlobalState,
+) -> Result<Vec<u64>> {
+    let config = &global_state.config;
+    let outstanding = invoices
+        .iter_mut()
+        .map(|invoice| {
+            let repayable = invoice.status.is_repayable()
+                && current_time <= invoice.due_date + config.grace_period_secs();
+            if !repayable {
+                return Ok(0);
+            }
+            Ok(invoice.accrue_charges(current_time, global_state.min_interest_bps, config.late_fee_bps_per_day)?.outstanding)
+        })
+        .collect::<Result<Vec<u64>>>()?;
+    let total_owed = outstanding
+        .iter()
+        .try_fold(0u64, |total, owed| total.checked_add(*owed))
+        .ok_or(ErrorCode::MathOverflow)?;
+    let amount = repayment_within(amount, total_owed, max_total)?;
+
+    let allocations = allocate_pro_rata(amount, &outstanding);
+    for (invoice, allocation) in invoices.iter_mut().zip(&allocations) {
+        if *allocation == 0 {
+            continue;
+        }
+        let split = invoice.apply_repayment(*allocation)?;
+        let outstanding = invoice.outstanding_balance()?;
+        global_state.record_repayment(*allocation, split.late_fee, outstanding == 0)?;
+        if outstanding > 0 {
+            invoice.transition(invoice.partially_repaid_status())?;
+        } else {
+            invoice.transition(InvoiceStatus::Repaid)?;
+            invoice.param_versions.settled = global_state.param_version;
+            invoice.repayment_date = Some(current_time);
+            invoice.final_repayment_amount = Some(invoice.amount_repaid);
+            invoice.late_fee = Some(invoice.settled_late_fee()?);
+        }
+    }
+    Ok(allocations)
+}
+
+// Every constituent has reached a terminal state
+pub fn bundle_settled(invoices: &[Invoice]) -> bool {
+    invoices
+        .iter()
+        .all(|invoice| {
+            matches!(
+                invoice.status,
+                InvoiceStatus::Repaid | InvoiceStatus::Defaulted | InvoiceStatus::RepaidAfterDefault
+            )
+        })
+}
+
+fn settle_bundle_account(bundle: &mut Account<Bundle>, current_time: i64) {
+    bundle.status = BundleStatus::Settled;
+    bundle.settled_at = Some(current_time);
+
+    emit_bounded(BundleSettled {
+        bundle_id: bundle.bundle_id,
+        amount_repaid: bundle.amount_repaid,
+        bundle: bundle.key(),
+        business_owner: bundle.business_owner,
+        investor: bundle.investor,
+        timestamp: current_time,
+    });
+    msg!("Bundle {} settled", bundle.bundle_id);
+}
+
+// Collections agencies keep at most 50% of what they recover
+pub const MAX_COLLECTIONS_FEE_BPS: u16 = 5_000;
+
+// Split money that comes back on a defaulted invoice. Every path that pays a
+// defaulted invoice back goes through here, so the order cannot drift between
+// instructions: an active collections agency's fee slice first, then the insurance
+// pool up to what it is still owed of its payout, then the investor up to the
+// principal the payout left uncovered plus the yield still owed, and whatever is
+// left back to the business.
+pub fn recovery_waterfall(
+    amount: u64,
+    fee_bps: u16,
+    pool_outstanding: u64,
+    investor_outstanding: u64,
+) -> Result<RecoverySplit> {
+    let agency_fee = amount.checked_mul(fee_bps as u64).ok_or(ErrorCode::MathOverflow)? / 10_000;
+    let pool = (amount - agency_fee).min(pool_outstanding);
+    let investor = (amount - agency_fee - pool).min(investor_outstanding);
+    Ok(RecoverySplit {
+        agency_fee,
+        pool,
+        investor,
+        business: amount - agency_fee - pool - investor,
+    })
+}
+
+// Accounts a recovery is taken from and paid out to, borrowed from the
+// remit_recovery and settle_recovery contexts
+pub struct RecoveryAccounts<'a, 'info> {
+    pub token_program: AccountInfo<'info>,
+    pub source: AccountInfo<'info>,
+    pub payer: AccountInfo<'info>,
+    pub insurance_pool_account: &'a Account<'info, TokenAccount>,
+    pub collections_assignment: Option<&'a mut Account<'info, CollectionsAssignment>>,
+    pub collections_agency: Option<&'a mut Account<'info, CollectionsAgency>>,
+    pub agency_token_account: Option<&'a Account<'info, TokenAccount>>,
+    pub investor_token_account: Option<&'a Account<'info, TokenAccount>>,
+    pub invoice_vault: Option<&'a Account<'info, TokenAccount>>,
+    pub business_token_account: Option<&'a Account<'info, TokenAccount>>,
+    pub pair_ledger: Option<&'a mut Account<'info, PairLedger>>,
+    pub portfolio: Option<&'a mut Account<'info, InvestorPortfolio>>,
+}
+
+// Pay `amount` recovered on a defaulted invoice from the payer's source account
+// through recovery_waterfall, charging the active collections assignment's fee if
+// there is one. A payer that is the business keeps the excess.
+fn distribute_recovery<'info>(
+    invoice: &mut Account<'info, Invoice>,
+    global_state: &mut GlobalState,
+    accounts: RecoveryAccounts<'_, 'info>,
+    amount: u64,
+    current_time: i64,
+) -> Result<RecoverySplit> {
+    let active_assignment = accounts.collections_assignment.filter(|a| a.active);
+    let fee_bps = active_assignment.as_ref().map_or(0, |a| a.fee_bps);
+    let interest_paid = invoice.interest_paid;
+    let split = invoice.apply_settlement(amount, fee_bps, current_time)?;
+    // The part of the investor's share that paid yield rather than written-off principal
+    let recovered_yield = invoice.interest_paid - interest_paid;
+
+    let transfer = |to, value: u64| -> Result<()> {
+        if value == 0 {
+            return Ok(());
+        }
+        let transfer_ctx = CpiContext::new(
+            accounts.token_program.clone(),
+            Transfer {
+                from: accounts.source.clone(),
+                to,
+                authority: accounts.payer.clone(),
+            },
+        );
+        token::transfer(transfer_ctx, value)
+    };
+
+    if let Some(assignment) = active_assignment {
+        let agency = accounts.collections_agency.ok_or(ErrorCode::CollectionsAccountsMissing)?;
+        let agency_token = accounts.agency_token_account.ok_or(ErrorCode::CollectionsAccountsMissing)?;
+        require_keys_eq!(agency.key(), assignment.agency, ErrorCode::CollectionsAgencyMismatch);
+        require_keys_eq!(agency_token.owner, agency.authority, ErrorCode::CollectionsAgencyMismatch);
+        require_keys_eq!(agency_token.mint, invoice.mint, ErrorCode::CollectionsAgencyMismatch);
+
+        transfer(agency_token.to_account_info(), split.agency_fee)?;
+        assignment.recovered += amount;
+        agency.recovered_total += amount;
+        agency.fees_earned += split.agency_fee;
+    }
+
+    transfer(accounts.insurance_pool_account.to_account_info(), split.pool)?;
+    global_state.insurance_pool_balance =
+        global_state.insurance_pool_balance.checked_add(split.pool).ok_or(ErrorCode::MathOverflow)?;
+
+    // Investor residual, into the vault for share holders of a partially funded
+    // invoice, or for a receipt holder who has not claimed yet
+    if split.investor > 0 {
+        if invoice.partial_funding || invoice.live_receipt().is_some() {
+            let vault = accounts.invoice_vault.ok_or(ErrorCode::InvoiceVaultMissing)?;
+            require_no_delegate(vault)?;
+            transfer(vault.to_account_info(), split.investor)?;
+            invoice.distributable_amount =
+                invoice.distributable_amount.checked_add(split.investor).ok_or(ErrorCode::MathOverflow)?;
+        } else {
+            let investor_token = accounts.investor_token_account.ok_or(ErrorCode::InvestorAccountMissing)?;
+            require_keys_eq!(investor_token.owner, invoice.investor, ErrorCode::InvestorAccountMissing);
+            transfer(investor_token.to_account_info(), split.investor)?;
+        }
+        if !invoice.partial_funding {
+            if let Some(ledger) = accounts.pair_ledger {
+                ledger.record_recovery(invoice.invoice_id, SettlementKind::Recovery, split.investor, current_time)?;
+            }
+        }
+        if let Some(portfolio) = invoice.booked_portfolio(accounts.portfolio.map(|p| &mut **p))? {
+            portfolio.record_recovery(split.investor - recovered_yield)?;
+            portfolio.record_recovered_yield(recovered_yield)?;
+        }
+    }
+    // What reached the investor here, or the whole position once settled, is no
+    // longer there to insure
+    sync_insured_exposure(invoice, global_state)?;
+
+    if split.business > 0 && accounts.payer.key() != invoice.business_owner {
+        let business_token = accounts.business_token_account.ok_or(ErrorCode::BusinessAccountMissing)?;
+        transfer(business_token.to_account_info(), split.business)?;
+    }
+
+    if invoice.status == InvoiceStatus::RepaidAfterDefault {
+        emit_bounded(InvoiceRepaidAfterDefault {
+            invoice_id: invoice.invoice_id,
+            insurance_payout: invoice.insurance_payout.unwrap_or(0),
+            insurance_clawback: invoice.pool_recovered,
+            to_investor: invoice.recovered_amount - invoice.pool_recovered,
+            invoice: invoice.key(),
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            timestamp: current_time,
+        });
+    }
+    Ok(split)
+}
+
+// What a claim for `entitlement` is paid now: all of it while the pool covers every
+// claim outstanding, otherwise the same fraction of each, so the pool is shared out
+// rather than emptied by whoever claims first. Never more than the pool holds.
+pub fn insurance_payout_share(entitlement: u64, pool_available: u64, claims_outstanding: u64) -> u64 {
+    // Claims from before the pool counted them are not in claims_outstanding
+    let claims = claims_outstanding.max(entitlement);
+    if pool_available >= claims {
+        return entitlement;
+    }
+    pro_rata(pool_available, entitlement, claims)
+}
+
+// Upper bound for the micro tier threshold (250 USDC)
+pub const MICRO_TIER_MAX_AMOUNT: u64 = 250_000_000;
+
+// Pricing experiments must be scheduled at least this far ahead (24 hours)
+pub const EXPERIMENT_TIMELOCK_SECS: i64 = 24 * 3600;
+
+// Deterministically place an invoice in an arm by hashing (business_owner, invoice_id)
+pub fn experiment_arm(business_owner: &Pubkey, invoice_id: u64, traffic_bps: u16) -> ExperimentArm {
+    let digest = anchor_lang::solana_program::hash::hashv(&[
+        business_owner.as_ref(),
+        &invoice_id.to_le_bytes(),
+    ]);
+    let mut bucket_bytes = [0u8; 8];
+    bucket_bytes.copy_from_slice(&digest.to_bytes()[..8]);
+    let bucket = u64::from_le_bytes(bucket_bytes) % 10_000;
+
+    if bucket < traffic_bps as u64 {
+        ExperimentArm::Treatment
+    } else {
+        ExperimentArm::Control
+    }
+}
+
+// Append an entry to the current outbox page, returning its global index
+fn append_outbox_entry(
+    processor: &mut PayoutProcessor,
+    page: &mut OutboxPage,
+    entry: OutboxEntry,
+) -> Result<u64> {
+    let index = processor.total_entries;
+    require!(
+        page.page as u64 == index / OutboxPage::MAX_ENTRIES as u64,
+        ErrorCode::OutboxPageMismatch
+    );
+    page.entries.push(entry);
+    processor.total_entries += 1;
+    Ok(index)
+}
+
+// Bring the pool's committed coverage in line with what the invoice is insured for
+// now, and its claims outstanding with what a default has made due
+fn sync_insured_exposure(invoice: &mut Invoice, global_state: &mut GlobalState) -> Result<()> {
+    let coverage = invoice.coverage_owed();
+    global_state.insured_exposure = global_state
+        .insured_exposure
+        .checked_sub(invoice.committed_coverage)
+        .and_then(|exposure| exposure.checked_add(coverage))
+        .ok_or(ErrorCode::MathOverflow)?;
+    invoice.committed_coverage = coverage;
+
+    let claim = if invoice.status == InvoiceStatus::Defaulted { coverage } else { 0 };
+    global_state.claims_outstanding = global_state
+        .claims_outstanding
+        .checked_sub(invoice.booked_claim)
+        .and_then(|claims| claims.checked_add(claim))
+        .ok_or(ErrorCode::MathOverflow)?;
+    invoice.booked_claim = claim;
+    Ok(())
+}
+
+// Risk and price of a listing's terms, as create_invoice sets them and
+// amend_invoice recomputes them
+pub struct ListingQuote {
+    pub risk_assessment: RiskAssessment,
+    // Risk points held back until the debtor acknowledges the invoice
+    pub acknowledgment_penalty: u8,
+    pub pricing: pricing::Pricing,
+    pub micro_tier: bool,
+    // Funding the protocol still accepts today under the daily cap
+    pub remaining_daily_capacity: u64,
+}
+
+// Quote a listing under the micro tier, if it applies, or the risk model. Until
+// its debtor acknowledges it, an invoice carries the policy's risk points;
+// acknowledge_invoice reprices without them. The micro tier and pricing
+// experiments are left out, as their prices compare equal risks.
+fn quote_listing(
+    listing: &ListingRisk,
+    principal: u64,
+    business_profile: &BusinessProfile,
+    global_state: &GlobalState,
+    micro_tier: Option<&MicroTierConfig>,
+    experiment_terms: Option<pricing::ExperimentTerms>,
+    current_time: i64,
+) -> Result<ListingQuote> {
+    let config = &global_state.config;
+    let mut risk_assessment = match micro_tier {
+        Some(tier) => tier.risk_assessment(),
+        None => calculate_enhanced_risk(
+            listing,
+            business_profile,
+            &global_state.risk_params,
+            &config.risk,
+            current_time,
+        ),
+    };
+
+    let unpenalized_score = risk_assessment.risk_score;
+    if micro_tier.is_none() && experiment_terms.is_none() {
+        risk_assessment.risk_score = unpenalized_score
+            .saturating_add(global_state.acknowledgment_policy.listing_penalty())
+            .min(global_state.risk_params.max_score.max(unpenalized_score));
+    }
+
+    let schedule = match micro_tier {
+        Some(tier) => tier.schedule(),
+        None => PremiumSchedule::RiskScaled,
+    };
+    let term = term_days(current_time, listing.due_date);
+    let mut pricing_inputs = PricingInputs::new(principal, risk_assessment, schedule, config.coverage, term)
+        .with_min_premium(global_state.min_insurance_premium);
+    if let Some(terms) = experiment_terms {
+        pricing_inputs = pricing_inputs.with_experiment(terms);
+    }
+
+    Ok(ListingQuote {
+        risk_assessment,
+        acknowledgment_penalty: risk_assessment.risk_score - unpenalized_score,
+        pricing: price_invoice(&pricing_inputs)?,
+        micro_tier: micro_tier.is_some(),
+        remaining_daily_capacity: global_state.remaining_daily_capacity(current_time),
+    })
+}
+
+// Checks an outright funding passes once the investor is known: the whitelist,
+// retail guardrails, outstanding exposure caps on both sides of the trade, the
+// daily funding ceiling and the debtor's cap. The exposure is booked as it goes.
+fn admit_funding(
+    global_state: &mut GlobalState,
+    invoice: &mut Invoice,
+    investor_stats: &mut InvestorStats,
+    business_profile: &mut BusinessProfile,
+    debtor: Option<&mut Debtor>,
+    investor_whitelist: Option<&InvestorWhitelist>,
+    amount: u64,
+) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    require!(invoice.funding_open(current_time), ErrorCode::FundingWindowClosed);
+    global_state.require_whitelisted(investor_whitelist, current_time)?;
+
+    // Retail protection for investors without a track record
+    investor_stats.require_retail_guardrails(global_state.retail_guardrails, amount, invoice.risk_score)?;
+
+    global_state.book_exposure(investor_stats, business_profile, amount)?;
+    invoice.exposure_booked = true;
+    business_profile.record_funding(1, amount)?;
+
+    // Soft-launch ceiling on new funding per UTC day
+    global_state.record_daily_funding(amount, current_time)?;
+
+    if let Some(debtor) = invoice.booked_debtor(debtor)? {
+        debtor.book_funding(amount, global_state.debtor_exposure_cap)?;
+    }
+    Ok(())
+}
+
+// Record an outright funding on the invoice. It waits in escrow when the protocol
+// has an acceptance window; an off-ramp payout is only released once the
+// processor acks it, an escrowed one once the business accepts it.
+fn mark_funded(
+    invoice: &mut Invoice,
+    global_state: &mut GlobalState,
+    investor: Pubkey,
+    amount: u64,
+    escrowed: bool,
+    from_balance: bool,
+    funded_at: i64,
+) -> Result<()> {
+    invoice.transition(if escrowed { InvoiceStatus::FundedPendingAcceptance } else { InvoiceStatus::Funded })?;
+    invoice.param_versions.funded = global_state.param_version;
+    invoice.funded_amount = amount;
+    invoice.remaining_balance = amount;
+    invoice.investor = investor;
+    invoice.funding_date = Some(funded_at);
+    invoice.fix_term_yield(funded_at)?;
+    invoice.released_at = (!invoice.offramp_requested && !escrowed).then_some(funded_at);
+    if escrowed {
+        invoice.escrow = Some(FundingEscrow {
+            deadline: funded_at
+                .checked_add(global_state.funding_acceptance_window)
+                .ok_or(ErrorCode::MathOverflow)?,
+            premium: if from_balance { 0 } else { invoice.investor_premium() },
+            from_balance,
+        });
+    }
+    sync_insured_exposure(invoice, global_state)
+}
+
+// Book a repayment that has reached the investor or the invoice vault: late fees,
+// interest and principal in that order, debtor and exposure release, the pair
+// ledger, portfolio and protocol totals, and with the last payment the invoice's
+// settlement. Emits
+// RepaymentReceived, and InvoiceRepaid once nothing is outstanding through
+// `event_authority`.
+#[allow(clippy::too_many_arguments)]
+fn record_repayment(
+    invoice: &mut Invoice,
+    invoice_key: Pubkey,
+    global_state: &mut GlobalState,
+    business_profile: &mut BusinessProfile,
+    mut investor_stats: Option<&mut InvestorStats>,
+    pair_ledger: Option<&mut PairLedger>,
+    portfolio: Option<&mut InvestorPortfolio>,
+    debtor: Option<&mut Debtor>,
+    experiment: Option<&mut Account<Experiment>>,
+    repayment_amount: u64,
+    days_overdue: i64,
+    current_time: i64,
+    event_authority: EventAuthority,
+) -> Result<()> {
+    let split = invoice.apply_repayment(repayment_amount)?;
+    if let Some(debtor) = invoice.booked_debtor(debtor)? {
+        debtor.release(split.principal);
+    }
+    invoice.release_exposure(investor_stats.as_deref_mut(), business_profile, split.principal)?;
+    if let Some(portfolio) = invoice.booked_portfolio(portfolio)? {
+        portfolio.record_repayment(&split)?;
+    }
+    sync_insured_exposure(invoice, global_state)?;
+    let outstanding = invoice.outstanding_balance()?;
+    if outstanding > 0 {
+        invoice.transition(invoice.partially_repaid_status())?;
+    } else {
+        invoice.transition(InvoiceStatus::Repaid)?;
+        business_profile.close_invoice();
+    }
+    global_state.record_repayment(repayment_amount, split.late_fee, outstanding == 0)?;
+
+    emit_bounded(RepaymentReceived {
+        invoice_id: invoice.invoice_id,
+        amount: repayment_amount,
+        late_fee_paid: split.late_fee,
+        remaining_balance: outstanding,
+        invoice: invoice_key,
+        business_owner: invoice.business_owner,
+        investor: invoice.investor,
+        timestamp: current_time,
+        status: invoice.status,
+    });
+
+    if let Some(ledger) = pair_ledger.filter(|_| !invoice.partial_funding) {
+        ledger.record_repayment(invoice.invoice_id, repayment_amount, &split, current_time)?;
+        if outstanding == 0 {
+            let funded_at = invoice.funding_date.unwrap_or(current_time);
+            ledger.record_repaid(((current_time - funded_at).max(0) / 86400) as u64)?;
+        }
+    }
+
+    if outstanding > 0 {
+        msg!("Invoice {} partially repaid: {} USDC, {} outstanding",
+             invoice.invoice_id, repayment_amount, outstanding);
+        return Ok(());
+    }
+
+    let total_repayment = invoice.amount_repaid;
+    let late_fee = invoice.settled_late_fee()?;
+    let early_repayment_discount = invoice.yield_component() - invoice.interest_paid.min(invoice.yield_component());
+    invoice.param_versions.settled = global_state.param_version;
+    invoice.repayment_date = Some(current_time);
+    invoice.final_repayment_amount = Some(total_repayment);
+    invoice.late_fee = Some(late_fee);
+
+    if let Some(investor_stats) = investor_stats {
+        investor_stats.completed_repayments =
+            investor_stats.completed_repayments.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+    }
+
+    if days_overdue > 0 {
+        business_profile.history.record_late_repayment(current_time);
+    }
+    business_profile.credit_history.record_repayment(days_overdue > 0, invoice.funded_amount);
+
+    record_experiment_outcome(invoice, experiment, ExperimentOutcome::Repaid)?;
+
+    emit_indexed(event_authority, InvoiceRepaid {
+        invoice_id: invoice.invoice_id,
+        amount: total_repayment,
+        late_fee,
+        days_overdue: days_overdue as u16,
+        yield_paid: invoice.interest_paid,
+        early_repayment_discount,
+        invoice: invoice_key,
+        business_owner: invoice.business_owner,
+        investor: invoice.investor,
+        timestamp: current_time,
+    })?;
+
+    msg!(
+        "Invoice {} repaid: {} USDC (yield: {}, late fee: {})",
+        invoice.invoice_id,
+        total_repayment,
+        invoice.interest_paid,
+        late_fee
+    );
+    Ok(())
+}
+
+// Move a funded invoice to Defaulted and write the loss into the business's
+// record, the debtor registry, booked exposure, the experiment, the pair ledger
+// and the portfolio. Returns the days overdue.
+#[allow(clippy::too_many_arguments)]
+fn default_invoice(
+    invoice: &mut Account<Invoice>,
+    global_state: &mut GlobalState,
+    business_profile: &mut BusinessProfile,
+    debtor: Option<&mut Debtor>,
+    investor_stats: Option<&mut InvestorStats>,
+    experiment: Option<&mut Account<Experiment>>,
+    pair_ledger: Option<&mut PairLedger>,
+    portfolio: Option<&mut InvestorPortfolio>,
+    current_time: i64,
+) -> Result<i64> {
+    invoice.transition(InvoiceStatus::Defaulted)?;
+    invoice.param_versions.settled = global_state.param_version;
+    invoice.defaulted_at = Some(current_time);
+    business_profile.close_invoice();
+    business_profile.history.record_default(current_time);
+    business_profile.credit_history.record_default(invoice.funded_amount);
+    if let Some(debtor) = invoice.booked_debtor(debtor)? {
+        debtor.record_default(invoice.remaining_balance);
+    }
+    invoice.release_exposure(investor_stats, business_profile, invoice.remaining_balance)?;
+    // The coverage becomes a claim on the pool
+    sync_insured_exposure(invoice, global_state)?;
+
+    record_experiment_outcome(invoice, experiment, ExperimentOutcome::Defaulted)?;
+
+    global_state.total_defaulted += 1;
+    global_state.total_defaulted_amount += invoice.remaining_balance;
+    global_state.pending_claims += 1;
+
+    if let Some(ledger) = pair_ledger.filter(|_| !invoice.partial_funding) {
+        ledger.record_default(invoice.invoice_id, invoice.remaining_balance, current_time)?;
+    }
+    if let Some(portfolio) = invoice.booked_portfolio(portfolio)? {
+        portfolio.record_write_off(invoice.remaining_balance)?;
+    }
+
+    // A dispute can end in default before the due date
+    let days_overdue = ((current_time - invoice.due_date) / 86400).max(0);
+    emit_bounded(InvoiceDefaulted {
+        invoice_id: invoice.invoice_id,
+        days_overdue: days_overdue as u16,
+        outstanding_principal: invoice.remaining_balance,
+        invoice: invoice.key(),
+        business_owner: invoice.business_owner,
+        investor: invoice.investor,
+        timestamp: current_time,
+    });
+    Ok(days_overdue)
+}
+
+// Mint a position's one receipt to the holder's account, then drop the mint
+// authority so the supply stays at one
+fn mint_position_receipt<'info>(
+    token_program: AccountInfo<'info>,
+    receipt_mint: AccountInfo<'info>,
+    holder_receipt: AccountInfo<'info>,
+    invoice: &mut Invoice,
+    invoice_info: AccountInfo<'info>,
+) -> Result<()> {
+    let invoice_id_bytes = invoice.invoice_id.to_le_bytes();
+    let seeds = &[INVOICE_SEED, invoice.business_owner.as_ref(), invoice_id_bytes.as_ref(), &[invoice.bump]];
+    let signer_seeds = &[&seeds[..]];
+    token::mint_to(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            MintTo { mint: receipt_mint.clone(), to: holder_receipt, authority: invoice_info.clone() },
+            signer_seeds,
+        ),
+        1,
+    )?;
+    token::set_authority(
+        CpiContext::new_with_signer(
+            token_program,
+            SetAuthority { current_authority: invoice_info.clone(), account_or_mint: receipt_mint.clone() },
+            signer_seeds,
+        ),
+        AuthorityType::MintTokens,
+        None,
+    )?;
+    invoice.receipt_mint = Some(receipt_mint.key());
+    emit_bounded(ReceiptMinted {
+        invoice_id: invoice.invoice_id,
+        mint: receipt_mint.key(),
+        holder: invoice.investor,
+        invoice: invoice_info.key(),
+        business_owner: invoice.business_owner,
+        investor: invoice.investor,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+// Record a protocol-level admin action on the current admin log page
+fn set_pause_flags(ctx: Context<UpdateGlobalState>, new_flags: u8, action: AdminActionCode) -> Result<()> {
+    require!(new_flags & !PAUSE_ALL == 0, ErrorCode::InvalidPauseFlags);
+    let global_state = &mut ctx.accounts.global_state;
+    let old_flags = global_state.paused;
+    global_state.paused = new_flags;
+
+    emit_bounded(PauseFlagsUpdated {
+        old_flags,
+        new_flags,
+        action,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    msg!("Pause flags changed from {:#05b} to {:#05b}", old_flags, new_flags);
+    record_admin_action(
+        global_state,
+        &mut ctx.accounts.admin_log,
+        ctx.accounts.authority.key(),
+        action,
+        Some(new_flags as u64),
+    )
+}
+
+fn record_admin_action(
+    global_state: &mut GlobalState,
+    admin_log: &mut AdminActionLog,
+    actor: Pubkey,
+    action: AdminActionCode,
+    amount: Option<u64>,
+) -> Result<()> {
+    // Only the authority keypair, the governance account and the approver set,
+    // individually or as the approvals PDA, get this far
+    let role = if actor == global_state.authority {
+        AdminRole::ProtocolAuthority
+    } else if global_state.approver_index(&actor).is_some() || actor == approvals_address().0 {
+        AdminRole::Approver
+    } else {
+        AdminRole::Governance
+    };
+    admin_log.append(
+        &mut global_state.admin_action_count,
+        AdminAction {
+            actor,
+            role,
+            action,
+            timestamp: Clock::get()?.unix_timestamp,
+            amount,
+        },
+    )?;
+    Ok(())
+}
+
+// Append the changed parameters to the history as one new parameter-set version;
+// a call that changed nothing leaves the version where it was
+fn record_param_changes(
+    global_state: &mut GlobalState,
+    param_history: &mut ParamHistory,
+    actor: Pubkey,
+    changes: Vec<ParamValueChange>,
+) -> Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    param_history.append(
+        &mut global_state.param_version,
+        ParamSetChange {
+            version: 0,
+            effective_at: Clock::get()?.unix_timestamp,
+            actor,
+            changes,
+        },
+    )?;
+    Ok(())
+}
+
+// Parameters whose value differs between `old` and `new` (same ids, same order)
+pub fn param_diff(old: &[(ParamId, u64)], new: &[(ParamId, u64)]) -> Vec<ParamValueChange> {
+    old.iter()
+        .zip(new)
+        .filter(|((_, old_value), (_, new_value))| old_value != new_value)
+        .map(|((param, old_value), (_, new_value))| ParamValueChange {
+            param: *param,
+            old_value: *old_value,
+            new_value: *new_value,
+        })
+        .collect()
+}
+
+// Every param history page, in order, so a lookup sees the whole timeline
+fn load_param_history(infos: &[AccountInfo], param_version: u32) -> Result<Vec<ParamHistory>> {
+    let pages = (param_version as usize).div_ceil(ParamHistory::MAX_ENTRIES);
+    require!(infos.len() == pages, ErrorCode::ParamHistoryIncomplete);
+    infos
+        .iter()
+        .enumerate()
+        .map(|(index, info)| {
+            require_keys_eq!(*info.owner, crate::ID, ErrorCode::ParamHistoryIncomplete);
+            let page = ParamHistory::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            let expected = Pubkey::create_program_address(
+                &[PARAM_HISTORY_SEED, &(index as u32).to_le_bytes(), &[page.bump]],
+                &crate::ID,
+            )
+            .map_err(|_| error!(ErrorCode::ParamHistoryIncomplete))?;
+            require!(page.page == index as u32 && *info.key == expected, ErrorCode::ParamHistoryIncomplete);
+            Ok(page)
+        })
+        .collect()
+}
+
+// Binary search the history for the last parameter set in force at `timestamp`. The
+// value is the newest change to `param` up to then; failing that, what the first later
+// change replaced; failing that, `current`, since it has never changed.
+pub fn param_at(entries: &[ParamSetChange], param: ParamId, timestamp: i64, current: u64) -> ParamAt {
+    let in_force = entries.partition_point(|entry| entry.effective_at <= timestamp);
+    let value = entries[..in_force]
+        .iter()
+        .rev()
+        .find_map(|entry| entry.change_of(param).map(|change| change.new_value))
+        .or_else(|| {
+            entries[in_force..]
+                .iter()
+                .find_map(|entry| entry.change_of(param).map(|change| change.old_value))
+        })
+        .unwrap_or(current);
+    ParamAt {
+        param,
+        value,
+        version: in_force.checked_sub(1).map_or(0, |index| entries[index].version),
+    }
+}
+
+// The experiment account for a listing priced while `running` is in force, which
+// must be the one passed; None when no experiment applies
+fn running_experiment<'a, 'info>(
+    running: Option<Pubkey>,
+    experiment: Option<&'a mut Account<'info, Experiment>>,
+) -> Result<Option<&'a mut Account<'info, Experiment>>> {
+    let Some(key) = running else {
+        return Ok(None);
+    };
+    let experiment = experiment.ok_or(ErrorCode::ExperimentAccountMissing)?;
+    require_keys_eq!(experiment.key(), key, ErrorCode::ExperimentMismatch);
+    Ok(Some(experiment))
+}
+
+// Bump the settlement counters of the invoice's experiment arm, if it was assigned one
+fn record_experiment_outcome(
+    invoice: &Invoice,
+    experiment: Option<&mut Account<Experiment>>,
+    outcome: ExperimentOutcome,
+) -> Result<()> {
+    if let Some(assignment) = &invoice.experiment {
+        let experiment = experiment.ok_or(ErrorCode::ExperimentAccountMissing)?;
+        require_keys_eq!(experiment.key(), assignment.experiment, ErrorCode::ExperimentMismatch);
+        experiment.arm_mut(assignment.arm).record(outcome);
+    }
+    Ok(())
+}
+
+// Delay before loosening protocol parameters takes effect (48 hours)
+pub const PARAMETER_TIMELOCK_SECS: i64 = 48 * 3600;
+
+// Start (00:00 UTC) of the civil day containing `timestamp`
+pub fn utc_day_start(timestamp: i64) -> i64 {
+    timestamp.div_euclid(86400) * 86400
+}
+
+// A cap of zero means unlimited; lowering the effective limit is a tightening
+pub fn is_cap_tightening(old_cap: u64, new_cap: u64) -> bool {
+    let effective = |cap: u64| if cap == 0 { u64::MAX } else { cap };
+    effective(new_cap) <= effective(old_cap)
+}
+
+// A due date can be pushed out once, by at most 90 days
+pub const MAX_DUE_DATE_EXTENSION_SECS: i64 = 90 * 86400;
+
+// Delay between requesting and executing an insurance LP withdrawal
+pub const LP_WITHDRAWAL_COOLDOWN_SECS: i64 = 7 * 86400;
+
+// Timelock on authority withdrawals of insurance pool surplus (72 hours)
+pub const POOL_WITHDRAWAL_TIMELOCK_SECS: i64 = 72 * 3600;
+
+// Retail guardrails for investors with fewer than RETAIL_GRADUATION_REPAYMENTS
+// completed positions: no single position above RETAIL_MAX_POSITION_BPS of their
+// cumulative deployed capital (positions up to RETAIL_POSITION_ALLOWANCE are always
+// fine, so a first investment is possible) and no invoice riskier than RETAIL_MAX_RISK_SCORE
+pub const RETAIL_GRADUATION_REPAYMENTS: u32 = 3;
+pub const RETAIL_MAX_POSITION_BPS: u64 = 2_000;
+pub const RETAIL_POSITION_ALLOWANCE: u64 = 100_000_000;
+pub const RETAIL_MAX_RISK_SCORE: u8 = 35;
+
+// Early repayment still owes at least a quarter of the yield component by default
+pub const DEFAULT_MIN_INTEREST_BPS: u16 = 2_500;
+
+// Settled invoices keep personal data for a year by default
+pub const DEFAULT_RETENTION_PERIOD_SECS: i64 = 365 * 86400;
+
+// Health thresholds a fresh deployment starts with: amber below 150% pool coverage
+// of insured exposure, red below 100%; amber from one unpaid claim, red from five;
+// amber at 80% of the daily cap, red once it is used up
+pub const DEFAULT_HEALTH_THRESHOLDS: HealthThresholds = HealthThresholds {
+    coverage_amber_bps: 15_000,
+    coverage_red_bps: 10_000,
+    claim_queue_amber: 1,
+    claim_queue_red: 5,
+    cap_utilization_amber_bps: 8_000,
+    cap_utilization_red_bps: 10_000,
+};
+
+// Kinds of activity the authority can pause; repayment is deliberately not one of them
+pub const PAUSE_CREATE: u8 = 1 << 0;
+pub const PAUSE_FUND: u8 = 1 << 1;
+pub const PAUSE_CLAIM: u8 = 1 << 2;
+pub const PAUSE_ALL: u8 = PAUSE_CREATE | PAUSE_FUND | PAUSE_CLAIM;
+
+// get_health reason bits, one per degraded condition
+pub const HEALTH_POOL_UNDERCOVERED: u16 = 1 << 0;
+pub const HEALTH_CLAIM_BACKLOG: u16 = 1 << 1;
+pub const HEALTH_POOL_MISMATCH: u16 = 1 << 2;
+pub const HEALTH_POOL_UNVERIFIED: u16 = 1 << 3;
+pub const HEALTH_DAILY_CAP_PRESSURE: u16 = 1 << 4;
+pub const HEALTH_PAUSED: u16 = 1 << 5;
+
+// Grade each subsystem against the configured thresholds; the overall verdict is
+// the worst of them
+pub fn assess_health(state: &GlobalState, pool_token_balance: Option<u64>, current_time: i64) -> HealthReport {
+    let thresholds = &state.health_thresholds;
+    let mut reasons = 0;
+    let mut grade = |status: HealthStatus, reason: u16| {
+        if status != HealthStatus::Green {
+            reasons |= reason;
+        }
+        status
+    };
+
+    let coverage_ratio_bps = if state.insured_exposure == 0 {
+        u64::MAX
+    } else {
+        (state.insurance_pool_balance as u128 * 10_000 / state.insured_exposure as u128).min(u64::MAX as u128) as u64
+    };
+    let pool_coverage = grade(
+        if coverage_ratio_bps < thresholds.coverage_red_bps as u64 {
+            HealthStatus::Red
+        } else if coverage_ratio_bps < thresholds.coverage_amber_bps as u64 {
+            HealthStatus::Amber
+        } else {
+            HealthStatus::Green
+        },
+        HEALTH_POOL_UNDERCOVERED,
+    );
+
+    let claim_queue = grade(
+        HealthStatus::at_least(state.pending_claims, thresholds.claim_queue_amber as u64, thresholds.claim_queue_red as u64),
+        HEALTH_CLAIM_BACKLOG,
+    );
+
+    // Tokens sent straight to the pool only add surplus; a shortfall against the
+    // mirror means the accounting is overstating what the pool can pay
+    let reconciliation = match pool_token_balance {
+        None => grade(HealthStatus::Amber, HEALTH_POOL_UNVERIFIED),
+        Some(balance) if balance < state.insurance_pool_balance => grade(HealthStatus::Red, HEALTH_POOL_MISMATCH),
+        Some(balance) if balance > state.insurance_pool_balance => grade(HealthStatus::Amber, HEALTH_POOL_MISMATCH),
+        Some(_) => HealthStatus::Green,
+    };
+
+    // Any pause is deliberate, so it warns rather than alarms
+    let pause = grade(
+        if state.paused == 0 { HealthStatus::Green } else { HealthStatus::Amber },
+        HEALTH_PAUSED,
+    );
+
+    let funded_today = if state.day_start_ts == utc_day_start(current_time) { state.funded_today } else { 0 };
+    let cap_utilization_bps = if state.daily_funding_cap == 0 {
+        0
+    } else {
+        pro_rata(10_000, funded_today, state.daily_funding_cap)
+    };
+    let daily_cap = grade(
+        HealthStatus::at_least(
+            cap_utilization_bps,
+            thresholds.cap_utilization_amber_bps as u64,
+            thresholds.cap_utilization_red_bps as u64,
+        ),
+        HEALTH_DAILY_CAP_PRESSURE,
+    );
+
+    HealthReport {
+        overall: pool_coverage.max(claim_queue).max(reconciliation).max(daily_cap).max(pause),
+        reasons,
+        paused: state.paused,
+        pool_coverage,
+        coverage_ratio_bps,
+        claim_queue,
+        pending_claims: state.pending_claims,
+        reconciliation,
+        pool_token_balance,
+        daily_cap,
+        cap_utilization_bps,
+    }
+}
+
+// floor(total * part / whole), used to split premiums and payouts across shares
+pub fn pro_rata(total: u64, part: u64, whole: u64) -> u64 {
+    if whole == 0 {
+        return 0;
+    }
+    (total as u128 * part as u128 / whole as u128) as u64
+}
+
+// Unpaid late fee on a funded invoice (`late_fee_bps_per_day` of the outstanding
+// principal per full day overdue, on top of fees already accrued), together with the
+// number of days overdue
+pub fn calculate_late_fee(invoice: &Invoice, current_time: i64, late_fee_bps_per_day: u16) -> Result<(u64, i64)> {
+    if current_time <= invoice.due_date {
+        return Ok((invoice.accrued_late_fee, 0));
+    }
+    let days_overdue = (current_time - invoice.due_date) / 86400;
+    // Days overdue under dispute count as accrued but charge nothing
+    if invoice.dispute_active() {
+        return Ok((invoice.accrued_late_fee, days_overdue));
+    }
+    let new_days = (days_overdue - invoice.late_fee_days_accrued as i64).max(0) as u64;
+    let new_fee = invoice
+        .remaining_balance
+        .checked_mul(new_days)
+        .and_then(|fee| fee.checked_mul(late_fee_bps_per_day as u64))
+        .ok_or(ErrorCode::MathOverflow)?
+        / 10000;
+    let late_fee = invoice.accrued_late_fee.checked_add(new_fee).ok_or(ErrorCode::MathOverflow)?;
+    Ok((late_fee, days_overdue))
+}
+
+// Deserialize an invoice passed through remaining_accounts
+pub fn load_invoice(info: &AccountInfo) -> Result<Invoice> {
+    require_keys_eq!(*info.owner, crate::ID, ErrorCode::InvalidInvoiceAccount);
+    let data = info.try_borrow_data()?;
+    let invoice = Invoice::try_deserialize(&mut &data[..])?;
+    require!(invoice.version == INVOICE_VERSION, ErrorCode::AccountVersionMismatch);
+    Ok(invoice)
+}
+
+// Shortest debtor_info the unversioned layout ever held, the "[erased]" marker,
+// and the cap it was held to, so the first byte of its length tells the layouts
+// apart: a version is either below that range or above it
+const LEGACY_MIN_DEBTOR_INFO_LEN: usize = 8;
+const LEGACY_MAX_DEBTOR_INFO_LEN: usize = 200;
+const _: () = assert!((INVOICE_VERSION as usize) > LEGACY_MAX_DEBTOR_INFO_LEN);
+
+// Whether the byte at INVOICE_VERSION_OFFSET is an unversioned invoice's text length
+fn is_legacy_length_byte(byte: u8) -> bool {
+    (LEGACY_MIN_DEBTOR_INFO_LEN..=LEGACY_MAX_DEBTOR_INFO_LEN).contains(&(byte as usize))
+}
+
+// Bring an invoice account's data up to INVOICE_VERSION, with the version it was
+// on (0 for unversioned), or None if it is there already. Versions 1 and 2 get
+// their status moved up to INVOICE_STATUS_OFFSET; otherwise versions only append
+// fields, which read as zero from the account extended to Invoice::SIZE.
+pub fn migrate_invoice_data(data: &[u8]) -> Result<Option<(u8, Invoice)>> {
+    let at = INVOICE_VERSION_OFFSET;
+    require!(data.len() > at && data[..8] == Invoice::DISCRIMINATOR, ErrorCode::InvalidInvoiceAccount);
+    let version = data[at];
+    if version == INVOICE_VERSION {
+        return Ok(None);
+    }
+    if is_legacy_length_byte(version) {
+        return Ok(Some((0, migrate_legacy_invoice(data)?)));
+    }
+    require!(version != 0 && version < INVOICE_VERSION, ErrorCode::AccountVersionMismatch);
+    let mut extended = if version < 3 { hoist_invoice_status(data)? } else { data.to_vec() };
+    extended.resize(data.len().max(Invoice::SIZE), 0);
+    let mut invoice = Invoice::try_deserialize(&mut &extended[..])?;
+    invoice.version = INVOICE_VERSION;
+    // Before version 5 the business owner always paid the rent
+    if version < 5 {
+        invoice.rent_payer = invoice.business_owner;
+    }
+    Ok(Some((version, invoice)))
+}
+
+// Rewrite an unversioned invoice account's data in the current layout. The
+// free-text debtor_info gives way to its sha256 and no URI; every other field is
+// carried over byte for byte. Erased invoices keep the hash taken of their text
+// at erasure.
+pub fn migrate_legacy_invoice(data: &[u8]) -> Result<Invoice> {
+    let at = INVOICE_VERSION_OFFSET;
+    require!(
+        data.len() >= at + 4 && data[..8] == Invoice::DISCRIMINATOR,
+        ErrorCode::InvalidInvoiceAccount
+    );
+    // A versioned invoice has its version where the string's length was
+    require!(is_legacy_length_byte(data[at]), ErrorCode::InvoiceAlreadyMigrated);
+    let text_len = u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]) as usize;
+    let text_end = at.checked_add(4 + text_len).filter(|end| *end <= data.len()).ok_or(ErrorCode::InvalidInvoiceAccount)?;
+
+    // status followed the text
+    require!(text_end < data.len(), ErrorCode::InvalidInvoiceAccount);
+
+    let mut migrated = Vec::with_capacity(data.len());
+    migrated.extend_from_slice(&data[..at]);
+    migrated.push(INVOICE_VERSION);
+    migrated.push(data[text_end]);
+    migrated.extend_from_slice(&anchor_lang::solana_program::hash::hash(&data[at + 4..text_end]).to_bytes());
+    migrated.push(0); // debtor_info_uri: None
+    migrated.extend_from_slice(&data[text_end + 1..]);
+    // Fields added since read as zero
+    migrated.resize(migrated.len().max(Invoice::SIZE), 0);
+
+    let mut invoice = Invoice::try_deserialize(&mut &migrated[..])?;
+    if invoice.erased {
+        invoice.debtor_info_hash = invoice.erased_content_hash;
+    }
+    invoice.rent_payer = invoice.business_owner;
+    Ok(invoice)
+}
+
+// Versions 1 and 2 kept status right after debtor_info_uri. Move it up to
+// INVOICE_STATUS_OFFSET, leaving every other byte where it was relative to its
+// neighbours.
+fn hoist_invoice_status(data: &[u8]) -> Result<Vec<u8>> {
+    let uri_at = INVOICE_STATUS_OFFSET + 32;
+    let status_at = match data.get(uri_at) {
+        Some(0) => uri_at + 1,
+        Some(1) => {
+            let len = data.get(uri_at + 1..uri_at + 5).ok_or(ErrorCode::InvalidInvoiceAccount)?;
+            uri_at + 5 + u32::from_le_bytes(len.try_into().unwrap()) as usize
+        }
+        _ => return err!(ErrorCode::InvalidInvoiceAccount),
+    };
+    let status = *data.get(status_at).ok_or(ErrorCode::InvalidInvoiceAccount)?;
+
+    let mut hoisted = Vec::with_capacity(data.len());
+    hoisted.extend_from_slice(&data[..INVOICE_STATUS_OFFSET]);
+    hoisted.push(status);
+    hoisted.extend_from_slice(&data[INVOICE_STATUS_OFFSET..status_at]);
+    hoisted.extend_from_slice(&data[status_at + 1..]);
+    Ok(hoisted)
+}
+
+// Bring a global state account's data up to GLOBAL_STATE_VERSION, with the version
+// it was on, or None if it is there already. The unversioned layout ended where
+// version begins, so it reads as version 0 from the account extended to
+// GlobalState::SIZE, as later versions read their missing fields as zero.
+pub fn migrate_global_state_data(data: &[u8]) -> Result<Option<(u8, GlobalState)>> {
+    let mut extended = data.to_vec();
+    extended.resize(data.len().max(GlobalState::SIZE), 0);
+    let mut global_state = GlobalState::try_deserialize(&mut &extended[..])?;
+    if global_state.version == GLOBAL_STATE_VERSION {
+        return Ok(None);
+    }
+    let from_version = global_state.version;
+    require!(from_version < GLOBAL_STATE_VERSION, ErrorCode::AccountVersionMismatch);
+    global_state.version = GLOBAL_STATE_VERSION;
+    if from_version < 4 {
+        global_state.min_insurance_premium = DEFAULT_MIN_INSURANCE_PREMIUM;
+    }
+    Ok(Some((from_version, global_state)))
+}
+
+// Resize a program-owned account to `size` for a migration, returning the rent it
+// no longer needs to `refund_to` and taking any shortfall from `payer`. Returns
+// the refund.
+fn resize_for_migration<'info>(
+    account: &AccountInfo<'info>,
+    size: usize,
+    refund_to: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<u64> {
+    account.realloc(size, true)?;
+    let rent = Rent::get()?.minimum_balance(size);
+    let lamports = account.lamports();
+    let refund = lamports.saturating_sub(rent);
+    if refund > 0 {
+        **account.try_borrow_mut_lamports()? -= refund;
+        **refund_to.try_borrow_mut_lamports()? += refund;
+    } else if lamports < rent {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer { from: payer.clone(), to: account.clone() },
+            ),
+            rent - lamports,
+        )?;
+    }
+    Ok(refund)
+}
+
+// Write back an invoice loaded with load_invoice
+pub fn store_invoice(info: &AccountInfo, invoice: &Invoice) -> Result<()> {
+    require!(info.is_writable, ErrorCode::InvalidInvoiceAccount);
+    let mut data = info.try_borrow_mut_data()?;
+    invoice.try_serialize(&mut &mut data[..])
+}
+
+// Invoices accepted per batch details call
+pub const MAX_DETAILS_BATCH: usize = 12;
+
+// Invoices repaid per repay_invoices_batch call. Each costs about what a
+// repay_invoice does, so a full batch needs a compute budget request above the
+// 200k default; the two events per invoice bound it against the log budget.
+pub const MAX_REPAYMENT_BATCH: usize = 4;
+
+// Classify a batch entry instead of failing the whole call on a bad account
+fn read_batch_invoice(info: &AccountInfo) -> std::result::Result<Invoice, BatchEntryStatus> {
+    if *info.owner != crate::ID {
+        return Err(BatchEntryStatus::WrongOwner);
+    }
+    let data = info.try_borrow_data().map_err(|_| BatchEntryStatus::NotAnInvoice)?;
+    let invoice = Invoice::try_deserialize(&mut &data[..]).map_err(|_| BatchEntryStatus::NotAnInvoice)?;
+    if invoice.version != INVOICE_VERSION {
+        return Err(BatchEntryStatus::VersionMismatch);
+    }
+    Ok(invoice)
+}
+
+// Invoices accepted per aging report page
+pub const MAX_AGING_REPORT_INVOICES: usize = 20;
+
+// Invoices folded per commit_book_root page, the least time between book
+// commitments, and the crank's reward per invoice folded, in lamports
+pub const MAX_BOOK_PAGE_INVOICES: usize = 10;
+pub const BOOK_COMMIT_INTERVAL_SECS: i64 = 24 * 3600;
+pub const BOOK_CRANK_REWARD_PER_LEAF: u64 = 5_000;
+
+// Timelock applied to destination allowlist additions and removals (48 hours)
+pub const ALLOWLIST_TIMELOCK_SECS: i64 = 48 * 3600;
+
+// Destination check for outflows whose destination the authority chooses. Pool
+// withdrawals and stray fund recoveries call it on the destination they pay,
+// ack_outbox_entry and apply_payout_custody on the payout custody account, and
+// reclaim_escrow on the treasury account paying compensation, each against its
+// own list.
+pub fn require_allowlisted_destination(
+    allowlist: &DestinationAllowlist,
+    destination: &Pubkey,
+    current_time: i64,
+) -> Result<()> {
+    require!(
+        allowlist.is_allowed(destination, current_time),
+        ErrorCode::DestinationNotAllowlisted
+    );
+    Ok(())
+}
+
+// Account structures
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = GlobalState::SIZE,
+        seeds = [GLOBAL_STATE_SEED],
+        bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    
+    pub usdc_mint: Account<'info, Mint>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg_attr(feature = "cpi-events", event_cpi)]
+#[derive(Accounts)]
+#[instruction(
+    amount: u64,
+    due_date: i64,
+    debtor_info_hash: [u8; 32],
+    debtor_info_uri: Option<String>,
+    offramp_requested: bool,
+    partial_funding: bool,
+    industry: IndustryCode,
+    credit_attestation: Option<CreditAttestation>,
+    debtor_id: [u8; 32],
+    debtor_wallet: Option<Pubkey>,
+    funding_deadline: Option<i64>,
+    origination: Option<Origination>,
+)]
+pub struct CreateInvoice<'info> {
+    pub business_owner: Signer<'info>,
+
+    // Pays the rent of the accounts opened here and gets the invoice's back when it
+    // closes; the business owner itself unless a platform sponsors it
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // Ahead of the invoice, whose address takes the profile's next id
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = BusinessProfile::SIZE,
+        seeds = [BUSINESS_PROFILE_SEED, business_owner.key().as_ref()],
+        bump
+    )]
+    pub business_profile: Account<'info, BusinessProfile>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = Invoice::SIZE,
+        seeds = [
+            INVOICE_SEED,
+            business_owner.key().as_ref(),
+            business_profile.next_invoice_id().to_le_bytes().as_ref(),
+        ],
+        bump
+    )]
+    pub invoice: Account<'info, Invoice>,
+    
+    #[account(
+        mut,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    // The running pricing experiment, recorded in the global state
+    #[account(
+        mut,
+        seeds = [EXPERIMENT_SEED, experiment.experiment_id.to_le_bytes().as_ref()],
+        bump = experiment.bump,
+    )]
+    pub experiment: Option<Account<'info, Experiment>>,
+
+    #[account(
+        seeds = [MICRO_TIER_SEED],
+        bump = micro_tier.bump,
+    )]
+    pub micro_tier: Option<Account<'info, MicroTierConfig>>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = Debtor::SIZE,
+        seeds = [DEBTOR_SEED, debtor_id.as_ref()],
+        bump
+    )]
+    pub debtor: Account<'info, Debtor>,
+
+    // Stablecoin the invoice is denominated in; the primary mint when omitted
+    pub mint: Option<Account<'info, Mint>>,
+
+    /// CHECK: the instructions sysvar, only needed with a credit attestation
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+
+    // Only needed when the business prepays the premium
+    #[account(
+        mut,
+        token::mint = global_state.usdc_mint,
+        token::authority = business_owner,
+    )]
+    pub business_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Option<Account<'info, TokenAccount>>,
+
+    // Only needed for an originated invoice
+    #[account(
+        mut,
+        seeds = [ORIGINATOR_STATS_SEED, originator_stats.originator.as_ref()],
+        bump = originator_stats.bump,
+    )]
+    pub originator_stats: Option<Account<'info, OriginatorStats>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: the business's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, business_owner.key().as_ref()], bump)]
+    pub business_blacklist: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigureMicroTier<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = MicroTierConfig::SIZE,
+        seeds = [MICRO_TIER_SEED],
+        bump
+    )]
+    pub micro_tier: Account<'info, MicroTierConfig>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelInvoice<'info> {
+    #[account(
+        mut,
+        has_one = business_owner @ ErrorCode::InvoiceOwnerMismatch,
+        has_one = rent_payer @ ErrorCode::RentPayerMismatch,
+        close = rent_payer,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(mut)]
+    pub business_owner: Signer<'info>,
+
+    /// CHECK: receives the invoice's rent, checked against the invoice
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
+
+    // Only needed to refund a premium the business prepaid
+    #[account(
+        mut,
+        token::mint = global_state.usdc_mint,
+        token::authority = business_owner,
+    )]
+    pub business_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Option<Account<'info, TokenAccount>>,
+
+    /// CHECK: signs for the insurance pool token account, holds no data
+    #[account(
+        seeds = [INSURANCE_POOL_AUTHORITY_SEED],
+        bump = global_state.insurance_pool_authority_bump,
+    )]
+    pub insurance_pool_authority: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+
+    // Takes the invoice off the business's open count
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Box<Account<'info, BusinessProfile>>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireInvoice<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    // Takes the invoice off the business's open count
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Box<Account<'info, BusinessProfile>>,
+}
+
+#[derive(Accounts)]
+pub struct PingOverdue<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+}
+
+#[derive(Accounts)]
+pub struct CloseInvoice<'info> {
+    #[account(
+        mut,
+        has_one = business_owner @ ErrorCode::InvoiceOwnerMismatch,
+        has_one = rent_payer @ ErrorCode::RentPayerMismatch,
+        close = rent_payer,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(mut)]
+    pub business_owner: Signer<'info>,
+
+    /// CHECK: receives the invoice's rent, checked against the invoice
+    #[account(mut)]
+    pub rent_payer: AccountInfo<'info>,
+
+    // Only needed for partially funded invoices, to show their shares are paid out
+    #[account(
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct AcknowledgeInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [INVOICE_SEED, invoice.business_owner.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub debtor_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AmendInvoice<'info> {
+    #[account(
+        mut,
+        has_one = business_owner @ ErrorCode::InvoiceOwnerMismatch,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, business_owner.key().as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Account<'info, BusinessProfile>,
+
+    // Only needed for an invoice priced in a pricing experiment, or while one runs
+    #[account(
+        mut,
+        seeds = [EXPERIMENT_SEED, experiment.experiment_id.to_le_bytes().as_ref()],
+        bump = experiment.bump,
+    )]
+    pub experiment: Option<Account<'info, Experiment>>,
+
+    #[account(
+        seeds = [MICRO_TIER_SEED],
+        bump = micro_tier.bump,
+    )]
+    pub micro_tier: Option<Account<'info, MicroTierConfig>>,
+
+    /// CHECK: the instructions sysvar, only needed with a credit attestation
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+
+    pub business_owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ValidateListing<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    // The business's profile, if it has one, for the verification requirement and
+    // the open invoice cap
+    pub business_profile: Option<Account<'info, BusinessProfile>>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterBusiness<'info> {
+    #[account(
+        init_if_needed,
+        payer = business_owner,
+        space = BusinessProfile::SIZE,
+        seeds = [BUSINESS_PROFILE_SEED, business_owner.key().as_ref()],
+        bump
+    )]
+    pub business_profile: Account<'info, BusinessProfile>,
+
+    #[account(mut)]
+    pub business_owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterOriginator<'info> {
+    #[account(
+        init,
+        payer = originator,
+        space = OriginatorStats::SIZE,
+        seeds = [ORIGINATOR_STATS_SEED, originator.key().as_ref()],
+        bump
+    )]
+    pub originator_stats: Account<'info, OriginatorStats>,
+
+    #[account(mut)]
+    pub originator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(business_owner: Pubkey)]
+pub struct VerifyBusiness<'info> {
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Account<'info, BusinessProfile>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DelistInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [INVOICE_SEED, invoice.business_owner.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = InvoiceAuditLog::SIZE,
+        seeds = [INVOICE_AUDIT_SEED, invoice.key().as_ref()],
+        bump
+    )]
+    pub invoice_audit_log: Account<'info, InvoiceAuditLog>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+
+    // Takes the invoice off the business's open count
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Box<Account<'info, BusinessProfile>>,
+}
+
+#[cfg_attr(feature = "cpi-events", event_cpi)]
+#[derive(Accounts)]
+pub struct FundInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [INVOICE_SEED, invoice.business_owner.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+    
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    
+    // Neither the business nor the debtor may fund the receivable between them
+    #[account(
+        mut,
+        constraint = investor.key() != invoice.business_owner @ ErrorCode::SelfFundingNotAllowed,
+        constraint = invoice.debtor_wallet != Some(investor.key()) @ ErrorCode::DebtorWalletIsParty,
+    )]
+    pub investor: Signer<'info>,
+    
+    #[account(
+        mut,
+        associated_token::mint = invoice.mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        associated_token::mint = invoice.mint,
+        associated_token::authority = invoice.business_owner,
+        constraint = business_token_account.owner == invoice.business_owner @ ErrorCode::TokenOwnerMismatch,
+    )]
+    pub business_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+        constraint = insurance_pool_account.mint == global_state.usdc_mint @ ErrorCode::TokenMintMismatch,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub experiment: Option<Account<'info, Experiment>>,
+
+    #[account(
+        mut,
+        seeds = [PAYOUT_PROCESSOR_SEED],
+        bump = payout_processor.bump,
+    )]
+    pub payout_processor: Option<Account<'info, PayoutProcessor>>,
+
+    #[account(mut)]
+    pub outbox_page: Option<Account<'info, OutboxPage>>,
+
+    #[account(mut)]
+    pub outbox_escrow: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = InvestorStats::SIZE,
+        seeds = [INVESTOR_STATS_SEED, investor.key().as_ref()],
+        bump
+    )]
+    pub investor_stats: Account<'info, InvestorStats>,
+
+    // Required while GlobalState::require_whitelist is on
+    #[account(
+        seeds = [INVESTOR_WHITELIST_SEED, investor.key().as_ref()],
+        bump = investor_whitelist.bump,
+    )]
+    pub investor_whitelist: Option<Account<'info, InvestorWhitelist>>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = PairLedger::SIZE,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), investor.key().as_ref()],
+        bump
+    )]
+    pub pair_ledger: Account<'info, PairLedger>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = InvestorPortfolio::SIZE,
+        seeds = [PORTFOLIO_SEED, investor.key().as_ref()],
+        bump
+    )]
+    pub portfolio: Box<Account<'info, InvestorPortfolio>>,
+
+    #[account(
+        mut,
+        seeds = [INVESTOR_BALANCE_SEED, investor.key().as_ref()],
+        bump = investor_balance.bump,
+    )]
+    pub investor_balance: Option<Account<'info, InvestorBalance>>,
+
+    #[account(mut)]
+    pub investor_custody: Option<Account<'info, TokenAccount>>,
+
+    // Left unminted while a funding waits in escrow, and reused if it is reclaimed
+    #[account(
+        init_if_needed,
+        payer = investor,
+        seeds = [RECEIPT_SEED, invoice.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = invoice,
+    )]
+    pub receipt_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_receipt: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = invoice,
+    )]
+    pub invoice_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = invoice.mint @ ErrorCode::TokenMintMismatch)]
+    pub mint: Box<Account<'info, Mint>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    // Registry entry of the invoice's debtor; required for invoices listed against one
+    #[account(mut, address = invoice.debtor @ ErrorCode::DebtorMismatch)]
+    pub debtor: Option<Account<'info, Debtor>>,
+
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Box<Account<'info, BusinessProfile>>,
+
+    // Both required for an originated invoice: the originator's account in the
+    // invoice's mint that the fee is paid to, and its stats
+    #[account(
+        mut,
+        constraint = originator_token_account.mint == invoice.mint @ ErrorCode::TokenMintMismatch,
+    )]
+    pub originator_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        seeds = [ORIGINATOR_STATS_SEED, originator_stats.originator.as_ref()],
+        bump = originator_stats.bump,
+    )]
+    pub originator_stats: Option<Box<Account<'info, OriginatorStats>>>,
+
+    /// CHECK: the investor's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, investor.key().as_ref()], bump)]
+    pub investor_blacklist: UncheckedAccount<'info>,
+
+    /// CHECK: the business's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, invoice.business_owner.as_ref()], bump)]
+    pub business_blacklist: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptFunding<'info> {
+    #[account(
+        mut,
+        has_one = business_owner @ ErrorCode::InvoiceOwnerMismatch,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(mut)]
+    pub business_owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        associated_token::mint = invoice.mint,
+        associated_token::authority = business_owner,
+    )]
+    pub business_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [RECEIPT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub receipt_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = invoice.investor,
+    )]
+    pub investor_receipt: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), invoice.investor.as_ref()],
+        bump = pair_ledger.bump,
+    )]
+    pub pair_ledger: Account<'info, PairLedger>,
+
+    // Missing for escrows placed before portfolios existed
+    #[account(
+        mut,
+        seeds = [PORTFOLIO_SEED, invoice.investor.as_ref()],
+        bump = portfolio.bump,
+    )]
+    pub portfolio: Option<Account<'info, InvestorPortfolio>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimEscrow<'info> {
+    #[account(
+        mut,
+        has_one = investor @ ErrorCode::Unauthorized,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    pub investor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Box<Account<'info, TokenAccount>>,
+
+    // Refund destination of a wallet funding
+    #[account(
+        mut,
+        associated_token::mint = invoice.mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // Refund destination of a custody funding
+    #[account(
+        mut,
+        seeds = [INVESTOR_BALANCE_SEED, investor.key().as_ref()],
+        bump = investor_balance.bump,
+    )]
+    pub investor_balance: Option<Account<'info, InvestorBalance>>,
+
+    #[account(mut)]
+    pub investor_custody: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INVESTOR_STATS_SEED, investor.key().as_ref()],
+        bump = investor_stats.bump,
+    )]
+    pub investor_stats: Account<'info, InvestorStats>,
+
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Box<Account<'info, BusinessProfile>>,
+
+    // Registry entry of the invoice's debtor; required for invoices listed against one
+    #[account(mut, address = invoice.debtor @ ErrorCode::DebtorMismatch)]
+    pub debtor: Option<Account<'info, Debtor>>,
+
+    pub token_program: Program<'info, Token>,
+
+    // Pays the escrow compensation: a token account in the invoice's mint, owned
+    // by the treasury authority and active on the treasury allowlist
+    #[account(mut)]
+    pub treasury: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        seeds = [ALLOWLIST_SEED, VaultKind::Treasury.seed().as_ref()],
+        bump = treasury_allowlist.bump,
+    )]
+    pub treasury_allowlist: Option<Account<'info, DestinationAllowlist>>,
+
+    /// CHECK: signs for the treasury token accounts, holds no data
+    #[account(seeds = [TREASURY_AUTHORITY_SEED], bump)]
+    pub treasury_authority: Option<UncheckedAccount<'info>>,
+}
+
+#[cfg_attr(feature = "cpi-events", event_cpi)]
+#[derive(Accounts)]
+pub struct RepayInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [INVOICE_SEED, invoice.business_owner.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    // Only the business that listed the invoice repays it
+    #[account(
+        mut,
+        constraint = business_owner.key() == invoice.business_owner @ ErrorCode::RepayerNotBusinessOwner,
+    )]
+    pub business_owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(
+        mut,
+        constraint = business_token_account.mint == invoice.mint @ ErrorCode::TokenMintMismatch,
+        constraint = business_token_account.owner == business_owner.key() @ ErrorCode::TokenOwnerMismatch,
+    )]
+    pub business_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = investor_token_account.mint == invoice.mint @ ErrorCode::TokenMintMismatch,
+        constraint = investor_token_account.owner == invoice.investor @ ErrorCode::RepaymentDestinationMismatch,
+    )]
+    pub investor_token_account: Option<Account<'info, TokenAccount>>,
+
+    // Partially funded invoices are repaid into their vault
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub experiment: Option<Account<'info, Experiment>>,
+
+    // Credits the investor with a completed repayment
+    #[account(
+        mut,
+        seeds = [INVESTOR_STATS_SEED, invoice.investor.as_ref()],
+        bump = investor_stats.bump,
+    )]
+    pub investor_stats: Option<Account<'info, InvestorStats>>,
+
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), invoice.investor.as_ref()],
+        bump = pair_ledger.bump,
+    )]
+    pub pair_ledger: Option<Account<'info, PairLedger>>,
+
+    // The recorded investor's portfolio; required once the position is booked in it
+    #[account(
+        mut,
+        seeds = [PORTFOLIO_SEED, invoice.investor.as_ref()],
+        bump = portfolio.bump,
+    )]
+    pub portfolio: Option<Account<'info, InvestorPortfolio>>,
+
+    // Records a late final repayment against the business
+    #[account(
+        init_if_needed,
+        payer = business_owner,
+        space = BusinessProfile::SIZE,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump
+    )]
+    pub business_profile: Account<'info, BusinessProfile>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    // Registry entry of the invoice's debtor; required for invoices listed against one
+    #[account(mut, address = invoice.debtor @ ErrorCode::DebtorMismatch)]
+    pub debtor: Option<Account<'info, Debtor>>,
+}
+
+#[cfg_attr(feature = "cpi-events", event_cpi)]
+#[derive(Accounts)]
+pub struct RepayInvoicesBatch<'info> {
+    #[account(mut)]
+    pub business_owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        constraint = business_token_account.owner == business_owner.key() @ ErrorCode::TokenOwnerMismatch,
+    )]
+    pub business_token_account: Account<'info, TokenAccount>,
+
+    // Records late final repayments against the business
+    #[account(
+        init_if_needed,
+        payer = business_owner,
+        space = BusinessProfile::SIZE,
+        seeds = [BUSINESS_PROFILE_SEED, business_owner.key().as_ref()],
+        bump
+    )]
+    pub business_profile: Account<'info, BusinessProfile>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendDueDate<'info> {
+    #[account(
+        mut,
+        has_one = business_owner @ ErrorCode::InvoiceOwnerMismatch,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub business_owner: Signer<'info>,
+    // The position holder: the recorded investor, or whoever holds the receipt
+    pub investor: Signer<'info>,
+    pub investor_receipt: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct MarkDefaulted<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Account<'info, BusinessProfile>,
+
+    #[account(mut)]
+    pub experiment: Option<Account<'info, Experiment>>,
+
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), invoice.investor.as_ref()],
+        bump = pair_ledger.bump,
+    )]
+    pub pair_ledger: Option<Account<'info, PairLedger>>,
+
+    // The recorded investor's portfolio; required once the position is booked in it
+    #[account(
+        mut,
+        seeds = [PORTFOLIO_SEED, invoice.investor.as_ref()],
+        bump = portfolio.bump,
+    )]
+    pub portfolio: Option<Account<'info, InvestorPortfolio>>,
+
+    // Registry entry of the invoice's debtor; required for invoices listed against one
+    #[account(mut, address = invoice.debtor @ ErrorCode::DebtorMismatch)]
+    pub debtor: Option<Account<'info, Debtor>>,
+
+    // Exposure booked at funding is released from the investor's stats
+    #[account(
+        mut,
+        seeds = [INVESTOR_STATS_SEED, invoice.investor.as_ref()],
+        bump = investor_stats.bump,
+    )]
+    pub investor_stats: Option<Account<'info, InvestorStats>>,
+}
+
+#[derive(Accounts)]
+pub struct RaiseDispute<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    // The business owner or the position holder
+    pub party: Signer<'info>,
+    pub investor_receipt: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, resolver.as_ref())
+            || global_state.dispute_arbiter == Some(resolver.key()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub resolver: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = InvoiceAuditLog::SIZE,
+        seeds = [INVOICE_AUDIT_SEED, invoice.key().as_ref()],
+        bump
+    )]
+    pub invoice_audit_log: Account<'info, InvoiceAuditLog>,
+
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Account<'info, BusinessProfile>,
+
+    #[account(mut)]
+    pub experiment: Option<Account<'info, Experiment>>,
+
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), invoice.investor.as_ref()],
+        bump = pair_ledger.bump,
+    )]
+    pub pair_ledger: Option<Account<'info, PairLedger>>,
+
+    // The recorded investor's portfolio; required once the position is booked in it
+    #[account(
+        mut,
+        seeds = [PORTFOLIO_SEED, invoice.investor.as_ref()],
+        bump = portfolio.bump,
+    )]
+    pub portfolio: Option<Account<'info, InvestorPortfolio>>,
+
+    // Registry entry of the invoice's debtor; required for invoices listed against one
+    #[account(mut, address = invoice.debtor @ ErrorCode::DebtorMismatch)]
+    pub debtor: Option<Account<'info, Debtor>>,
+
+    // Exposure booked at funding is released from the investor's stats
+    #[account(
+        mut,
+        seeds = [INVESTOR_STATS_SEED, invoice.investor.as_ref()],
+        bump = investor_stats.bump,
+    )]
+    pub investor_stats: Option<Account<'info, InvestorStats>>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg_attr(feature = "cpi-events", event_cpi)]
+#[derive(Accounts)]
+pub struct ClaimInsurance<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+    
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    
+    pub investor: Signer<'info>,
+    
+    #[account(
+        mut,
+        associated_token::mint = invoice.mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+    
+    /// CHECK: signs for the insurance pool token account, holds no data
+    #[account(
+        seeds = [INSURANCE_POOL_AUTHORITY_SEED],
+        bump = global_state.insurance_pool_authority_bump,
+    )]
+    pub insurance_pool_authority: AccountInfo<'info>,
+
+    // Claimant's share and the payout vault for partially funded invoices
+    #[account(
+        seeds = [FUNDING_SHARE_SEED, invoice.key().as_ref(), investor.key().as_ref()],
+        bump = funding_share.bump,
+    )]
+    pub funding_share: Option<Account<'info, FundingShare>>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), invoice.investor.as_ref()],
+        bump = pair_ledger.bump,
+    )]
+    pub pair_ledger: Option<Account<'info, PairLedger>>,
+
+    // The recorded investor's portfolio; required once the position is booked in it
+    #[account(
+        mut,
+        seeds = [PORTFOLIO_SEED, invoice.investor.as_ref()],
+        bump = portfolio.bump,
+    )]
+    pub portfolio: Option<Account<'info, InvestorPortfolio>>,
+
+    // The claimant's position receipt, burned by the claim
+    #[account(
+        mut,
+        seeds = [RECEIPT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub receipt_mint: Option<Account<'info, Mint>>,
+
+    #[account(mut)]
+    pub investor_receipt: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRemainingInsurance<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    // The recorded investor's account; partially funded invoices pay the vault
+    #[account(
+        mut,
+        associated_token::mint = invoice.mint,
+        associated_token::authority = invoice.investor,
+    )]
+    pub investor_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    /// CHECK: signs for the insurance pool token account, holds no data
+    #[account(
+        seeds = [INSURANCE_POOL_AUTHORITY_SEED],
+        bump = global_state.insurance_pool_authority_bump,
+    )]
+    pub insurance_pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), invoice.investor.as_ref()],
+        bump = pair_ledger.bump,
+    )]
+    pub pair_ledger: Option<Account<'info, PairLedger>>,
+
+    // The recorded investor's portfolio; required once the position is booked in it
+    #[account(
+        mut,
+        seeds = [PORTFOLIO_SEED, invoice.investor.as_ref()],
+        bump = portfolio.bump,
+    )]
+    pub portfolio: Option<Account<'info, InvestorPortfolio>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "cpi-events", event_cpi)]
+#[derive(Accounts)]
+pub struct ContributeFunding<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = FundingShare::SIZE,
+        seeds = [FUNDING_SHARE_SEED, invoice.key().as_ref(), investor.key().as_ref()],
+        bump,
+    )]
+    pub funding_share: Account<'info, FundingShare>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = invoice,
+    )]
+    pub invoice_vault: Account<'info, TokenAccount>,
+
+    #[account(address = global_state.usdc_mint)]
+    pub usdc_mint: Account<'info, Mint>,
+
+    // Neither the business nor the debtor may fund the receivable between them
+    #[account(
+        mut,
+        constraint = investor.key() != invoice.business_owner @ ErrorCode::SelfFundingNotAllowed,
+        constraint = invoice.debtor_wallet != Some(investor.key()) @ ErrorCode::DebtorWalletIsParty,
+    )]
+    pub investor: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = invoice.business_owner,
+    )]
+    pub business_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub experiment: Option<Account<'info, Experiment>>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = InvestorStats::SIZE,
+        seeds = [INVESTOR_STATS_SEED, investor.key().as_ref()],
+        bump
+    )]
+    pub investor_stats: Account<'info, InvestorStats>,
+
+    // Required while GlobalState::require_whitelist is on
+    #[account(
+        seeds = [INVESTOR_WHITELIST_SEED, investor.key().as_ref()],
+        bump = investor_whitelist.bump,
+    )]
+    pub investor_whitelist: Option<Account<'info, InvestorWhitelist>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    // Registry entry of the invoice's debtor; required for invoices listed against one
+    #[account(mut, address = invoice.debtor @ ErrorCode::DebtorMismatch)]
+    pub debtor: Option<Account<'info, Debtor>>,
+
+    // Counts the invoice as funded once contributions reach its face value
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Box<Account<'info, BusinessProfile>>,
+
+    /// CHECK: the investor's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, investor.key().as_ref()], bump)]
+    pub investor_blacklist: UncheckedAccount<'info>,
+
+    /// CHECK: the business's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, invoice.business_owner.as_ref()], bump)]
+    pub business_blacklist: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawContribution<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        close = investor,
+        seeds = [FUNDING_SHARE_SEED, invoice.key().as_ref(), investor.key().as_ref()],
+        bump = funding_share.bump,
+    )]
+    pub funding_share: Account<'info, FundingShare>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub investor: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = invoice_vault.mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+
+    // Takes the invoice off the business's open count when this withdrawal expires it
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Box<Account<'info, BusinessProfile>>,
+
+    // Gives back the capital the contribution counted as deployed
+    #[account(
+        mut,
+        seeds = [INVESTOR_STATS_SEED, investor.key().as_ref()],
+        bump = investor_stats.bump,
+    )]
+    pub investor_stats: Account<'info, InvestorStats>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimShareRepayment<'info> {
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [FUNDING_SHARE_SEED, invoice.key().as_ref(), investor.key().as_ref()],
+        bump = funding_share.bump,
+    )]
+    pub funding_share: Account<'info, FundingShare>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Account<'info, TokenAccount>,
+
+    pub investor: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = invoice_vault.mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct DepositInsuranceLiquidity<'info> {
+    #[account(
+        init_if_needed,
+        payer = provider,
+        space = InsuranceLpPosition::SIZE,
+        seeds = [INSURANCE_LP_SEED, provider.key().as_ref()],
+        bump
+    )]
+    pub lp_position: Account<'info, InsuranceLpPosition>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(mut)]
+    pub provider: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = provider,
+    )]
+    pub provider_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RequestInsuranceWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [INSURANCE_LP_SEED, provider.key().as_ref()],
+        bump = lp_position.bump,
+    )]
+    pub lp_position: Account<'info, InsuranceLpPosition>,
+
+    pub provider: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawInsuranceLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [INSURANCE_LP_SEED, provider.key().as_ref()],
+        bump = lp_position.bump,
+    )]
+    pub lp_position: Account<'info, InsuranceLpPosition>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub provider: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = provider,
+    )]
+    pub provider_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    /// CHECK: signs for the insurance pool token account, holds no data
+    #[account(
+        seeds = [INSURANCE_POOL_AUTHORITY_SEED],
+        bump = global_state.insurance_pool_authority_bump,
+    )]
+    pub insurance_pool_authority: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ProposePoolWithdrawal<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = PoolWithdrawalProposal::SIZE,
+        seeds = [POOL_WITHDRAWAL_SEED],
+        bump
+    )]
+    pub proposal: Account<'info, PoolWithdrawalProposal>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub destination: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecutePoolWithdrawal<'info> {
+    #[account(
+        mut,
+        close = authority,
+        seeds = [POOL_WITHDRAWAL_SEED],
+        bump = proposal.bump,
+    )]
+    pub proposal: Account<'info, PoolWithdrawalProposal>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    #[account(
+        seeds = [ALLOWLIST_SEED, VaultKind::InsurancePool.seed().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Account<'info, DestinationAllowlist>,
+
+    #[account(
+        mut,
+        address = proposal.destination,
+    )]
+    pub destination: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    /// CHECK: signs for the insurance pool token account, holds no data
+    #[account(
+        seeds = [INSURANCE_POOL_AUTHORITY_SEED],
+        bump = global_state.insurance_pool_authority_bump,
+    )]
+    pub insurance_pool_authority: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPoolWithdrawal<'info> {
+    #[account(
+        mut,
+        close = authority,
+        seeds = [POOL_WITHDRAWAL_SEED],
+        bump = proposal.bump,
+    )]
+    pub proposal: Account<'info, PoolWithdrawalProposal>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DepositBalance<'info> {
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = InvestorBalance::SIZE,
+        seeds = [INVESTOR_BALANCE_SEED, investor.key().as_ref()],
+        bump
+    )]
+    pub investor_balance: Account<'info, InvestorBalance>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        seeds = [INVESTOR_CUSTODY_SEED, investor.key().as_ref()],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = investor_balance,
+    )]
+    pub investor_custody: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(address = global_state.usdc_mint)]
+    pub usdc_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub investor: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawBalance<'info> {
+    #[account(
+        mut,
+        seeds = [INVESTOR_BALANCE_SEED, investor.key().as_ref()],
+        bump = investor_balance.bump,
+    )]
+    pub investor_balance: Account<'info, InvestorBalance>,
+
+    #[account(
+        mut,
+        address = investor_balance.custody @ ErrorCode::InvalidCustodyAccount,
+    )]
+    pub investor_custody: Account<'info, TokenAccount>,
+
+    pub investor: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = investor_custody.mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CommitBookRoot<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    // The commitment being built; a new one opens once the last is finalized
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        space = BookCommitment::SIZE,
+        seeds = [BOOK_COMMITMENT_SEED, global_state.book_commitment_count.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub book_commitment: Box<Account<'info, BookCommitment>>,
+
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        space = CrankTreasury::SIZE,
+        seeds = [CRANK_TREASURY_SEED],
+        bump,
+    )]
+    pub crank_treasury: Account<'info, CrankTreasury>,
+
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SweepBalancePremium<'info> {
+    #[account(
+        mut,
+        seeds = [INVESTOR_BALANCE_SEED, investor_balance.investor.as_ref()],
+        bump = investor_balance.bump,
+    )]
+    pub investor_balance: Account<'info, InvestorBalance>,
+
+    #[account(
+        mut,
+        address = investor_balance.custody @ ErrorCode::InvalidCustodyAccount,
+    )]
+    pub investor_custody: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AuditInvestorBalance<'info> {
+    pub investor_balance: Account<'info, InvestorBalance>,
+
+    #[account(address = investor_balance.custody @ ErrorCode::InvalidCustodyAccount)]
+    pub investor_custody: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyVaultIntegrity<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub vault: Account<'info, TokenAccount>,
+
+    // Only the account that locates the vault of the requested kind is needed
+    #[account(seeds = [PAYOUT_PROCESSOR_SEED], bump = payout_processor.bump)]
+    pub payout_processor: Option<Account<'info, PayoutProcessor>>,
+    pub investor_balance: Option<Account<'info, InvestorBalance>>,
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Option<Account<'info, Invoice>>,
+}
+
+#[derive(Accounts)]
+pub struct GetInvoiceDetails<'info> {
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Account<'info, Invoice>,
+}
+
+#[derive(Accounts)]
+pub struct GetListingProof<'info> {
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Account<'info, Invoice>,
+}
+
+#[derive(Accounts)]
+pub struct PublishListingProof<'info> {
+    #[account(
+        mut,
+        has_one = business_owner @ ErrorCode::InvoiceOwnerMismatch,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    pub business_owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetGlobalStats<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    // Left out before the pool is initialized
+    #[account(
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct GetHealth<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Option<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct GetDailyFundingCapacity<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyDebtorInfo<'info> {
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Account<'info, Invoice>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateInvoice<'info> {
+    /// CHECK: an invoice on an older layout, which Account<Invoice> cannot load;
+    /// migrate_invoice_data checks its discriminator and version
+    #[account(mut, owner = crate::ID @ ErrorCode::InvalidInvoiceAccount)]
+    pub invoice: UncheckedAccount<'info>,
+
+    /// CHECK: receives the rent the migration frees; checked against the invoice
+    #[account(mut)]
+    pub business_owner: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateGlobalState<'info> {
+    /// CHECK: may be on an older layout Account<GlobalState> cannot load;
+    /// migrate_global_state_data decodes it
+    #[account(mut, seeds = [GLOBAL_STATE_SEED], bump)]
+    pub global_state: UncheckedAccount<'info>,
+
+    /// CHECK: receives any rent the migration frees; checked against the global state
+    #[account(mut)]
+    pub authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ErasePersonalData<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = InvoiceAuditLog::SIZE,
+        seeds = [INVOICE_AUDIT_SEED, invoice.key().as_ref()],
+        bump
+    )]
+    pub invoice_audit_log: Account<'info, InvoiceAuditLog>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetInvoicesDetailsBatch {}
+
+#[derive(Accounts)]
+pub struct GetBusinessAgingReport<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+}
+
+#[derive(Accounts)]
+#[instruction(business_owner: Pubkey)]
+pub struct ExportBusinessHistory<'info> {
+    #[account(seeds = [BUSINESS_PROFILE_SEED, business_owner.as_ref()], bump = business_profile.bump)]
+    pub business_profile: Account<'info, BusinessProfile>,
+}
+
+#[derive(Accounts)]
+#[instruction(vault: VaultKind)]
+pub struct RegisterDestination<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = DestinationAllowlist::SIZE,
+        seeds = [ALLOWLIST_SEED, vault.seed().as_ref()],
+        bump
+    )]
+    pub allowlist: Account<'info, DestinationAllowlist>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    /// CHECK: a USDC token account, checked in the handler, except on the
+    /// lost-and-found allowlist, which takes any account a recovery may pay
+    pub destination: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(vault: VaultKind)]
+pub struct UpdateDestinationAllowlist<'info> {
+    #[account(
+        mut,
+        seeds = [ALLOWLIST_SEED, vault.seed().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Account<'info, DestinationAllowlist>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+}
+
+// UpdateGlobalState plus the param history page a governed parameter change lands in
+#[derive(Accounts)]
+pub struct UpdateGovernedParams<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    #[account(
+        mut,
+        seeds = [PARAM_HISTORY_SEED, param_history.page.to_le_bytes().as_ref()],
+        bump = param_history.bump,
+    )]
+    pub param_history: Account<'info, ParamHistory>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAdminAction<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        init,
+        payer = proposer,
+        space = AdminProposal::SIZE,
+        seeds = [ADMIN_PROPOSAL_SEED, global_state.admin_proposal_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, AdminProposal>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveAdminAction<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_PROPOSAL_SEED, proposal.proposal_id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+    )]
+    pub proposal: Account<'info, AdminProposal>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub approver: Signer<'info>,
+}
+
+// The proposed instruction's own accounts follow as remaining accounts, in the order
+// the proposal lists them
+#[derive(Accounts)]
+pub struct ExecuteAdminAction<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        close = proposer,
+        seeds = [ADMIN_PROPOSAL_SEED, proposal.proposal_id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+    )]
+    pub proposal: Account<'info, AdminProposal>,
+
+    /// CHECK: receives the proposal's rent
+    #[account(mut, address = proposal.proposer)]
+    pub proposer: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    /// CHECK: signs the proposed instruction as its authority, holds no data
+    #[account(seeds = [APPROVALS_SEED], bump)]
+    pub approvals: AccountInfo<'info>,
+
+    pub executor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAdminAction<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        close = proposer,
+        seeds = [ADMIN_PROPOSAL_SEED, proposal.proposal_id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+    )]
+    pub proposal: Account<'info, AdminProposal>,
+
+    /// CHECK: receives the proposal's rent
+    #[account(mut, address = proposal.proposer)]
+    pub proposer: AccountInfo<'info>,
+
+    pub canceller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RecoverForeignTokens<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    #[account(mut)]
+    pub source: Account<'info, TokenAccount>,
+
+    #[account(mut, constraint = destination.mint == source.mint @ ErrorCode::TokenMintMismatch)]
+    pub destination: Account<'info, TokenAccount>,
+
+    // Recoveries pay only destinations on the lost-and-found allowlist
+    #[account(
+        seeds = [ALLOWLIST_SEED, VaultKind::LostAndFound.seed().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Account<'info, DestinationAllowlist>,
+
+    /// CHECK: the program PDA owning `source`, checked against the seeds of `kind`
+    pub owner: AccountInfo<'info>,
+
+    // Only the account that locates the PDA of the requested kind is needed
+    #[account(seeds = [PAYOUT_PROCESSOR_SEED], bump = payout_processor.bump)]
+    pub payout_processor: Option<Account<'info, PayoutProcessor>>,
+    pub investor_balance: Option<Account<'info, InvestorBalance>>,
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Option<Account<'info, Invoice>>,
+
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RecoverExcessLamports<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    /// CHECK: any account the program owns; only lamports above its rent move
+    #[account(mut, owner = crate::ID)]
+    pub account: AccountInfo<'info>,
+
+    /// CHECK: receives the lamports; checked against the allowlist
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
+
+    // Recoveries pay only destinations on the lost-and-found allowlist
+    #[account(
+        seeds = [ALLOWLIST_SEED, VaultKind::LostAndFound.seed().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Account<'info, DestinationAllowlist>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeInsurancePool<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    #[account(address = global_state.usdc_mint)]
+    pub usdc_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [INSURANCE_POOL_SEED],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = insurance_pool_authority,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    /// CHECK: signs for the insurance pool token account, holds no data
+    #[account(
+        seeds = [INSURANCE_POOL_AUTHORITY_SEED],
+        bump,
+    )]
+    pub insurance_pool_authority: AccountInfo<'info>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGlobalState<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMintApproval<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(address = global_state.usdc_mint)]
+    pub primary_mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigurePayoutProcessor<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = PayoutProcessor::SIZE,
+        seeds = [PAYOUT_PROCESSOR_SEED],
+        bump
+    )]
+    pub payout_processor: Account<'info, PayoutProcessor>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        seeds = [OUTBOX_ESCROW_SEED],
+        bump,
+        token::mint = usdc_mint,
+        token::authority = outbox_authority,
+    )]
+    pub outbox_escrow: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the outbox escrow authority PDA
+    #[account(
+        seeds = [OUTBOX_AUTHORITY_SEED],
+        bump,
+    )]
+    pub outbox_authority: AccountInfo<'info>,
+
+    pub custody: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [ALLOWLIST_SEED, VaultKind::PayoutCustody.seed().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Account<'info, DestinationAllowlist>,
+
+    #[account(address = global_state.usdc_mint)]
+    pub usdc_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyPayoutCustody<'info> {
+    #[account(
+        mut,
+        seeds = [PAYOUT_PROCESSOR_SEED],
+        bump = payout_processor.bump,
+    )]
+    pub payout_processor: Account<'info, PayoutProcessor>,
+
+    #[account(
+        seeds = [ALLOWLIST_SEED, VaultKind::PayoutCustody.seed().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Account<'info, DestinationAllowlist>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(page: u32)]
+pub struct OpenOutboxPage<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = OutboxPage::SIZE,
+        seeds = [OUTBOX_SEED, page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub outbox_page: Account<'info, OutboxPage>,
+
+    #[account(
+        seeds = [PAYOUT_PROCESSOR_SEED],
+        bump = payout_processor.bump,
+    )]
+    pub payout_processor: Account<'info, PayoutProcessor>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(page: u32)]
+pub struct OpenAdminLogPage<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = AdminActionLog::SIZE,
+        seeds = [ADMIN_LOG_SEED, page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(page: u32)]
+pub struct OpenParamHistoryPage<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = ParamHistory::SIZE,
+        seeds = [PARAM_HISTORY_SEED, page.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub param_history: Account<'info, ParamHistory>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetParamAt<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+}
+
+#[derive(Accounts)]
+pub struct GetAdminActionLog<'info> {
+    pub admin_log: Account<'info, AdminActionLog>,
+}
+
+#[derive(Accounts)]
+pub struct GetInvoiceAuditTrail<'info> {
+    pub invoice_audit_log: Account<'info, InvoiceAuditLog>,
+}
+
+#[derive(Accounts)]
+#[instruction(business: Pubkey)]
+pub struct GetBusinessStats<'info> {
+    #[account(seeds = [BUSINESS_PROFILE_SEED, business.as_ref()], bump = business_profile.bump)]
+    pub business_profile: Account<'info, BusinessProfile>,
+}
+
+#[derive(Accounts)]
+#[instruction(investor: Pubkey)]
+pub struct GetInvestorPortfolio<'info> {
+    #[account(seeds = [PORTFOLIO_SEED, investor.as_ref()], bump = portfolio.bump)]
+    pub portfolio: Account<'info, InvestorPortfolio>,
+}
+
+#[derive(Accounts)]
+#[instruction(business: Pubkey, investor: Pubkey)]
+pub struct GetPairStatement<'info> {
+    #[account(
+        seeds = [PAIR_LEDGER_SEED, business.as_ref(), investor.as_ref()],
+        bump = pair_ledger.bump,
+    )]
+    pub pair_ledger: Account<'info, PairLedger>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePairLedger<'info> {
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, business.key().as_ref(), investor.key().as_ref()],
+        bump = pair_ledger.bump,
+        close = investor,
+    )]
+    pub pair_ledger: Account<'info, PairLedger>,
+
+    pub business: Signer<'info>,
+    #[account(mut)]
+    pub investor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RedeemReceipt<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [RECEIPT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub receipt_mint: Account<'info, Mint>,
+
+    // Any account holding the receipt will do, not only the associated one
+    #[account(mut)]
+    pub holder_receipt: Account<'info, TokenAccount>,
+
+    // Writable to take the lamports redeem_receipt_to_sol unwraps
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = holder_token_account.mint == invoice_vault.mint @ ErrorCode::TokenMintMismatch,
+        constraint = holder_token_account.owner == holder.key() @ ErrorCode::TokenOwnerMismatch,
+    )]
+    pub holder_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SweepUnclaimedRepayment<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = InvoiceAuditLog::SIZE,
+        seeds = [INVOICE_AUDIT_SEED, invoice.key().as_ref()],
+        bump
+    )]
+    pub invoice_audit_log: Account<'info, InvoiceAuditLog>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ListPosition<'info> {
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        init,
+        payer = seller,
+        space = PositionListing::SIZE,
+        seeds = [POSITION_LISTING_SEED, invoice.key().as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, PositionListing>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    // Required for positions funded with a receipt
+    #[account(mut)]
+    pub seller_receipt: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelPositionListing<'info> {
+    #[account(
+        mut,
+        seeds = [POSITION_LISTING_SEED, listing.invoice.as_ref()],
+        bump = listing.bump,
+        has_one = seller @ ErrorCode::Unauthorized,
+        close = seller,
+    )]
+    pub listing: Account<'info, PositionListing>,
+
+    #[account(mut)]
+    pub seller: Signer<'info>,
+
+    #[account(mut)]
+    pub seller_receipt: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    // Consumed by the sale; its rent goes back to the seller
+    #[account(
+        mut,
+        seeds = [POSITION_LISTING_SEED, invoice.key().as_ref()],
+        bump = listing.bump,
+        has_one = invoice,
+        has_one = seller @ ErrorCode::PositionListingStale,
+        close = seller,
+    )]
+    pub listing: Account<'info, PositionListing>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    // The business may not buy back a position in its own receivable
+    #[account(
+        mut,
+        constraint = buyer.key() != invoice.business_owner @ ErrorCode::SelfFundingNotAllowed,
+    )]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: receives the listing rent; must be the listing's seller
+    #[account(mut)]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == invoice.mint @ ErrorCode::TokenMintMismatch,
+        constraint = buyer_token_account.owner == buyer.key() @ ErrorCode::TokenOwnerMismatch,
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = seller_token_account.mint == invoice.mint @ ErrorCode::TokenMintMismatch,
+        constraint = seller_token_account.owner == listing.seller @ ErrorCode::TokenOwnerMismatch,
+    )]
+    pub seller_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = InvestorStats::SIZE,
+        seeds = [INVESTOR_STATS_SEED, buyer.key().as_ref()],
+        bump
+    )]
+    pub investor_stats: Account<'info, InvestorStats>,
+
+    // Required while GlobalState::require_whitelist is on
+    #[account(
+        seeds = [INVESTOR_WHITELIST_SEED, buyer.key().as_ref()],
+        bump = buyer_whitelist.bump,
+    )]
+    pub buyer_whitelist: Option<Account<'info, InvestorWhitelist>>,
+
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), listing.seller.as_ref()],
+        bump = seller_ledger.bump,
+    )]
+    pub seller_ledger: Option<Account<'info, PairLedger>>,
+
+    // Portfolio of the recorded investor, the seller unless the receipt changed
+    // hands off-program; required once the position is booked in it
+    #[account(
+        mut,
+        seeds = [PORTFOLIO_SEED, invoice.investor.as_ref()],
+        bump = seller_portfolio.bump,
+    )]
+    pub seller_portfolio: Option<Account<'info, InvestorPortfolio>>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = PairLedger::SIZE,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_ledger: Account<'info, PairLedger>,
+
+    // The receipt moves from the seller's account to the buyer's
+    #[account(mut)]
+    pub seller_receipt: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub buyer_receipt: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    // Required for positions whose exposure was booked at funding
+    #[account(
+        mut,
+        seeds = [INVESTOR_STATS_SEED, listing.seller.as_ref()],
+        bump = seller_stats.bump,
+    )]
+    pub seller_stats: Option<Account<'info, InvestorStats>>,
+
+    /// CHECK: the buyer's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, buyer.key().as_ref()], bump)]
+    pub buyer_blacklist: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AckOutboxEntry<'info> {
+    #[account(
+        seeds = [PAYOUT_PROCESSOR_SEED],
+        bump = payout_processor.bump,
+        has_one = processor @ ErrorCode::Unauthorized,
+        has_one = custody,
+        constraint = payout_processor.escrow == outbox_escrow.key() @ ErrorCode::InvalidOutboxEscrow,
+    )]
+    pub payout_processor: Account<'info, PayoutProcessor>,
+
+    #[account(
+        mut,
+        seeds = [OUTBOX_SEED, outbox_page.page.to_le_bytes().as_ref()],
+        bump = outbox_page.bump,
+    )]
+    pub outbox_page: Account<'info, OutboxPage>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_SEED, invoice.business_owner.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(mut, address = payout_processor.escrow @ ErrorCode::InvalidOutboxEscrow)]
+    pub outbox_escrow: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub custody: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [ALLOWLIST_SEED, VaultKind::PayoutCustody.seed().as_ref()],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Account<'info, DestinationAllowlist>,
+
+    /// CHECK: This is the outbox escrow authority PDA
+    #[account(
+        seeds = [OUTBOX_AUTHORITY_SEED],
+        bump = payout_processor.authority_bump,
+    )]
+    pub outbox_authority: AccountInfo<'info>,
+
+    pub processor: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RetryOutboxEntry<'info> {
+    #[account(
+        seeds = [PAYOUT_PROCESSOR_SEED],
+        bump = payout_processor.bump,
+    )]
+    pub payout_processor: Account<'info, PayoutProcessor>,
+
+    #[account(
+        mut,
+        seeds = [OUTBOX_SEED, outbox_page.page.to_le_bytes().as_ref()],
+        bump = outbox_page.bump,
+    )]
+    pub outbox_page: Account<'info, OutboxPage>,
+
+    // Invoice the entry pays out, named in the retry event
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Account<'info, Invoice>,
+
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOutboxEntry<'info> {
+    #[account(
+        seeds = [PAYOUT_PROCESSOR_SEED],
+        bump = payout_processor.bump,
+        constraint = payout_processor.escrow == outbox_escrow.key() @ ErrorCode::InvalidOutboxEscrow,
+    )]
+    pub payout_processor: Account<'info, PayoutProcessor>,
+
+    #[account(
+        mut,
+        seeds = [OUTBOX_SEED, outbox_page.page.to_le_bytes().as_ref()],
+        bump = outbox_page.bump,
+    )]
+    pub outbox_page: Account<'info, OutboxPage>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_SEED, invoice.business_owner.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(mut, address = payout_processor.escrow @ ErrorCode::InvalidOutboxEscrow)]
+    pub outbox_escrow: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = business_owner,
+    )]
+    pub business_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the outbox escrow authority PDA
+    #[account(
+        seeds = [OUTBOX_AUTHORITY_SEED],
+        bump = payout_processor.authority_bump,
+    )]
+    pub outbox_authority: AccountInfo<'info>,
+
+    pub business_owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(experiment_id: u64)]
+pub struct CreateExperiment<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = Experiment::SIZE,
+        seeds = [EXPERIMENT_SEED, experiment_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub experiment: Account<'info, Experiment>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetExperimentResults<'info> {
+    pub experiment: Account<'info, Experiment>,
+}
+
+#[derive(Accounts)]
+#[instruction(agency: Pubkey)]
+pub struct RegisterCollectionsAgency<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = CollectionsAgency::SIZE,
+        seeds = [COLLECTIONS_AGENCY_SEED, agency.as_ref()],
+        bump
+    )]
+    pub collections_agency: Account<'info, CollectionsAgency>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AssignCollections<'info> {
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTIONS_AGENCY_SEED, collections_agency.authority.as_ref()],
+        bump = collections_agency.bump,
+    )]
+    pub collections_agency: Account<'info, CollectionsAgency>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = CollectionsAssignment::SIZE,
+        seeds = [COLLECTIONS_SEED, invoice.key().as_ref()],
+        bump
+    )]
+    pub collections_assignment: Account<'info, CollectionsAssignment>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = InvoiceAuditLog::SIZE,
+        seeds = [INVOICE_AUDIT_SEED, invoice.key().as_ref()],
+        bump
+    )]
+    pub invoice_audit_log: Account<'info, InvoiceAuditLog>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemitRecovery<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = global_state.usdc_mint,
+        token::authority = payer,
+    )]
+    pub payer_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTIONS_SEED, invoice.key().as_ref()],
+        bump = collections_assignment.bump,
+    )]
+    pub collections_assignment: Option<Account<'info, CollectionsAssignment>>,
+
+    #[account(mut)]
+    pub collections_agency: Option<Account<'info, CollectionsAgency>>,
+
+    #[account(mut)]
+    pub agency_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub investor_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Option<Account<'info, TokenAccount>>,
+
+    // Receives the excess once the pool and the investor are whole
+    #[account(
+        mut,
+        token::mint = invoice.mint,
+        token::authority = invoice.business_owner,
+    )]
+    pub business_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), invoice.investor.as_ref()],
+        bump = pair_ledger.bump,
+    )]
+    pub pair_ledger: Option<Account<'info, PairLedger>>,
+
+    // The recorded investor's portfolio; required once the position is booked in it
+    #[account(
+        mut,
+        seeds = [PORTFOLIO_SEED, invoice.investor.as_ref()],
+        bump = portfolio.bump,
+    )]
+    pub portfolio: Option<Account<'info, InvestorPortfolio>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SettleRecovery<'info> {
+    #[account(
+        mut,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    // The invoice's business, or the authority settling on its behalf
+    #[account(
+        constraint = settler.key() == invoice.business_owner
+            || is_authority(&global_state, settler.as_ref()) @ ErrorCode::Unauthorized,
+    )]
+    pub settler: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = invoice.mint,
+        token::authority = settler,
+    )]
+    pub settler_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTIONS_SEED, invoice.key().as_ref()],
+        bump = collections_assignment.bump,
+    )]
+    pub collections_assignment: Option<Account<'info, CollectionsAssignment>>,
+
+    #[account(mut)]
+    pub collections_agency: Option<Account<'info, CollectionsAgency>>,
+
+    #[account(mut)]
+    pub agency_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub investor_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+    )]
+    pub invoice_vault: Option<Account<'info, TokenAccount>>,
+
+    // Receives the excess when the authority settles
+    #[account(
+        mut,
+        token::mint = invoice.mint,
+        token::authority = invoice.business_owner,
+    )]
+    pub business_token_account: Option<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), invoice.investor.as_ref()],
+        bump = pair_ledger.bump,
+    )]
+    pub pair_ledger: Option<Account<'info, PairLedger>>,
+
+    // The recorded investor's portfolio; required once the position is booked in it
+    #[account(
+        mut,
+        seeds = [PORTFOLIO_SEED, invoice.investor.as_ref()],
+        bump = portfolio.bump,
+    )]
+    pub portfolio: Option<Account<'info, InvestorPortfolio>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateInvestmentPool<'info> {
+    #[account(
+        init,
+        payer = manager,
+        space = InvestmentPool::SIZE,
+        seeds = [INVESTMENT_POOL_SEED, manager.key().as_ref()],
+        bump,
+    )]
+    pub pool: Account<'info, InvestmentPool>,
+
+    /// CHECK: investor of record for the pool's positions, holds no data
+    #[account(
+        seeds = [POOL_AUTHORITY_SEED, pool.key().as_ref()],
+        bump,
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = manager,
+        seeds = [POOL_SHARES_SEED, pool.key().as_ref()],
+        bump,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = pool_authority,
+    )]
+    pub share_mint: Account<'info, Mint>,
+
+    // Associated, since fund_invoice takes the investor's associated account
+    #[account(
+        init_if_needed,
+        payer = manager,
+        associated_token::mint = usdc_mint,
+        associated_token::authority = pool_authority,
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(address = global_state.usdc_mint @ ErrorCode::TokenMintMismatch)]
     pub usdc_mint: Account<'info, Mint>,
-    
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(mut)]
+    pub manager: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositToPool<'info> {
+    #[account(
+        seeds = [INVESTMENT_POOL_SEED, pool.manager.as_ref()],
+        bump = pool.bump,
+        has_one = share_mint @ ErrorCode::TokenMintMismatch,
+        has_one = vault @ ErrorCode::PoolVaultMismatch,
+    )]
+    pub pool: Account<'info, InvestmentPool>,
+
+    /// CHECK: mints the shares, holds no data
+    #[account(
+        seeds = [POOL_AUTHORITY_SEED, pool.key().as_ref()],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub share_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = pool.mint,
+        token::authority = depositor,
+    )]
+    pub depositor_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        associated_token::mint = share_mint,
+        associated_token::authority = depositor,
+    )]
+    pub depositor_shares: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RedeemFromPool<'info> {
+    #[account(
+        seeds = [INVESTMENT_POOL_SEED, pool.manager.as_ref()],
+        bump = pool.bump,
+        has_one = share_mint @ ErrorCode::TokenMintMismatch,
+        has_one = vault @ ErrorCode::PoolVaultMismatch,
+    )]
+    pub pool: Account<'info, InvestmentPool>,
+
+    /// CHECK: signs for the vault, holds no data
+    #[account(
+        seeds = [POOL_AUTHORITY_SEED, pool.key().as_ref()],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub share_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub vault: Account<'info, TokenAccount>,
+
+    pub holder: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = share_mint,
+        token::authority = holder,
+    )]
+    pub holder_shares: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = pool.mint,
+        token::authority = holder,
+    )]
+    pub holder_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct PoolFundInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [INVESTMENT_POOL_SEED, manager.key().as_ref()],
+        bump = pool.bump,
+        has_one = manager @ ErrorCode::Unauthorized,
+    )]
+    pub pool: Account<'info, InvestmentPool>,
+
+    /// CHECK: signs fund_invoice as the investor, holds no data
+    #[account(
+        mut,
+        seeds = [POOL_AUTHORITY_SEED, pool.key().as_ref()],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+
+    /// CHECK: read through load_invoice; fund_invoice checks the rest
+    #[account(mut)]
+    pub invoice: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = manager,
+        space = PoolPosition::SIZE,
+        seeds = [POOL_POSITION_SEED, pool.key().as_ref(), invoice.key().as_ref()],
+        bump,
+    )]
+    pub position: Account<'info, PoolPosition>,
+
+    #[account(mut)]
+    pub manager: Signer<'info>,
+
+    pub invoice_financing_program: Program<'info, InvoiceFinancing>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettlePoolPosition<'info> {
+    #[account(
+        mut,
+        seeds = [INVESTMENT_POOL_SEED, pool.manager.as_ref()],
+        bump = pool.bump,
+        has_one = vault @ ErrorCode::PoolVaultMismatch,
+    )]
+    pub pool: Account<'info, InvestmentPool>,
+
+    /// CHECK: signs the settlement as the investor, holds no data
+    #[account(
+        mut,
+        seeds = [POOL_AUTHORITY_SEED, pool.key().as_ref()],
+        bump = pool.authority_bump,
+    )]
+    pub pool_authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub vault: Account<'info, TokenAccount>,
+
+    /// CHECK: the position's invoice, read through load_invoice
+    #[account(mut, address = position.invoice @ ErrorCode::InvalidInvoiceAccount)]
+    pub invoice: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        close = manager,
+        seeds = [POOL_POSITION_SEED, pool.key().as_ref(), position.invoice.as_ref()],
+        bump = position.bump,
+    )]
+    pub position: Account<'info, PoolPosition>,
+
+    /// CHECK: takes back the rent the position was opened with
+    #[account(mut, address = pool.manager @ ErrorCode::Unauthorized)]
+    pub manager: UncheckedAccount<'info>,
+
+    pub invoice_financing_program: Program<'info, InvoiceFinancing>,
+}
+
+#[derive(Accounts)]
+pub struct CreateAutoInvestMandate<'info> {
+    #[account(
+        init,
+        payer = investor,
+        space = AutoInvestMandate::SIZE,
+        seeds = [AUTO_INVEST_MANDATE_SEED, investor.key().as_ref()],
+        bump,
+    )]
+    pub mandate: Account<'info, AutoInvestMandate>,
+
+    #[account(mut)]
+    pub investor: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageAutoInvestMandate<'info> {
+    #[account(
+        mut,
+        seeds = [AUTO_INVEST_MANDATE_SEED, investor.key().as_ref()],
+        bump = mandate.bump,
+        has_one = investor @ ErrorCode::Unauthorized,
+    )]
+    pub mandate: Account<'info, AutoInvestMandate>,
+
+    pub investor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeAutoInvestMandate<'info> {
+    #[account(
+        mut,
+        close = investor,
+        seeds = [AUTO_INVEST_MANDATE_SEED, investor.key().as_ref()],
+        bump = mandate.bump,
+        has_one = investor @ ErrorCode::Unauthorized,
+    )]
+    pub mandate: Account<'info, AutoInvestMandate>,
+
+    #[account(mut)]
+    pub investor: Signer<'info>,
+
+    #[account(mut, address = mandate.token_account @ ErrorCode::TokenOwnerMismatch)]
+    pub investor_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "cpi-events", event_cpi)]
+#[derive(Accounts)]
+pub struct ExecuteAutoInvest<'info> {
+    #[account(
+        mut,
+        seeds = [AUTO_INVEST_MANDATE_SEED, mandate.investor.as_ref()],
+        bump = mandate.bump,
+    )]
+    pub mandate: Account<'info, AutoInvestMandate>,
+
+    /// CHECK: the mandate's investor, credited with the position; does not sign
+    #[account(address = mandate.investor @ ErrorCode::Unauthorized)]
+    pub investor: UncheckedAccount<'info>,
+
+    #[account(mut, address = mandate.token_account @ ErrorCode::TokenOwnerMismatch)]
+    pub investor_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_SEED, invoice.business_owner.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump,
+        constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch,
+        constraint = invoice.business_owner != investor.key() @ ErrorCode::SelfFundingNotAllowed,
+        constraint = invoice.debtor_wallet != Some(investor.key()) @ ErrorCode::DebtorWalletIsParty,
+    )]
+    pub invoice: Box<Account<'info, Invoice>>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Box<Account<'info, GlobalState>>,
+
+    #[account(
+        mut,
+        associated_token::mint = invoice.mint,
+        associated_token::authority = invoice.business_owner,
+    )]
+    pub business_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub experiment: Option<Account<'info, Experiment>>,
+
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        space = InvestorStats::SIZE,
+        seeds = [INVESTOR_STATS_SEED, investor.key().as_ref()],
+        bump
+    )]
+    pub investor_stats: Box<Account<'info, InvestorStats>>,
+
+    // Required while GlobalState::require_whitelist is on
+    #[account(
+        seeds = [INVESTOR_WHITELIST_SEED, investor.key().as_ref()],
+        bump = investor_whitelist.bump,
+    )]
+    pub investor_whitelist: Option<Account<'info, InvestorWhitelist>>,
+
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        space = PairLedger::SIZE,
+        seeds = [PAIR_LEDGER_SEED, invoice.business_owner.as_ref(), investor.key().as_ref()],
+        bump
+    )]
+    pub pair_ledger: Box<Account<'info, PairLedger>>,
+
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        seeds = [RECEIPT_SEED, invoice.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = invoice,
+    )]
+    pub receipt_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_receipt: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        seeds = [INVOICE_VAULT_SEED, invoice.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = invoice,
+    )]
+    pub invoice_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = invoice.mint @ ErrorCode::TokenMintMismatch)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    // Registry entry of the invoice's debtor; required for invoices listed against one
+    #[account(mut, address = invoice.debtor @ ErrorCode::DebtorMismatch)]
+    pub debtor: Option<Account<'info, Debtor>>,
+
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, invoice.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Box<Account<'info, BusinessProfile>>,
+
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: the investor's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, investor.key().as_ref()], bump)]
+    pub investor_blacklist: UncheckedAccount<'info>,
+
+    /// CHECK: the business's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, invoice.business_owner.as_ref()], bump)]
+    pub business_blacklist: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReportUncollectible<'info> {
+    #[account(constraint = invoice.version == INVOICE_VERSION @ ErrorCode::AccountVersionMismatch)]
+    pub invoice: Account<'info, Invoice>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTIONS_AGENCY_SEED, authority.key().as_ref()],
+        bump = collections_agency.bump,
+    )]
+    pub collections_agency: Account<'info, CollectionsAgency>,
+
+    #[account(
+        mut,
+        seeds = [COLLECTIONS_SEED, invoice.key().as_ref()],
+        bump = collections_assignment.bump,
+        constraint = collections_assignment.agency == collections_agency.key() @ ErrorCode::CollectionsAgencyMismatch,
+    )]
+    pub collections_assignment: Account<'info, CollectionsAssignment>,
+
+    #[account(
+        mut,
+        seeds = [INVOICE_AUDIT_SEED, invoice.key().as_ref()],
+        bump = invoice_audit_log.bump,
+    )]
+    pub invoice_audit_log: Account<'info, InvoiceAuditLog>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(investor: Pubkey)]
+pub struct SetProfessionalAttestation<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = InvestorStats::SIZE,
+        seeds = [INVESTOR_STATS_SEED, investor.as_ref()],
+        bump
+    )]
+    pub investor_stats: Account<'info, InvestorStats>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(investor: Pubkey)]
+pub struct ApproveInvestor<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = InvestorWhitelist::SIZE,
+        seeds = [INVESTOR_WHITELIST_SEED, investor.as_ref()],
+        bump
+    )]
+    pub investor_whitelist: Account<'info, InvestorWhitelist>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeInvestor<'info> {
+    #[account(
+        mut,
+        seeds = [INVESTOR_WHITELIST_SEED, investor_whitelist.investor.as_ref()],
+        bump = investor_whitelist.bump,
+    )]
+    pub investor_whitelist: Account<'info, InvestorWhitelist>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(party: Pubkey)]
+pub struct BlacklistParty<'info> {
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = BlacklistEntry::SIZE,
+        seeds = [BLACKLIST_SEED, party.as_ref()],
+        bump
+    )]
+    pub blacklist_entry: Account<'info, BlacklistEntry>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
+    pub authority: Signer<'info>,
+
+    // Funds new accounts; a governance account holds data and cannot pay rent
     #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnblacklistParty<'info> {
+    #[account(
+        mut,
+        seeds = [BLACKLIST_SEED, blacklist_entry.party.as_ref()],
+        bump = blacklist_entry.bump,
+    )]
+    pub blacklist_entry: Account<'info, BlacklistEntry>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = is_authority(&global_state, authority.as_ref()) @ ErrorCode::Unauthorized,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [ADMIN_LOG_SEED, admin_log.page.to_le_bytes().as_ref()],
+        bump = admin_log.bump,
+    )]
+    pub admin_log: Account<'info, AdminActionLog>,
+
     pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetCollectionsAgencyStats<'info> {
+    pub collections_agency: Account<'info, CollectionsAgency>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: u64)]
+pub struct CreateBundle<'info> {
+    #[account(
+        init,
+        payer = business_owner,
+        space = Bundle::SIZE,
+        seeds = [BUNDLE_SEED, business_owner.key().as_ref(), bundle_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub bundle: Account<'info, Bundle>,
+
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(mut)]
+    pub business_owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Unbundle<'info> {
+    #[account(
+        mut,
+        has_one = business_owner @ ErrorCode::InvoiceOwnerMismatch,
+        close = business_owner,
+    )]
+    pub bundle: Account<'info, Bundle>,
+
+    #[account(mut)]
+    pub business_owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundBundle<'info> {
+    #[account(mut)]
+    pub bundle: Account<'info, Bundle>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        constraint = investor.key() != bundle.business_owner @ ErrorCode::SelfFundingNotAllowed,
+    )]
+    pub investor: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = bundle.business_owner,
+    )]
+    pub business_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [INSURANCE_POOL_SEED],
+        bump = global_state.insurance_pool_bump,
+    )]
+    pub insurance_pool_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = investor,
+        space = InvestorStats::SIZE,
+        seeds = [INVESTOR_STATS_SEED, investor.key().as_ref()],
+        bump
+    )]
+    pub investor_stats: Account<'info, InvestorStats>,
+
+    // Required while GlobalState::require_whitelist is on
+    #[account(
+        seeds = [INVESTOR_WHITELIST_SEED, investor.key().as_ref()],
+        bump = investor_whitelist.bump,
+    )]
+    pub investor_whitelist: Option<Account<'info, InvestorWhitelist>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        mut,
+        seeds = [BUSINESS_PROFILE_SEED, bundle.business_owner.as_ref()],
+        bump = business_profile.bump,
+    )]
+    pub business_profile: Box<Account<'info, BusinessProfile>>,
+
+    /// CHECK: the investor's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, investor.key().as_ref()], bump)]
+    pub investor_blacklist: UncheckedAccount<'info>,
+
+    /// CHECK: the business's blacklist entry; empty unless it was ever blacklisted
+    #[account(seeds = [BLACKLIST_SEED, bundle.business_owner.as_ref()], bump)]
+    pub business_blacklist: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RepayBundle<'info> {
+    #[account(
+        mut,
+        has_one = business_owner @ ErrorCode::InvoiceOwnerMismatch,
+    )]
+    pub bundle: Account<'info, Bundle>,
+
+    #[account(mut)]
+    pub business_owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        constraint = global_state.version == GLOBAL_STATE_VERSION @ ErrorCode::AccountVersionMismatch,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = business_owner,
+    )]
+    pub business_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = global_state.usdc_mint,
+        associated_token::authority = bundle.investor,
+    )]
+    pub investor_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = business_owner,
+        space = BusinessProfile::SIZE,
+        seeds = [BUSINESS_PROFILE_SEED, business_owner.key().as_ref()],
+        bump
+    )]
+    pub business_profile: Account<'info, BusinessProfile>,
+
+    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
+
+    // Exposure booked at funding is released from the investor's stats
+    #[account(
+        mut,
+        seeds = [INVESTOR_STATS_SEED, bundle.investor.as_ref()],
+        bump = investor_stats.bump,
+    )]
+    pub investor_stats: Option<Account<'info, InvestorStats>>,
+}
+
+#[derive(Accounts)]
+pub struct SettleBundle<'info> {
+    #[account(mut)]
+    pub bundle: Account<'info, Bundle>,
+}
+
+// Enhanced data structures
+#[account]
+#[derive(Default)]
+pub struct GlobalState {
+    pub total_invoices: u64,
+    pub total_funded: u64,
+    pub insurance_pool_balance: u64,
+    pub authority: Pubkey,
+    pub usdc_mint: Pubkey,
+    pub bump: u8,
+    pub cluster_id: [u8; 32],
+    pub retention_period_secs: i64,
+
+    // Daily funding cap (0 = unlimited), reset lazily at each UTC midnight
+    pub daily_funding_cap: u64,
+    pub funded_today: u64,
+    pub day_start_ts: i64,
+    pub pending_daily_funding_cap: Option<u64>,
+    pub pending_daily_funding_cap_at: i64,
+
+    // Interest floor for early repayment, in bps of the invoice's yield component
+    pub min_interest_bps: u16,
+
+    // Diversification limits for first-time investors
+    pub retail_guardrails: bool,
+
+    // Invoices marked defaulted and the principal outstanding when they were
+    pub total_defaulted: u64,
+    pub total_defaulted_amount: u64,
+
+    // Protocol-level admin actions recorded so far, across all admin log pages
+    pub admin_action_count: u64,
+
+    // Insurance LP shares outstanding, and coverage the pool owes on live invoices
+    pub total_lp_shares: u64,
+    pub insured_exposure: u64,
+
+    // Defaulted invoices whose insurance claim has not been paid, and the
+    // thresholds get_health grades the protocol against
+    pub pending_claims: u64,
+    pub health_thresholds: HealthThresholds,
+
+    // Economic parameters, tunable through update_config
+    pub config: ProtocolConfig,
+
+    // PAUSE_* bits for activity currently stopped
+    pub paused: u8,
+
+    // Bumps of the insurance pool token account and of the PDA that owns it,
+    // both set by initialize_insurance_pool
+    pub insurance_pool_bump: u8,
+    pub insurance_pool_authority_bump: u8,
+
+    // Parameter-set version in force; bumped by every change to a governed parameter
+    pub param_version: u32,
+
+    // SPL Governance program and realm whose governance account may act as the
+    // authority; once governance_enabled, governed-lane instructions require it
+    pub governance_program: Option<Pubkey>,
+    pub governance_realm: Pubkey,
+    pub governance_enabled: bool,
+    pub pending_governance_enabled: Option<bool>,
+    pub pending_governance_enabled_at: i64,
+
+    // Invoices ever created (never decremented, unlike total_invoices), book
+    // commitments finalized, and when the last one was
+    pub invoices_created: u64,
+    pub book_commitment_count: u64,
+    pub last_book_commit_at: i64,
+
+    // Funding restricted to investors with a live InvestorWhitelist entry
+    pub require_whitelist: bool,
+
+    // Listing restricted to businesses whose profile the authority verified
+    pub require_business_verification: bool,
+
+    // Key whose Ed25519 signature create_invoice accepts on a credit attestation
+    pub oracle_authority: Option<Pubkey>,
+
+    // Weights of the listing-time risk score, tunable through set_risk_params
+    pub risk_params: RiskParams,
+
+    // Most funded principal outstanding against any one debtor (0 = no cap)
+    pub debtor_exposure_cap: u64,
+
+    // Most funded principal outstanding per investor and per business (0 = no cap)
+    pub investor_exposure_cap: u64,
+    pub business_exposure_cap: u64,
+
+    // Stablecoins invoices may be denominated in besides usdc_mint, the primary mint
+    pub approved_mints: Vec<Pubkey>,
+
+    // Seconds a business has to accept an escrowed funding (0 = no escrow)
+    pub funding_acceptance_window: i64,
+
+    // What funding makes of invoices their debtor has not acknowledged
+    pub acknowledgment_policy: AcknowledgmentPolicy,
+
+    // May resolve invoice disputes alongside the authority
+    pub dispute_arbiter: Option<Pubkey>,
+
+    // Businesses prepay the insurance premium at listing instead of the investor at
+    // funding; applies to invoices listed after it is set
+    pub business_pays_premium: bool,
+
+    // Insurance owed on defaulted invoices: coverage not yet claimed plus what
+    // partial payouts fell short by. An underfunded pool pays claims pro rata to it.
+    pub claims_outstanding: u64,
+
+    // Most insured exposure the pool may carry, as a multiple of its balance in
+    // bps: 30_000 lets coverage sold reach three times the pool (0 = no cap)
+    pub coverage_capacity_bps: u32,
+
+    // Most an invoice may promise its originator, in bps of the principal (0 = no
+    // originator fees), and whether it comes out of the principal or on top of
+    // the investor's cost
+    pub originator_fee_cap_bps: u16,
+    pub originator_fee_from_principal: bool,
+
+    // GLOBAL_STATE_VERSION once current. Fields added after it read as zero until
+    // migrate_global_state fills in their defaults.
+    pub version: u8,
+
+    // Invoices repaid in full and everything businesses repaid, late fees
+    // included, premiums the pool took in net of refunds, and insurance it paid
+    // out. Counted from version 2 on; migration starts them at zero.
+    pub total_repaid: u64,
+    pub total_repaid_amount: u64,
+    pub total_late_fees: u64,
+    pub total_premiums: u64,
+    pub total_insurance_payouts: u64,
+
+    // M-of-N approver set guarding the sensitive instructions (see approvals.rs);
+    // empty with a zero threshold leaves them to the authority. Proposals expire
+    // approval_window_secs after they are raised and are void once the set changes,
+    // which bumps approver_set_nonce. From version 3 on.
+    pub admin_approvers: Vec<Pubkey>,
+    pub approval_threshold: u8,
+    pub approval_window_secs: i64,
+    pub approver_set_nonce: u32,
+    pub admin_proposal_count: u64,
+
+    // Least premium an insured invoice is charged, however small its rate makes
+    // it. From version 4 on; migration starts it at the default.
+    pub min_insurance_premium: u64,
+
+    // Most invoices a business may have open at once, from listing until repaid,
+    // defaulted, cancelled, expired or delisted (0 = no cap). From version 5 on;
+    // migration leaves it uncapped.
+    pub max_open_invoices_per_business: u32,
+
+    // The pricing experiment create_experiment last scheduled and its window.
+    // Listings priced inside the window must pass that experiment, so a business
+    // cannot choose whether, or which, experiment prices it. From version 6 on;
+    // migration starts with none.
+    pub pricing_experiment: Option<Pubkey>,
+    pub pricing_experiment_starts_at: i64,
+    pub pricing_experiment_ends_at: i64,
+
+    // What the treasury pays an investor, per day of the acceptance window, when
+    // the business lets an escrowed funding expire, in bps of the principal (0 =
+    // nothing). From version 7 on; migration starts it at zero.
+    pub escrow_compensation_bps_per_day: u16,
+}
+
+impl GlobalState {
+    pub const SIZE: usize = 8 + 8 + 8 + 8 + 32 + 32 + 1 + 32 + 8 + 8 + 8 + 8 + (1 + 8) + 8 + 2 + 1 + 8 + 8 + 8 + 8 + 8
+        + 8 + HealthThresholds::SIZE + ProtocolConfig::SIZE + 1 + 1 + 1 + 4 + (1 + 32) + 32 + 1 + (1 + 1) + 8 + 8 + 8 + 8 + 1 + 1 + (1 + 32) + RiskParams::SIZE + 8 + 8 + 8
+        + (4 + 32 * MAX_APPROVED_MINTS) + 8 + AcknowledgmentPolicy::SIZE + (1 + 32) + 1 + 8 + 4 + 2 + 1 + 1
+        + 8 + 8 + 8 + 8 + 8
+        + (4 + 32 * MAX_ADMIN_APPROVERS) + 1 + 8 + 4 + 8
+        + 8
+        + 4
+        + (1 + 32) + 8 + 8
+        + 2;
+
+    // Current value of a governed parameter
+
+    // Count `amount` of new funding against the investor's and the business's
+    // outstanding exposure, refusing it if either would go over its cap
+
+    // The scheduled pricing experiment, while `current_time` is inside its window
+    pub fn experiment_in_force(&self, current_time: i64) -> Option<Pubkey> {
+        self.pricing_experiment.filter(|_| {
+            current_time >= self.pricing_experiment_starts_at && current_time < self.pricing_experiment_ends_at
+        })
+    }
+
+    pub fn approvers_configured(&self) -> bool {
+        self.approval_threshold > 0
+    }
+
+    pub fn approver_index(&self, key: &Pubkey) -> Option<usize> {
+        self.admin_approvers.iter().position(|approver| approver == key)
+    }
+
+    pub fn is_approved_mint(&self, mint: &Pubkey) -> bool {
+        *mint == self.usdc_mint || self.approved_mints.contains(mint)
+    }
+
+    // Add or drop a secondary mint. Invoices already listed in a dropped mint
+    // still fund and settle in it; only new listings are refused.
+    pub fn set_mint_approval(&mut self, mint: Pubkey, approved: bool) -> Result<()> {
+        require_keys_neq!(mint, self.usdc_mint, ErrorCode::PrimaryMintFixed);
+        match (approved, self.approved_mints.iter().position(|approved| *approved == mint)) {
+            (true, None) => {
+                require!(self.approved_mints.len() < MAX_APPROVED_MINTS, ErrorCode::ApprovedMintsFull);
+                self.approved_mints.push(mint);
+            }
+            (false, Some(index)) => {
+                self.approved_mints.remove(index);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    pub fn book_exposure(&self, investor: &mut InvestorStats, business: &mut BusinessProfile, amount: u64) -> Result<()> {
+        book_outstanding(&mut investor.outstanding, amount, self.investor_exposure_cap, ErrorCode::InvestorExposureExceeded)?;
+        book_outstanding(
+            &mut business.outstanding_financed,
+            amount,
+            self.business_exposure_cap,
+            ErrorCode::BusinessExposureExceeded,
+        )
+    }
+    pub fn param_value(&self, param: ParamId) -> u64 {
+        match param {
+            ParamId::MinInterestBps => self.min_interest_bps as u64,
+            ParamId::DailyFundingCap => self.daily_funding_cap,
+            ParamId::EscrowCompensationBpsPerDay => self.escrow_compensation_bps_per_day as u64,
+            _ => self
+                .config
+                .param_values()
+                .iter()
+                .find(|(id, _)| *id == param)
+                .map_or(0, |(_, value)| *value),
+        }
+    }
+
+    pub fn require_not_paused(&self, flag: u8) -> Result<()> {
+        require!(self.paused & flag == 0, ErrorCode::ProtocolPaused);
+        Ok(())
+    }
+
+    // Whether a listing from this business is refused for want of verification
+    pub fn business_unverified(&self, profile: Option<&BusinessProfile>) -> bool {
+        self.require_business_verification && !matches!(profile, Some(profile) if profile.verified)
+    }
+
+    // Whether a listing from this business is refused for the invoices it already has open
+    pub fn open_invoice_limit_reached(&self, profile: Option<&BusinessProfile>) -> bool {
+        let cap = self.max_open_invoices_per_business;
+        cap != 0 && matches!(profile, Some(profile) if profile.open_invoices >= cap)
+    }
+
+    // Reject an invoice its debtor has not acknowledged, when the policy requires it
+    pub fn require_acknowledged(&self, invoice: &Invoice) -> Result<()> {
+        if self.acknowledgment_policy == AcknowledgmentPolicy::Required {
+            require!(invoice.debtor_acknowledged, ErrorCode::DebtorAcknowledgmentRequired);
+        }
+        Ok(())
+    }
+
+    // Reject an investor without a live whitelist entry, when the whitelist is on
+    pub fn require_whitelisted(&self, entry: Option<&InvestorWhitelist>, current_time: i64) -> Result<()> {
+        if self.require_whitelist {
+            require!(
+                matches!(entry, Some(entry) if entry.is_valid(current_time)),
+                ErrorCode::InvestorNotWhitelisted
+            );
+        }
+        Ok(())
+    }
+
+    // Insured exposure against the pool balance, in bps
+    pub fn coverage_utilization_bps(&self) -> u64 {
+        if self.insurance_pool_balance == 0 {
+            return if self.insured_exposure == 0 { 0 } else { u64::MAX };
+        }
+        (self.insured_exposure as u128 * 10_000 / self.insurance_pool_balance as u128).min(u64::MAX as u128) as u64
+    }
+
+    // Reject a funding that left the pool insuring more than coverage_capacity_bps
+    // of its balance
+    pub fn require_coverage_capacity(&self) -> Result<()> {
+        if self.coverage_capacity_bps == 0 {
+            return Ok(());
+        }
+        let capacity = self.insurance_pool_balance as u128 * self.coverage_capacity_bps as u128 / 10_000;
+        require!(self.insured_exposure as u128 <= capacity, ErrorCode::CoverageCapacityExceeded);
+        Ok(())
+    }
+
+    // A premium reached the insurance pool
+    pub fn collect_premium(&mut self, premium: u64) -> Result<()> {
+        self.insurance_pool_balance = self.insurance_pool_balance.checked_add(premium).ok_or(ErrorCode::MathOverflow)?;
+        self.total_premiums = self.total_premiums.checked_add(premium).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // A premium went back out of the pool to whoever paid it
+    pub fn refund_premium(&mut self, premium: u64) -> Result<()> {
+        self.insurance_pool_balance = self.insurance_pool_balance.checked_sub(premium).ok_or(ErrorCode::MathOverflow)?;
+        self.total_premiums = self.total_premiums.saturating_sub(premium);
+        Ok(())
+    }
+
+    // The pool paid out `payout` on an insurance claim
+    pub fn pay_insurance(&mut self, payout: u64) -> Result<()> {
+        self.insurance_pool_balance = self.insurance_pool_balance.checked_sub(payout).ok_or(ErrorCode::MathOverflow)?;
+        self.total_insurance_payouts =
+            self.total_insurance_payouts.checked_add(payout).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // A business repaid `amount` on an invoice, `late_fee` of it in late fees;
+    // `settled` once that paid the invoice off
+    pub fn record_repayment(&mut self, amount: u64, late_fee: u64, settled: bool) -> Result<()> {
+        self.total_repaid_amount = self.total_repaid_amount.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        self.total_late_fees = self.total_late_fees.checked_add(late_fee).ok_or(ErrorCode::MathOverflow)?;
+        if settled {
+            self.total_repaid = self.total_repaid.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    // Snapshot for get_global_stats, against the pool's actual token balance
+    pub fn stats(&self, pool_token_balance: Option<u64>, current_time: i64) -> GlobalStats {
+        let settled = self.total_repaid.saturating_add(self.total_defaulted);
+        GlobalStats {
+            invoices_created: self.invoices_created,
+            total_invoices: self.total_invoices,
+            total_funded: self.total_funded,
+            total_repaid: self.total_repaid,
+            total_repaid_amount: self.total_repaid_amount,
+            total_defaulted: self.total_defaulted,
+            total_defaulted_amount: self.total_defaulted_amount,
+            default_rate_bps: if settled == 0 { 0 } else { (self.total_defaulted as u128 * 10_000 / settled as u128) as u64 },
+            total_late_fees: self.total_late_fees,
+            total_premiums: self.total_premiums,
+            total_insurance_payouts: self.total_insurance_payouts,
+            insurance_pool_balance: self.insurance_pool_balance,
+            pool_token_balance,
+            pool_balance_drift: pool_token_balance
+                .map(|balance| (balance as i128 - self.insurance_pool_balance as i128) as i64),
+            insured_exposure: self.insured_exposure,
+            claims_outstanding: self.claims_outstanding,
+            coverage_utilization_bps: self.coverage_utilization_bps(),
+            remaining_daily_capacity: self.remaining_daily_capacity(current_time),
+        }
+    }
+
+    // Pool balance beyond the coverage it owes on live invoices
+    pub fn pool_surplus(&self) -> u64 {
+        self.insurance_pool_balance.saturating_sub(self.insured_exposure)
+    }
+
+    // Funding still accepted today under the daily cap
+    pub fn remaining_daily_capacity(&self, current_time: i64) -> u64 {
+        if self.daily_funding_cap == 0 {
+            return u64::MAX;
+        }
+        if self.day_start_ts != utc_day_start(current_time) {
+            return self.daily_funding_cap;
+        }
+        self.daily_funding_cap.saturating_sub(self.funded_today)
+    }
+
+    pub fn record_daily_funding(&mut self, amount: u64, current_time: i64) -> Result<()> {
+        require!(
+            amount <= self.remaining_daily_capacity(current_time),
+            ErrorCode::DailyCapReached
+        );
+
+        let day_start = utc_day_start(current_time);
+        if self.day_start_ts != day_start {
+            self.day_start_ts = day_start;
+            self.funded_today = 0;
+        }
+        self.funded_today += amount;
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Invoice {
+    pub invoice_id: u64,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub funded_amount: u64,
+    pub due_date: i64,
+    // INVOICE_VERSION once current. The unversioned layout kept the length of a
+    // free-text debtor_info here, 8 to 200, so versions stay outside that.
+    pub version: u8,
+    // At INVOICE_STATUS_OFFSET; versions before 3 kept it after debtor_info_uri
+    pub status: InvoiceStatus,
+    // sha256 of the canonical invoice document, and where an encrypted copy of it
+    // is kept; the document itself never goes on-chain
+    pub debtor_info_hash: [u8; 32],
+    pub debtor_info_uri: Option<String>,
+    pub risk_score: u8,
+    pub insurance_premium: u64,
+    pub created_at: i64,
+    pub funding_date: Option<i64>,
+    pub repayment_date: Option<i64>,
+    pub expected_return: Option<u64>,
+    pub final_repayment_amount: Option<u64>,
+    pub late_fee: Option<u64>,
+    pub insurance_claim_date: Option<i64>,
+    pub insurance_payout: Option<u64>,
+    pub bump: u8,
+    
+    // Enhanced risk factors
+    pub industry_risk: u8,
+    pub credit_score: u16,
+    pub payment_terms_days: u16,
+
+    // Pricing experiment assignment
+    pub experiment: Option<ExperimentAssignment>,
+
+    // Principal is paid out through the fiat processor outbox
+    pub offramp_requested: bool,
+
+    // Personal data erasure
+    pub erased: bool,
+    pub erased_content_hash: [u8; 32],
+
+    // Multi-investor funding through FundingShare accounts and the invoice vault
+    pub partial_funding: bool,
+    pub contributor_count: u16,
+    pub distributable_amount: u64,
+
+    // Created through the flat-priced micro tier
+    pub micro_tier: bool,
+
+    // Partial repayments: principal still owed and late fees accrued but unpaid
+    pub remaining_balance: u64,
+    pub amount_repaid: u64,
+    pub accrued_late_fee: u64,
+    pub late_fee_days_accrued: u16,
+
+    // Interest (yield prorated by days outstanding) accrued but unpaid, and paid so far
+    pub accrued_interest: u64,
+    pub interest_paid: u64,
+
+    // Recoveries after default returned to the insurance pool
+    pub pool_recovered: u64,
+
+    // When mark_defaulted moved the invoice to Defaulted
+    pub defaulted_at: Option<i64>,
+
+    // Due date before the (single) agreed extension
+    pub original_due_date: Option<i64>,
+
+    // This invoice's share of GlobalState::insured_exposure
+    pub committed_coverage: u64,
+
+    // pricing::PRICING_VERSION the premium and yield were computed under
+    pub pricing_version: u8,
+
+    // Bundle the invoice is locked in, if any
+    pub bundle: Option<Pubkey>,
+
+    // Coverage tier the invoice was priced under, in percent of unpaid principal
+    pub coverage_percentage: u8,
+
+    // Hash of the listing terms the business last published (listing::ListingTerms::hash)
+    pub published_listing_hash: Option<[u8; 32]>,
+
+    // Parameter-set versions in force at creation, funding and settlement
+    pub param_versions: ParamVersions,
+
+    // When the principal reached the business. funding_date is when the investor's
+    // capital left their wallet, possibly into escrow; yield accrues from then.
+    pub released_at: Option<i64>,
+
+    // Why review took the listing down, when status is Delisted
+    pub rejection_reason: Option<RejectionReason>,
+
+    // Mint of the position receipt issued at funding, and whether it has been
+    // burned to settle the position
+    pub receipt_mint: Option<Pubkey>,
+    pub receipt_redeemed: bool,
+
+    // Position in the protocol-wide creation order, from 1; 0 for invoices
+    // created before the book was numbered
+    pub book_nonce: u64,
+
+    // Industry the business declared at listing; priced through RiskParams
+    pub industry: IndustryCode,
+
+    // Debtor registry entry the invoice's exposure is booked against; default for
+    // invoices listed before the registry
+    pub debtor: Pubkey,
+
+    // Funding counted against the investor's and business's exposure caps
+    pub exposure_booked: bool,
+
+    // Stablecoin the invoice is denominated, funded and repaid in
+    pub mint: Pubkey,
+
+    // Funding held in the invoice vault until the business accepts it
+    pub escrow: Option<FundingEscrow>,
+
+    // Whether the debtor confirmed the invoice through acknowledge_invoice, and the
+    // wallet that may do so; none means the invoice can never be acknowledged
+    pub debtor_acknowledged: bool,
+    pub debtor_wallet: Option<Pubkey>,
+    pub debtor_acknowledged_at: Option<i64>,
+    // Risk points listing added for the missing acknowledgment
+    pub acknowledgment_penalty: u8,
+
+    // Latest dispute over the underlying invoice; active until resolved_at is set
+    pub dispute: Option<Dispute>,
+
+    // Everything recovered after default that went to the pool or the investor,
+    // through collections or the business's own settlements
+    pub recovered_amount: u64,
+
+    // Repayments the receipt holder left in the vault past
+    // UNCLAIMED_REPAYMENT_SWEEP_SECS, moved to the insurance pool
+    pub unclaimed_swept: u64,
+
+    // The business paid the premium into the pool at listing; funding moves the
+    // principal alone, and cancelling refunds it
+    pub premium_prepaid: bool,
+
+    // Last moment the invoice can be funded; expire_invoice retires it after this
+    pub funding_deadline: i64,
+
+    // Coverage an underfunded pool could not pay at the claim, still owed through
+    // claim_remaining_insurance
+    pub insurance_payout_outstanding: u64,
+
+    // This invoice's share of GlobalState::claims_outstanding
+    pub booked_claim: u64,
+
+    // Platform that listed the invoice for the business, and its cut of the
+    // principal; from_principal is GlobalState's policy at listing
+    pub originator: Option<Pubkey>,
+    pub originator_fee_bps: u16,
+    pub originator_fee_from_principal: bool,
+    pub originator_fee_paid: u64,
+
+    // Funding counted in the investor's InvestorPortfolio, which then follows the
+    // position until it settles or is sold
+    pub portfolio_booked: bool,
+
+    // Investor APR the invoice was priced at; funding fixes expected_return from it
+    // and the term left. Zero for invoices priced under a flat yield.
+    pub apr_bps: u16,
+
+    // Wallet that paid the invoice account's rent at creation, refunded when the
+    // invoice is cancelled or closed
+    pub rent_payer: Pubkey,
+
+    // Last time ping_overdue signalled the invoice past due
+    pub last_overdue_ping: Option<i64>,
+
+    // Rate insurance_premium was priced at, in bps of face value; the premium is
+    // more when the protocol minimum applied. 0 when uninsured or priced before
+    // version 7.
+    pub premium_bps: u16,
+
+    // Factoring: the investor advances advance_rate_bps of the face value, the
+    // advance_amount, and collects the whole face value at maturity. Both 0 for
+    // invoices funded at par.
+    pub advance_rate_bps: u16,
+    pub advance_amount: u64,
+}
+
+impl Invoice {
+    pub const SIZE: usize = 8 + 8 + 32 + 32 + 8 + 8 + 8 + 1 + 1 + 32 + (1 + 4 + MAX_DEBTOR_INFO_URI_LEN as usize) + 1 + 8 + 8 + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + 1 + 1 + 2 + 2 + (1 + ExperimentAssignment::SIZE) + 1 + 1 + 32 + 1 + 2 + 8 + 1 + 8 + 8 + 8 + 2 + 8 + 8 + 8 + (1 + 8) + (1 + 8) + 8 + 1 + (1 + 32) + 1 + (1 + 32) + ParamVersions::SIZE + (1 + 8) + (1 + 1) + (1 + 32) + 1 + 8 + 1 + 32 + 1 + 32 + (1 + FundingEscrow::SIZE) + 1 + (1 + 32) + (1 + 8) + 1 + (1 + Dispute::SIZE) + 8 + 8 + 1 + 8 + 8 + 8 + (1 + 32) + 2 + 1 + 8 + 1 + 2 + 32 + (1 + 8) + 2 + 2 + 8; // ~1066 bytes
+}
+
+impl Invoice {
+    // The debtor entry this invoice's exposure is booked against. Invoices listed
+    // against a debtor must bring it (the account constraint checks it is theirs);
+    // invoices from before the registry have none.
+    pub fn booked_debtor<'a>(&self, debtor: Option<&'a mut Debtor>) -> Result<Option<&'a mut Debtor>> {
+        if self.debtor == Pubkey::default() {
+            return Ok(None);
+        }
+        debtor.map(Some).ok_or(error!(ErrorCode::DebtorAccountMissing))
+    }
+
+    // Count the funding in the investor's portfolio, which must then come along
+    // with every settlement of the position
+    pub fn book_portfolio(&mut self, portfolio: &mut InvestorPortfolio, premium: u64) -> Result<()> {
+        portfolio.record_funding(self.funded_amount, premium)?;
+        self.portfolio_booked = true;
+        Ok(())
+    }
+
+    // The recorded investor's portfolio, if the position is booked in it (the
+    // account constraint checks it is theirs)
+    pub fn booked_portfolio<'a>(
+        &self,
+        portfolio: Option<&'a mut InvestorPortfolio>,
+    ) -> Result<Option<&'a mut InvestorPortfolio>> {
+        if !self.portfolio_booked {
+            return Ok(None);
+        }
+        portfolio.map(Some).ok_or(error!(ErrorCode::PortfolioMissing))
+    }
+
+    // Funding deadline for a listing of `due_date` made at `created_at`. Invoices
+    // due within the margin stay fundable until they are due.
+    pub fn default_funding_deadline(created_at: i64, due_date: i64) -> i64 {
+        let before_due = due_date - FUNDING_DEADLINE_MARGIN_SECS;
+        let closes_at = if before_due > created_at { before_due } else { due_date };
+        closes_at.min(created_at.saturating_add(DEFAULT_FUNDING_WINDOW_SECS))
+    }
+
+    // Invoices listed before funding deadlines close at their due date
+    pub fn funding_closes_at(&self) -> i64 {
+        if self.funding_deadline == 0 {
+            self.due_date
+        } else {
+            self.funding_deadline
+        }
+    }
+
+    // Funding is accepted up to and including the deadline
+    pub fn funding_open(&self, current_time: i64) -> bool {
+        current_time <= self.funding_closes_at()
+    }
+
+    // Whether a day has passed since the last overdue ping, or there was none
+    pub fn overdue_ping_due(&self, current_time: i64) -> bool {
+        match self.last_overdue_ping {
+            Some(pinged_at) => current_time >= pinged_at + OVERDUE_PING_INTERVAL_SECS,
+            None => true,
+        }
+    }
+
+    // Move the invoice to `to`; every status change after listing goes through here
+    pub fn transition(&mut self, to: InvoiceStatus) -> Result<()> {
+        if !self.status.can_transition_to(to) {
+            msg!("Invoice {} cannot go from {:?} to {:?}", self.invoice_id, self.status, to);
+            return err!(ErrorCode::IllegalStatusTransition);
+        }
+        self.status = to;
+        Ok(())
+    }
+
+    // What a payment that leaves a balance moves the invoice to: a disputed
+    // invoice stays disputed until the dispute is resolved
+    fn partially_repaid_status(&self) -> InvoiceStatus {
+        if self.status == InvoiceStatus::Disputed {
+            InvoiceStatus::Disputed
+        } else {
+            InvoiceStatus::PartiallyRepaid
+        }
+    }
+
+    pub fn dispute_active(&self) -> bool {
+        matches!(&self.dispute, Some(dispute) if dispute.resolved_at.is_none())
+    }
+
+    // Record the debtor's acknowledgment and reprice without the risk points the
+    // listing carried for its absence. Uninsured invoices stay without premium
+    // and coverage, and a prepaid premium is not repriced.
+    // Take on the risk and price quoted for the invoice's amount and due date.
+    // Off the primary mint it is uninsured, so pays no premium.
+    pub fn apply_quote(&mut self, quote: &ListingQuote, insured: bool, current_time: i64) -> Result<()> {
+        let risk = quote.risk_assessment;
+        self.risk_score = risk.risk_score;
+        self.industry_risk = risk.industry_risk;
+        self.credit_score = risk.estimated_credit_score;
+        self.acknowledgment_penalty = quote.acknowledgment_penalty;
+        self.insurance_premium = if insured { quote.pricing.insurance_premium } else { 0 };
+        self.premium_bps = if insured { quote.pricing.premium_bps } else { 0 };
+        self.coverage_percentage = if insured { quote.pricing.coverage_percentage as u8 } else { 0 };
+        self.apply_return(&quote.pricing)?;
+        self.pricing_version = quote.pricing.version;
+        self.payment_terms_days = ((self.due_date - current_time) / 86400) as u16;
+        self.micro_tier = quote.micro_tier;
+        Ok(())
+    }
+
+    pub fn record_acknowledgment(
+        &mut self,
+        coverage: CoverageTiers,
+        min_premium: u64,
+        insured: bool,
+        acknowledged_at: i64,
+    ) -> Result<()> {
+        self.debtor_acknowledged = true;
+        self.debtor_acknowledged_at = Some(acknowledged_at);
+        if self.acknowledgment_penalty == 0 {
+            return Ok(());
+        }
+
+        let risk = RiskAssessment {
+            risk_score: self.risk_score.saturating_sub(self.acknowledgment_penalty),
+            ..self.risk_assessment()
+        };
+        // A funded invoice keeps the term its yield was fixed over
+        let term = term_days(self.funding_date.unwrap_or(acknowledged_at), self.due_date);
+        let inputs = PricingInputs::new(self.principal(), risk, PremiumSchedule::RiskScaled, coverage, term);
+        let pricing = price_invoice(&inputs.with_min_premium(min_premium))?;
+        self.risk_score = risk.risk_score;
+        self.apply_return(&pricing)?;
+        self.pricing_version = pricing.version;
+        // A premium the business already paid stands, and the coverage it bought
+        if insured && !self.premium_prepaid {
+            self.insurance_premium = pricing.insurance_premium;
+            self.premium_bps = pricing.premium_bps;
+            self.coverage_percentage = pricing.coverage_percentage as u8;
+        }
+        self.acknowledgment_penalty = 0;
+        Ok(())
+    }
+
+    // Take repaid or written-off principal off the exposure booked at funding.
+    // Invoices funded before the caps, or through partial funding, booked none.
+    pub fn release_exposure(
+        &self,
+        investor: Option<&mut InvestorStats>,
+        business: &mut BusinessProfile,
+        principal: u64,
+    ) -> Result<()> {
+        if !self.exposure_booked {
+            return Ok(());
+        }
+        let investor = investor.ok_or(ErrorCode::InvestorStatsMissing)?;
+        investor.outstanding = investor.outstanding.saturating_sub(principal);
+        business.outstanding_financed = business.outstanding_financed.saturating_sub(principal);
+        Ok(())
+    }
+
+    // Risk factors recorded when the invoice was priced
+    pub fn risk_assessment(&self) -> RiskAssessment {
+        RiskAssessment {
+            risk_score: self.risk_score,
+            industry_risk: self.industry_risk,
+            estimated_credit_score: self.credit_score,
+        }
+    }
+
+    // Whether the insurance pool may still have to pay out on this invoice
+    pub fn insurance_claimable(&self) -> bool {
+        match self.status {
+            InvoiceStatus::Funded | InvoiceStatus::PartiallyRepaid | InvoiceStatus::Disputed => true,
+            InvoiceStatus::Defaulted => self.insurance_payout.is_none() || self.insurance_payout_outstanding > 0,
+            _ => false,
+        }
+    }
+
+    // Receipt still standing for this position, if it was funded with one
+    pub fn live_receipt(&self) -> Option<Pubkey> {
+        self.receipt_mint.filter(|_| !self.receipt_redeemed)
+    }
+
+    // Whether the vault holds repayments its receipt holder has left unredeemed
+    // for UNCLAIMED_REPAYMENT_SWEEP_SECS since the invoice was repaid
+    pub fn unclaimed_sweep_open(&self, current_time: i64) -> bool {
+        matches!(self.status, InvoiceStatus::Repaid | InvoiceStatus::RepaidAfterDefault)
+            && !self.partial_funding
+            && self.live_receipt().is_some()
+            && self.distributable_amount > 0
+            && matches!(self.repayment_date, Some(repaid_at) if current_time - repaid_at >= UNCLAIMED_REPAYMENT_SWEEP_SECS)
+    }
+
+    // Whether `holder` holds the position: by presenting its receipt, or for
+    // positions funded without one, by being the recorded investor
+    pub fn is_position_holder(&self, holder: &Pubkey, receipt: Option<&TokenAccount>) -> bool {
+        match self.live_receipt() {
+            Some(mint) => matches!(receipt, Some(receipt) if holds_receipt(receipt, &mint, holder)),
+            None => *holder == self.investor,
+        }
+    }
+
+    // Whether the investor can sell the position on: still live, and held by a single
+    // investor rather than through funding shares or a bundle
+    pub fn position_transferable(&self) -> bool {
+        self.status.is_repayable()
+            && !self.partial_funding
+            && self.bundle.is_none()
+    }
+
+    // How the invoice is funded: at par, or as a factoring advance on its face value
+    pub fn funding_mode(&self) -> FundingMode {
+        if self.advance_rate_bps == 0 { FundingMode::Par } else { FundingMode::Factoring }
+    }
+
+    // What the investor puts up at funding: the face value at par, the advance
+    // when factored. Premium, coverage and exposure are all on this amount.
+    pub fn principal(&self) -> u64 {
+        match self.funding_mode() {
+            FundingMode::Par => self.amount,
+            FundingMode::Factoring => self.advance_amount,
+        }
+    }
+
+    // Fund the invoice as a factoring advance of `advance_rate_bps` of its face
+    // value, or at par with none. Rounds the advance down.
+    pub fn set_advance_rate(&mut self, advance_rate_bps: Option<u16>) -> Result<()> {
+        let Some(rate) = advance_rate_bps else {
+            self.advance_rate_bps = 0;
+            self.advance_amount = 0;
+            return Ok(());
+        };
+        require!(
+            (MIN_ADVANCE_RATE_BPS..=MAX_ADVANCE_RATE_BPS).contains(&rate),
+            ErrorCode::InvalidAdvanceRate
+        );
+        self.advance_rate_bps = rate;
+        self.advance_amount = pro_rata(self.amount, rate as u64, 10_000);
+        Ok(())
+    }
+
+    // Expected return and APR under `pricing`. A factored invoice returns its face
+    // value, so the discount on the advance is the investor's yield, and has no APR.
+    fn apply_return(&mut self, pricing: &pricing::Pricing) -> Result<()> {
+        match self.funding_mode() {
+            FundingMode::Par => {
+                self.expected_return = Some(pricing.expected_return(self.amount)?);
+                self.apr_bps = pricing.estimated_yield_bps;
+            }
+            FundingMode::Factoring => {
+                self.expected_return = Some(self.amount);
+                self.apr_bps = 0;
+            }
+        }
+        Ok(())
+    }
+
+    // Premium the investor pays on top of the principal at funding
+    pub fn investor_premium(&self) -> u64 {
+        if self.premium_prepaid { 0 } else { self.insurance_premium }
+    }
+
+    // The originator's cut of the principal; none without an originator
+    pub fn originator_fee(&self) -> u64 {
+        match self.originator {
+            Some(_) => pro_rata(self.principal(), self.originator_fee_bps as u64, 10_000),
+            None => 0,
+        }
+    }
+
+    // Originator fee the investor pays on top of the principal at funding
+    pub fn investor_originator_fee(&self) -> u64 {
+        if self.originator_fee_from_principal { 0 } else { self.originator_fee() }
+    }
+
+    // What funding pays the business: the principal, less the originator fee when
+    // it comes out of it
+    pub fn business_proceeds(&self) -> u64 {
+        if self.originator_fee_from_principal { self.principal() - self.originator_fee() } else { self.principal() }
+    }
+
+    // What a claim would pay out right now
+    pub fn insured_coverage(&self) -> u64 {
+        pro_rata(self.remaining_balance, self.coverage_percentage as u64, 100)
+    }
+
+    // What the pool stands to pay on this invoice: its insured coverage until a
+    // claim, then whatever the claim fell short by
+    pub fn coverage_owed(&self) -> u64 {
+        if !self.insurance_claimable() {
+            return 0;
+        }
+        match self.insurance_payout {
+            Some(_) => self.insurance_payout_outstanding,
+            None => self.insured_coverage(),
+        }
+    }
+
+    // When the invoice was repaid, directly or through recoveries after default, if
+    // it was: the states close_invoice accepts
+    pub fn closable_at(&self) -> Option<i64> {
+        match self.status {
+            InvoiceStatus::Repaid | InvoiceStatus::RepaidAfterDefault => self.repayment_date,
+            _ => None,
+        }
+    }
+
+    // When the invoice reached a terminal state, if it has
+    pub fn settled_at(&self) -> Option<i64> {
+        match self.status {
+            InvoiceStatus::Repaid | InvoiceStatus::RepaidAfterDefault => self.repayment_date,
+            InvoiceStatus::Defaulted => self.defaulted_at.or(self.insurance_claim_date),
+            _ => None,
+        }
+    }
+
+    // Principal plus unpaid interest and late fees accrued so far
+    pub fn outstanding_balance(&self) -> Result<u64> {
+        owed_balance(self.remaining_balance, self.accrued_interest, self.accrued_late_fee)
+    }
+
+    // Fix the yield at funding: the invoice's APR on its face value over the days
+    // left from `funded_at` to the due date, floored. Invoices priced under the
+    // flat yield, or factored at a discount, keep the one they were listed with.
+    pub fn fix_term_yield(&mut self, funded_at: i64) -> Result<()> {
+        if self.pricing_version < pricing::APR_PRICING_VERSION || self.funding_mode() == FundingMode::Factoring {
+            return Ok(());
+        }
+        let term = term_days(funded_at, self.due_date);
+        let yield_amount = term_yield(self.amount, self.apr_bps, term, Rounding::Floor)?;
+        self.expected_return = Some(self.amount.checked_add(yield_amount).ok_or(ErrorCode::MathOverflow)?);
+        Ok(())
+    }
+
+    // Yield the investor earns if the invoice runs to its due date
+    pub fn yield_component(&self) -> u64 {
+        self.expected_return.map_or(0, |expected| expected.saturating_sub(self.funded_amount))
+    }
+
+    // Interest owed when settling at `current_time`: the yield component prorated by
+    // days outstanding over the funded term, never below the floor. Settling on or
+    // after the due date owes the full yield component. Both the term and the days
+    // outstanding run from funding_date, so capital waiting in escrow earns from the
+    // day it was committed, not from released_at. A factored invoice always owes
+    // its face value, so the whole discount whenever it is settled.
+    pub fn interest_due(&self, current_time: i64, min_interest_bps: u16) -> u64 {
+        let full = self.yield_component();
+        if self.funding_mode() == FundingMode::Factoring {
+            return full;
+        }
+        let funded_at = self.funding_date.unwrap_or(self.created_at);
+        let term = term_days(funded_at, self.due_date);
+        let days_outstanding = term_days(funded_at, current_time).min(term);
+        let prorated = if term == 0 { full } else { pro_rata(full, days_outstanding, term) };
+        let floor = full * min_interest_bps as u64 / 10_000;
+        prorated.max(floor)
+    }
+
+    // Fold interest and late fees for any new full days overdue into the accrued
+    // balances, returning the days overdue and the outstanding balance they leave.
+    // Fails with MathOverflow rather than leave a balance that does not fit in a u64.
+    pub fn accrue_charges(&mut self, current_time: i64, min_interest_bps: u16, late_fee_bps_per_day: u16) -> Result<Accrual> {
+        let (late_fee, days_overdue) = calculate_late_fee(self, current_time, late_fee_bps_per_day)?;
+        let accrued_interest = self
+            .interest_due(current_time, min_interest_bps)
+            .saturating_sub(self.interest_paid);
+        let outstanding = owed_balance(self.remaining_balance, accrued_interest, late_fee)?;
+
+        self.accrued_late_fee = late_fee;
+        self.late_fee_days_accrued = self.late_fee_days_accrued.max(days_overdue as u16);
+        self.accrued_interest = accrued_interest;
+        Ok(Accrual { days_overdue, outstanding })
+    }
+
+    // Apply a payment to accrued late fees first, then interest, then principal.
+    // Callers cap the amount at outstanding_balance().
+    pub fn apply_repayment(&mut self, amount: u64) -> Result<RepaymentSplit> {
+        let late_fee = amount.min(self.accrued_late_fee);
+        let interest = (amount - late_fee).min(self.accrued_interest);
+        let principal = amount - late_fee - interest;
+        self.remaining_balance = self.remaining_balance.checked_sub(principal).ok_or(ErrorCode::MathOverflow)?;
+        self.interest_paid = self.interest_paid.checked_add(interest).ok_or(ErrorCode::MathOverflow)?;
+        self.amount_repaid = self.amount_repaid.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        self.accrued_late_fee -= late_fee;
+        self.accrued_interest -= interest;
+        Ok(RepaymentSplit { late_fee, interest, principal })
+    }
+
+    // What a recovery still owes the investor: the principal the insurance payout
+    // left uncovered, plus the contractual yield not yet paid
+    pub fn recovery_investor_outstanding(&self) -> u64 {
+        let uncovered = self.remaining_balance.saturating_sub(self.insurance_payout.unwrap_or(0));
+        uncovered + self.yield_component().saturating_sub(self.interest_paid)
+    }
+
+    // Apply money recovered after default through recovery_waterfall, `fee_bps` to
+    // an active collections agency. Before a claim nothing is owed to the pool;
+    // the investor's share pays the uncovered principal before the yield. Once the
+    // pool has its payout back and the investor both, the invoice is
+    // RepaidAfterDefault.
+    pub fn apply_settlement(&mut self, amount: u64, fee_bps: u16, current_time: i64) -> Result<RecoverySplit> {
+        let insurance_payout = self.insurance_payout.unwrap_or(0);
+        let split = recovery_waterfall(
+            amount,
+            fee_bps,
+            insurance_payout.saturating_sub(self.pool_recovered),
+            self.recovery_investor_outstanding(),
+        )?;
+        self.pool_recovered += split.pool;
+        let principal = split.investor.min(self.remaining_balance.saturating_sub(insurance_payout));
+        self.remaining_balance -= principal;
+        self.interest_paid = self
+            .interest_paid
+            .checked_add(split.investor - principal)
+            .ok_or(ErrorCode::MathOverflow)?;
+        // A shortfall is never owed beyond the principal the payout left unpaid
+        self.insurance_payout_outstanding =
+            self.insurance_payout_outstanding.min(self.remaining_balance.saturating_sub(insurance_payout));
+        self.recovered_amount = self
+            .recovered_amount
+            .checked_add(split.pool + split.investor)
+            .ok_or(ErrorCode::MathOverflow)?;
+        if self.pool_recovered >= insurance_payout && self.recovery_investor_outstanding() == 0 {
+            self.transition(InvoiceStatus::RepaidAfterDefault)?;
+            self.repayment_date = Some(current_time);
+        }
+        Ok(split)
+    }
+
+    // Late fee collected over the invoice's life once it is fully repaid: everything
+    // paid beyond principal and interest
+    pub fn settled_late_fee(&self) -> Result<u64> {
+        self.amount_repaid
+            .checked_sub(self.funded_amount)
+            .and_then(|paid| paid.checked_sub(self.interest_paid))
+            .ok_or(error!(ErrorCode::MathOverflow))
+    }
+
+    // Drop the pointer to the invoice document, keeping the hash it was committed by
+    pub fn erase_personal_data(&mut self) -> [u8; 32] {
+        self.debtor_info_uri = None;
+        self.erased_content_hash = self.debtor_info_hash;
+        self.erased = true;
+        self.debtor_info_hash
+    }
+
+    // Whether `document` is the one the invoice committed to at listing
+    pub fn debtor_info_matches(&self, document: &[u8]) -> bool {
+        anchor_lang::solana_program::hash::hash(document).to_bytes() == self.debtor_info_hash
+    }
+}
+
+// Flat pricing for small invoices: no risk model lookups, fixed score and premium
+#[account]
+pub struct MicroTierConfig {
+    pub max_amount: u64,
+    pub risk_score: u8,
+    pub premium_bps: u16,
+    pub bump: u8,
+}
+
+impl MicroTierConfig {
+    pub const SIZE: usize = 8 + 8 + 1 + 2 + 1;
+
+    pub fn applies(&self, amount: u64) -> bool {
+        amount <= self.max_amount
+    }
+
+    pub fn schedule(&self) -> PremiumSchedule {
+        PremiumSchedule::Flat { premium_bps: self.premium_bps }
+    }
+
+    pub fn risk_assessment(&self) -> RiskAssessment {
+        RiskAssessment {
+            risk_score: self.risk_score,
+            industry_risk: 0,
+            estimated_credit_score: 0,
+        }
+    }
+}
+
+// Per-business track record; its recent defaults and late repayments raise the
+// risk score of the business's new listings, and its lifetime repayment record
+// moves it either way
+#[account]
+#[derive(Default)]
+pub struct BusinessProfile {
+    pub business_owner: Pubkey,
+    pub history: ReputationHistory,
+    // Invoices this business has created; ids run from 1
+    pub invoices_created: u64,
+    pub bump: u8,
+
+    // KYC: what the business registered (ISO 3166-1 alpha-2 jurisdiction, NAICS
+    // industry code, hash of the documents submitted off-chain) and whether the
+    // authority verified it. The credit score is attested at verification.
+    pub verified: bool,
+    pub verified_at: Option<i64>,
+    pub jurisdiction: [u8; 2],
+    pub industry_code: u32,
+    pub document_hash: [u8; 32],
+    pub credit_score: u16,
+
+    // Updated by the program on every repayment and default, never by the business
+    pub credit_history: CreditHistory,
+
+    // Nonce of the latest oracle credit attestation the business listed with
+    pub credit_attestation_nonce: u64,
+
+    // Financed principal not yet repaid or defaulted, held under business_exposure_cap
+    pub outstanding_financed: u64,
+
+    // Invoices funded, each counted once however it was funded, and the principal
+    // they raised. An escrowed funding the investor reclaims comes off again.
+    pub invoices_funded: u64,
+    pub volume_funded: u64,
+
+    // Invoices listed and not yet repaid, defaulted, cancelled, expired or
+    // delisted, held under max_open_invoices_per_business
+    pub open_invoices: u32,
+}
+
+impl BusinessProfile {
+    pub const SIZE: usize =
+        8 + 32 + ReputationHistory::SIZE + 8 + 1 + 1 + (1 + 8) + 2 + 4 + 32 + 2 + CreditHistory::SIZE + 8 + 8 + 8 + 8 + 4;
+
+    pub fn next_invoice_id(&self) -> u64 {
+        self.invoices_created + 1
+    }
+
+    pub fn record_funding(&mut self, invoices: u64, principal: u64) -> Result<()> {
+        self.invoices_funded = self.invoices_funded.checked_add(invoices).ok_or(ErrorCode::MathOverflow)?;
+        self.volume_funded = self.volume_funded.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // An escrowed funding of `principal` went back to its investor
+    pub fn unrecord_funding(&mut self, principal: u64) {
+        self.invoices_funded = self.invoices_funded.saturating_sub(1);
+        self.volume_funded = self.volume_funded.saturating_sub(principal);
+    }
+
+    // One of the business's invoices left the book; those listed before the
+    // count was kept may take it below what is actually open, so it stops at zero
+    pub fn close_invoice(&mut self) {
+        self.open_invoices = self.open_invoices.saturating_sub(1);
+    }
+
+    pub fn stats(&self) -> BusinessStats {
+        BusinessStats {
+            business: self.business_owner,
+            invoices_created: self.invoices_created,
+            invoices_funded: self.invoices_funded,
+            repaid_on_time: self.credit_history.repaid_on_time,
+            repaid_late: self.credit_history.repaid_late,
+            defaulted: self.credit_history.defaulted,
+            volume_funded: self.volume_funded,
+            outstanding: self.outstanding_financed,
+            verified: self.verified,
+            open_invoices: self.open_invoices,
+        }
+    }
+}
+
+// Returned by get_business_stats: a business's track record from its profile
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct BusinessStats {
+    pub business: Pubkey,
+    pub invoices_created: u64,
+    pub invoices_funded: u64,
+    pub repaid_on_time: u32,
+    pub repaid_late: u32,
+    pub defaulted: u32,
+    pub volume_funded: u64,
+    // Outright and bundled principal not yet repaid or written off; partially
+    // funded invoices are not booked here
+    pub outstanding: u64,
+    pub verified: bool,
+    pub open_invoices: u32,
+}
+
+// Registry entry for one debtor, shared by every business that lists invoices
+// against it, at [DEBTOR_SEED, debtor_id]. The id is a hash of the debtor's
+// identifier (e.g. sha256 of its tax or LEI number), so no identity goes on-chain.
+// Outstanding is the funded principal not yet repaid or written off in default.
+#[account]
+#[derive(Default)]
+pub struct Debtor {
+    pub debtor_id: [u8; 32],
+    pub invoices_listed: u32,
+    pub invoices_financed: u32,
+    pub outstanding: u64,
+    pub total_financed: u64,
+    pub defaults: u32,
+    pub defaulted_amount: u64,
+    pub bump: u8,
+}
+
+impl Debtor {
+    pub const SIZE: usize = 8 + 32 + 4 + 4 + 8 + 8 + 4 + 8 + 1;
+
+    // Refuse funding that would take outstanding exposure over `cap` (0 = no cap)
+    pub fn require_capacity(&self, amount: u64, cap: u64) -> Result<()> {
+        let exposure = self.outstanding.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        require!(cap == 0 || exposure <= cap, ErrorCode::DebtorConcentrationExceeded);
+        Ok(())
+    }
+
+    pub fn book_funding(&mut self, amount: u64, cap: u64) -> Result<()> {
+        self.require_capacity(amount, cap)?;
+        self.outstanding += amount;
+        self.total_financed = self.total_financed.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        self.invoices_financed = self.invoices_financed.saturating_add(1);
+        Ok(())
+    }
+
+    pub fn release(&mut self, principal: u64) {
+        self.outstanding = self.outstanding.saturating_sub(principal);
+    }
+
+    // Take back a funding that never went through
+    pub fn unbook(&mut self, amount: u64) {
+        self.release(amount);
+        self.total_financed = self.total_financed.saturating_sub(amount);
+        self.invoices_financed = self.invoices_financed.saturating_sub(1);
+    }
+
+    pub fn record_default(&mut self, principal: u64) {
+        self.release(principal);
+        self.defaults = self.defaults.saturating_add(1);
+        self.defaulted_amount = self.defaulted_amount.saturating_add(principal);
+    }
+}
+
+// Add `amount` to an outstanding total unless that takes it over `cap` (0 = no cap)
+pub fn book_outstanding(outstanding: &mut u64, amount: u64, cap: u64, exceeded: ErrorCode) -> Result<()> {
+    let total = outstanding.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    if cap != 0 && total > cap {
+        return Err(exceeded.into());
+    }
+    *outstanding = total;
+    Ok(())
+}
+
+// Debtor entries passed through remaining_accounts, written back with store()
+pub struct DebtorBook<'a, 'info> {
+    entries: Vec<(&'a AccountInfo<'info>, Debtor)>,
+}
+
+impl<'a, 'info> DebtorBook<'a, 'info> {
+    pub fn load(accounts: &'a [AccountInfo<'info>]) -> Result<Self> {
+        let entries = accounts
+            .iter()
+            .enumerate()
+            .map(|(index, info)| {
+                // Each entry once, or a later copy would overwrite the earlier one's updates
+                let repeated = accounts[..index].iter().any(|earlier| earlier.key() == info.key());
+                require!(!repeated, ErrorCode::DebtorMismatch);
+                require_keys_eq!(*info.owner, crate::ID, ErrorCode::DebtorMismatch);
+                require!(info.is_writable, ErrorCode::DebtorMismatch);
+                let data = info.try_borrow_data()?;
+                Ok((info, Debtor::try_deserialize(&mut &data[..])?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    pub fn booked_debtor(&mut self, invoice: &Invoice) -> Result<Option<&mut Debtor>> {
+        let debtor = self
+            .entries
+            .iter_mut()
+            .find(|(info, _)| info.key() == invoice.debtor)
+            .map(|(_, debtor)| debtor);
+        invoice.booked_debtor(debtor)
+    }
+
+    pub fn store(&self) -> Result<()> {
+        for (info, debtor) in &self.entries {
+            let mut data = info.try_borrow_mut_data()?;
+            debtor.try_serialize(&mut &mut data[..])?;
+        }
+        Ok(())
+    }
+}
+
+// Accounts a batch of repayments updates besides the invoices themselves, each
+// passed once and told apart by its account type
+pub struct RepaymentBooks<'a, 'info> {
+    debtors: DebtorBook<'a, 'info>,
+    investor_stats: Vec<(&'a AccountInfo<'info>, InvestorStats)>,
+    pair_ledgers: Vec<(&'a AccountInfo<'info>, PairLedger)>,
+    portfolios: Vec<(&'a AccountInfo<'info>, InvestorPortfolio)>,
+}
+
+impl<'a, 'info> RepaymentBooks<'a, 'info> {
+    pub fn load(accounts: &'a [AccountInfo<'info>]) -> Result<Self> {
+        let mut books = Self {
+            debtors: DebtorBook { entries: Vec::new() },
+            investor_stats: Vec::new(),
+            pair_ledgers: Vec::new(),
+            portfolios: Vec::new(),
+        };
+        for (index, info) in accounts.iter().enumerate() {
+            // Each entry once, or a later copy would overwrite the earlier one's updates
+            let repeated = accounts[..index].iter().any(|earlier| earlier.key() == info.key());
+            require!(!repeated && info.is_writable, ErrorCode::InvalidBatchAccount);
+            require_keys_eq!(*info.owner, crate::ID, ErrorCode::InvalidBatchAccount);
+            let data = info.try_borrow_data()?;
+            let discriminator = data.get(..8).ok_or(ErrorCode::InvalidBatchAccount)?;
+            if discriminator == Debtor::DISCRIMINATOR {
+                books.debtors.entries.push((info, Debtor::try_deserialize(&mut &data[..])?));
+            } else if discriminator == InvestorStats::DISCRIMINATOR {
+                books.investor_stats.push((info, InvestorStats::try_deserialize(&mut &data[..])?));
+            } else if discriminator == PairLedger::DISCRIMINATOR {
+                books.pair_ledgers.push((info, PairLedger::try_deserialize(&mut &data[..])?));
+            } else if discriminator == InvestorPortfolio::DISCRIMINATOR {
+                books.portfolios.push((info, InvestorPortfolio::try_deserialize(&mut &data[..])?));
+            } else {
+                return err!(ErrorCode::InvalidBatchAccount);
+            }
+        }
+        Ok(books)
+    }
+
+    // The invoice's debtor entry, which it must have if it was listed against one,
+    // and its investor's stats, pair ledger and portfolio if they were passed
+    #[allow(clippy::type_complexity)]
+    pub fn entries_for(
+        &mut self,
+        invoice: &Invoice,
+    ) -> Result<(
+        Option<&mut Debtor>,
+        Option<&mut InvestorStats>,
+        Option<&mut PairLedger>,
+        Option<&mut InvestorPortfolio>,
+    )> {
+        let debtor = self.debtors.booked_debtor(invoice)?;
+        let investor_stats = self
+            .investor_stats
+            .iter_mut()
+            .find(|(_, stats)| stats.investor == invoice.investor)
+            .map(|(_, stats)| stats);
+        let pair_ledger = self
+            .pair_ledgers
+            .iter_mut()
+            .find(|(_, ledger)| ledger.business == invoice.business_owner && ledger.investor == invoice.investor)
+            .map(|(_, ledger)| ledger);
+        let portfolio = self
+            .portfolios
+            .iter_mut()
+            .find(|(_, portfolio)| portfolio.investor == invoice.investor)
+            .map(|(_, portfolio)| portfolio);
+        Ok((debtor, investor_stats, pair_ledger, portfolio))
+    }
+
+    pub fn store(&self) -> Result<()> {
+        self.debtors.store()?;
+        for (info, stats) in &self.investor_stats {
+            stats.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        }
+        for (info, ledger) in &self.pair_ledgers {
+            ledger.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        }
+        for (info, portfolio) in &self.portfolios {
+            portfolio.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        }
+        Ok(())
+    }
+}
+
+// Accreditation record for one investor, kept by the authority; funding needs
+// a live entry while GlobalState::require_whitelist is on
+#[account]
+#[derive(Default)]
+pub struct InvestorWhitelist {
+    pub investor: Pubkey,
+    pub approved: bool,
+    // No expiry when None
+    pub expires_at: Option<i64>,
+    pub updated_at: i64,
+    pub bump: u8,
+}
+
+impl InvestorWhitelist {
+    pub const SIZE: usize = 8 + 32 + 1 + (1 + 8) + 8 + 1;
+
+    pub fn is_valid(&self, current_time: i64) -> bool {
+        self.approved && !matches!(self.expires_at, Some(expires_at) if current_time >= expires_at)
+    }
+}
+
+// A party's blacklist record; see blacklist.rs. It stays once cleared, barring
+// only the roles still flagged.
+#[account]
+#[derive(Default)]
+pub struct BlacklistEntry {
+    pub party: Pubkey,
+    pub business: bool,
+    pub investor: bool,
+    // Reason given for the last change, and when it was made
+    pub reason: BlacklistReason,
+    pub updated_at: i64,
+    pub bump: u8,
+}
+
+impl BlacklistEntry {
+    pub const SIZE: usize = 8 + 32 + 1 + 1 + 1 + 8 + 1;
+
+    pub fn bars(&self, role: BlacklistRole) -> bool {
+        match role {
+            BlacklistRole::Business => self.business,
+            BlacklistRole::Investor => self.investor,
+        }
+    }
+
+    pub fn set(&mut self, role: BlacklistRole, barred: bool) {
+        match role {
+            BlacklistRole::Business => self.business = barred,
+            BlacklistRole::Investor => self.investor = barred,
+        }
+    }
+}
+
+// Per-investor track record used by the retail guardrails
+#[account]
+#[derive(Default)]
+pub struct InvestorStats {
+    pub investor: Pubkey,
+    pub deployed_capital: u64,
+    pub completed_repayments: u32,
+    pub professional: bool,
+    pub bump: u8,
+
+    // Funded principal not yet repaid or defaulted, held under investor_exposure_cap
+    pub outstanding: u64,
+}
+
+impl InvestorStats {
+    pub const SIZE: usize = 8 + 32 + 8 + 4 + 1 + 1 + 8;
+
+    // Reject a position of `position` (the investor's total in one invoice) that
+    // breaks the retail limits, unless they are off, waived or outgrown
+    pub fn require_retail_guardrails(&self, enabled: bool, position: u64, risk_score: u8) -> Result<()> {
+        if !enabled || self.professional || self.completed_repayments >= RETAIL_GRADUATION_REPAYMENTS {
+            return Ok(());
+        }
+        require!(risk_score <= RETAIL_MAX_RISK_SCORE, ErrorCode::RiskTierLocked);
+
+        let deployed_after = self.deployed_capital as u128 + position as u128;
+        require!(
+            position <= RETAIL_POSITION_ALLOWANCE
+                || position as u128 * 10_000 <= deployed_after * RETAIL_MAX_POSITION_BPS as u128,
+            ErrorCode::DiversificationLimit
+        );
+        Ok(())
+    }
+}
+
+// One investor's running totals across the invoices it funded outright through
+// fund_invoice, opened at its first funding with the investor paying the rent.
+// Positions bought on the secondary market, partial fundings, bundles and
+// auto-invested invoices are not included. At all times
+// principal_deployed == outstanding + principal_repaid + principal_written_off + principal_sold.
+#[account]
+#[derive(Default)]
+pub struct InvestorPortfolio {
+    pub investor: Pubkey,
+    pub invoices_funded: u32,
+    pub principal_deployed: u64,
+    // Insurance premiums paid on top of the principal
+    pub premiums_paid: u64,
+    // Interest and late fees received, and what sales fetched above the principal
+    pub realized_yield: u64,
+    // Principal written off, less the insurance and recoveries received on it,
+    // and what sales fetched below the principal
+    pub realized_losses: u64,
+    // Funded principal not yet repaid, written off or sold
+    pub outstanding: u64,
+    pub principal_repaid: u64,
+    // Principal still owed at default, or written down in a dispute
+    pub principal_written_off: u64,
+    pub principal_sold: u64,
+    // Insurance payouts and recoveries received on written-off principal
+    pub recovered: u64,
+    pub bump: u8,
+}
+
+impl InvestorPortfolio {
+    pub const SIZE: usize = 8 + 32 + 4 + 8 * 10 + 1;
+
+    pub fn record_funding(&mut self, principal: u64, premium: u64) -> Result<()> {
+        self.invoices_funded = self.invoices_funded.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.principal_deployed = self.principal_deployed.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        self.premiums_paid = self.premiums_paid.checked_add(premium).ok_or(ErrorCode::MathOverflow)?;
+        self.outstanding = self.outstanding.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn record_repayment(&mut self, split: &RepaymentSplit) -> Result<()> {
+        self.outstanding = self.outstanding.saturating_sub(split.principal);
+        self.principal_repaid = self.principal_repaid.checked_add(split.principal).ok_or(ErrorCode::MathOverflow)?;
+        self.realized_yield = self
+            .realized_yield
+            .checked_add(split.interest)
+            .and_then(|total| total.checked_add(split.late_fee))
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // `principal` will not be repaid: the invoice defaulted owing it, or a dispute
+    // wrote it down
+    pub fn record_write_off(&mut self, principal: u64) -> Result<()> {
+        self.outstanding = self.outstanding.saturating_sub(principal);
+        self.principal_written_off =
+            self.principal_written_off.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        self.realized_losses = self.realized_losses.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // Insurance or a recovery reached the investor on written-off principal;
+    // whatever reimburses the pool first never counted as theirs
+    pub fn record_recovery(&mut self, amount: u64) -> Result<()> {
+        self.recovered = self.recovered.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        self.realized_losses = self.realized_losses.saturating_sub(amount);
+        Ok(())
+    }
+
+    // A recovery paid yield the defaulted invoice still owed
+    pub fn record_recovered_yield(&mut self, interest: u64) -> Result<()> {
+        self.realized_yield = self.realized_yield.checked_add(interest).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    // The investor sold the `principal` still owed on a position for `price`
+    pub fn record_sale(&mut self, principal: u64, price: u64) -> Result<()> {
+        self.outstanding = self.outstanding.saturating_sub(principal);
+        self.principal_sold = self.principal_sold.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        if price >= principal {
+            self.realized_yield = self.realized_yield.checked_add(price - principal).ok_or(ErrorCode::MathOverflow)?;
+        } else {
+            self.realized_losses = self.realized_losses.checked_add(principal - price).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    pub fn statement(&self) -> PortfolioStatement {
+        PortfolioStatement {
+            investor: self.investor,
+            invoices_funded: self.invoices_funded,
+            principal_deployed: self.principal_deployed,
+            premiums_paid: self.premiums_paid,
+            realized_yield: self.realized_yield,
+            realized_losses: self.realized_losses,
+            outstanding: self.outstanding,
+            principal_repaid: self.principal_repaid,
+            principal_written_off: self.principal_written_off,
+            principal_sold: self.principal_sold,
+            recovered: self.recovered,
+            net_realized: self.realized_yield as i64 - self.realized_losses as i64 - self.premiums_paid as i64,
+        }
+    }
+}
+
+// Returned by get_investor_portfolio
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct PortfolioStatement {
+    pub investor: Pubkey,
+    pub invoices_funded: u32,
+    pub principal_deployed: u64,
+    pub premiums_paid: u64,
+    pub realized_yield: u64,
+    pub realized_losses: u64,
+    pub outstanding: u64,
+    pub principal_repaid: u64,
+    pub principal_written_off: u64,
+    pub principal_sold: u64,
+    pub recovered: u64,
+    // Yield less losses and the premiums paid for cover
+    pub net_realized: i64,
+}
+
+// A collections agency approved by the protocol authority, with its track record
+#[account]
+pub struct CollectionsAgency {
+    pub authority: Pubkey,
+    pub fee_bps: u16,
+    pub assignments: u32,
+    pub recovered_total: u64,
+    pub fees_earned: u64,
+    pub uncollectible_count: u32,
+    pub bump: u8,
+}
+
+impl CollectionsAgency {
+    pub const SIZE: usize = 8 + 32 + 2 + 4 + 8 + 8 + 4 + 1;
+}
+
+// Referral of one defaulted invoice to an agency; the fee is fixed at assignment
+#[account]
+pub struct CollectionsAssignment {
+    pub invoice: Pubkey,
+    pub agency: Pubkey,
+    pub fee_bps: u16,
+    pub assigned_at: i64,
+    pub closed_at: Option<i64>,
+    pub recovered: u64,
+    pub active: bool,
+    pub bump: u8,
+}
+
+impl CollectionsAssignment {
+    pub const SIZE: usize = 8 + 32 + 32 + 2 + 8 + (1 + 8) + 8 + 1 + 1;
+}
+
+// A basket of one business's invoices funded and repaid as a single unit
+#[account]
+#[derive(Default)]
+pub struct Bundle {
+    pub bundle_id: u64,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub status: BundleStatus,
+    pub constituents: Vec<BundleConstituent>,
+
+    // Aggregate face value, blended risk and the single premium and return it was priced at
+    pub amount: u64,
+    pub risk_score: u8,
+    pub insurance_premium: u64,
+    pub expected_return: u64,
+    pub pricing_version: u8,
+    pub coverage_percentage: u8,
+
+    pub amount_repaid: u64,
+    pub created_at: i64,
+    pub funding_date: Option<i64>,
+    pub settled_at: Option<i64>,
+    pub bump: u8,
+}
+
+impl Bundle {
+    pub const SIZE: usize = 8 + 8 + 32 + 32 + 1 + (4 + MAX_BUNDLE_SIZE * BundleConstituent::SIZE)
+        + 8 + 1 + 8 + 8 + 1 + 1 + 8 + 8 + (1 + 8) + (1 + 8) + 1;
+
+    pub fn invoice_ids(&self) -> Vec<u64> {
+        self.constituents.iter().map(|constituent| constituent.invoice_id).collect()
+    }
+
+    // Split remaining_accounts into the constituent invoices and, after them, the
+    // registry entries of their debtors (one per distinct debtor)
+    pub fn split_accounts<'a, 'info>(
+        &self,
+        accounts: &'a [AccountInfo<'info>],
+    ) -> (&'a [AccountInfo<'info>], &'a [AccountInfo<'info>]) {
+        accounts.split_at(self.constituents.len().min(accounts.len()))
+    }
+
+    // Load the constituent invoices from remaining_accounts, which must list exactly
+    // the bundle's invoices in bundle order
+    pub fn load_constituents<'a, 'info>(
+        &self,
+        accounts: &'a [AccountInfo<'info>],
+    ) -> Result<Vec<(&'a AccountInfo<'info>, Invoice)>> {
+        require!(accounts.len() == self.constituents.len(), ErrorCode::BundleConstituentMismatch);
+        accounts
+            .iter()
+            .zip(&self.constituents)
+            .map(|(info, constituent)| {
+                require_keys_eq!(info.key(), constituent.invoice, ErrorCode::BundleConstituentMismatch);
+                Ok((info, load_invoice(info)?))
+            })
+            .collect()
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct BundleConstituent {
+    pub invoice: Pubkey,
+    pub invoice_id: u64,
+    pub amount: u64,
+}
+
+impl BundleConstituent {
+    pub const SIZE: usize = 32 + 8 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum BundleStatus {
+    #[default]
+    Open,
+    Funded,
+    Settled,
+}
+
+// Par funding advances the face value and earns a yield on top; factoring advances
+// a share of it and collects the face value
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum FundingMode {
+    Par,
+    Factoring,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RecoverySplit {
+    pub agency_fee: u64,
+    pub pool: u64,
+    pub investor: u64,
+    pub business: u64,
+}
+
+// Principal plus interest and late fees, or MathOverflow
+fn owed_balance(principal: u64, interest: u64, late_fee: u64) -> Result<u64> {
+    principal
+        .checked_add(interest)
+        .and_then(|owed| owed.checked_add(late_fee))
+        .ok_or_else(|| error!(ErrorCode::MathOverflow))
+}
+
+// Where accrue_charges left an invoice: how late it is and what it now owes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Accrual {
+    pub days_overdue: i64,
+    pub outstanding: u64,
+}
+
+// How a single repayment was applied
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RepaymentSplit {
+    pub late_fee: u64,
+    pub interest: u64,
+    pub principal: u64,
+}
+
+// One investor's stake in a partially funded invoice
+#[account]
+pub struct FundingShare {
+    pub invoice: Pubkey,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub premium_paid: u64,
+    pub claimed: u64,
+    pub bump: u8,
+}
+
+impl FundingShare {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 8 + 8 + 1;
+}
+
+// Shared vault of invoice positions, see pool.rs
+#[account]
+pub struct InvestmentPool {
+    pub manager: Pubkey,
+    pub mint: Pubkey,
+    pub share_mint: Pubkey,
+    pub vault: Pubkey,
+    // Invoices scored above this are refused
+    pub max_risk_score: u8,
+    // Open positions at cost: principal plus the premium paid on it
+    pub deployed: u64,
+    pub open_positions: u32,
+    pub invoices_funded: u64,
+    pub authority_bump: u8,
+    pub bump: u8,
+}
+
+impl InvestmentPool {
+    pub const SIZE: usize = 8 + 32 * 4 + 1 + 8 + 4 + 8 + 1 + 1;
+
+    // Net asset value: cash in the vault plus open positions at cost
+    pub fn nav(&self, vault_balance: u64) -> Result<u64> {
+        vault_balance.checked_add(self.deployed).ok_or(ErrorCode::MathOverflow.into())
+    }
+}
+
+// An invoice a pool funded and has not settled yet
+#[account]
+pub struct PoolPosition {
+    pub pool: Pubkey,
+    pub invoice: Pubkey,
+    pub cost: u64,
+    pub opened_at: i64,
+    pub bump: u8,
+}
+
+impl PoolPosition {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 8 + 1;
+}
+
+// Platform named on an invoice it lists for a business, with its fee in bps of the
+// principal (at most GlobalState::originator_fee_cap_bps)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Origination {
+    pub originator: Pubkey,
+    pub fee_bps: u16,
+}
+
+// What a platform's originated invoices raised and paid it, for reconciling
+#[account]
+#[derive(Default)]
+pub struct OriginatorStats {
+    pub originator: Pubkey,
+    pub invoices_originated: u64,
+    pub invoices_funded: u64,
+    pub volume_funded: u64,
+    pub fees_earned: u64,
+    pub last_funded_at: Option<i64>,
+    pub bump: u8,
+}
+
+impl OriginatorStats {
+    pub const SIZE: usize = 8 + 32 + 8 + 8 + 8 + 8 + (1 + 8) + 1;
+
+    pub fn record_funding(&mut self, amount: u64, fee: u64, funded_at: i64) -> Result<()> {
+        self.invoices_funded = self.invoices_funded.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.volume_funded = self.volume_funded.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        self.fees_earned = self.fees_earned.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+        self.last_funded_at = Some(funded_at);
+        Ok(())
+    }
+}
+
+// What an auto-invest mandate funds
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct MandateRules {
+    pub max_risk_score: u8,
+    pub min_amount: u64,
+    pub max_amount: u64,
+    // Days from funding to the due date, rounded up
+    pub max_term_days: u16,
+    // Ceiling on the investor's outstanding principal, fundings by hand included
+    pub exposure_cap: u64,
+}
+
+impl MandateRules {
+    pub fn is_valid(&self) -> bool {
+        self.min_amount <= self.max_amount && self.max_amount > 0 && self.max_term_days > 0 && self.exposure_cap > 0
+    }
+
+    // Whether a pending invoice falls within the rules at `now`. Off-ramp payouts
+    // never qualify, since nobody is there to queue them with the processor.
+    pub fn matches(&self, invoice: &Invoice, now: i64) -> bool {
+        let term_days = (invoice.due_date - now + 86_399).div_euclid(86_400);
+        invoice.risk_score <= self.max_risk_score
+            && (self.min_amount..=self.max_amount).contains(&invoice.amount)
+            && term_days > 0
+            && term_days <= self.max_term_days as i64
+            && !invoice.offramp_requested
+    }
+}
+
+// An investor's standing instruction to fund matching invoices, executed by
+// anyone through execute_auto_invest; one per investor
+#[account]
+pub struct AutoInvestMandate {
+    pub investor: Pubkey,
+    // USDC account the mandate is approved over
+    pub token_account: Pubkey,
+    pub rules: MandateRules,
+    pub paused: bool,
+    pub invoices_funded: u32,
+    pub total_funded: u64,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl AutoInvestMandate {
+    pub const SIZE: usize = 8 + 32 + 32 + MandateRules::INIT_SPACE + 1 + 4 + 8 + 8 + 1;
+}
+
+// A pending authority withdrawal from the insurance pool
+#[account]
+pub struct PoolWithdrawalProposal {
+    pub amount: u64,
+    pub destination: Pubkey,
+    pub proposed_at: i64,
+    pub executable_at: i64,
+    pub bump: u8,
+}
+
+impl PoolWithdrawalProposal {
+    pub const SIZE: usize = 8 + 8 + 32 + 8 + 8 + 1;
+}
+
+// A guarded instruction awaiting the approver set. `approvals` holds one bit per
+// index into GlobalState::admin_approvers, valid only while the set is the one
+// the proposal was raised under.
+#[account]
+pub struct AdminProposal {
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub approver_set_nonce: u32,
+    pub approvals: u8,
+    pub accounts: Vec<AdminAccountMeta>,
+    pub data: Vec<u8>,
+    pub proposed_at: i64,
+    pub expires_at: i64,
+    pub bump: u8,
+}
+
+impl AdminProposal {
+    pub const SIZE: usize = 8 + 8 + 32 + 4 + 1
+        + (4 + AdminAccountMeta::INIT_SPACE * approvals::MAX_ADMIN_ACTION_ACCOUNTS)
+        + (4 + approvals::MAX_ADMIN_ACTION_DATA_LEN)
+        + 8 + 8 + 1;
+
+    // Discriminator of the proposed instruction
+    pub fn instruction(&self) -> [u8; 8] {
+        self.data[..8].try_into().unwrap()
+    }
+
+    pub fn approval_count(&self) -> u8 {
+        self.approvals.count_ones() as u8
+    }
+
+    // Count `approver`'s sign-off, returning the approvals so far
+    pub fn approve(&mut self, state: &GlobalState, approver: &Pubkey, current_time: i64) -> Result<u8> {
+        self.require_live(state, current_time)?;
+        let bit = 1u8 << state.approver_index(approver).ok_or(ErrorCode::NotAnApprover)?;
+        require!(self.approvals & bit == 0, ErrorCode::AlreadyApproved);
+        self.approvals |= bit;
+        Ok(self.approval_count())
+    }
+
+    pub fn require_executable(&self, state: &GlobalState, current_time: i64) -> Result<()> {
+        self.require_live(state, current_time)?;
+        require!(self.approval_count() >= state.approval_threshold, ErrorCode::ApprovalThresholdNotMet);
+        Ok(())
+    }
+
+    fn require_live(&self, state: &GlobalState, current_time: i64) -> Result<()> {
+        require!(
+            state.approvers_configured() && self.approver_set_nonce == state.approver_set_nonce,
+            ErrorCode::ApproverSetChanged
+        );
+        require!(current_time < self.expires_at, ErrorCode::ProposalExpired);
+        Ok(())
+    }
+}
+
+// One liquidity provider's stake in the insurance pool
+#[account]
+#[derive(Default)]
+pub struct InsuranceLpPosition {
+    pub provider: Pubkey,
+    pub shares: u64,
+    // Shares locked in by a withdrawal request, burnable from withdrawal_available_at
+    pub pending_withdrawal_shares: u64,
+    pub withdrawal_available_at: i64,
+    pub bump: u8,
+}
+
+impl InsuranceLpPosition {
+    pub const SIZE: usize = 8 + 32 + 8 + 8 + 8 + 1;
+}
+
+// Pre-deposited USDC held in a program-custodied token account for one investor
+#[account]
+#[derive(Default)]
+pub struct InvestorBalance {
+    pub investor: Pubkey,
+    pub custody: Pubkey,
+    // Free to fund invoices with or withdraw
+    pub available: u64,
+    // Premiums drawn by fundings, still in custody until swept to the insurance pool
+    pub premium_owed: u64,
+    pub bump: u8,
+}
+
+impl InvestorBalance {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 8 + 1;
+
+    pub fn draw(&mut self, principal: u64, premium: u64) -> Result<()> {
+        let total = principal.checked_add(premium).ok_or(ErrorCode::InsufficientFunds)?;
+        require!(self.available >= total, ErrorCode::InsufficientFunds);
+        self.available -= total;
+        self.premium_owed += premium;
+        Ok(())
+    }
+
+    // Undo a draw whose funding was never accepted
+    pub fn refund(&mut self, principal: u64, premium: u64) -> Result<()> {
+        let total = principal.checked_add(premium).ok_or(ErrorCode::MathOverflow)?;
+        self.available = self.available.checked_add(total).ok_or(ErrorCode::MathOverflow)?;
+        self.premium_owed = self.premium_owed.saturating_sub(premium);
+        Ok(())
+    }
+
+    pub fn withdraw(&mut self, amount: u64) -> Result<()> {
+        require!(amount > 0 && amount <= self.available, ErrorCode::BalanceUnavailable);
+        self.available -= amount;
+        Ok(())
+    }
+
+    // The custody token account must hold exactly what the balance accounts for
+    pub fn reconciles(&self, custody_amount: u64) -> bool {
+        self.available.checked_add(self.premium_owed) == Some(custody_amount)
+    }
+}
+
+// A claim that the debtor disputes the underlying invoice. The reason itself is
+// kept off-chain; the invoice carries its sha256.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Dispute {
+    pub raised_by: Pubkey,
+    pub reason_hash: [u8; 32],
+    pub raised_at: i64,
+    pub resolved_at: Option<i64>,
+    pub resolution: Option<DisputeResolution>,
+}
+
+impl Dispute {
+    pub const SIZE: usize = 32 + 32 + 8 + (1 + 8) + (1 + DisputeResolution::SIZE);
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub enum DisputeResolution {
+    // The dispute did not hold; the invoice carries on as funded
+    Dismissed,
+    // The debtor owes less: unpaid principal is written down to remaining_balance
+    Adjusted { remaining_balance: u64 },
+    // The debtor will not pay; the invoice defaults without waiting out the grace period
+    Defaulted,
+}
+
+impl DisputeResolution {
+    pub const SIZE: usize = 1 + 8;
+}
+
+// What funding makes of an invoice its debtor has not acknowledged
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, Default, InitSpace)]
+pub enum AcknowledgmentPolicy {
+    // Acknowledgment is recorded for investors to filter on, nothing more
+    #[default]
+    Optional,
+    // Invoices list with extra risk points, taken off when the debtor acknowledges
+    Penalized { risk_points: u8 },
+    // fund_invoice, contribute_funding and fund_bundle refuse unacknowledged invoices
+    Required,
+}
+
+impl AcknowledgmentPolicy {
+    pub const SIZE: usize = 1 + 1;
+
+    pub fn listing_penalty(&self) -> u8 {
+        match self {
+            Self::Penalized { risk_points } => *risk_points,
+            _ => 0,
+        }
+    }
+
+    pub fn is_valid(&self, params: &RiskParams) -> bool {
+        match self {
+            Self::Penalized { risk_points } => *risk_points > 0 && *risk_points <= params.max_score,
+            _ => true,
+        }
+    }
+}
+
+// A funding waiting in the invoice vault for the business to accept it. The
+// vault holds the principal and, for wallet fundings, the premium; a custody
+// funding's premium stays in custody as premium_owed.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct FundingEscrow {
+    // Last moment the business can accept; the investor may reclaim after it
+    pub deadline: i64,
+    pub premium: u64,
+    pub from_balance: bool,
+}
+
+impl FundingEscrow {
+    pub const SIZE: usize = 8 + 8 + 1;
+
+    // Owed for `principal` escrowed at `funded_at` and left to expire: `bps_per_day`
+    // for each whole day up to the deadline. Time past it is the investor's own, to
+    // end by reclaiming.
+    pub fn compensation(&self, principal: u64, funded_at: i64, bps_per_day: u16) -> Result<u64> {
+        let days = (self.deadline - funded_at).max(0) / 86_400;
+        u64::try_from(principal as u128 * bps_per_day as u128 * days as u128 / 10_000)
+            .map_err(|_| error!(ErrorCode::MathOverflow))
+    }
+}
+
+// Stored as its variant index at INVOICE_STATUS_OFFSET, which clients filter on;
+// new variants go at the end
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, Default, InitSpace)]
+pub enum InvoiceStatus {
+    #[default]
+    PendingFunding,
+    Funded,
+    Repaid,
+    Defaulted,
+    PartiallyRepaid,
+    // Taken off the marketplace by review; see Invoice::rejection_reason
+    Delisted,
+    // Funded into escrow, waiting on accept_funding; see Invoice::escrow
+    FundedPendingAcceptance,
+    // Defaulted, then settled through settle_recovery: the insurance pool got its
+    // payout back and the investor the principal it left uncovered
+    RepaidAfterDefault,
+    // Withdrawn by the business before funding; the account closes with it
+    Cancelled,
+    // Past its due date without reaching face value; contributors withdraw
+    Expired,
+    // Funded, with a dispute open; see Invoice::dispute
+    Disputed,
+}
+
+impl InvoiceStatus {
+    // The invoice lifecycle. Repaid, RepaidAfterDefault and Cancelled are terminal.
+    pub fn can_transition_to(self, to: InvoiceStatus) -> bool {
+        use InvoiceStatus::*;
+        matches!(
+            (self, to),
+            (PendingFunding, Funded | FundedPendingAcceptance | Delisted | Expired | Cancelled)
+                | (FundedPendingAcceptance, Funded | PendingFunding)
+                | (Funded | PartiallyRepaid, PartiallyRepaid | Repaid | Defaulted | Disputed)
+                | (Disputed, Funded | PartiallyRepaid | Repaid | Defaulted)
+                | (Defaulted, RepaidAfterDefault)
+                | (Delisted | Expired, Cancelled)
+        )
+    }
+
+    // Funded and not yet settled: repayments, disputes and defaults apply
+    pub fn is_repayable(self) -> bool {
+        matches!(self, InvoiceStatus::Funded | InvoiceStatus::PartiallyRepaid | InvoiceStatus::Disputed)
+    }
+}
+
+#[account]
+pub struct DestinationAllowlist {
+    pub vault: VaultKind,
+    pub destinations: Vec<AllowlistedDestination>,
+    pub frozen_at: Option<i64>,
+    pub bump: u8,
+}
+
+impl DestinationAllowlist {
+    pub const MAX_DESTINATIONS: usize = 8;
+    pub const SIZE: usize = 8 + 1 + (4 + Self::MAX_DESTINATIONS * AllowlistedDestination::SIZE) + (1 + 8) + 1;
+
+    pub fn find(&self, token_account: &Pubkey) -> Option<usize> {
+        self.destinations
+            .iter()
+            .position(|d| d.token_account == *token_account)
+    }
+
+    // Registered and not yet due for removal, whether or not already active
+    pub fn is_registered(&self, token_account: &Pubkey, current_time: i64) -> bool {
+        self.destinations.iter().any(|d| {
+            d.token_account == *token_account && !matches!(d.removable_at, Some(at) if current_time >= at)
+        })
+    }
+
+    // Registered, past its activation timelock, and not yet due for removal
+    pub fn is_allowed(&self, token_account: &Pubkey, current_time: i64) -> bool {
+        self.destinations.iter().any(|d| {
+            d.token_account == *token_account
+                && current_time >= d.active_at
+                && !matches!(d.removable_at, Some(at) if current_time >= at)
+        })
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AllowlistedDestination {
+    pub name: [u8; 32],
+    pub token_account: Pubkey,
+    pub active_at: i64,
+    pub removable_at: Option<i64>,
+}
+
+impl AllowlistedDestination {
+    pub const SIZE: usize = 32 + 32 + 8 + (1 + 8);
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub enum VaultKind {
+    Treasury,
+    InsurancePool,
+    LostAndFound,
+    PayoutCustody,
+}
+
+impl VaultKind {
+    pub fn seed(&self) -> [u8; 1] {
+        [*self as u8]
+    }
+}
+
+#[account]
+pub struct PayoutProcessor {
+    pub processor: Pubkey,
+    pub custody: Pubkey,
+    pub escrow: Pubkey,
+    pub ack_timeout_secs: i64,
+    pub total_entries: u64,
+    pub bump: u8,
+    pub authority_bump: u8,
+    pub pending_custody: Option<Pubkey>,
+    pub pending_custody_at: i64,
+}
+
+impl PayoutProcessor {
+    pub const SIZE: usize = 8 + 32 + 32 + 32 + 8 + 8 + 1 + 1 + (1 + 32) + 8;
+
+    // Point payouts at `custody`: at once when none is set yet, otherwise behind the
+    // parameter timelock, returning when the change can be applied. Naming the
+    // current custody again drops a pending change.
+    pub fn stage_custody(&mut self, custody: Pubkey, current_time: i64) -> Option<i64> {
+        if self.custody == Pubkey::default() {
+            self.custody = custody;
+            None
+        } else if custody == self.custody {
+            self.pending_custody = None;
+            None
+        } else {
+            self.pending_custody = Some(custody);
+            self.pending_custody_at = current_time + PARAMETER_TIMELOCK_SECS;
+            Some(self.pending_custody_at)
+        }
+    }
+
+    // Swap in the scheduled custody account once its timelock has elapsed and it is
+    // active on the allowlist, returning it
+    pub fn apply_pending_custody(&mut self, allowlist: &DestinationAllowlist, current_time: i64) -> Result<Pubkey> {
+        let custody = self.pending_custody.ok_or(ErrorCode::NoPendingChange)?;
+        require!(current_time >= self.pending_custody_at, ErrorCode::TimelockNotElapsed);
+        require_allowlisted_destination(allowlist, &custody, current_time)?;
+        self.custody = custody;
+        self.pending_custody = None;
+        Ok(custody)
+    }
+}
+
+#[account]
+pub struct OutboxPage {
+    pub page: u32,
+    pub entries: Vec<OutboxEntry>,
+    pub bump: u8,
+}
+
+impl OutboxPage {
+    pub const MAX_ENTRIES: usize = 16;
+    pub const SIZE: usize = 8 + 4 + (4 + Self::MAX_ENTRIES * OutboxEntry::SIZE) + 1;
+
+    pub fn entry_mut(&mut self, index: u64) -> Result<&mut OutboxEntry> {
+        require!(
+            index / Self::MAX_ENTRIES as u64 == self.page as u64,
+            ErrorCode::OutboxPageMismatch
+        );
+        self.entries
+            .get_mut((index % Self::MAX_ENTRIES as u64) as usize)
+            .ok_or(error!(ErrorCode::OutboxEntryNotFound))
+    }
+}
+
+// Paged record of protocol-level admin actions (parameter changes, registrations)
+#[account]
+pub struct AdminActionLog {
+    pub page: u32,
+    pub entries: Vec<AdminAction>,
+    pub bump: u8,
+}
+
+impl AdminActionLog {
+    pub const MAX_ENTRIES: usize = 16;
+    pub const SIZE: usize = 8 + 4 + (4 + Self::MAX_ENTRIES * AdminAction::SIZE) + 1;
+
+    // Append the action numbered `total`, returning its global index
+    pub fn append(&mut self, total: &mut u64, action: AdminAction) -> Result<u64> {
+        let index = *total;
+        require!(
+            self.page as u64 == index / Self::MAX_ENTRIES as u64,
+            ErrorCode::AdminLogPageMismatch
+        );
+        self.entries.push(action);
+        *total += 1;
+        Ok(index)
+    }
+}
+
+// Governed parameters tracked in the param history
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParamId {
+    MaxInvoiceAmount,
+    MaxTermDays,
+    GracePeriodDays,
+    LateFeeBpsPerDay,
+    CoverageLow,
+    CoverageMedium,
+    CoverageHigh,
+    CoverageVeryHigh,
+    RiskDefaultPoints,
+    RiskLateRepaymentPoints,
+    RiskHalfLifeMonths,
+    RiskMaxHistoryPoints,
+    MinInterestBps,
+    DailyFundingCap,
+    EscrowCompensationBpsPerDay,
+}
+
+impl ParamId {
+    pub const COUNT: usize = 15;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ParamValueChange {
+    pub param: ParamId,
+    pub old_value: u64,
+    pub new_value: u64,
+}
+
+impl ParamValueChange {
+    pub const SIZE: usize = 1 + 8 + 8;
+}
+
+// Everything one governing instruction changed; `version` is the parameter-set
+// version it produced, stamped on invoices at each economic event
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct ParamSetChange {
+    pub version: u32,
+    pub effective_at: i64,
+    pub actor: Pubkey,
+    pub changes: Vec<ParamValueChange>,
+}
+
+impl ParamSetChange {
+    pub const SIZE: usize = 4 + 8 + 32 + (4 + ParamId::COUNT * ParamValueChange::SIZE);
+
+    pub fn change_of(&self, param: ParamId) -> Option<&ParamValueChange> {
+        self.changes.iter().find(|change| change.param == param)
+    }
+}
+
+// Paged changefeed of governed parameter values; entry n of the whole feed is
+// parameter-set version n + 1
+#[account]
+pub struct ParamHistory {
+    pub page: u32,
+    pub entries: Vec<ParamSetChange>,
+    pub bump: u8,
+}
+
+impl ParamHistory {
+    pub const MAX_ENTRIES: usize = 8;
+    pub const SIZE: usize = 8 + 4 + (4 + Self::MAX_ENTRIES * ParamSetChange::SIZE) + 1;
+
+    // Append the next parameter set, stamping and returning its version
+    pub fn append(&mut self, version: &mut u32, mut entry: ParamSetChange) -> Result<u32> {
+        require!(
+            self.page == *version / Self::MAX_ENTRIES as u32,
+            ErrorCode::ParamHistoryPageMismatch
+        );
+        *version += 1;
+        entry.version = *version;
+        self.entries.push(entry);
+        Ok(*version)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ParamAt {
+    pub param: ParamId,
+    pub value: u64,
+    pub version: u32,
+}
+
+// Parameter-set versions in force at an invoice's economic events; 0 is the set a
+// deployment started with
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub struct ParamVersions {
+    pub created: u32,
+    pub funded: u32,
+    pub settled: u32,
+}
+
+impl ParamVersions {
+    pub const SIZE: usize = 4 + 4 + 4;
+}
+
+// The last privileged actions taken on one invoice, kept as a ring buffer
+#[account]
+pub struct InvoiceAuditLog {
+    pub invoice: Pubkey,
+    pub total_actions: u64,
+    pub entries: Vec<AdminAction>,
+    pub bump: u8,
+}
+
+impl InvoiceAuditLog {
+    pub const MAX_ENTRIES: usize = 8;
+    pub const SIZE: usize = 8 + 32 + 8 + (4 + Self::MAX_ENTRIES * AdminAction::SIZE) + 1;
+
+    // Record an action, overwriting the oldest once the buffer is full
+    pub fn record(&mut self, action: AdminAction) {
+        let slot = (self.total_actions % Self::MAX_ENTRIES as u64) as usize;
+        if self.entries.len() < Self::MAX_ENTRIES {
+            self.entries.push(action);
+        } else {
+            self.entries[slot] = action;
+        }
+        self.total_actions += 1;
+    }
+
+    // Retained actions, oldest first
+    pub fn chronological(&self) -> Vec<AdminAction> {
+        let mut entries = self.entries.clone();
+        if entries.len() == Self::MAX_ENTRIES {
+            entries.rotate_left((self.total_actions % Self::MAX_ENTRIES as u64) as usize);
+        }
+        entries
+    }
+}
+
+// Merkle commitment to the invoice book at a slot: a root over the BookLeaf of
+// every invoice with nonce first_nonce..=last_nonce, in nonce order (book.rs)
+#[account]
+#[derive(Default)]
+pub struct BookCommitment {
+    pub index: u64,
+    pub opened: bool,
+    pub finalized: bool,
+    // Highest nonce the commitment may include, fixed when it opens
+    pub through_nonce: u64,
+    pub first_nonce: u64,
+    pub last_nonce: u64,
+    pub frontier: BookFrontier,
+    pub root: [u8; 32],
+    pub opened_slot: u64,
+    pub committed_slot: u64,
+    pub committed_at: i64,
+    pub bump: u8,
+}
+
+impl BookCommitment {
+    pub const SIZE: usize = 8 + 8 + 1 + 1 + 8 + 8 + 8 + BookFrontier::SIZE + 32 + 8 + 8 + 8 + 1;
+}
+
+// Lamports anyone may send to fund rewards for permissionless cranks
+#[account]
+#[derive(Default)]
+pub struct CrankTreasury {
+    pub bump: u8,
+}
+
+impl CrankTreasury {
+    pub const SIZE: usize = 8 + 1;
+}
+
+// Running bilateral history between a business and one investor across every
+// invoice the investor funded outright. Opened at their first funding, rent paid
+// by the investor; partially funded and bundled invoices are not included.
+#[account]
+#[derive(Default)]
+pub struct PairLedger {
+    pub business: Pubkey,
+    pub investor: Pubkey,
+    pub invoices_financed: u32,
+    // Funded and neither repaid nor defaulted yet
+    pub open_invoices: u32,
+    pub invoices_repaid: u32,
+    pub invoices_defaulted: u32,
+    // Positions the investor sold on before they settled
+    pub invoices_sold: u32,
+    pub total_financed: u64,
+    // Everything the business paid the investor: principal, interest and late fees
+    pub total_repaid: u64,
+    pub principal_repaid: u64,
+    pub late_fees_paid: u64,
+    // Principal still owed when invoices defaulted, and what reached the investor
+    // on them afterwards through insurance and recoveries
+    pub total_defaulted: u64,
+    pub total_recovered: u64,
+    // Principal still owed on positions when they were sold
+    pub principal_sold: u64,
+    // Days from funding to full repayment, summed over repaid invoices
+    pub days_to_pay_total: u64,
+    pub total_entries: u64,
+    pub entries: Vec<SettlementEntry>,
+    pub bump: u8,
+}
+
+impl PairLedger {
+    pub const MAX_ENTRIES: usize = 10;
+    pub const SIZE: usize = 8 + 32 + 32 + 4 * 5 + 8 * 8 + 8 + (4 + Self::MAX_ENTRIES * SettlementEntry::SIZE) + 1;
+
+    pub fn record_funding(&mut self, invoice_id: u64, amount: u64, timestamp: i64) -> Result<()> {
+        self.invoices_financed = self.invoices_financed.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.open_invoices = self.open_invoices.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.total_financed = self.total_financed.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        self.log(invoice_id, SettlementKind::Funding, amount, timestamp);
+        Ok(())
+    }
+
+    pub fn record_repayment(&mut self, invoice_id: u64, amount: u64, split: &RepaymentSplit, timestamp: i64) -> Result<()> {
+        self.total_repaid = self.total_repaid.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        self.principal_repaid = self.principal_repaid.checked_add(split.principal).ok_or(ErrorCode::MathOverflow)?;
+        self.late_fees_paid = self.late_fees_paid.checked_add(split.late_fee).ok_or(ErrorCode::MathOverflow)?;
+        self.log(invoice_id, SettlementKind::Repayment, amount, timestamp);
+        Ok(())
+    }
+
+    // An invoice between the pair was paid off `days_to_pay` days after funding
+    pub fn record_repaid(&mut self, days_to_pay: u64) -> Result<()> {
+        self.open_invoices = self.open_invoices.saturating_sub(1);
+        self.invoices_repaid = self.invoices_repaid.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.days_to_pay_total = self.days_to_pay_total.checked_add(days_to_pay).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    pub fn record_default(&mut self, invoice_id: u64, outstanding_principal: u64, timestamp: i64) -> Result<()> {
+        self.open_invoices = self.open_invoices.saturating_sub(1);
+        self.invoices_defaulted = self.invoices_defaulted.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.total_defaulted = self.total_defaulted.checked_add(outstanding_principal).ok_or(ErrorCode::MathOverflow)?;
+        self.log(invoice_id, SettlementKind::Default, outstanding_principal, timestamp);
+        Ok(())
+    }
+
+    pub fn record_recovery(&mut self, invoice_id: u64, kind: SettlementKind, amount: u64, timestamp: i64) -> Result<()> {
+        self.total_recovered = self.total_recovered.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        self.log(invoice_id, kind, amount, timestamp);
+        Ok(())
+    }
+
+    // The investor bought an open position in one of the business's invoices:
+    // `principal` still owed on it, for `price`
+    pub fn record_purchase(&mut self, invoice_id: u64, principal: u64, price: u64, timestamp: i64) -> Result<()> {
+        self.invoices_financed = self.invoices_financed.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.open_invoices = self.open_invoices.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.total_financed = self.total_financed.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        self.log(invoice_id, SettlementKind::PositionBought, price, timestamp);
+        Ok(())
+    }
+
+    pub fn record_sale(&mut self, invoice_id: u64, principal: u64, price: u64, timestamp: i64) -> Result<()> {
+        self.open_invoices = self.open_invoices.saturating_sub(1);
+        self.invoices_sold = self.invoices_sold.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        self.principal_sold = self.principal_sold.checked_add(principal).ok_or(ErrorCode::MathOverflow)?;
+        self.log(invoice_id, SettlementKind::PositionSold, price, timestamp);
+        Ok(())
+    }
+
+    // Principal still owed on the pair's open invoices
+    pub fn outstanding(&self) -> u64 {
+        self.total_financed
+            .saturating_sub(self.principal_repaid)
+            .saturating_sub(self.total_defaulted)
+            .saturating_sub(self.principal_sold)
+    }
+
+    // Overwrites the oldest entry once the buffer is full
+    fn log(&mut self, invoice_id: u64, kind: SettlementKind, amount: u64, timestamp: i64) {
+        let entry = SettlementEntry { invoice_id, kind, amount, timestamp };
+        let slot = (self.total_entries % Self::MAX_ENTRIES as u64) as usize;
+        if self.entries.len() < Self::MAX_ENTRIES {
+            self.entries.push(entry);
+        } else {
+            self.entries[slot] = entry;
+        }
+        self.total_entries += 1;
+    }
+
+    // Retained entries, oldest first
+    pub fn chronological(&self) -> Vec<SettlementEntry> {
+        let mut entries = self.entries.clone();
+        if entries.len() == Self::MAX_ENTRIES {
+            entries.rotate_left((self.total_entries % Self::MAX_ENTRIES as u64) as usize);
+        }
+        entries
+    }
+
+    pub fn statement(&self) -> PairStatement {
+        PairStatement {
+            business: self.business,
+            investor: self.investor,
+            invoices_financed: self.invoices_financed,
+            open_invoices: self.open_invoices,
+            invoices_repaid: self.invoices_repaid,
+            invoices_defaulted: self.invoices_defaulted,
+            invoices_sold: self.invoices_sold,
+            total_financed: self.total_financed,
+            total_repaid: self.total_repaid,
+            outstanding: self.outstanding(),
+            late_fees_paid: self.late_fees_paid,
+            total_defaulted: self.total_defaulted,
+            total_recovered: self.total_recovered,
+            average_days_to_pay: (self.invoices_repaid > 0)
+                .then(|| self.days_to_pay_total / self.invoices_repaid as u64),
+            recent: self.chronological(),
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub enum SettlementKind {
+    Funding,
+    Repayment,
+    Default,
+    InsurancePayout,
+    Recovery,
+    PositionBought,
+    PositionSold,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct SettlementEntry {
+    pub invoice_id: u64,
+    pub kind: SettlementKind,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+impl SettlementEntry {
+    pub const SIZE: usize = 8 + 1 + 8 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Debug)]
+pub struct PairStatement {
+    pub business: Pubkey,
+    pub investor: Pubkey,
+    pub invoices_financed: u32,
+    pub open_invoices: u32,
+    pub invoices_repaid: u32,
+    pub invoices_defaulted: u32,
+    pub invoices_sold: u32,
+    pub total_financed: u64,
+    pub total_repaid: u64,
+    pub outstanding: u64,
+    pub late_fees_paid: u64,
+    pub total_defaulted: u64,
+    pub total_recovered: u64,
+    pub average_days_to_pay: Option<u64>,
+    pub recent: Vec<SettlementEntry>,
+}
+
+// A standing offer to sell a funded position at a fixed price. Whoever pays the
+// asking price through transfer_position takes the investor's place on the invoice.
+#[account]
+pub struct PositionListing {
+    pub invoice: Pubkey,
+    pub seller: Pubkey,
+    pub ask_price: u64,
+    pub listed_at: i64,
+    pub bump: u8,
+}
+
+impl PositionListing {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 8 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct AdminAction {
+    pub actor: Pubkey,
+    pub role: AdminRole,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+    pub amount: Option<u64>,
+}
+
+impl AdminAction {
+    pub const SIZE: usize = 32 + 1 + 1 + 8 + (1 + 8);
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum AdminRole {
+    ProtocolAuthority,
+    BusinessOwner,
+    CollectionsAgency,
+    Governance,
+    DisputeArbiter,
+    Approver,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub enum AdminActionCode {
+    // Protocol-level
+    DailyFundingCapSet,
+    DailyFundingCapApplied,
+    RetentionPeriodSet,
+    MinInterestSet,
+    RetailGuardrailsSet,
+    ClusterIdSet,
+    ProfessionalAttestationSet,
+    MicroTierConfigured,
+    DestinationRegistered,
+    DestinationRemovalRequested,
+    DestinationRemoved,
+    DestinationAllowlistFrozen,
+    PayoutProcessorConfigured,
+    ExperimentCreated,
+    CollectionsAgencyRegistered,
+    PoolWithdrawalProposed,
+    PoolWithdrawalExecuted,
+    PoolWithdrawalCancelled,
+    HealthThresholdsSet,
+    ConfigUpdated,
+    Paused,
+    Unpaused,
+    InsurancePoolInitialized,
+    GovernanceConfigured,
+    GovernanceEnablementProposed,
+    GovernanceEnablementApplied,
+    InvestorApproved,
+    InvestorRevoked,
+    WhitelistRequirementSet,
+    BusinessVerificationSet,
+    BusinessVerificationRequirementSet,
+    OracleAuthoritySet,
+    RiskParamsUpdated,
+    DebtorExposureCapSet,
+    ExposureCapsSet,
+    MintApprovalSet,
+    FundingAcceptanceWindowSet,
+    AcknowledgmentPolicySet,
+    DisputeArbiterSet,
+    PremiumPayerSet,
+    CoverageCapacitySet,
+    OriginatorFeePolicySet,
+    AdminApproversSet,
+    AdminActionProposed,
+    AdminActionApproved,
+    AdminActionExecuted,
+    AdminActionCancelled,
+    ForeignTokensRecovered,
+    ExcessLamportsRecovered,
+    MinInsurancePremiumSet,
+    MaxOpenInvoicesSet,
+    PartyBlacklisted,
+    PartyUnblacklisted,
+    PayoutCustodyApplied,
+    EscrowCompensationSet,
+    // Per-invoice
+    PersonalDataErased,
+    CollectionsAssigned,
+    ReportedUncollectible,
+    InvoiceDelisted,
+    DisputeResolved,
+    UnclaimedRepaymentSwept,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OutboxEntry {
+    pub invoice_id: u64,
+    pub beneficiary: Pubkey,
+    pub amount: u64,
+    pub currency_mint: Pubkey,
+    pub kind: OutboxEntryKind,
+    pub status: OutboxEntryStatus,
+    pub queued_at: i64,
+    pub retries: u8,
+    pub reference_hash: [u8; 32],
+    pub processed_at: Option<i64>,
+}
+
+impl OutboxEntry {
+    pub const SIZE: usize = 8 + 32 + 8 + 32 + 1 + 1 + 8 + 1 + 32 + (1 + 8);
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub enum OutboxEntryKind {
+    Disbursement,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum OutboxEntryStatus {
+    Pending,
+    Processed,
+    Cancelled,
+}
+
+#[account]
+pub struct Experiment {
+    pub experiment_id: u64,
+    pub premium_bps_per_risk_point: u16,
+    pub traffic_bps: u16,
+    pub starts_at: i64,
+    pub ends_at: i64,
+    pub max_premium_delta_bps: u16,
+    pub control: ArmCounters,
+    pub treatment: ArmCounters,
+    pub bump: u8,
+}
+
+impl Experiment {
+    pub const SIZE: usize = 8 + 8 + 2 + 2 + 8 + 8 + 2 + ArmCounters::SIZE * 2 + 1;
+
+    pub fn is_active(&self, current_time: i64) -> bool {
+        current_time >= self.starts_at && current_time < self.ends_at
+    }
+
+    pub fn arm_mut(&mut self, arm: ExperimentArm) -> &mut ArmCounters {
+        match arm {
+            ExperimentArm::Control => &mut self.control,
+            ExperimentArm::Treatment => &mut self.treatment,
+        }
+    }
+
+    // Treatment terms for an invoice, with the arm it deterministically hashes into
+    pub fn terms(&self, business_owner: &Pubkey, invoice_id: u64) -> pricing::ExperimentTerms {
+        pricing::ExperimentTerms {
+            premium_bps_per_risk_point: self.premium_bps_per_risk_point,
+            max_premium_delta_bps: self.max_premium_delta_bps,
+            arm: experiment_arm(business_owner, invoice_id, self.traffic_bps),
+        }
+    }
+
+    // Count the invoice in the arm it was priced under (control when the treatment
+    // premium strayed further than the guardrail allows)
+    pub fn assign(&mut self, experiment: Pubkey, pricing: &pricing::Pricing) -> ExperimentAssignment {
+        let arm = pricing.experiment_arm.unwrap_or(ExperimentArm::Control);
+        self.arm_mut(arm).assigned += 1;
+
+        ExperimentAssignment {
+            experiment,
+            arm,
+            guardrail_fallback: pricing.guardrail_fallback,
+            control_premium: pricing.control_premium,
+            treatment_premium: pricing.treatment_premium.unwrap_or(pricing.control_premium),
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub struct ArmCounters {
+    pub assigned: u64,
+    pub funded: u64,
+    pub repaid: u64,
+    pub defaulted: u64,
+}
+
+impl ArmCounters {
+    pub const SIZE: usize = 8 * 4;
+
+    pub fn record(&mut self, outcome: ExperimentOutcome) {
+        match outcome {
+            ExperimentOutcome::Funded => self.funded += 1,
+            ExperimentOutcome::Repaid => self.repaid += 1,
+            ExperimentOutcome::Defaulted => self.defaulted += 1,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ExperimentArm {
+    Control,
+    Treatment,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExperimentOutcome {
+    Funded,
+    Repaid,
+    Defaulted,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct ExperimentAssignment {
+    pub experiment: Pubkey,
+    pub arm: ExperimentArm,
+    pub guardrail_fallback: bool,
+    pub control_premium: u64,
+    pub treatment_premium: u64,
+}
+
+impl ExperimentAssignment {
+    pub const SIZE: usize = 32 + 1 + 1 + 8 + 8;
+
+    pub fn applied_premium(&self) -> u64 {
+        match self.arm {
+            ExperimentArm::Control => self.control_premium,
+            ExperimentArm::Treatment => self.treatment_premium,
+        }
+    }
+}
+
+// Return types
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ListingSnapshot {
+    // listing::ListingProof in its canonical encoding
+    pub proof: Vec<u8>,
+    pub terms_hash: [u8; 32],
+    // Whether the published hash still matches the terms; None if never published
+    pub matches_published: Option<bool>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, PartialEq, Debug)]
+pub struct InvoiceDetails {
+    pub invoice_id: u64,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub funded_amount: u64,
+    pub due_date: i64,
+    pub status: InvoiceStatus,
+    pub risk_score: u8,
+    pub insurance_premium: u64,
+    pub created_at: i64,
+    pub funding_date: Option<i64>,
+    pub released_at: Option<i64>,
+    pub repayment_date: Option<i64>,
+    pub expected_return: Option<u64>,
+    pub late_fee: Option<u64>,
+    pub insurance_payout: Option<u64>,
+    pub payment_terms_days: u16,
+    pub rejection_reason: Option<RejectionReason>,
+    pub erased: bool,
+    pub premium_bps: u16,
+    pub advance_rate_bps: u16,
+    pub advance_amount: u64,
+}
+
+impl InvoiceDetails {
+    pub const SIZE: usize =
+        8 + 32 + 32 + 8 + 8 + 8 + 1 + 1 + 8 + 8 + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + 2 + (1 + 1) + 1 + 2 + 2 + 8;
+}
+
+impl From<&Invoice> for InvoiceDetails {
+    fn from(invoice: &Invoice) -> Self {
+        InvoiceDetails {
+            invoice_id: invoice.invoice_id,
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            amount: invoice.amount,
+            funded_amount: invoice.funded_amount,
+            due_date: invoice.due_date,
+            status: invoice.status,
+            risk_score: invoice.risk_score,
+            insurance_premium: invoice.insurance_premium,
+            created_at: invoice.created_at,
+            funding_date: invoice.funding_date,
+            released_at: invoice.released_at,
+            repayment_date: invoice.repayment_date,
+            expected_return: invoice.expected_return,
+            late_fee: invoice.late_fee,
+            insurance_payout: invoice.insurance_payout,
+            payment_terms_days: invoice.payment_terms_days,
+            rejection_reason: invoice.rejection_reason,
+            erased: invoice.erased,
+            premium_bps: invoice.premium_bps,
+            advance_rate_bps: invoice.advance_rate_bps,
+            advance_amount: invoice.advance_amount,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InvoiceSummary {
+    pub invoice_id: u64,
+    pub status: InvoiceStatus,
+    pub amount: u64,
+    pub due_date: i64,
+    pub risk_score: u8,
+}
+
+impl From<&Invoice> for InvoiceSummary {
+    fn from(invoice: &Invoice) -> Self {
+        InvoiceSummary {
+            invoice_id: invoice.invoice_id,
+            status: invoice.status,
+            amount: invoice.amount,
+            due_date: invoice.due_date,
+            risk_score: invoice.risk_score,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InvoiceBatch {
+    pub mode: BatchMode,
+    pub entries: Vec<InvoiceBatchEntry>,
+}
+
+impl InvoiceBatch {
+    // Serialized size of a full-details response for `count` invoices
+    pub fn full_size(count: usize) -> usize {
+        1 + 4 + count * (1 + InvoiceDetails::SIZE)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum BatchMode {
+    Full,
+    Compact,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub enum InvoiceBatchEntry {
+    Full(InvoiceDetails),
+    Compact(InvoiceSummary),
+    Unavailable(BatchEntryStatus),
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum BatchEntryStatus {
+    WrongOwner,
+    NotAnInvoice,
+    // Still to be migrated to INVOICE_VERSION
+    VersionMismatch,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct InvestorBalanceAudit {
+    pub investor: Pubkey,
+    pub available: u64,
+    pub premium_owed: u64,
+    pub custody_amount: u64,
+    pub reconciled: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct ProtocolConfig {
+    pub max_invoice_amount: u64,
+    pub max_term_days: u16,
+    pub grace_period_days: u16,
+    pub late_fee_bps_per_day: u16,
+    // Coverage tiers applied when an invoice is priced; listed invoices keep theirs
+    pub coverage: CoverageTiers,
+    // Weight and decay of a business's own history in its risk score
+    pub risk: RiskConfig,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            max_invoice_amount: DEFAULT_MAX_INVOICE_AMOUNT,
+            max_term_days: DEFAULT_MAX_TERM_DAYS,
+            grace_period_days: DEFAULT_GRACE_PERIOD_DAYS,
+            late_fee_bps_per_day: DEFAULT_LATE_FEE_BPS_PER_DAY,
+            coverage: CoverageTiers::DEFAULT,
+            risk: RiskConfig::DEFAULT,
+        }
+    }
+}
+
+impl ProtocolConfig {
+    pub const SIZE: usize = 8 + 2 + 2 + 2 + CoverageTiers::SIZE + RiskConfig::SIZE;
+
+    pub fn max_term_secs(&self) -> i64 {
+        self.max_term_days as i64 * 86400
+    }
+
+    pub fn grace_period_secs(&self) -> i64 {
+        self.grace_period_days as i64 * 86400
+    }
+
+    // Every governed value in the config, as the param history records them
+    pub fn param_values(&self) -> [(ParamId, u64); 12] {
+        [
+            (ParamId::MaxInvoiceAmount, self.max_invoice_amount),
+            (ParamId::MaxTermDays, self.max_term_days as u64),
+            (ParamId::GracePeriodDays, self.grace_period_days as u64),
+            (ParamId::LateFeeBpsPerDay, self.late_fee_bps_per_day as u64),
+            (ParamId::CoverageLow, self.coverage.low as u64),
+            (ParamId::CoverageMedium, self.coverage.medium as u64),
+            (ParamId::CoverageHigh, self.coverage.high as u64),
+            (ParamId::CoverageVeryHigh, self.coverage.very_high as u64),
+            (ParamId::RiskDefaultPoints, self.risk.default_points as u64),
+            (ParamId::RiskLateRepaymentPoints, self.risk.late_repayment_points as u64),
+            (ParamId::RiskHalfLifeMonths, self.risk.half_life_months as u64),
+            (ParamId::RiskMaxHistoryPoints, self.risk.max_history_points as u64),
+        ]
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (1..=MAX_CONFIG_INVOICE_AMOUNT).contains(&self.max_invoice_amount)
+            && (1..=MAX_CONFIG_TERM_DAYS).contains(&self.max_term_days)
+            && self.grace_period_days <= MAX_CONFIG_GRACE_PERIOD_DAYS
+            && self.late_fee_bps_per_day <= MAX_CONFIG_LATE_FEE_BPS_PER_DAY
+            && self.coverage.is_valid()
+            && self.risk.is_valid()
+    }
+}
+
+// A zero amber or red threshold disables that level
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub struct HealthThresholds {
+    // Pool balance as a share of insured exposure; below these is degraded
+    pub coverage_amber_bps: u32,
+    pub coverage_red_bps: u32,
+    // Unpaid claims; at or above these is degraded
+    pub claim_queue_amber: u32,
+    pub claim_queue_red: u32,
+    // Share of today's cap already funded; at or above these is degraded
+    pub cap_utilization_amber_bps: u16,
+    pub cap_utilization_red_bps: u16,
+}
+
+impl HealthThresholds {
+    pub const SIZE: usize = 4 + 4 + 4 + 4 + 2 + 2;
+
+    // Red must never be reached before amber
+    pub fn is_valid(&self) -> bool {
+        let ordered = |amber: u64, red: u64| amber == 0 || red == 0 || amber <= red;
+        self.coverage_red_bps <= self.coverage_amber_bps
+            && ordered(self.claim_queue_amber as u64, self.claim_queue_red as u64)
+            && ordered(self.cap_utilization_amber_bps as u64, self.cap_utilization_red_bps as u64)
+            && self.cap_utilization_amber_bps <= 10_000
+            && self.cap_utilization_red_bps <= 10_000
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum HealthStatus {
+    Green,
+    Amber,
+    Red,
+}
+
+impl HealthStatus {
+    // Grade a value that is worse the higher it gets
+    fn at_least(value: u64, amber: u64, red: u64) -> Self {
+        if red > 0 && value >= red {
+            HealthStatus::Red
+        } else if amber > 0 && value >= amber {
+            HealthStatus::Amber
+        } else {
+            HealthStatus::Green
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct HealthReport {
+    pub overall: HealthStatus,
+    pub reasons: u16,
+    pub paused: u8,
+    pub pool_coverage: HealthStatus,
+    pub coverage_ratio_bps: u64,
+    pub claim_queue: HealthStatus,
+    pub pending_claims: u64,
+    pub reconciliation: HealthStatus,
+    pub pool_token_balance: Option<u64>,
+    pub daily_cap: HealthStatus,
+    pub cap_utilization_bps: u64,
+}
+
+// Returned by get_global_stats
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Debug)]
+pub struct GlobalStats {
+    pub invoices_created: u64,
+    pub total_invoices: u64,
+    pub total_funded: u64,
+    pub total_repaid: u64,
+    pub total_repaid_amount: u64,
+    pub total_defaulted: u64,
+    pub total_defaulted_amount: u64,
+    // Defaults among invoices settled either way
+    pub default_rate_bps: u64,
+    pub total_late_fees: u64,
+    pub total_premiums: u64,
+    pub total_insurance_payouts: u64,
+    // What the program tracks against what the pool's token account holds; the
+    // drift is the token balance less the tracked one
+    pub insurance_pool_balance: u64,
+    pub pool_token_balance: Option<u64>,
+    pub pool_balance_drift: Option<i64>,
+    pub insured_exposure: u64,
+    pub claims_outstanding: u64,
+    // Insured exposure against the tracked pool balance
+    pub coverage_utilization_bps: u64,
+    // Funding still accepted today under the daily cap; u64::MAX when uncapped
+    pub remaining_daily_capacity: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct DailyFundingCapacity {
+    pub daily_funding_cap: u64,
+    pub funded_today: u64,
+    pub remaining: u64,
+    pub resets_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default)]
+pub struct AgingReport {
+    pub business_owner: Pubkey,
+    pub as_of: i64,
+    pub current: AgingBucket,
+    pub overdue_1_30: AgingBucket,
+    pub overdue_31_60: AgingBucket,
+    pub overdue_61_90: AgingBucket,
+    pub overdue_90_plus: AgingBucket,
+    pub pending_funding: AgingBucket,
+    pub defaulted: AgingBucket, // value is the recovery outstanding
+    pub total_financed: u64,
+    pub total_owed: u64,
+    pub next_due_date: Option<i64>,
+    pub invoices: Vec<AgingLine>,
+}
+
+impl AgingReport {
+    pub fn add(&mut self, invoice: &Invoice, current_time: i64, late_fee_bps_per_day: u16) -> Result<()> {
+        let mut line = AgingLine {
+            invoice_id: invoice.invoice_id,
+            status: invoice.status,
+            due_date: invoice.due_date,
+            amount_owed: 0,
+            days_overdue: 0,
+        };
+
+        match invoice.status {
+            // Nothing is owed until the business accepts an escrowed funding
+            InvoiceStatus::PendingFunding | InvoiceStatus::FundedPendingAcceptance => {
+                self.pending_funding.add(invoice.amount)
+            }
+            InvoiceStatus::Funded | InvoiceStatus::PartiallyRepaid | InvoiceStatus::Disputed => {
+                let (late_fee, days_overdue) = calculate_late_fee(invoice, current_time, late_fee_bps_per_day)?;
+                let owed = invoice.remaining_balance.checked_add(late_fee).ok_or(ErrorCode::MathOverflow)?;
+                line.amount_owed = owed;
+                line.days_overdue = days_overdue as u16;
+
+                let bucket = if current_time <= invoice.due_date {
+                    &mut self.current
+                } else {
+                    match days_overdue {
+                        0..=30 => &mut self.overdue_1_30,
+                        31..=60 => &mut self.overdue_31_60,
+                        61..=90 => &mut self.overdue_61_90,
+                        _ => &mut self.overdue_90_plus,
+                    }
+                };
+                bucket.add(owed);
+
+                self.total_financed += invoice.funded_amount;
+                self.total_owed += owed;
+                if invoice.due_date >= current_time
+                    && !matches!(self.next_due_date, Some(next) if next <= invoice.due_date)
+                {
+                    self.next_due_date = Some(invoice.due_date);
+                }
+            }
+            InvoiceStatus::Defaulted => {
+                let outstanding = invoice.remaining_balance;
+                line.amount_owed = outstanding;
+                self.defaulted.add(outstanding);
+                self.total_financed += invoice.funded_amount;
+            }
+            InvoiceStatus::Repaid | InvoiceStatus::RepaidAfterDefault => self.total_financed += invoice.funded_amount,
+            // Never funded and no longer for sale: listed, but in no bucket
+            InvoiceStatus::Delisted | InvoiceStatus::Expired | InvoiceStatus::Cancelled => {}
+        }
+
+        self.invoices.push(line);
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub struct AgingBucket {
+    pub count: u16,
+    pub value: u64,
+}
+
+impl AgingBucket {
+    pub fn add(&mut self, value: u64) {
+        self.count += 1;
+        self.value += value;
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AgingLine {
+    pub invoice_id: u64,
+    pub status: InvoiceStatus,
+    pub due_date: i64,
+    pub amount_owed: u64,
+    pub days_overdue: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExperimentResults {
+    pub experiment_id: u64,
+    pub traffic_bps: u16,
+    pub control: ArmCounters,
+    pub treatment: ArmCounters,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct RiskAssessment {
+    pub risk_score: u8,
+    pub industry_risk: u8,
+    pub estimated_credit_score: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CollectionsAgencyStats {
+    pub agency: Pubkey,
+    pub fee_bps: u16,
+    pub assignments: u32,
+    pub recovered_total: u64,
+    pub fees_earned: u64,
+    pub uncollectible_count: u32,
+}
+
+// Event size budget. The runtime keeps at most LOG_TRUNCATION_BYTES of log output per
+// transaction and silently drops everything after it, so every event has a fixed upper
+// size and every instruction a cap on the log space its events may take.
+pub const LOG_TRUNCATION_BYTES: usize = 10_000;
+pub const MAX_INSTRUCTION_EVENT_LOG_BYTES: usize = 2_000;
+
+// Log bytes taken by an event of `event_bytes`: "Program data: " plus base64
+pub const fn event_log_bytes(event_bytes: usize) -> usize {
+    "Program data: ".len() + event_bytes.div_ceil(3) * 4
+}
+
+pub trait BoundedEvent: anchor_lang::Event + Space {
+    // Discriminator plus the largest possible encoding of the fields. Space only
+    // accepts strings and vectors with a #[max_len], so this bound always exists.
+    const MAX_EVENT_BYTES: usize = 8 + Self::INIT_SPACE;
+}
+
+impl<E: anchor_lang::Event + Space> BoundedEvent for E {}
+
+// Emit an event; all emission goes through here so the size bound is checked in tests
+pub fn emit_bounded<E: BoundedEvent>(event: E) {
+    let data = event.data();
+    debug_assert!(data.len() <= E::MAX_EVENT_BYTES, "event exceeds MAX_EVENT_BYTES");
+    anchor_lang::solana_program::log::sol_log_data(&[&data]);
+}
+
+// The event authority of an #[event_cpi] context, which emit_indexed signs its
+// self-CPI with. Built with event_authority!(ctx); empty without cpi-events,
+// whose contexts have none.
+#[cfg(feature = "cpi-events")]
+#[derive(Clone, Copy)]
+pub struct EventAuthority<'a, 'info> {
+    pub info: &'a AccountInfo<'info>,
+    pub bump: u8,
+}
+
+#[cfg(not(feature = "cpi-events"))]
+#[derive(Clone, Copy)]
+pub struct EventAuthority<'a, 'info>(std::marker::PhantomData<&'a AccountInfo<'info>>);
+
+// Emit one of the events indexers depend on: InvoiceCreated, InvoiceFunded,
+// InvoiceRepaid and InsuranceClaimed. With cpi-events it goes out as the data of
+// a self-CPI, as emit_cpi! would send it, which neither log truncation nor RPCs
+// that drop logs can lose; emit_cpi! itself needs a ctx, which record_repayment
+// does not have.
+#[cfg(feature = "cpi-events")]
+pub fn emit_indexed<E: BoundedEvent>(authority: EventAuthority, event: E) -> Result<()> {
+    use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+
+    let data = event.data();
+    debug_assert!(data.len() <= E::MAX_EVENT_BYTES, "event exceeds MAX_EVENT_BYTES");
+    let ix = Instruction::new_with_bytes(
+        crate::ID,
+        &[&anchor_lang::event::EVENT_IX_TAG_LE[..], &data].concat(),
+        vec![AccountMeta::new_readonly(*authority.info.key, true)],
+    );
+    anchor_lang::solana_program::program::invoke_signed(
+        &ix,
+        std::slice::from_ref(authority.info),
+        &[&[EVENT_AUTHORITY_SEED, &[authority.bump]]],
+    )?;
+    Ok(())
+}
+
+#[cfg(not(feature = "cpi-events"))]
+pub fn emit_indexed<E: BoundedEvent>(_authority: EventAuthority, event: E) -> Result<()> {
+    emit_bounded(event);
+    Ok(())
+}
+
+// Worst-case event log usage of the instructions that emit more than one event
+const _: () = assert!(
+    event_log_bytes(InvoiceFunded::MAX_EVENT_BYTES)
+        + event_log_bytes(OutboxEntryAppended::MAX_EVENT_BYTES)
+        <= MAX_INSTRUCTION_EVENT_LOG_BYTES
+);
+const _: () = assert!(
+    event_log_bytes(InvoiceFunded::MAX_EVENT_BYTES)
+        + event_log_bytes(ReceiptMinted::MAX_EVENT_BYTES)
+        + event_log_bytes(AutoInvestExecuted::MAX_EVENT_BYTES)
+        <= MAX_INSTRUCTION_EVENT_LOG_BYTES
+);
+const _: () = assert!(
+    event_log_bytes(FundingContributed::MAX_EVENT_BYTES)
+        + event_log_bytes(InvoiceFunded::MAX_EVENT_BYTES)
+        <= MAX_INSTRUCTION_EVENT_LOG_BYTES
+);
+const _: () = assert!(
+    event_log_bytes(RepaymentReceived::MAX_EVENT_BYTES)
+        + event_log_bytes(InvoiceRepaid::MAX_EVENT_BYTES)
+        <= MAX_INSTRUCTION_EVENT_LOG_BYTES
+);
+const _: () = assert!(
+    MAX_REPAYMENT_BATCH
+        * (event_log_bytes(RepaymentReceived::MAX_EVENT_BYTES) + event_log_bytes(InvoiceRepaid::MAX_EVENT_BYTES))
+        <= MAX_INSTRUCTION_EVENT_LOG_BYTES
+);
+const _: () = assert!(
+    event_log_bytes(ReceiptRedeemed::MAX_EVENT_BYTES)
+        + event_log_bytes(ReceiptBurned::MAX_EVENT_BYTES)
+        + event_log_bytes(InsuranceClaimed::MAX_EVENT_BYTES)
+        <= MAX_INSTRUCTION_EVENT_LOG_BYTES
+);
+const _: () = assert!(
+    event_log_bytes(ConfigUpdated::MAX_EVENT_BYTES)
+        + event_log_bytes(AdminActionExecuted::MAX_EVENT_BYTES)
+        <= MAX_INSTRUCTION_EVENT_LOG_BYTES
+);
+const _: () = assert!(
+    event_log_bytes(BundleRepaymentAllocated::MAX_EVENT_BYTES)
+        + event_log_bytes(BundleSettled::MAX_EVENT_BYTES)
+        <= MAX_INSTRUCTION_EVENT_LOG_BYTES
+);
+
+// Stand-in for free text in events: the sha256 of the full text plus its first bytes,
+// so indexers can match and display it while the event keeps a fixed size
+pub const BOUNDED_TEXT_PREFIX: usize = 32;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct BoundedText {
+    pub hash: [u8; 32],
+    pub len: u16,
+    pub prefix: [u8; BOUNDED_TEXT_PREFIX],
+}
+
+impl BoundedText {
+    pub fn new(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let mut prefix = [0u8; BOUNDED_TEXT_PREFIX];
+        let kept = bytes.len().min(BOUNDED_TEXT_PREFIX);
+        prefix[..kept].copy_from_slice(&bytes[..kept]);
+        Self {
+            hash: anchor_lang::solana_program::hash::hash(bytes).to_bytes(),
+            len: bytes.len().min(u16::MAX as usize) as u16,
+            prefix,
+        }
+    }
+}
+
+// Enhanced events
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceCreated {
+    pub invoice_id: u64,
+    pub business_owner: Pubkey,
+    pub amount: u64,
+    pub industry: IndustryCode,
+    pub debtor: Pubkey,
+    pub mint: Pubkey,
+    pub risk_score: u8,
+    pub insurance_premium: u64,
+    pub estimated_yield: u16,
+    pub invoice: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+    // What the investor advances against `amount` when factored; 0 at par
+    pub funding_mode: FundingMode,
+    pub advance_amount: u64,
+    // Funding the protocol still accepts today under the daily cap
+    pub remaining_daily_capacity: u64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct MicroInvoiceCreated {
+    pub invoice_id: u64,
+    pub business_owner: Pubkey,
+    pub amount: u64,
+    pub industry: IndustryCode,
+    pub debtor: Pubkey,
+    pub mint: Pubkey,
+    pub risk_score: u8,
+    pub insurance_premium: u64,
+    pub invoice: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+    pub funding_mode: FundingMode,
+    pub advance_amount: u64,
+    pub remaining_daily_capacity: u64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceAcknowledged {
+    pub invoice_id: u64,
+    pub business_owner: Pubkey,
+    pub debtor_wallet: Pubkey,
+    pub risk_score: u8,
+    pub insurance_premium: u64,
+    pub invoice: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceAmended {
+    pub invoice_id: u64,
+    pub old_amount: u64,
+    pub amount: u64,
+    pub old_due_date: i64,
+    pub due_date: i64,
+    pub old_debtor_info_hash: [u8; 32],
+    pub debtor_info_hash: [u8; 32],
+    pub old_risk_score: u8,
+    pub risk_score: u8,
+    pub old_insurance_premium: u64,
+    pub insurance_premium: u64,
+    pub payment_terms_days: u16,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+    pub remaining_daily_capacity: u64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct MicroTierConfigured {
+    pub max_amount: u64,
+    pub risk_score: u8,
+    pub premium_bps: u16,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceDelisted {
+    pub invoice_id: u64,
+    pub business_owner: Pubkey,
+    pub reason: RejectionReason,
+    pub hint: RemediationHint,
+    pub action: AdminActionCode,
+    pub invoice: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PairLedgerClosed {
+    pub business: Pubkey,
+    pub investor: Pubkey,
+    pub invoices_financed: u32,
+    pub total_financed: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ReceiptMinted {
+    pub invoice_id: u64,
+    pub mint: Pubkey,
+    pub holder: Pubkey,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct FundingEscrowed {
+    pub invoice_id: u64,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub insurance_premium: u64,
+    pub deadline: i64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct FundingAccepted {
+    pub invoice_id: u64,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub insurance_premium: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct EscrowReclaimed {
+    pub invoice_id: u64,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub insurance_premium: u64,
+    // Paid by the treasury on top of the refund
+    pub compensation: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ReceiptRedeemed {
+    pub invoice_id: u64,
+    pub holder: Pubkey,
+    pub amount: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct UnclaimedRepaymentSwept {
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub action: AdminActionCode,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ReceiptBurned {
+    pub invoice_id: u64,
+    pub mint: Pubkey,
+    pub holder: Pubkey,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PositionListed {
+    pub invoice_id: u64,
+    pub seller: Pubkey,
+    pub ask_price: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PositionTransferred {
+    pub invoice_id: u64,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub price: u64,
+    // Principal still owed, and what the new holder stands to collect in total
+    pub remaining_balance: u64,
+    pub expected_return: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceCancelled {
+    pub invoice_id: u64,
+    pub business_owner: Pubkey,
+    pub invoice: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceExpired {
+    pub invoice_id: u64,
+    pub funding_deadline: i64,
+    // Contributions still in the vault, for their holders to withdraw
+    pub funded_amount: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+// A funded invoice past its due date, from ping_overdue
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceOverdue {
+    pub invoice_id: u64,
+    pub due_date: i64,
+    pub days_overdue: u64,
+    // Unpaid late fee as of now, including days not yet folded into the invoice
+    pub accrued_late_fee: u64,
+    pub remaining_balance: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+// Last record of an invoice before close_invoice deletes its account
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceClosed {
+    pub invoice_id: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub mint: Pubkey,
+    pub status: InvoiceStatus,
+    pub amount: u64,
+    pub funded_amount: u64,
+    pub amount_repaid: u64,
+    pub interest_paid: u64,
+    pub late_fee: u64,
+    pub insurance_premium: u64,
+    pub insurance_payout: u64,
+    pub recovered_amount: u64,
+    pub created_at: i64,
+    pub funding_date: Option<i64>,
+    pub settled_at: i64,
+    pub closed_at: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceFunded {
+    pub invoice_id: u64,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub insurance_premium: u64,
+    // Face value plus the yield fixed at funding, from apr_bps over the term left
+    pub expected_return: u64,
+    pub apr_bps: u16,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+    // Insured exposure against the pool balance after the funding, in bps
+    pub coverage_utilization_bps: u64,
+    // Paid to the invoice's originator; 0 without one
+    pub originator_fee: u64,
+    // `amount` is the face value at par, or the advance against face_value when
+    // factored, whose expected_return is then face_value itself
+    pub funding_mode: FundingMode,
+    pub face_value: u64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceRepaid {
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub late_fee: u64,
+    pub days_overdue: u16,
+    // Interest the investor received on top of principal; the yield component less
+    // early_repayment_discount
+    pub yield_paid: u64,
+    pub early_repayment_discount: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct RepaymentReceived {
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub late_fee_paid: u64,
+    pub remaining_balance: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+    // PartiallyRepaid, or Repaid with InvoiceRepaid following
+    pub status: InvoiceStatus,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ProfessionalAttestationSet {
+    pub investor: Pubkey,
+    pub professional: bool,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BusinessRegistered {
+    pub business_owner: Pubkey,
+    pub jurisdiction: [u8; 2],
+    pub industry_code: u32,
+    pub document_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BusinessVerificationSet {
+    pub business_owner: Pubkey,
+    pub verified: bool,
+    pub credit_score: u16,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BusinessVerificationRequirementSet {
+    pub enabled: bool,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DebtorExposureCapSet {
+    pub previous: u64,
+    pub cap: u64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ExposureCapsSet {
+    pub previous_investor_cap: u64,
+    pub previous_business_cap: u64,
+    pub investor_cap: u64,
+    pub business_cap: u64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct MintApprovalSet {
+    pub mint: Pubkey,
+    pub approved: bool,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct FundingAcceptanceWindowSet {
+    pub previous_window_secs: i64,
+    pub window_secs: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PremiumPayerSet {
+    pub business_pays: bool,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct CoverageCapacitySet {
+    pub previous: u32,
+    pub capacity_bps: u32,
+    pub utilization_bps: u64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct OriginatorFeePolicySet {
+    pub previous_cap_bps: u16,
+    pub cap_bps: u16,
+    pub from_principal: bool,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct OriginatorRegistered {
+    pub originator: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct AcknowledgmentPolicySet {
+    pub previous: AcknowledgmentPolicy,
+    pub policy: AcknowledgmentPolicy,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DisputeArbiterSet {
+    pub previous: Option<Pubkey>,
+    pub arbiter: Option<Pubkey>,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct RiskParamsUpdated {
+    pub old_params: RiskParams,
+    pub new_params: RiskParams,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct OracleAuthoritySet {
+    pub previous: Option<Pubkey>,
+    pub oracle_authority: Option<Pubkey>,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvestorApproved {
+    pub investor: Pubkey,
+    pub expires_at: Option<i64>,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvestorRevoked {
+    pub investor: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PartyBlacklisted {
+    pub party: Pubkey,
+    pub role: BlacklistRole,
+    pub reason: BlacklistReason,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PartyUnblacklisted {
+    pub party: Pubkey,
+    pub role: BlacklistRole,
+    pub reason: BlacklistReason,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct WhitelistRequirementSet {
+    pub enabled: bool,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct CollectionsAgencyRegistered {
+    pub agency: Pubkey,
+    pub fee_bps: u16,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct CollectionsAssigned {
+    pub invoice_id: u64,
+    pub agency: Pubkey,
+    pub fee_bps: u16,
+    pub action: AdminActionCode,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct RecoveryRemitted {
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub agency_fee: u64,
+    pub to_pool: u64,
+    pub to_investor: u64,
+    pub to_business: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct RecoverySettled {
+    pub invoice_id: u64,
+    pub settled_by: Pubkey,
+    pub amount: u64,
+    pub agency_fee: u64,
+    pub to_pool: u64,
+    pub to_investor: u64,
+    pub to_business: u64,
+    pub recovered_amount: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceRepaidAfterDefault {
+    pub invoice_id: u64,
+    pub insurance_payout: u64,
+    // Everything settlements returned to the pool, and paid the investor
+    pub insurance_clawback: u64,
+    pub to_investor: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvestmentPoolCreated {
+    pub pool: Pubkey,
+    pub manager: Pubkey,
+    pub share_mint: Pubkey,
+    pub vault: Pubkey,
+    pub max_risk_score: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PoolDeposited {
+    pub pool: Pubkey,
+    pub depositor: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    // Net asset value the shares were priced at
+    pub nav: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PoolRedeemed {
+    pub pool: Pubkey,
+    pub holder: Pubkey,
+    pub shares: u64,
+    pub amount: u64,
+    pub nav: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PoolInvoiceFunded {
+    pub pool: Pubkey,
+    pub invoice_id: u64,
+    pub cost: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PoolPositionSettled {
+    pub pool: Pubkey,
+    pub invoice_id: u64,
+    pub cost: u64,
+    // What the settlement paid into the vault
+    pub proceeds: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct AutoInvestMandateCreated {
+    pub investor: Pubkey,
+    pub rules: MandateRules,
+    pub allowance: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct AutoInvestMandatePaused {
+    pub investor: Pubkey,
+    pub paused: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct AutoInvestMandateRevoked {
+    pub investor: Pubkey,
+    pub invoices_funded: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct AutoInvestExecuted {
+    pub investor: Pubkey,
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub premium: u64,
+    pub executed_by: Pubkey,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DebtReportedUncollectible {
+    pub invoice_id: u64,
+    pub business_owner: Pubkey,
+    pub agency: Pubkey,
+    pub recovered: u64,
+    pub action: AdminActionCode,
+    pub invoice: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DueDateExtended {
+    pub invoice_id: u64,
+    pub old_due_date: i64,
+    pub new_due_date: i64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BookRootCommitted {
+    pub index: u64,
+    pub root: [u8; 32],
+    pub first_nonce: u64,
+    pub last_nonce: u64,
+    pub leaf_count: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceDefaulted {
+    pub invoice_id: u64,
+    pub days_overdue: u16,
+    pub outstanding_principal: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DisputeRaised {
+    pub invoice_id: u64,
+    pub raised_by: Pubkey,
+    pub reason_hash: [u8; 32],
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DisputeResolved {
+    pub invoice_id: u64,
+    pub resolver: Pubkey,
+    pub resolution: DisputeResolution,
+    pub remaining_balance: u64,
+    pub action: AdminActionCode,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InsuranceClaimed {
+    pub invoice_id: u64,
+    pub investor: Pubkey,
+    pub payout_amount: u64,
+    pub coverage_percentage: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+    pub kind: InsurancePayoutKind,
+    // Left for claim_remaining_insurance
+    pub payout_outstanding: u64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InsuranceRemainderPaid {
+    pub invoice_id: u64,
+    pub investor: Pubkey,
+    pub payout_amount: u64,
+    pub payout_outstanding: u64,
+    pub kind: InsurancePayoutKind,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub enum InsurancePayoutKind {
+    // Nothing more is owed on the claim
+    Full,
+    // The pool was short; the rest is outstanding on the invoice
+    Partial,
+}
+
+impl InsurancePayoutKind {
+    pub fn of(outstanding: u64) -> Self {
+        if outstanding == 0 {
+            InsurancePayoutKind::Full
+        } else {
+            InsurancePayoutKind::Partial
+        }
+    }
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct FundingContributed {
+    pub invoice_id: u64,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub premium: u64,
+    pub funded_amount: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ContributionWithdrawn {
+    pub invoice_id: u64,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub premium_refunded: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ShareRepaymentClaimed {
+    pub invoice_id: u64,
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub total_claimed: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InsuranceLiquidityDeposited {
+    pub provider: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InsuranceWithdrawalRequested {
+    pub provider: Pubkey,
+    pub shares: u64,
+    pub available_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InsuranceLiquidityWithdrawn {
+    pub provider: Pubkey,
+    pub shares: u64,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct AdminApproversSet {
+    #[max_len(MAX_ADMIN_APPROVERS)]
+    pub approvers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub window_secs: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+// The proposed instruction is identified by its discriminator, its arguments by
+// the sha256 of the full data; the proposal account holds both in full
+#[event]
+#[derive(InitSpace)]
+pub struct AdminActionProposed {
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub instruction: [u8; 8],
+    pub data_hash: [u8; 32],
+    pub expires_at: i64,
+    pub approvals: u8,
+    pub threshold: u8,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct AdminActionApproved {
+    pub proposal_id: u64,
+    pub approver: Pubkey,
+    pub approvals: u8,
+    pub threshold: u8,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct AdminActionExecuted {
+    pub proposal_id: u64,
+    pub executor: Pubkey,
+    pub instruction: [u8; 8],
+    pub approvals: u8,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct AdminActionCancelled {
+    pub proposal_id: u64,
+    pub cancelled_by: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ForeignTokensRecovered {
+    pub kind: ProgramVault,
+    pub source: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub destination: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ExcessLamportsRecovered {
+    pub account: Pubkey,
+    pub amount: u64,
+    pub destination: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PoolWithdrawalProposed {
+    pub amount: u64,
+    pub destination: Pubkey,
+    pub executable_at: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PoolWithdrawalExecuted {
+    pub amount: u64,
+    pub destination: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PoolWithdrawalCancelled {
+    pub amount: u64,
+    pub destination: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BalanceDeposited {
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub available: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BalanceWithdrawn {
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub available: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BalancePremiumSwept {
+    pub investor: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DailyFundingCapUpdated {
+    pub old_cap: u64,
+    pub new_cap: u64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DailyFundingCapIncreaseProposed {
+    pub old_cap: u64,
+    pub new_cap: u64,
+    pub effective_at: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InvoiceMigrated {
+    pub invoice_id: u64,
+    pub from_version: u8,
+    pub version: u8,
+    pub debtor_info_hash: [u8; 32],
+    pub previous_size: u32,
+    pub size: u32,
+    pub rent_refunded: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct GlobalStateMigrated {
+    pub from_version: u8,
+    pub version: u8,
+    pub previous_size: u32,
+    pub size: u32,
+    pub rent_refunded: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PersonalDataErased {
+    pub invoice_id: u64,
+    pub business_owner: Pubkey,
+    pub erased_by: Pubkey,
+    pub content_hash: [u8; 32],
+    pub erased_at: i64,
+    pub action: AdminActionCode,
+    pub invoice: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DestinationRegistered {
+    pub vault: VaultKind,
+    pub name: [u8; 32],
+    pub token_account: Pubkey,
+    pub active_at: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DestinationRemovalRequested {
+    pub vault: VaultKind,
+    pub token_account: Pubkey,
+    pub removable_at: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DestinationRemoved {
+    pub vault: VaultKind,
+    pub token_account: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct DestinationAllowlistFrozen {
+    pub vault: VaultKind,
+    pub cleared: u8,
+    pub frozen_at: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PayoutProcessorConfigured {
+    pub processor: Pubkey,
+    pub custody: Pubkey,
+    pub ack_timeout_secs: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PayoutCustodyChangeProposed {
+    pub old_custody: Pubkey,
+    pub new_custody: Pubkey,
+    pub effective_at: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PayoutCustodyChanged {
+    pub old_custody: Pubkey,
+    pub new_custody: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace, Clone)]
+pub struct VaultIntegrityReport {
+    pub vault: Pubkey,
+    pub kind: ProgramVault,
+    pub integrity: VaultIntegrity,
+    pub amount: u64,
+    // What the program's accounting says the vault holds, where it keeps one
+    pub expected_amount: Option<u64>,
+    pub sound: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct OutboxEntryAppended {
+    pub index: u64,
+    pub invoice_id: u64,
+    pub beneficiary: Pubkey,
+    pub amount: u64,
+    pub kind: OutboxEntryKind,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct OutboxEntryAcked {
+    pub index: u64,
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub reference_hash: [u8; 32],
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct OutboxEntryRetried {
+    pub index: u64,
+    pub invoice_id: u64,
+    pub retries: u8,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct OutboxEntryCancelled {
+    pub index: u64,
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ExperimentCreated {
+    pub experiment_id: u64,
+    pub premium_bps_per_risk_point: u16,
+    pub traffic_bps: u16,
+    pub starts_at: i64,
+    pub ends_at: i64,
+    pub max_premium_delta_bps: u16,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+// A page of a business's records was exported
+#[event]
+#[derive(InitSpace)]
+pub struct ExportRequested {
+    pub business: Pubkey,
+    pub cursor: u64,
+    pub records: u8,
+    // The page was the export's last
+    pub complete: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ListingProofPublished {
+    pub invoice_id: u64,
+    pub terms_hash: [u8; 32],
+    pub slot: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct GlobalStateInitialized {
+    pub global_state: Pubkey,
+    pub authority: Pubkey,
+    pub usdc_mint: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct InsurancePoolInitialized {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct PauseFlagsUpdated {
+    pub old_flags: u8,
+    pub new_flags: u8,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct GovernanceConfigured {
+    pub governance_program: Pubkey,
+    pub realm: Pubkey,
+    pub governance_account: Pubkey,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct GovernanceEnablementProposed {
+    pub enabled: bool,
+    pub effective_at: i64,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct GovernanceEnablementApplied {
+    pub enabled: bool,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct ConfigUpdated {
+    pub old_config: ProtocolConfig,
+    pub new_config: ProtocolConfig,
+    pub action: AdminActionCode,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BundleCreated {
+    pub bundle_id: u64,
+    pub business_owner: Pubkey,
+    #[max_len(MAX_BUNDLE_SIZE)]
+    pub invoice_ids: Vec<u64>,
+    pub amount: u64,
+    pub risk_score: u8,
+    pub insurance_premium: u64,
+    pub bundle: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BundleDissolved {
+    pub bundle_id: u64,
+    pub business_owner: Pubkey,
+    #[max_len(MAX_BUNDLE_SIZE)]
+    pub invoice_ids: Vec<u64>,
+    pub bundle: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BundleFunded {
+    pub bundle_id: u64,
+    pub investor: Pubkey,
+    #[max_len(MAX_BUNDLE_SIZE)]
+    pub invoice_ids: Vec<u64>,
+    pub amount: u64,
+    pub insurance_premium: u64,
+    pub expected_return: u64,
+    pub bundle: Pubkey,
+    pub business_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct ConstituentRepayment {
+    pub invoice_id: u64,
+    pub amount: u64,
+    pub remaining_balance: u64,
+    pub invoice: Pubkey,
+    pub status: InvoiceStatus,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BundleRepaymentAllocated {
+    pub bundle_id: u64,
+    pub amount: u64,
+    #[max_len(MAX_BUNDLE_SIZE)]
+    pub allocations: Vec<ConstituentRepayment>,
+    pub bundle: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+#[derive(InitSpace)]
+pub struct BundleSettled {
+    pub bundle_id: u64,
+    pub amount_repaid: u64,
+    pub bundle: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub timestamp: i64,
+}
+
+// Enhanced error codes
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Amount too large")]
+    AmountTooLarge,
+    #[msg("Invalid due date")]
+    InvalidDueDate,
+    #[msg("Due date too far in future")]
+    DueDateTooFar,
+    #[msg("Debtor info URI is longer than MAX_DEBTOR_INFO_URI_LEN")]
+    DebtorInfoTooLong,
+    #[msg("Debtor info hash is missing")]
+    DebtorInfoTooShort,
+    #[msg("Invoice not available for funding")]
+    InvoiceNotAvailable,
+    #[msg("Invalid funding amount - must fund full amount")]
+    InvalidFundingAmount,
+    #[msg("Insufficient funds in investor account")]
+    InsufficientFunds,
+    #[msg("Invoice not funded")]
+    InvoiceNotFunded,
+    #[msg("Insufficient repayment amount")]
+    InsufficientRepayment,
+    #[msg("Insufficient funds for repayment")]
+    InsufficientRepaymentFunds,
+    #[msg("Repayment period has expired")]
+    RepaymentPeriodExpired,
+    #[msg("Not eligible for insurance claim")]
+    NotEligibleForClaim,
+    #[msg("Unauthorized insurance claim")]
+    UnauthorizedInsuranceClaim,
+    #[msg("Insufficient insurance pool funds")]
+    InsufficientInsurancePool,
+    #[msg("Signer is not the protocol authority")]
+    Unauthorized,
+    #[msg("Destination is not allowlisted for this vault")]
+    DestinationNotAllowlisted,
+    #[msg("Destination already registered")]
+    DestinationAlreadyRegistered,
+    #[msg("Destination not found in allowlist")]
+    DestinationNotFound,
+    #[msg("Destination allowlist is full")]
+    AllowlistFull,
+    #[msg("Destination token account has the wrong mint")]
+    InvalidDestinationMint,
+    #[msg("Destination removal was not requested")]
+    RemovalNotRequested,
+    #[msg("Timelock has not elapsed")]
+    TimelockNotElapsed,
+    #[msg("Invalid experiment parameters")]
+    InvalidExperimentParams,
+    #[msg("Invoice is part of an experiment but the experiment account is missing")]
+    ExperimentAccountMissing,
+    #[msg("Experiment account does not match the invoice assignment")]
+    ExperimentMismatch,
+    #[msg("Off-ramp invoice requires the payout processor, outbox page and escrow accounts")]
+    OutboxAccountsMissing,
+    #[msg("Outbox escrow account does not match the payout processor")]
+    InvalidOutboxEscrow,
+    #[msg("Outbox page does not hold this entry")]
+    OutboxPageMismatch,
+    #[msg("Outbox entry not found")]
+    OutboxEntryNotFound,
+    #[msg("Outbox entry is not pending")]
+    OutboxEntryNotPending,
+    #[msg("Outbox entry has not timed out yet")]
+    OutboxEntryNotTimedOut,
+    #[msg("Invalid acknowledgment timeout")]
+    InvalidAckTimeout,
+    #[msg("Account is not the instructions sysvar")]
+    InvalidInstructionsSysvar,
+    #[msg("Ed25519 signature instruction must directly precede this instruction")]
+    MissingEd25519Instruction,
+    #[msg("Malformed Ed25519 signature instruction")]
+    InvalidEd25519Instruction,
+    #[msg("Message was signed by an unexpected key")]
+    SignerMismatch,
+    #[msg("Signed message does not match the expected message")]
+    SignedMessageMismatch,
+    #[msg("Signed message has expired")]
+    SignedMessageExpired,
+    #[msg("Signature nonce already used")]
+    SignatureNonceReused,
+    #[msg("Too many invoices for one request")]
+    TooManyInvoices,
+    #[msg("Account is not an invoice of this program")]
+    InvalidInvoiceAccount,
+    #[msg("Invoice belongs to a different business")]
+    InvoiceOwnerMismatch,
+    #[msg("Invoice is not in a terminal state")]
+    InvoiceNotTerminal,
+    #[msg("Retention period has not lapsed")]
+    RetentionPeriodActive,
+    #[msg("Personal data already erased")]
+    AlreadyErased,
+    #[msg("Invalid retention period")]
+    InvalidRetentionPeriod,
+    #[msg("Daily funding cap reached")]
+    DailyCapReached,
+    #[msg("No pending change to apply")]
+    NoPendingChange,
+    #[msg("Invoice does not accept partial funding")]
+    PartialFundingNotEnabled,
+    #[msg("Partially funded invoices must be funded through contribute_funding")]
+    PartialFundingInvoice,
+    #[msg("Partial funding cannot be combined with an off-ramp payout")]
+    PartialFundingOfframpUnsupported,
+    #[msg("Funding window closed at the funding deadline")]
+    FundingWindowClosed,
+    #[msg("The funding window is still open")]
+    FundingWindowOpen,
+    #[msg("Invoice vault account is required for partially funded invoices")]
+    InvoiceVaultMissing,
+    #[msg("Investor token account is required")]
+    InvestorAccountMissing,
+    #[msg("Nothing to claim")]
+    NothingToClaim,
+    #[msg("Invoice already has investor contributions")]
+    InvoiceHasContributions,
+    #[msg("Invalid micro tier configuration")]
+    InvalidMicroTierConfig,
+    #[msg("Repayment exceeds the outstanding balance")]
+    RepaymentExceedsBalance,
+    #[msg("Minimum interest must be at most 10000 bps")]
+    InvalidMinInterest,
+    #[msg("Collections fee exceeds the maximum")]
+    InvalidCollectionsFee,
+    #[msg("Invoice has not defaulted")]
+    InvoiceNotDefaulted,
+    #[msg("Collections agency accounts are required while an assignment is active")]
+    CollectionsAccountsMissing,
+    #[msg("Collections agency does not match the assignment")]
+    CollectionsAgencyMismatch,
+    #[msg("Collections assignment is not active")]
+    CollectionsNotActive,
+    #[msg("Position too large for a first-time investor: keep each invoice under 20% of deployed capital")]
+    DiversificationLimit,
+    #[msg("Risk tier locked until three repayments have completed")]
+    RiskTierLocked,
+    #[msg("Grace period after the due date has not passed")]
+    GracePeriodActive,
+    #[msg("Insurance already claimed for this invoice")]
+    InsuranceAlreadyClaimed,
+    #[msg("Signer is not the invoice's investor")]
+    UnauthorizedInvestor,
+    #[msg("Due date has already been extended")]
+    DueDateAlreadyExtended,
+    #[msg("Extension exceeds 90 days")]
+    ExtensionTooLong,
+    #[msg("Admin log page does not hold the next admin action")]
+    AdminLogPageMismatch,
+    #[msg("Funding from balance requires the investor balance and custody accounts")]
+    InvestorBalanceMissing,
+    #[msg("Custody account does not belong to this investor balance")]
+    InvalidCustodyAccount,
+    #[msg("Amount exceeds the balance available to withdraw")]
+    BalanceUnavailable,
+    #[msg("Not enough insurance pool shares")]
+    InsufficientLpShares,
+    #[msg("A withdrawal is already pending")]
+    WithdrawalAlreadyRequested,
+    #[msg("No withdrawal has been requested")]
+    WithdrawalNotRequested,
+    #[msg("Withdrawal would leave committed insurance coverage unfunded")]
+    PoolUtilizationLimit,
+    #[msg("Export pages take invoice and audit log pairs for the business's invoice ids from the cursor on")]
+    InvalidExportPage,
+    #[msg("Pricing inputs are for an unsupported pricing version")]
+    UnsupportedPricingVersion,
+    #[msg("Health thresholds are out of range or red comes before amber")]
+    InvalidHealthThresholds,
+    #[msg("A bundle holds between 2 and 10 invoices")]
+    InvalidBundleSize,
+    #[msg("Invoice is locked in a bundle")]
+    InvoiceBundled,
+    #[msg("Accounts do not match the bundle's invoices")]
+    BundleConstituentMismatch,
+    #[msg("Partial-funding and off-ramp invoices cannot be bundled")]
+    BundleConstituentIneligible,
+    #[msg("Bundled invoices must share a debtor")]
+    BundleDebtorMismatch,
+    #[msg("Bundle is no longer open")]
+    BundleNotOpen,
+    #[msg("Bundle is not funded")]
+    BundleNotFunded,
+    #[msg("Bundle still has invoices being repaid")]
+    BundleNotSettled,
+    #[msg("Config value out of range")]
+    InvalidConfig,
+    #[msg("Listing proof bytes are malformed")]
+    InvalidListingProof,
+    #[msg("Listing proof does not match the invoice")]
+    ListingProofMismatch,
+    #[msg("This activity is paused")]
+    ProtocolPaused,
+    #[msg("Unknown pause flags")]
+    InvalidPauseFlags,
+    #[msg("Amount moved beyond the caller's slippage bound")]
+    SlippageExceeded,
+    #[msg("Param history page is not the one the next change lands in")]
+    ParamHistoryPageMismatch,
+    #[msg("Every param history page must be supplied, in order")]
+    ParamHistoryIncomplete,
+    #[msg("Token account is not for the protocol's USDC mint")]
+    TokenMintMismatch,
+    #[msg("Token account belongs to someone else")]
+    TokenOwnerMismatch,
+    #[msg("Outbox entry belongs to a different invoice")]
+    OutboxInvoiceMismatch,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+    #[msg("Program-controlled token account has a delegate")]
+    VaultDelegateSet,
+    #[msg("Program-controlled token account has a foreign close authority")]
+    VaultCloseAuthoritySet,
+    #[msg("Account is not the expected program vault")]
+    VaultAddressMismatch,
+    #[msg("Accounts needed to locate this vault were not supplied")]
+    VaultContextMissing,
+    #[msg("Listing was rejected by review")]
+    ListingRejected,
+    #[msg("Only review reasons can be given when delisting")]
+    NotAReviewReason,
+    #[msg("The pair still has invoices outstanding")]
+    PairExposureOutstanding,
+    #[msg("Only live, wholly held positions can be transferred")]
+    PositionNotTransferable,
+    #[msg("Seller no longer holds this position")]
+    PositionListingStale,
+    #[msg("Position receipt missing, or not held by the signer")]
+    InvalidPositionReceipt,
+    #[msg("Invoice has no position receipt")]
+    NoPositionReceipt,
+    #[msg("This instruction must come through governance")]
+    GovernanceRequired,
+    #[msg("No governance program configured")]
+    GovernanceNotConfigured,
+    #[msg("Book commitment is full")]
+    BookFull,
+    #[msg("Invoices must follow the commitment's last nonce, in order, up to its coverage limit")]
+    BookNonceOutOfOrder,
+    #[msg("Too soon since the last book commitment")]
+    BookCommitTooSoon,
+    #[msg("Investor is not approved, or the approval has expired")]
+    InvestorNotWhitelisted,
+    #[msg("Whitelist expiry must be in the future")]
+    InvalidWhitelistExpiry,
+    #[msg("Business is not verified")]
+    BusinessNotVerified,
+    #[msg("Business has not registered its KYC details")]
+    BusinessNotRegistered,
+    #[msg("Business documents changed since they were reviewed")]
+    BusinessProfileChanged,
+    #[msg("Credit score must be between 300 and 850")]
+    InvalidCreditScore,
+    #[msg("Jurisdiction must be an ISO 3166-1 alpha-2 code")]
+    InvalidJurisdiction,
+    #[msg("No credit oracle is configured")]
+    OracleNotConfigured,
+    #[msg("Credit attestation has expired")]
+    CreditAttestationExpired,
+    #[msg("A newer credit attestation has already been used")]
+    CreditAttestationSuperseded,
+    #[msg("Risk bands must be ordered and weights at most 50")]
+    InvalidRiskParams,
+    #[msg("Debtor id must be a non-zero hash")]
+    InvalidDebtorId,
+    #[msg("Invoice is listed against a debtor; pass its registry entry")]
+    DebtorAccountMissing,
+    #[msg("Debtor account does not match the invoice's debtor")]
+    DebtorMismatch,
+    #[msg("Funding would exceed the exposure cap for this debtor")]
+    DebtorConcentrationExceeded,
+    #[msg("Funding would exceed the investor's exposure cap")]
+    InvestorExposureExceeded,
+    #[msg("Funding would exceed the business's exposure cap")]
+    BusinessExposureExceeded,
+    #[msg("Pass the investor stats account the funding exposure is booked in")]
+    InvestorStatsMissing,
+    #[msg("Mint is not approved for invoices")]
+    MintNotApproved,
+    #[msg("Insurance, partial funding, off-ramp payouts and custody balances use the primary mint only")]
+    PrimaryMintOnly,
+    #[msg("The primary mint is always approved")]
+    PrimaryMintFixed,
+    #[msg("No room for another approved mint")]
+    ApprovedMintsFull,
+    #[msg("Mint decimals differ from the primary mint's")]
+    MintDecimalsMismatch,
+    #[msg("Funding acceptance window out of range")]
+    InvalidAcceptanceWindow,
+    #[msg("Invoice has no funding waiting for acceptance")]
+    FundingNotEscrowed,
+    #[msg("Acceptance deadline has passed")]
+    AcceptanceWindowClosed,
+    #[msg("Acceptance deadline has not passed yet")]
+    AcceptanceWindowOpen,
+    #[msg("Signer is not the debtor wallet named on the invoice")]
+    DebtorWalletMismatch,
+    #[msg("Invoice already acknowledged by its debtor")]
+    InvoiceAlreadyAcknowledged,
+    #[msg("Invoice must be acknowledged by its debtor before funding")]
+    DebtorAcknowledgmentRequired,
+    #[msg("Invalid acknowledgment policy")]
+    InvalidAcknowledgmentPolicy,
+    #[msg("Invoice is under dispute")]
+    InvoiceDisputed,
+    #[msg("Invoice has no dispute to resolve")]
+    NoActiveDispute,
+    #[msg("Adjusted balance must be positive and below the unpaid principal")]
+    InvalidDisputeAdjustment,
+    #[msg("Business token account is required")]
+    BusinessAccountMissing,
+    #[msg("No unclaimed repayment can be swept from this invoice yet")]
+    UnclaimedSweepNotOpen,
+    #[msg("Insurance pool accounts are required")]
+    InsurancePoolAccountMissing,
+    #[msg("Invoice is outside the pool's mandate")]
+    PoolMandateExceeded,
+    #[msg("Pool funds can only move through the pool vault")]
+    PoolVaultMismatch,
+    #[msg("Pool vault cannot cover this redemption right now")]
+    PoolIlliquid,
+    #[msg("Pool position is not settled yet")]
+    PoolPositionOpen,
+    #[msg("Pool has shares outstanding but nothing to back them")]
+    PoolInsolvent,
+    #[msg("Mandate rules need a positive amount range, term and exposure cap")]
+    InvalidMandateRules,
+    #[msg("Auto-invest mandate is paused")]
+    AutoInvestPaused,
+    #[msg("Invoice does not match the auto-invest mandate")]
+    AutoInvestMismatch,
+    #[msg("Auto-invest allowance does not cover this funding")]
+    AutoInvestAllowanceExhausted,
+    #[msg("Funding would take the investor past the mandate's exposure cap")]
+    AutoInvestExposureExceeded,
+    #[msg("Batch must repay between one and MAX_REPAYMENT_BATCH invoices")]
+    RepaymentBatchTooLarge,
+    #[msg("Batch accounts are missing, repeated or of the wrong type")]
+    InvalidBatchAccount,
+    #[msg("Only repaid invoices can be closed")]
+    InvoiceNotClosable,
+    #[msg("The invoice vault still holds funds owed to its investors")]
+    InvoicePositionOpen,
+    #[msg("The invoice cannot move to that status from its current one")]
+    IllegalStatusTransition,
+    #[msg("Funding deadline must fall after listing and no later than the due date")]
+    InvalidFundingDeadline,
+    #[msg("No insurance payout is outstanding on this invoice")]
+    NoInsuranceOutstanding,
+    #[msg("Funding would take insured exposure past the pool's coverage capacity")]
+    CoverageCapacityExceeded,
+    #[msg("Coverage capacity must be 0 (no cap) or at least 10000 bps")]
+    InvalidCoverageCapacity,
+    #[msg("Originated invoices are funded outright from the investor's wallet: no partial funding, off-ramp payout, escrow, custody balance, bundle or auto-invest")]
+    OriginationUnsupported,
+    #[msg("Originator fee is above the protocol's cap")]
+    OriginatorFeeTooHigh,
+    #[msg("Originator has no stats account; it must register first")]
+    OriginatorNotRegistered,
+    #[msg("Funding an originated invoice needs the originator's token account and stats")]
+    OriginatorAccountsMissing,
+    #[msg("Originator fee cap is above MAX_ORIGINATOR_FEE_BPS")]
+    InvalidOriginatorFeeCap,
+    #[msg("Invoice is already in the current account layout")]
+    InvoiceAlreadyMigrated,
+    #[msg("Account is on another layout version; run migrate_invoice or migrate_global_state")]
+    AccountVersionMismatch,
+    #[msg("Invoice terms changed since they were quoted; refetch the invoice")]
+    InvoiceTermsChanged,
+    #[msg("Business prepaid the premium for these terms; cancel and relist instead")]
+    InvoicePremiumPrepaid,
+    #[msg("Investor portfolio account required for this invoice")]
+    PortfolioMissing,
+    #[msg("An approver set guards this instruction; propose it with propose_admin_action")]
+    ApprovalRequired,
+    #[msg("Approvers must be distinct, at most MAX_ADMIN_APPROVERS, with a threshold they can meet and a window within MAX_APPROVAL_WINDOW_SECS")]
+    InvalidApproverSet,
+    #[msg("Signer is not in the approver set")]
+    NotAnApprover,
+    #[msg("Approver already approved this proposal")]
+    AlreadyApproved,
+    #[msg("Proposal's approval window has closed")]
+    ProposalExpired,
+    #[msg("Proposal has fewer approvals than the threshold")]
+    ApprovalThresholdNotMet,
+    #[msg("Approver set changed since the proposal was raised")]
+    ApproverSetChanged,
+    #[msg("Only guarded instructions within the account and data bounds can be proposed")]
+    InvalidAdminAction,
+    #[msg("These funds are tracked by the program and cannot be recovered")]
+    RecoveryProtected,
+    #[msg("Account holds nothing above its rent exemption")]
+    NothingToRecover,
+    #[msg("Rent refund must go to the wallet that paid the invoice's rent")]
+    RentPayerMismatch,
+    #[msg("Invoice is not past its due date")]
+    InvoiceNotOverdue,
+    #[msg("Invoice was already signalled overdue in the last day")]
+    OverduePingTooSoon,
+    #[msg("Minimum insurance premium is above the protocol's bound")]
+    InvalidMinInsurancePremium,
+    #[msg("A business cannot fund its own invoice")]
+    SelfFundingNotAllowed,
+    #[msg("The debtor wallet cannot be the business or the investor")]
+    DebtorWalletIsParty,
+    #[msg("Only the invoice's business owner can repay it")]
+    RepayerNotBusinessOwner,
+    #[msg("Repayment must go to a token account the invoice's investor owns")]
+    RepaymentDestinationMismatch,
+    #[msg("Advance rate is outside the allowed range")]
+    InvalidAdvanceRate,
+    #[msg("Factored invoices are funded outright by a single investor")]
+    FactoringUnsupported,
+    #[msg("Only invoices denominated in wrapped SOL can be funded or redeemed in SOL")]
+    NativeMintOnly,
+    #[msg("Business has as many open invoices as the protocol allows")]
+    OpenInvoiceLimitReached,
+    #[msg("Party is blacklisted from this activity")]
+    PartyBlacklisted,
+    #[msg("Blacklisting takes a reason for imposing it, unblacklisting one for lifting it")]
+    InvalidBlacklistReason,
+    #[msg("Another pricing experiment is scheduled or running until after this one would start")]
+    ExperimentOverlap,
+    #[msg("Escrow compensation is above the most the protocol allows")]
+    InvalidEscrowCompensation,
+    #[msg("Invoices in a pricing experiment are repaid one at a time with repay_invoice")]
+    InvoiceInExperiment,
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Event;
+
+    fn experiment(traffic_bps: u16, premium_bps_per_risk_point: u16, max_premium_delta_bps: u16) -> Experiment {
+        Experiment {
+            experiment_id: 1,
+            premium_bps_per_risk_point,
+            traffic_bps,
+            starts_at: 0,
+            ends_at: i64::MAX,
+            max_premium_delta_bps,
+            control: ArmCounters::default(),
+            treatment: ArmCounters::default(),
+            bump: 255,
+        }
+    }
+
+    // Price a standard invoice under the experiment and record its assignment
+    fn assign(exp: &mut Experiment, owner: &Pubkey, invoice_id: u64, amount: u64, risk_score: u8) -> ExperimentAssignment {
+        let risk = RiskAssessment { risk_score, industry_risk: 0, estimated_credit_score: 0 };
+        let inputs = PricingInputs::new(amount, risk, PremiumSchedule::RiskScaled, CoverageTiers::DEFAULT, 30)
+            .with_experiment(exp.terms(owner, invoice_id));
+        exp.assign(Pubkey::new_unique(), &price_invoice(&inputs).unwrap())
+    }
+
+    fn funded_invoice(invoice_id: u64, funded_amount: u64, due_date: i64) -> Invoice {
+        Invoice {
+            invoice_id,
+            amount: funded_amount,
+            funded_amount,
+            remaining_balance: funded_amount,
+            due_date,
+            status: InvoiceStatus::Funded,
+            version: INVOICE_VERSION,
+            ..Invoice::default()
+        }
+    }
+
+    #[test]
+    fn aging_report_buckets_by_days_overdue() {
+        let now = 1_700_000_000;
+        let day = 86_400;
+        let mut report = AgingReport::default();
+
+        report.add(&funded_invoice(1, 100_000_000, now + 10 * day), now, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        report.add(&funded_invoice(2, 200_000_000, now - 5 * day), now, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        report.add(&funded_invoice(3, 300_000_000, now - 45 * day), now, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        report.add(&funded_invoice(4, 50_000_000, now - 120 * day), now, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        report.add(
+            &Invoice { status: InvoiceStatus::PendingFunding, ..funded_invoice(5, 70_000_000, now + day) },
+            now,
+            DEFAULT_LATE_FEE_BPS_PER_DAY,
+        ).unwrap();
+        report.add(
+            &Invoice { status: InvoiceStatus::Defaulted, ..funded_invoice(6, 80_000_000, now - 60 * day) },
+            now,
+            DEFAULT_LATE_FEE_BPS_PER_DAY,
+        ).unwrap();
+
+        assert_eq!(report.current, AgingBucket { count: 1, value: 100_000_000 });
+        // 5 days late: 200 USDC + 0.25% late fee
+        assert_eq!(report.overdue_1_30, AgingBucket { count: 1, value: 200_500_000 });
+        // 45 days late: 300 USDC + 2.25% late fee
+        assert_eq!(report.overdue_31_60, AgingBucket { count: 1, value: 306_750_000 });
+        assert_eq!(report.overdue_61_90, AgingBucket::default());
+        assert_eq!(report.overdue_90_plus, AgingBucket { count: 1, value: 53_000_000 });
+        assert_eq!(report.pending_funding, AgingBucket { count: 1, value: 70_000_000 });
+        assert_eq!(report.defaulted, AgingBucket { count: 1, value: 80_000_000 });
+        assert_eq!(report.total_financed, 730_000_000);
+        assert_eq!(report.total_owed, 100_000_000 + 200_500_000 + 306_750_000 + 53_000_000);
+        assert_eq!(report.next_due_date, Some(now + 10 * day));
+        assert_eq!(report.invoices.len(), 6);
+        assert_eq!(report.invoices[2].days_overdue, 45);
+    }
+
+    #[test]
+    fn erasing_personal_data_keeps_a_hash_of_the_original() {
+        let debtor_info = "Jane Doe, 12 Rue de Rivoli, Paris";
+        let mut invoice = Invoice {
+            debtor_info_hash: anchor_lang::solana_program::hash::hash(debtor_info.as_bytes()).to_bytes(),
+            debtor_info_uri: Some("ar://bNbA3TEQVL60xlgCcqdz4ZPHFZ711cZ3hmkpGttDt_U".to_string()),
+            status: InvoiceStatus::Repaid,
+            repayment_date: Some(1_700_000_000),
+            ..Invoice::default()
+        };
+
+        let content_hash = invoice.erase_personal_data();
+        assert_eq!(
+            content_hash,
+            anchor_lang::solana_program::hash::hash(debtor_info.as_bytes()).to_bytes()
+        );
+        assert_eq!(invoice.erased_content_hash, content_hash);
+        assert_eq!(invoice.debtor_info_uri, None);
+        assert!(invoice.erased);
+        assert_eq!(invoice.settled_at(), Some(1_700_000_000));
+    }
+
+    #[test]
+    fn daily_cap_resets_at_utc_midnight() {
+        let midnight = 1_700_006_400; // 2023-11-15 00:00:00 UTC
+        let mut state = GlobalState {
+            daily_funding_cap: 1_000,
+            ..GlobalState::default()
+        };
+
+        state.record_daily_funding(600, midnight - 3600).unwrap();
+        assert_eq!(state.remaining_daily_capacity(midnight - 1), 400);
+        assert_eq!(
+            state.record_daily_funding(401, midnight - 1).unwrap_err(),
+            error!(ErrorCode::DailyCapReached)
+        );
+
+        // A new UTC day restores the full cap without any explicit reset
+        assert_eq!(state.remaining_daily_capacity(midnight), 1_000);
+        state.record_daily_funding(1_000, midnight).unwrap();
+        assert_eq!(state.day_start_ts, midnight);
+        assert_eq!(state.remaining_daily_capacity(midnight + 86399), 0);
+    }
+
+    #[test]
+    fn only_cap_tightening_skips_the_timelock() {
+        assert!(is_cap_tightening(1_000, 500));
+        assert!(is_cap_tightening(1_000, 1_000));
+        assert!(is_cap_tightening(0, 5_000)); // unlimited -> capped
+        assert!(!is_cap_tightening(1_000, 1_001));
+        assert!(!is_cap_tightening(1_000, 0)); // capped -> unlimited
+        assert!(is_cap_tightening(0, 0));
+    }
+
+    #[test]
+    fn batch_details_fit_in_return_data() {
+        // Worst case: every optional field populated
+        let invoice = Invoice {
+            funding_date: Some(1),
+            released_at: Some(1),
+            repayment_date: Some(2),
+            expected_return: Some(3),
+            late_fee: Some(4),
+            insurance_payout: Some(5),
+            rejection_reason: Some(RejectionReason::PolicyViolation),
+            ..funded_invoice(1, 1_000_000, 1_700_000_000)
+        };
+        let details = InvoiceDetails::from(&invoice);
+        assert_eq!(details.try_to_vec().unwrap().len(), InvoiceDetails::SIZE);
+
+        let full_count = (0..=MAX_DETAILS_BATCH)
+            .take_while(|count| InvoiceBatch::full_size(*count) <= MAX_RETURN_DATA)
+            .last()
+            .unwrap();
+        let full = InvoiceBatch {
+            mode: BatchMode::Full,
+            entries: (0..full_count)
+                .map(|_| InvoiceBatchEntry::Full(InvoiceDetails::from(&invoice)))
+                .collect(),
+        };
+        assert!(full.try_to_vec().unwrap().len() <= MAX_RETURN_DATA);
+
+        let compact = InvoiceBatch {
+            mode: BatchMode::Compact,
+            entries: (0..MAX_DETAILS_BATCH)
+                .map(|_| InvoiceBatchEntry::Compact(InvoiceSummary::from(&invoice)))
+                .collect(),
+        };
+        assert!(compact.try_to_vec().unwrap().len() <= MAX_RETURN_DATA);
+    }
+
+    #[test]
+    fn experiment_assignment_is_stable() {
+        let owner = Pubkey::new_unique();
+        for invoice_id in 0..50 {
+            let first = experiment_arm(&owner, invoice_id, 2_500);
+            for _ in 0..3 {
+                assert_eq!(experiment_arm(&owner, invoice_id, 2_500), first);
+            }
+        }
+        assert_eq!(experiment_arm(&owner, 7, 0), ExperimentArm::Control);
+        assert_eq!(experiment_arm(&owner, 7, 10_000), ExperimentArm::Treatment);
+    }
+
+    #[test]
+    fn experiment_guardrail_falls_back_to_control() {
+        let owner = Pubkey::new_unique();
+        // 20 bps per point doubles the control premium, a 100% delta
+        let mut strict = experiment(10_000, 20, 5_000);
+        let assignment = assign(&mut strict, &owner, 1, 1_000_000_000, 30);
+        assert_eq!(assignment.treatment_premium, 60_000_000);
+        assert_eq!(assignment.arm, ExperimentArm::Control);
+        assert!(assignment.guardrail_fallback);
+        assert_eq!(assignment.applied_premium(), 30_000_000);
+
+        let mut loose = experiment(10_000, 20, 10_000);
+        let assignment = assign(&mut loose, &owner, 1, 1_000_000_000, 30);
+        assert_eq!(assignment.arm, ExperimentArm::Treatment);
+        assert!(!assignment.guardrail_fallback);
+        assert_eq!(assignment.applied_premium(), 60_000_000);
+    }
+
+    #[test]
+    fn experiment_counters_track_each_arm() {
+        let owner = Pubkey::new_unique();
+        let mut exp = experiment(5_000, 12, 10_000);
+        let mut arms = Vec::new();
+        for invoice_id in 0..20 {
+            arms.push(assign(&mut exp, &owner, invoice_id, 100_000_000, 25).arm);
+        }
+        for arm in &arms {
+            exp.arm_mut(*arm).record(ExperimentOutcome::Funded);
+        }
+        exp.arm_mut(arms[0]).record(ExperimentOutcome::Repaid);
+        exp.arm_mut(arms[1]).record(ExperimentOutcome::Defaulted);
+
+        let treatment = arms.iter().filter(|a| **a == ExperimentArm::Treatment).count() as u64;
+        assert_eq!(exp.treatment.assigned, treatment);
+        assert_eq!(exp.control.assigned, 20 - treatment);
+        assert_eq!(exp.treatment.funded + exp.control.funded, 20);
+        assert_eq!(exp.treatment.repaid + exp.control.repaid, 1);
+        assert_eq!(exp.treatment.defaulted + exp.control.defaulted, 1);
+        assert_eq!(exp.arm_mut(arms[0]).repaid, 1);
+    }
+
+    #[test]
+    fn only_the_scheduled_experiment_is_in_force_and_only_inside_its_window() {
+        let scheduled = Pubkey::new_unique();
+        let global_state = GlobalState {
+            pricing_experiment: Some(scheduled),
+            pricing_experiment_starts_at: 1_700_000_000,
+            pricing_experiment_ends_at: 1_702_592_000,
+            ..GlobalState::default()
+        };
+        assert_eq!(global_state.experiment_in_force(1_699_999_999), None);
+        assert_eq!(global_state.experiment_in_force(1_700_000_000), Some(scheduled));
+        assert_eq!(global_state.experiment_in_force(1_702_591_999), Some(scheduled));
+        assert_eq!(global_state.experiment_in_force(1_702_592_000), None);
+
+        let key = Pubkey::new_unique();
+        let mut data = Vec::new();
+        experiment(5_000, 12, 10_000).try_serialize(&mut data).unwrap();
+        let mut lamports = 1_000_000;
+        let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &crate::ID, false, 0);
+        let mut passed = Account::<Experiment>::try_from(&info).unwrap();
+
+        assert!(running_experiment(None, Some(&mut passed)).unwrap().is_none());
+        assert_eq!(
+            running_experiment(Some(key), None).err(),
+            Some(error!(ErrorCode::ExperimentAccountMissing))
+        );
+        assert!(running_experiment(Some(scheduled), Some(&mut passed)).is_err());
+        assert_eq!(running_experiment(Some(key), Some(&mut passed)).unwrap().map(|e| e.key()), Some(key));
+    }
+
+    #[test]
+    fn outflows_pay_only_destinations_active_on_the_allowlist() {
+        let (active, pending, leaving, unknown) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let destination = |token_account, active_at, removable_at| AllowlistedDestination {
+            name: [0u8; 32],
+            token_account,
+            active_at,
+            removable_at,
+        };
+        let allowlist = DestinationAllowlist {
+            vault: VaultKind::PayoutCustody,
+            destinations: vec![
+                destination(active, 1_000, None),
+                destination(pending, 2_000, None),
+                destination(leaving, 1_000, Some(1_500)),
+            ],
+            frozen_at: None,
+            bump: 0,
+        };
+        let refused = Some(error!(ErrorCode::DestinationNotAllowlisted));
+
+        assert!(require_allowlisted_destination(&allowlist, &active, 1_000).is_ok());
+        assert_eq!(require_allowlisted_destination(&allowlist, &pending, 1_999).err(), refused);
+        assert!(require_allowlisted_destination(&allowlist, &pending, 2_000).is_ok());
+        assert!(require_allowlisted_destination(&allowlist, &leaving, 1_499).is_ok());
+        assert_eq!(require_allowlisted_destination(&allowlist, &leaving, 1_500).err(), refused);
+        assert_eq!(require_allowlisted_destination(&allowlist, &unknown, 3_000).err(), refused);
+    }
+
+    #[test]
+    fn a_new_payout_custody_waits_out_the_timelock_and_the_allowlist() {
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut processor = PayoutProcessor {
+            processor: Pubkey::new_unique(),
+            custody: Pubkey::default(),
+            escrow: Pubkey::new_unique(),
+            ack_timeout_secs: 60,
+            total_entries: 0,
+            bump: 0,
+            authority_bump: 0,
+            pending_custody: None,
+            pending_custody_at: 0,
+        };
+        let mut allowlist = DestinationAllowlist {
+            vault: VaultKind::PayoutCustody,
+            destinations: vec![AllowlistedDestination {
+                name: [0u8; 32],
+                token_account: second,
+                active_at: 1_000 + PARAMETER_TIMELOCK_SECS + 1,
+                removable_at: None,
+            }],
+            frozen_at: None,
+            bump: 0,
+        };
+
+        // The first custody account is set outright
+        assert_eq!(processor.stage_custody(first, 1_000), None);
+        assert_eq!(processor.custody, first);
+
+        // Naming the current one again cancels a scheduled change
+        assert_eq!(processor.stage_custody(second, 1_000), Some(1_000 + PARAMETER_TIMELOCK_SECS));
+        assert_eq!(processor.stage_custody(first, 1_000), None);
+        assert_eq!(
+            processor.apply_pending_custody(&allowlist, 1_000 + PARAMETER_TIMELOCK_SECS).err(),
+            Some(error!(ErrorCode::NoPendingChange))
+        );
+
+        processor.stage_custody(second, 1_000);
+        assert_eq!(processor.custody, first);
+        assert_eq!(
+            processor.apply_pending_custody(&allowlist, 1_000 + PARAMETER_TIMELOCK_SECS - 1).err(),
+            Some(error!(ErrorCode::TimelockNotElapsed))
+        );
+        assert_eq!(
+            processor.apply_pending_custody(&allowlist, 1_000 + PARAMETER_TIMELOCK_SECS).err(),
+            Some(error!(ErrorCode::DestinationNotAllowlisted))
+        );
+        allowlist.destinations[0].active_at = 1_000;
+        assert_eq!(processor.apply_pending_custody(&allowlist, 1_000 + PARAMETER_TIMELOCK_SECS).unwrap(), second);
+        assert_eq!(processor.custody, second);
+        assert_eq!(processor.pending_custody, None);
+    }
+
+    #[test]
+    fn premium_shares_add_up_to_the_full_premium() {
+        let (face_value, premium) = (100_000_007u64, 2_500_001u64);
+        let mut funded = 0;
+        let mut collected = 0;
+        for contribution in [33_333_333, 1, 50_000_000, 16_666_673] {
+            let after = funded + contribution;
+            collected += pro_rata(premium, after, face_value) - pro_rata(premium, funded, face_value);
+            funded = after;
+        }
+        assert_eq!(funded, face_value);
+        assert_eq!(collected, premium);
+    }
+
+    #[test]
+    fn share_payouts_never_exceed_the_vault() {
+        let shares = [60_000_000u64, 30_000_000, 10_000_001];
+        let funded: u64 = shares.iter().sum();
+        let distributable = 105_123_457;
+        let paid: u64 = shares.iter().map(|s| pro_rata(distributable, *s, funded)).sum();
+        assert!(paid <= distributable);
+        assert!(distributable - paid < shares.len() as u64);
+        assert_eq!(pro_rata(distributable, 0, 0), 0);
+    }
+
+    #[test]
+    fn micro_tier_stops_at_its_threshold() {
+        let tier = MicroTierConfig { max_amount: 250_000_000, risk_score: 15, premium_bps: 100, bump: 0 };
+        assert!(tier.applies(250_000_000));
+        assert!(!tier.applies(250_000_001));
+        let inputs = PricingInputs::new(250_000_000, tier.risk_assessment(), tier.schedule(), CoverageTiers::DEFAULT, 30);
+        assert_eq!(price_invoice(&inputs).unwrap().insurance_premium, 2_500_000);
+        assert_eq!(tier.risk_assessment().risk_score, 15);
+    }
+
+    #[test]
+    fn partial_repayments_settle_late_fees_first() {
+        let day = 86_400;
+        let due = 1_700_000_000;
+        let mut invoice = funded_invoice(1, 100_000_000, due);
+
+        // On time: half the principal
+        assert_eq!(invoice.accrue_charges(due - day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap().days_overdue, 0);
+        assert_eq!(invoice.apply_repayment(50_000_000).unwrap().late_fee, 0);
+        assert_eq!(invoice.outstanding_balance().unwrap(), 50_000_000);
+
+        // 4 days late: 0.2% of the remaining 50 USDC accrues
+        assert_eq!(invoice.accrue_charges(due + 4 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap().days_overdue, 4);
+        assert_eq!(invoice.accrued_late_fee, 100_000);
+        assert_eq!(invoice.apply_repayment(20_100_000).unwrap().late_fee, 100_000);
+        assert_eq!(invoice.remaining_balance, 30_000_000);
+
+        // Same day again: nothing new accrues
+        invoice.accrue_charges(due + 4 * day + 3600, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.accrued_late_fee, 0);
+
+        // 6 days late: two more days on the remaining 30 USDC
+        invoice.accrue_charges(due + 6 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.outstanding_balance().unwrap(), 30_030_000);
+        invoice.apply_repayment(30_030_000).unwrap();
+        assert_eq!(invoice.outstanding_balance().unwrap(), 0);
+        assert_eq!(invoice.amount_repaid, 100_130_000);
+    }
+
+    #[test]
+    fn events_serialize_within_their_budget() {
+        let outbox = OutboxEntryAppended {
+            index: u64::MAX,
+            invoice_id: u64::MAX,
+            beneficiary: Pubkey::new_unique(),
+            amount: u64::MAX,
+            kind: OutboxEntryKind::Disbursement,
+            invoice: Pubkey::new_unique(),
+            business_owner: Pubkey::new_unique(),
+            investor: Pubkey::new_unique(),
+            timestamp: i64::MAX,
+        };
+        assert_eq!(outbox.data().len(), OutboxEntryAppended::MAX_EVENT_BYTES);
+
+        let erased = PersonalDataErased {
+            invoice_id: 1,
+            business_owner: Pubkey::new_unique(),
+            erased_by: Pubkey::new_unique(),
+            content_hash: [9; 32],
+            erased_at: i64::MAX,
+            action: AdminActionCode::PersonalDataErased,
+            invoice: Pubkey::new_unique(),
+            investor: Pubkey::new_unique(),
+            timestamp: i64::MAX,
+        };
+        assert_eq!(erased.data().len(), PersonalDataErased::MAX_EVENT_BYTES);
+        assert_eq!(event_log_bytes(erased.data().len()), "Program data: ".len() + 260);
+    }
+
+    #[test]
+    fn event_context_fields_follow_the_original_layout() {
+        let invoice = Pubkey::new_unique();
+        let (business_owner, investor) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let repaid = InvoiceRepaid {
+            invoice_id: 7,
+            amount: 102_000_000,
+            late_fee: 0,
+            days_overdue: 0,
+            yield_paid: 2_000_000,
+            early_repayment_discount: 0,
+            invoice,
+            business_owner,
+            investor,
+            timestamp: 1_700_000_000,
+        };
+        let data = repaid.data();
+
+        // Decoders reading the fields the event had before still find them in place
+        let original = (7u64, 102_000_000u64, 0u64, 0u16, 2_000_000u64, 0u64).try_to_vec().unwrap();
+        assert_eq!(&data[..8], InvoiceRepaid::DISCRIMINATOR);
+        assert_eq!(&data[8..8 + original.len()], &original[..]);
+        let context = (invoice, business_owner, investor, 1_700_000_000i64).try_to_vec().unwrap();
+        assert_eq!(&data[8 + original.len()..], &context[..]);
+    }
+
+    #[test]
+    fn bounded_text_keeps_a_hash_and_prefix() {
+        let long = "Net 45, invoice #42 for Acme Corp, see attached purchase order";
+        let text = BoundedText::new(long);
+        assert_eq!(text.len as usize, long.len());
+        assert_eq!(&text.prefix[..], &long.as_bytes()[..BOUNDED_TEXT_PREFIX]);
+        assert_eq!(text.hash, anchor_lang::solana_program::hash::hash(long.as_bytes()).to_bytes());
+
+        let short = BoundedText::new("ok");
+        assert_eq!(&short.prefix[..3], b"ok\0");
+        assert_eq!(short.try_to_vec().unwrap().len(), BoundedText::INIT_SPACE);
+    }
+
+    #[test]
+    fn early_repayment_prorates_the_yield() {
+        let day = 86_400;
+        let funded_at = 1_700_000_000;
+        let mut invoice = funded_invoice(1, 100_000_000, funded_at + 60 * day);
+        invoice.funding_date = Some(funded_at);
+        invoice.expected_return = Some(106_000_000);
+
+        // On the due date the full yield is owed, as before the discount
+        assert_eq!(invoice.interest_due(funded_at + 60 * day, 2_500), 6_000_000);
+        assert_eq!(invoice.interest_due(funded_at + 90 * day, 2_500), 6_000_000);
+        // Halfway through the term, half the yield
+        assert_eq!(invoice.interest_due(funded_at + 30 * day, 2_500), 3_000_000);
+        // A partial day counts as a day outstanding
+        assert_eq!(invoice.interest_due(funded_at + 29 * day + 1, 0), 3_000_000);
+        // Next-day repayment is held at the floor
+        assert_eq!(invoice.interest_due(funded_at + day, 2_500), 1_500_000);
+        assert_eq!(invoice.interest_due(funded_at + day, 0), 100_000);
+    }
+
+    #[test]
+    fn repayments_settle_interest_before_principal() {
+        let day = 86_400;
+        let funded_at = 1_700_000_000;
+        let mut invoice = funded_invoice(1, 100_000_000, funded_at + 60 * day);
+        invoice.funding_date = Some(funded_at);
+        invoice.expected_return = Some(106_000_000);
+
+        invoice.accrue_charges(funded_at + 30 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.outstanding_balance().unwrap(), 103_000_000);
+        let split = invoice.apply_repayment(4_000_000).unwrap();
+        assert_eq!(split, RepaymentSplit { late_fee: 0, interest: 3_000_000, principal: 1_000_000 });
+
+        // Ten days later only the newly accrued interest is owed on top
+        invoice.accrue_charges(funded_at + 40 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.accrued_interest, 1_000_000);
+        assert_eq!(invoice.outstanding_balance().unwrap(), 100_000_000);
+    }
+
+    #[test]
+    fn recovery_pays_agency_then_pool_then_investor_then_business() {
+        // 15% agency fee, pool still owed 60 USDC, investor 40
+        let split = recovery_waterfall(100_000_000, 1_500, 60_000_000, 40_000_000).unwrap();
+        assert_eq!(split, RecoverySplit { agency_fee: 15_000_000, pool: 60_000_000, investor: 25_000_000, business: 0 });
+
+        // Pool made whole: everything after the fee goes to the investor
+        let split = recovery_waterfall(10_000_000, 1_500, 0, 40_000_000).unwrap();
+        assert_eq!(split, RecoverySplit { agency_fee: 1_500_000, pool: 0, investor: 8_500_000, business: 0 });
+
+        // No active assignment: no fee slice
+        let split = recovery_waterfall(10_000_001, 0, 10_000_000, 40_000_000).unwrap();
+        assert_eq!(split, RecoverySplit { agency_fee: 0, pool: 10_000_000, investor: 1, business: 0 });
+
+        // 80 USDC paid out on a 100 USDC default, 20 already back in the pool
+        let split = recovery_waterfall(50_000_000, 0, 60_000_000, 20_000_000).unwrap();
+        assert_eq!(split, RecoverySplit { agency_fee: 0, pool: 50_000_000, investor: 0, business: 0 });
+        let split = recovery_waterfall(90_000_000, 0, 10_000_000, 20_000_000).unwrap();
+        assert_eq!(split, RecoverySplit { agency_fee: 0, pool: 10_000_000, investor: 20_000_000, business: 60_000_000 });
+
+        // Everyone made whole: the business gets it all back, less the agency's cut
+        let split = recovery_waterfall(5_000_000, 0, 0, 0).unwrap();
+        assert_eq!(split, RecoverySplit { agency_fee: 0, pool: 0, investor: 0, business: 5_000_000 });
+        let split = recovery_waterfall(5_000_000, 1_000, 0, 0).unwrap();
+        assert_eq!(split, RecoverySplit { agency_fee: 500_000, pool: 0, investor: 0, business: 4_500_000 });
+
+        // A fee on an amount too large to scale is refused rather than wrapped
+        assert_eq!(recovery_waterfall(u64::MAX, 1_000, 0, 0).err(), Some(error!(ErrorCode::MathOverflow)));
+    }
+
+    #[test]
+    fn late_fee_clock_restarts_after_extension() {
+        let day = 86_400;
+        let due = 1_700_000_000;
+        let mut invoice = funded_invoice(1, 100_000_000, due);
+
+        // 10 days late on the original terms, then extended by 30 days
+        invoice.accrue_charges(due + 10 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.accrued_late_fee, 500_000);
+        invoice.late_fee_days_accrued = 0;
+        invoice.due_date = due + 30 * day;
+
+        // No new fees until the extended date passes
+        invoice.accrue_charges(due + 29 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.accrued_late_fee, 500_000);
+        invoice.accrue_charges(due + 32 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.accrued_late_fee, 600_000);
+    }
+
+    #[test]
+    fn retail_guardrails_lift_after_three_repayments() {
+        let mut stats = InvestorStats { deployed_capital: 800_000_000, ..InvestorStats::default() };
+
+        // 200 of 1000 USDC deployed is exactly 20%, 201 is over
+        assert!(stats.require_retail_guardrails(true, 200_000_000, 35).is_ok());
+        assert_eq!(
+            stats.require_retail_guardrails(true, 201_000_000, 35).unwrap_err(),
+            error!(ErrorCode::DiversificationLimit)
+        );
+        assert_eq!(
+            stats.require_retail_guardrails(true, 50_000_000, 36).unwrap_err(),
+            error!(ErrorCode::RiskTierLocked)
+        );
+        // Small first positions are always allowed; the flag turns everything off
+        assert!(InvestorStats::default().require_retail_guardrails(true, 100_000_000, 20).is_ok());
+        assert!(stats.require_retail_guardrails(false, 1_000_000_000, 50).is_ok());
+
+        stats.completed_repayments = 2;
+        assert!(stats.require_retail_guardrails(true, 1_000_000_000, 50).is_err());
+        stats.completed_repayments = 3;
+        assert!(stats.require_retail_guardrails(true, 1_000_000_000, 50).is_ok());
+    }
+
+    #[test]
+    fn professional_investors_bypass_retail_guardrails() {
+        let stats = InvestorStats { professional: true, ..InvestorStats::default() };
+        assert!(stats.require_retail_guardrails(true, 1_000_000_000, 50).is_ok());
+    }
+
+    fn admin_action(timestamp: i64) -> AdminAction {
+        AdminAction {
+            actor: Pubkey::new_unique(),
+            role: AdminRole::ProtocolAuthority,
+            action: AdminActionCode::CollectionsAssigned,
+            timestamp,
+            amount: None,
+        }
+    }
+
+    #[test]
+    fn invoice_audit_log_keeps_the_last_eight_actions_in_order() {
+        let mut log = InvoiceAuditLog {
+            invoice: Pubkey::new_unique(),
+            total_actions: 0,
+            entries: Vec::new(),
+            bump: 255,
+        };
+        for timestamp in 0..3 {
+            log.record(admin_action(timestamp));
+        }
+        let timestamps: Vec<i64> = log.chronological().iter().map(|a| a.timestamp).collect();
+        assert_eq!(timestamps, vec![0, 1, 2]);
+
+        for timestamp in 3..11 {
+            log.record(admin_action(timestamp));
+        }
+        let timestamps: Vec<i64> = log.chronological().iter().map(|a| a.timestamp).collect();
+        assert_eq!(log.total_actions, 11);
+        assert_eq!(timestamps, (3..11).collect::<Vec<i64>>());
+        assert!(log.try_to_vec().unwrap().len() + 8 <= InvoiceAuditLog::SIZE);
+    }
+
+    #[test]
+    fn admin_proposals_count_each_approver_once_until_they_lapse() {
+        let approvers = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut state = GlobalState {
+            admin_approvers: approvers.to_vec(),
+            approval_threshold: 2,
+            approval_window_secs: 86_400,
+            approver_set_nonce: 4,
+            ..GlobalState::default()
+        };
+        let now = 1_700_000_000;
+        let mut proposal = AdminProposal {
+            proposal_id: 0,
+            proposer: approvers[0],
+            approver_set_nonce: 4,
+            approvals: 0,
+            accounts: Vec::new(),
+            data: instruction::Pause::DISCRIMINATOR.to_vec(),
+            proposed_at: now,
+            expires_at: now + 86_400,
+            bump: 255,
+        };
+
+        assert_eq!(proposal.approve(&state, &approvers[0], now).unwrap(), 1);
+        assert_eq!(proposal.require_executable(&state, now).unwrap_err(), error!(ErrorCode::ApprovalThresholdNotMet));
+        assert_eq!(proposal.approve(&state, &approvers[0], now).unwrap_err(), error!(ErrorCode::AlreadyApproved));
+        assert_eq!(proposal.approve(&state, &Pubkey::new_unique(), now).unwrap_err(), error!(ErrorCode::NotAnApprover));
+        assert_eq!(proposal.approve(&state, &approvers[2], now).unwrap(), 2);
+        proposal.require_executable(&state, now).unwrap();
+        assert_eq!(proposal.instruction(), instruction::Pause::DISCRIMINATOR);
+
+        // The window closes at expires_at
+        let expired = proposal.expires_at;
+        assert_eq!(proposal.require_executable(&state, expired).unwrap_err(), error!(ErrorCode::ProposalExpired));
+        assert_eq!(proposal.approve(&state, &approvers[1], expired).unwrap_err(), error!(ErrorCode::ProposalExpired));
+
+        // A new set voids it, even with the same members, as does clearing the set
+        state.approver_set_nonce += 1;
+        assert_eq!(proposal.require_executable(&state, now).unwrap_err(), error!(ErrorCode::ApproverSetChanged));
+        state.approver_set_nonce = 4;
+        state.approval_threshold = 0;
+        assert_eq!(proposal.require_executable(&state, now).unwrap_err(), error!(ErrorCode::ApproverSetChanged));
+
+        let mut proposal_data = Vec::new();
+        proposal.try_serialize(&mut proposal_data).unwrap();
+        let full = AdminProposal {
+            accounts: vec![AdminAccountMeta { pubkey: Pubkey::new_unique(), is_signer: true, is_writable: true }; approvals::MAX_ADMIN_ACTION_ACCOUNTS],
+            data: vec![0; approvals::MAX_ADMIN_ACTION_DATA_LEN],
+            ..proposal
+        };
+        let mut full_data = Vec::new();
+        full.try_serialize(&mut full_data).unwrap();
+        assert_eq!(full_data.len(), AdminProposal::SIZE);
+        assert!(proposal_data.len() < AdminProposal::SIZE);
+    }
+
+    #[test]
+    fn admin_log_rejects_actions_for_another_page() {
+        let mut page = AdminActionLog { page: 0, entries: Vec::new(), bump: 255 };
+        let mut total = 0;
+        for _ in 0..AdminActionLog::MAX_ENTRIES {
+            page.append(&mut total, admin_action(0)).unwrap();
+        }
+        assert_eq!(total, AdminActionLog::MAX_ENTRIES as u64);
+        assert!(page.try_to_vec().unwrap().len() + 8 <= AdminActionLog::SIZE);
+        assert_eq!(
+            page.append(&mut total, admin_action(0)).unwrap_err(),
+            error!(ErrorCode::AdminLogPageMismatch)
+        );
+
+        let mut next = AdminActionLog { page: 1, entries: Vec::new(), bump: 255 };
+        assert_eq!(next.append(&mut total, admin_action(0)).unwrap(), 16);
+    }
+
+    #[test]
+    fn custody_premiums_owed_to_the_pool_cannot_be_withdrawn() {
+        let mut balance = InvestorBalance { available: 1_000, ..InvestorBalance::default() };
+        balance.draw(900, 50).unwrap();
+        assert_eq!(balance.available, 50);
+        assert_eq!(balance.premium_owed, 50);
+        assert!(balance.reconciles(100));
+
+        assert_eq!(balance.withdraw(51).unwrap_err(), error!(ErrorCode::BalanceUnavailable));
+        balance.withdraw(50).unwrap();
+        assert!(balance.reconciles(50));
+        assert!(!balance.reconciles(51));
+
+        assert_eq!(balance.draw(1, 0).unwrap_err(), error!(ErrorCode::InsufficientFunds));
+    }
+
+    #[test]
+    fn insured_exposure_follows_the_invoice_until_the_claim() {
+        let mut global_state = GlobalState::default();
+        let mut invoice = funded_invoice(1, 1_000_000, 0);
+        invoice.coverage_percentage = 80;
+
+        sync_insured_exposure(&mut invoice, &mut global_state).unwrap();
+        assert_eq!(global_state.insured_exposure, 800_000);
+
+        invoice.remaining_balance = 400_000;
+        invoice.status = InvoiceStatus::PartiallyRepaid;
+        sync_insured_exposure(&mut invoice, &mut global_state).unwrap();
+        assert_eq!(global_state.insured_exposure, 320_000);
+
+        // A defaulted invoice stays covered until its claim is paid
+        invoice.status = InvoiceStatus::Defaulted;
+        sync_insured_exposure(&mut invoice, &mut global_state).unwrap();
+        assert_eq!(global_state.insured_exposure, 320_000);
+
+        invoice.insurance_payout = Some(invoice.insured_coverage());
+        sync_insured_exposure(&mut invoice, &mut global_state).unwrap();
+        assert_eq!(global_state.insured_exposure, 0);
+        assert_eq!(invoice.committed_coverage, 0);
+    }
+
+    #[test]
+    fn protocol_totals_track_premiums_payouts_and_repayments() {
+        let mut state = GlobalState::default();
+        state.collect_premium(3_000_000).unwrap();
+        state.collect_premium(2_000_000).unwrap();
+        // A prepaid premium refunded on cancellation was never really collected
+        state.refund_premium(2_000_000).unwrap();
+        assert_eq!((state.insurance_pool_balance, state.total_premiums), (3_000_000, 3_000_000));
+
+        state.pay_insurance(1_000_000).unwrap();
+        assert_eq!((state.insurance_pool_balance, state.total_insurance_payouts), (2_000_000, 1_000_000));
+        assert_eq!(state.pay_insurance(2_000_001).unwrap_err(), error!(ErrorCode::MathOverflow));
+
+        state.record_repayment(40_000_000, 0, false).unwrap();
+        state.record_repayment(62_000_000, 500_000, true).unwrap();
+        assert_eq!(state.total_repaid, 1);
+        assert_eq!(state.total_repaid_amount, 102_000_000);
+        assert_eq!(state.total_late_fees, 500_000);
+    }
+
+    #[test]
+    fn pool_surplus_excludes_insured_exposure() {
+        let mut global_state = GlobalState { insurance_pool_balance: 1_000, insured_exposure: 700, ..GlobalState::default() };
+        assert_eq!(global_state.pool_surplus(), 300);
+
+        global_state.insured_exposure = 1_200;
+        assert_eq!(global_state.pool_surplus(), 0);
+    }
+
+    fn healthy_state(now: i64) -> GlobalState {
+        GlobalState {
+            insurance_pool_balance: 2_000,
+            insured_exposure: 1_000,
+            daily_funding_cap: 10_000,
+            funded_today: 1_000,
+            day_start_ts: utc_day_start(now),
+            health_thresholds: DEFAULT_HEALTH_THRESHOLDS,
+            ..GlobalState::default()
+        }
+    }
+
+    #[test]
+    fn health_is_green_when_every_subsystem_is_within_thresholds() {
+        let now = 1_700_000_000;
+        let report = assess_health(&healthy_state(now), Some(2_000), now);
+
+        assert_eq!(report.overall, HealthStatus::Green);
+        assert_eq!(report.reasons, 0);
+        assert_eq!(report.coverage_ratio_bps, 20_000);
+        assert_eq!(report.cap_utilization_bps, 1_000);
+    }
+
+    #[test]
+    fn draining_the_pool_degrades_coverage() {
+        let now = 1_700_000_000;
+        let mut state = healthy_state(now);
+
+        state.insurance_pool_balance = 1_200;
+        let report = assess_health(&state, Some(1_200), now);
+        assert_eq!(report.pool_coverage, HealthStatus::Amber);
+        assert_eq!(report.overall, HealthStatus::Amber);
+        assert_eq!(report.reasons, HEALTH_POOL_UNDERCOVERED);
+
+        state.insurance_pool_balance = 900;
+        let report = assess_health(&state, Some(900), now);
+        assert_eq!(report.pool_coverage, HealthStatus::Red);
+        assert_eq!(report.overall, HealthStatus::Red);
+        assert_eq!(report.reasons, HEALTH_POOL_UNDERCOVERED);
+
+        // With nothing insured an empty pool is not undercovered
+        state.insured_exposure = 0;
+        state.insurance_pool_balance = 0;
+        assert_eq!(assess_health(&state, Some(0), now).pool_coverage, HealthStatus::Green);
+    }
+
+    #[test]
+    fn unpaid_claims_build_a_backlog() {
+        let now = 1_700_000_000;
+        let mut state = healthy_state(now);
+
+        state.pending_claims = 1;
+        let report = assess_health(&state, Some(2_000), now);
+        assert_eq!(report.claim_queue, HealthStatus::Amber);
+        assert_eq!(report.reasons, HEALTH_CLAIM_BACKLOG);
+
+        state.pending_claims = 5;
+        assert_eq!(assess_health(&state, Some(2_000), now).overall, HealthStatus::Red);
+    }
+
+    #[test]
+    fn pool_mirror_is_reconciled_against_the_token_account() {
+        let now = 1_700_000_000;
+        let state = healthy_state(now);
+
+        let short = assess_health(&state, Some(1_999), now);
+        assert_eq!(short.reconciliation, HealthStatus::Red);
+        assert_eq!(short.reasons, HEALTH_POOL_MISMATCH);
+
+        let excess = assess_health(&state, Some(2_001), now);
+        assert_eq!(excess.reconciliation, HealthStatus::Amber);
+        assert_eq!(excess.reasons, HEALTH_POOL_MISMATCH);
+
+        let unverified = assess_health(&state, None, now);
+        assert_eq!(unverified.reconciliation, HealthStatus::Amber);
+        assert_eq!(unverified.reasons, HEALTH_POOL_UNVERIFIED);
+    }
+
+    #[test]
+    fn daily_cap_pressure_clears_at_midnight() {
+        let now = 1_700_000_000;
+        let mut state = healthy_state(now);
+
+        state.funded_today = 8_000;
+        assert_eq!(assess_health(&state, Some(2_000), now).daily_cap, HealthStatus::Amber);
+        state.funded_today = 10_000;
+        let report = assess_health(&state, Some(2_000), now);
+        assert_eq!(report.daily_cap, HealthStatus::Red);
+        assert_eq!(report.reasons, HEALTH_DAILY_CAP_PRESSURE);
+
+        let tomorrow = utc_day_start(now) + 86400;
+        assert_eq!(assess_health(&state, Some(2_000), tomorrow).overall, HealthStatus::Green);
+    }
+
+    #[test]
+    fn degraded_subsystems_combine_into_the_worst_verdict() {
+        let now = 1_700_000_000;
+        let state = GlobalState {
+            insurance_pool_balance: 900,
+            pending_claims: 2,
+            ..healthy_state(now)
+        };
+
+        let report = assess_health(&state, None, now);
+        assert_eq!(report.overall, HealthStatus::Red);
+        assert_eq!(report.reasons, HEALTH_POOL_UNDERCOVERED | HEALTH_CLAIM_BACKLOG | HEALTH_POOL_UNVERIFIED);
+
+        // Zeroed thresholds switch alerting off entirely
+        let unconfigured = GlobalState { health_thresholds: HealthThresholds::default(), ..state };
+        assert_eq!(assess_health(&unconfigured, Some(900), now).overall, HealthStatus::Green);
+    }
+
+    #[test]
+    fn health_thresholds_must_escalate_from_amber_to_red() {
+        assert!(DEFAULT_HEALTH_THRESHOLDS.is_valid());
+        assert!(HealthThresholds::default().is_valid());
+        assert!(!HealthThresholds { coverage_red_bps: 20_000, ..DEFAULT_HEALTH_THRESHOLDS }.is_valid());
+        assert!(!HealthThresholds { claim_queue_amber: 6, ..DEFAULT_HEALTH_THRESHOLDS }.is_valid());
+        assert!(!HealthThresholds { cap_utilization_red_bps: 10_001, ..DEFAULT_HEALTH_THRESHOLDS }.is_valid());
+    }
+
+    fn risk(risk_score: u8, industry_risk: u8) -> RiskAssessment {
+        RiskAssessment {
+            risk_score,
+            industry_risk,
+            estimated_credit_score: 720,
+        }
+    }
+
+    #[test]
+    fn bundle_pricing_blends_risk_by_amount() {
+        let blended = blend_risk(&[(100_000_000, risk(20, 5)), (300_000_000, risk(40, 8))]);
+        assert_eq!(blended, risk(35, 8));
+
+        // Evenly weighted, the bundle premium matches the constituents' premiums combined
+        let priced = price_invoice(&PricingInputs::new(400_000_000, blended, PremiumSchedule::RiskScaled, CoverageTiers::DEFAULT, 30)).unwrap();
+        assert_eq!(priced.insurance_premium, 2_000_000 + 12_000_000);
+
+        // A fractional blend rounds up, never below the constituents' average
+        assert_eq!(blend_risk(&[(1, risk(20, 5)), (2, risk(21, 5))]).risk_score, 21);
+
+        // The quoted term is amount-weighted and rounds up the same way
+        assert_eq!(blend_term(&[(100_000_000, 30), (300_000_000, 90)]), 75);
+        assert_eq!(blend_term(&[(1, 30), (2, 31)]), 31);
+        assert_eq!(blend_term(&[]), 0);
+    }
+
+    #[test]
+    fn pro_rata_allocation_distributes_rounding_dust() {
+        assert_eq!(allocate_pro_rata(10, &[5, 5, 5]), vec![4, 3, 3]);
+        assert_eq!(allocate_pro_rata(7, &[0, 5, 2]), vec![0, 5, 2]);
+        assert_eq!(allocate_pro_rata(1_000_001, &[100_000_000, 300_000_000]), vec![250_001, 750_000]);
+    }
+
+    #[test]
+    fn partial_bundle_repayment_is_allocated_by_outstanding_balance() {
+        let now = 1_700_000_000;
+        let mut invoices = vec![
+            funded_invoice(1, 100_000_000, now + 30 * 86_400),
+            funded_invoice(2, 300_000_000, now + 30 * 86_400),
+        ];
+
+        let mut global_state = GlobalState::default();
+        let allocations = apply_bundle_repayment(&mut invoices, 200_000_000, None, now, &mut global_state).unwrap();
+        assert_eq!(allocations, vec![50_000_000, 150_000_000]);
+        assert_eq!(invoices[0].remaining_balance, 50_000_000);
+        assert_eq!(invoices[1].remaining_balance, 150_000_000);
+        assert!(invoices.iter().all(|invoice| invoice.status == InvoiceStatus::PartiallyRepaid));
+        assert!(!bundle_settled(&invoices));
+        assert_eq!((global_state.total_repaid, global_state.total_repaid_amount), (0, 200_000_000));
+
+        assert_eq!(
+            apply_bundle_repayment(&mut invoices, 200_000_001, None, now, &mut GlobalState::default()).unwrap_err(),
+            error!(ErrorCode::RepaymentExceedsBalance)
+        );
+
+        apply_bundle_repayment(&mut invoices, 200_000_000, None, now, &mut global_state).unwrap();
+        assert!(invoices.iter().all(|invoice| invoice.status == InvoiceStatus::Repaid));
+        assert_eq!(invoices[1].final_repayment_amount, Some(300_000_000));
+        assert!(bundle_settled(&invoices));
+        // Each constituent counts once, when it is paid off
+        assert_eq!((global_state.total_repaid, global_state.total_repaid_amount), (2, 400_000_000));
+    }
+
+    #[test]
+    fn defaulted_constituent_is_left_out_of_bundle_repayments() {
+        let now = 1_700_000_000;
+        let mut invoices = vec![
+            funded_invoice(1, 100_000_000, now + 30 * 86_400),
+            Invoice {
+                status: InvoiceStatus::Defaulted,
+                ..funded_invoice(2, 300_000_000, now - 40 * 86_400)
+            },
+            funded_invoice(3, 100_000_000, now + 30 * 86_400),
+        ];
+
+        // Only what the live constituents owe can be repaid through the bundle
+        assert_eq!(
+            apply_bundle_repayment(&mut invoices, 200_000_001, None, now, &mut GlobalState::default()).unwrap_err(),
+            error!(ErrorCode::RepaymentExceedsBalance)
+        );
+
+        let allocations = apply_bundle_repayment(&mut invoices, 150_000_000, None, now, &mut GlobalState::default()).unwrap();
+        assert_eq!(allocations, vec![75_000_000, 0, 75_000_000]);
+        assert_eq!(invoices[1].remaining_balance, 300_000_000);
+        assert_eq!(invoices[1].status, InvoiceStatus::Defaulted);
+
+        apply_bundle_repayment(&mut invoices, 50_000_000, None, now, &mut GlobalState::default()).unwrap();
+        assert_eq!(invoices[0].status, InvoiceStatus::Repaid);
+        assert_eq!(invoices[2].status, InvoiceStatus::Repaid);
+        assert!(bundle_settled(&invoices));
+    }
+
+    #[test]
+    fn config_updates_are_range_checked() {
+        let config = ProtocolConfig::default();
+        assert!(config.is_valid());
+        assert_eq!(config.grace_period_secs(), 30 * 86_400);
+
+        assert!(!ProtocolConfig { max_invoice_amount: 0, ..config }.is_valid());
+        assert!(!ProtocolConfig { max_term_days: MAX_CONFIG_TERM_DAYS + 1, ..config }.is_valid());
+        assert!(!ProtocolConfig { grace_period_days: MAX_CONFIG_GRACE_PERIOD_DAYS + 1, ..config }.is_valid());
+        assert!(!ProtocolConfig { late_fee_bps_per_day: MAX_CONFIG_LATE_FEE_BPS_PER_DAY + 1, ..config }.is_valid());
+        let inverted = CoverageTiers { high: 85, ..CoverageTiers::DEFAULT };
+        assert!(!ProtocolConfig { coverage: inverted, ..config }.is_valid());
+    }
+
+    #[test]
+    fn late_fee_follows_the_configured_rate() {
+        let due = 1_700_000_000;
+        let invoice = funded_invoice(1, 1_000_000_000, due);
+
+        assert_eq!(calculate_late_fee(&invoice, due + 4 * 86_400, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap().0, 2_000_000);
+        assert_eq!(calculate_late_fee(&invoice, due + 4 * 86_400, 20).unwrap().0, 8_000_000);
+    }
+
+    #[test]
+    fn overdue_pings_come_at_most_once_a_day() {
+        let pinged_at = 1_700_000_000;
+        let mut invoice = funded_invoice(1, 1_000_000_000, pinged_at - 86_400);
+        assert!(invoice.overdue_ping_due(pinged_at));
+
+        invoice.last_overdue_ping = Some(pinged_at);
+        assert!(!invoice.overdue_ping_due(pinged_at));
+        assert!(!invoice.overdue_ping_due(pinged_at + OVERDUE_PING_INTERVAL_SECS - 1));
+        assert!(invoice.overdue_ping_due(pinged_at + OVERDUE_PING_INTERVAL_SECS));
+    }
+
+    #[test]
+    fn pause_flags_stop_only_the_paused_activity() {
+        let mut state = GlobalState { paused: PAUSE_FUND, ..GlobalState::default() };
+        state.require_not_paused(PAUSE_CREATE).unwrap();
+        state.require_not_paused(PAUSE_CLAIM).unwrap();
+        assert_eq!(state.require_not_paused(PAUSE_FUND).unwrap_err(), error!(ErrorCode::ProtocolPaused));
+
+        state.paused = PAUSE_ALL;
+        for flag in [PAUSE_CREATE, PAUSE_FUND, PAUSE_CLAIM] {
+            assert_eq!(state.require_not_paused(flag).unwrap_err(), error!(ErrorCode::ProtocolPaused));
+        }
+
+        state.paused = 0;
+        for flag in [PAUSE_CREATE, PAUSE_FUND, PAUSE_CLAIM] {
+            state.require_not_paused(flag).unwrap();
+        }
+    }
+
+    #[test]
+    fn a_pause_shows_up_in_health() {
+        let now = 1_700_000_000;
+        let state = GlobalState { paused: PAUSE_CLAIM, ..healthy_state(now) };
+
+        let report = assess_health(&state, Some(2_000), now);
+        assert_eq!(report.overall, HealthStatus::Amber);
+        assert_eq!(report.reasons, HEALTH_PAUSED);
+        assert_eq!(report.paused, PAUSE_CLAIM);
+    }
+
+    #[test]
+    fn pay_off_absorbs_fees_accrued_since_the_quote_within_max_total() {
+        let day = 86_400;
+        let due = 1_700_000_000;
+        let mut simulated = funded_invoice(1, 100_000_000, due);
+        simulated.accrue_charges(due + 2 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        let quote = simulated.outstanding_balance().unwrap();
+
+        // Another day of late fees lands between simulation and execution
+        let mut executed = funded_invoice(1, 100_000_000, due);
+        executed.accrue_charges(due + 3 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        let owed = executed.outstanding_balance().unwrap();
+        assert_eq!(owed, quote + 50_000);
+
+        assert_eq!(repayment_within(quote, owed, Some(quote + 100_000)).unwrap(), owed);
+        assert_eq!(
+            repayment_within(quote, owed, Some(quote + 49_999)).unwrap_err(),
+            error!(ErrorCode::SlippageExceeded)
+        );
+
+        // Without a bound the requested amount is paid as is
+        assert_eq!(repayment_within(quote, owed, None).unwrap(), quote);
+        assert_eq!(
+            repayment_within(owed + 1, owed, None).unwrap_err(),
+            error!(ErrorCode::RepaymentExceedsBalance)
+        );
+    }
+
+    #[test]
+    fn bundle_pay_off_settles_every_constituent_within_max_total() {
+        let now = 1_700_000_000;
+        let mut invoices = vec![
+            funded_invoice(1, 100_000_000, now - 86_400),
+            funded_invoice(2, 300_000_000, now + 30 * 86_400),
+        ];
+
+        // One constituent is a day late, so the quote of face value is 50_000 short
+        assert_eq!(
+            apply_bundle_repayment(&mut invoices.clone(), 400_000_000, Some(400_000_000), now, &mut GlobalState::default())
+                .unwrap_err(),
+            error!(ErrorCode::SlippageExceeded)
+        );
+
+        let allocations =
+            apply_bundle_repayment(&mut invoices, 400_000_000, Some(400_100_000), now, &mut GlobalState::default()).unwrap();
+        assert_eq!(allocations, vec![100_050_000, 300_000_000]);
+        assert!(bundle_settled(&invoices));
+    }
+
+    fn grace_change(effective_at: i64, old_value: u64, new_value: u64) -> ParamSetChange {
+        ParamSetChange {
+            version: 0,
+            effective_at,
+            actor: Pubkey::default(),
+            changes: param_diff(&[(ParamId::GracePeriodDays, old_value)], &[(ParamId::GracePeriodDays, new_value)]),
+        }
+    }
+
+    #[test]
+    fn param_history_pages_stamp_consecutive_versions() {
+        let mut version = 0;
+        let mut page = ParamHistory { page: 0, entries: Vec::new(), bump: 255 };
+        for _ in 0..ParamHistory::MAX_ENTRIES {
+            page.append(&mut version, grace_change(0, 30, 10)).unwrap();
+        }
+        assert_eq!(version, ParamHistory::MAX_ENTRIES as u32);
+        assert_eq!(page.entries.last().unwrap().version, version);
+        assert!(page.try_to_vec().unwrap().len() + 8 <= ParamHistory::SIZE);
+
+        assert_eq!(
+            page.append(&mut version, grace_change(0, 10, 30)).unwrap_err(),
+            error!(ErrorCode::ParamHistoryPageMismatch)
+        );
+        let mut next = ParamHistory { page: 1, entries: Vec::new(), bump: 255 };
+        assert_eq!(next.append(&mut version, grace_change(0, 10, 30)).unwrap(), 9);
+    }
+
+    #[test]
+    fn param_at_finds_the_value_in_force_at_any_time() {
+        let t = 1_700_000_000;
+        let mut version = 0;
+        let mut page = ParamHistory { page: 0, entries: Vec::new(), bump: 255 };
+        let min_interest_change = ParamSetChange {
+            changes: param_diff(&[(ParamId::MinInterestBps, 0)], &[(ParamId::MinInterestBps, 2_500)]),
+            ..grace_change(t + 50, 0, 0)
+        };
+        page.append(&mut version, grace_change(t, 30, 10)).unwrap();
+        page.append(&mut version, min_interest_change).unwrap();
+        page.append(&mut version, grace_change(t + 100, 10, 0)).unwrap();
+        let entries = &page.entries;
+
+        let grace_at = |timestamp| param_at(entries, ParamId::GracePeriodDays, timestamp, 0);
+        // Before any change the first change's old value applied
+        assert_eq!(grace_at(t - 1), ParamAt { param: ParamId::GracePeriodDays, value: 30, version: 0 });
+        assert_eq!(grace_at(t).value, 10);
+        assert_eq!(grace_at(t).version, 1);
+        // Another parameter's change bumps the version but not the grace period
+        assert_eq!(grace_at(t + 60).value, 10);
+        assert_eq!(grace_at(t + 60).version, 2);
+        assert_eq!(grace_at(t + 100), ParamAt { param: ParamId::GracePeriodDays, value: 0, version: 3 });
+
+        // A parameter that never changed reads its current value
+        assert_eq!(param_at(entries, ParamId::LateFeeBpsPerDay, t + 60, 5).value, 5);
+        assert_eq!(param_at(&[], ParamId::GracePeriodDays, t, 30).value, 30);
+    }
+
+    #[test]
+    fn config_diff_lists_only_changed_parameters() {
+        let old = ProtocolConfig::default();
+        let new = ProtocolConfig {
+            grace_period_days: 10,
+            coverage: CoverageTiers { high: 65, ..CoverageTiers::DEFAULT },
+            ..old
+        };
+        let changes = param_diff(&old.param_values(), &new.param_values());
+        assert_eq!(
+            changes,
+            vec![
+                ParamValueChange { param: ParamId::GracePeriodDays, old_value: 30, new_value: 10 },
+                ParamValueChange { param: ParamId::CoverageHigh, old_value: 70, new_value: 65 },
+            ]
+        );
+        assert!(param_diff(&old.param_values(), &old.param_values()).is_empty());
+
+        let state = GlobalState { config: new, min_interest_bps: 2_500, ..GlobalState::default() };
+        assert_eq!(state.param_value(ParamId::GracePeriodDays), 10);
+        assert_eq!(state.param_value(ParamId::MinInterestBps), 2_500);
+    }
+
+    #[test]
+    fn escrowed_capital_earns_from_funding_not_release() {
+        let day = 86_400;
+        let escrowed_at = 1_700_000_000;
+        let mut invoice = funded_invoice(1, 100_000_000, escrowed_at + 60 * day);
+        invoice.funding_date = Some(escrowed_at);
+        invoice.expected_return = Some(106_000_000);
+
+        // Off-ramp payout acked two days after the investor committed
+        invoice.released_at = Some(escrowed_at + 2 * day);
+
+        // Thirty days after release is 32 of the 60-day term
+        assert_eq!(invoice.interest_due(escrowed_at + 32 * day, 0), 3_200_000);
+        assert_eq!(invoice.interest_due(escrowed_at + 60 * day, 0), 6_000_000);
+        assert_eq!(InvoiceDetails::from(&invoice).released_at, Some(escrowed_at + 2 * day));
+    }
+
+    #[test]
+    fn charges_at_the_amount_cap_stay_exact_for_hundreds_of_days() {
+        let day = 86_400;
+        let due = 1_700_000_000;
+        let cap = DEFAULT_MAX_INVOICE_AMOUNT;
+        let mut invoice = funded_invoice(1, cap, due);
+        invoice.funding_date = Some(due - 60 * day);
+        invoice.expected_return = Some(cap + cap / 20);
+
+        // 400 days late at the steepest late fee the config allows
+        let days = invoice.accrue_charges(due + 400 * day, 0, MAX_CONFIG_LATE_FEE_BPS_PER_DAY).unwrap().days_overdue;
+        assert_eq!(days, 400);
+        assert_eq!(invoice.accrued_late_fee, cap * 4);
+        assert_eq!(invoice.outstanding_balance().unwrap(), cap + cap / 20 + cap * 4);
+    }
+
+    #[test]
+    fn overflowing_charges_fail_without_touching_the_invoice() {
+        let day = 86_400;
+        let due = 1_700_000_000;
+        let mut invoice = funded_invoice(1, u64::MAX / 2, due);
+
+        assert_eq!(
+            calculate_late_fee(&invoice, due + 300 * day, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap_err(),
+            error!(ErrorCode::MathOverflow)
+        );
+        let before = invoice.clone();
+        assert_eq!(
+            invoice.accrue_charges(due + 300 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap_err(),
+            error!(ErrorCode::MathOverflow)
+        );
+        assert_eq!(invoice.accrued_late_fee, before.accrued_late_fee);
+        assert_eq!(invoice.late_fee_days_accrued, before.late_fee_days_accrued);
+
+        // A fee that fits on its own but not on top of the principal also fails
+        invoice.remaining_balance = u64::MAX - 10;
+        assert_eq!(
+            invoice.accrue_charges(due + day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap_err(),
+            error!(ErrorCode::MathOverflow)
+        );
+        // and so does a balance whose parts no longer sum
+        invoice.accrued_interest = 11;
+        assert_eq!(invoice.outstanding_balance().unwrap_err(), error!(ErrorCode::MathOverflow));
+    }
+
+    #[test]
+    fn settlement_arithmetic_errors_instead_of_wrapping() {
+        let mut invoice = funded_invoice(1, 100_000_000, 1_700_000_000);
+        invoice.amount_repaid = u64::MAX;
+        invoice.accrued_interest = 1;
+        assert_eq!(invoice.apply_repayment(1).unwrap_err(), error!(ErrorCode::MathOverflow));
+
+        // Repaid less than principal plus interest: no negative late fee
+        let short = Invoice { amount_repaid: 50_000_000, ..funded_invoice(2, 100_000_000, 1_700_000_000) };
+        assert_eq!(short.settled_late_fee().unwrap_err(), error!(ErrorCode::MathOverflow));
+
+        let pricing = price_invoice(&PricingInputs::new(
+            1_000,
+            RiskAssessment { risk_score: 50, industry_risk: 5, estimated_credit_score: 700 },
+            PremiumSchedule::RiskScaled,
+            CoverageTiers::DEFAULT,
+            365,
+        ))
+        .unwrap();
+        assert_eq!(pricing.expected_return(u64::MAX).unwrap_err(), error!(ErrorCode::MathOverflow));
+
+        // Coverage on the largest balance no longer overflows before dividing
+        let huge = Invoice { coverage_percentage: 90, ..funded_invoice(3, u64::MAX, 1_700_000_000) };
+        assert_eq!(huge.insured_coverage(), pro_rata(u64::MAX, 90, 100));
+
+        let mut global_state = GlobalState::default();
+        let mut claimed = Invoice { committed_coverage: 1, ..funded_invoice(4, 100, 1_700_000_000) };
+        assert_eq!(
+            sync_insured_exposure(&mut claimed, &mut global_state).unwrap_err(),
+            error!(ErrorCode::MathOverflow)
+        );
+    }
+
+    #[test]
+    fn invoice_ids_are_scoped_to_their_business() {
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert_ne!(pda::find_invoice_address(1, &alice).0, pda::find_invoice_address(1, &bob).0);
+        assert_ne!(pda::find_invoice_address(1, &alice).0, pda::find_invoice_address(2, &alice).0);
+
+        let mut profile = BusinessProfile::default();
+        assert_eq!(profile.next_invoice_id(), 1);
+        profile.invoices_created = profile.next_invoice_id();
+        assert_eq!(profile.next_invoice_id(), 2);
+    }
+
+    #[test]
+    fn pair_statement_aggregates_a_mixed_history() {
+        let day = 86_400;
+        let mut ledger = PairLedger::default();
+
+        // #1 repaid on time, #2 repaid late, #3 defaulted then recovered
+        ledger.record_funding(1, 100_000_000, 0).unwrap();
+        ledger.record_funding(2, 50_000_000, 0).unwrap();
+        ledger.record_funding(3, 80_000_000, 10 * day).unwrap();
+        let on_time = RepaymentSplit { late_fee: 0, interest: 2_000_000, principal: 100_000_000 };
+        ledger.record_repayment(1, 102_000_000, &on_time, 30 * day).unwrap();
+        ledger.record_repaid(30).unwrap();
+        let late = RepaymentSplit { late_fee: 3_000_000, interest: 1_000_000, principal: 50_000_000 };
+        ledger.record_repayment(2, 54_000_000, &late, 70 * day).unwrap();
+        ledger.record_repaid(70).unwrap();
+        ledger.record_default(3, 80_000_000, 120 * day).unwrap();
+        ledger.record_recovery(3, SettlementKind::InsurancePayout, 72_000_000, 121 * day).unwrap();
+        ledger.record_recovery(3, SettlementKind::Recovery, 5_000_000, 150 * day).unwrap();
+
+        let statement = ledger.statement();
+        assert_eq!(statement.invoices_financed, 3);
+        assert_eq!(statement.open_invoices, 0);
+        assert_eq!((statement.invoices_repaid, statement.invoices_defaulted), (2, 1));
+        assert_eq!(statement.total_financed, 230_000_000);
+        assert_eq!(statement.total_repaid, 156_000_000);
+        assert_eq!(statement.outstanding, 0);
+        assert_eq!(statement.late_fees_paid, 3_000_000);
+        assert_eq!(statement.total_defaulted, 80_000_000);
+        assert_eq!(statement.total_recovered, 77_000_000);
+        assert_eq!(statement.average_days_to_pay, Some(50));
+        assert_eq!(
+            statement.recent.iter().map(|entry| entry.kind).collect::<Vec<_>>(),
+            vec![
+                SettlementKind::Funding,
+                SettlementKind::Funding,
+                SettlementKind::Funding,
+                SettlementKind::Repayment,
+                SettlementKind::Repayment,
+                SettlementKind::Default,
+                SettlementKind::InsurancePayout,
+                SettlementKind::Recovery,
+            ]
+        );
+    }
+
+    #[test]
+    fn pair_ledger_keeps_the_last_ten_entries_in_order() {
+        let mut ledger = PairLedger::default();
+        for invoice_id in 1..=12 {
+            ledger.record_funding(invoice_id, 1_000_000, invoice_id as i64).unwrap();
+        }
+        assert_eq!(ledger.entries.len(), PairLedger::MAX_ENTRIES);
+        assert_eq!(
+            ledger.chronological().iter().map(|entry| entry.invoice_id).collect::<Vec<_>>(),
+            (3..=12).collect::<Vec<_>>()
+        );
+        assert_eq!(ledger.statement().outstanding, 12_000_000);
+        assert_eq!(ledger.statement().average_days_to_pay, None);
+
+        let full = PairLedger { entries: ledger.chronological(), ..ledger };
+        assert!(full.try_to_vec().unwrap().len() + 8 <= PairLedger::SIZE);
+    }
+
+    #[test]
+    fn only_live_wholly_held_positions_transfer() {
+        let mut invoice = Invoice { status: InvoiceStatus::Funded, ..Invoice::default() };
+        assert!(invoice.position_transferable());
+        invoice.status = InvoiceStatus::PartiallyRepaid;
+        assert!(invoice.position_transferable());
+
+        for status in [InvoiceStatus::PendingFunding, InvoiceStatus::Repaid, InvoiceStatus::Defaulted] {
+            assert!(!Invoice { status, ..Invoice::default() }.position_transferable());
+        }
+        assert!(!Invoice { partial_funding: true, ..invoice.clone() }.position_transferable());
+        assert!(!Invoice { bundle: Some(Pubkey::new_unique()), ..invoice }.position_transferable());
+    }
+
+    #[test]
+    fn sold_positions_move_between_pair_ledgers() {
+        let mut seller = PairLedger::default();
+        let mut buyer = PairLedger::default();
+        seller.record_funding(7, 100_000_000, 0).unwrap();
+        let split = RepaymentSplit { late_fee: 0, interest: 1_000_000, principal: 40_000_000 };
+        seller.record_repayment(7, 41_000_000, &split, 10).unwrap();
+
+        // 60 USDC of principal still owed changes hands for 59
+        seller.record_sale(7, 60_000_000, 59_000_000, 20).unwrap();
+        buyer.record_purchase(7, 60_000_000, 59_000_000, 20).unwrap();
+
+        let sold = seller.statement();
+        assert_eq!((sold.open_invoices, sold.invoices_sold, sold.outstanding), (0, 1, 0));
+        assert_eq!(sold.recent.last().unwrap().kind, SettlementKind::PositionSold);
+        let bought = buyer.statement();
+        assert_eq!((bought.invoices_financed, bought.open_invoices), (1, 1));
+        assert_eq!(bought.outstanding, 60_000_000);
+        assert_eq!(bought.recent[0].amount, 59_000_000);
+    }
+
+    #[test]
+    fn receipt_holder_replaces_recorded_investor() {
+        let invoice_key = Pubkey::new_unique();
+        let mint = receipt::receipt_address(&invoice_key);
+        let (funder, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let receipt_of = |owner: Pubkey| {
+            use anchor_lang::solana_program::program_pack::Pack;
+            use anchor_spl::token::spl_token::state::{Account as SplTokenAccount, AccountState};
+            let account = SplTokenAccount { mint, owner, amount: 1, state: AccountState::Initialized, ..Default::default() };
+            let mut data = vec![0u8; SplTokenAccount::LEN];
+            SplTokenAccount::pack(account, &mut data).unwrap();
+            TokenAccount::try_deserialize_unchecked(&mut &data[..]).unwrap()
+        };
+
+        // Without a receipt the recorded investor holds the position
+        let mut invoice = Invoice { investor: funder, status: InvoiceStatus::Funded, ..Invoice::default() };
+        assert!(invoice.is_position_holder(&funder, None));
+
+        // With one, only whoever presents it does, however the token got there
+        invoice.receipt_mint = Some(mint);
+        assert!(!invoice.is_position_holder(&funder, None));
+        assert!(!invoice.is_position_holder(&funder, Some(&receipt_of(buyer))));
+        assert!(invoice.is_position_holder(&buyer, Some(&receipt_of(buyer))));
+
+        // Once burned the recorded investor takes over again
+        invoice.receipt_redeemed = true;
+        invoice.investor = buyer;
+        assert_eq!(invoice.live_receipt(), None);
+        assert!(invoice.is_position_holder(&buyer, None));
+    }
+
+    // Host stand-in for the runtime's return data slot, clock and rent
+    struct HostStubs;
+
+    static RETURN_DATA: std::sync::Mutex<Option<(Pubkey, Vec<u8>)>> = std::sync::Mutex::new(None);
+    // Every CPI made, with the address its signer seeds derive
+    static CPIS: std::sync::Mutex<Vec<(anchor_lang::solana_program::instruction::Instruction, Vec<Pubkey>)>> =
+        std::sync::Mutex::new(Vec::new());
+    static HOST_TIME: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(1_700_000_000);
+
+    impl anchor_lang::solana_program::program_stubs::SyscallStubs for HostStubs {
+        fn sol_set_return_data(&self, data: &[u8]) {
+            *RETURN_DATA.lock().unwrap() = Some((crate::ID, data.to_vec()));
+        }
+
+        fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+            RETURN_DATA.lock().unwrap().clone()
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &anchor_lang::solana_program::instruction::Instruction,
+            _account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> anchor_lang::solana_program::entrypoint::ProgramResult {
+            let signers = signers_seeds
+                .iter()
+                .map(|seeds| Pubkey::create_program_address(seeds, &crate::ID).unwrap())
+                .collect();
+            CPIS.lock().unwrap().push((instruction.clone(), signers));
+            Ok(())
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            let clock = Clock {
+                unix_timestamp: HOST_TIME.load(std::sync::atomic::Ordering::SeqCst),
+                ..Clock::default()
+            };
+            unsafe { *(var_addr as *mut Clock) = clock };
+            anchor_lang::solana_program::entrypoint::SUCCESS
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            anchor_lang::solana_program::entrypoint::SUCCESS
+        }
+    }
+
+    #[test]
+    fn invoice_details_round_trip_through_return_data() {
+        use anchor_lang::solana_program::program::get_return_data;
+        use anchor_lang::InstructionData;
+
+        anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(HostStubs));
+
+        let invoice = Invoice {
+            late_fee: Some(1_250_000),
+            insurance_payout: Some(72_000_000),
+            payment_terms_days: 45,
+            ..funded_invoice(9, 80_000_000, 1_700_000_000)
+        };
+        let mut data = Vec::new();
+        invoice.try_serialize(&mut data).unwrap();
+        let key = Pubkey::new_unique();
+        let mut lamports = 1_000_000;
+        let accounts = [AccountInfo::new(&key, false, false, &mut lamports, &mut data, &crate::ID, false, 0)];
+
+        entry(&crate::ID, &accounts, &instruction::GetInvoiceDetails {}.data()).unwrap();
+
+        let (program_id, return_data) = get_return_data().unwrap();
+        assert_eq!(program_id, crate::ID);
+        let details = InvoiceDetails::try_from_slice(&return_data).unwrap();
+        assert_eq!(details, InvoiceDetails::from(&invoice));
+        assert_eq!(details.late_fee, Some(1_250_000));
+        assert_eq!(details.insurance_payout, Some(72_000_000));
+        assert_eq!(details.payment_terms_days, 45);
+    }
+
+    #[test]
+    fn global_stats_decode_from_return_data() {
+        use anchor_lang::solana_program::program::get_return_data;
+        use anchor_lang::InstructionData;
+
+        anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(HostStubs));
+
+        let global_state = GlobalState {
+            bump: Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID).1,
+            version: GLOBAL_STATE_VERSION,
+            invoices_created: 12,
+            total_funded: 900_000_000,
+            total_repaid: 6,
+            total_defaulted: 2,
+            total_premiums: 27_000_000,
+            insurance_pool_balance: 40_000_000,
+            insured_exposure: 60_000_000,
+            ..GlobalState::default()
+        };
+        let mut data = Vec::new();
+        global_state.try_serialize(&mut data).unwrap();
+        data.resize(GlobalState::SIZE, 0);
+        let key = global_state_address();
+        let mut lamports = 1_000_000;
+        // The pool account left out, as before it is initialized
+        let (mut pool_lamports, mut pool_data) = (0, Vec::new());
+        let accounts = [
+            AccountInfo::new(&key, false, false, &mut lamports, &mut data, &crate::ID, false, 0),
+            AccountInfo::new(&crate::ID, false, false, &mut pool_lamports, &mut pool_data, &crate::ID, false, 0),
+        ];
+
+        entry(&crate::ID, &accounts, &instruction::GetGlobalStats {}.data()).unwrap();
+
+        let (program_id, return_data) = get_return_data().unwrap();
+        assert_eq!(program_id, crate::ID);
+        let stats = GlobalStats::try_from_slice(&return_data).unwrap();
+        // Uncapped, so the clock the handler read doesn't matter
+        assert_eq!(stats, global_state.stats(None, 0));
+        assert_eq!(stats.remaining_daily_capacity, u64::MAX);
+        assert_eq!((stats.invoices_created, stats.total_repaid, stats.total_premiums), (12, 6, 27_000_000));
+        assert_eq!(stats.default_rate_bps, 2_500);
+        assert_eq!(stats.coverage_utilization_bps, 15_000);
+        assert_eq!((stats.pool_token_balance, stats.pool_balance_drift), (None, None));
+
+        // A token balance short of the tracked one drifts negative
+        let stats = global_state.stats(Some(39_000_000), 0);
+        assert_eq!(stats.pool_balance_drift, Some(-1_000_000));
+        assert_eq!(GlobalState::default().stats(None, 0).default_rate_bps, 0);
+
+        // Capped, the headroom is what today leaves and resets at midnight
+        let day = 20_000 * 86_400;
+        let capped = GlobalState {
+            daily_funding_cap: 5_000_000,
+            funded_today: 2_000_000,
+            day_start_ts: day,
+            ..global_state
+        };
+        assert_eq!(capped.stats(None, day + 3_600).remaining_daily_capacity, 3_000_000);
+        assert_eq!(capped.stats(None, day + 86_400).remaining_daily_capacity, 5_000_000);
+    }
+
+    #[test]
+    fn constant_seeds_derive_the_addresses_instructions_check() {
+        use std::collections::BTreeSet;
+
+        let business_owner = Pubkey::new_unique();
+        let (invoice_key, invoice_bump) =
+            Pubkey::find_program_address(&[INVOICE_SEED, business_owner.as_ref(), &4u64.to_le_bytes()], &crate::ID);
+        let (state_key, state_bump) = Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID);
+        let invoice = Invoice { business_owner, bump: invoice_bump, ..funded_invoice(4, 1_000_000, 1_700_000_000) };
+        let state = GlobalState { bump: state_bump, version: GLOBAL_STATE_VERSION, ..GlobalState::default() };
+        let (mut invoice_data, mut state_data) = (Vec::new(), Vec::new());
+        invoice.try_serialize(&mut invoice_data).unwrap();
+        state.try_serialize(&mut state_data).unwrap();
+
+        let check = |invoice_key: &Pubkey| {
+            let (mut invoice_data, mut state_data) = (invoice_data.clone(), state_data.clone());
+            let (mut invoice_lamports, mut state_lamports, mut wallet_lamports) = (1_000_000, 1_000_000, 1_000_000);
+            let (wallet, mut wallet_data) = (Pubkey::new_unique(), Vec::new());
+            let system = anchor_lang::system_program::ID;
+            let infos = [
+                AccountInfo::new(invoice_key, false, true, &mut invoice_lamports, &mut invoice_data, &crate::ID, false, 0),
+                AccountInfo::new(&state_key, false, false, &mut state_lamports, &mut state_data, &crate::ID, false, 0),
+                AccountInfo::new(&wallet, true, false, &mut wallet_lamports, &mut wallet_data, &system, false, 0),
+            ];
+            AcknowledgeInvoice::try_accounts(
+                &crate::ID,
+                &mut &infos[..],
+                &[],
+                &mut AcknowledgeInvoiceBumps::default(),
+                &mut BTreeSet::new(),
+            )
+            .map(|_| ())
+        };
+
+        check(&invoice_key).unwrap();
+        // The same invoice data at any other address fails the seeds constraint
+        let error = check(&Pubkey::new_unique()).unwrap_err();
+        assert_eq!(error, error!(anchor_lang::error::ErrorCode::ConstraintSeeds));
+    }
+
+    #[cfg(feature = "cpi-events")]
+    #[test]
+    fn indexed_events_decode_from_the_self_cpi() {
+        anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(HostStubs));
+
+        let (authority, bump) = Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], &crate::ID);
+        let (mut lamports, mut data) = (0, Vec::new());
+        let system = anchor_lang::system_program::ID;
+        let info = AccountInfo::new(&authority, true, false, &mut lamports, &mut data, &system, false, 0);
+        let (invoice, investor) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let repaid = InvoiceRepaid {
+            invoice_id: 90_210,
+            amount: 102_000_000,
+            late_fee: 500_000,
+            days_overdue: 3,
+            yield_paid: 1_500_000,
+            early_repayment_discount: 0,
+            invoice,
+            business_owner: Pubkey::new_unique(),
+            investor,
+            timestamp: 1_700_000_000,
+        };
+        emit_indexed(EventAuthority { info: &info, bump }, repaid).unwrap();
+
+        // What an indexer reads from the inner instruction: the event CPI tag, then
+        // the event as it would have been logged
+        let decode = |data: &[u8]| {
+            let data = data.strip_prefix(&anchor_lang::event::EVENT_IX_TAG_LE[..])?;
+            let data = data.strip_prefix(&InvoiceRepaid::DISCRIMINATOR[..])?;
+            InvoiceRepaid::try_from_slice(data).ok()
+        };
+        let (ix, signers) = CPIS
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(ix, _)| decode(&ix.data).is_some_and(|event| event.invoice_id == 90_210))
+            .cloned()
+            .unwrap();
+        let event = decode(&ix.data).unwrap();
+        assert_eq!((event.amount, event.late_fee, event.days_overdue), (102_000_000, 500_000, 3));
+        assert_eq!((event.invoice, event.investor), (invoice, investor));
+        assert_eq!(ix.program_id, crate::ID);
+        assert_eq!(ix.accounts, vec![AccountMeta::new_readonly(authority, true)]);
+        assert_eq!(signers, vec![authority]);
+
+        // The program takes the call back as a no-op signed by its event authority
+        let signed = [info.clone()];
+        entry(&crate::ID, &signed, &ix.data).unwrap();
+        let unsigned = [AccountInfo { is_signer: false, ..info.clone() }];
+        assert!(entry(&crate::ID, &unsigned, &ix.data).is_err());
+    }
+
+    // Stand-in for the SPL Governance program: it owns the governance account and,
+    // when a proposal executes, signs for it. Here that is simply an account owned
+    // by the mock program id, at the address derived for the configured realm.
+    struct MockGovernance {
+        program: Pubkey,
+        realm: Pubkey,
+    }
+
+    impl MockGovernance {
+        fn account(&self) -> Pubkey {
+            governance_address(&self.program, &self.realm, &global_state_address())
+        }
+    }
+
+    fn global_state_address() -> Pubkey {
+        Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID).0
+    }
+
+    // Protocol accounts that UpdateGlobalState instructions touch, kept as raw data
+    // across calls the way the runtime keeps them between transactions
+    struct AdminHarness {
+        global_state: Vec<u8>,
+        admin_log: Vec<u8>,
+    }
+
+    impl AdminHarness {
+        fn new(authority: Pubkey) -> Self {
+            let (_, bump) = Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID);
+            let (_, log_bump) = Pubkey::find_program_address(&[ADMIN_LOG_SEED, 0u32.to_le_bytes().as_ref()], &crate::ID);
+
+            let mut global_state = Vec::new();
+            GlobalState { authority, bump, version: GLOBAL_STATE_VERSION, ..GlobalState::default() }.try_serialize(&mut global_state).unwrap();
+            global_state.resize(GlobalState::SIZE, 0);
+            let mut admin_log = Vec::new();
+            AdminActionLog { page: 0, entries: Vec::new(), bump: log_bump }.try_serialize(&mut admin_log).unwrap();
+            admin_log.resize(AdminActionLog::SIZE, 0);
+            Self { global_state, admin_log }
+        }
+
+        fn call(&mut self, signer: Pubkey, signer_owner: Pubkey, data: Vec<u8>) -> std::result::Result<(), ProgramError> {
+            let global_state_key = global_state_address();
+            let admin_log_key = Pubkey::find_program_address(&[ADMIN_LOG_SEED, 0u32.to_le_bytes().as_ref()], &crate::ID).0;
+            let (mut state_lamports, mut log_lamports, mut signer_lamports) = (1_000_000, 1_000_000, 1_000_000);
+            let mut signer_data = vec![0u8; 8];
+            let accounts = [
+                AccountInfo::new(&global_state_key, false, true, &mut state_lamports, &mut self.global_state, &crate::ID, false, 0),
+                AccountInfo::new(&admin_log_key, false, true, &mut log_lamports, &mut self.admin_log, &crate::ID, false, 0),
+                AccountInfo::new(&signer, true, false, &mut signer_lamports, &mut signer_data, &signer_owner, false, 0),
+            ];
+            entry(&crate::ID, &accounts, &data)
+        }
+
+        fn state(&self) -> GlobalState {
+            GlobalState::try_deserialize(&mut self.global_state.as_slice()).unwrap()
+        }
+
+        fn log(&self) -> AdminActionLog {
+            AdminActionLog::try_deserialize(&mut self.admin_log.as_slice()).unwrap()
+        }
+    }
+
+    fn custom(code: ErrorCode) -> std::result::Result<(), ProgramError> {
+        Err(ProgramError::Custom(code.into()))
+    }
+
+    #[test]
+    fn governed_instructions_require_governance_once_enabled() {
+        use anchor_lang::InstructionData;
+
+        anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(HostStubs));
+
+        let authority = Pubkey::new_unique();
+        let system = anchor_lang::solana_program::system_program::ID;
+        let governance = MockGovernance { program: Pubkey::new_unique(), realm: Pubkey::new_unique() };
+        let mut harness = AdminHarness::new(authority);
+        let retention = |secs: i64| instruction::SetRetentionPeriod { retention_period_secs: secs }.data();
+
+        // Before enablement the keypair runs governed instructions itself
+        harness.call(authority, system, retention(86_400)).unwrap();
+        harness
+            .call(
+                authority,
+                system,
+                instruction::ConfigureGovernance { governance_program: governance.program, realm: governance.realm }.data(),
+            )
+            .unwrap();
+
+        // Enablement waits out the timelock
+        harness.call(authority, system, instruction::ProposeGovernanceEnabled { enabled: true }.data()).unwrap();
+        assert_eq!(
+            harness.call(authority, system, instruction::ApplyGovernanceEnabled {}.data()),
+            custom(ErrorCode::TimelockNotElapsed)
+        );
+        HOST_TIME.fetch_add(PARAMETER_TIMELOCK_SECS, std::sync::atomic::Ordering::SeqCst);
+        harness.call(authority, system, instruction::ApplyGovernanceEnabled {}.data()).unwrap();
+        assert!(harness.state().governance_enabled);
+
+        // The keypair is now refused on the governed lane but keeps the fast lane
+        assert_eq!(harness.call(authority, system, retention(3_600)), custom(ErrorCode::GovernanceRequired));
+        harness.call(authority, system, instruction::Pause { flags: PAUSE_FUND }.data()).unwrap();
+        assert_eq!(harness.state().paused, PAUSE_FUND);
+
+        // The governance account passes; the same address owned by another program does not
+        harness.call(governance.account(), governance.program, retention(3_600)).unwrap();
+        assert_eq!(harness.state().retention_period_secs, 3_600);
+        assert_eq!(
+            harness.call(governance.account(), system, retention(60)),
+            custom(ErrorCode::Unauthorized)
+        );
+        let other_realm = MockGovernance { program: governance.program, realm: Pubkey::new_unique() };
+        assert_eq!(
+            harness.call(other_realm.account(), governance.program, retention(60)),
+            custom(ErrorCode::Unauthorized)
+        );
+
+        // Governance can also run the fast lane, and the log tells the two paths apart
+        harness.call(governance.account(), governance.program, instruction::Unpause { flags: PAUSE_FUND }.data()).unwrap();
+        let roles: Vec<AdminRole> = harness.log().entries.iter().map(|entry| entry.role).collect();
+        assert_eq!(
+            roles,
+            [
+                AdminRole::ProtocolAuthority,
+                AdminRole::ProtocolAuthority,
+                AdminRole::ProtocolAuthority,
+                AdminRole::ProtocolAuthority,
+                AdminRole::ProtocolAuthority,
+                AdminRole::Governance,
+                AdminRole::Governance,
+            ]
+        );
+        assert_eq!(harness.state().retention_period_secs, 3_600);
+    }
+
+    // Accounts of commit_book_root kept between pages, plus the invoices of the book
+    struct BookHarness {
+        global_state: Vec<u8>,
+        commitment: Vec<u8>,
+        treasury: Vec<u8>,
+        treasury_lamports: u64,
+        cranker_lamports: u64,
+        invoices: Vec<(Pubkey, Vec<u8>)>,
+    }
+
+    impl BookHarness {
+        fn new(invoices: &[Invoice]) -> Self {
+            let (_, bump) = Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID);
+            let (_, commitment_bump) = Self::commitment_address();
+            let (_, treasury_bump) = Pubkey::find_program_address(&[CRANK_TREASURY_SEED], &crate::ID);
+            let account = |value: &dyn Fn(&mut Vec<u8>), size: usize| {
+                let mut data = Vec::new();
+                value(&mut data);
+                data.resize(size, 0);
+                data
+            };
+
+            let state = GlobalState {
+                bump,
+                invoices_created: invoices.len() as u64,
+                version: GLOBAL_STATE_VERSION,
+                ..GlobalState::default()
+            };
+            let commitment = BookCommitment { bump: commitment_bump, ..BookCommitment::default() };
+            let treasury = CrankTreasury { bump: treasury_bump };
+            Self {
+                global_state: account(&|data| state.try_serialize(data).unwrap(), GlobalState::SIZE),
+                commitment: account(&|data| commitment.try_serialize(data).unwrap(), BookCommitment::SIZE),
+                treasury: account(&|data| treasury.try_serialize(data).unwrap(), CrankTreasury::SIZE),
+                treasury_lamports: Rent::default().minimum_balance(CrankTreasury::SIZE) + 1_000_000,
+                cranker_lamports: 0,
+                invoices: invoices
+                    .iter()
+                    .map(|invoice| (Pubkey::new_unique(), account(&|data| invoice.try_serialize(data).unwrap(), Invoice::SIZE)))
+                    .collect(),
+            }
+        }
+
+        fn commitment_address() -> (Pubkey, u8) {
+            Pubkey::find_program_address(&[BOOK_COMMITMENT_SEED, 0u64.to_le_bytes().as_ref()], &crate::ID)
+        }
+
+        fn commit(&mut self, page: std::ops::Range<usize>, final_page: bool) -> std::result::Result<(), ProgramError> {
+            use anchor_lang::InstructionData;
+
+            let keys = [
+                Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID).0,
+                Self::commitment_address().0,
+                Pubkey::find_program_address(&[CRANK_TREASURY_SEED], &crate::ID).0,
+                Pubkey::new_unique(),
+                anchor_lang::solana_program::system_program::ID,
+            ];
+            let rent = Rent::default();
+            let mut state_lamports = rent.minimum_balance(GlobalState::SIZE);
+            let mut commitment_lamports = rent.minimum_balance(BookCommitment::SIZE);
+            let mut system_lamports = 1;
+            let mut system_data = Vec::new();
+            let mut invoice_lamports = vec![1_000_000u64; page.len()];
+            let loader = Pubkey::default();
+
+            let mut accounts = vec![
+                AccountInfo::new(&keys[0], false, true, &mut state_lamports, &mut self.global_state, &crate::ID, false, 0),
+                AccountInfo::new(&keys[1], false, true, &mut commitment_lamports, &mut self.commitment, &crate::ID, false, 0),
+                AccountInfo::new(&keys[2], false, true, &mut self.treasury_lamports, &mut self.treasury, &crate::ID, false, 0),
+                AccountInfo::new(&keys[3], true, true, &mut self.cranker_lamports, &mut [], &keys[4], false, 0),
+                AccountInfo::new(&keys[4], false, false, &mut system_lamports, &mut system_data, &loader, true, 0),
+            ];
+            for ((key, data), lamports) in self.invoices[page].iter_mut().zip(invoice_lamports.iter_mut()) {
+                accounts.push(AccountInfo::new(key, false, false, lamports, data, &crate::ID, false, 0));
+            }
+            entry(&crate::ID, &accounts, &instruction::CommitBookRoot { final_page }.data())
+        }
+
+        fn commitment(&self) -> BookCommitment {
+            BookCommitment::try_deserialize(&mut self.commitment.as_slice()).unwrap()
+        }
+
+        fn leaves(&self) -> Vec<BookLeaf> {
+            self.invoices
+                .iter()
+                .map(|(key, data)| BookLeaf::of(*key, &Invoice::try_deserialize(&mut data.as_slice()).unwrap()))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn book_root_commits_across_pages_and_proves_membership() {
+        use book::{verify_book_proof, BookTree};
+
+        anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(HostStubs));
+
+        // Twenty invoices in creation order, at various stages of their life
+        let book: Vec<Invoice> = (1..=20u64)
+            .map(|nonce| {
+                let invoice = funded_invoice(nonce, nonce * 1_000_000, 1_702_000_000);
+                let invoice = match nonce % 4 {
+                    0 => Invoice { status: InvoiceStatus::PendingFunding, funded_amount: 0, ..invoice },
+                    1 => Invoice { funding_date: Some(1_700_000_000 + nonce as i64), ..invoice },
+                    2 => Invoice {
+                        status: InvoiceStatus::Repaid,
+                        remaining_balance: 0,
+                        amount_repaid: nonce * 1_000_000,
+                        final_repayment_amount: Some(nonce * 1_050_000),
+                        ..invoice
+                    },
+                    _ => Invoice { status: InvoiceStatus::Defaulted, defaulted_at: Some(1_703_000_000), ..invoice },
+                };
+                Invoice { book_nonce: nonce, ..invoice }
+            })
+            .collect();
+        let mut harness = BookHarness::new(&book);
+
+        harness.commit(0..10, false).unwrap();
+        assert!(!harness.commitment().finalized);
+        // Nonces already folded cannot be folded again
+        assert_eq!(harness.commit(9..12, true), custom(ErrorCode::BookNonceOutOfOrder));
+        harness.commit(10..20, true).unwrap();
+
+        let commitment = harness.commitment();
+        assert!(commitment.finalized);
+        assert_eq!((commitment.first_nonce, commitment.last_nonce, commitment.through_nonce), (1, 20, 20));
+        assert_eq!(commitment.frontier.leaf_count, 20);
+        assert_eq!(harness.cranker_lamports, 20 * BOOK_CRANK_REWARD_PER_LEAF);
+
+        // Off chain: rebuild the tree from the invoice accounts and check proofs
+        let leaves = harness.leaves();
+        let tree = BookTree::new(&leaves);
+        assert_eq!(tree.root(), commitment.root);
+
+        let repaid = &leaves[13];
+        assert_eq!((repaid.nonce, repaid.status), (14, InvoiceStatus::Repaid));
+        assert!(verify_book_proof(&commitment.root, repaid, &tree.proof(13)));
+
+        // Claiming the invoice defaulted instead, or shaving its repayment, fails
+        let falsified = BookLeaf { status: InvoiceStatus::Defaulted, ..*repaid };
+        assert!(!verify_book_proof(&commitment.root, &falsified, &tree.proof(13)));
+        let falsified = BookLeaf { final_repayment_amount: Some(1), ..*repaid };
+        assert!(!verify_book_proof(&commitment.root, &falsified, &tree.proof(13)));
+    }
+
+    #[test]
+    fn whitelist_gates_funding_only_while_required() {
+        let now = 1_700_000_000;
+        let entry = |approved: bool, expires_at: Option<i64>| InvestorWhitelist { approved, expires_at, ..InvestorWhitelist::default() };
+        let mut state = GlobalState::default();
+
+        // Off: anyone funds, listed or not
+        assert!(state.require_whitelisted(None, now).is_ok());
+        assert!(state.require_whitelisted(Some(&entry(false, None)), now).is_ok());
+
+        state.require_whitelist = true;
+        assert!(state.require_whitelisted(Some(&entry(true, None)), now).is_ok());
+        assert!(state.require_whitelisted(Some(&entry(true, Some(now + 1))), now).is_ok());
+        for refused in [None, Some(entry(false, None)), Some(entry(true, Some(now))), Some(entry(true, Some(now - 1)))] {
+            assert_eq!(
+                state.require_whitelisted(refused.as_ref(), now).unwrap_err(),
+                ErrorCode::InvestorNotWhitelisted.into()
+            );
+        }
+    }
+
+    #[test]
+    fn verified_profile_drives_the_gate_and_the_risk_inputs() {
+        let mut state = GlobalState::default();
+        let mut profile = BusinessProfile { credit_score: 820, industry_code: 5_415, ..BusinessProfile::default() };
+        let now = 1_700_000_000;
+        let risk = |profile: &BusinessProfile, attested_score: Option<u16>| {
+            let listing = ListingRisk {
+                amount: 10_000_000,
+                due_date: now + 60 * 86_400,
+                industry: IndustryCode::Services,
+                attested_score,
+            };
+            calculate_enhanced_risk(&listing, profile, &RiskParams::DEFAULT, &RiskConfig::DEFAULT, now)
+        };
+
+        // Off: unverified and unregistered businesses list as before
+        assert!(!state.business_unverified(None));
+        assert!(!state.business_unverified(Some(&profile)));
+        state.require_business_verification = true;
+        assert!(state.business_unverified(None));
+        assert!(state.business_unverified(Some(&profile)));
+
+        // The attested score and sector only count once verified
+        let unverified = risk(&profile, None);
+        assert_eq!(unverified.estimated_credit_score, 0);
+        profile.verified = true;
+        assert!(!state.business_unverified(Some(&profile)));
+        let verified = risk(&profile, None);
+        assert_eq!(verified.estimated_credit_score, 820);
+        assert!(verified.risk_score < unverified.risk_score);
+
+        // A fresh oracle attestation outranks the score recorded at verification
+        let attested = risk(&profile, Some(640));
+        assert_eq!(attested.estimated_credit_score, 640);
+        assert!(attested.risk_score > verified.risk_score);
+    }
+
+    #[test]
+    fn debtor_exposure_stays_under_the_cap() {
+        let mut debtor = Debtor::default();
+        let cap = 25_000_000;
+        debtor.book_funding(10_000_000, cap).unwrap();
+        debtor.book_funding(10_000_000, cap).unwrap();
+        assert!(debtor.book_funding(10_000_000, cap).is_err());
+        assert_eq!(debtor.outstanding, 20_000_000);
+        // No cap, no limit
+        debtor.require_capacity(u64::MAX - debtor.outstanding, 0).unwrap();
+
+        // Repayment frees room; a default writes the rest off and is remembered
+        debtor.release(8_000_000);
+        debtor.book_funding(10_000_000, cap).unwrap();
+        debtor.record_default(22_000_000);
+        assert_eq!(debtor.outstanding, 0);
+        assert_eq!((debtor.invoices_financed, debtor.total_financed), (3, 30_000_000));
+        assert_eq!((debtor.defaults, debtor.defaulted_amount), (1, 22_000_000));
+
+        let legacy = Invoice::default();
+        assert!(legacy.booked_debtor(None).unwrap().is_none());
+        let listed = Invoice { debtor: Pubkey::new_unique(), ..Invoice::default() };
+        assert!(listed.booked_debtor(None).is_err());
+        assert!(listed.booked_debtor(Some(&mut debtor)).unwrap().is_some());
+    }
+
+    #[test]
+    fn exposure_caps_hold_per_party_until_repaid() {
+        let mut state = GlobalState::default();
+        let mut investor = InvestorStats::default();
+        let mut business = BusinessProfile::default();
+
+        // Uncapped by default
+        state.book_exposure(&mut investor, &mut business, 900_000_000).unwrap();
+        state.investor_exposure_cap = 1_000_000_000;
+        state.business_exposure_cap = 950_000_000;
+        assert_eq!(
+            state.book_exposure(&mut investor, &mut business, 150_000_000).unwrap_err(),
+            error!(ErrorCode::InvestorExposureExceeded)
+        );
+        assert_eq!(investor.outstanding, 900_000_000);
+        let mut fresh_investor = InvestorStats::default();
+        assert_eq!(
+            state.book_exposure(&mut fresh_investor, &mut business, 100_000_000).unwrap_err(),
+            error!(ErrorCode::BusinessExposureExceeded)
+        );
+
+        let mut invoice = Invoice::default();
+        invoice.release_exposure(None, &mut business, 100_000_000).unwrap();
+        assert_eq!(business.outstanding_financed, 900_000_000);
+
+        invoice.exposure_booked = true;
+        assert!(invoice.release_exposure(None, &mut business, 100_000_000).is_err());
+        invoice.release_exposure(Some(&mut investor), &mut business, 100_000_000).unwrap();
+        assert_eq!((investor.outstanding, business.outstanding_financed), (800_000_000, 800_000_000));
+        state.book_exposure(&mut investor, &mut business, 100_000_000).unwrap();
+    }
+
+    #[test]
+    fn business_stats_count_each_invoice_once() {
+        let state = GlobalState::default();
+        let mut investor = InvestorStats::default();
+        let mut business =
+            BusinessProfile { business_owner: Pubkey::new_unique(), invoices_created: 5, ..BusinessProfile::default() };
+        let invoice = Invoice { exposure_booked: true, ..Invoice::default() };
+
+        // Two outright fundings and a bundle of two, one funding reclaimed from escrow
+        for amount in [100_000_000, 40_000_000] {
+            state.book_exposure(&mut investor, &mut business, amount).unwrap();
+            business.record_funding(1, amount).unwrap();
+        }
+        state.book_exposure(&mut investor, &mut business, 60_000_000).unwrap();
+        business.record_funding(2, 60_000_000).unwrap();
+        invoice.release_exposure(Some(&mut investor), &mut business, 40_000_000).unwrap();
+        business.unrecord_funding(40_000_000);
+
+        // A partial repayment settles nothing; the final one and a default each count once
+        invoice.release_exposure(Some(&mut investor), &mut business, 30_000_000).unwrap();
+        invoice.release_exposure(Some(&mut investor), &mut business, 70_000_000).unwrap();
+        business.credit_history.record_repayment(true, 100_000_000);
+        invoice.release_exposure(Some(&mut investor), &mut business, 25_000_000).unwrap();
+        business.credit_history.record_default(25_000_000);
+
+        let stats = business.stats();
+        assert_eq!(stats.business, business.business_owner);
+        assert_eq!((stats.invoices_created, stats.invoices_funded), (5, 3));
+        assert_eq!(stats.volume_funded, 160_000_000);
+        assert_eq!((stats.repaid_on_time, stats.repaid_late, stats.defaulted), (0, 1, 1));
+        assert_eq!(stats.outstanding, 35_000_000);
+    }
+
+    #[test]
+    fn open_invoice_cap_frees_up_as_invoices_settle() {
+        let mut state = GlobalState::default();
+        let mut business = BusinessProfile { open_invoices: 2, ..BusinessProfile::default() };
+        // No cap, and no profile yet for a first listing
+        assert!(!state.open_invoice_limit_reached(Some(&business)));
+        state.max_open_invoices_per_business = 2;
+        assert!(state.open_invoice_limit_reached(Some(&business)));
+        assert!(!state.open_invoice_limit_reached(None));
+
+        business.close_invoice();
+        assert!(!state.open_invoice_limit_reached(Some(&business)));
+        assert_eq!(business.stats().open_invoices, 1);
+
+        // Invoices listed before the count was kept do not take it below zero
+        business.close_invoice();
+        business.close_invoice();
+        assert_eq!(business.open_invoices, 0);
+    }
+
+    #[test]
+    fn approved_mints_sit_beside_the_fixed_primary() {
+        let usdc = Pubkey::new_unique();
+        let usdt = Pubkey::new_unique();
+        let mut state = GlobalState { usdc_mint: usdc, ..GlobalState::default() };
+        assert!(state.is_approved_mint(&usdc));
+        assert!(!state.is_approved_mint(&usdt));
+
+        state.set_mint_approval(usdt, true).unwrap();
+        state.set_mint_approval(usdt, true).unwrap();
+        assert_eq!(state.approved_mints, vec![usdt]);
+        assert!(state.is_approved_mint(&usdt));
+        assert!(state.set_mint_approval(usdc, false).is_err());
+
+        for _ in 1..MAX_APPROVED_MINTS {
+            state.set_mint_approval(Pubkey::new_unique(), true).unwrap();
+        }
+        assert_eq!(
+            state.set_mint_approval(Pubkey::new_unique(), true).unwrap_err(),
+            error!(ErrorCode::ApprovedMintsFull)
+        );
+        assert!(state.try_to_vec().unwrap().len() + 8 <= GlobalState::SIZE);
+        state.set_mint_approval(usdt, false).unwrap();
+        assert!(!state.is_approved_mint(&usdt));
+    }
+
+    #[test]
+    fn a_reclaimed_escrow_leaves_no_trace_on_balances_or_debtors() {
+        let mut balance = InvestorBalance { available: 1_000, ..InvestorBalance::default() };
+        balance.draw(900, 50).unwrap();
+        balance.refund(900, 50).unwrap();
+        assert_eq!((balance.available, balance.premium_owed), (1_000, 0));
+        assert!(balance.reconciles(1_000));
+
+        let mut debtor = Debtor::default();
+        debtor.book_funding(10_000_000, 0).unwrap();
+        debtor.book_funding(5_000_000, 0).unwrap();
+        debtor.unbook(5_000_000);
+        assert_eq!(debtor.outstanding, 10_000_000);
+        assert_eq!((debtor.invoices_financed, debtor.total_financed), (1, 10_000_000));
+
+        let invoice = Invoice {
+            debtor_info_uri: Some("d".repeat(MAX_DEBTOR_INFO_URI_LEN as usize)),
+            escrow: Some(FundingEscrow { deadline: 1_700_000_000, premium: 1_000_000, from_balance: false }),
+            ..Invoice::default()
+        };
+        assert!(invoice.try_to_vec().unwrap().len() + 8 <= Invoice::SIZE);
+    }
+
+    #[test]
+    fn escrow_compensation_covers_whole_days_up_to_the_deadline() {
+        let day = 86_400;
+        let funded_at = 1_700_000_000;
+        let escrow = FundingEscrow { deadline: funded_at + 2 * day, premium: 0, from_balance: false };
+
+        // Two days at 5 bps a day on 100 USDC
+        assert_eq!(escrow.compensation(100_000_000, funded_at, 5).unwrap(), 100_000);
+        assert_eq!(escrow.compensation(100_000_000, funded_at, 0).unwrap(), 0);
+        // A part day earns nothing, nor does a deadline before the funding
+        assert_eq!(escrow.compensation(100_000_000, funded_at + 1, 5).unwrap(), 50_000);
+        assert_eq!(escrow.compensation(100_000_000, funded_at + 3 * day, 5).unwrap(), 0);
+
+        let long = FundingEscrow { deadline: i64::MAX, ..escrow };
+        assert_eq!(long.compensation(u64::MAX, 0, u16::MAX).unwrap_err(), error!(ErrorCode::MathOverflow));
+    }
+
+    #[test]
+    fn a_reclaimed_escrow_is_compensated_from_the_treasury() {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use anchor_lang::InstructionData;
+        use anchor_spl::token::spl_token::{
+            instruction::TokenInstruction,
+            state::{Account as SplTokenAccount, AccountState},
+        };
+
+        anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(HostStubs));
+
+        let day = 86_400;
+        let (business_owner, investor, mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (invoice_key, invoice_bump) =
+            Pubkey::find_program_address(&[INVOICE_SEED, business_owner.as_ref(), &3u64.to_le_bytes()], &crate::ID);
+        let (vault_key, _) = Pubkey::find_program_address(&[INVOICE_VAULT_SEED, invoice_key.as_ref()], &crate::ID);
+        let (state_key, state_bump) = Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID);
+        let (stats_key, stats_bump) =
+            Pubkey::find_program_address(&[INVESTOR_STATS_SEED, investor.as_ref()], &crate::ID);
+        let (profile_key, profile_bump) =
+            Pubkey::find_program_address(&[BUSINESS_PROFILE_SEED, business_owner.as_ref()], &crate::ID);
+        let (allowlist_key, allowlist_bump) =
+            Pubkey::find_program_address(&[ALLOWLIST_SEED, VaultKind::Treasury.seed().as_ref()], &crate::ID);
+        let (authority_key, _) = pda::find_treasury_authority_address();
+        let wallet_key = anchor_spl::associated_token::get_associated_token_address(&investor, &mint);
+        let treasury_key = Pubkey::new_unique();
+
+        // Funded 2 days before a deadline that has since passed, at 5 bps a day
+        let deadline = 1_600_000_000;
+        let invoice = Invoice {
+            business_owner,
+            investor,
+            mint,
+            bump: invoice_bump,
+            status: InvoiceStatus::FundedPendingAcceptance,
+            funding_date: Some(deadline - 2 * day),
+            escrow: Some(FundingEscrow { deadline, premium: 1_000_000, from_balance: false }),
+            ..funded_invoice(3, 100_000_000, deadline + 60 * day)
+        };
+        let state = GlobalState {
+            bump: state_bump,
+            version: GLOBAL_STATE_VERSION,
+            total_funded: 100_000_000,
+            escrow_compensation_bps_per_day: 5,
+            ..GlobalState::default()
+        };
+        let stats = InvestorStats { investor, deployed_capital: 100_000_000, bump: stats_bump, ..InvestorStats::default() };
+        let profile = BusinessProfile { business_owner, bump: profile_bump, ..BusinessProfile::default() };
+        let allowlist = DestinationAllowlist {
+            vault: VaultKind::Treasury,
+            destinations: vec![AllowlistedDestination {
+                name: [0u8; 32],
+                token_account: treasury_key,
+                active_at: 0,
+                removable_at: None,
+            }],
+            frozen_at: None,
+            bump: allowlist_bump,
+        };
+        let serialize = |account: &dyn Fn(&mut Vec<u8>) -> Result<()>, size: usize| {
+            let mut data = Vec::new();
+            account(&mut data).unwrap();
+            data.resize(size.max(data.len()), 0);
+            data
+        };
+        let token_account = |owner: Pubkey, amount: u64| {
+            let account = SplTokenAccount { mint, owner, amount, state: AccountState::Initialized, ..Default::default() };
+            let mut data = vec![0u8; SplTokenAccount::LEN];
+            SplTokenAccount::pack(account, &mut data).unwrap();
+            data
+        };
+        let mut invoice_data = serialize(&|data| invoice.try_serialize(data), 0);
+        let mut state_data = serialize(&|data| state.try_serialize(data), GlobalState::SIZE);
+        let mut stats_data = serialize(&|data| stats.try_serialize(data), InvestorStats::SIZE);
+        let mut profile_data = serialize(&|data| profile.try_serialize(data), 0);
+        let mut allowlist_data = serialize(&|data| allowlist.try_serialize(data), DestinationAllowlist::SIZE);
+        let mut vault_data = token_account(invoice_key, 101_000_000);
+        let mut wallet_data = token_account(investor, 0);
+        let mut treasury_data = token_account(authority_key, 1_000_000_000);
+        let (mut investor_data, mut authority_data, mut none_data, mut token_program_data) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let mut lamports = [1_000_000u64; 12];
+        let [l0, l1, l2, l3, l4, l5, l6, l7, l8, l9, l10, l11] = &mut lamports;
+        let (system, token) = (anchor_lang::system_program::ID, token::ID);
+        // Optional accounts left out are passed as the program id
+        let none = AccountInfo::new(&crate::ID, false, false, l5, &mut none_data, &crate::ID, false, 0);
+        let accounts = [
+            AccountInfo::new(&invoice_key, false, true, l0, &mut invoice_data, &crate::ID, false, 0),
+            AccountInfo::new(&investor, true, false, l1, &mut investor_data, &system, false, 0),
+            AccountInfo::new(&state_key, false, true, l2, &mut state_data, &crate::ID, false, 0),
+            AccountInfo::new(&vault_key, false, true, l3, &mut vault_data, &token, false, 0),
+            AccountInfo::new(&wallet_key, false, true, l4, &mut wallet_data, &token, false, 0),
+            // No investor balance or custody: a wallet funding
+            none.clone(),
+            none.clone(),
+            AccountInfo::new(&stats_key, false, true, l6, &mut stats_data, &crate::ID, false, 0),
+            AccountInfo::new(&profile_key, false, true, l7, &mut profile_data, &crate::ID, false, 0),
+            // No debtor
+            none,
+            AccountInfo::new(&token, false, false, l8, &mut token_program_data, &system, true, 0),
+            AccountInfo::new(&treasury_key, false, true, l9, &mut treasury_data, &token, false, 0),
+            AccountInfo::new(&allowlist_key, false, false, l10, &mut allowlist_data, &crate::ID, false, 0),
+            AccountInfo::new(&authority_key, false, false, l11, &mut authority_data, &system, false, 0),
+        ];
+
+        entry(&crate::ID, &accounts, &instruction::ReclaimEscrow {}.data()).unwrap();
+
+        // The refund leaves the vault and the compensation the treasury, both to the wallet
+        let transfers: Vec<_> = CPIS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(ix, _)| ix.program_id == token && ix.accounts[1].pubkey == wallet_key)
+            .map(|(ix, signers)| match TokenInstruction::unpack(&ix.data).unwrap() {
+                TokenInstruction::Transfer { amount } => (ix.accounts[0].pubkey, amount, signers.clone()),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            transfers,
+            vec![(vault_key, 101_000_000, vec![invoice_key]), (treasury_key, 100_000, vec![authority_key])]
+        );
+
+        let invoice = Invoice::try_deserialize(&mut &accounts[0].try_borrow_data().unwrap()[..]).unwrap();
+        assert_eq!((invoice.status, invoice.escrow), (InvoiceStatus::PendingFunding, None));
+        let stats = InvestorStats::try_deserialize(&mut &accounts[7].try_borrow_data().unwrap()[..]).unwrap();
+        assert_eq!(stats.deployed_capital, 0);
+    }
+
+    #[test]
+    fn batch_repayment_refuses_invoices_in_an_experiment() {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use anchor_lang::InstructionData;
+        use anchor_spl::token::spl_token::state::{Account as SplTokenAccount, AccountState};
+
+        anchor_lang::solana_program::program_stubs::set_syscall_stubs(Box::new(HostStubs));
+
+        let (business_owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (state_key, state_bump) = Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID);
+        let (profile_key, profile_bump) =
+            Pubkey::find_program_address(&[BUSINESS_PROFILE_SEED, business_owner.as_ref()], &crate::ID);
+        let (invoice_key, token_key, destination_key) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let invoice = Invoice {
+            business_owner,
+            mint,
+            experiment: Some(ExperimentAssignment {
+                experiment: Pubkey::new_unique(),
+                arm: ExperimentArm::Treatment,
+                guardrail_fallback: false,
+                control_premium: 2_000_000,
+                treatment_premium: 1_500_000,
+            }),
+            ..funded_invoice(5, 50_000_000, 2_000_000_000)
+        };
+        let state = GlobalState { bump: state_bump, version: GLOBAL_STATE_VERSION, ..GlobalState::default() };
+        let profile = BusinessProfile { business_owner, bump: profile_bump, ..BusinessProfile::default() };
+        let (mut invoice_data, mut state_data, mut profile_data) = (Vec::new(), Vec::new(), Vec::new());
+        invoice.try_serialize(&mut invoice_data).unwrap();
+        state.try_serialize(&mut state_data).unwrap();
+        state_data.resize(GlobalState::SIZE, 0);
+        profile.try_serialize(&mut profile_data).unwrap();
+        profile_data.resize(BusinessProfile::SIZE, 0);
+        let token_account = |owner: Pubkey| {
+            let account = SplTokenAccount { mint, owner, amount: 0, state: AccountState::Initialized, ..Default::default() };
+            let mut data = vec![0u8; SplTokenAccount::LEN];
+            SplTokenAccount::pack(account, &mut data).unwrap();
+            data
+        };
+        let (mut token_data, mut destination_data) = (token_account(business_owner), token_account(Pubkey::new_unique()));
+        let (mut owner_data, mut token_program_data, mut system_program_data) = (Vec::new(), Vec::new(), Vec::new());
+        let (mut authority_data, mut program_data) = (Vec::new(), Vec::new());
+        let mut lamports = [1_000_000u64; 10];
+        // The profile is rent exempt, as init_if_needed checks
+        lamports[3] = Rent::default().minimum_balance(BusinessProfile::SIZE);
+        let [l0, l1, l2, l3, l4, l5, l6, l7, l8, l9] = &mut lamports;
+        let (system, token) = (anchor_lang::system_program::ID, token::ID);
+        let event_authority = Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], &crate::ID).0;
+        let mut accounts = vec![
+            AccountInfo::new(&business_owner, true, true, l0, &mut owner_data, &system, false, 0),
+            AccountInfo::new(&state_key, false, true, l1, &mut state_data, &crate::ID, false, 0),
+            AccountInfo::new(&token_key, false, true, l2, &mut token_data, &token, false, 0),
+            AccountInfo::new(&profile_key, false, true, l3, &mut profile_data, &crate::ID, false, 0),
+            AccountInfo::new(&token, false, false, l4, &mut token_program_data, &system, true, 0),
+            AccountInfo::new(&system, false, false, l5, &mut system_program_data, &system, true, 0),
+        ];
+        if cfg!(feature = "cpi-events") {
+            accounts.push(AccountInfo::new(&event_authority, false, false, l6, &mut authority_data, &system, false, 0));
+            accounts.push(AccountInfo::new(&crate::ID, false, false, l7, &mut program_data, &system, true, 0));
+        }
+        accounts.push(AccountInfo::new(&invoice_key, false, true, l8, &mut invoice_data, &crate::ID, false, 0));
+        accounts.push(AccountInfo::new(&destination_key, false, true, l9, &mut destination_data, &token, false, 0));
+
+        let data = instruction::RepayInvoicesBatch { count: 1, max_total: u64::MAX }.data();
+        assert_eq!(
+            entry(&crate::ID, &accounts, &data).unwrap_err(),
+            ProgramError::from(error!(ErrorCode::InvoiceInExperiment))
+        );
+    }
+
+    #[test]
+    fn funding_fixes_the_yield_over_the_term_left() {
+        let due = 1_700_000_000;
+        let listed = Invoice {
+            amount: 100_000_000,
+            apr_bps: 1_100,
+            expected_return: Some(105_000_000),
+            due_date: due,
+            pricing_version: pricing::APR_PRICING_VERSION,
+            ..Invoice::default()
+        };
+
+        // Funded with a year to run: the full 11%; with a day, one day of it
+        let mut invoice = listed.clone();
+        invoice.fix_term_yield(due - 365 * 86_400).unwrap();
+        assert_eq!(invoice.expected_return, Some(111_000_000));
+        invoice.fix_term_yield(due - 86_400).unwrap();
+        assert_eq!(invoice.expected_return, Some(100_030_136));
+        invoice.fix_term_yield(due + 1).unwrap();
+        assert_eq!(invoice.expected_return, Some(100_000_000));
+
+        // Invoices priced before APR pricing keep their flat yield
+        let mut flat = Invoice { pricing_version: 1, ..listed };
+        flat.fix_term_yield(due - 365 * 86_400).unwrap();
+        assert_eq!(flat.expected_return, Some(105_000_000));
+    }
+
+    #[test]
+    fn acknowledgment_reprices_without_the_listing_penalty() {
+        let mut invoice = Invoice {
+            amount: 100_000_000,
+            risk_score: 30,
+            acknowledgment_penalty: 10,
+            insurance_premium: 3_000_000,
+            expected_return: Some(106_000_000),
+            coverage_percentage: 80,
+            due_date: 1_700_000_000 + 73 * 86_400,
+            ..Invoice::default()
+        };
+        invoice.record_acknowledgment(CoverageTiers::DEFAULT, 0, true, 1_700_000_000).unwrap();
+        assert!(invoice.debtor_acknowledged);
+        assert_eq!(invoice.debtor_acknowledged_at, Some(1_700_000_000));
+        assert_eq!((invoice.risk_score, invoice.acknowledgment_penalty), (20, 0));
+        assert_eq!(invoice.insurance_premium, 2_000_000);
+        // 9% a year over the 73 days still to run
+        assert_eq!((invoice.apr_bps, invoice.expected_return), (900, Some(101_800_000)));
+
+        // Once funded, the term runs from funding_date instead
+        let mut funded = Invoice {
+            amount: 100_000_000,
+            risk_score: 30,
+            acknowledgment_penalty: 10,
+            funding_date: Some(1_700_000_000 - 73 * 86_400),
+            due_date: 1_700_000_000 + 73 * 86_400,
+            ..Invoice::default()
+        };
+        funded.record_acknowledgment(CoverageTiers::DEFAULT, 0, true, 1_700_000_000).unwrap();
+        assert_eq!(funded.expected_return, Some(103_600_000));
+        assert_eq!(invoice.coverage_percentage, 90);
+
+        // Other mints stay uninsured; nothing to take off leaves the price alone
+        let mut uninsured = Invoice { amount: 100_000_000, risk_score: 30, acknowledgment_penalty: 10, ..Invoice::default() };
+        uninsured.record_acknowledgment(CoverageTiers::DEFAULT, 0, false, 0).unwrap();
+        assert_eq!((uninsured.insurance_premium, uninsured.coverage_percentage), (0, 0));
+        let mut unpenalized = Invoice { risk_score: 30, expected_return: Some(1), ..Invoice::default() };
+        unpenalized.record_acknowledgment(CoverageTiers::DEFAULT, 0, true, 0).unwrap();
+        assert_eq!((unpenalized.risk_score, unpenalized.expected_return), (30, Some(1)));
+
+        let mut state = GlobalState { acknowledgment_policy: AcknowledgmentPolicy::Required, ..GlobalState::default() };
+        assert_eq!(
+            state.require_acknowledged(&Invoice::default()).unwrap_err(),
+            error!(ErrorCode::DebtorAcknowledgmentRequired)
+        );
+        state.require_acknowledged(&invoice).unwrap();
+        state.acknowledgment_policy = AcknowledgmentPolicy::Penalized { risk_points: 10 };
+        state.require_acknowledged(&Invoice::default()).unwrap();
+        assert!(!AcknowledgmentPolicy::Penalized { risk_points: 0 }.is_valid(&RiskParams::DEFAULT));
+        assert!(!AcknowledgmentPolicy::Penalized { risk_points: 51 }.is_valid(&RiskParams::DEFAULT));
+    }
+
+    #[test]
+    fn late_fees_stand_still_while_disputed() {
+        let day = 86_400;
+        let due = 1_700_000_000;
+        let mut invoice = funded_invoice(1, 100_000_000, due);
+
+        invoice.accrue_charges(due + 2 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.accrued_late_fee, 100_000);
+        invoice.dispute = Some(Dispute {
+            raised_by: invoice.business_owner,
+            reason_hash: [7; 32],
+            raised_at: due + 2 * day,
+            resolved_at: None,
+            resolution: None,
+        });
+        assert!(invoice.dispute_active());
+
+        // Eight days under dispute charge nothing, before or after it is resolved
+        assert_eq!(invoice.accrue_charges(due + 10 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap().days_overdue, 10);
+        assert_eq!(invoice.accrued_late_fee, 100_000);
+        if let Some(dispute) = invoice.dispute.as_mut() {
+            dispute.resolved_at = Some(due + 10 * day);
+            dispute.resolution = Some(DisputeResolution::Dismissed);
+        }
+        assert!(!invoice.dispute_active());
+        invoice.accrue_charges(due + 12 * day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.accrued_late_fee, 200_000);
+
+        let resolution = DisputeResolution::Adjusted { remaining_balance: 60_000_000 };
+        assert_eq!(resolution.try_to_vec().unwrap().len(), DisputeResolution::SIZE);
+        assert!(invoice.dispute.unwrap().try_to_vec().unwrap().len() <= Dispute::SIZE);
+    }
+
+    #[test]
+    fn principal_alone_does_not_settle_the_yield() {
+        let day = 86_400;
+        let funded_at = 1_700_000_000;
+        let mut invoice = funded_invoice(1, 100_000_000, funded_at + 60 * day);
+        invoice.funding_date = Some(funded_at);
+        invoice.expected_return = Some(106_000_000);
+
+        // Paying at par on the due date goes to the yield first and leaves principal owed
+        invoice.accrue_charges(funded_at + 60 * day, DEFAULT_MIN_INTEREST_BPS, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(
+            repayment_within(0, invoice.outstanding_balance().unwrap(), Some(100_000_000)).unwrap_err(),
+            error!(ErrorCode::SlippageExceeded)
+        );
+        let split = invoice.apply_repayment(100_000_000).unwrap();
+        assert_eq!(split, RepaymentSplit { late_fee: 0, interest: 6_000_000, principal: 94_000_000 });
+        assert_eq!(invoice.outstanding_balance().unwrap(), 6_000_000);
+
+        invoice.apply_repayment(6_000_000).unwrap();
+        assert_eq!((invoice.outstanding_balance().unwrap(), invoice.interest_paid), (0, invoice.yield_component()));
+    }
+
+    #[test]
+    fn unclaimed_repayments_wait_two_years_for_their_holder() {
+        let repaid_at = 1_700_000_000;
+        let invoice = Invoice {
+            status: InvoiceStatus::Repaid,
+            receipt_mint: Some(Pubkey::new_unique()),
+            distributable_amount: 106_000_000,
+            repayment_date: Some(repaid_at),
+            ..Invoice::default()
+        };
+
+        assert!(!invoice.unclaimed_sweep_open(repaid_at + UNCLAIMED_REPAYMENT_SWEEP_SECS - 1));
+        assert!(invoice.unclaimed_sweep_open(repaid_at + UNCLAIMED_REPAYMENT_SWEEP_SECS));
+        // Redeemed, already swept, or owed to share holders who claim their own
+        let redeemed = Invoice { receipt_redeemed: true, ..invoice.clone() };
+        assert!(!redeemed.unclaimed_sweep_open(repaid_at + UNCLAIMED_REPAYMENT_SWEEP_SECS));
+        let swept = Invoice { distributable_amount: 0, ..invoice.clone() };
+        assert!(!swept.unclaimed_sweep_open(repaid_at + UNCLAIMED_REPAYMENT_SWEEP_SECS));
+        let shared = Invoice { partial_funding: true, ..invoice };
+        assert!(!shared.unclaimed_sweep_open(repaid_at + UNCLAIMED_REPAYMENT_SWEEP_SECS));
+    }
+
+    #[test]
+    fn settlements_claw_back_the_payout_before_paying_the_investor() {
+        let defaulted = || Invoice {
+            status: InvoiceStatus::Defaulted,
+            remaining_balance: 100_000_000,
+            insurance_payout: Some(80_000_000),
+            ..Invoice::default()
+        };
+        let now = 1_700_000_000;
+
+        // Less than the payout: all of it back to the pool, still defaulted
+        let mut invoice = defaulted();
+        let split = invoice.apply_settlement(50_000_000, 0, now).unwrap();
+        assert_eq!((split.pool, split.investor), (50_000_000, 0));
+        assert_eq!(invoice.status, InvoiceStatus::Defaulted);
+
+        // Exactly the payout: the pool is whole, the investor's 20 USDC still owed
+        let mut invoice = defaulted();
+        invoice.apply_settlement(80_000_000, 0, now).unwrap();
+        assert_eq!((invoice.pool_recovered, invoice.remaining_balance), (80_000_000, 100_000_000));
+        assert_eq!(invoice.status, InvoiceStatus::Defaulted);
+
+        // More than the payout: the investor gets the uncovered 20, the rest is excess
+        let split = invoice.apply_settlement(30_000_000, 0, now).unwrap();
+        assert_eq!((split.pool, split.investor, split.business), (0, 20_000_000, 10_000_000));
+        assert_eq!(invoice.status, InvoiceStatus::RepaidAfterDefault);
+        assert_eq!((invoice.recovered_amount, invoice.settled_at()), (100_000_000, Some(now)));
+
+        // Never claimed: the investor is owed the whole balance
+        let mut unclaimed = Invoice { insurance_payout: None, ..defaulted() };
+        let split = unclaimed.apply_settlement(100_000_000, 0, now).unwrap();
+        assert_eq!((split.pool, split.investor), (0, 100_000_000));
+        assert_eq!((unclaimed.remaining_balance, unclaimed.status), (0, InvoiceStatus::RepaidAfterDefault));
+
+        // The contractual yield is owed after the uncovered principal, before any excess
+        let mut with_yield = Invoice { funded_amount: 100_000_000, expected_return: Some(104_000_000), ..defaulted() };
+        let split = with_yield.apply_settlement(82_000_000, 0, now).unwrap();
+        assert_eq!((split.pool, split.investor), (80_000_000, 2_000_000));
+        assert_eq!((with_yield.remaining_balance, with_yield.interest_paid), (98_000_000, 0));
+        let split = with_yield.apply_settlement(30_000_000, 0, now).unwrap();
+        assert_eq!((split.investor, split.business), (22_000_000, 8_000_000));
+        assert_eq!((with_yield.remaining_balance, with_yield.interest_paid), (80_000_000, 4_000_000));
+        assert_eq!(with_yield.status, InvoiceStatus::RepaidAfterDefault);
+    }
+
+    #[test]
+    fn portfolio_counters_reconcile_across_mixed_outcomes() {
+        let mut portfolio = InvestorPortfolio::default();
+        let reconciles = |portfolio: &InvestorPortfolio| {
+            portfolio.principal_deployed
+                == portfolio.outstanding
+                    + portfolio.principal_repaid
+                    + portfolio.principal_written_off
+                    + portfolio.principal_sold
+        };
+
+        // #1 repaid in two instalments, the second late
+        portfolio.record_funding(100_000_000, 3_000_000).unwrap();
+        portfolio.record_repayment(&RepaymentSplit { late_fee: 0, interest: 2_000_000, principal: 40_000_000 }).unwrap();
+        assert!(reconciles(&portfolio));
+        assert_eq!(portfolio.outstanding, 60_000_000);
+        portfolio.record_repayment(&RepaymentSplit { late_fee: 500_000, interest: 0, principal: 60_000_000 }).unwrap();
+
+        // #2 defaults with 30 repaid, insurance pays 56, the debtor later pays 100
+        // of which the pool claws back its 56 and the investor gets the uncovered 14
+        let mut defaulted = Invoice {
+            status: InvoiceStatus::Defaulted,
+            remaining_balance: 70_000_000,
+            insurance_payout: Some(56_000_000),
+            ..Invoice::default()
+        };
+        portfolio.record_funding(100_000_000, 4_000_000).unwrap();
+        portfolio.record_repayment(&RepaymentSplit { late_fee: 0, interest: 0, principal: 30_000_000 }).unwrap();
+        portfolio.record_write_off(defaulted.remaining_balance).unwrap();
+        portfolio.record_recovery(56_000_000).unwrap();
+        assert!(reconciles(&portfolio));
+        assert_eq!(portfolio.realized_losses, 14_000_000);
+        let split = defaulted.apply_settlement(100_000_000, 0, 0).unwrap();
+        assert_eq!((split.pool, split.investor), (56_000_000, 14_000_000));
+        portfolio.record_recovery(split.investor).unwrap();
+
+        // #3 sold below what is still owed on it, #4 still open
+        portfolio.record_funding(50_000_000, 1_000_000).unwrap();
+        portfolio.record_sale(50_000_000, 48_000_000).unwrap();
+        portfolio.record_funding(20_000_000, 500_000).unwrap();
+
+        assert!(reconciles(&portfolio));
+        let statement = portfolio.statement();
+        assert_eq!(statement.invoices_funded, 4);
+        assert_eq!(statement.principal_deployed, 270_000_000);
+        assert_eq!(statement.premiums_paid, 8_500_000);
+        assert_eq!(statement.outstanding, 20_000_000);
+        assert_eq!(statement.principal_repaid, 130_000_000);
+        assert_eq!((statement.principal_written_off, statement.recovered), (70_000_000, 70_000_000));
+        assert_eq!(statement.principal_sold, 50_000_000);
+        assert_eq!(statement.realized_yield, 2_500_000);
+        // Only the sale lost money once the clawback made the investor whole
+        assert_eq!(statement.realized_losses, 2_000_000);
+        assert_eq!(statement.net_realized, 2_500_000 - 2_000_000 - 8_500_000);
+    }
+
+    #[test]
+    fn a_prepaid_premium_is_neither_charged_again_nor_repriced() {
+        let mut invoice = Invoice {
+            amount: 100_000_000,
+            due_date: 1_702_000_000,
+            created_at: 1_700_000_000,
+            risk_score: 60,
+            acknowledgment_penalty: 10,
+            insurance_premium: 3_000_000,
+            coverage_percentage: 80,
+            premium_prepaid: true,
+            ..Invoice::default()
+        };
+        assert_eq!(invoice.investor_premium(), 0);
+
+        invoice.record_acknowledgment(CoverageTiers::DEFAULT, 0, true, 1_700_100_000).unwrap();
+        assert_eq!(invoice.risk_score, 50);
+        assert_eq!((invoice.insurance_premium, invoice.coverage_percentage), (3_000_000, 80));
+
+        invoice.premium_prepaid = false;
+        assert_eq!(invoice.investor_premium(), 3_000_000);
+    }
+
+    #[test]
+    fn mandates_match_on_risk_amount_and_term() {
+        let now = 1_700_000_000;
+        let day = 86_400;
+        let rules = MandateRules {
+            max_risk_score: 40,
+            min_amount: 10_000_000,
+            max_amount: 50_000_000,
+            max_term_days: 30,
+            exposure_cap: 200_000_000,
+        };
+        let invoice = Invoice {
+            status: InvoiceStatus::PendingFunding,
+            amount: 25_000_000,
+            risk_score: 40,
+            due_date: now + 30 * day,
+            ..Invoice::default()
+        };
+        assert!(rules.is_valid());
+        assert!(rules.matches(&invoice, now));
+
+        // A part day counts as a whole one
+        assert!(!rules.matches(&Invoice { due_date: now + 30 * day + 1, ..invoice.clone() }, now));
+        assert!(!rules.matches(&Invoice { due_date: now, ..invoice.clone() }, now));
+        assert!(!rules.matches(&Invoice { risk_score: 41, ..invoice.clone() }, now));
+        assert!(rules.matches(&Invoice { amount: 10_000_000, ..invoice.clone() }, now));
+        assert!(!rules.matches(&Invoice { amount: 50_000_001, ..invoice.clone() }, now));
+        assert!(!rules.matches(&Invoice { offramp_requested: true, ..invoice.clone() }, now));
+
+        assert!(!MandateRules { min_amount: 60_000_000, ..rules }.is_valid());
+        assert!(!MandateRules { exposure_cap: 0, ..rules }.is_valid());
+    }
+
+    #[test]
+    fn only_repaid_invoices_can_close() {
+        let now = 1_700_000_000;
+        let repaid = Invoice {
+            status: InvoiceStatus::Repaid,
+            repayment_date: Some(now),
+            ..funded_invoice(1, 50_000_000, now - 86_400)
+        };
+        assert_eq!(repaid.closable_at(), Some(now));
+        let recovered = Invoice { status: InvoiceStatus::RepaidAfterDefault, ..repaid.clone() };
+        assert_eq!(recovered.closable_at(), Some(now));
+        for status in [
+            InvoiceStatus::PendingFunding,
+            InvoiceStatus::Funded,
+            InvoiceStatus::FundedPendingAcceptance,
+            InvoiceStatus::PartiallyRepaid,
+            InvoiceStatus::Defaulted,
+            InvoiceStatus::Disputed,
+            InvoiceStatus::Expired,
+        ] {
+            assert_eq!(Invoice { status, ..repaid.clone() }.closable_at(), None);
+        }
+    }
+
+    #[test]
+    fn status_transitions_follow_the_lifecycle() {
+        use InvoiceStatus::*;
+        const ALL: [InvoiceStatus; 11] = [
+            PendingFunding,
+            Funded,
+            Repaid,
+            Defaulted,
+            PartiallyRepaid,
+            Delisted,
+            FundedPendingAcceptance,
+            RepaidAfterDefault,
+            Cancelled,
+            Expired,
+            Disputed,
+        ];
+        // A new status fails to compile here until it is added to ALL and the table
+        for status in ALL {
+            match status {
+                PendingFunding | Funded | Repaid | Defaulted | PartiallyRepaid | Delisted | FundedPendingAcceptance
+                | RepaidAfterDefault | Cancelled | Expired | Disputed => {}
+            }
+        }
+        let allowed: &[(InvoiceStatus, &[InvoiceStatus])] = &[
+            (PendingFunding, &[Funded, FundedPendingAcceptance, Delisted, Expired, Cancelled]),
+            (FundedPendingAcceptance, &[Funded, PendingFunding]),
+            (Funded, &[PartiallyRepaid, Repaid, Defaulted, Disputed]),
+            (PartiallyRepaid, &[PartiallyRepaid, Repaid, Defaulted, Disputed]),
+            (Disputed, &[Funded, PartiallyRepaid, Repaid, Defaulted]),
+            (Defaulted, &[RepaidAfterDefault]),
+            (Delisted, &[Cancelled]),
+            (Expired, &[Cancelled]),
+            (Repaid, &[]),
+            (RepaidAfterDefault, &[]),
+            (Cancelled, &[]),
+        ];
+        assert_eq!(allowed.len(), ALL.len());
+
+        for from in ALL {
+            let (_, targets) = allowed.iter().find(|(status, _)| *status == from).unwrap();
+            for to in ALL {
+                let mut invoice = Invoice { status: from, ..Invoice::default() };
+                let legal = targets.contains(&to);
+                assert_eq!(invoice.transition(to).is_ok(), legal, "{:?} -> {:?}", from, to);
+                assert_eq!(invoice.status, if legal { to } else { from }, "{:?} -> {:?}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn funding_closes_at_the_deadline() {
+        let now = 1_700_000_000;
+        let day = 86_400;
+        // A day before the due date, at most 30 days out
+        assert_eq!(Invoice::default_funding_deadline(now, now + 10 * day), now + 9 * day);
+        assert_eq!(Invoice::default_funding_deadline(now, now + 90 * day), now + 30 * day);
+        // Due within the day: fundable until due
+        assert_eq!(Invoice::default_funding_deadline(now, now + 60), now + 60);
+
+        let invoice = Invoice { funding_deadline: now + 5 * day, ..funded_invoice(1, 50_000_000, now + 10 * day) };
+        assert!(invoice.funding_open(now + 5 * day));
+        assert!(!invoice.funding_open(now + 5 * day + 1));
+        // Listed before deadlines: open until the due date
+        let legacy = Invoice { funding_deadline: 0, ..invoice.clone() };
+        assert!(legacy.funding_open(now + 10 * day));
+        assert!(!legacy.funding_open(now + 10 * day + 1));
+    }
+
+    #[test]
+    fn underfunded_pool_pays_claims_pro_rata() {
+        // Covered in full while the pool can meet every claim
+        assert_eq!(insurance_payout_share(80, 500, 300), 80);
+        // Two claims of 80 and 120 on a pool of 100 get half each
+        assert_eq!(insurance_payout_share(80, 100, 200), 40);
+        assert_eq!(insurance_payout_share(120, 60, 120), 60);
+        // A claim the pool never counted is still limited to what it holds
+        assert_eq!(insurance_payout_share(80, 50, 0), 50);
+        assert_eq!(insurance_payout_share(0, 0, 0), 0);
+
+        let mut global_state = GlobalState::default();
+        let mut invoice = Invoice { status: InvoiceStatus::Defaulted, ..funded_invoice(1, 1_000_000, 0) };
+        invoice.coverage_percentage = 80;
+        sync_insured_exposure(&mut invoice, &mut global_state).unwrap();
+        assert_eq!(global_state.claims_outstanding, 800_000);
+
+        // A partial payout leaves the shortfall committed and claimable
+        invoice.insurance_payout = Some(300_000);
+        invoice.insurance_payout_outstanding = 500_000;
+        sync_insured_exposure(&mut invoice, &mut global_state).unwrap();
+        assert!(invoice.insurance_claimable());
+        assert_eq!(global_state.insured_exposure, 500_000);
+        assert_eq!(global_state.claims_outstanding, 500_000);
+
+        // Recovered principal the payout left unpaid caps what is still owed
+        let split = invoice.apply_settlement(400_000, 0, 1).unwrap();
+        assert_eq!((split.pool, split.investor), (300_000, 100_000));
+        assert_eq!(invoice.insurance_payout_outstanding, 500_000);
+        let split = invoice.apply_settlement(300_000, 0, 1).unwrap();
+        assert_eq!(split.investor, 300_000);
+        assert_eq!(invoice.insurance_payout_outstanding, 300_000);
+
+        invoice.insurance_payout = Some(600_000);
+        invoice.insurance_payout_outstanding = 0;
+        sync_insured_exposure(&mut invoice, &mut global_state).unwrap();
+        assert!(!invoice.insurance_claimable());
+        assert_eq!(global_state.insured_exposure, 0);
+        assert_eq!(global_state.claims_outstanding, 0);
+    }
+
+    #[test]
+    fn coverage_capacity_caps_insured_exposure() {
+        let mut state = GlobalState { insurance_pool_balance: 1_000_000, insured_exposure: 3_000_000, ..GlobalState::default() };
+        assert_eq!(state.coverage_utilization_bps(), 30_000);
+        // No cap until one is set
+        assert!(state.require_coverage_capacity().is_ok());
+
+        state.coverage_capacity_bps = 30_000;
+        assert!(state.require_coverage_capacity().is_ok());
+        state.insured_exposure += 1;
+        assert!(state.require_coverage_capacity().is_err());
+
+        // An empty pool can insure nothing
+        let empty = GlobalState { insured_exposure: 1, coverage_capacity_bps: 30_000, ..GlobalState::default() };
+        assert_eq!(empty.coverage_utilization_bps(), u64::MAX);
+        assert!(empty.require_coverage_capacity().is_err());
+        assert_eq!(GlobalState::default().coverage_utilization_bps(), 0);
+    }
+
+    #[test]
+    fn originator_fee_comes_from_the_configured_side() {
+        let mut invoice = Invoice { amount: 1_000_000, insurance_premium: 20_000, originator_fee_bps: 150, ..Invoice::default() };
+        // No originator, no fee, whatever the bps say
+        assert_eq!(invoice.originator_fee(), 0);
+        assert_eq!(invoice.business_proceeds(), 1_000_000);
+
+        invoice.originator = Some(Pubkey::new_unique());
+        assert_eq!(invoice.originator_fee(), 15_000);
+        assert_eq!(invoice.investor_originator_fee(), 15_000);
+        assert_eq!(invoice.business_proceeds(), 1_000_000);
+
+        invoice.originator_fee_from_principal = true;
+        assert_eq!(invoice.investor_originator_fee(), 0);
+        assert_eq!(invoice.business_proceeds(), 985_000);
+
+        let mut stats = OriginatorStats::default();
+        stats.record_funding(invoice.amount, invoice.originator_fee(), 42).unwrap();
+        stats.record_funding(500_000, 0, 43).unwrap();
+        assert_eq!((stats.invoices_funded, stats.volume_funded, stats.fees_earned), (2, 1_500_000, 15_000));
+        assert_eq!(stats.last_funded_at, Some(43));
+    }
+
+    #[test]
+    fn legacy_invoices_migrate_to_a_debtor_info_hash() {
+        // Unversioned bytes for an invoice: its current image with the version, hash
+        // and URI swapped for the free-text debtor_info they replaced, which status
+        // followed
+        fn legacy_image(invoice: &Invoice, debtor_info: &str) -> Vec<u8> {
+            let mut current = Vec::new();
+            invoice.try_serialize(&mut current).unwrap();
+            let at = INVOICE_VERSION_OFFSET;
+            let mut legacy = current[..at].to_vec();
+            legacy.extend_from_slice(&(debtor_info.len() as u32).to_le_bytes());
+            legacy.extend_from_slice(debtor_info.as_bytes());
+            legacy.push(current[INVOICE_STATUS_OFFSET]);
+            legacy.extend_from_slice(&current[INVOICE_STATUS_OFFSET + 1 + 32 + 1..]);
+            legacy
+        }
+        let serialized = |invoice: &Invoice| {
+            let mut bytes = Vec::new();
+            invoice.try_serialize(&mut bytes).unwrap();
+            bytes
+        };
+
+        let debtor_info = "Acme Corp, net 45 invoice #42";
+        let business_owner = Pubkey::new_unique();
+        let invoice = Invoice {
+            invoice_id: 7,
+            business_owner,
+            // Legacy invoices were paid for by their business
+            rent_payer: business_owner,
+            amount: 1_000_000,
+            due_date: 1_700_000_000,
+            debtor_info_hash: anchor_lang::solana_program::hash::hash(debtor_info.as_bytes()).to_bytes(),
+            status: InvoiceStatus::Funded,
+            funding_deadline: 1_699_000_000,
+            version: INVOICE_VERSION,
+            ..Invoice::default()
+        };
+        let (from_version, migrated) = migrate_invoice_data(&legacy_image(&invoice, debtor_info)).unwrap().unwrap();
+        assert_eq!(from_version, 0);
+        assert_eq!(serialized(&migrated), serialized(&invoice));
+        assert!(migrated.debtor_info_matches(debtor_info.as_bytes()));
+        assert!(!migrated.debtor_info_matches(b"Acme Corp, net 30"));
+
+        // Only once: a current account has its version where the length was
+        assert!(migrate_invoice_data(&serialized(&migrated)).unwrap().is_none());
+        assert_eq!(
+            migrate_legacy_invoice(&serialized(&migrated)).err(),
+            Some(error!(ErrorCode::InvoiceAlreadyMigrated))
+        );
+
+        // An erased invoice keeps the hash taken of its text before erasure
+        let erased = Invoice { erased: true, erased_content_hash: [9u8; 32], ..invoice };
+        let migrated = migrate_legacy_invoice(&legacy_image(&erased, "[erased]")).unwrap();
+        assert_eq!(migrated.debtor_info_hash, [9u8; 32]);
+        assert_eq!(migrated.debtor_info_uri, None);
+    }
+
+    #[test]
+    fn invoice_filter_fields_sit_at_the_documented_offsets() {
+        let mut data = Vec::new();
+        Invoice { version: INVOICE_VERSION, ..Invoice::default() }.try_serialize(&mut data).unwrap();
+        let business_owner = Pubkey::new_unique();
+        let investor = Pubkey::new_unique();
+        data[INVOICE_BUSINESS_OWNER_OFFSET..INVOICE_BUSINESS_OWNER_OFFSET + 32].copy_from_slice(business_owner.as_ref());
+        data[INVOICE_INVESTOR_OFFSET..INVOICE_INVESTOR_OFFSET + 32].copy_from_slice(investor.as_ref());
+        data[INVOICE_DUE_DATE_OFFSET..INVOICE_DUE_DATE_OFFSET + 8].copy_from_slice(&1_702_000_000i64.to_le_bytes());
+        data[INVOICE_STATUS_OFFSET] = InvoiceStatus::FundedPendingAcceptance as u8;
+        // A URI of any length leaves them where they are
+        data[INVOICE_STATUS_OFFSET + 1 + 32] = 1;
+        data.splice(INVOICE_STATUS_OFFSET + 1 + 32 + 1..INVOICE_STATUS_OFFSET + 1 + 32 + 1, [3, 0, 0, 0, b'a', b'r', b':']);
+
+        let invoice = Invoice::try_deserialize(&mut &data[..]).unwrap();
+        assert_eq!(invoice.version, INVOICE_VERSION);
+        assert_eq!(invoice.business_owner, business_owner);
+        assert_eq!(invoice.investor, investor);
+        assert_eq!(invoice.due_date, 1_702_000_000);
+        assert_eq!(invoice.status, InvoiceStatus::FundedPendingAcceptance);
+        assert_eq!(invoice.debtor_info_uri.as_deref(), Some("ar:"));
+
+        // Clients filter on the discriminants, so they never move
+        assert_eq!(InvoiceStatus::PendingFunding as u8, 0);
+        assert_eq!(InvoiceStatus::Funded as u8, 1);
+        assert_eq!(InvoiceStatus::Repaid as u8, 2);
+        assert_eq!(InvoiceStatus::Defaulted as u8, 3);
+        assert_eq!(InvoiceStatus::Disputed as u8, 10);
+    }
+
+    #[test]
+    fn accounts_migrate_up_to_the_current_version() {
+        let invoice = Invoice { invoice_id: 3, amount: 500, version: INVOICE_VERSION, ..Invoice::default() };
+        let mut data = Vec::new();
+        invoice.try_serialize(&mut data).unwrap();
+        assert!(migrate_invoice_data(&data).unwrap().is_none());
+
+        // No version 0; from LEGACY_MIN_DEBTOR_INFO_LEN up the byte is an
+        // unversioned invoice's text length
+        data[INVOICE_VERSION_OFFSET] = 0;
+        assert_eq!(migrate_invoice_data(&data).err(), Some(error!(ErrorCode::AccountVersionMismatch)));
+
+        // Versions 1 and 2 kept status after debtor_info_uri
+        fn pre_v3_image(invoice: &Invoice, version: u8) -> Vec<u8> {
+            let mut current = Vec::new();
+            invoice.try_serialize(&mut current).unwrap();
+            let uri_len = invoice.debtor_info_uri.as_ref().map_or(0, |uri| 4 + uri.len());
+            let uri_end = INVOICE_STATUS_OFFSET + 1 + 32 + 1 + uri_len;
+            let mut image = current[..INVOICE_STATUS_OFFSET].to_vec();
+            image.extend_from_slice(&current[INVOICE_STATUS_OFFSET + 1..uri_end]);
+            image.push(current[INVOICE_STATUS_OFFSET]);
+            image.extend_from_slice(&current[uri_end..]);
+            image[INVOICE_VERSION_OFFSET] = version;
+            // and every version before 4 ended before apr_bps and what followed it
+            image.truncate(image.len() - 2 - 32 - 9 - 2 - 2 - 8);
+            image
+        }
+        let business_owner = Pubkey::new_unique();
+        let listed = Invoice {
+            status: InvoiceStatus::Disputed,
+            debtor_info_uri: Some("ar://invoice-3".to_string()),
+            risk_score: 17,
+            business_owner,
+            rent_payer: business_owner,
+            ..invoice.clone()
+        };
+        let (from_version, migrated) = migrate_invoice_data(&pre_v3_image(&listed, 2)).unwrap().unwrap();
+        assert_eq!(from_version, 2);
+        let (mut expected, mut actual) = (Vec::new(), Vec::new());
+        listed.try_serialize(&mut expected).unwrap();
+        migrated.try_serialize(&mut actual).unwrap();
+        assert_eq!(actual, expected);
+
+        // Version 3 already had status up front and reads apr_bps as 0
+        let mut v3 = Vec::new();
+        Invoice { apr_bps: 1_100, ..listed.clone() }.try_serialize(&mut v3).unwrap();
+        v3.truncate(v3.len() - 2 - 32 - 9 - 2 - 2 - 8);
+        v3[INVOICE_VERSION_OFFSET] = 3;
+        let (from_version, migrated) = migrate_invoice_data(&v3).unwrap().unwrap();
+        assert_eq!(from_version, 3);
+        let mut actual = Vec::new();
+        migrated.try_serialize(&mut actual).unwrap();
+        assert_eq!(actual, expected);
 
-#[derive(Accounts)]
-#[instruction(invoice_id: u64)]
-pub struct CreateInvoice<'info> {
-    #[account(
-        init,
-        payer = business_owner,
-        space = Invoice::SIZE,
-        seeds = [b"invoice", invoice_id.to_le_bytes().as_ref()],
-        bump
-    )]
-    pub invoice: Account<'info, Invoice>,
-    
-    #[account(mut)]
-    pub global_state: Account<'info, GlobalState>,
-    
-    #[account(mut)]
-    pub business_owner: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+        // Version 4 ended before rent_payer, which goes to the business owner
+        let mut v4 = Vec::new();
+        Invoice { rent_payer: Pubkey::new_unique(), ..listed.clone() }.try_serialize(&mut v4).unwrap();
+        v4.truncate(v4.len() - 32 - 9 - 2 - 2 - 8);
+        v4[INVOICE_VERSION_OFFSET] = 4;
+        let (from_version, migrated) = migrate_invoice_data(&v4).unwrap().unwrap();
+        assert_eq!((from_version, migrated.rent_payer), (4, business_owner));
+        let mut actual = Vec::new();
+        migrated.try_serialize(&mut actual).unwrap();
+        assert_eq!(actual, expected);
 
-#[derive(Accounts)]
-pub struct FundInvoice<'info> {
-    #[account(mut)]
-    pub invoice: Account<'info, Invoice>,
-    
-    #[account(mut)]
-    pub global_state: Account<'info, GlobalState>,
-    
-    #[account(mut)]
-    pub investor: Signer<'info>,
-    
-    #[account(
-        mut,
-        associated_token::mint = global_state.usdc_mint,
-        associated_token::authority = investor,
-    )]
-    pub investor_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        mut,
-        associated_token::mint = global_state.usdc_mint,
-        associated_token::authority = invoice.business_owner,
-    )]
-    pub business_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        mut,
-        seeds = [b"insurance_pool"],
-        bump = global_state.bump,
-    )]
-    pub insurance_pool_account: Account<'info, TokenAccount>,
-    
-    pub token_program: Program<'info, Token>,
-}
+        // Version 5 ended before last_overdue_ping, which reads as never pinged,
+        // and keeps a sponsor as the rent payer
+        let sponsor = Pubkey::new_unique();
+        let mut v5 = Vec::new();
+        Invoice { last_overdue_ping: Some(1_700_000_000), rent_payer: sponsor, ..listed.clone() }
+            .try_serialize(&mut v5)
+            .unwrap();
+        v5.truncate(v5.len() - 9 - 2 - 2 - 8);
+        v5[INVOICE_VERSION_OFFSET] = 5;
+        let (from_version, migrated) = migrate_invoice_data(&v5).unwrap().unwrap();
+        assert_eq!((from_version, migrated.last_overdue_ping, migrated.rent_payer), (5, None, sponsor));
 
-#[derive(Accounts)]
-pub struct RepayInvoice<'info> {
-    #[account(mut)]
-    pub invoice: Account<'info, Invoice>,
-    
-    #[account(mut)]
-    pub business_owner: Signer<'info>,
-    
-    #[account(
-        mut,
-        associated_token::mint = invoice.business_owner, // This should be usdc_mint - fix in integration
-        associated_token::authority = business_owner,
-    )]
-    pub business_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        mut,
-        associated_token::mint = invoice.investor, // This should be usdc_mint - fix in integration
-        associated_token::authority = invoice.investor,
-    )]
-    pub investor_token_account: Account<'info, TokenAccount>,
-    
-    pub token_program: Program<'info, Token>,
-}
+        // and version 6 before premium_bps, which reads as unrecorded
+        let mut v6 = Vec::new();
+        Invoice { premium_bps: 170, ..listed.clone() }.try_serialize(&mut v6).unwrap();
+        v6.truncate(v6.len() - 2 - 2 - 8);
+        v6[INVOICE_VERSION_OFFSET] = 6;
+        let (from_version, migrated) = migrate_invoice_data(&v6).unwrap().unwrap();
+        assert_eq!((from_version, migrated.premium_bps), (6, 0));
+        let mut actual = Vec::new();
+        migrated.try_serialize(&mut actual).unwrap();
+        assert_eq!(actual, expected);
 
-#[derive(Accounts)]
-pub struct ClaimInsurance<'info> {
-    #[account(mut)]
-    pub invoice: Account<'info, Invoice>,
-    
-    #[account(mut)]
-    pub global_state: Account<'info, GlobalState>,
-    
-    pub investor: Signer<'info>,
-    
-    #[account(
-        mut,
-        associated_token::mint = global_state.usdc_mint,
-        associated_token::authority = investor,
-    )]
-    pub investor_token_account: Account<'info, TokenAccount>,
-    
-    #[account(
-        mut,
-        seeds = [b"insurance_pool"],
-        bump = global_state.bump,
-    )]
-    pub insurance_pool_account: Account<'info, TokenAccount>,
-    
-    /// CHECK: This is the insurance pool authority PDA
-    #[account(
-        seeds = [b"insurance_pool"],
-        bump = global_state.bump,
-    )]
-    pub insurance_pool_authority: AccountInfo<'info>,
-    
-    pub token_program: Program<'info, Token>,
-}
+        // Version 7 ended before the advance, so reads as funded at par
+        let mut v7 = Vec::new();
+        Invoice { advance_rate_bps: 9_000, advance_amount: 450, ..listed.clone() }.try_serialize(&mut v7).unwrap();
+        v7.truncate(v7.len() - 2 - 8);
+        v7[INVOICE_VERSION_OFFSET] = 7;
+        let (from_version, migrated) = migrate_invoice_data(&v7).unwrap().unwrap();
+        assert_eq!((from_version, migrated.funding_mode()), (7, FundingMode::Par));
+        let mut actual = Vec::new();
+        migrated.try_serialize(&mut actual).unwrap();
+        assert_eq!(actual, expected);
 
-#[derive(Accounts)]
-pub struct GetInvoiceDetails<'info> {
-    pub invoice: Account<'info, Invoice>,
-}
+        // Versions skip the range an unversioned invoice's text length falls in
+        for byte in [LEGACY_MIN_DEBTOR_INFO_LEN as u8, LEGACY_MAX_DEBTOR_INFO_LEN as u8] {
+            assert!(is_legacy_length_byte(byte));
+        }
+        assert!(!is_legacy_length_byte(7) && !is_legacy_length_byte(INVOICE_VERSION));
 
-// Enhanced data structures
-#[account]
-pub struct GlobalState {
-    pub total_invoices: u64,
-    pub total_funded: u64,
-    pub insurance_pool_balance: u64,
-    pub authority: Pubkey,
-    pub usdc_mint: Pubkey,
-    pub bump: u8,
-}
+        // Version 1 ended before portfolio_booked, which reads as unbooked
+        let booked = Invoice { portfolio_booked: true, ..listed.clone() };
+        let mut v1 = pre_v3_image(&booked, 1);
+        v1.truncate(v1.len() - 1);
+        let (from_version, migrated) = migrate_invoice_data(&v1).unwrap().unwrap();
+        assert_eq!((from_version, migrated.version), (1, INVOICE_VERSION));
+        assert_eq!((migrated.invoice_id, migrated.amount), (3, 500));
+        assert_eq!(migrated.status, InvoiceStatus::Disputed);
+        assert!(!migrated.portfolio_booked);
 
-impl GlobalState {
-    pub const SIZE: usize = 8 + 8 + 8 + 8 + 32 + 32 + 1;
-}
+        // The unversioned global state ended where version begins, version 1 right
+        // after it, before the protocol totals, version 2 before the approver set
+        // version 3 before the minimum premium, version 4 before the open invoice
+        // cap, version 5 before the pricing experiment and version 6 before the
+        // escrow compensation
+        let global_state = GlobalState {
+            authority: Pubkey::new_unique(),
+            version: GLOBAL_STATE_VERSION,
+            total_repaid: 7,
+            total_premiums: 9_000,
+            min_insurance_premium: 250_000,
+            max_open_invoices_per_business: 20,
+            pricing_experiment: Some(Pubkey::new_unique()),
+            pricing_experiment_starts_at: 1_700_000_000,
+            pricing_experiment_ends_at: 1_702_592_000,
+            escrow_compensation_bps_per_day: 3,
+            ..GlobalState::default()
+        };
+        let mut current = Vec::new();
+        global_state.try_serialize(&mut current).unwrap();
+        let v6_len = current.len() - 2;
+        let v5_len = v6_len - (1 + 32 + 8 + 8);
+        let v4_len = v5_len - 4;
+        let v3_len = v4_len - 8;
+        let v2_len = v3_len - (4 + 1 + 8 + 4 + 8);
+        let version_at = v2_len - 5 * 8 - 1;
+        let (from_version, migrated) = migrate_global_state_data(&current[..version_at]).unwrap().unwrap();
+        assert_eq!(from_version, 0);
+        assert_eq!(migrated.version, GLOBAL_STATE_VERSION);
+        assert_eq!(migrated.authority, global_state.authority);
+        assert!(migrate_global_state_data(&current).unwrap().is_none());
 
-#[account]
-pub struct Invoice {
-    pub invoice_id: u64,
-    pub business_owner: Pubkey,
-    pub investor: Pubkey,
-    pub amount: u64,
-    pub funded_amount: u64,
-    pub due_date: i64,
-    pub debtor_info: String,
-    pub status: InvoiceStatus,
-    pub risk_score: u8,
-    pub insurance_premium: u64,
-    pub created_at: i64,
-    pub funding_date: Option<i64>,
-    pub repayment_date: Option<i64>,
-    pub expected_return: Option<u64>,
-    pub final_repayment_amount: Option<u64>,
-    pub late_fee: Option<u64>,
-    pub insurance_claim_date: Option<i64>,
-    pub insurance_payout: Option<u64>,
-    pub bump: u8,
-    
-    // Enhanced risk factors
-    pub industry_risk: u8,
-    pub credit_score: u16,
-    pub payment_terms_days: u16,
-}
+        let mut v1 = current[..=version_at].to_vec();
+        v1[version_at] = 1;
+        let (from_version, migrated) = migrate_global_state_data(&v1).unwrap().unwrap();
+        assert_eq!(from_version, 1);
+        assert_eq!(migrated.version, GLOBAL_STATE_VERSION);
+        assert_eq!((migrated.total_repaid, migrated.total_premiums), (0, 0));
 
-impl Invoice {
-    pub const SIZE: usize = 8 + 8 + 32 + 32 + 8 + 8 + 8 + (4 + 200) + 1 + 1 + 8 + 8 + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + (1 + 8) + 1 + 1 + 2 + 2; // ~450 bytes
-}
+        let mut v2 = current[..v2_len].to_vec();
+        v2[version_at] = 2;
+        let (from_version, migrated) = migrate_global_state_data(&v2).unwrap().unwrap();
+        assert_eq!(from_version, 2);
+        assert_eq!((migrated.total_repaid, migrated.total_premiums), (7, 9_000));
+        assert!(migrated.admin_approvers.is_empty() && !migrated.approvers_configured());
+        assert_eq!(migrated.admin_proposal_count, 0);
+        assert_eq!(migrated.min_insurance_premium, DEFAULT_MIN_INSURANCE_PREMIUM);
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
-pub enum InvoiceStatus {
-    PendingFunding,
-    Funded,
-    Repaid,
-    Defaulted,
-}
+        let mut v3 = current[..v3_len].to_vec();
+        v3[version_at] = 3;
+        let (from_version, migrated) = migrate_global_state_data(&v3).unwrap().unwrap();
+        assert_eq!((from_version, migrated.total_premiums), (3, 9_000));
+        assert_eq!(migrated.min_insurance_premium, DEFAULT_MIN_INSURANCE_PREMIUM);
 
-// Return types
-#[derive(AnchorSerialize, AnchorDeserialize)]
-pub struct InvoiceDetails {
-    pub invoice_id: u64,
-    pub business_owner: Pubkey,
-    pub investor: Pubkey,
-    pub amount: u64,
-    pub funded_amount: u64,
-    pub due_date: i64,
-    pub status: InvoiceStatus,
-    pub risk_score: u8,
-    pub insurance_premium: u64,
-    pub created_at: i64,
-    pub funding_date: Option<i64>,
-    pub repayment_date: Option<i64>,
-    pub expected_return: Option<u64>,
-}
+        // A version 4 state keeps its minimum premium and starts uncapped
+        let mut v4 = current[..v4_len].to_vec();
+        v4[version_at] = 4;
+        let (from_version, migrated) = migrate_global_state_data(&v4).unwrap().unwrap();
+        assert_eq!((from_version, migrated.min_insurance_premium), (4, 250_000));
+        assert_eq!(migrated.max_open_invoices_per_business, 0);
+        let busy = BusinessProfile { open_invoices: 500, ..BusinessProfile::default() };
+        assert!(!migrated.open_invoice_limit_reached(Some(&busy)));
 
-#[derive(AnchorSerialize, AnchorDeserialize)]
-pub struct RiskAssessment {
-    pub risk_score: u8,
-    pub industry_risk: u8,
-    pub estimated_credit_score: u16,
-    pub estimated_yield: u16, // Basis points
-}
+        // A version 5 state keeps its cap and has no experiment in force
+        let mut v5 = current[..v5_len].to_vec();
+        v5[version_at] = 5;
+        let (from_version, migrated) = migrate_global_state_data(&v5).unwrap().unwrap();
+        assert_eq!((from_version, migrated.max_open_invoices_per_business), (5, 20));
+        assert_eq!(migrated.pricing_experiment, None);
+        assert_eq!(migrated.experiment_in_force(1_701_000_000), None);
 
-// Enhanced events
-#[event]
-pub struct InvoiceCreated {
-    pub invoice_id: u64,
-    pub business_owner: Pubkey,
-    pub amount: u64,
-    pub risk_score: u8,
-    pub insurance_premium: u64,
-    pub estimated_yield: u16,
-}
+        // A version 6 state keeps its experiment and pays no escrow compensation
+        let mut v6 = current[..v6_len].to_vec();
+        v6[version_at] = 6;
+        let (from_version, migrated) = migrate_global_state_data(&v6).unwrap().unwrap();
+        assert_eq!((from_version, migrated.pricing_experiment), (6, global_state.pricing_experiment));
+        assert_eq!(migrated.escrow_compensation_bps_per_day, 0);
 
-#[event]
-pub struct InvoiceFunded {
-    pub invoice_id: u64,
-    pub investor: Pubkey,
-    pub amount: u64,
-    pub insurance_premium: u64,
-    pub expected_return: u64,
-}
+        let mut ahead = current.clone();
+        ahead[version_at] = GLOBAL_STATE_VERSION + 1;
+        assert_eq!(migrate_global_state_data(&ahead).err(), Some(error!(ErrorCode::AccountVersionMismatch)));
+    }
 
-#[event]
-pub struct InvoiceRepaid {
-    pub invoice_id: u64,
-    pub amount: u64,
-    pub late_fee: u64,
-    pub days_overdue: u16,
-}
+    #[test]
+    fn an_amendment_is_quoted_like_a_fresh_listing() {
+        let now = 1_700_000_000;
+        let global_state = GlobalState {
+            risk_params: RiskParams::DEFAULT,
+            acknowledgment_policy: AcknowledgmentPolicy::Penalized { risk_points: 5 },
+            version: GLOBAL_STATE_VERSION,
+            ..GlobalState::default()
+        };
+        let business_profile = BusinessProfile::default();
+        let quote = |amount: u64, due_date: i64, micro_tier: Option<&MicroTierConfig>| {
+            let listing = ListingRisk { amount, due_date, industry: IndustryCode::Healthcare, attested_score: None };
+            quote_listing(&listing, amount, &business_profile, &global_state, micro_tier, None, now).unwrap()
+        };
 
-#[event]
-pub struct InsuranceClaimed {
-    pub invoice_id: u64,
-    pub investor: Pubkey,
-    pub payout_amount: u64,
-    pub coverage_percentage: u64,
-}
+        let mut invoice = Invoice { amount: 10_000_000, due_date: now + 30 * 86_400, ..Invoice::default() };
+        invoice.apply_quote(&quote(invoice.amount, invoice.due_date, None), true, now).unwrap();
+        let (listed_score, listed_premium) = (invoice.risk_score, invoice.insurance_premium);
+        assert_eq!(invoice.acknowledgment_penalty, 5);
+        assert_eq!(invoice.payment_terms_days, 30);
 
-// Enhanced error codes
-#[error_code]
-pub enum ErrorCode {
-    #[msg("Invalid amount")]
-    InvalidAmount,
-    #[msg("Amount too large")]
-    AmountTooLarge,
-    #[msg("Invalid due date")]
-    InvalidDueDate,
-    #[msg("Due date too far in future")]
-    DueDateTooFar,
-    #[msg("Debtor info too long")]
-    DebtorInfoTooLong,
-    #[msg("Debtor info too short")]
-    DebtorInfoTooShort,
-    #[msg("Invoice not available for funding")]
-    InvoiceNotAvailable,
-    #[msg("Invalid funding amount - must fund full amount")]
-    InvalidFundingAmount,
-    #[msg("Insufficient funds in investor account")]
-    InsufficientFunds,
-    #[msg("Invoice not funded")]
-    InvoiceNotFunded,
-    #[msg("Insufficient repayment amount")]
-    InsufficientRepayment,
-    #[msg("Insufficient funds for repayment")]
-    InsufficientRepaymentFunds,
-    #[msg("Repayment period has expired")]
-    RepaymentPeriodExpired,
-    #[msg("Not eligible for insurance claim")]
-    NotEligibleForClaim,
-    #[msg("Unauthorized insurance claim")]
-    UnauthorizedInsuranceClaim,
-    #[msg("Insufficient insurance pool funds")]
-    InsufficientInsurancePool,
-}
\ No newline at end of file
+        // A larger amount on the same term scores and costs more
+        invoice.amount = 60_000_000;
+        let amended = quote(invoice.amount, invoice.due_date, None);
+        invoice.apply_quote(&amended, true, now).unwrap();
+        assert!(invoice.risk_score > listed_score);
+        assert!(invoice.insurance_premium > listed_premium);
+        // with no more of the penalty than fits under the maximum score
+        assert_eq!((invoice.risk_score, invoice.acknowledgment_penalty), (50, 3));
+        assert_eq!(invoice.expected_return, Some(amended.pricing.expected_return(60_000_000).unwrap()));
+        // and, with no daily cap set, quotes today's funding as unlimited
+        assert_eq!(amended.remaining_daily_capacity, u64::MAX);
+
+        // Off the primary mint it stays uninsured
+        invoice.apply_quote(&amended, false, now).unwrap();
+        assert_eq!((invoice.insurance_premium, invoice.coverage_percentage), (0, 0));
+
+        // Brought under the micro tier, it takes the tier's flat terms and no penalty
+        let tier = MicroTierConfig { max_amount: 5_000_000, risk_score: 15, premium_bps: 100, bump: 0 };
+        invoice.amount = 2_000_000;
+        invoice.apply_quote(&quote(invoice.amount, invoice.due_date, Some(&tier)), true, now).unwrap();
+        assert!(invoice.micro_tier);
+        assert_eq!((invoice.risk_score, invoice.acknowledgment_penalty), (15, 0));
+    }
+
+    #[test]
+    fn factored_invoices_advance_a_share_and_collect_face_value() {
+        let now = 1_700_000_000;
+        let day = 86_400;
+        let global_state = GlobalState { risk_params: RiskParams::DEFAULT, ..GlobalState::default() };
+        let business_profile = BusinessProfile::default();
+        let listing = ListingRisk { amount: 100_000_000, due_date: now + 60 * day, industry: IndustryCode::Healthcare, attested_score: None };
+
+        let mut invoice = Invoice { amount: 100_000_000, due_date: listing.due_date, ..Invoice::default() };
+        for rate in [MIN_ADVANCE_RATE_BPS - 1, MAX_ADVANCE_RATE_BPS + 1] {
+            assert_eq!(invoice.set_advance_rate(Some(rate)).err(), Some(error!(ErrorCode::InvalidAdvanceRate)));
+        }
+        invoice.set_advance_rate(Some(9_000)).unwrap();
+        assert_eq!((invoice.funding_mode(), invoice.principal()), (FundingMode::Factoring, 90_000_000));
+
+        // Premium on the advance, and the face value as the whole return
+        let quote = quote_listing(&listing, invoice.principal(), &business_profile, &global_state, None, None, now).unwrap();
+        let par = quote_listing(&listing, listing.amount, &business_profile, &global_state, None, None, now).unwrap();
+        invoice.apply_quote(&quote, true, now).unwrap();
+        assert!(invoice.insurance_premium < par.pricing.insurance_premium);
+        assert_eq!((invoice.expected_return, invoice.apr_bps), (Some(100_000_000), 0));
+        assert_eq!(invoice.business_proceeds(), 90_000_000);
+
+        // Funding keeps the discount as the yield, owed in full however early it repays
+        invoice.funded_amount = invoice.principal();
+        invoice.remaining_balance = invoice.principal();
+        invoice.funding_date = Some(now);
+        invoice.pricing_version = pricing::PRICING_VERSION;
+        invoice.fix_term_yield(now).unwrap();
+        assert_eq!(invoice.expected_return, Some(100_000_000));
+        invoice.accrue_charges(now + day, 0, DEFAULT_LATE_FEE_BPS_PER_DAY).unwrap();
+        assert_eq!(invoice.outstanding_balance().unwrap(), 100_000_000);
+        // and its coverage is on the advance
+        invoice.coverage_percentage = 80;
+        assert_eq!(invoice.insured_coverage(), 72_000_000);
+
+        // Back at par, nothing is advanced short of the face value
+        invoice.set_advance_rate(None).unwrap();
+        assert_eq!((invoice.funding_mode(), invoice.principal(), invoice.advance_amount), (FundingMode::Par, 100_000_000, 0));
+    }
+}
@@ -0,0 +1,201 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+
+use crate::{ErrorCode, Invoice};
+
+// Domain tag every listing proof starts with; bump the version with any layout change
+pub const LISTING_PROOF_DOMAIN: &[u8] = b"sureinv:listing-proof:v1";
+
+// Canonical listing terms, integers little-endian, no padding:
+//
+//   offset  len  field
+//        0   24  LISTING_PROOF_DOMAIN
+//       24   32  invoice account
+//       56    8  nonce (i64, the listing's created_at, so a re-listed id never matches)
+//       64    8  amount (u64)
+//       72    8  yield_amount (u64, expected return above face value)
+//       80    1  risk_score (u8)
+//       81    1  coverage_percentage (u8)
+//       82    8  deadline (i64, due date)
+//       90    8  sort_key (u64, yield in bps of face value)
+//
+// A proof is the terms followed by the slot it was read at (u64).
+pub const LISTING_TERMS_LEN: usize = 24 + 32 + 8 + 8 + 8 + 1 + 1 + 8 + 8;
+pub const LISTING_PROOF_LEN: usize = LISTING_TERMS_LEN + 8;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ListingTerms {
+    pub invoice: Pubkey,
+    pub nonce: i64,
+    pub amount: u64,
+    pub yield_amount: u64,
+    pub risk_score: u8,
+    pub coverage_percentage: u8,
+    pub deadline: i64,
+    pub sort_key: u64,
+}
+
+impl ListingTerms {
+    pub fn of(invoice_key: Pubkey, invoice: &Invoice) -> Self {
+        let yield_amount = invoice.expected_return.map_or(0, |expected| expected.saturating_sub(invoice.amount));
+        Self {
+            invoice: invoice_key,
+            nonce: invoice.created_at,
+            amount: invoice.amount,
+            yield_amount,
+            risk_score: invoice.risk_score,
+            coverage_percentage: invoice.coverage_percentage,
+            deadline: invoice.due_date,
+            sort_key: if invoice.amount == 0 { 0 } else { crate::pro_rata(10_000, yield_amount, invoice.amount) },
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; LISTING_TERMS_LEN] {
+        let mut bytes = [0u8; LISTING_TERMS_LEN];
+        let mut at = 0;
+        let mut put = |field: &[u8]| {
+            bytes[at..at + field.len()].copy_from_slice(field);
+            at += field.len();
+        };
+        put(LISTING_PROOF_DOMAIN);
+        put(self.invoice.as_ref());
+        put(&self.nonce.to_le_bytes());
+        put(&self.amount.to_le_bytes());
+        put(&self.yield_amount.to_le_bytes());
+        put(&[self.risk_score, self.coverage_percentage]);
+        put(&self.deadline.to_le_bytes());
+        put(&self.sort_key.to_le_bytes());
+        bytes
+    }
+
+    // sha256 of the canonical terms; what publish_listing_proof anchors on the invoice
+    pub fn hash(&self) -> [u8; 32] {
+        hash(&self.to_bytes()).to_bytes()
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ListingProof {
+    pub terms: ListingTerms,
+    pub slot: u64,
+}
+
+impl ListingProof {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(LISTING_PROOF_LEN);
+        bytes.extend_from_slice(&self.terms.to_bytes());
+        bytes.extend_from_slice(&self.slot.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        require!(
+            bytes.len() == LISTING_PROOF_LEN && bytes.starts_with(LISTING_PROOF_DOMAIN),
+            ErrorCode::InvalidListingProof
+        );
+        // Offsets as laid out above
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let invoice = Pubkey::try_from(&bytes[24..56]).map_err(|_| error!(ErrorCode::InvalidListingProof))?;
+
+        Ok(Self {
+            terms: ListingTerms {
+                invoice,
+                nonce: u64_at(56) as i64,
+                amount: u64_at(64),
+                yield_amount: u64_at(72),
+                risk_score: bytes[80],
+                coverage_percentage: bytes[81],
+                deadline: u64_at(82) as i64,
+                sort_key: u64_at(90),
+            },
+            slot: u64_at(LISTING_TERMS_LEN),
+        })
+    }
+}
+
+// Check a proof a partner embedded against a later fetch of the invoice account.
+// Any figure that differs from the account, e.g. a doctored yield, fails.
+#[cfg(any(test, feature = "client"))]
+pub fn verify_listing_proof(proof: &[u8], invoice_key: Pubkey, invoice: &Invoice) -> Result<ListingProof> {
+    let proof = ListingProof::from_bytes(proof)?;
+    require!(
+        proof.terms == ListingTerms::of(invoice_key, invoice),
+        ErrorCode::ListingProofMismatch
+    );
+    Ok(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed_invoice() -> Invoice {
+        Invoice {
+            invoice_id: 7,
+            amount: 250_000_000,
+            expected_return: Some(265_000_000),
+            risk_score: 30,
+            coverage_percentage: 80,
+            due_date: 1_702_000_000,
+            created_at: 1_700_000_000,
+            ..Invoice::default()
+        }
+    }
+
+    fn proof(invoice_key: Pubkey) -> ListingProof {
+        ListingProof {
+            terms: ListingTerms::of(invoice_key, &listed_invoice()),
+            slot: 123_456,
+        }
+    }
+
+    #[test]
+    fn proof_round_trips_through_its_canonical_bytes() {
+        let original = proof(Pubkey::new_unique());
+        let bytes = original.to_bytes();
+        assert_eq!(bytes.len(), LISTING_PROOF_LEN);
+        assert_eq!(ListingProof::from_bytes(&bytes).unwrap(), original);
+    }
+
+    #[test]
+    fn canonical_layout_is_fixed() {
+        let invoice_key = Pubkey::new_from_array([9; 32]);
+        let bytes = proof(invoice_key).to_bytes();
+
+        assert_eq!(&bytes[..24], LISTING_PROOF_DOMAIN);
+        assert_eq!(&bytes[24..56], &[9; 32]);
+        assert_eq!(bytes[56..64], 1_700_000_000i64.to_le_bytes());
+        assert_eq!(bytes[64..72], 250_000_000u64.to_le_bytes());
+        assert_eq!(bytes[72..80], 15_000_000u64.to_le_bytes());
+        assert_eq!(bytes[80..82], [30, 80]);
+        assert_eq!(bytes[82..90], 1_702_000_000i64.to_le_bytes());
+        assert_eq!(bytes[90..98], 600u64.to_le_bytes());
+        assert_eq!(bytes[98..106], 123_456u64.to_le_bytes());
+    }
+
+    #[test]
+    fn verifies_against_the_fetched_account_and_catches_a_doctored_yield() {
+        let invoice_key = Pubkey::new_unique();
+        let mut bytes = proof(invoice_key).to_bytes();
+        verify_listing_proof(&bytes, invoice_key, &listed_invoice()).unwrap();
+
+        bytes[72..80].copy_from_slice(&25_000_000u64.to_le_bytes());
+        assert_eq!(
+            verify_listing_proof(&bytes, invoice_key, &listed_invoice()).unwrap_err(),
+            error!(ErrorCode::ListingProofMismatch)
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_or_foreign_bytes() {
+        let bytes = proof(Pubkey::new_unique()).to_bytes();
+        assert_eq!(
+            ListingProof::from_bytes(&bytes[..LISTING_PROOF_LEN - 1]).unwrap_err(),
+            error!(ErrorCode::InvalidListingProof)
+        );
+
+        let mut foreign = bytes.clone();
+        foreign[0] ^= 1;
+        assert_eq!(ListingProof::from_bytes(&foreign).unwrap_err(), error!(ErrorCode::InvalidListingProof));
+    }
+}
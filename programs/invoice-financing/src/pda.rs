@@ -0,0 +1,387 @@
+use anchor_lang::prelude::*;
+
+use crate::constants::*;
+
+// Addresses of the program's PDAs, derived from the seeds its account constraints
+// check, so keepers, indexers and test harnesses can depend on this crate (with
+// `cpi` or `no-entrypoint`) instead of copying them. Each returns the address and
+// its bump.
+
+pub fn find_global_state_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID)
+}
+
+// One page of the admin action log
+pub fn find_admin_log_address(page: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ADMIN_LOG_SEED, &page.to_le_bytes()], &crate::ID)
+}
+
+pub fn find_micro_tier_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MICRO_TIER_SEED], &crate::ID)
+}
+
+pub fn find_crank_treasury_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CRANK_TREASURY_SEED], &crate::ID)
+}
+
+// The PDA that owns the treasury's token accounts
+pub fn find_treasury_authority_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TREASURY_AUTHORITY_SEED], &crate::ID)
+}
+
+// Signs the self-CPIs indexed events go out through
+pub fn find_event_authority_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], &crate::ID)
+}
+
+// The insurance pool's USDC token account
+pub fn find_insurance_pool_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INSURANCE_POOL_SEED], &crate::ID)
+}
+
+// The PDA that owns the insurance pool token account
+pub fn find_insurance_pool_authority_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INSURANCE_POOL_AUTHORITY_SEED], &crate::ID)
+}
+
+pub fn find_payout_processor_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PAYOUT_PROCESSOR_SEED], &crate::ID)
+}
+
+// A business's invoice `invoice_id`, as counted by its business profile
+pub fn find_invoice_address(invoice_id: u64, business_owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INVOICE_SEED, business_owner.as_ref(), &invoice_id.to_le_bytes()], &crate::ID)
+}
+
+pub fn find_business_profile_address(business_owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[BUSINESS_PROFILE_SEED, business_owner.as_ref()], &crate::ID)
+}
+
+// Token account a partially funded invoice collects its shares in
+pub fn find_invoice_vault_address(invoice: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INVOICE_VAULT_SEED, invoice.as_ref()], &crate::ID)
+}
+
+// A debtor's registry entry
+pub fn find_debtor_address(debtor_id: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[DEBTOR_SEED, debtor_id], &crate::ID)
+}
+
+pub fn find_originator_stats_address(originator: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ORIGINATOR_STATS_SEED, originator.as_ref()], &crate::ID)
+}
+
+pub fn find_investor_whitelist_address(investor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INVESTOR_WHITELIST_SEED, investor.as_ref()], &crate::ID)
+}
+
+pub fn find_investor_stats_address(investor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INVESTOR_STATS_SEED, investor.as_ref()], &crate::ID)
+}
+
+pub fn find_investor_balance_address(investor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[INVESTOR_BALANCE_SEED, investor.as_ref()], &crate::ID)
+}
+
+pub fn find_portfolio_address(investor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PORTFOLIO_SEED, investor.as_ref()], &crate::ID)
+}
+
+pub fn find_blacklist_address(party: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[BLACKLIST_SEED, party.as_ref()], &crate::ID)
+}
+
+// Exposure of one investor to one business
+pub fn find_pair_ledger_address(business_owner: &Pubkey, investor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PAIR_LEDGER_SEED, business_owner.as_ref(), investor.as_ref()], &crate::ID)
+}
+
+// Account metas for the invoice lifecycle instructions, with every PDA filled in
+// and optional accounts left out. Override fields with struct update syntax where
+// an instruction needs more, then pass the result to `to_account_metas`.
+
+#[cfg(any(test, feature = "client"))]
+pub fn create_invoice_accounts(
+    business_owner: &Pubkey,
+    payer: &Pubkey,
+    invoice_id: u64,
+    debtor_id: &[u8; 32],
+) -> crate::accounts::CreateInvoice {
+    crate::accounts::CreateInvoice {
+        business_owner: *business_owner,
+        payer: *payer,
+        business_profile: find_business_profile_address(business_owner).0,
+        invoice: find_invoice_address(invoice_id, business_owner).0,
+        global_state: find_global_state_address().0,
+        experiment: None,
+        micro_tier: None,
+        debtor: find_debtor_address(debtor_id).0,
+        mint: None,
+        instructions: None,
+        business_token_account: None,
+        insurance_pool_account: None,
+        originator_stats: None,
+        token_program: None,
+        system_program: anchor_lang::system_program::ID,
+        business_blacklist: find_blacklist_address(business_owner).0,
+        #[cfg(feature = "cpi-events")]
+        event_authority: find_event_authority_address().0,
+        #[cfg(feature = "cpi-events")]
+        program: crate::ID,
+    }
+}
+
+// Rent goes back to whoever the invoice recorded as its payer
+#[cfg(any(test, feature = "client"))]
+pub fn cancel_invoice_accounts(invoice: &crate::Invoice) -> crate::accounts::CancelInvoice {
+    crate::accounts::CancelInvoice {
+        invoice: find_invoice_address(invoice.invoice_id, &invoice.business_owner).0,
+        global_state: find_global_state_address().0,
+        business_owner: invoice.business_owner,
+        rent_payer: invoice.rent_payer,
+        business_token_account: None,
+        insurance_pool_account: None,
+        insurance_pool_authority: None,
+        token_program: None,
+        business_profile: find_business_profile_address(&invoice.business_owner).0,
+    }
+}
+
+#[cfg(any(test, feature = "client"))]
+pub fn close_invoice_accounts(invoice: &crate::Invoice) -> crate::accounts::CloseInvoice {
+    let address = find_invoice_address(invoice.invoice_id, &invoice.business_owner).0;
+    crate::accounts::CloseInvoice {
+        invoice: address,
+        global_state: find_global_state_address().0,
+        business_owner: invoice.business_owner,
+        rent_payer: invoice.rent_payer,
+        invoice_vault: invoice.partial_funding.then(|| find_invoice_vault_address(&address).0),
+    }
+}
+
+#[cfg(any(test, feature = "client"))]
+pub fn acknowledge_invoice_accounts(
+    invoice_id: u64,
+    business_owner: &Pubkey,
+    debtor_wallet: &Pubkey,
+) -> crate::accounts::AcknowledgeInvoice {
+    crate::accounts::AcknowledgeInvoice {
+        invoice: find_invoice_address(invoice_id, business_owner).0,
+        global_state: find_global_state_address().0,
+        debtor_wallet: *debtor_wallet,
+    }
+}
+
+#[cfg(any(test, feature = "client"))]
+pub fn expire_invoice_accounts(invoice_id: u64, business_owner: &Pubkey) -> crate::accounts::ExpireInvoice {
+    crate::accounts::ExpireInvoice {
+        invoice: find_invoice_address(invoice_id, business_owner).0,
+        business_profile: find_business_profile_address(business_owner).0,
+    }
+}
+
+#[cfg(any(test, feature = "client"))]
+pub fn ping_overdue_accounts(invoice_id: u64, business_owner: &Pubkey) -> crate::accounts::PingOverdue {
+    crate::accounts::PingOverdue {
+        invoice: find_invoice_address(invoice_id, business_owner).0,
+        global_state: find_global_state_address().0,
+    }
+}
+
+// Migrations refund the rent they free to the business owner
+#[cfg(any(test, feature = "client"))]
+pub fn migrate_invoice_accounts(
+    invoice_id: u64,
+    business_owner: &Pubkey,
+    payer: &Pubkey,
+) -> crate::accounts::MigrateInvoice {
+    crate::accounts::MigrateInvoice {
+        invoice: find_invoice_address(invoice_id, business_owner).0,
+        business_owner: *business_owner,
+        payer: *payer,
+        system_program: anchor_lang::system_program::ID,
+    }
+}
+
+#[cfg(any(test, feature = "client"))]
+pub fn get_global_stats_accounts(pool_initialized: bool) -> crate::accounts::GetGlobalStats {
+    crate::accounts::GetGlobalStats {
+        global_state: find_global_state_address().0,
+        insurance_pool_account: pool_initialized.then(|| find_insurance_pool_address().0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AcknowledgeInvoice, AcknowledgeInvoiceBumps, GetGlobalStats, GetGlobalStatsBumps, GlobalState, Invoice,
+        PingOverdue, PingOverdueBumps, GLOBAL_STATE_VERSION, INVOICE_VERSION,
+    };
+    use anchor_lang::solana_program::instruction::AccountMeta;
+    use anchor_lang::solana_program::program_pack::Pack;
+    use anchor_spl::token::spl_token;
+    use std::collections::BTreeSet;
+
+    // Raw account state for the metas a builder produced, owned by the program
+    // unless listed in `owners`
+    struct RawAccounts {
+        metas: Vec<AccountMeta>,
+        data: Vec<Vec<u8>>,
+        lamports: Vec<u64>,
+        owners: Vec<Pubkey>,
+    }
+
+    impl RawAccounts {
+        fn new(metas: Vec<AccountMeta>, mut data: impl FnMut(&Pubkey) -> (Vec<u8>, Pubkey)) -> Self {
+            let (data, owners) = metas.iter().map(|meta| data(&meta.pubkey)).unzip();
+            let lamports = vec![1_000_000; metas.len()];
+            RawAccounts { metas, data, lamports, owners }
+        }
+
+        fn infos(&mut self) -> Vec<AccountInfo<'_>> {
+            self.metas
+                .iter()
+                .zip(self.data.iter_mut())
+                .zip(self.lamports.iter_mut())
+                .zip(&self.owners)
+                .map(|(((meta, data), lamports), owner)| {
+                    AccountInfo::new(&meta.pubkey, meta.is_signer, meta.is_writable, lamports, data, owner, false, 0)
+                })
+                .collect()
+        }
+    }
+
+    fn serialized<T: AccountSerialize>(account: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        data
+    }
+
+    fn global_state() -> Vec<u8> {
+        serialized(&GlobalState {
+            bump: find_global_state_address().1,
+            version: GLOBAL_STATE_VERSION,
+            insurance_pool_bump: find_insurance_pool_address().1,
+            ..GlobalState::default()
+        })
+    }
+
+    fn invoice(invoice_id: u64, business_owner: Pubkey) -> Invoice {
+        Invoice {
+            invoice_id,
+            business_owner,
+            bump: find_invoice_address(invoice_id, &business_owner).1,
+            version: INVOICE_VERSION,
+            ..Invoice::default()
+        }
+    }
+
+    #[test]
+    fn addresses_are_program_pdas_with_canonical_bumps() {
+        let owner = Pubkey::new_unique();
+        let cases = [
+            (find_global_state_address(), vec![GLOBAL_STATE_SEED.to_vec()]),
+            (find_insurance_pool_address(), vec![INSURANCE_POOL_SEED.to_vec()]),
+            (find_admin_log_address(3), vec![ADMIN_LOG_SEED.to_vec(), 3u32.to_le_bytes().to_vec()]),
+            (
+                find_invoice_address(7, &owner),
+                vec![INVOICE_SEED.to_vec(), owner.to_bytes().to_vec(), 7u64.to_le_bytes().to_vec()],
+            ),
+            (find_debtor_address(&[9; 32]), vec![DEBTOR_SEED.to_vec(), vec![9; 32]]),
+        ];
+        for ((address, bump), seeds) in cases {
+            assert!(!address.is_on_curve());
+            let mut seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+            let bump = [bump];
+            seeds.push(&bump);
+            assert_eq!(Pubkey::create_program_address(&seeds, &crate::ID).unwrap(), address);
+        }
+    }
+
+    #[test]
+    fn builders_pass_the_seed_constraints() {
+        let (business_owner, wallet) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let invoice_data = serialized(&invoice(4, business_owner));
+        let state = global_state();
+        let data = |key: &Pubkey| {
+            if *key == find_global_state_address().0 {
+                (state.clone(), crate::ID)
+            } else if *key == wallet {
+                (Vec::new(), anchor_lang::system_program::ID)
+            } else {
+                (invoice_data.clone(), crate::ID)
+            }
+        };
+
+        let metas = acknowledge_invoice_accounts(4, &business_owner, &wallet).to_account_metas(None);
+        let mut accounts = RawAccounts::new(metas, data);
+        let infos = accounts.infos();
+        AcknowledgeInvoice::try_accounts(
+            &crate::ID,
+            &mut &infos[..],
+            &[],
+            &mut AcknowledgeInvoiceBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .unwrap();
+
+        let metas = ping_overdue_accounts(4, &business_owner).to_account_metas(None);
+        let mut accounts = RawAccounts::new(metas, data);
+        let infos = accounts.infos();
+        PingOverdue::try_accounts(&crate::ID, &mut &infos[..], &[], &mut PingOverdueBumps::default(), &mut BTreeSet::new())
+            .unwrap();
+
+        // The builder's address for another id does not hold this invoice
+        let metas = acknowledge_invoice_accounts(5, &business_owner, &wallet).to_account_metas(None);
+        let mut accounts = RawAccounts::new(metas, data);
+        let infos = accounts.infos();
+        let error = AcknowledgeInvoice::try_accounts(
+            &crate::ID,
+            &mut &infos[..],
+            &[],
+            &mut AcknowledgeInvoiceBumps::default(),
+            &mut BTreeSet::new(),
+        )
+        .map(|_| ())
+        .unwrap_err();
+        assert_eq!(error, error!(anchor_lang::error::ErrorCode::ConstraintSeeds));
+    }
+
+    #[test]
+    fn global_stats_builder_finds_the_insurance_pool() {
+        let (pool, _) = find_insurance_pool_address();
+        let mut pool_data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint: Pubkey::new_unique(),
+            owner: find_insurance_pool_authority_address().0,
+            state: spl_token::state::AccountState::Initialized,
+            ..spl_token::state::Account::default()
+        }
+        .pack_into_slice(&mut pool_data);
+        let state = global_state();
+        let data = |key: &Pubkey| {
+            if *key == pool {
+                (pool_data.clone(), spl_token::ID)
+            } else {
+                (state.clone(), crate::ID)
+            }
+        };
+
+        for pool_initialized in [true, false] {
+            let metas = get_global_stats_accounts(pool_initialized).to_account_metas(None);
+            assert_eq!(metas.len(), 2);
+            let mut accounts = RawAccounts::new(metas, data);
+            let infos = accounts.infos();
+            let stats = GetGlobalStats::try_accounts(
+                &crate::ID,
+                &mut &infos[..],
+                &[],
+                &mut GetGlobalStatsBumps::default(),
+                &mut BTreeSet::new(),
+            )
+            .unwrap();
+            assert_eq!(stats.insurance_pool_account.is_some(), pool_initialized);
+        }
+    }
+}
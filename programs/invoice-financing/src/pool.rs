@@ -0,0 +1,103 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_spl::token::spl_token::state::Account as SplTokenAccount;
+
+use crate::ErrorCode;
+
+// An investment pool lets many depositors share a book of invoices. Deposits go
+// into a USDC vault and mint shares at the pool's net asset value: the vault
+// balance plus the open positions at cost (principal and premium paid). Shares
+// redeem for their slice of that value, as far as the vault has cash on hand.
+//
+// The pool's investor of record is a data-less PDA at ["pool_authority", pool]. It
+// owns the vault, mints the shares, and signs, through invoke_signed, when the
+// pool re-enters fund_invoice, redeem_receipt, claim_insurance and reclaim_escrow,
+// so a pool position goes through exactly the checks and bookkeeping an investor's
+// does. Those instructions take the same accounts as when an investor sends them,
+// passed as remaining accounts. Anything they pay out to the investor lands in the
+// vault, later recoveries included.
+#[constant]
+pub const INVESTMENT_POOL_SEED: &[u8] = b"investment_pool";
+#[constant]
+pub const POOL_AUTHORITY_SEED: &[u8] = b"pool_authority";
+#[constant]
+pub const POOL_SHARES_SEED: &[u8] = b"pool_shares";
+#[constant]
+pub const POOL_POSITION_SEED: &[u8] = b"pool_position";
+
+// Shares minted for `amount` deposited into a pool worth `nav` with `supply` out;
+// the first deposit mints one for one
+pub fn shares_for_deposit(amount: u64, nav: u64, supply: u64) -> Result<u64> {
+    if supply == 0 {
+        return Ok(amount);
+    }
+    require!(nav > 0, ErrorCode::PoolInsolvent);
+    u64::try_from(amount as u128 * supply as u128 / nav as u128).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+// What `shares` redeem for out of a pool worth `nav` with `supply` out
+pub fn redemption_value(shares: u64, nav: u64, supply: u64) -> u64 {
+    crate::pro_rata(nav, shares, supply)
+}
+
+// Proceeds must land in the vault: the pool authority may own other accounts of
+// the pool's mint, anyone can open one for it, but none of them may be handed to
+// an instruction the pool signs
+pub fn require_vault_destination(accounts: &[AccountInfo], pool_authority: &Pubkey, mint: &Pubkey, vault: &Pubkey) -> Result<()> {
+    for info in accounts {
+        if info.key == vault || *info.owner != anchor_spl::token::ID || info.data_len() != SplTokenAccount::LEN {
+            continue;
+        }
+        let account = SplTokenAccount::unpack_unchecked(&info.try_borrow_data()?)?;
+        require!(
+            !(account.owner == *pool_authority && account.mint == *mint),
+            ErrorCode::PoolVaultMismatch
+        );
+    }
+    Ok(())
+}
+
+// Run one of this program's instructions with the pool authority signing. The
+// instruction's accounts come in order in `accounts`; `program` is this program's
+// own account, which the runtime needs among the infos of a self-invocation.
+pub fn invoke_as_pool<'info>(
+    data: Vec<u8>,
+    accounts: &[AccountInfo<'info>],
+    program: &AccountInfo<'info>,
+    pool_authority: &Pubkey,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let metas = accounts
+        .iter()
+        .map(|info| AccountMeta {
+            pubkey: *info.key,
+            is_signer: info.is_signer || info.key == pool_authority,
+            is_writable: info.is_writable,
+        })
+        .collect();
+    let instruction = Instruction { program_id: crate::ID, accounts: metas, data };
+    let mut infos = accounts.to_vec();
+    infos.push(program.clone());
+    invoke_signed(&instruction, &infos, signer_seeds).map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_track_the_pool_value() {
+        // First deposit one for one
+        assert_eq!(shares_for_deposit(100_000_000, 0, 0).unwrap(), 100_000_000);
+        // After a 10% gain a share is worth 1.1: 55 USDC buys 50 shares
+        assert_eq!(shares_for_deposit(55_000_000, 110_000_000, 100_000_000).unwrap(), 50_000_000);
+        assert_eq!(redemption_value(50_000_000, 165_000_000, 150_000_000), 55_000_000);
+        // Rounding favours the shares that stay in
+        assert_eq!(shares_for_deposit(2, 3, 2).unwrap(), 1);
+        assert_eq!(redemption_value(1, 3, 2), 1);
+        // Shares out against nothing would mint without bound
+        assert!(shares_for_deposit(1_000_000, 0, 100).is_err());
+    }
+}
@@ -0,0 +1,382 @@
+use anchor_lang::prelude::*;
+
+use crate::{ErrorCode, ExperimentArm, RiskAssessment};
+
+// Bumped whenever any formula below changes; every invoice records the version
+// it was priced under. Version 1 paid a flat yield whatever the term; version 2
+// prices it as an APR over the term, fixed at funding. Version 3 rounds premiums
+// up and holds them to the protocol's minimum.
+pub const PRICING_VERSION: u8 = 3;
+// First version whose yield funding fixes from the APR and the term left
+pub const APR_PRICING_VERSION: u8 = 2;
+
+// Control pricing charges 10 bps of face value per risk point
+pub const CONTROL_PREMIUM_BPS_PER_RISK_POINT: u16 = 10;
+
+// Investor APR: a 5% base plus 20 bps a year per risk point
+const YIELD_BPS_PER_RISK_POINT: u16 = 20;
+const BASE_ESTIMATED_YIELD_BPS: u16 = 500;
+
+const DAYS_PER_YEAR: u128 = 365;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+}
+
+// Share of the unpaid principal the insurance pool covers, per risk tier: low (score
+// up to 20), medium (up to 35), high (up to 50) and very high
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct CoverageTiers {
+    pub low: u8,
+    pub medium: u8,
+    pub high: u8,
+    pub very_high: u8,
+}
+
+impl CoverageTiers {
+    pub const SIZE: usize = 4;
+    pub const DEFAULT: Self = Self { low: 90, medium: 80, high: 70, very_high: 60 };
+
+    // Percentages, and riskier tiers never covered more than safer ones
+    pub fn is_valid(&self) -> bool {
+        self.low <= 100 && self.low >= self.medium && self.medium >= self.high && self.high >= self.very_high
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PremiumSchedule {
+    // Premium scales with the risk score
+    RiskScaled,
+    // Micro tier: a flat share of face value, whatever the score
+    Flat { premium_bps: u16 },
+}
+
+// The running experiment's treatment terms and the arm the invoice hashed into
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ExperimentTerms {
+    pub premium_bps_per_risk_point: u16,
+    pub max_premium_delta_bps: u16,
+    pub arm: ExperimentArm,
+}
+
+// Everything a price depends on. Every entry path builds the full struct, so two
+// paths given the same facts cannot disagree on the price.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PricingInputs {
+    pub version: u8,
+    pub amount: u64,
+    pub risk: RiskAssessment,
+    pub schedule: PremiumSchedule,
+    pub experiment: Option<ExperimentTerms>,
+    pub coverage: CoverageTiers,
+    // Whole days the yield runs for, as term_days counts them
+    pub term_days: u64,
+    // Least premium an insured invoice pays, whatever its rate comes to
+    pub min_premium: u64,
+    // How the yield rounds; premiums always round up, in the pool's favour
+    pub rounding: Rounding,
+}
+
+impl PricingInputs {
+    pub fn new(
+        amount: u64,
+        risk: RiskAssessment,
+        schedule: PremiumSchedule,
+        coverage: CoverageTiers,
+        term_days: u64,
+    ) -> Self {
+        Self {
+            version: PRICING_VERSION,
+            amount,
+            risk,
+            schedule,
+            experiment: None,
+            coverage,
+            term_days,
+            min_premium: 0,
+            rounding: Rounding::Floor,
+        }
+    }
+
+    pub fn with_experiment(self, terms: ExperimentTerms) -> Self {
+        Self { experiment: Some(terms), ..self }
+    }
+
+    pub fn with_min_premium(self, min_premium: u64) -> Self {
+        Self { min_premium, ..self }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Pricing {
+    pub version: u8,
+    pub insurance_premium: u64,
+    // Rate the charged premium was priced at, in bps of face value, before the
+    // minimum premium applies
+    pub premium_bps: u16,
+    pub control_premium: u64,
+    pub treatment_premium: Option<u64>,
+    // Arm actually applied; control when the treatment broke the guardrail
+    pub experiment_arm: Option<ExperimentArm>,
+    pub guardrail_fallback: bool,
+    // Yield over the priced term, and the APR it was computed at
+    pub yield_amount: u64,
+    pub estimated_yield_bps: u16,
+    pub coverage_percentage: u64,
+}
+
+impl Pricing {
+    pub fn expected_return(&self, amount: u64) -> Result<u64> {
+        amount.checked_add(self.yield_amount).ok_or(error!(ErrorCode::MathOverflow))
+    }
+}
+
+pub fn price_invoice(inputs: &PricingInputs) -> Result<Pricing> {
+    require!(inputs.version == PRICING_VERSION, ErrorCode::UnsupportedPricingVersion);
+
+    let risk_score = inputs.risk.risk_score as u16;
+    let control_bps = match inputs.schedule {
+        PremiumSchedule::RiskScaled => risk_score * CONTROL_PREMIUM_BPS_PER_RISK_POINT,
+        PremiumSchedule::Flat { premium_bps } => premium_bps,
+    };
+    let control_premium = premium(inputs.amount, control_bps, inputs.min_premium);
+
+    let mut insurance_premium = control_premium;
+    let mut premium_bps = control_bps;
+    let mut treatment_premium = None;
+    let mut experiment_arm = None;
+    let mut guardrail_fallback = false;
+    if let Some(terms) = inputs.experiment {
+        let treatment_bps = risk_score.saturating_mul(terms.premium_bps_per_risk_point);
+        let treatment = premium(inputs.amount, treatment_bps, inputs.min_premium);
+        let delta = treatment.abs_diff(control_premium);
+        let within_guardrail =
+            delta as u128 * 10_000 <= control_premium as u128 * terms.max_premium_delta_bps as u128;
+
+        guardrail_fallback = terms.arm == ExperimentArm::Treatment && !within_guardrail;
+        let arm = if guardrail_fallback { ExperimentArm::Control } else { terms.arm };
+        if arm == ExperimentArm::Treatment {
+            insurance_premium = treatment;
+            premium_bps = treatment_bps;
+        }
+        treatment_premium = Some(treatment);
+        experiment_arm = Some(arm);
+    }
+
+    Ok(Pricing {
+        version: inputs.version,
+        insurance_premium,
+        premium_bps,
+        control_premium,
+        treatment_premium,
+        experiment_arm,
+        guardrail_fallback,
+        yield_amount: term_yield(inputs.amount, apr_bps(inputs.risk.risk_score), inputs.term_days, inputs.rounding)?,
+        estimated_yield_bps: apr_bps(inputs.risk.risk_score),
+        coverage_percentage: coverage_percentage(inputs.risk.risk_score, &inputs.coverage),
+    })
+}
+
+// Investor APR for a risk score, in bps a year
+pub fn apr_bps(risk_score: u8) -> u16 {
+    BASE_ESTIMATED_YIELD_BPS + risk_score as u16 * YIELD_BPS_PER_RISK_POINT
+}
+
+// Yield on `amount` at `apr_bps` a year for `term_days` of a 365-day year:
+// amount * apr_bps * term_days / (10_000 * 365), rounded once at the end
+pub fn term_yield(amount: u64, apr_bps: u16, term_days: u64, rounding: Rounding) -> Result<u64> {
+    let product = (amount as u128 * apr_bps as u128)
+        .checked_mul(term_days as u128)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let value = match rounding {
+        Rounding::Floor => product / (10_000 * DAYS_PER_YEAR),
+        Rounding::Ceil => product.div_ceil(10_000 * DAYS_PER_YEAR),
+    };
+    u64::try_from(value).map_err(|_| error!(ErrorCode::MathOverflow))
+}
+
+// Days of yield from `from` to `to`: a day begun counts in full, and none once
+// `to` has passed. interest_due prorates over the same count.
+pub fn term_days(from: i64, to: i64) -> u64 {
+    (to.saturating_sub(from).max(0) as u64).div_ceil(86400)
+}
+
+// Share of the unpaid principal the insurance pool covers, by risk tier
+pub fn coverage_percentage(risk_score: u8, tiers: &CoverageTiers) -> u64 {
+    let percentage = match risk_score {
+        0..=20 => tiers.low,
+        21..=35 => tiers.medium,
+        36..=50 => tiers.high,
+        _ => tiers.very_high,
+    };
+    percentage as u64
+}
+
+// Premium at `premium_bps` of face value, rounded up so no insured invoice rides
+// free, and at least `min_premium` but never more than the invoice itself
+pub fn premium(amount: u64, premium_bps: u16, min_premium: u64) -> u64 {
+    let priced = (amount as u128 * premium_bps as u128).div_ceil(10_000);
+    priced.max(min_premium as u128).min(amount as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn risk(risk_score: u8) -> RiskAssessment {
+        RiskAssessment {
+            risk_score,
+            industry_risk: 5,
+            estimated_credit_score: 720,
+        }
+    }
+
+    fn terms(arm: ExperimentArm, premium_bps_per_risk_point: u16, max_premium_delta_bps: u16) -> ExperimentTerms {
+        ExperimentTerms {
+            premium_bps_per_risk_point,
+            max_premium_delta_bps,
+            arm,
+        }
+    }
+
+    // One set of inputs per way an invoice can be priced today
+    fn entry_paths(amount: u64, risk_score: u8) -> Vec<PricingInputs> {
+        let standard = PricingInputs::new(amount, risk(risk_score), PremiumSchedule::RiskScaled, CoverageTiers::DEFAULT, 90);
+        vec![
+            standard,
+            PricingInputs::new(amount, risk(risk_score), PremiumSchedule::Flat { premium_bps: 100 }, CoverageTiers::DEFAULT, 90),
+            standard.with_experiment(terms(ExperimentArm::Control, 15, 10_000)),
+            standard.with_experiment(terms(ExperimentArm::Treatment, 15, 10_000)),
+            standard.with_experiment(terms(ExperimentArm::Treatment, 40, 1_000)),
+        ]
+    }
+
+    #[test]
+    fn repricing_unchanged_inputs_is_idempotent_on_every_path() {
+        for amount in [1, 999, 250_000_000, 10_000_000_000] {
+            for risk_score in [0, 17, 35, 50] {
+                for inputs in entry_paths(amount, risk_score) {
+                    let first = price_invoice(&inputs).unwrap();
+                    let copy = inputs;
+                    assert_eq!(price_invoice(&copy).unwrap(), first);
+                    assert_eq!(price_invoice(&inputs).unwrap(), first);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn control_arm_and_guardrail_fallback_price_like_no_experiment() {
+        for inputs in entry_paths(100_000_000, 30) {
+            let priced = price_invoice(&inputs).unwrap();
+            if priced.experiment_arm != Some(ExperimentArm::Treatment) {
+                assert_eq!(priced.insurance_premium, priced.control_premium);
+            }
+        }
+
+        let standard = PricingInputs::new(100_000_000, risk(30), PremiumSchedule::RiskScaled, CoverageTiers::DEFAULT, 90);
+        let plain = price_invoice(&standard).unwrap();
+        let fallback = price_invoice(&standard.with_experiment(terms(ExperimentArm::Treatment, 40, 1_000))).unwrap();
+        assert!(fallback.guardrail_fallback);
+        assert_eq!(fallback.experiment_arm, Some(ExperimentArm::Control));
+        assert_eq!(fallback.insurance_premium, plain.insurance_premium);
+        assert_eq!(fallback.yield_amount, plain.yield_amount);
+    }
+
+    #[test]
+    fn prices_match_the_published_formulas() {
+        let priced = price_invoice(&PricingInputs::new(1_000_000_000, risk(30), PremiumSchedule::RiskScaled, CoverageTiers::DEFAULT, 365)).unwrap();
+        assert_eq!(priced.insurance_premium, 30_000_000);
+        assert_eq!(priced.yield_amount, 110_000_000);
+        assert_eq!(priced.estimated_yield_bps, 1_100);
+        assert_eq!(priced.coverage_percentage, 80);
+
+        let stingy = CoverageTiers { medium: 50, ..CoverageTiers::DEFAULT };
+        let inputs = PricingInputs::new(1_000_000_000, risk(30), PremiumSchedule::RiskScaled, stingy, 365);
+        assert_eq!(price_invoice(&inputs).unwrap().coverage_percentage, 50);
+
+        let flat = PricingInputs::new(250_000_000, risk(15), PremiumSchedule::Flat { premium_bps: 100 }, CoverageTiers::DEFAULT, 90);
+        assert_eq!(price_invoice(&flat).unwrap().insurance_premium, 2_500_000);
+
+        assert_eq!(price_invoice(&flat).unwrap().premium_bps, 100);
+        assert_eq!(price_invoice(&inputs).unwrap().premium_bps, 300);
+    }
+
+    #[test]
+    fn premiums_round_up_to_the_minimum_and_stop_at_face_value() {
+        // 10 bps of 1_000 is exactly 1; a unit more starts the next one
+        assert_eq!(premium(1_000, 10, 0), 1);
+        assert_eq!(premium(1_001, 10, 0), 2);
+        assert_eq!(premium(999, 10, 0), 1);
+        // A single unit still pays, and a zero rate pays nothing without a minimum
+        assert_eq!(premium(1, 10, 0), 1);
+        assert_eq!(premium(1_000_000, 0, 0), 0);
+
+        // The minimum lifts small premiums but never past the invoice itself
+        assert_eq!(premium(1_000_000, 170, 100_000), 100_000);
+        assert_eq!(premium(100_000_000, 170, 100_000), 1_700_000);
+        assert_eq!(premium(1, 170, 100_000), 1);
+        assert_eq!(premium(1_000_000, 0, 100_000), 100_000);
+
+        // The largest configurable invoice at the highest rates stays in range
+        assert_eq!(premium(1_000_000_000_000, u8::MAX as u16 * 10, 0), 255_000_000_000);
+        assert_eq!(premium(u64::MAX, 10_000, 0), u64::MAX);
+        assert_eq!(premium(u64::MAX, u16::MAX, 0), u64::MAX);
+
+        // The floor applies on every path, the yield still rounds down
+        let rounding = PricingInputs::new(1_001, risk(1), PremiumSchedule::RiskScaled, CoverageTiers::DEFAULT, 90);
+        let priced = price_invoice(&rounding).unwrap();
+        assert_eq!((priced.insurance_premium, priced.premium_bps), (2, 10));
+        for inputs in entry_paths(1_000_000, 17) {
+            let priced = price_invoice(&inputs.with_min_premium(100_000)).unwrap();
+            assert_eq!(priced.insurance_premium, 100_000);
+            assert_eq!(priced.control_premium, 100_000);
+            assert_eq!(priced.yield_amount, price_invoice(&inputs).unwrap().yield_amount);
+        }
+    }
+
+    #[test]
+    fn term_yield_rounds_once_at_both_ends_of_the_term() {
+        // 11% on 1_000 USDC: a full year is exact, a single day is 301_369.86...
+        assert_eq!(term_yield(1_000_000_000, 1_100, 365, Rounding::Floor).unwrap(), 110_000_000);
+        assert_eq!(term_yield(1_000_000_000, 1_100, 365, Rounding::Ceil).unwrap(), 110_000_000);
+        assert_eq!(term_yield(1_000_000_000, 1_100, 1, Rounding::Floor).unwrap(), 301_369);
+        assert_eq!(term_yield(1_000_000_000, 1_100, 1, Rounding::Ceil).unwrap(), 301_370);
+        // 365 one-day terms never add up to more than the year
+        assert!(365 * term_yield(1_000_000_000, 1_100, 1, Rounding::Floor).unwrap() <= 110_000_000);
+        assert_eq!(term_yield(1_000_000_000, 1_100, 0, Rounding::Ceil).unwrap(), 0);
+        assert_eq!(term_yield(u64::MAX, u16::MAX, u64::MAX, Rounding::Floor).unwrap_err(), error!(ErrorCode::MathOverflow));
+    }
+
+    #[test]
+    fn yield_scales_with_the_term_at_a_fixed_apr() {
+        let inputs = |term_days| PricingInputs::new(250_000_000, risk(20), PremiumSchedule::RiskScaled, CoverageTiers::DEFAULT, term_days);
+        let short = price_invoice(&inputs(30)).unwrap();
+        let long = price_invoice(&inputs(120)).unwrap();
+        assert_eq!(short.estimated_yield_bps, 900);
+        assert_eq!(long.estimated_yield_bps, 900);
+        assert_eq!(short.yield_amount, 1_849_315);
+        assert_eq!(long.yield_amount, 7_397_260);
+        assert_eq!(short.insurance_premium, long.insurance_premium);
+    }
+
+    #[test]
+    fn term_days_count_a_started_day_in_full() {
+        assert_eq!(term_days(0, 0), 0);
+        assert_eq!(term_days(0, 1), 1);
+        assert_eq!(term_days(0, 86_400), 1);
+        assert_eq!(term_days(0, 86_401), 2);
+        assert_eq!(term_days(86_400, 0), 0);
+    }
+
+    #[test]
+    fn rejects_inputs_from_another_pricing_version() {
+        let inputs = PricingInputs {
+            version: PRICING_VERSION + 1,
+            ..PricingInputs::new(1_000, risk(10), PremiumSchedule::RiskScaled, CoverageTiers::DEFAULT, 90)
+        };
+        assert_eq!(price_invoice(&inputs).unwrap_err(), error!(ErrorCode::UnsupportedPricingVersion));
+    }
+}
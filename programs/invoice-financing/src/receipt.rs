@@ -0,0 +1,48 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::spl_token::state::Account as SplTokenAccount;
+
+// A funded position is represented by a receipt: a 0-decimal mint at
+// ["position_receipt", invoice] with a supply of exactly one, minted to the funding
+// investor. Whoever holds it collects repayments and claims insurance, so the
+// position moves with an ordinary token transfer.
+//
+// The mint authority is dropped once the single token is minted, so the receipt
+// can neither be split nor reissued. The program accepts it from any token account
+// of the receipt mint, not only the holder's associated account. A holder can move
+// it to a fresh account and close the old one, since SPL will not close an
+// account that still holds the token.
+#[constant]
+pub const RECEIPT_SEED: &[u8] = b"position_receipt";
+
+pub fn receipt_address(invoice: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[RECEIPT_SEED, invoice.as_ref()], &crate::ID).0
+}
+
+// Whether `account` is `holder`'s account holding the one receipt of `mint`
+pub fn holds_receipt(account: &SplTokenAccount, mint: &Pubkey, holder: &Pubkey) -> bool {
+    account.mint == *mint && account.owner == *holder && account.amount == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_spl::token::spl_token::state::AccountState;
+
+    fn receipt(mint: Pubkey, owner: Pubkey, amount: u64) -> SplTokenAccount {
+        SplTokenAccount { mint, owner, amount, state: AccountState::Initialized, ..SplTokenAccount::default() }
+    }
+
+    #[test]
+    fn only_the_holder_of_the_receipt_counts() {
+        let invoice = Pubkey::new_unique();
+        let mint = receipt_address(&invoice);
+        let holder = Pubkey::new_unique();
+
+        assert!(holds_receipt(&receipt(mint, holder, 1), &mint, &holder));
+        // Sent on, already burned, or a receipt for another invoice
+        assert!(!holds_receipt(&receipt(mint, Pubkey::new_unique(), 1), &mint, &holder));
+        assert!(!holds_receipt(&receipt(mint, holder, 0), &mint, &holder));
+        let other = receipt_address(&Pubkey::new_unique());
+        assert!(!holds_receipt(&receipt(other, holder, 1), &mint, &holder));
+    }
+}
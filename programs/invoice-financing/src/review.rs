@@ -0,0 +1,263 @@
+use anchor_lang::prelude::*;
+
+use crate::{ErrorCode, ProtocolConfig, MAX_DEBTOR_INFO_URI_LEN};
+
+// Why a listing was refused, whether by validation in create_invoice or by
+// review afterwards. Clients key on these, so the codes are stable: append new
+// reasons at the end, never reorder or remove one.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum RejectionReason {
+    // Validation
+    AmountZero,
+    AmountTooLarge,
+    DueDateNotInFuture,
+    TenorTooLong,
+    // No debtor_info_hash committed
+    DebtorInfoTooShort,
+    // debtor_info_uri over MAX_DEBTOR_INFO_URI_LEN
+    DebtorInfoTooLong,
+    OfframpWithPartialFunding,
+    CreationPaused,
+    // Review
+    DebtorUnconfirmed,
+    RiskAboveAppetite,
+    DuplicateListing,
+    PolicyViolation,
+    // Validation, while business verification is required
+    BusinessNotVerified,
+    // Validation: the debtor wallet named to acknowledge the invoice is the business's own
+    DebtorWalletIsBusiness,
+    // Validation: the business already has max_open_invoices_per_business open
+    OpenInvoiceLimitReached,
+}
+
+// What the business can do about a rejection; same stability rule as the reasons
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum RemediationHint {
+    ReduceAmount,
+    IncreaseAmount,
+    ExtendTenor,
+    ShortenTenor,
+    ProvideDebtorDetails,
+    ShortenDebtorDetails,
+    ChooseOfframpOrPartialFunding,
+    RetryLater,
+    ProvideDebtorConfirmation,
+    AddCollateral,
+    ContactSupport,
+    CompleteVerification,
+    NameDebtorWallet,
+    SettleOpenInvoices,
+}
+
+impl RejectionReason {
+    pub fn hint(self) -> RemediationHint {
+        match self {
+            Self::AmountZero => RemediationHint::IncreaseAmount,
+            Self::AmountTooLarge => RemediationHint::ReduceAmount,
+            Self::DueDateNotInFuture => RemediationHint::ExtendTenor,
+            Self::TenorTooLong => RemediationHint::ShortenTenor,
+            Self::DebtorInfoTooShort => RemediationHint::ProvideDebtorDetails,
+            Self::DebtorInfoTooLong => RemediationHint::ShortenDebtorDetails,
+            Self::OfframpWithPartialFunding => RemediationHint::ChooseOfframpOrPartialFunding,
+            Self::CreationPaused => RemediationHint::RetryLater,
+            Self::DebtorUnconfirmed => RemediationHint::ProvideDebtorConfirmation,
+            Self::RiskAboveAppetite => RemediationHint::AddCollateral,
+            Self::DuplicateListing | Self::PolicyViolation => RemediationHint::ContactSupport,
+            Self::BusinessNotVerified => RemediationHint::CompleteVerification,
+            Self::DebtorWalletIsBusiness => RemediationHint::NameDebtorWallet,
+            Self::OpenInvoiceLimitReached => RemediationHint::SettleOpenInvoices,
+        }
+    }
+
+    // Only these can be given when delisting; the rest come out of validation
+    pub fn is_review_reason(self) -> bool {
+        matches!(
+            self,
+            Self::DebtorUnconfirmed | Self::RiskAboveAppetite | Self::DuplicateListing | Self::PolicyViolation
+        )
+    }
+
+    // The error create_invoice fails with, unchanged from before reasons existed
+    pub fn error(self) -> ErrorCode {
+        match self {
+            Self::AmountZero => ErrorCode::InvalidAmount,
+            Self::AmountTooLarge => ErrorCode::AmountTooLarge,
+            Self::DueDateNotInFuture => ErrorCode::InvalidDueDate,
+            Self::TenorTooLong => ErrorCode::DueDateTooFar,
+            Self::DebtorInfoTooShort => ErrorCode::DebtorInfoTooShort,
+            Self::DebtorInfoTooLong => ErrorCode::DebtorInfoTooLong,
+            Self::OfframpWithPartialFunding => ErrorCode::PartialFundingOfframpUnsupported,
+            Self::CreationPaused => ErrorCode::ProtocolPaused,
+            Self::BusinessNotVerified => ErrorCode::BusinessNotVerified,
+            Self::DebtorWalletIsBusiness => ErrorCode::DebtorWalletIsParty,
+            Self::OpenInvoiceLimitReached => ErrorCode::OpenInvoiceLimitReached,
+            Self::DebtorUnconfirmed | Self::RiskAboveAppetite | Self::DuplicateListing | Self::PolicyViolation => {
+                ErrorCode::ListingRejected
+            }
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct ListingProblem {
+    pub reason: RejectionReason,
+    pub hint: RemediationHint,
+}
+
+impl From<RejectionReason> for ListingProblem {
+    fn from(reason: RejectionReason) -> Self {
+        Self { reason, hint: reason.hint() }
+    }
+}
+
+// The create_invoice arguments a listing is validated on
+pub struct ListingDraft<'a> {
+    pub amount: u64,
+    pub due_date: i64,
+    pub debtor_info_hash: [u8; 32],
+    pub debtor_info_uri: Option<&'a str>,
+    pub offramp_requested: bool,
+    pub partial_funding: bool,
+    // The debtor wallet is the business owner, which could acknowledge for itself
+    pub self_acknowledged: bool,
+}
+
+// Every problem with a draft listing, in the order create_invoice checks them.
+// create_invoice fails on the first; validate_listing reports them all.
+pub fn listing_problems(
+    draft: &ListingDraft,
+    config: &ProtocolConfig,
+    creation_paused: bool,
+    business_unverified: bool,
+    open_invoice_limit_reached: bool,
+    current_time: i64,
+) -> Vec<ListingProblem> {
+    let checks = [
+        (creation_paused, RejectionReason::CreationPaused),
+        (business_unverified, RejectionReason::BusinessNotVerified),
+        (open_invoice_limit_reached, RejectionReason::OpenInvoiceLimitReached),
+        (draft.amount == 0, RejectionReason::AmountZero),
+        (draft.amount > config.max_invoice_amount, RejectionReason::AmountTooLarge),
+        (draft.due_date <= current_time, RejectionReason::DueDateNotInFuture),
+        (
+            draft.due_date > current_time.saturating_add(config.max_term_secs()),
+            RejectionReason::TenorTooLong,
+        ),
+        (
+            draft.debtor_info_uri.is_some_and(|uri| uri.len() > MAX_DEBTOR_INFO_URI_LEN as usize),
+            RejectionReason::DebtorInfoTooLong,
+        ),
+        (draft.debtor_info_hash == [0u8; 32], RejectionReason::DebtorInfoTooShort),
+        (
+            draft.partial_funding && draft.offramp_requested,
+            RejectionReason::OfframpWithPartialFunding,
+        ),
+        (draft.self_acknowledged, RejectionReason::DebtorWalletIsBusiness),
+    ];
+    checks
+        .into_iter()
+        .filter(|(failed, _)| *failed)
+        .map(|(_, reason)| reason.into())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn config() -> ProtocolConfig {
+        ProtocolConfig { max_invoice_amount: 50_000_000, max_term_days: 90, ..ProtocolConfig::default() }
+    }
+
+    fn draft(debtor_info_hash: [u8; 32]) -> ListingDraft<'static> {
+        ListingDraft {
+            amount: 10_000_000,
+            due_date: NOW + 30 * 86_400,
+            debtor_info_hash,
+            debtor_info_uri: Some("ar://bNbA3TEQVL60xlgCcqdz4ZPHFZ711cZ3hmkpGttDt_U"),
+            offramp_requested: false,
+            partial_funding: false,
+            self_acknowledged: false,
+        }
+    }
+
+    #[test]
+    fn clean_listing_has_no_problems() {
+        assert!(listing_problems(&draft([7u8; 32]), &config(), false, false, false, NOW).is_empty());
+    }
+
+    #[test]
+    fn bad_listing_reports_every_problem_at_once() {
+        let bad = ListingDraft {
+            amount: 80_000_000,
+            due_date: NOW + 365 * 86_400,
+            offramp_requested: true,
+            partial_funding: true,
+            ..draft([0u8; 32])
+        };
+        let problems = listing_problems(&bad, &config(), false, false, false, NOW);
+        assert_eq!(
+            problems,
+            vec![
+                ListingProblem { reason: RejectionReason::AmountTooLarge, hint: RemediationHint::ReduceAmount },
+                ListingProblem { reason: RejectionReason::TenorTooLong, hint: RemediationHint::ShortenTenor },
+                ListingProblem {
+                    reason: RejectionReason::DebtorInfoTooShort,
+                    hint: RemediationHint::ProvideDebtorDetails,
+                },
+                ListingProblem {
+                    reason: RejectionReason::OfframpWithPartialFunding,
+                    hint: RemediationHint::ChooseOfframpOrPartialFunding,
+                },
+            ]
+        );
+        // create_invoice keeps failing with the error it always did for the first one
+        assert_eq!(error!(problems[0].reason.error()), error!(ErrorCode::AmountTooLarge));
+    }
+
+    #[test]
+    fn a_business_acknowledging_its_own_invoice_is_a_problem() {
+        let draft = ListingDraft { self_acknowledged: true, ..draft([7u8; 32]) };
+        let problems = listing_problems(&draft, &config(), false, false, false, NOW);
+        assert_eq!(
+            problems,
+            vec![ListingProblem {
+                reason: RejectionReason::DebtorWalletIsBusiness,
+                hint: RemediationHint::NameDebtorWallet,
+            }]
+        );
+        assert_eq!(error!(problems[0].reason.error()), error!(ErrorCode::DebtorWalletIsParty));
+    }
+
+    #[test]
+    fn a_business_at_its_open_invoice_cap_is_refused() {
+        let problems = listing_problems(&draft([7u8; 32]), &config(), false, false, true, NOW);
+        assert_eq!(
+            problems,
+            vec![ListingProblem {
+                reason: RejectionReason::OpenInvoiceLimitReached,
+                hint: RemediationHint::SettleOpenInvoices,
+            }]
+        );
+        assert_eq!(error!(problems[0].reason.error()), error!(ErrorCode::OpenInvoiceLimitReached));
+    }
+
+    #[test]
+    fn codes_are_stable() {
+        assert_eq!(RejectionReason::AmountZero.try_to_vec().unwrap(), vec![0]);
+        assert_eq!(RejectionReason::CreationPaused.try_to_vec().unwrap(), vec![7]);
+        assert_eq!(RejectionReason::PolicyViolation.try_to_vec().unwrap(), vec![11]);
+        assert_eq!(RemediationHint::ContactSupport.try_to_vec().unwrap(), vec![10]);
+        assert_eq!(RejectionReason::BusinessNotVerified.try_to_vec().unwrap(), vec![12]);
+        assert_eq!(RejectionReason::DebtorWalletIsBusiness.try_to_vec().unwrap(), vec![13]);
+        assert_eq!(RemediationHint::NameDebtorWallet.try_to_vec().unwrap(), vec![12]);
+        assert_eq!(RejectionReason::OpenInvoiceLimitReached.try_to_vec().unwrap(), vec![14]);
+        assert_eq!(RemediationHint::SettleOpenInvoices.try_to_vec().unwrap(), vec![13]);
+        assert!(!RejectionReason::BusinessNotVerified.is_review_reason());
+        assert!(RejectionReason::DebtorUnconfirmed.is_review_reason());
+        assert!(!RejectionReason::AmountTooLarge.is_review_reason());
+    }
+}
@@ -0,0 +1,724 @@
+use anchor_lang::prelude::*;
+
+use crate::{BusinessProfile, RiskAssessment};
+
+// A business's defaults and late repayments are kept per month for this long;
+// anything older only shows up in the lifetime totals
+pub const REPUTATION_MONTHS: usize = 24;
+
+// Reputation buckets are 30-day months counted from the unix epoch
+pub const SECONDS_PER_MONTH: i64 = 30 * 86400;
+
+// How much a business's own track record moves its risk score
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct RiskConfig {
+    // Risk points a default adds the month it happens
+    pub default_points: u8,
+    // Risk points a late repayment adds the month it happens
+    pub late_repayment_points: u8,
+    // Months after which an event counts half as much
+    pub half_life_months: u8,
+    // Ceiling on the whole history adjustment
+    pub max_history_points: u8,
+}
+
+impl RiskConfig {
+    pub const SIZE: usize = 4;
+    pub const DEFAULT: Self = Self {
+        default_points: 10,
+        late_repayment_points: 3,
+        half_life_months: 6,
+        max_history_points: 20,
+    };
+
+    pub fn is_valid(&self) -> bool {
+        (1..=REPUTATION_MONTHS as u8).contains(&self.half_life_months)
+            && self.default_points <= 50
+            && self.late_repayment_points <= 50
+            && self.max_history_points <= 50
+    }
+}
+
+pub const AMOUNT_BANDS: usize = 5;
+pub const TERM_BANDS: usize = 6;
+pub const CREDIT_BANDS: usize = 5;
+pub const INDUSTRIES: usize = 7;
+// Named apart from the bands: InitSpace cannot size an array length expression
+const AMOUNT_LIMITS: usize = AMOUNT_BANDS - 1;
+const TERM_LIMITS: usize = TERM_BANDS - 1;
+const CREDIT_FLOORS: usize = CREDIT_BANDS - 1;
+
+// Industry a listing's business operates in, declared at create_invoice. Codes are
+// stable and index RiskParams::industry_points: append, never reorder.
+#[derive(AnchorSerialize, Clone, Copy, PartialEq, Eq, Debug, Default, InitSpace)]
+pub enum IndustryCode {
+    Construction,
+    Retail,
+    Healthcare,
+    Logistics,
+    Manufacturing,
+    Services,
+    #[default]
+    Other,
+}
+
+impl IndustryCode {
+    pub const ALL: [Self; INDUSTRIES] = [
+        Self::Construction,
+        Self::Retail,
+        Self::Healthcare,
+        Self::Logistics,
+        Self::Manufacturing,
+        Self::Services,
+        Self::Other,
+    ];
+
+    // Industry of a NAICS code, by its first two digits
+    pub fn from_naics(code: u32) -> Self {
+        let mut sector = code;
+        while sector >= 100 {
+            sector /= 10;
+        }
+        match sector {
+            23 => Self::Construction,
+            44 | 45 => Self::Retail,
+            62 => Self::Healthcare,
+            42 | 48 | 49 => Self::Logistics,
+            31..=33 => Self::Manufacturing,
+            51..=56 | 61 | 71 | 72 | 81 => Self::Services,
+            _ => Self::Other,
+        }
+    }
+}
+
+// Borsh's derived decoder fails an unknown variant with a bare "unexpected
+// variant"; this one names the code in the program log before failing
+impl AnchorDeserialize for IndustryCode {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let code = u8::deserialize_reader(reader)?;
+        Self::ALL.get(code as usize).copied().ok_or_else(|| {
+            msg!("Unknown industry code {}; expected 0..{}", code, INDUSTRIES);
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "unknown industry code")
+        })
+    }
+}
+
+// Highest cap set_risk_params accepts; coverage tiers run out above 50
+pub const MAX_RISK_SCORE: u8 = 100;
+
+// Weights of the listing-time risk score. A banded factor has one limit fewer
+// than it has bands: a value falls in the first band whose limit it is within,
+// or in the last band once past them all.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct RiskParams {
+    pub base_score: u8,
+    // Face value in USDC base units, inclusive upper limits; larger is riskier
+    pub amount_limits: [u64; AMOUNT_LIMITS],
+    pub amount_points: [u8; AMOUNT_BANDS],
+    // Whole days to the due date, inclusive upper limits; shorter is riskier
+    pub term_limits_days: [u16; TERM_LIMITS],
+    pub term_points: [u8; TERM_BANDS],
+    // Credit score floors, best band first
+    pub credit_floors: [u16; CREDIT_FLOORS],
+    pub credit_points: [u8; CREDIT_BANDS],
+    // Points for a business with neither a verified nor an attested score
+    pub unscored_points: u8,
+    pub max_score: u8,
+    // Points per industry, indexed by IndustryCode
+    pub industry_points: [u8; INDUSTRIES],
+}
+
+impl RiskParams {
+    pub const SIZE: usize = 1
+        + 8 * AMOUNT_LIMITS
+        + AMOUNT_BANDS
+        + 2 * TERM_LIMITS
+        + TERM_BANDS
+        + 2 * CREDIT_FLOORS
+        + CREDIT_BANDS
+        + 1
+        + 1
+        + INDUSTRIES;
+    pub const DEFAULT: Self = Self {
+        base_score: 10,
+        amount_limits: [10_000_000, 50_000_000, 100_000_000, 500_000_000],
+        amount_points: [5, 10, 15, 25, 35],
+        term_limits_days: [7, 14, 30, 60, 90],
+        term_points: [20, 15, 10, 5, 2, 0],
+        credit_floors: [800, 750, 700, 650],
+        credit_points: [0, 2, 5, 10, 15],
+        unscored_points: 10,
+        max_score: 50,
+        // Construction, retail, healthcare, logistics, manufacturing, services, other
+        industry_points: [10, 8, 2, 5, 5, 3, 5],
+    };
+
+    pub fn is_valid(&self) -> bool {
+        let points = [self.base_score, self.unscored_points]
+            .into_iter()
+            .chain(self.amount_points)
+            .chain(self.term_points)
+            .chain(self.credit_points)
+            .chain(self.industry_points);
+        self.amount_limits.windows(2).all(|pair| pair[0] < pair[1])
+            && self.term_limits_days.windows(2).all(|pair| pair[0] < pair[1])
+            && self.credit_floors.windows(2).all(|pair| pair[0] > pair[1])
+            && (1..=MAX_RISK_SCORE).contains(&self.max_score)
+            && points.into_iter().all(|points| points <= 50)
+    }
+
+    pub fn amount_points(&self, amount: u64) -> u8 {
+        band(&self.amount_limits, &self.amount_points, |limit| amount <= *limit)
+    }
+
+    // A due date already past counts as the shortest term
+    pub fn term_points(&self, days_to_due: i64) -> u8 {
+        let days = days_to_due.max(0);
+        band(&self.term_limits_days, &self.term_points, |limit| days <= *limit as i64)
+    }
+
+    pub fn industry_points(&self, industry: IndustryCode) -> u8 {
+        self.industry_points[industry as usize]
+    }
+
+    pub fn credit_points(&self, credit_score: Option<u16>) -> u8 {
+        match credit_score {
+            Some(score) => band(&self.credit_floors, &self.credit_points, |floor| score >= *floor),
+            None => self.unscored_points,
+        }
+    }
+}
+
+impl Default for RiskParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// Points of the first band whose limit `within` accepts, else of the last band
+fn band<T>(limits: &[T], points: &[u8], within: impl Fn(&T) -> bool) -> u8 {
+    let index = limits.iter().position(within).unwrap_or(limits.len());
+    points[index]
+}
+
+// Ring of monthly event counts; `head` is the bucket for `month`, the one before
+// it the month before, and so on back REPUTATION_MONTHS - 1 months
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Debug, InitSpace)]
+pub struct ReputationHistory {
+    pub month: u32,
+    pub head: u8,
+    pub defaults: [u8; REPUTATION_MONTHS],
+    pub late_repayments: [u8; REPUTATION_MONTHS],
+    pub lifetime_defaults: u32,
+    pub lifetime_late_repayments: u32,
+}
+
+impl ReputationHistory {
+    pub const SIZE: usize = 4 + 1 + REPUTATION_MONTHS * 2 + 4 + 4;
+
+    pub fn record_default(&mut self, current_time: i64) {
+        let head = self.advance(current_time);
+        self.defaults[head] = self.defaults[head].saturating_add(1);
+        self.lifetime_defaults = self.lifetime_defaults.saturating_add(1);
+    }
+
+    pub fn record_late_repayment(&mut self, current_time: i64) {
+        let head = self.advance(current_time);
+        self.late_repayments[head] = self.late_repayments[head].saturating_add(1);
+        self.lifetime_late_repayments = self.lifetime_late_repayments.saturating_add(1);
+    }
+
+    // Rotate the ring forward to the current month, clearing the buckets that fall
+    // off the end. Only ever called on update, at most REPUTATION_MONTHS steps.
+    fn advance(&mut self, current_time: i64) -> usize {
+        let month = month_of(current_time);
+        if month > self.month {
+            let elapsed = (month - self.month).min(REPUTATION_MONTHS as u32);
+            for _ in 0..elapsed {
+                self.head = ((self.head as usize + 1) % REPUTATION_MONTHS) as u8;
+                self.defaults[self.head as usize] = 0;
+                self.late_repayments[self.head as usize] = 0;
+            }
+            self.month = month;
+        }
+        self.head as usize
+    }
+
+    // (age in months, defaults, late repayments) for every bucket still in the
+    // window as of `current_time`, without touching the stored ring
+    pub fn buckets(&self, current_time: i64) -> impl Iterator<Item = (u32, u8, u8)> + '_ {
+        let lag = month_of(current_time).saturating_sub(self.month);
+        (0..REPUTATION_MONTHS).filter_map(move |back| {
+            let age = lag + back as u32;
+            let index = (self.head as usize + REPUTATION_MONTHS - back) % REPUTATION_MONTHS;
+            (age < REPUTATION_MONTHS as u32).then(|| (age, self.defaults[index], self.late_repayments[index]))
+        })
+    }
+}
+
+// Lifetime outcome of every invoice the business has settled, kept by the program
+// as invoices are repaid or default. Volume is the principal investors advanced
+// on those invoices.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Debug, InitSpace)]
+pub struct CreditHistory {
+    pub repaid_on_time: u32,
+    pub repaid_late: u32,
+    pub defaulted: u32,
+    pub volume_financed: u64,
+}
+
+// Settled invoices a business needs before its record counts either way
+pub const MIN_CREDIT_RECORD: u32 = 3;
+
+impl CreditHistory {
+    pub const SIZE: usize = 4 + 4 + 4 + 8;
+
+    pub fn record_repayment(&mut self, late: bool, principal: u64) {
+        if late {
+            self.repaid_late = self.repaid_late.saturating_add(1);
+        } else {
+            self.repaid_on_time = self.repaid_on_time.saturating_add(1);
+        }
+        self.volume_financed = self.volume_financed.saturating_add(principal);
+    }
+
+    pub fn record_default(&mut self, principal: u64) {
+        self.defaulted = self.defaulted.saturating_add(1);
+        self.volume_financed = self.volume_financed.saturating_add(principal);
+    }
+
+    pub fn settled(&self) -> u32 {
+        self.repaid_on_time.saturating_add(self.repaid_late).saturating_add(self.defaulted)
+    }
+}
+
+// Points the business's repayment record moves a new listing of `amount` by:
+// negative for a clean record, positive once defaults make up a share of it. A
+// business with fewer than MIN_CREDIT_RECORD settled invoices is neutral, and a
+// record earned on invoices smaller in total than the new one earns half the credit.
+pub fn record_adjustment(credit: &CreditHistory, amount: u64) -> i8 {
+    let settled = credit.settled() as u64;
+    if settled < MIN_CREDIT_RECORD as u64 {
+        return 0;
+    }
+    let default_bps = credit.defaulted as u64 * 10_000 / settled;
+    let on_time_bps = credit.repaid_on_time as u64 * 10_000 / settled;
+    let points: i8 = match (default_bps, on_time_bps) {
+        (2_000.., _) => 10,
+        (1_000.., _) => 6,
+        (1.., _) => 3,
+        (0, 9_500..) if settled >= 10 => -10,
+        (0, 9_000..) => -6,
+        (0, 7_500..) => -3,
+        _ => 0,
+    };
+    if points < 0 && credit.volume_financed < amount {
+        points / 2
+    } else {
+        points
+    }
+}
+
+fn month_of(timestamp: i64) -> u32 {
+    timestamp.max(0).div_euclid(SECONDS_PER_MONTH) as u32
+}
+
+// Weight of an event `age` months old, in bps: halves every half-life and falls
+// linearly in between, so the adjustment shrinks month by month rather than in steps
+fn decay_weight_bps(age: u32, half_life_months: u8) -> u64 {
+    let half_life = half_life_months.max(1) as u64;
+    let age = age as u64;
+    let halvings = age / half_life;
+    if halvings >= 64 {
+        return 0;
+    }
+    let base = 10_000u64 >> halvings;
+    base - base * (age % half_life) / (2 * half_life)
+}
+
+// Risk points a business's recent defaults and late repayments add to a new listing
+pub fn history_adjustment(history: &ReputationHistory, config: &RiskConfig, current_time: i64) -> u8 {
+    let weighted: u64 = history
+        .buckets(current_time)
+        .map(|(age, defaults, late_repayments)| {
+            let points = defaults as u64 * config.default_points as u64
+                + late_repayments as u64 * config.late_repayment_points as u64;
+            points * decay_weight_bps(age, config.half_life_months)
+        })
+        .sum();
+    (weighted / 10_000).min(config.max_history_points as u64) as u8
+}
+
+// What a new listing is scored on, besides the business's profile
+pub struct ListingRisk {
+    pub amount: u64,
+    pub due_date: i64,
+    pub industry: IndustryCode,
+    // Score from a fresh oracle attestation, if the listing came with one
+    pub attested_score: Option<u16>,
+}
+
+// Risk score of a new listing from its size, term and industry, the business's
+// credit and its own track record, with the weights in `params`. A fresh oracle
+// attestation takes precedence over the score recorded at verification, which
+// only counts once the authority has verified the profile. A verified sector is a
+// floor on the industry points, so a business cannot declare its way cheaper.
+pub fn calculate_enhanced_risk(
+    listing: &ListingRisk,
+    business_profile: &BusinessProfile,
+    params: &RiskParams,
+    config: &RiskConfig,
+    current_time: i64,
+) -> RiskAssessment {
+    let amount = listing.amount;
+    let days_to_due = (listing.due_date - current_time) / 86400;
+    let verified = business_profile.verified;
+    let credit_score = listing.attested_score.or(verified.then_some(business_profile.credit_score));
+    let mut industry_risk = params.industry_points(listing.industry);
+    if verified {
+        let sector = IndustryCode::from_naics(business_profile.industry_code);
+        industry_risk = industry_risk.max(params.industry_points(sector));
+    }
+
+    let risk_score = params
+        .base_score
+        .saturating_add(params.amount_points(amount))
+        .saturating_add(params.term_points(days_to_due))
+        .saturating_add(params.credit_points(credit_score))
+        .saturating_add(industry_risk)
+        // The business's own defaults and late repayments, fading with age
+        .saturating_add(history_adjustment(&business_profile.history, config, current_time))
+        // Its lifetime repayment record; neutral until it has one
+        .saturating_add_signed(record_adjustment(&business_profile.credit_history, amount));
+
+    RiskAssessment {
+        risk_score: risk_score.min(params.max_score),
+        industry_risk,
+        estimated_credit_score: credit_score.unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn industry_comes_from_the_leading_naics_digits() {
+        assert_eq!(IndustryCode::from_naics(236_220), IndustryCode::Construction);
+        assert_eq!(IndustryCode::from_naics(5_415), IndustryCode::Services); // computer systems design
+        assert_eq!(IndustryCode::from_naics(62), IndustryCode::Healthcare);
+        assert_eq!(IndustryCode::from_naics(11), IndustryCode::Other); // agriculture
+    }
+
+    #[test]
+    fn industry_codes_round_trip_and_unknown_ones_are_refused() {
+        for industry in IndustryCode::ALL {
+            let bytes = industry.try_to_vec().unwrap();
+            assert_eq!(bytes, vec![industry as u8]);
+            assert_eq!(IndustryCode::try_from_slice(&bytes).unwrap(), industry);
+        }
+        let unknown = IndustryCode::try_from_slice(&[INDUSTRIES as u8]).unwrap_err();
+        assert_eq!(unknown.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn declared_industry_is_weighted_and_a_verified_sector_is_a_floor() {
+        let params = RiskParams::DEFAULT;
+        let listing = |industry| ListingRisk {
+            amount: 10_000_000,
+            due_date: T0 + 45 * 86400,
+            industry,
+            attested_score: None,
+        };
+        let industry_risk = |industry, profile: &BusinessProfile| {
+            calculate_enhanced_risk(&listing(industry), profile, &params, &RiskConfig::DEFAULT, T0).industry_risk
+        };
+
+        let unverified = BusinessProfile { industry_code: 236_220, ..BusinessProfile::default() };
+        assert_eq!(industry_risk(IndustryCode::Healthcare, &unverified), 2);
+        assert_eq!(industry_risk(IndustryCode::Construction, &unverified), 10);
+
+        // Verified as construction: declaring healthcare does not price cheaper
+        let verified = BusinessProfile { verified: true, ..unverified };
+        assert_eq!(industry_risk(IndustryCode::Healthcare, &verified), 10);
+
+        let retuned = RiskParams { industry_points: [10, 8, 2, 5, 5, 3, 20], ..params };
+        let other = listing(IndustryCode::Other);
+        assert_eq!(calculate_enhanced_risk(&other, &unverified, &retuned, &RiskConfig::DEFAULT, T0).industry_risk, 20);
+    }
+
+    fn bands(points: impl Fn(u64) -> u8, values: &[u64]) -> Vec<u8> {
+        values.iter().map(|value| points(*value)).collect()
+    }
+
+    #[test]
+    fn amount_bands_include_their_upper_limit() {
+        let params = RiskParams::DEFAULT;
+        let amounts = [
+            0,
+            10_000_000,
+            10_000_001,
+            50_000_000,
+            50_000_001,
+            100_000_000,
+            100_000_001,
+            500_000_000,
+            500_000_001,
+            u64::MAX,
+        ];
+        assert_eq!(
+            bands(|amount| params.amount_points(amount), &amounts),
+            vec![5, 5, 10, 10, 15, 15, 25, 25, 35, 35]
+        );
+    }
+
+    #[test]
+    fn term_bands_include_their_upper_limit() {
+        let params = RiskParams::DEFAULT;
+        let days = [0, 7, 8, 14, 15, 30, 31, 60, 61, 90, 91, 365];
+        assert_eq!(
+            bands(|days| params.term_points(days as i64), &days),
+            vec![20, 20, 15, 15, 10, 10, 5, 5, 2, 2, 0, 0]
+        );
+        assert_eq!(params.term_points(-3), 20);
+    }
+
+    #[test]
+    fn credit_bands_include_their_floor() {
+        let params = RiskParams::DEFAULT;
+        let scores = [850, 800, 799, 750, 749, 700, 699, 650, 649, 300];
+        assert_eq!(
+            bands(|score| params.credit_points(Some(score as u16)), &scores),
+            vec![0, 0, 2, 2, 5, 5, 10, 10, 15, 15]
+        );
+        assert_eq!(params.credit_points(None), 10);
+    }
+
+    #[test]
+    fn score_is_the_capped_sum_of_weighted_factors() {
+        let now = T0;
+        let profile = BusinessProfile::default();
+        let score = |amount: u64, days: i64, params: &RiskParams| {
+            let due_date = now + days * 86400;
+            let listing = ListingRisk { amount, due_date, industry: IndustryCode::Other, attested_score: None };
+            calculate_enhanced_risk(&listing, &profile, params, &RiskConfig::DEFAULT, now)
+        };
+
+        // Base 10, amount 5, term 5, unscored 10, sector 5
+        assert_eq!(score(10_000_000, 45, &RiskParams::DEFAULT).risk_score, 35);
+        // 10 + 35 + 20 + 10 + 5 = 80, capped
+        assert_eq!(score(900_000_000, 3, &RiskParams::DEFAULT).risk_score, 50);
+
+        let tuned = RiskParams { base_score: 0, unscored_points: 0, max_score: 100, ..RiskParams::DEFAULT };
+        assert_eq!(score(10_000_000, 45, &tuned).risk_score, 15);
+        assert_eq!(score(900_000_000, 3, &tuned).risk_score, 60);
+    }
+
+    fn score_of(listing: &ListingRisk, profile: &BusinessProfile, params: &RiskParams) -> u8 {
+        calculate_enhanced_risk(listing, profile, params, &RiskConfig::DEFAULT, T0).risk_score
+    }
+
+    #[test]
+    fn term_counts_whole_days_from_the_listing_time() {
+        let profile = BusinessProfile::default();
+        let uncapped = RiskParams { max_score: 100, ..RiskParams::DEFAULT };
+        let day = 86400;
+        // Base 10, amount 5, unscored 10, sector 5, then the term
+        let term_points = |seconds: i64| {
+            let listing = ListingRisk {
+                amount: 10_000_000,
+                due_date: T0 + seconds,
+                industry: IndustryCode::Other,
+                attested_score: None,
+            };
+            score_of(&listing, &profile, &uncapped) - 30
+        };
+        assert_eq!(term_points(7 * day), 20);
+        assert_eq!(term_points(8 * day - 1), 20);
+        assert_eq!(term_points(8 * day), 15);
+        assert_eq!(term_points(90 * day), 2);
+        assert_eq!(term_points(91 * day - 1), 2);
+        assert_eq!(term_points(91 * day), 0);
+        // Due already, by less than a day or by more
+        assert_eq!(term_points(-1), 20);
+        assert_eq!(term_points(-3 * day), 20);
+    }
+
+    #[test]
+    fn credit_comes_from_an_attestation_then_a_verified_profile() {
+        let uncapped = RiskParams { max_score: 100, ..RiskParams::DEFAULT };
+        let listing = |attested_score| ListingRisk {
+            amount: 10_000_000,
+            due_date: T0 + 45 * 86400,
+            industry: IndustryCode::Other,
+            attested_score,
+        };
+        // Base 10, amount 5, term 5, sector 5, then the credit points
+        let credit_points = |listing: &ListingRisk, profile: &BusinessProfile| score_of(listing, profile, &uncapped) - 25;
+
+        // An unverified profile's recorded score does not count
+        let unverified = BusinessProfile { credit_score: 820, ..BusinessProfile::default() };
+        assert_eq!(credit_points(&listing(None), &unverified), 10);
+        let verified = BusinessProfile { verified: true, industry_code: 11, ..unverified };
+        assert_eq!(credit_points(&listing(None), &verified), 0);
+        // A fresh attestation wins over either
+        assert_eq!(credit_points(&listing(Some(700)), &unverified), 5);
+        assert_eq!(credit_points(&listing(Some(649)), &verified), 15);
+        let assessment = calculate_enhanced_risk(&listing(Some(649)), &verified, &uncapped, &RiskConfig::DEFAULT, T0);
+        assert_eq!(assessment.estimated_credit_score, 649);
+    }
+
+    #[test]
+    fn score_is_capped_at_max_score_and_never_wraps() {
+        let profile = BusinessProfile::default();
+        let listing = ListingRisk {
+            amount: 10_000_000,
+            due_date: T0 + 45 * 86400,
+            industry: IndustryCode::Other,
+            attested_score: None,
+        };
+        // Amount 5, term 5, unscored 10, sector 5 on top of the base
+        let with_base = |base_score| RiskParams { base_score, ..RiskParams::DEFAULT };
+        assert_eq!(score_of(&listing, &profile, &with_base(24)), 49);
+        assert_eq!(score_of(&listing, &profile, &with_base(25)), 50);
+        assert_eq!(score_of(&listing, &profile, &with_base(26)), 50);
+        assert_eq!(score_of(&listing, &profile, &RiskParams { max_score: 51, ..with_base(26) }), 51);
+
+        // Every factor at its heaviest adds up past u8 and saturates before the cap
+        let heaviest = RiskParams {
+            base_score: 50,
+            amount_points: [50; AMOUNT_BANDS],
+            term_points: [50; TERM_BANDS],
+            credit_points: [50; CREDIT_BANDS],
+            unscored_points: 50,
+            industry_points: [50; INDUSTRIES],
+            max_score: MAX_RISK_SCORE,
+            ..RiskParams::DEFAULT
+        };
+        assert!(heaviest.is_valid());
+        let mut history = ReputationHistory::default();
+        for _ in 0..5 {
+            history.record_default(T0);
+        }
+        let defaulter = BusinessProfile { history, ..BusinessProfile::default() };
+        assert_eq!(score_of(&listing, &defaulter, &heaviest), MAX_RISK_SCORE);
+    }
+
+    #[test]
+    fn params_must_keep_bands_ordered_and_weights_bounded() {
+        assert!(RiskParams::DEFAULT.is_valid());
+        let unordered = RiskParams { amount_limits: [10, 50, 50, 500], ..RiskParams::DEFAULT };
+        let inverted = RiskParams { credit_floors: [650, 700, 750, 800], ..RiskParams::DEFAULT };
+        let heavy = RiskParams { term_points: [80, 15, 10, 5, 2, 0], ..RiskParams::DEFAULT };
+        let uncapped = RiskParams { max_score: 0, ..RiskParams::DEFAULT };
+        for params in [unordered, inverted, heavy, uncapped] {
+            assert!(!params.is_valid());
+        }
+    }
+
+    fn record(on_time: u32, late: u32, defaulted: u32) -> CreditHistory {
+        let mut credit = CreditHistory::default();
+        for _ in 0..on_time {
+            credit.record_repayment(false, 10_000_000);
+        }
+        for _ in 0..late {
+            credit.record_repayment(true, 10_000_000);
+        }
+        for _ in 0..defaulted {
+            credit.record_default(10_000_000);
+        }
+        credit
+    }
+
+    #[test]
+    fn repayment_record_moves_the_score_as_it_accumulates() {
+        // No history, or too little of it, is neutral
+        assert_eq!(record_adjustment(&CreditHistory::default(), 10_000_000), 0);
+        assert_eq!(record_adjustment(&record(2, 0, 0), 10_000_000), 0);
+
+        // On-time repayments earn a growing discount
+        let scores: Vec<i8> = [record(3, 0, 0), record(9, 1, 0), record(12, 0, 0)]
+            .iter()
+            .map(|credit| record_adjustment(credit, 10_000_000))
+            .collect();
+        assert_eq!(scores, vec![-6, -6, -10]);
+        assert_eq!(record_adjustment(&record(3, 2, 0), 10_000_000), 0);
+
+        // A default turns the record against the business, more so as a share
+        assert_eq!(record_adjustment(&record(19, 0, 1), 10_000_000), 3);
+        assert_eq!(record_adjustment(&record(8, 1, 1), 10_000_000), 6);
+        assert_eq!(record_adjustment(&record(3, 0, 1), 10_000_000), 10);
+
+        // A clean record on small invoices vouches for half as much on a bigger one
+        let credit = record(12, 0, 0);
+        assert_eq!(credit.volume_financed, 120_000_000);
+        assert_eq!(record_adjustment(&credit, 500_000_000), -5);
+    }
+
+    const T0: i64 = 1_700_000_000;
+
+    fn months(n: i64) -> i64 {
+        T0 + n * SECONDS_PER_MONTH
+    }
+
+    #[test]
+    fn the_same_default_costs_fewer_points_at_every_later_listing() {
+        let mut history = ReputationHistory::default();
+        history.record_default(T0);
+
+        // One listing every three months after the default
+        let points: Vec<u8> = (0..=8)
+            .map(|quarter| history_adjustment(&history, &RiskConfig::DEFAULT, months(quarter * 3)))
+            .collect();
+        assert_eq!(points, vec![10, 7, 5, 3, 2, 1, 1, 0, 0]);
+        assert_eq!(history.lifetime_defaults, 1);
+    }
+
+    #[test]
+    fn half_life_comes_from_the_config() {
+        let mut history = ReputationHistory::default();
+        history.record_default(T0);
+
+        let slow = RiskConfig { half_life_months: 12, ..RiskConfig::DEFAULT };
+        assert_eq!(history_adjustment(&history, &slow, months(12)), 5);
+        assert_eq!(history_adjustment(&history, &RiskConfig::DEFAULT, months(12)), 2);
+    }
+
+    #[test]
+    fn recent_events_outweigh_old_ones() {
+        let mut history = ReputationHistory::default();
+        history.record_default(T0);
+        history.record_late_repayment(months(12));
+        history.record_late_repayment(months(12));
+
+        // 10 * 0.25 for the default plus 2 * 3 for the fresh late repayments
+        assert_eq!(history_adjustment(&history, &RiskConfig::DEFAULT, months(12)), 8);
+        assert_eq!(history.lifetime_late_repayments, 2);
+    }
+
+    #[test]
+    fn aged_out_events_only_count_in_lifetime_totals() {
+        let mut history = ReputationHistory::default();
+        history.record_default(T0);
+        history.record_default(T0);
+
+        let slowest = RiskConfig { half_life_months: 24, ..RiskConfig::DEFAULT };
+        assert_eq!(history_adjustment(&history, &slowest, months(23)), 10);
+        assert_eq!(history_adjustment(&history, &slowest, months(24)), 0);
+
+        // Recording long after clears the stale buckets as the ring advances
+        history.record_late_repayment(months(40));
+        assert_eq!(history.defaults, [0; REPUTATION_MONTHS]);
+        assert_eq!(history.lifetime_defaults, 2);
+        assert_eq!(history_adjustment(&history, &RiskConfig::DEFAULT, months(40)), 3);
+    }
+
+    #[test]
+    fn adjustment_is_capped() {
+        let mut history = ReputationHistory::default();
+        for _ in 0..5 {
+            history.record_default(T0);
+        }
+        assert_eq!(history_adjustment(&history, &RiskConfig::DEFAULT, T0), 20);
+    }
+}
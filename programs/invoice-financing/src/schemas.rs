@@ -0,0 +1,139 @@
+use anchor_lang::prelude::*;
+
+use crate::{Invoice, InvoiceStatus};
+
+// Domain tag every book leaf starts with; bump the version with any layout change
+pub const BOOK_LEAF_DOMAIN: &[u8] = b"sureinv:book-leaf:v1";
+
+// Canonical book leaf: an invoice's summary and settlement record as of the
+// commitment's slot. Integers little-endian, options as a 0/1 tag followed by the
+// value (zero when absent), no padding:
+//
+//   offset  len  field
+//        0   20  BOOK_LEAF_DOMAIN
+//       20    8  nonce (u64, Invoice::book_nonce)
+//       28   32  invoice account
+//       60   32  business owner
+//       92   32  investor (default key while unfunded)
+//      124    1  status (u8, InvoiceStatus discriminant)
+//      125    8  amount (u64, face value)
+//      133    8  funded_amount (u64)
+//      141    8  due_date (i64)
+//      149    8  remaining_balance (u64)
+//      157    8  amount_repaid (u64)
+//      165    9  funding_date (Option<i64>)
+//      174    9  repayment_date (Option<i64>)
+//      183    9  final_repayment_amount (Option<u64>)
+//      192    9  insurance_payout (Option<u64>)
+//      201    9  defaulted_at (Option<i64>)
+pub const BOOK_LEAF_LEN: usize = 20 + 8 + 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8 + 5 * 9;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct BookLeaf {
+    pub nonce: u64,
+    pub invoice: Pubkey,
+    pub business_owner: Pubkey,
+    pub investor: Pubkey,
+    pub status: InvoiceStatus,
+    pub amount: u64,
+    pub funded_amount: u64,
+    pub due_date: i64,
+    pub remaining_balance: u64,
+    pub amount_repaid: u64,
+    pub funding_date: Option<i64>,
+    pub repayment_date: Option<i64>,
+    pub final_repayment_amount: Option<u64>,
+    pub insurance_payout: Option<u64>,
+    pub defaulted_at: Option<i64>,
+}
+
+impl BookLeaf {
+    pub fn of(invoice_key: Pubkey, invoice: &Invoice) -> Self {
+        Self {
+            nonce: invoice.book_nonce,
+            invoice: invoice_key,
+            business_owner: invoice.business_owner,
+            investor: invoice.investor,
+            status: invoice.status,
+            amount: invoice.amount,
+            funded_amount: invoice.funded_amount,
+            due_date: invoice.due_date,
+            remaining_balance: invoice.remaining_balance,
+            amount_repaid: invoice.amount_repaid,
+            funding_date: invoice.funding_date,
+            repayment_date: invoice.repayment_date,
+            final_repayment_amount: invoice.final_repayment_amount,
+            insurance_payout: invoice.insurance_payout,
+            defaulted_at: invoice.defaulted_at,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; BOOK_LEAF_LEN] {
+        let mut bytes = [0u8; BOOK_LEAF_LEN];
+        let mut at = 0;
+        let mut put = |field: &[u8]| {
+            bytes[at..at + field.len()].copy_from_slice(field);
+            at += field.len();
+        };
+        put(BOOK_LEAF_DOMAIN);
+        put(&self.nonce.to_le_bytes());
+        put(self.invoice.as_ref());
+        put(self.business_owner.as_ref());
+        put(self.investor.as_ref());
+        put(&[self.status as u8]);
+        put(&self.amount.to_le_bytes());
+        put(&self.funded_amount.to_le_bytes());
+        put(&self.due_date.to_le_bytes());
+        put(&self.remaining_balance.to_le_bytes());
+        put(&self.amount_repaid.to_le_bytes());
+        for value in [
+            self.funding_date.map(|value| value as u64),
+            self.repayment_date.map(|value| value as u64),
+            self.final_repayment_amount,
+            self.insurance_payout,
+            self.defaulted_at.map(|value| value as u64),
+        ] {
+            put(&[value.is_some() as u8]);
+            put(&value.unwrap_or(0).to_le_bytes());
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaf_layout_matches_the_documented_offsets() {
+        let leaf = BookLeaf {
+            nonce: 1042,
+            invoice: Pubkey::new_unique(),
+            business_owner: Pubkey::new_unique(),
+            investor: Pubkey::new_unique(),
+            status: InvoiceStatus::Funded,
+            amount: 80_000_000,
+            funded_amount: 80_000_000,
+            due_date: 1_702_000_000,
+            remaining_balance: 80_000_000,
+            amount_repaid: 0,
+            funding_date: Some(1_700_100_000),
+            repayment_date: None,
+            final_repayment_amount: None,
+            insurance_payout: None,
+            defaulted_at: None,
+        };
+        let bytes = leaf.to_bytes();
+
+        assert!(bytes.starts_with(BOOK_LEAF_DOMAIN));
+        assert_eq!(u64::from_le_bytes(bytes[20..28].try_into().unwrap()), 1042);
+        assert_eq!(&bytes[28..60], leaf.invoice.as_ref());
+        assert_eq!(bytes[124], InvoiceStatus::Funded as u8);
+        assert_eq!(u64::from_le_bytes(bytes[125..133].try_into().unwrap()), 80_000_000);
+        assert_eq!(bytes[165], 1);
+        assert_eq!(i64::from_le_bytes(bytes[166..174].try_into().unwrap()), 1_700_100_000);
+        // Absent options are a zero tag and a zero value
+        assert_eq!(&bytes[174..183], &[0u8; 9]);
+        assert_eq!(&bytes[201..BOOK_LEAF_LEN], &[0u8; 9]);
+    }
+}
@@ -0,0 +1,370 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::sysvar::instructions::{
+    self as instructions_sysvar, load_current_index_checked, load_instruction_at_checked,
+};
+
+use crate::ErrorCode;
+
+// Domain tag prefixed to every message the program accepts an off-chain signature for
+pub const SIGNED_MESSAGE_DOMAIN: &[u8] = b"sureinv:signed-message:v1";
+
+// Ed25519 program data: signature count, padding, then one 14-byte offsets record
+const ED25519_HEADER_LEN: usize = 2;
+const ED25519_OFFSETS_LEN: usize = 14;
+
+// Offsets pointing at this value refer to the Ed25519 instruction's own data
+const CURRENT_INSTRUCTION: u16 = u16::MAX;
+
+// An off-chain signed message, bound to this program, one cluster, one account,
+// an expiry and a per-signer nonce so it cannot be replayed anywhere else
+pub struct SignedMessage<'a> {
+    pub cluster_id: [u8; 32],
+    pub subject: Pubkey,
+    pub expiry: i64,
+    pub nonce: u64,
+    pub payload: &'a [u8],
+}
+
+impl SignedMessage<'_> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SIGNED_MESSAGE_DOMAIN.len() + 32 * 3 + 16 + self.payload.len());
+        bytes.extend_from_slice(SIGNED_MESSAGE_DOMAIN);
+        bytes.extend_from_slice(crate::ID.as_ref());
+        bytes.extend_from_slice(&self.cluster_id);
+        bytes.extend_from_slice(self.subject.as_ref());
+        bytes.extend_from_slice(&self.expiry.to_le_bytes());
+        bytes.extend_from_slice(&self.nonce.to_le_bytes());
+        bytes.extend_from_slice(self.payload);
+        bytes
+    }
+}
+
+// Check expiry and nonce, then require the instruction right before ours to be an
+// Ed25519 verification of exactly this message by exactly this signer. On success
+// the signer's stored nonce advances so the message can never be accepted again.
+pub fn verify_signed_message(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &SignedMessage,
+    last_nonce: &mut u64,
+    current_time: i64,
+) -> Result<()> {
+    require!(current_time <= message.expiry, ErrorCode::SignedMessageExpired);
+    require!(message.nonce > *last_nonce, ErrorCode::SignatureNonceReused);
+
+    verify_ed25519_instruction(instructions, signer, &message.to_bytes())?;
+
+    *last_nonce = message.nonce;
+    Ok(())
+}
+
+pub fn verify_ed25519_instruction(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> Result<()> {
+    require_keys_eq!(
+        *instructions.key,
+        instructions_sysvar::ID,
+        ErrorCode::InvalidInstructionsSysvar
+    );
+
+    let current_index = load_current_index_checked(instructions)?;
+    require!(current_index > 0, ErrorCode::MissingEd25519Instruction);
+    let ed25519_ix = load_instruction_at_checked(current_index as usize - 1, instructions)?;
+    require_keys_eq!(
+        ed25519_ix.program_id,
+        ed25519_program::ID,
+        ErrorCode::MissingEd25519Instruction
+    );
+
+    let (signed_by, signed_message) = parse_ed25519_instruction(&ed25519_ix.data)?;
+    require!(signed_by == signer.as_ref(), ErrorCode::SignerMismatch);
+    require!(signed_message == message, ErrorCode::SignedMessageMismatch);
+    Ok(())
+}
+
+// Tag opening the payload of a credit attestation, ahead of the score (u16 LE)
+pub const CREDIT_ATTESTATION_TAG: &[u8] = b"credit-score";
+
+// A credit bureau score the oracle attests for one business, signed as a
+// SignedMessage whose subject is the business owner. The nonce orders one
+// business's attestations: the latest one it used supersedes any earlier, so an
+// old, better score cannot be brought back, while the current one may be reused
+// for every listing until it expires.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub struct CreditAttestation {
+    pub score: u16,
+    pub expiry: i64,
+    pub nonce: u64,
+}
+
+impl CreditAttestation {
+    pub fn message(&self, cluster_id: [u8; 32], business_owner: Pubkey) -> Vec<u8> {
+        let payload = [CREDIT_ATTESTATION_TAG, &self.score.to_le_bytes()].concat();
+        SignedMessage {
+            cluster_id,
+            subject: business_owner,
+            expiry: self.expiry,
+            nonce: self.nonce,
+            payload: &payload,
+        }
+        .to_bytes()
+    }
+}
+
+// Check a credit attestation for `business_owner` against the oracle's Ed25519
+// signature right before our instruction, returning the attested score
+pub fn verify_credit_attestation(
+    instructions: &AccountInfo,
+    oracle: &Pubkey,
+    cluster_id: [u8; 32],
+    business_owner: Pubkey,
+    attestation: &CreditAttestation,
+    last_nonce: &mut u64,
+    current_time: i64,
+) -> Result<u16> {
+    require!(current_time <= attestation.expiry, ErrorCode::CreditAttestationExpired);
+    require!(attestation.nonce >= *last_nonce, ErrorCode::CreditAttestationSuperseded);
+    require!((300..=850).contains(&attestation.score), ErrorCode::InvalidCreditScore);
+
+    verify_ed25519_instruction(instructions, oracle, &attestation.message(cluster_id, business_owner))?;
+
+    *last_nonce = attestation.nonce;
+    Ok(attestation.score)
+}
+
+// Extract (public key, message) from a single-signature Ed25519 instruction whose
+// offsets all point into its own data
+fn parse_ed25519_instruction(data: &[u8]) -> Result<(&[u8], &[u8])> {
+    require!(
+        data.len() >= ED25519_HEADER_LEN + ED25519_OFFSETS_LEN && data[0] == 1,
+        ErrorCode::InvalidEd25519Instruction
+    );
+
+    let read_u16 = |at: usize| {
+        let at = ED25519_HEADER_LEN + at;
+        u16::from_le_bytes([data[at], data[at + 1]])
+    };
+    let signature_offset = read_u16(0) as usize;
+    let signature_ix = read_u16(2);
+    let public_key_offset = read_u16(4) as usize;
+    let public_key_ix = read_u16(6);
+    let message_offset = read_u16(8) as usize;
+    let message_size = read_u16(10) as usize;
+    let message_ix = read_u16(12);
+
+    require!(
+        signature_ix == CURRENT_INSTRUCTION
+            && public_key_ix == CURRENT_INSTRUCTION
+            && message_ix == CURRENT_INSTRUCTION,
+        ErrorCode::InvalidEd25519Instruction
+    );
+
+    let slice = |offset: usize, len: usize| {
+        data.get(offset..offset + len)
+            .ok_or(error!(ErrorCode::InvalidEd25519Instruction))
+    };
+    slice(signature_offset, 64)?;
+    let public_key = slice(public_key_offset, 32)?;
+    let message = slice(message_offset, message_size)?;
+    Ok((public_key, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::sysvar::instructions::{
+        construct_instructions_data, store_current_index, BorrowedInstruction,
+    };
+
+    const NOW: i64 = 1_700_000_000;
+
+    // Mirror of the Ed25519 program's own instruction layout (signature is not
+    // checked here, the runtime verifies it before our instruction runs)
+    fn ed25519_data(signer: &Pubkey, message: &[u8]) -> Vec<u8> {
+        let public_key_offset = (ED25519_HEADER_LEN + ED25519_OFFSETS_LEN) as u16;
+        let signature_offset = public_key_offset + 32;
+        let message_offset = signature_offset + 64;
+
+        let mut data = vec![1u8, 0];
+        for value in [
+            signature_offset,
+            CURRENT_INSTRUCTION,
+            public_key_offset,
+            CURRENT_INSTRUCTION,
+            message_offset,
+            message.len() as u16,
+            CURRENT_INSTRUCTION,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(&[7u8; 64]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    // Build instructions sysvar data for `ixs`, marking `current` as ours
+    fn sysvar_data(ixs: &[(Pubkey, Vec<u8>)], current: u16) -> Vec<u8> {
+        let borrowed: Vec<BorrowedInstruction> = ixs
+            .iter()
+            .map(|(program_id, data)| BorrowedInstruction {
+                program_id,
+                accounts: vec![],
+                data,
+            })
+            .collect();
+        let mut data = construct_instructions_data(&borrowed);
+        store_current_index(&mut data, current);
+        data
+    }
+
+    fn verify(data: &mut [u8], signer: &Pubkey, message: &SignedMessage, last_nonce: &mut u64) -> Result<()> {
+        let key = instructions_sysvar::ID;
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, data, &owner, false, 0);
+        verify_signed_message(&info, signer, message, last_nonce, NOW)
+    }
+
+    fn message(cluster: u8, subject: Pubkey, expiry: i64, nonce: u64) -> SignedMessage<'static> {
+        SignedMessage {
+            cluster_id: [cluster; 32],
+            subject,
+            expiry,
+            nonce,
+            payload: b"score:742",
+        }
+    }
+
+    fn assert_error(result: Result<()>, code: ErrorCode) {
+        assert_eq!(result.unwrap_err(), error!(code));
+    }
+
+    #[test]
+    fn accepts_a_fresh_message_and_advances_the_nonce() {
+        let signer = Pubkey::new_unique();
+        let invoice = Pubkey::new_unique();
+        let msg = message(1, invoice, NOW + 60, 5);
+        let mut data = sysvar_data(
+            &[(ed25519_program::ID, ed25519_data(&signer, &msg.to_bytes())), (crate::ID, vec![])],
+            1,
+        );
+
+        let mut last_nonce = 4;
+        verify(&mut data, &signer, &msg, &mut last_nonce).unwrap();
+        assert_eq!(last_nonce, 5);
+    }
+
+    #[test]
+    fn rejects_a_reused_nonce() {
+        let signer = Pubkey::new_unique();
+        let msg = message(1, Pubkey::new_unique(), NOW + 60, 5);
+        let mut data = sysvar_data(
+            &[(ed25519_program::ID, ed25519_data(&signer, &msg.to_bytes())), (crate::ID, vec![])],
+            1,
+        );
+
+        let mut last_nonce = 5;
+        assert_error(verify(&mut data, &signer, &msg, &mut last_nonce), ErrorCode::SignatureNonceReused);
+    }
+
+    #[test]
+    fn rejects_a_message_signed_for_another_invoice() {
+        let signer = Pubkey::new_unique();
+        let signed = message(1, Pubkey::new_unique(), NOW + 60, 1);
+        let mut data = sysvar_data(
+            &[(ed25519_program::ID, ed25519_data(&signer, &signed.to_bytes())), (crate::ID, vec![])],
+            1,
+        );
+
+        let presented = message(1, Pubkey::new_unique(), NOW + 60, 1);
+        assert_error(verify(&mut data, &signer, &presented, &mut 0), ErrorCode::SignedMessageMismatch);
+    }
+
+    #[test]
+    fn rejects_an_expired_message() {
+        let signer = Pubkey::new_unique();
+        let msg = message(1, Pubkey::new_unique(), NOW - 1, 1);
+        let mut data = sysvar_data(
+            &[(ed25519_program::ID, ed25519_data(&signer, &msg.to_bytes())), (crate::ID, vec![])],
+            1,
+        );
+
+        assert_error(verify(&mut data, &signer, &msg, &mut 0), ErrorCode::SignedMessageExpired);
+    }
+
+    #[test]
+    fn rejects_a_signature_instruction_placed_after_ours() {
+        let signer = Pubkey::new_unique();
+        let msg = message(1, Pubkey::new_unique(), NOW + 60, 1);
+        let mut data = sysvar_data(
+            &[(crate::ID, vec![]), (ed25519_program::ID, ed25519_data(&signer, &msg.to_bytes()))],
+            0,
+        );
+
+        assert_error(verify(&mut data, &signer, &msg, &mut 0), ErrorCode::MissingEd25519Instruction);
+    }
+
+    #[test]
+    fn rejects_a_message_signed_for_another_cluster() {
+        let signer = Pubkey::new_unique();
+        let subject = Pubkey::new_unique();
+        let devnet = message(2, subject, NOW + 60, 1);
+        let mut data = sysvar_data(
+            &[(ed25519_program::ID, ed25519_data(&signer, &devnet.to_bytes())), (crate::ID, vec![])],
+            1,
+        );
+
+        let mainnet = message(1, subject, NOW + 60, 1);
+        assert_error(verify(&mut data, &signer, &mainnet, &mut 0), ErrorCode::SignedMessageMismatch);
+    }
+
+    #[test]
+    fn credit_attestation_binds_business_and_supersedes_older_scores() {
+        let oracle = Pubkey::new_unique();
+        let business = Pubkey::new_unique();
+        let attestation = CreditAttestation { score: 742, expiry: NOW + 60, nonce: 3 };
+        let signed = attestation.message([1; 32], business);
+        let mut data = sysvar_data(&[(ed25519_program::ID, ed25519_data(&oracle, &signed)), (crate::ID, vec![])], 1);
+        let key = instructions_sysvar::ID;
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        let check = |business: Pubkey, attestation: &CreditAttestation, last_nonce: &mut u64| {
+            verify_credit_attestation(&info, &oracle, [1; 32], business, attestation, last_nonce, NOW)
+        };
+
+        // The current attestation can be presented again
+        let mut last_nonce = 3;
+        assert_eq!(check(business, &attestation, &mut last_nonce).unwrap(), 742);
+        assert_eq!(check(business, &attestation, &mut last_nonce).unwrap(), 742);
+
+        assert_eq!(check(business, &attestation, &mut 4).unwrap_err(), error!(ErrorCode::CreditAttestationSuperseded));
+        assert_eq!(
+            check(Pubkey::new_unique(), &attestation, &mut 0).unwrap_err(),
+            error!(ErrorCode::SignedMessageMismatch)
+        );
+        let better = CreditAttestation { score: 820, ..attestation };
+        assert_eq!(check(business, &better, &mut 0).unwrap_err(), error!(ErrorCode::SignedMessageMismatch));
+        let stale = CreditAttestation { expiry: NOW - 1, ..attestation };
+        assert_eq!(check(business, &stale, &mut 0).unwrap_err(), error!(ErrorCode::CreditAttestationExpired));
+    }
+
+    #[test]
+    fn rejects_a_different_signer() {
+        let signer = Pubkey::new_unique();
+        let msg = message(1, Pubkey::new_unique(), NOW + 60, 1);
+        let mut data = sysvar_data(
+            &[(ed25519_program::ID, ed25519_data(&signer, &msg.to_bytes())), (crate::ID, vec![])],
+            1,
+        );
+
+        assert_error(
+            verify(&mut data, &Pubkey::new_unique(), &msg, &mut 0),
+            ErrorCode::SignerMismatch,
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_spl::token::spl_token::state::Account as SplTokenAccount;
+
+use crate::ErrorCode;
+
+// Every token account the program holds funds in (insurance pool, outbox escrow,
+// investor custody, invoice vaults) must answer to its PDA authority alone: owned
+// by it, no delegate, and no close authority other than the PDA itself. A delegate
+// or foreign close authority could move funds outside the program's accounting.
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub enum ProgramVault {
+    InsurancePool,
+    OutboxEscrow,
+    InvestorCustody,
+    InvoiceVault,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug, InitSpace)]
+pub struct VaultIntegrity {
+    pub owner_ok: bool,
+    pub delegate_clear: bool,
+    pub close_authority_ok: bool,
+}
+
+impl VaultIntegrity {
+    pub fn of(account: &SplTokenAccount, authority: &Pubkey) -> Self {
+        Self {
+            owner_ok: account.owner == *authority,
+            delegate_clear: account.delegate.is_none(),
+            close_authority_ok: match account.close_authority {
+                COption::None => true,
+                COption::Some(close_authority) => close_authority == *authority,
+            },
+        }
+    }
+
+    pub fn is_sound(&self) -> bool {
+        self.owner_ok && self.delegate_clear && self.close_authority_ok
+    }
+
+    pub fn require_sound(&self) -> Result<()> {
+        require!(self.owner_ok, ErrorCode::TokenOwnerMismatch);
+        require!(self.delegate_clear, ErrorCode::VaultDelegateSet);
+        require!(self.close_authority_ok, ErrorCode::VaultCloseAuthoritySet);
+        Ok(())
+    }
+}
+
+// Full check, run when a vault is created or first used
+pub fn require_sound_vault(account: &SplTokenAccount, authority: &Pubkey) -> Result<()> {
+    VaultIntegrity::of(account, authority).require_sound()
+}
+
+// Cheap re-check before the program signs a transfer out of (or escrows into) a vault
+pub fn require_no_delegate(account: &SplTokenAccount) -> Result<()> {
+    require!(account.delegate.is_none(), ErrorCode::VaultDelegateSet);
+    Ok(())
+}
+
+// Whether tokens a program PDA holds are outside the program's accounting, so
+// recover_foreign_tokens may move them out: never a mint invoices are
+// denominated in, nor the vault of the given kind itself or its mint
+pub fn is_foreign(mint: &Pubkey, source: &Pubkey, invoice_mint: bool, tracked: Option<(&Pubkey, &Pubkey)>) -> bool {
+    !invoice_mint && !tracked.is_some_and(|(vault, vault_mint)| source == vault || mint == vault_mint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_spl::token::spl_token::state::AccountState;
+
+    fn vault(authority: Pubkey) -> SplTokenAccount {
+        SplTokenAccount {
+            mint: Pubkey::new_unique(),
+            owner: authority,
+            amount: 1_000,
+            state: AccountState::Initialized,
+            ..SplTokenAccount::default()
+        }
+    }
+
+    #[test]
+    fn clean_vault_passes() {
+        let authority = Pubkey::new_unique();
+        let account = vault(authority);
+        assert!(VaultIntegrity::of(&account, &authority).is_sound());
+        assert!(require_sound_vault(&account, &authority).is_ok());
+        assert!(require_no_delegate(&account).is_ok());
+
+        // The PDA may hold the close authority itself
+        let self_closing = SplTokenAccount { close_authority: COption::Some(authority), ..account };
+        assert!(require_sound_vault(&self_closing, &authority).is_ok());
+    }
+
+    #[test]
+    fn poisoned_vaults_are_refused() {
+        let authority = Pubkey::new_unique();
+        let attacker = Pubkey::new_unique();
+
+        let delegated = SplTokenAccount {
+            delegate: COption::Some(attacker),
+            delegated_amount: 1_000,
+            ..vault(authority)
+        };
+        assert_eq!(require_no_delegate(&delegated).unwrap_err(), error!(ErrorCode::VaultDelegateSet));
+        assert_eq!(
+            require_sound_vault(&delegated, &authority).unwrap_err(),
+            error!(ErrorCode::VaultDelegateSet)
+        );
+
+        let closable = SplTokenAccount { close_authority: COption::Some(attacker), ..vault(authority) };
+        assert!(require_no_delegate(&closable).is_ok());
+        assert_eq!(
+            require_sound_vault(&closable, &authority).unwrap_err(),
+            error!(ErrorCode::VaultCloseAuthoritySet)
+        );
+
+        let foreign = vault(attacker);
+        let report = VaultIntegrity::of(&foreign, &authority);
+        assert_eq!(
+            report,
+            VaultIntegrity { owner_ok: false, delegate_clear: true, close_authority_ok: true }
+        );
+        assert_eq!(report.require_sound().unwrap_err(), error!(ErrorCode::TokenOwnerMismatch));
+    }
+
+    #[test]
+    fn only_tokens_outside_the_books_are_foreign() {
+        let (usdc, stray, pool) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let stray_account = Pubkey::new_unique();
+
+        // The USDC pool balance, or USDC anywhere else, never is
+        assert!(!is_foreign(&usdc, &pool, true, Some((&pool, &usdc))));
+        assert!(!is_foreign(&usdc, &stray_account, true, None));
+        assert!(is_foreign(&stray, &stray_account, false, Some((&pool, &usdc))));
+
+        // A vault in a mint since dropped from the approved list still holds invoice funds
+        let dropped = Pubkey::new_unique();
+        assert!(!is_foreign(&dropped, &stray_account, false, Some((&pool, &dropped))));
+        assert!(!is_foreign(&stray, &pool, false, Some((&pool, &dropped))));
+        assert!(is_foreign(&stray, &stray_account, false, None));
+    }
+}
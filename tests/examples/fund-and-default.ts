@@ -0,0 +1,21 @@
+import { assert } from "chai";
+import { TestEnv } from "../fixtures";
+
+// What an integrator's first test looks like, using nothing but the fixtures
+describe("example: fund and default an invoice", () => {
+  it("defaults a funded invoice once its grace period is over", async () => {
+    const env = await TestEnv.new();
+    const business = await env.createBusiness();
+    const investor = await env.createInvestor();
+
+    await env.withConfig({ gracePeriodDays: 0 }, async () => {
+      const { invoice } = await env.createInvoice(business).amount(80_000_000).dueInSeconds(5).listed();
+      await env.fund(invoice).by(investor);
+      assert.deepEqual((await env.program.account.invoice.fetch(invoice)).status, { funded: {} });
+
+      await env.warpTo(invoice, "defaultable");
+      await env.markDefaulted(invoice);
+      assert.deepEqual((await env.program.account.invoice.fetch(invoice)).status, { defaulted: {} });
+    });
+  });
+});
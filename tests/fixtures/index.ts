@@ -0,0 +1,1229 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { ComputeBudgetProgram, Ed25519Program, Keypair, PublicKey, SYSVAR_INSTRUCTIONS_PUBKEY } from "@solana/web3.js";
+import { assert } from "chai";
+import { createHash } from "crypto";
+import { InvoiceFinancing } from "../../target/types/invoice_financing";
+
+// Shared scaffolding for anything that drives the program end to end: our own
+// suite, the frontend and indexer tests, and integrators composing over CPI.
+// Every helper talks to the cluster in ANCHOR_PROVIDER_URL, normally the
+// localnet `anchor test` starts.
+
+export const DAY = 86400;
+export const now = () => Math.floor(Date.now() / 1000);
+export const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+export const expectError = async (promise: PromiseLike<unknown>, code: string) => {
+  try {
+    await promise;
+    assert.fail(`expected ${code}`);
+  } catch (err) {
+    assert.include(String(err), code);
+  }
+};
+
+// anchor_lang::event::EVENT_IX_TAG_LE, which opens every self-CPI event
+export const EVENT_IX_TAG = Buffer.from([0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d]);
+
+export const ADMIN_LOG_PAGE_SIZE = 16;
+export const PARAM_HISTORY_PAGE_SIZE = 8;
+
+// Rules of an auto-invest mandate, as create_auto_invest_mandate takes them
+export interface MandateRules {
+  maxRiskScore: number;
+  minAmount: anchor.BN;
+  maxAmount: anchor.BN;
+  maxTermDays: number;
+  exposureCap: anchor.BN;
+}
+
+// A wallet the fixtures funded, with its USDC associated token account
+export interface Party {
+  keypair: Keypair;
+  publicKey: PublicKey;
+  usdc: PublicKey;
+}
+
+export interface Business extends Party {
+  // Created by the business's first create_invoice
+  profile: PublicKey;
+}
+
+// Debtor registry id: a hash of the debtor's identifier, never the identifier itself
+export const debtorId = (identifier: string) => [...createHash("sha256").update(identifier).digest()];
+
+// What an invoice commits to in place of its document: the document's sha256
+export const debtorInfoHash = (document: string) => [...createHash("sha256").update(document).digest()];
+
+// A credit score the oracle attests for one business (signature.rs)
+export interface CreditAttestation {
+  score: number;
+  expiry: anchor.BN;
+  nonce: anchor.BN;
+}
+
+// The SignedMessage bytes an oracle signs over a credit attestation
+export const creditAttestationMessage = (
+  programId: PublicKey,
+  clusterId: number[],
+  businessOwner: PublicKey,
+  { score, expiry, nonce }: CreditAttestation
+) => {
+  const score16 = Buffer.alloc(2);
+  score16.writeUInt16LE(score);
+  return Buffer.concat([
+    Buffer.from("sureinv:signed-message:v1"),
+    programId.toBuffer(),
+    Buffer.from(clusterId),
+    businessOwner.toBuffer(),
+    expiry.toArrayLike(Buffer, "le", 8),
+    nonce.toArrayLike(Buffer, "le", 8),
+    Buffer.from("credit-score"),
+    score16,
+  ]);
+};
+
+export interface CreatedInvoice {
+  invoiceId: anchor.BN;
+  invoice: PublicKey;
+}
+
+// Where warpTo moves the clock relative to an invoice: its due date, or the end
+// of its grace period, after which anyone may mark it defaulted
+export type Phase = "due" | "defaultable";
+
+// A localnet cannot move its clock, so warpTo waits in real time and refuses
+// anything longer than this; list with dueInSeconds to reach either phase
+const MAX_WARP_SECONDS = 60;
+
+// Builders do nothing until awaited
+abstract class Step<T> implements PromiseLike<T> {
+  protected abstract run(): Promise<T>;
+
+  then<R1 = T, R2 = never>(
+    onfulfilled?: ((value: T) => R1 | PromiseLike<R1>) | null,
+    onrejected?: ((reason: any) => R2 | PromiseLike<R2>) | null
+  ): PromiseLike<R1 | R2> {
+    return this.run().then(onfulfilled, onrejected);
+  }
+}
+
+export class BusinessBuilder extends Step<Business> {
+  private usdcAmount = 0;
+
+  constructor(private readonly env: TestEnv) {
+    super();
+  }
+
+  // Starting USDC balance, e.g. headroom for interest when repaying
+  withUsdc(amount: number) {
+    this.usdcAmount = amount;
+    return this;
+  }
+
+  protected async run() {
+    const party = await this.env.createParty(this.usdcAmount);
+    return { ...party, profile: this.env.businessProfilePda(party.publicKey) };
+  }
+}
+
+export class InvoiceBuilder extends Step<CreatedInvoice> {
+  private opts = {
+    amount: 100_000_000,
+    dueAt: undefined as number | undefined,
+    tenorDays: 45,
+    debtor: "Acme Corp, net 45 invoice #42",
+    debtorInfoUri: null as string | null,
+    offramp: false,
+    partial: false,
+    industry: { other: {} } as Record<string, object>,
+    experiment: null as PublicKey | null,
+    microTier: null as PublicKey | null,
+    mint: null as PublicKey | null,
+    debtorWallet: null as PublicKey | null,
+    fundingDeadline: null as number | null,
+    origination: null as { originator: PublicKey; feeBps: number } | null,
+    advanceRateBps: null as number | null,
+    attestation: null as { oracle: Keypair; attestation: CreditAttestation } | null,
+    prepaysPremium: false,
+    payer: null as Keypair | null,
+    listed: false,
+  };
+
+  constructor(private readonly env: TestEnv, private readonly business: Business | Party | Keypair) {
+    super();
+  }
+
+  amount(amount: number) {
+    this.opts.amount = amount;
+    return this;
+  }
+
+  tenorDays(days: number) {
+    this.opts.tenorDays = days;
+    return this;
+  }
+
+  dueAt(timestamp: number) {
+    this.opts.dueAt = timestamp;
+    return this;
+  }
+
+  // Due a few seconds out, so warpTo can reach it on a localnet
+  dueInSeconds(seconds: number) {
+    return this.dueAt(now() + seconds);
+  }
+
+  debtor(info: string) {
+    this.opts.debtor = info;
+    return this;
+  }
+
+  // Where the encrypted invoice document is kept; the debtor string stands in for the document
+  debtorInfoUri(uri: string) {
+    this.opts.debtorInfoUri = uri;
+    return this;
+  }
+
+  offramp() {
+    this.opts.offramp = true;
+    return this;
+  }
+
+  partial() {
+    this.opts.partial = true;
+    return this;
+  }
+
+  // IDL enum form, e.g. { construction: {} }
+  industry(industry: Record<string, object>) {
+    this.opts.industry = industry;
+    return this;
+  }
+
+  experiment(experiment: PublicKey) {
+    this.opts.experiment = experiment;
+    return this;
+  }
+
+  microTier(microTier: PublicKey) {
+    this.opts.microTier = microTier;
+    return this;
+  }
+
+  // Denominate in an approved secondary stablecoin instead of the primary mint
+  mint(mint: PublicKey) {
+    this.opts.mint = mint;
+    return this;
+  }
+
+  // Name the debtor wallet that may acknowledge the invoice
+  debtorWallet(wallet: PublicKey) {
+    this.opts.debtorWallet = wallet;
+    return this;
+  }
+
+  // Close funding at `timestamp` instead of the default a day before the due date
+  fundingDeadline(timestamp: number) {
+    this.opts.fundingDeadline = timestamp;
+    return this;
+  }
+
+  // List through a registered originator platform, promising it `feeBps` of the principal
+  originator(originator: PublicKey, feeBps: number) {
+    this.opts.origination = { originator, feeBps };
+    return this;
+  }
+
+  // Factor the invoice: investors advance `bps` of the face value and collect all of it
+  factored(bps: number) {
+    this.opts.advanceRateBps = bps;
+    return this;
+  }
+
+  // Present an oracle credit attestation, with the oracle's signature ahead of it
+  creditAttestation(oracle: Keypair, attestation: CreditAttestation) {
+    this.opts.attestation = { oracle, attestation };
+    return this;
+  }
+
+  // Pass the accounts the premium is prepaid through, for when the business pays it
+  prepaysPremium() {
+    this.opts.prepaysPremium = true;
+    return this;
+  }
+
+  // Have a platform wallet pay the rent while the business still signs
+  sponsoredBy(payer: Keypair) {
+    this.opts.payer = payer;
+    return this;
+  }
+
+  // Also anchor the listing proof, as the marketplace does before showing it
+  listed() {
+    this.opts.listed = true;
+    return this;
+  }
+
+  protected async run() {
+    const { program, globalState } = this.env;
+    const owner = this.business instanceof Keypair ? this.business : this.business.keypair;
+    const invoiceId = await this.env.nextInvoiceId(owner.publicKey);
+    const invoice = this.env.invoicePda(owner.publicKey, invoiceId);
+    const debtor = debtorId(this.opts.debtor);
+    const attested = this.opts.attestation;
+    const preInstructions = [];
+    if (attested) {
+      const { clusterId } = await program.account.globalState.fetch(globalState);
+      const message = creditAttestationMessage(program.programId, clusterId, owner.publicKey, attested.attestation);
+      preInstructions.push(Ed25519Program.createInstructionWithPrivateKey({ privateKey: attested.oracle.secretKey, message }));
+    }
+    await program.methods
+      .createInvoice(
+        new anchor.BN(this.opts.amount),
+        new anchor.BN(this.opts.dueAt ?? now() + this.opts.tenorDays * DAY),
+        debtorInfoHash(this.opts.debtor),
+        this.opts.debtorInfoUri,
+        this.opts.offramp,
+        this.opts.partial,
+        this.opts.industry as any,
+        attested?.attestation ?? null,
+        debtor,
+        this.opts.debtorWallet,
+        this.opts.fundingDeadline === null ? null : new anchor.BN(this.opts.fundingDeadline),
+        this.opts.origination,
+        this.opts.advanceRateBps
+      )
+      .accountsPartial({
+        invoice,
+        globalState,
+        businessOwner: owner.publicKey,
+        payer: (this.opts.payer ?? owner).publicKey,
+        debtor: this.env.debtorPda(debtor),
+        experiment: this.opts.experiment,
+        microTier: this.opts.microTier,
+        mint: this.opts.mint,
+        instructions: attested ? SYSVAR_INSTRUCTIONS_PUBKEY : null,
+        originatorStats: this.opts.origination ? this.env.originatorStatsPda(this.opts.origination.originator) : null,
+        ...(this.opts.prepaysPremium
+          ? {
+              businessTokenAccount: await this.env.usdcAccount(owner.publicKey),
+              insurancePoolAccount: this.env.insurancePool,
+              tokenProgram: TOKEN_PROGRAM_ID,
+            }
+          : { businessTokenAccount: null, insurancePoolAccount: null, tokenProgram: null }),
+      })
+      .preInstructions(preInstructions)
+      .signers(this.opts.payer ? [owner, this.opts.payer] : [owner])
+      .rpc();
+
+    if (this.opts.listed) {
+      await program.methods
+        .publishListingProof()
+        .accountsPartial({ invoice, businessOwner: owner.publicKey })
+        .signers([owner])
+        .rpc();
+    }
+    return { invoiceId, invoice };
+  }
+}
+
+export class FundBuilder extends Step<string> {
+  private investor?: Party;
+  private amount?: anchor.BN;
+  private maxPremium?: anchor.BN;
+  private riskScore?: number;
+  private dueDate?: anchor.BN;
+  private source?: PublicKey;
+  private inLamports = false;
+  private outboxPage?: PublicKey;
+
+  constructor(private readonly env: TestEnv, private readonly invoice: PublicKey) {
+    super();
+  }
+
+  by(investor: Party) {
+    this.investor = investor;
+    return this;
+  }
+
+  // Defaults to the whole face value
+  withAmount(amount: number) {
+    this.amount = new anchor.BN(amount);
+    return this;
+  }
+
+  // Defaults to the quoted premium
+  withMaxPremium(maxPremium: anchor.BN) {
+    this.maxPremium = maxPremium;
+    return this;
+  }
+
+  // Defaults to the current risk score
+  expectingRiskScore(riskScore: number) {
+    this.riskScore = riskScore;
+    return this;
+  }
+
+  // Defaults to the current due date
+  expectingDueDate(dueDate: anchor.BN) {
+    this.dueDate = dueDate;
+    return this;
+  }
+
+  // Defaults to the investor's associated token account
+  from(tokenAccount: PublicKey) {
+    this.source = tokenAccount;
+    return this;
+  }
+
+  // Sends an off-ramp invoice's principal to the outbox escrow, queueing its
+  // payout on `page`
+  throughOutbox(page: PublicKey) {
+    this.outboxPage = page;
+    return this;
+  }
+
+  // Pays in SOL through fund_invoice_with_sol, wrapping into the investor's wSOL account
+  inSol() {
+    this.inLamports = true;
+    return this;
+  }
+
+  protected async run() {
+    const { program, globalState } = this.env;
+    if (!this.investor) {
+      throw new Error("fund(invoice) needs .by(investor)");
+    }
+    const {
+      amount,
+      advanceRateBps,
+      advanceAmount,
+      insurancePremium,
+      riskScore,
+      dueDate,
+      businessOwner,
+      debtor,
+      mint,
+      originator,
+    } = await program.account.invoice.fetch(this.invoice);
+    // A factored invoice is funded with its advance, not its face value
+    const principal = advanceRateBps > 0 ? advanceAmount : amount;
+    const args = [
+      this.amount ?? principal,
+      this.maxPremium ?? insurancePremium,
+      this.riskScore ?? riskScore,
+      this.dueDate ?? dueDate,
+    ] as const;
+    const method = this.inLamports
+      ? program.methods.fundInvoiceWithSol(...args)
+      : program.methods.fundInvoice(args[0], false, args[1], args[2], args[3]);
+    return method
+      .accountsPartial({
+        invoice: this.invoice,
+        debtor,
+        globalState,
+        investor: this.investor.publicKey,
+        investorTokenAccount: this.source ?? (await this.env.tokenAccount(mint, this.investor.publicKey)),
+        businessTokenAccount: await this.env.tokenAccount(mint, businessOwner),
+        insurancePoolAccount: this.env.insurancePool,
+        experiment: null,
+        payoutProcessor: this.outboxPage ? this.env.pda([Buffer.from("payout_processor")]) : null,
+        outboxPage: this.outboxPage ?? null,
+        outboxEscrow: this.outboxPage ? this.env.pda([Buffer.from("outbox_escrow")]) : null,
+        investorBalance: null,
+        investorCustody: null,
+        originatorTokenAccount: originator ? await this.env.tokenAccount(mint, originator) : null,
+        originatorStats: originator ? this.env.originatorStatsPda(originator) : null,
+        investorWhitelist: await this.env.investorWhitelist(this.investor.publicKey),
+        pairLedger: this.env.pairLedgerPda(businessOwner, this.investor.publicKey),
+        portfolio: this.env.portfolioPda(this.investor.publicKey),
+        mint,
+      })
+      .signers([this.investor.keypair])
+      .rpc();
+  }
+}
+
+let shared: Promise<TestEnv> | undefined;
+
+export class TestEnv {
+  readonly globalState: PublicKey;
+  readonly insurancePool: PublicKey;
+  readonly insurancePoolAuthority: PublicKey;
+
+  private constructor(
+    readonly program: Program<InvoiceFinancing>,
+    readonly provider: anchor.AnchorProvider,
+    readonly usdcMint: PublicKey
+  ) {
+    this.globalState = this.pda([Buffer.from("global_state")]);
+    this.insurancePool = this.pda([Buffer.from("insurance_pool")]);
+    this.insurancePoolAuthority = this.pda([Buffer.from("insurance_pool_authority")]);
+  }
+
+  // One environment per cluster: the first caller creates the USDC mint, the
+  // global state and the insurance pool, everyone after shares them
+  static new(): Promise<TestEnv> {
+    if (!shared) {
+      shared = TestEnv.setUp();
+    }
+    return shared;
+  }
+
+  private static async setUp() {
+    const provider = anchor.AnchorProvider.env();
+    anchor.setProvider(provider);
+    const program = anchor.workspace.invoiceFinancing as Program<InvoiceFinancing>;
+    const authority = provider.wallet as anchor.Wallet;
+    const [globalState] = PublicKey.findProgramAddressSync([Buffer.from("global_state")], program.programId);
+
+    let usdcMint = (await program.account.globalState.fetchNullable(globalState))?.usdcMint;
+    if (!usdcMint) {
+      usdcMint = await createMint(provider.connection, authority.payer, authority.publicKey, null, 6);
+      await program.methods
+        .initialize()
+        .accountsPartial({ globalState, usdcMint, authority: authority.publicKey })
+        .rpc();
+    }
+
+    const env = new TestEnv(program, provider, usdcMint);
+    if (!(await provider.connection.getAccountInfo(env.insurancePool))) {
+      await program.methods
+        .initializeInsurancePool()
+        .accountsPartial({
+          globalState,
+          adminLog: await env.adminLog(),
+          usdcMint,
+          insurancePoolAccount: env.insurancePool,
+          insurancePoolAuthority: env.insurancePoolAuthority,
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .rpc();
+    }
+    return env;
+  }
+
+  get authority() {
+    return this.provider.wallet as anchor.Wallet;
+  }
+
+  pda(seeds: Buffer[]) {
+    return PublicKey.findProgramAddressSync(seeds, this.program.programId)[0];
+  }
+
+  // Invoice ids count up per business, starting at 1
+  async nextInvoiceId(owner: PublicKey) {
+    const profile = await this.program.account.businessProfile.fetchNullable(this.businessProfilePda(owner));
+    return profile ? profile.invoicesCreated.addn(1) : new anchor.BN(1);
+  }
+
+  invoicePda(owner: PublicKey, invoiceId: anchor.BN) {
+    return this.pda([Buffer.from("invoice"), owner.toBuffer(), invoiceId.toArrayLike(Buffer, "le", 8)]);
+  }
+
+  businessProfilePda(owner: PublicKey) {
+    return this.pda([Buffer.from("business_profile"), owner.toBuffer()]);
+  }
+
+  pairLedgerPda(business: PublicKey, investor: PublicKey) {
+    return this.pda([Buffer.from("pair_ledger"), business.toBuffer(), investor.toBuffer()]);
+  }
+
+  debtorPda(id: number[]) {
+    return this.pda([Buffer.from("debtor"), Buffer.from(id)]);
+  }
+
+  // The registry entry an invoice's exposure is booked against
+  async debtorOf(invoice: PublicKey) {
+    return (await this.program.account.invoice.fetch(invoice)).debtor;
+  }
+
+  originatorStatsPda(originator: PublicKey) {
+    return this.pda([Buffer.from("originator_stats"), originator.toBuffer()]);
+  }
+
+  investorStatsPda(investor: PublicKey) {
+    return this.pda([Buffer.from("investor_stats"), investor.toBuffer()]);
+  }
+
+  // Stats of the investor an invoice's funding exposure is booked against
+  async investorStatsOf(invoice: PublicKey) {
+    return this.investorStatsPda((await this.program.account.invoice.fetch(invoice)).investor);
+  }
+
+  portfolioPda(investor: PublicKey) {
+    return this.pda([Buffer.from("portfolio"), investor.toBuffer()]);
+  }
+
+  // The recorded investor's portfolio if the invoice's funding is booked in it, else null
+  async portfolioOf(invoice: PublicKey) {
+    const { investor, portfolioBooked } = await this.program.account.invoice.fetch(invoice);
+    return portfolioBooked ? this.portfolioPda(investor) : null;
+  }
+
+  investorWhitelistPda(investor: PublicKey) {
+    return this.pda([Buffer.from("investor"), investor.toBuffer()]);
+  }
+
+  blacklistPda(party: PublicKey) {
+    return this.pda([Buffer.from("blacklist"), party.toBuffer()]);
+  }
+
+  // The investor's whitelist entry if the authority ever approved them, else null
+  async investorWhitelist(investor: PublicKey) {
+    const entry = this.investorWhitelistPda(investor);
+    return (await this.provider.connection.getAccountInfo(entry)) ? entry : null;
+  }
+
+  invoiceVaultPda(invoice: PublicKey) {
+    return this.pda([Buffer.from("invoice_vault"), invoice.toBuffer()]);
+  }
+
+  // The position receipt minted at funding, and a holder's associated account for it
+  receiptMintPda(invoice: PublicKey) {
+    return this.pda([Buffer.from("position_receipt"), invoice.toBuffer()]);
+  }
+
+  receiptAccount(invoice: PublicKey, holder: PublicKey) {
+    return getAssociatedTokenAddressSync(this.receiptMintPda(invoice), holder);
+  }
+
+  async airdrop(to: PublicKey) {
+    await this.provider.connection.confirmTransaction(await this.provider.connection.requestAirdrop(to, 2e9));
+  }
+
+  // The holder's USDC associated token account, topped up by `amount`
+  usdcAccount(holder: PublicKey, amount = 0) {
+    return this.tokenAccount(this.usdcMint, holder, amount);
+  }
+
+  // The holder's associated account for a mint the fixtures' authority mints
+  async tokenAccount(mint: PublicKey, holder: PublicKey, amount = 0) {
+    const { connection } = this.provider;
+    const payer = this.authority.payer;
+    const account = (await getOrCreateAssociatedTokenAccount(connection, payer, mint, holder)).address;
+    if (amount > 0) {
+      await mintTo(connection, payer, mint, account, this.authority.publicKey, amount);
+    }
+    return account;
+  }
+
+  // What a token account holds, in base units
+  async tokenBalance(account: PublicKey) {
+    return Number((await getAccount(this.provider.connection, account)).amount);
+  }
+
+  async createParty(usdc = 0): Promise<Party> {
+    const keypair = Keypair.generate();
+    await this.airdrop(keypair.publicKey);
+    return { keypair, publicKey: keypair.publicKey, usdc: await this.usdcAccount(keypair.publicKey, usdc) };
+  }
+
+  createBusiness() {
+    return new BusinessBuilder(this);
+  }
+
+  createInvestor(usdc = 1_000_000_000) {
+    return this.createParty(usdc);
+  }
+
+  createInvoice(business: Business | Party | Keypair) {
+    return new InvoiceBuilder(this, business);
+  }
+
+  fund(invoice: PublicKey) {
+    return new FundBuilder(this, invoice);
+  }
+
+  async markDefaulted(invoice: PublicKey) {
+    const { businessOwner, investor, debtor } = await this.program.account.invoice.fetch(invoice);
+    return this.program.methods
+      .markDefaulted()
+      .accountsPartial({
+        invoice,
+        debtor,
+        investorStats: this.investorStatsPda(investor),
+        globalState: this.globalState,
+        experiment: null,
+        pairLedger: this.pairLedgerPda(businessOwner, investor),
+        portfolio: await this.portfolioOf(invoice),
+      })
+      .rpc();
+  }
+
+  // Report the debtor disputing the invoice, as its business or position holder
+  raiseDispute(invoice: PublicKey, party: Party, reasonHash = [...createHash("sha256").update("disputed").digest()]) {
+    return this.program.methods
+      .raiseDispute(reasonHash)
+      .accountsPartial({ invoice, globalState: this.globalState, party: party.publicKey, investorReceipt: null })
+      .signers([party.keypair])
+      .rpc();
+  }
+
+  // Settle a dispute as the authority, or as the arbiter when one signs
+  async resolveDispute(invoice: PublicKey, resolution: Record<string, object>, arbiter?: Keypair) {
+    const { businessOwner, investor, debtor } = await this.program.account.invoice.fetch(invoice);
+    const resolver = arbiter?.publicKey ?? this.authority.publicKey;
+    return this.program.methods
+      .resolveDispute(resolution as any)
+      .accountsPartial({
+        invoice,
+        globalState: this.globalState,
+        resolver,
+        payer: resolver,
+        businessProfile: this.businessProfilePda(businessOwner),
+        experiment: null,
+        pairLedger: this.pairLedgerPda(businessOwner, investor),
+        portfolio: await this.portfolioOf(invoice),
+        debtor,
+        investorStats: this.investorStatsPda(investor),
+      })
+      .signers(arbiter ? [arbiter] : [])
+      .rpc();
+  }
+
+  // Claim insurance on a defaulted invoice as the holder of its receipt
+  async claimInsurance(invoice: PublicKey, holder: Party) {
+    const { businessOwner, investor } = await this.program.account.invoice.fetch(invoice);
+    return this.program.methods
+      .claimInsurance()
+      .accountsPartial({
+        invoice,
+        globalState: this.globalState,
+        investor: holder.publicKey,
+        investorTokenAccount: holder.usdc,
+        insurancePoolAccount: this.insurancePool,
+        insurancePoolAuthority: this.insurancePoolAuthority,
+        fundingShare: null,
+        invoiceVault: this.invoiceVaultPda(invoice),
+        pairLedger: this.pairLedgerPda(businessOwner, investor),
+        portfolio: await this.portfolioOf(invoice),
+        receiptMint: this.receiptMintPda(invoice),
+        investorReceipt: this.receiptAccount(invoice, holder.publicKey),
+      })
+      .signers([holder.keypair])
+      .rpc();
+  }
+
+  // Pay down what a partial insurance payout left outstanding; anyone may send it
+  async claimRemainingInsurance(invoice: PublicKey) {
+    const { businessOwner, investor, mint, partialFunding } = await this.program.account.invoice.fetch(invoice);
+    return this.program.methods
+      .claimRemainingInsurance()
+      .accountsPartial({
+        invoice,
+        globalState: this.globalState,
+        investorTokenAccount: partialFunding ? null : await this.tokenAccount(mint, investor),
+        insurancePoolAccount: this.insurancePool,
+        insurancePoolAuthority: this.insurancePoolAuthority,
+        invoiceVault: partialFunding ? this.invoiceVaultPda(invoice) : null,
+        pairLedger: partialFunding ? null : this.pairLedgerPda(businessOwner, investor),
+        portfolio: await this.portfolioOf(invoice),
+      })
+      .rpc();
+  }
+
+  // Pay in a post-default recovery as the business, or as the authority on its behalf;
+  // pass the collections agency working the invoice, if any, to pay its fee
+  async settleRecovery(invoice: PublicKey, amount: number, business?: Party, agency?: PublicKey) {
+    const { businessOwner, investor, mint } = await this.program.account.invoice.fetch(invoice);
+    const settler = business?.publicKey ?? this.authority.publicKey;
+    return this.program.methods
+      .settleRecovery(new anchor.BN(amount))
+      .accountsPartial({
+        invoice,
+        globalState: this.globalState,
+        settler,
+        settlerTokenAccount: await this.tokenAccount(mint, settler),
+        insurancePoolAccount: this.insurancePool,
+        collectionsAssignment: agency ? this.pda([Buffer.from("collections"), invoice.toBuffer()]) : null,
+        collectionsAgency: agency ? this.pda([Buffer.from("collections_agency"), agency.toBuffer()]) : null,
+        agencyTokenAccount: agency ? await this.tokenAccount(mint, agency) : null,
+        investorTokenAccount: await this.tokenAccount(mint, investor),
+        invoiceVault: this.invoiceVaultPda(invoice),
+        businessTokenAccount: await this.tokenAccount(mint, businessOwner),
+        pairLedger: this.pairLedgerPda(businessOwner, investor),
+        portfolio: await this.portfolioOf(invoice),
+      })
+      .signers(business ? [business.keypair] : [])
+      .rpc();
+  }
+
+  // The debtor wallet named at listing confirms the invoice
+  acknowledge(invoice: PublicKey, debtorWallet: Keypair) {
+    return this.program.methods
+      .acknowledgeInvoice()
+      .accountsPartial({ invoice, globalState: this.globalState, debtorWallet: debtorWallet.publicKey })
+      .signers([debtorWallet])
+      .rpc();
+  }
+
+  // Release an escrowed funding to the business before its deadline
+  async acceptFunding(invoice: PublicKey, business: Party) {
+    const { investor, mint } = await this.program.account.invoice.fetch(invoice);
+    return this.program.methods
+      .acceptFunding()
+      .accountsPartial({
+        invoice,
+        businessOwner: business.publicKey,
+        globalState: this.globalState,
+        businessTokenAccount: await this.tokenAccount(mint, business.publicKey),
+        invoiceVault: this.invoiceVaultPda(invoice),
+        insurancePoolAccount: this.insurancePool,
+        receiptMint: this.receiptMintPda(invoice),
+        investorReceipt: this.receiptAccount(invoice, investor),
+        pairLedger: this.pairLedgerPda(business.publicKey, investor),
+        portfolio: this.portfolioPda(investor),
+      })
+      .signers([business.keypair])
+      .rpc();
+  }
+
+  // Take back a wallet funding the business let expire
+  async reclaimEscrow(invoice: PublicKey, investor: Party) {
+    const { businessOwner, debtor, mint } = await this.program.account.invoice.fetch(invoice);
+    return this.program.methods
+      .reclaimEscrow()
+      .accountsPartial({
+        invoice,
+        investor: investor.publicKey,
+        globalState: this.globalState,
+        invoiceVault: this.invoiceVaultPda(invoice),
+        investorTokenAccount: await this.tokenAccount(mint, investor.publicKey),
+        investorBalance: null,
+        investorCustody: null,
+        investorStats: this.investorStatsPda(investor.publicKey),
+        businessProfile: this.businessProfilePda(businessOwner),
+        debtor,
+        treasury: null,
+        treasuryAllowlist: null,
+        treasuryAuthority: null,
+      })
+      .signers([investor.keypair])
+      .rpc();
+  }
+
+  // An investment pool, the PDA that holds its positions, and its share mint and vault
+  investmentPoolPda(manager: PublicKey) {
+    return this.pda([Buffer.from("investment_pool"), manager.toBuffer()]);
+  }
+
+  poolAuthorityPda(pool: PublicKey) {
+    return this.pda([Buffer.from("pool_authority"), pool.toBuffer()]);
+  }
+
+  poolSharesPda(pool: PublicKey) {
+    return this.pda([Buffer.from("pool_shares"), pool.toBuffer()]);
+  }
+
+  poolVault(pool: PublicKey) {
+    return getAssociatedTokenAddressSync(this.usdcMint, this.poolAuthorityPda(pool), true);
+  }
+
+  poolPositionPda(pool: PublicKey, invoice: PublicKey) {
+    return this.pda([Buffer.from("pool_position"), pool.toBuffer(), invoice.toBuffer()]);
+  }
+
+  async createInvestmentPool(manager: Party, maxRiskScore = 100) {
+    const pool = this.investmentPoolPda(manager.publicKey);
+    const poolAuthority = this.poolAuthorityPda(pool);
+    await this.program.methods
+      .createInvestmentPool(maxRiskScore)
+      .accountsPartial({
+        pool,
+        poolAuthority,
+        shareMint: this.poolSharesPda(pool),
+        vault: this.poolVault(pool),
+        usdcMint: this.usdcMint,
+        globalState: this.globalState,
+        manager: manager.publicKey,
+      })
+      .signers([manager.keypair])
+      .rpc();
+    // Float for the rent of what funding opens; pool_fund_invoice tops it back up
+    await this.airdrop(poolAuthority);
+    return pool;
+  }
+
+  async depositToPool(pool: PublicKey, depositor: Party, amount: number) {
+    return this.program.methods
+      .depositToPool(new anchor.BN(amount))
+      .accountsPartial({
+        pool,
+        poolAuthority: this.poolAuthorityPda(pool),
+        shareMint: this.poolSharesPda(pool),
+        vault: this.poolVault(pool),
+        depositor: depositor.publicKey,
+        depositorTokenAccount: depositor.usdc,
+        depositorShares: getAssociatedTokenAddressSync(this.poolSharesPda(pool), depositor.publicKey),
+      })
+      .signers([depositor.keypair])
+      .rpc();
+  }
+
+  async redeemFromPool(pool: PublicKey, holder: Party, shares: number) {
+    return this.program.methods
+      .redeemFromPool(new anchor.BN(shares))
+      .accountsPartial({
+        pool,
+        poolAuthority: this.poolAuthorityPda(pool),
+        shareMint: this.poolSharesPda(pool),
+        vault: this.poolVault(pool),
+        holder: holder.publicKey,
+        holderShares: getAssociatedTokenAddressSync(this.poolSharesPda(pool), holder.publicKey),
+        holderTokenAccount: holder.usdc,
+      })
+      .signers([holder.keypair])
+      .rpc();
+  }
+
+  // The pool re-enters the program as its investor: build the inner instruction as
+  // an investor would send it and pass its accounts on, signer flags cleared
+  private asRemainingAccounts(instruction: anchor.web3.TransactionInstruction) {
+    return instruction.keys.map((key) => ({ ...key, isSigner: false }));
+  }
+
+  async poolFundInvoice(pool: PublicKey, manager: Party, invoice: PublicKey) {
+    const poolAuthority = this.poolAuthorityPda(pool);
+    const { amount, insurancePremium, riskScore, dueDate, businessOwner, debtor, mint } =
+      await this.program.account.invoice.fetch(invoice);
+    const inner = await this.program.methods
+      .fundInvoice(amount, false, insurancePremium, riskScore, dueDate)
+      .accountsPartial({
+        invoice,
+        debtor,
+        globalState: this.globalState,
+        investor: poolAuthority,
+        investorTokenAccount: this.poolVault(pool),
+        businessTokenAccount: await this.tokenAccount(mint, businessOwner),
+        insurancePoolAccount: this.insurancePool,
+        experiment: null,
+        payoutProcessor: null,
+        outboxPage: null,
+        outboxEscrow: null,
+        investorBalance: null,
+        investorCustody: null,
+        originatorTokenAccount: null,
+        originatorStats: null,
+        investorWhitelist: await this.investorWhitelist(poolAuthority),
+        pairLedger: this.pairLedgerPda(businessOwner, poolAuthority),
+        portfolio: this.portfolioPda(poolAuthority),
+        mint,
+      })
+      .instruction();
+    return this.program.methods
+      .poolFundInvoice(insurancePremium)
+      .accountsPartial({
+        pool,
+        poolAuthority,
+        invoice,
+        position: this.poolPositionPda(pool, invoice),
+        manager: manager.publicKey,
+        invoiceFinancingProgram: this.program.programId,
+      })
+      .remainingAccounts(this.asRemainingAccounts(inner))
+      .signers([manager.keypair])
+      .rpc();
+  }
+
+  // Redeem, claim or reclaim, whichever the invoice's status calls for
+  async settlePoolPosition(pool: PublicKey, invoice: PublicKey) {
+    const poolAuthority = this.poolAuthorityPda(pool);
+    const vault = this.poolVault(pool);
+    const { status, businessOwner, debtor } = await this.program.account.invoice.fetch(invoice);
+    const receiptMint = this.receiptMintPda(invoice);
+    const receipt = getAssociatedTokenAddressSync(receiptMint, poolAuthority, true);
+    let inner: anchor.web3.TransactionInstruction;
+    if ("defaulted" in status) {
+      inner = await this.program.methods
+        .claimInsurance()
+        .accountsPartial({
+          invoice,
+          globalState: this.globalState,
+          investor: poolAuthority,
+          investorTokenAccount: vault,
+          insurancePoolAccount: this.insurancePool,
+          insurancePoolAuthority: this.insurancePoolAuthority,
+          fundingShare: null,
+          invoiceVault: this.invoiceVaultPda(invoice),
+          pairLedger: this.pairLedgerPda(businessOwner, poolAuthority),
+          portfolio: await this.portfolioOf(invoice),
+          receiptMint,
+          investorReceipt: receipt,
+        })
+        .instruction();
+    } else if ("fundedPendingAcceptance" in status) {
+      inner = await this.program.methods
+        .reclaimEscrow()
+        .accountsPartial({
+          invoice,
+          investor: poolAuthority,
+          globalState: this.globalState,
+          invoiceVault: this.invoiceVaultPda(invoice),
+          investorTokenAccount: vault,
+          investorBalance: null,
+          investorCustody: null,
+          investorStats: this.investorStatsPda(poolAuthority),
+          businessProfile: this.businessProfilePda(businessOwner),
+          debtor,
+          treasury: null,
+          treasuryAllowlist: null,
+          treasuryAuthority: null,
+        })
+        .instruction();
+    } else {
+      inner = await this.program.methods
+        .redeemReceipt()
+        .accountsPartial({
+          invoice,
+          receiptMint,
+          holderReceipt: receipt,
+          holder: poolAuthority,
+          invoiceVault: this.invoiceVaultPda(invoice),
+          holderTokenAccount: vault,
+        })
+        .instruction();
+    }
+    return this.program.methods
+      .settlePoolPosition()
+      .accountsPartial({
+        pool,
+        poolAuthority,
+        vault,
+        invoice,
+        position: this.poolPositionPda(pool, invoice),
+        manager: (await this.program.account.investmentPool.fetch(pool)).manager,
+        invoiceFinancingProgram: this.program.programId,
+      })
+      .remainingAccounts(this.asRemainingAccounts(inner))
+      .rpc();
+  }
+
+  autoInvestMandatePda(investor: PublicKey) {
+    return this.pda([Buffer.from("auto_invest_mandate"), investor.toBuffer()]);
+  }
+
+  // Rules default to anything up to 50 USDC due within a year
+  async createAutoInvestMandate(investor: Party, allowance: number, rules: Partial<MandateRules> = {}) {
+    return this.program.methods
+      .createAutoInvestMandate(
+        {
+          maxRiskScore: 100,
+          minAmount: new anchor.BN(1),
+          maxAmount: new anchor.BN(50_000_000),
+          maxTermDays: 365,
+          exposureCap: new anchor.BN(1_000_000_000),
+          ...rules,
+        },
+        new anchor.BN(allowance)
+      )
+      .accountsPartial({
+        mandate: this.autoInvestMandatePda(investor.publicKey),
+        investor: investor.publicKey,
+        investorTokenAccount: investor.usdc,
+        globalState: this.globalState,
+      })
+      .signers([investor.keypair])
+      .rpc();
+  }
+
+  async executeAutoInvest(invoice: PublicKey, investor: PublicKey, cranker: Party) {
+    const { businessOwner, debtor, mint } = await this.program.account.invoice.fetch(invoice);
+    return this.program.methods
+      .executeAutoInvest()
+      .accountsPartial({
+        mandate: this.autoInvestMandatePda(investor),
+        investor,
+        investorTokenAccount: await this.usdcAccount(investor),
+        invoice,
+        globalState: this.globalState,
+        businessTokenAccount: await this.tokenAccount(mint, businessOwner),
+        insurancePoolAccount: this.insurancePool,
+        experiment: null,
+        investorWhitelist: await this.investorWhitelist(investor),
+        pairLedger: this.pairLedgerPda(businessOwner, investor),
+        mint,
+        debtor,
+        cranker: cranker.publicKey,
+      })
+      .signers([cranker.keypair])
+      .rpc();
+  }
+
+  // Repays each invoice in full in one transaction: every invoice with the
+  // account its repayment goes to, then the debtor entries, investor stats, pair
+  // ledgers and portfolios they touch
+  async repayInvoicesBatch(business: Party, invoices: PublicKey[], maxTotal: number) {
+    const items: PublicKey[] = [];
+    const books = new Map<string, PublicKey>();
+    const { connection } = this.provider;
+    for (const invoice of invoices) {
+      const { businessOwner, investor, debtor, mint, partialFunding, receiptMint, receiptRedeemed } =
+        await this.program.account.invoice.fetch(invoice);
+      const toVault = partialFunding || (receiptMint !== null && !receiptRedeemed);
+      items.push(invoice, toVault ? this.invoiceVaultPda(invoice) : await this.tokenAccount(mint, investor));
+      if (!debtor.equals(PublicKey.default)) {
+        books.set(debtor.toBase58(), debtor);
+      }
+      const ledgers = [
+        this.investorStatsPda(investor),
+        this.pairLedgerPda(businessOwner, investor),
+        this.portfolioPda(investor),
+      ];
+      for (const book of ledgers) {
+        if (await connection.getAccountInfo(book)) {
+          books.set(book.toBase58(), book);
+        }
+      }
+    }
+    return this.program.methods
+      .repayInvoicesBatch(invoices.length, new anchor.BN(maxTotal))
+      .accountsPartial({
+        businessOwner: business.publicKey,
+        globalState: this.globalState,
+        businessTokenAccount: business.usdc,
+      })
+      .remainingAccounts(
+        [...items, ...books.values()].map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+      )
+      .preInstructions([ComputeBudgetProgram.setComputeUnitLimit({ units: 1_400_000 })])
+      .signers([business.keypair])
+      .rpc();
+  }
+
+  // Events the program emitted through its event authority in a transaction:
+  // InvoiceCreated, InvoiceFunded, InvoiceRepaid and InsuranceClaimed go out as
+  // the data of a self-CPI rather than in the logs
+  async cpiEvents(signature: string) {
+    const { connection } = this.provider;
+    await connection.confirmTransaction(signature, "confirmed");
+    const tx = await connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const keys = tx.transaction.message.getAccountKeys({ accountKeysFromLookups: tx.meta.loadedAddresses });
+    return tx.meta.innerInstructions
+      .flatMap(({ instructions }) => instructions)
+      .filter(({ programIdIndex }) => keys.get(programIdIndex).equals(this.program.programId))
+      .map(({ data }) => anchor.utils.bytes.bs58.decode(data))
+      .filter((data) => data.subarray(0, 8).equals(EVENT_IX_TAG))
+      .map((data) => this.program.coder.events.decode(anchor.utils.bytes.base64.encode(data.subarray(8))));
+  }
+
+  // Retire a listing nobody funded by its deadline; anyone may send it
+  expireInvoice(invoice: PublicKey) {
+    return this.program.methods.expireInvoice().accountsPartial({ invoice }).rpc();
+  }
+
+  registerOriginator(originator: Party) {
+    return this.program.methods
+      .registerOriginator()
+      .accountsPartial({ originator: originator.publicKey })
+      .signers([originator.keypair])
+      .rpc();
+  }
+
+  depositInsurance(lp: Party, amount: number) {
+    return this.program.methods
+      .depositInsuranceLiquidity(new anchor.BN(amount))
+      .accountsPartial({
+        globalState: this.globalState,
+        provider: lp.publicKey,
+        providerTokenAccount: lp.usdc,
+        insurancePoolAccount: this.insurancePool,
+      })
+      .signers([lp.keypair])
+      .rpc();
+  }
+
+  // Wait until the invoice reaches `phase`; see MAX_WARP_SECONDS
+  async warpTo(invoice: PublicKey, phase: Phase) {
+    const { dueDate } = await this.program.account.invoice.fetch(invoice);
+    const { config } = await this.program.account.globalState.fetch(this.globalState);
+    const target = dueDate.toNumber() + (phase === "defaultable" ? config.gracePeriodDays * DAY : 0) + 1;
+    const wait = target - now();
+    if (wait > MAX_WARP_SECONDS) {
+      throw new Error(`warpTo(${phase}) is ${wait}s away; list the invoice with dueInSeconds`);
+    }
+    if (wait > 0) {
+      await sleep(wait * 1000);
+    }
+  }
+
+  async updateConfig(changes: Record<string, unknown>) {
+    const { config } = await this.program.account.globalState.fetch(this.globalState);
+    await this.program.methods
+      .updateConfig({ ...config, ...changes } as typeof config)
+      .accountsPartial({
+        globalState: this.globalState,
+        adminLog: await this.adminLog(),
+        paramHistory: await this.paramHistory(),
+        authority: this.authority.publicKey,
+      })
+      .rpc();
+    return config;
+  }
+
+  // Run `body` under changed config, restoring the previous config afterwards
+  async withConfig<T>(changes: Record<string, unknown>, body: () => Promise<T>) {
+    const previous = await this.updateConfig(changes);
+    try {
+      return await body();
+    } finally {
+      await this.updateConfig(previous);
+    }
+  }
+
+  adminLogPage(page: number) {
+    const buf = Buffer.alloc(4);
+    buf.writeUInt32LE(page);
+    return this.pda([Buffer.from("admin_log"), buf]);
+  }
+
+  // Admin log page the next protocol-level admin action lands in, opened on demand
+  async adminLog() {
+    const state = await this.program.account.globalState.fetch(this.globalState);
+    const page = Math.floor(state.adminActionCount.toNumber() / ADMIN_LOG_PAGE_SIZE);
+    const pda = this.adminLogPage(page);
+    if (!(await this.provider.connection.getAccountInfo(pda))) {
+      await this.program.methods
+        .openAdminLogPage(page)
+        .accountsPartial({ adminLog: pda, globalState: this.globalState, payer: this.authority.publicKey })
+        .rpc();
+    }
+    return pda;
+  }
+
+  paramHistoryPage(page: number) {
+    const buf = Buffer.alloc(4);
+    buf.writeUInt32LE(page);
+    return this.pda([Buffer.from("param_history"), buf]);
+  }
+
+  // Param history page the next governed parameter change lands in, opened on demand
+  async paramHistory() {
+    const state = await this.program.account.globalState.fetch(this.globalState);
+    const page = Math.floor(state.paramVersion / PARAM_HISTORY_PAGE_SIZE);
+    const pda = this.paramHistoryPage(page);
+    if (!(await this.provider.connection.getAccountInfo(pda))) {
+      await this.program.methods
+        .openParamHistoryPage(page)
+        .accountsPartial({ paramHistory: pda, globalState: this.globalState, payer: this.authority.publicKey })
+        .rpc();
+    }
+    return pda;
+  }
+}
+
+export * from "./presets";
@@ -0,0 +1,51 @@
+import type { Business, CreatedInvoice, Party, TestEnv } from "./index";
+
+// Canned books for scenario and load tests. Each preset adds to whatever the
+// shared environment already holds and returns only what it created.
+
+export interface Book {
+  businesses: Business[];
+  investors: Party[];
+  invoices: CreatedInvoice[];
+}
+
+// `count` invoices from different businesses, all funded and performing, with
+// LP liquidity behind the insurance pool
+export const healthyBook = async (env: TestEnv, count = 3): Promise<Book> => {
+  const lp = await env.createInvestor();
+  await env.depositInsurance(lp, 500_000_000);
+
+  const book: Book = { businesses: [], investors: [lp], invoices: [] };
+  for (let i = 0; i < count; i++) {
+    const business = await env.createBusiness();
+    const investor = await env.createInvestor();
+    const invoice = await env.createInvoice(business).amount(50_000_000 + i * 10_000_000).listed();
+    await env.fund(invoice.invoice).by(investor);
+    book.businesses.push(business);
+    book.investors.push(investor);
+    book.invoices.push(invoice);
+  }
+  return book;
+};
+
+// `count` funded invoices that all fall due together and are marked defaulted,
+// with the grace period lifted for the duration
+export const defaultWave = async (env: TestEnv, count = 3): Promise<Book> => {
+  const book: Book = { businesses: [], investors: [], invoices: [] };
+  await env.withConfig({ gracePeriodDays: 0 }, async () => {
+    for (let i = 0; i < count; i++) {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const invoice = await env.createInvoice(business).dueInSeconds(10);
+      await env.fund(invoice.invoice).by(investor);
+      book.businesses.push(business);
+      book.investors.push(investor);
+      book.invoices.push(invoice);
+    }
+    for (const { invoice } of book.invoices) {
+      await env.warpTo(invoice, "defaultable");
+      await env.markDefaulted(invoice);
+    }
+  });
+  return book;
+};
@@ -1,16 +1,5058 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
+import {
+  NATIVE_MINT,
+  TOKEN_PROGRAM_ID,
+  approve,
+  createAccount,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  transfer,
+} from "@solana/spl-token";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
 import { InvoiceFinancing } from "../target/types/invoice_financing";
+import {
+  ADMIN_LOG_PAGE_SIZE,
+  Business,
+  DAY,
+  PARAM_HISTORY_PAGE_SIZE,
+  Party,
+  TestEnv,
+  debtorId,
+  debtorInfoHash,
+  expectError,
+  now,
+  sleep,
+} from "./fixtures";
 
 describe("invoice-financing", () => {
   // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.AnchorProvider.env());
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
 
   const program = anchor.workspace.invoiceFinancing as Program<InvoiceFinancing>;
+  const authority = provider.wallet as anchor.Wallet;
+
+  const [globalState] = PublicKey.findProgramAddressSync(
+    [Buffer.from("global_state")],
+    program.programId
+  );
+  const [insurancePool] = PublicKey.findProgramAddressSync(
+    [Buffer.from("insurance_pool")],
+    program.programId
+  );
+  const [insurancePoolAuthority] = PublicKey.findProgramAddressSync(
+    [Buffer.from("insurance_pool_authority")],
+    program.programId
+  );
+
+  let env: TestEnv;
+  let usdcMint: PublicKey;
+
+  const airdrop = (to: PublicKey) => env.airdrop(to);
+  const invoicePda = (owner: PublicKey, invoiceId: anchor.BN) => env.invoicePda(owner, invoiceId);
+  const pairLedger = (business: PublicKey, investor: PublicKey) => env.pairLedgerPda(business, investor);
+  const portfolio = (investor: PublicKey) => env.portfolioPda(investor);
+  const invoiceVault = (invoice: PublicKey) => env.invoiceVaultPda(invoice);
+
+  const createInvoice = (
+    owner: Keypair,
+    opts: {
+      amount?: number;
+      dueInDays?: number;
+      dueAt?: number;
+      experiment?: PublicKey;
+      offramp?: boolean;
+      partial?: boolean;
+      microTier?: PublicKey;
+    } = {}
+  ) => {
+    const builder = env.createInvoice(owner).amount(opts.amount ?? 100_000_000);
+    if (opts.dueAt !== undefined) builder.dueAt(opts.dueAt);
+    if (opts.dueInDays !== undefined) builder.tenorDays(opts.dueInDays);
+    if (opts.experiment) builder.experiment(opts.experiment);
+    if (opts.microTier) builder.microTier(opts.microTier);
+    if (opts.offramp) builder.offramp();
+    if (opts.partial) builder.partial();
+    return builder;
+  };
+
+  const adminLogPage = (page: number) => env.adminLogPage(page);
+  const adminLog = () => env.adminLog();
+  const paramHistoryPage = (page: number) => env.paramHistoryPage(page);
+  const paramHistory = () => env.paramHistory();
+
+  before(async () => {
+    env = await TestEnv.new();
+    usdcMint = env.usdcMint;
+  });
 
   it("Is initialized!", async () => {
-    // Add your test here.
-    const tx = await program.methods.initialize().rpc();
-    console.log("Your transaction signature", tx);
+    const state = await program.account.globalState.fetch(globalState);
+    assert.ok(state.authority.equals(authority.publicKey));
+    assert.ok(state.usdcMint.equals(usdcMint));
+  });
+
+  it("creates the insurance pool under its own authority PDA", async () => {
+    const pool = await getAccount(provider.connection, insurancePool);
+    assert.ok(pool.owner.equals(insurancePoolAuthority));
+    assert.ok(pool.mint.equals(usdcMint));
+
+    const state = await program.account.globalState.fetch(globalState);
+    const [, poolBump] = PublicKey.findProgramAddressSync([Buffer.from("insurance_pool")], program.programId);
+    const [, authorityBump] = PublicKey.findProgramAddressSync(
+      [Buffer.from("insurance_pool_authority")],
+      program.programId
+    );
+    assert.equal(state.insurancePoolBump, poolBump);
+    assert.equal(state.insurancePoolAuthorityBump, authorityBump);
+  });
+
+  it("publishes the seeds and sizes clients derive accounts with", async () => {
+    const constant = (name: string) => program.idl.constants.find((c) => c.name === name).value;
+    const seed = (name: string) => Buffer.from(JSON.parse(constant(name)));
+    const derive = (...seeds: Buffer[]) => PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+
+    assert.ok(derive(seed("GLOBAL_STATE_SEED")).equals(globalState));
+    assert.ok(derive(seed("INSURANCE_POOL_SEED")).equals(insurancePool));
+    assert.ok(derive(seed("INSURANCE_POOL_AUTHORITY_SEED")).equals(insurancePoolAuthority));
+
+    const business = await env.createBusiness();
+    const { invoiceId, invoice } = await env.createInvoice(business);
+    const id = Buffer.alloc(8);
+    id.writeBigUInt64LE(BigInt(invoiceId.toString()));
+    assert.ok(derive(seed("INVOICE_SEED"), business.publicKey.toBuffer(), id).equals(invoice));
+    const info = await provider.connection.getAccountInfo(invoice);
+    assert.equal(info.data.length, Number(constant("INVOICE_SIZE")));
+    assert.equal(Number(constant("DEFAULT_MAX_INVOICE_AMOUNT")), 10_000_000_000);
+  });
+
+  describe("destination allowlist", () => {
+    const treasury = { treasury: {} };
+    const [allowlist] = PublicKey.findProgramAddressSync(
+      [Buffer.from("allowlist"), Buffer.from([0])],
+      program.programId
+    );
+    const name = (label: string) => Array.from(Buffer.alloc(32, label));
+
+    it("registers a destination that only activates after the timelock", async () => {
+      const destination = await createAccount(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        Keypair.generate().publicKey
+      );
+
+      await program.methods
+        .registerDestination(treasury, name("ops"))
+        .accountsPartial({
+          allowlist,
+          globalState,
+          adminLog: await adminLog(),
+          destination,
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .rpc();
+
+      const list = await program.account.destinationAllowlist.fetch(allowlist);
+      const entry = list.destinations.find((d) => d.tokenAccount.equals(destination));
+      assert.ok(entry);
+      assert.isAbove(entry.activeAt.toNumber(), Math.floor(Date.now() / 1000));
+    });
+
+    it("rejects registration by anyone but the authority", async () => {
+      const intruder = Keypair.generate();
+      await airdrop(intruder.publicKey);
+      const destination = await createAccount(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        intruder.publicKey
+      );
+
+      await expectError(
+        program.methods
+          .registerDestination(treasury, name("evil"))
+          .accountsPartial({
+            allowlist,
+            globalState,
+            adminLog: await adminLog(),
+            destination,
+            authority: intruder.publicKey,
+            payer: intruder.publicKey,
+          })
+          .signers([intruder])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+
+    it("rejects removing a destination that was never registered", async () => {
+      await expectError(
+        program.methods
+          .requestDestinationRemoval(treasury, Keypair.generate().publicKey)
+          .accountsPartial({ allowlist, globalState, adminLog: await adminLog(), authority: authority.publicKey })
+          .rpc(),
+        "DestinationNotFound"
+      );
+    });
+
+    it("emergency freeze clears every destination instantly", async () => {
+      await program.methods
+        .freezeDestinationAllowlist(treasury)
+        .accountsPartial({ allowlist, globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+      const list = await program.account.destinationAllowlist.fetch(allowlist);
+      assert.lengthOf(list.destinations, 0);
+      assert.isNotNull(list.frozenAt);
+    });
+  });
+
+  describe("pricing experiments", () => {
+    const experimentPda = (id: number) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("experiment"), new anchor.BN(id).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const createExperiment = async (id: number, startsAt: number) =>
+      program.methods
+        .createExperiment(
+          new anchor.BN(id),
+          15,
+          2_000,
+          new anchor.BN(startsAt),
+          new anchor.BN(startsAt + 30 * DAY),
+          5_000
+        )
+        .accountsPartial({
+          experiment: experimentPda(id),
+          globalState,
+          adminLog: await adminLog(),
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .rpc();
+
+    it("rejects an experiment that starts inside the timelock", async () => {
+      await expectError(createExperiment(1, now() + 60), "TimelockNotElapsed");
+    });
+
+    it("schedules an experiment with empty per-arm results", async () => {
+      await createExperiment(2, now() + 2 * DAY);
+
+      const results = await program.methods
+        .getExperimentResults()
+        .accountsPartial({ experiment: experimentPda(2) })
+        .view();
+      assert.equal(results.trafficBps, 2_000);
+      assert.equal(results.control.assigned.toNumber(), 0);
+      assert.equal(results.treatment.assigned.toNumber(), 0);
+    });
+
+    it("leaves invoices unassigned before the experiment starts", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner, { experiment: experimentPda(2) });
+
+      const account = await program.account.invoice.fetch(invoice);
+      assert.isNull(account.experiment);
+    });
+
+    it("records the scheduled experiment that listings in its window must pass", async () => {
+      const state = await program.account.globalState.fetch(globalState);
+      assert.ok(state.pricingExperiment.equals(experimentPda(2)));
+      assert.equal(
+        state.pricingExperimentEndsAt.toNumber() - state.pricingExperimentStartsAt.toNumber(),
+        30 * DAY
+      );
+    });
+
+    it("rejects an experiment that overlaps the scheduled one", async () => {
+      await expectError(createExperiment(3, now() + 3 * DAY), "ExperimentOverlap");
+    });
+  });
+
+  describe("payout outbox", () => {
+    const processor = Keypair.generate();
+    const pda = (seed: string, ...extra: Buffer[]) =>
+      PublicKey.findProgramAddressSync([Buffer.from(seed), ...extra], program.programId)[0];
+    const payoutProcessor = pda("payout_processor");
+    const outboxEscrow = pda("outbox_escrow");
+    const outboxAuthority = pda("outbox_authority");
+    const custodyAllowlist = pda("allowlist", Buffer.from([3]));
+    const page = (n: number) => {
+      const buf = Buffer.alloc(4);
+      buf.writeUInt32LE(n);
+      return pda("outbox", buf);
+    };
+    // Short enough for the lifecycle tests to wait out on a localnet
+    const ACK_TIMEOUT = 3;
+
+    const newCustody = () =>
+      createAccount(provider.connection, authority.payer, usdcMint, processor.publicKey, Keypair.generate());
+    const registerCustody = async (custody: PublicKey) =>
+      program.methods
+        .registerDestination({ payoutCustody: {} }, Array.from(Buffer.alloc(32, "processor custody")))
+        .accountsPartial({
+          allowlist: custodyAllowlist,
+          globalState,
+          adminLog: await adminLog(),
+          destination: custody,
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .rpc();
+    const configure = async (custody: PublicKey) =>
+      program.methods
+        .configurePayoutProcessor(processor.publicKey, new anchor.BN(ACK_TIMEOUT))
+        .accountsPartial({
+          payoutProcessor,
+          outboxEscrow,
+          outboxAuthority,
+          custody,
+          allowlist: custodyAllowlist,
+          usdcMint,
+          globalState,
+          adminLog: await adminLog(),
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .rpc();
+    const applyCustody = async () =>
+      program.methods
+        .applyPayoutCustody()
+        .accountsPartial({
+          payoutProcessor,
+          allowlist: custodyAllowlist,
+          globalState,
+          adminLog: await adminLog(),
+          authority: authority.publicKey,
+        })
+        .rpc();
+
+    it("registers the processor with a custody account from the allowlist", async () => {
+      const custody = await newCustody();
+      await registerCustody(custody);
+      await configure(custody);
+
+      const state = await program.account.payoutProcessor.fetch(payoutProcessor);
+      assert.ok(state.processor.equals(processor.publicKey));
+      assert.ok(state.custody.equals(custody));
+      assert.ok(state.escrow.equals(outboxEscrow));
+      assert.equal(state.totalEntries.toNumber(), 0);
+      assert.isNull(state.pendingCustody);
+    });
+
+    it("refuses a custody account that is not on the allowlist", async () => {
+      await expectError(configure(await newCustody()), "DestinationNotAllowlisted");
+    });
+
+    it("puts a change of custody behind the timelock", async () => {
+      const { custody } = await program.account.payoutProcessor.fetch(payoutProcessor);
+      const replacement = await newCustody();
+      await registerCustody(replacement);
+      await configure(replacement);
+
+      let state = await program.account.payoutProcessor.fetch(payoutProcessor);
+      assert.ok(state.custody.equals(custody));
+      assert.ok(state.pendingCustody.equals(replacement));
+      assert.isAbove(state.pendingCustodyAt.toNumber(), now() + DAY);
+      await expectError(applyCustody(), "TimelockNotElapsed");
+
+      // Naming the current custody again drops the scheduled change
+      await configure(custody);
+      state = await program.account.payoutProcessor.fetch(payoutProcessor);
+      assert.ok(state.custody.equals(custody));
+      assert.isNull(state.pendingCustody);
+      await expectError(applyCustody(), "NoPendingChange");
+    });
+
+    it("only opens the page the next entry will land in", async () => {
+      await expectError(
+        program.methods
+          .openOutboxPage(1)
+          .accountsPartial({ outboxPage: page(1), payoutProcessor, payer: authority.publicKey })
+          .rpc(),
+        "OutboxPageMismatch"
+      );
+
+      await program.methods
+        .openOutboxPage(0)
+        .accountsPartial({ outboxPage: page(0), payoutProcessor, payer: authority.publicKey })
+        .rpc();
+      const outbox = await program.account.outboxPage.fetch(page(0));
+      assert.lengthOf(outbox.entries, 0);
+    });
+
+    it("records the off-ramp request on the invoice", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner, { offramp: true });
+
+      const account = await program.account.invoice.fetch(invoice);
+      assert.isTrue(account.offrampRequested);
+    });
+
+    it("rejects acks for entries that do not exist", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner, { offramp: true });
+
+      await expectError(
+        program.methods
+          .ackOutboxEntry(new anchor.BN(0), Array(32).fill(1))
+          .accountsPartial({
+            payoutProcessor,
+            outboxPage: page(0),
+            invoice,
+            outboxEscrow,
+            custody: (await program.account.payoutProcessor.fetch(payoutProcessor)).custody,
+            allowlist: custodyAllowlist,
+            outboxAuthority,
+            processor: processor.publicKey,
+          })
+          .signers([processor])
+          .rpc(),
+        "OutboxEntryNotFound"
+      );
+    });
+
+    it("only releases from the registered escrow", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner, { offramp: true });
+      // Owned by the escrow authority, which anyone can arrange, but not the escrow
+      const lookalike = await createAccount(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        outboxAuthority,
+        Keypair.generate()
+      );
+
+      await expectError(
+        program.methods
+          .ackOutboxEntry(new anchor.BN(0), Array(32).fill(1))
+          .accountsPartial({
+            payoutProcessor,
+            outboxPage: page(0),
+            invoice,
+            outboxEscrow: lookalike,
+            custody: (await program.account.payoutProcessor.fetch(payoutProcessor)).custody,
+            allowlist: custodyAllowlist,
+            outboxAuthority,
+            processor: processor.publicKey,
+          })
+          .signers([processor])
+          .rpc(),
+        "InvalidOutboxEscrow"
+      );
+    });
+
+    describe("a funded off-ramp payout", () => {
+      let business: Business;
+      let invoice: PublicKey;
+      let index: anchor.BN;
+      const amount = 20_000_000;
+
+      const entry = async () => (await program.account.outboxPage.fetch(page(0))).entries[index.toNumber()];
+      const escrowed = async () => (await getAccount(provider.connection, outboxEscrow)).amount;
+      const ack = async () =>
+        program.methods
+          .ackOutboxEntry(index, Array(32).fill(1))
+          .accountsPartial({
+            payoutProcessor,
+            outboxPage: page(0),
+            invoice,
+            outboxEscrow,
+            custody: (await program.account.payoutProcessor.fetch(payoutProcessor)).custody,
+            allowlist: custodyAllowlist,
+            outboxAuthority,
+            processor: processor.publicKey,
+          })
+          .signers([processor])
+          .rpc();
+      const retry = (signer: Keypair) =>
+        program.methods
+          .retryOutboxEntry(index)
+          .accountsPartial({ payoutProcessor, outboxPage: page(0), invoice, signer: signer.publicKey })
+          .signers([signer])
+          .rpc();
+      const cancel = () =>
+        program.methods
+          .cancelOutboxEntry(index)
+          .accountsPartial({
+            payoutProcessor,
+            outboxPage: page(0),
+            invoice,
+            outboxEscrow,
+            globalState,
+            businessTokenAccount: business.usdc,
+            outboxAuthority,
+            businessOwner: business.publicKey,
+          })
+          .signers([business.keypair])
+          .rpc();
+
+      before(async () => {
+        business = await env.createBusiness();
+        ({ invoice } = await env.createInvoice(business).amount(amount).offramp().listed());
+        const held = await escrowed();
+        await env.fund(invoice).by(await env.createInvestor()).throughOutbox(page(0));
+        index = (await program.account.payoutProcessor.fetch(payoutProcessor)).totalEntries.subn(1);
+        assert.equal(Number((await escrowed()) - held), amount);
+      });
+
+      it("queues the principal in escrow as a pending entry", async () => {
+        const queued = await entry();
+        assert.equal(queued.amount.toNumber(), amount);
+        assert.ok(queued.beneficiary.equals(business.publicKey));
+        assert.deepEqual(queued.status, { pending: {} });
+        assert.equal(queued.retries, 0);
+      });
+
+      it("holds the ack until custody is active on the allowlist", async () => {
+        const before = await escrowed();
+        await expectError(ack(), "DestinationNotAllowlisted");
+        assert.equal(await escrowed(), before);
+        assert.deepEqual((await entry()).status, { pending: {} });
+      });
+
+      it("leaves the entry with the processor until the ack timeout lapses", async () => {
+        await expectError(retry(business.keypair), "OutboxEntryNotTimedOut");
+        await expectError(cancel(), "OutboxEntryNotTimedOut");
+      });
+
+      it("re-queues the entry once the ack timeout lapses", async () => {
+        const { queuedAt } = await entry();
+        await sleep((ACK_TIMEOUT + 1) * 1000);
+        await retry(business.keypair);
+
+        const requeued = await entry();
+        assert.equal(requeued.retries, 1);
+        assert.isAbove(requeued.queuedAt.toNumber(), queuedAt.toNumber());
+        assert.deepEqual(requeued.status, { pending: {} });
+        // The retry restarts the clock
+        await expectError(cancel(), "OutboxEntryNotTimedOut");
+      });
+
+      it("hands a timed-out payout back to the business on-chain", async () => {
+        await sleep((ACK_TIMEOUT + 1) * 1000);
+        const before = (await getAccount(provider.connection, business.usdc)).amount;
+        await cancel();
+
+        assert.equal(Number((await getAccount(provider.connection, business.usdc)).amount - before), amount);
+        const cancelled = await entry();
+        assert.deepEqual(cancelled.status, { cancelled: {} });
+        assert.isNotNull(cancelled.processedAt);
+        assert.isNotNull((await program.account.invoice.fetch(invoice)).releasedAt);
+        await expectError(ack(), "OutboxEntryNotPending");
+      });
+    });
+  });
+
+  describe("aging report", () => {
+    it("rolls up a seeded portfolio of pending invoices", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const seeded = [
+        await createInvoice(owner, { amount: 40_000_000, dueInDays: 20 }),
+        await createInvoice(owner, { amount: 60_000_000, dueInDays: 50 }),
+        await createInvoice(owner, { amount: 25_000_000, dueInDays: 90 }),
+      ];
+
+      const report = await program.methods
+        .getBusinessAgingReport(owner.publicKey)
+        .accountsPartial({ globalState })
+        .remainingAccounts(
+          seeded.map(({ invoice }) => ({ pubkey: invoice, isSigner: false, isWritable: false }))
+        )
+        .view();
+
+      // Nothing is funded yet: every invoice sits in the pending bucket, nothing is owed
+      assert.equal(report.pendingFunding.count, 3);
+      assert.equal(report.pendingFunding.value.toNumber(), 125_000_000);
+      assert.equal(report.current.count, 0);
+      assert.equal(report.totalOwed.toNumber(), 0);
+      assert.isNull(report.nextDueDate);
+      assert.deepEqual(
+        report.invoices.map((line) => line.invoiceId.toNumber()),
+        seeded.map(({ invoiceId }) => invoiceId.toNumber())
+      );
+    });
+
+    it("rejects invoices belonging to another business", async () => {
+      const owner = Keypair.generate();
+      const other = Keypair.generate();
+      await airdrop(other.publicKey);
+      const { invoice } = await createInvoice(other);
+
+      await expectError(
+        program.methods
+          .getBusinessAgingReport(owner.publicKey)
+          .accountsPartial({ globalState })
+          .remainingAccounts([{ pubkey: invoice, isSigner: false, isWritable: false }])
+          .view(),
+        "InvoiceOwnerMismatch"
+      );
+    });
+
+    it("rejects an invoice passed twice rather than counting it twice", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner);
+      const entry = { pubkey: invoice, isSigner: false, isWritable: false };
+
+      await expectError(
+        program.methods
+          .getBusinessAgingReport(owner.publicKey)
+          .accountsPartial({ globalState })
+          .remainingAccounts([entry, entry])
+          .view(),
+        "InvalidBatchAccount"
+      );
+    });
+  });
+
+  describe("business history export", () => {
+    const exportPage = (business: PublicKey, start: number, count: number) =>
+      program.methods
+        .exportBusinessHistory(business, new anchor.BN(start))
+        .accountsPartial({})
+        .remainingAccounts(
+          Array.from({ length: count }, (_, i) => {
+            const invoice = env.invoicePda(business, new anchor.BN(start + i));
+            const audit = env.pda([Buffer.from("invoice_audit"), invoice.toBuffer()]);
+            return [invoice, audit].map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }));
+          }).flat()
+        )
+        .view();
+
+    it("rejects a page that would not move the cursor", async () => {
+      const business = await env.createBusiness();
+      await env.createInvoice(business);
+
+      await expectError(exportPage(business.publicKey, 1, 0), "InvalidExportPage");
+      await expectError(exportPage(business.publicKey, 2, 1), "InvalidExportPage");
+      const done = await exportPage(business.publicKey, 2, 0);
+      assert.lengthOf(done.records, 0);
+      assert.isNull(done.nextCursor);
+    });
+  });
+
+  describe("personal data erasure", () => {
+    it("refuses to erase an invoice that is still active", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner);
+
+      await expectError(
+        program.methods
+          .erasePersonalData()
+          .accountsPartial({ invoice, globalState, signer: owner.publicKey })
+          .signers([owner])
+          .rpc(),
+        "InvoiceNotTerminal"
+      );
+
+      const account = await program.account.invoice.fetch(invoice);
+      assert.isFalse(account.erased);
+      assert.deepEqual(account.debtorInfoHash, debtorInfoHash("Acme Corp, net 45 invoice #42"));
+    });
+
+    it("only lets the business or the authority erase", async () => {
+      const owner = Keypair.generate();
+      const stranger = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner);
+
+      await expectError(
+        program.methods
+          .erasePersonalData()
+          .accountsPartial({ invoice, globalState, signer: stranger.publicKey })
+          .signers([stranger])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("daily funding cap", () => {
+    // Large enough that later funding tests never hit it
+    const CAP = new anchor.BN("1000000000000000");
+
+    const setCap = async (cap: anchor.BN) =>
+      program.methods
+        .setDailyFundingCap(cap)
+        .accountsPartial({ globalState, adminLog: await adminLog(), paramHistory: await paramHistory(), authority: authority.publicKey })
+        .rpc();
+
+    it("applies a tightening immediately", async () => {
+      await setCap(CAP);
+
+      const state = await program.account.globalState.fetch(globalState);
+      assert.equal(state.dailyFundingCap.toString(), CAP.toString());
+      assert.isNull(state.pendingDailyFundingCap);
+
+      const capacity = await program.methods
+        .getDailyFundingCapacity()
+        .accountsPartial({ globalState })
+        .view();
+      assert.equal(capacity.remaining.toString(), CAP.sub(capacity.fundedToday).toString());
+      assert.equal(capacity.resetsAt.toNumber() % DAY, 0);
+
+      const stats = await program.methods
+        .getGlobalStats()
+        .accountsPartial({ globalState, insurancePoolAccount: null })
+        .view();
+      assert.equal(stats.remainingDailyCapacity.toString(), capacity.remaining.toString());
+    });
+
+    it("puts an increase behind the timelock", async () => {
+      await setCap(CAP.muln(2));
+
+      const state = await program.account.globalState.fetch(globalState);
+      assert.equal(state.dailyFundingCap.toString(), CAP.toString());
+      assert.equal(state.pendingDailyFundingCap.toString(), CAP.muln(2).toString());
+
+      await expectError(
+        program.methods
+          .applyDailyFundingCap()
+          .accountsPartial({ globalState, adminLog: await adminLog(), paramHistory: await paramHistory(), authority: authority.publicKey })
+          .rpc(),
+        "TimelockNotElapsed"
+      );
+    });
+
+    it("lets a tightening cancel a pending increase", async () => {
+      await setCap(CAP);
+
+      const state = await program.account.globalState.fetch(globalState);
+      assert.isNull(state.pendingDailyFundingCap);
+    });
+  });
+
+  describe("batch invoice details", () => {
+    const asRemaining = (keys: PublicKey[]) =>
+      keys.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }));
+
+    let watchlist: PublicKey[] = [];
+
+    before(async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      for (let i = 0; i < 12; i++) {
+        const { invoice } = await createInvoice(owner, {
+          amount: 10_000_000 * (i + 1),
+          dueInDays: 30 + i,
+        });
+        watchlist.push(invoice);
+      }
+    });
+
+    it("returns a single invoice's details to a simulated call", async () => {
+      const details = await program.methods.getInvoiceDetails().accountsPartial({ invoice: watchlist[0] }).view();
+      const fetched = await program.account.invoice.fetch(watchlist[0]);
+
+      assert.equal(details.invoiceId.toString(), fetched.invoiceId.toString());
+      assert.equal(details.paymentTermsDays, fetched.paymentTermsDays);
+      assert.isNull(details.lateFee);
+      assert.isNull(details.insurancePayout);
+    });
+
+    it("returns compact summaries for a 12-invoice batch, in order", async () => {
+      const batch = await program.methods
+        .getInvoicesDetailsBatch()
+        .remainingAccounts(asRemaining(watchlist))
+        .view();
+
+      assert.deepEqual(batch.mode, { compact: {} });
+      assert.lengthOf(batch.entries, 12);
+      for (let i = 0; i < 12; i++) {
+        const fetched = await program.account.invoice.fetch(watchlist[i]);
+        const summary = batch.entries[i].compact[0];
+        assert.equal(summary.invoiceId.toString(), fetched.invoiceId.toString());
+        assert.equal(summary.amount.toString(), fetched.amount.toString());
+        assert.equal(summary.dueDate.toString(), fetched.dueDate.toString());
+        assert.equal(summary.riskScore, fetched.riskScore);
+      }
+    });
+
+    it("returns full details for a small batch and flags bad entries", async () => {
+      const batch = await program.methods
+        .getInvoicesDetailsBatch()
+        .remainingAccounts(asRemaining([watchlist[0], globalState, watchlist[1]]))
+        .view();
+
+      assert.deepEqual(batch.mode, { full: {} });
+      const fetched = await program.account.invoice.fetch(watchlist[1]);
+      assert.ok(batch.entries[0].full);
+      assert.deepEqual(batch.entries[1], { unavailable: { 0: { notAnInvoice: {} } } });
+      const details = batch.entries[2].full[0];
+      assert.ok(details.businessOwner.equals(fetched.businessOwner));
+      assert.equal(details.insurancePremium.toString(), fetched.insurancePremium.toString());
+    });
+
+    it("rejects more than 12 invoices", async () => {
+      await expectError(
+        program.methods
+          .getInvoicesDetailsBatch()
+          .remainingAccounts(asRemaining([...watchlist, watchlist[0]]))
+          .view(),
+        "TooManyInvoices"
+      );
+    });
+  });
+
+  describe("partial funding", () => {
+    const owner = Keypair.generate();
+
+    before(async () => {
+      await airdrop(owner.publicKey);
+    });
+
+    it("records partial funding on the invoice", async () => {
+      const { invoice } = await createInvoice(owner, { partial: true });
+      const fetched = await program.account.invoice.fetch(invoice);
+      assert.isTrue(fetched.partialFunding);
+      assert.equal(fetched.contributorCount, 0);
+      assert.equal(fetched.distributableAmount.toNumber(), 0);
+    });
+
+    it("rejects partial funding combined with an off-ramp payout", async () => {
+      await expectError(
+        createInvoice(owner, { partial: true, offramp: true }),
+        "PartialFundingOfframpUnsupported"
+      );
+    });
+  });
+
+  describe("invoice cancellation", () => {
+    const owner = Keypair.generate();
+    const investor = Keypair.generate();
+
+    const cancel = (invoice: PublicKey, signer: Keypair) =>
+      program.methods
+        .cancelInvoice()
+        .accountsPartial({ invoice, globalState, businessOwner: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      await airdrop(owner.publicKey);
+      await airdrop(investor.publicKey);
+    });
+
+    it("closes the invoice and returns its rent to the owner", async () => {
+      const { invoice } = await createInvoice(owner);
+      const before = await program.account.globalState.fetch(globalState);
+      const rent = await provider.connection.getBalance(invoice);
+      const balance = await provider.connection.getBalance(owner.publicKey);
+
+      await cancel(invoice, owner);
+
+      assert.isNull(await program.account.invoice.fetchNullable(invoice));
+      const after = await program.account.globalState.fetch(globalState);
+      assert.equal(after.totalInvoices.toNumber(), before.totalInvoices.toNumber() - 1);
+      assert.isAbove(await provider.connection.getBalance(owner.publicKey), balance + rent - 10_000);
+    });
+
+    it("only lets the business owner cancel", async () => {
+      const { invoice } = await createInvoice(owner);
+      await expectError(cancel(invoice, investor), "InvoiceOwnerMismatch");
+    });
+
+    it("lets exactly one of funding and cancellation win in the same slot", async () => {
+      const { invoice } = await createInvoice(owner);
+      const investorAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        investor.publicKey
+      );
+      const businessAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        owner.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        investorAta.address,
+        authority.publicKey,
+        1_000_000_000
+      );
+
+      const { insurancePremium, riskScore, dueDate } = await program.account.invoice.fetch(invoice);
+      const fund = program.methods
+        .fundInvoice(new anchor.BN(100_000_000), false, insurancePremium, riskScore, dueDate)
+        .accountsPartial({
+          invoice,
+          debtor: await env.debtorOf(invoice),
+          globalState,
+          investor: investor.publicKey,
+          investorTokenAccount: investorAta.address,
+          businessTokenAccount: businessAta.address,
+          insurancePoolAccount: insurancePool,
+          experiment: null,
+          payoutProcessor: null,
+          outboxPage: null,
+          outboxEscrow: null,
+          investorBalance: null,
+          investorWhitelist: null,
+          investorCustody: null,
+          originatorTokenAccount: null,
+          originatorStats: null,
+          mint: usdcMint,
+          pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+          portfolio: portfolio(investor.publicKey),
+        })
+        .signers([investor])
+        .rpc();
+      const [funded, cancelled] = await Promise.allSettled([fund, cancel(invoice, owner)]);
+
+      const account = await program.account.invoice.fetchNullable(invoice);
+      if (cancelled.status === "fulfilled") {
+        assert.equal(funded.status, "rejected");
+        assert.isNull(account);
+      } else {
+        assert.equal(funded.status, "fulfilled");
+        assert.include(String(cancelled.reason), "InvoiceNotAvailable");
+        assert.deepEqual(account.status, { funded: {} });
+      }
+    });
+  });
+
+  describe("micro-invoice tier", () => {
+    const owner = Keypair.generate();
+    const THRESHOLD = 250_000_000;
+    const [microTier] = PublicKey.findProgramAddressSync(
+      [Buffer.from("micro_tier")],
+      program.programId
+    );
+
+    before(async () => {
+      await airdrop(owner.publicKey);
+      await program.methods
+        .configureMicroTier(new anchor.BN(THRESHOLD), 15, 100)
+        .accountsPartial({ microTier, globalState, adminLog: await adminLog(), authority: authority.publicKey, payer: authority.publicKey })
+        .rpc();
+    });
+
+    it("rejects a threshold above 250 USDC", async () => {
+      await expectError(
+        program.methods
+          .configureMicroTier(new anchor.BN(THRESHOLD + 1), 15, 100)
+          .accountsPartial({ microTier, globalState, adminLog: await adminLog(), authority: authority.publicKey, payer: authority.publicKey })
+          .rpc(),
+        "InvalidMicroTierConfig"
+      );
+    });
+
+    it("prices an invoice at the threshold with the flat model", async () => {
+      let event: any;
+      const listener = program.addEventListener("microInvoiceCreated", (e) => (event = e));
+      const { invoice } = await createInvoice(owner, { amount: THRESHOLD, microTier });
+      await program.removeEventListener(listener);
+
+      const fetched = await program.account.invoice.fetch(invoice);
+      assert.isTrue(fetched.microTier);
+      assert.equal(fetched.riskScore, 15);
+      assert.equal(fetched.insurancePremium.toNumber(), THRESHOLD / 100);
+      assert.equal(fetched.creditScore, 0);
+      assert.ok(event);
+      assert.equal(event.invoice.toBase58(), invoice.toBase58());
+      assert.equal(event.businessOwner.toBase58(), owner.publicKey.toBase58());
+      assert.isTrue(event.investor.equals(PublicKey.default));
+      assert.isAtMost(Math.abs(event.timestamp.toNumber() - now()), 60);
+    });
+
+    it("falls back to the standard path one unit over the threshold", async () => {
+      const { invoice } = await createInvoice(owner, { amount: THRESHOLD + 1, microTier });
+
+      const fetched = await program.account.invoice.fetch(invoice);
+      assert.isFalse(fetched.microTier);
+      assert.notEqual(fetched.creditScore, 0);
+      assert.equal(fetched.premiumBps, fetched.riskScore * 10);
+      assert.equal(
+        fetched.insurancePremium.toNumber(),
+        Math.ceil(((THRESHOLD + 1) * fetched.premiumBps) / 10_000)
+      );
+    });
+  });
+
+  describe("event log budget", () => {
+    it("keeps every event of an instruction in the untruncated logs", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const invoiceId = await env.nextInvoiceId(owner.publicKey);
+      const invoice = invoicePda(owner.publicKey, invoiceId);
+
+      const signature = await program.methods
+        .createInvoice(
+          new anchor.BN(100_000_000),
+          new anchor.BN(now() + 45 * DAY),
+          debtorInfoHash("x".repeat(200)),
+          "x".repeat(64),
+          false,
+          false,
+          { other: {} },
+          null,
+          debtorId("x".repeat(200)),
+          null,
+          null,
+          null
+        )
+        .accountsPartial({
+          invoice,
+          globalState,
+          businessOwner: owner.publicKey,
+          payer: owner.publicKey,
+          debtor: env.debtorPda(debtorId("x".repeat(200))),
+          experiment: null,
+          microTier: null,
+          mint: null,
+          instructions: null,
+          businessTokenAccount: null,
+          insurancePoolAccount: null,
+          originatorStats: null,
+          tokenProgram: null,
+        })
+        .signers([owner])
+        .rpc({ commitment: "confirmed" });
+
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const logs = tx.meta.logMessages;
+      assert.notInclude(logs.join("\n"), "Log truncated");
+
+      // InvoiceCreated goes out through the event authority, not the logs
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      assert.isEmpty([...parser.parseLogs(logs)]);
+      const events = await env.cpiEvents(signature);
+      assert.deepEqual(events.map((e) => e.name), ["invoiceCreated"]);
+      assert.equal(events[0].data.invoiceId.toString(), invoiceId.toString());
+    });
+  });
+
+  describe("events through the event authority", () => {
+    it("emits the indexed lifecycle events as self-CPIs", async () => {
+      const investor = await env.createInvestor();
+      const business = await env.createBusiness().withUsdc(10_000_000);
+      const { invoice } = await env.createInvoice(business).amount(20_000_000).listed();
+
+      const [funded] = await env.cpiEvents(await env.fund(invoice).by(investor));
+      assert.equal(funded.name, "invoiceFunded");
+      assert.ok(funded.data.invoice.equals(invoice));
+      assert.ok(funded.data.investor.equals(investor.publicKey));
+      assert.equal(funded.data.amount.toNumber(), 20_000_000);
+
+      const [repaid] = await env.cpiEvents(await env.repayInvoicesBatch(business, [invoice], 100_000_000));
+      assert.equal(repaid.name, "invoiceRepaid");
+      assert.ok(repaid.data.invoice.equals(invoice));
+      assert.isAtLeast(repaid.data.amount.toNumber(), 20_000_000);
+    });
+
+    it("emits InsuranceClaimed as a self-CPI", async () => {
+      const investor = await env.createInvestor();
+      const business = await env.createBusiness();
+      await env.withConfig({ gracePeriodDays: 0 }, async () => {
+        const { invoice } = await env.createInvoice(business).amount(10_000_000).dueInSeconds(5).listed();
+        await env.fund(invoice).by(investor);
+        await env.warpTo(invoice, "defaultable");
+        await env.markDefaulted(invoice);
+
+        const [claimed] = await env.cpiEvents(await env.claimInsurance(invoice, investor));
+        assert.equal(claimed.name, "insuranceClaimed");
+        assert.ok(claimed.data.invoice.equals(invoice));
+        assert.isAbove(claimed.data.payoutAmount.toNumber(), 0);
+      });
+    });
+  });
+
+  describe("minimum insurance premium", () => {
+    const setMinimum = async (amount: number) =>
+      program.methods
+        .setMinInsurancePremium(new anchor.BN(amount))
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    after(async () => setMinimum(100_000));
+
+    it("starts at 0.10 USDC", async () => {
+      const state = await program.account.globalState.fetch(globalState);
+      assert.equal(state.minInsurancePremium.toNumber(), 100_000);
+    });
+
+    it("charges small insured invoices the minimum", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner, { amount: 1_000_000 });
+      const fetched = await program.account.invoice.fetch(invoice);
+      assert.isAbove(fetched.premiumBps, 0);
+      assert.isBelow((1_000_000 * fetched.premiumBps) / 10_000, 100_000);
+      assert.equal(fetched.insurancePremium.toNumber(), 100_000);
+    });
+
+    it("is bounded and only the authority may set it", async () => {
+      await expectError(setMinimum(100_000_001), "InvalidMinInsurancePremium");
+      const stranger = Keypair.generate();
+      await expectError(
+        program.methods
+          .setMinInsurancePremium(new anchor.BN(0))
+          .accountsPartial({ globalState, adminLog: await adminLog(), authority: stranger.publicKey })
+          .signers([stranger])
+          .rpc(),
+        "Unauthorized"
+      );
+      await setMinimum(250_000);
+      assert.equal((await program.account.globalState.fetch(globalState)).minInsurancePremium.toNumber(), 250_000);
+    });
+  });
+
+  describe("early repayment interest floor", () => {
+    const setFloor = async (bps: number) =>
+      program.methods
+        .setMinInterestBps(bps)
+        .accountsPartial({ globalState, adminLog: await adminLog(), paramHistory: await paramHistory(), authority: authority.publicKey })
+        .rpc();
+
+    it("starts at a quarter of the yield", async () => {
+      const state = await program.account.globalState.fetch(globalState);
+      assert.equal(state.minInterestBps, 2_500);
+    });
+
+    it("only accepts floors up to the full yield", async () => {
+      await expectError(setFloor(10_001), "InvalidMinInterest");
+      await setFloor(10_000);
+      await setFloor(2_500);
+      const state = await program.account.globalState.fetch(globalState);
+      assert.equal(state.minInterestBps, 2_500);
+    });
+  });
+
+  describe("collections agencies", () => {
+    const agency = Keypair.generate();
+    const [collectionsAgency] = PublicKey.findProgramAddressSync(
+      [Buffer.from("collections_agency"), agency.publicKey.toBuffer()],
+      program.programId
+    );
+
+    it("registers an agency with its fee arrangement", async () => {
+      await expectError(
+        program.methods
+          .registerCollectionsAgency(agency.publicKey, 5_001)
+          .accountsPartial({ collectionsAgency, globalState, adminLog: await adminLog(), authority: authority.publicKey, payer: authority.publicKey })
+          .rpc(),
+        "InvalidCollectionsFee"
+      );
+
+      await program.methods
+        .registerCollectionsAgency(agency.publicKey, 1_500)
+        .accountsPartial({ collectionsAgency, globalState, adminLog: await adminLog(), authority: authority.publicKey, payer: authority.publicKey })
+        .rpc();
+
+      const stats = await program.methods
+        .getCollectionsAgencyStats()
+        .accountsPartial({ collectionsAgency })
+        .view();
+      assert.ok(stats.agency.equals(agency.publicKey));
+      assert.equal(stats.feeBps, 1_500);
+      assert.equal(stats.assignments, 0);
+      assert.equal(stats.recoveredTotal.toNumber(), 0);
+    });
+
+    it("only assigns defaulted invoices", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner);
+      const [collectionsAssignment] = PublicKey.findProgramAddressSync(
+        [Buffer.from("collections"), invoice.toBuffer()],
+        program.programId
+      );
+
+      await expectError(
+        program.methods
+          .assignCollections()
+          .accountsPartial({
+            invoice,
+            collectionsAgency,
+            collectionsAssignment,
+            globalState,
+            authority: authority.publicKey,
+            payer: authority.publicKey,
+          })
+          .rpc(),
+        "InvoiceNotDefaulted"
+      );
+    });
+  });
+
+  describe("retail guardrails", () => {
+    const investor = Keypair.generate();
+    const [investorStats] = PublicKey.findProgramAddressSync(
+      [Buffer.from("investor_stats"), investor.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const setGuardrails = async (enabled: boolean) =>
+      program.methods
+        .setRetailGuardrails(enabled)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("toggles the guardrails flag", async () => {
+      await setGuardrails(true);
+      assert.isTrue((await program.account.globalState.fetch(globalState)).retailGuardrails);
+      await setGuardrails(false);
+      assert.isFalse((await program.account.globalState.fetch(globalState)).retailGuardrails);
+    });
+
+    it("records a professional attestation on the investor's stats", async () => {
+      await program.methods
+        .setProfessionalAttestation(investor.publicKey, true)
+        .accountsPartial({ investorStats, globalState, adminLog: await adminLog(), authority: authority.publicKey, payer: authority.publicKey })
+        .rpc();
+
+      const stats = await program.account.investorStats.fetch(investorStats);
+      assert.ok(stats.investor.equals(investor.publicKey));
+      assert.isTrue(stats.professional);
+      assert.equal(stats.completedRepayments, 0);
+    });
+
+    it("only lets the authority attest", async () => {
+      const impostor = Keypair.generate();
+      await airdrop(impostor.publicKey);
+      await expectError(
+        program.methods
+          .setProfessionalAttestation(impostor.publicKey, true)
+          .accountsPartial({ globalState, adminLog: await adminLog(), authority: impostor.publicKey, payer: impostor.publicKey })
+          .signers([impostor])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("default crank", () => {
+    it("refuses to default an invoice that was never funded", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner);
+      const stateBefore = await program.account.globalState.fetch(globalState);
+
+      await expectError(
+        program.methods
+          .markDefaulted()
+          .accountsPartial({
+            invoice,
+            debtor: await env.debtorOf(invoice),
+            investorStats: null,
+            globalState,
+            experiment: null,
+            pairLedger: null,
+            portfolio: null,
+          })
+          .rpc(),
+        "InvoiceNotFunded"
+      );
+      const state = await program.account.globalState.fetch(globalState);
+      assert.equal(state.totalDefaulted.toNumber(), stateBefore.totalDefaulted.toNumber());
+    });
+  });
+
+  describe("due date extension", () => {
+    it("needs the recorded investor's signature", async () => {
+      const owner = Keypair.generate();
+      const stranger = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner);
+
+      await expectError(
+        program.methods
+          .extendDueDate(new anchor.BN(now() + 60 * DAY))
+          .accountsPartial({
+            invoice,
+            globalState,
+            businessOwner: owner.publicKey,
+            investor: stranger.publicKey,
+            investorReceipt: null,
+          })
+          .signers([owner, stranger])
+          .rpc(),
+        "UnauthorizedInvestor"
+      );
+    });
+  });
+
+  describe("admin audit trail", () => {
+    it("reconstructs who changed which parameter and when", async () => {
+      const before = await program.account.globalState.fetch(globalState);
+      const first = before.adminActionCount.toNumber();
+
+      await program.methods
+        .setRetentionPeriod(new anchor.BN(30 * DAY))
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+      await program.methods
+        .setMinInterestBps(2_500)
+        .accountsPartial({ globalState, adminLog: await adminLog(), paramHistory: await paramHistory(), authority: authority.publicKey })
+        .rpc();
+
+      const state = await program.account.globalState.fetch(globalState);
+      assert.equal(state.adminActionCount.toNumber(), first + 2);
+
+      // Walk the pages holding the two new entries, as an auditor would
+      const actions = [];
+      for (let index = first; index < first + 2; index++) {
+        const entries = await program.methods
+          .getAdminActionLog()
+          .accountsPartial({ adminLog: adminLogPage(Math.floor(index / ADMIN_LOG_PAGE_SIZE)) })
+          .view();
+        actions.push(entries[index % ADMIN_LOG_PAGE_SIZE]);
+      }
+
+      assert.deepEqual(actions.map((a) => Object.keys(a.action)[0]), [
+        "retentionPeriodSet",
+        "minInterestSet",
+      ]);
+      for (const action of actions) {
+        assert.ok(action.actor.equals(authority.publicKey));
+        assert.deepEqual(action.role, { protocolAuthority: {} });
+        assert.isAtMost(Math.abs(action.timestamp.toNumber() - now()), 60);
+      }
+    });
+
+    it("refuses to open an admin log page out of order", async () => {
+      const state = await program.account.globalState.fetch(globalState);
+      const skipped = Math.floor(state.adminActionCount.toNumber() / ADMIN_LOG_PAGE_SIZE) + 1;
+
+      await expectError(
+        program.methods
+          .openAdminLogPage(skipped)
+          .accountsPartial({
+            adminLog: adminLogPage(skipped),
+            globalState,
+            payer: authority.publicKey,
+          })
+          .rpc(),
+        "AdminLogPageMismatch"
+      );
+    });
+  });
+
+  describe("investor custody balance", () => {
+    const investor = Keypair.generate();
+    const [investorBalance] = PublicKey.findProgramAddressSync(
+      [Buffer.from("investor_balance"), investor.publicKey.toBuffer()],
+      program.programId
+    );
+    const [investorCustody] = PublicKey.findProgramAddressSync(
+      [Buffer.from("investor_custody"), investor.publicKey.toBuffer()],
+      program.programId
+    );
+    let investorAta: PublicKey;
+
+    const audit = () =>
+      program.methods
+        .auditInvestorBalance()
+        .accountsPartial({ investorBalance, investorCustody })
+        .view();
+
+    before(async () => {
+      await airdrop(investor.publicKey);
+      investorAta = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          authority.payer,
+          usdcMint,
+          investor.publicKey
+        )
+      ).address;
+      await mintTo(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        investorAta,
+        authority.publicKey,
+        500_000_000
+      );
+    });
+
+    it("deposits into custody and reconciles with the token account", async () => {
+      await program.methods
+        .depositBalance(new anchor.BN(300_000_000))
+        .accountsPartial({
+          investorBalance,
+          investorCustody,
+          globalState,
+          usdcMint,
+          investor: investor.publicKey,
+          investorTokenAccount: investorAta,
+        })
+        .signers([investor])
+        .rpc();
+
+      const report = await audit();
+      assert.equal(report.available.toNumber(), 300_000_000);
+      assert.equal(report.custodyAmount.toNumber(), 300_000_000);
+      assert.isTrue(report.reconciled);
+    });
+
+    it("withdraws idle funds but never more than is available", async () => {
+      await expectError(
+        program.methods
+          .withdrawBalance(new anchor.BN(300_000_001))
+          .accountsPartial({
+            investorBalance,
+            investorCustody,
+            investor: investor.publicKey,
+            investorTokenAccount: investorAta,
+          })
+          .signers([investor])
+          .rpc(),
+        "BalanceUnavailable"
+      );
+
+      await program.methods
+        .withdrawBalance(new anchor.BN(100_000_000))
+        .accountsPartial({
+          investorBalance,
+          investorCustody,
+          investor: investor.publicKey,
+          investorTokenAccount: investorAta,
+        })
+        .signers([investor])
+        .rpc();
+
+      const report = await audit();
+      assert.equal(report.available.toNumber(), 200_000_000);
+      assert.isTrue(report.reconciled);
+    });
+  });
+
+  describe("insurance pool surplus withdrawal", () => {
+    const [proposal] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_withdrawal")],
+      program.programId
+    );
+    let destination: PublicKey;
+
+    before(async () => {
+      destination = await createAccount(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        Keypair.generate().publicKey
+      );
+    });
+
+    it("never proposes more than the pool holds beyond insured exposure", async () => {
+      const state = await program.account.globalState.fetch(globalState);
+      const surplus = state.insurancePoolBalance.sub(state.insuredExposure);
+
+      await expectError(
+        program.methods
+          .proposePoolWithdrawal(surplus.addn(1))
+          .accountsPartial({
+            proposal,
+            globalState,
+            adminLog: await adminLog(),
+            destination,
+            authority: authority.publicKey,
+            payer: authority.publicKey,
+          })
+          .rpc(),
+        "PoolUtilizationLimit"
+      );
+      assert.isNull(await provider.connection.getAccountInfo(proposal));
+    });
+
+    it("is restricted to the protocol authority", async () => {
+      const intruder = Keypair.generate();
+      await airdrop(intruder.publicKey);
+
+      await expectError(
+        program.methods
+          .proposePoolWithdrawal(new anchor.BN(1))
+          .accountsPartial({
+            proposal,
+            globalState,
+            adminLog: await adminLog(),
+            destination,
+            authority: intruder.publicKey,
+            payer: intruder.publicKey,
+          })
+          .signers([intruder])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("protocol health", () => {
+    const HEALTH_POOL_UNVERIFIED = 1 << 3;
+
+    const health = () =>
+      program.methods
+        .getHealth()
+        .accountsPartial({ globalState, insurancePoolAccount: null })
+        .view();
+
+    const setThresholds = async (thresholds: any) =>
+      program.methods
+        .setHealthThresholds(thresholds)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("flags the pool mirror as unverified without the token account", async () => {
+      const report = await health();
+      assert.deepEqual(report.reconciliation, { amber: {} });
+      assert.isNull(report.poolTokenBalance);
+      assert.notEqual(report.reasons & HEALTH_POOL_UNVERIFIED, 0);
+      assert.notDeepEqual(report.overall, { green: {} });
+    });
+
+    it("lets the authority tune thresholds", async () => {
+      const state = await program.account.globalState.fetch(globalState);
+      const defaults = state.healthThresholds;
+      assert.equal(defaults.coverageRedBps, 10_000);
+
+      await setThresholds({ ...defaults, claimQueueAmber: 2, claimQueueRed: 9 });
+      const updated = await program.account.globalState.fetch(globalState);
+      assert.equal(updated.healthThresholds.claimQueueRed, 9);
+
+      await setThresholds(defaults);
+    });
+
+    it("rejects thresholds where red comes before amber", async () => {
+      const state = await program.account.globalState.fetch(globalState);
+      await expectError(
+        setThresholds({ ...state.healthThresholds, coverageRedBps: 20_000 }),
+        "InvalidHealthThresholds"
+      );
+    });
+  });
+
+  describe("invoice bundles", () => {
+    const owner = Keypair.generate();
+    let nextBundleId = 1;
+
+    const bundlePda = (bundleId: anchor.BN) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("bundle"), owner.publicKey.toBuffer(), bundleId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const asWritable = (keys: PublicKey[]) =>
+      keys.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }));
+
+    const createBundle = async (invoices: PublicKey[], sameDebtor = true) => {
+      const bundleId = new anchor.BN(nextBundleId++);
+      const bundle = bundlePda(bundleId);
+      await program.methods
+        .createBundle(bundleId, sameDebtor)
+        .accountsPartial({ bundle, globalState, businessOwner: owner.publicKey })
+        .remainingAccounts(asWritable(invoices))
+        .signers([owner])
+        .rpc();
+      return bundle;
+    };
+
+    before(async () => {
+      await airdrop(owner.publicKey);
+    });
+
+    it("prices the basket on amount-weighted risk and locks its invoices", async () => {
+      const small = await createInvoice(owner, { amount: 20_000_000 });
+      const large = await createInvoice(owner, { amount: 60_000_000 });
+      const bundle = await createBundle([small.invoice, large.invoice]);
+
+      const account = await program.account.bundle.fetch(bundle);
+      const constituents = await Promise.all(
+        [small.invoice, large.invoice].map((key) => program.account.invoice.fetch(key))
+      );
+      const weighted = constituents[0].riskScore * 20 + constituents[1].riskScore * 60;
+      assert.equal(account.amount.toNumber(), 80_000_000);
+      assert.equal(account.riskScore, Math.ceil(weighted / 80));
+      assert.deepEqual(account.status, { open: {} });
+      for (const invoice of constituents) {
+        assert.ok(invoice.bundle.equals(bundle));
+      }
+
+      await expectError(
+        program.methods
+          .cancelInvoice()
+          .accountsPartial({ invoice: small.invoice, globalState, businessOwner: owner.publicKey })
+          .signers([owner])
+          .rpc(),
+        "InvoiceBundled"
+      );
+    });
+
+    it("only bundles between two and ten invoices", async () => {
+      const { invoice } = await createInvoice(owner);
+      await expectError(createBundle([invoice]), "InvalidBundleSize");
+    });
+
+    it("releases the invoices when unbundled before funding", async () => {
+      const first = await createInvoice(owner);
+      const second = await createInvoice(owner);
+      const bundle = await createBundle([first.invoice, second.invoice]);
+
+      await program.methods
+        .unbundle()
+        .accountsPartial({ bundle, businessOwner: owner.publicKey })
+        .remainingAccounts(asWritable([first.invoice, second.invoice]))
+        .signers([owner])
+        .rpc();
+
+      assert.isNull(await program.account.bundle.fetchNullable(bundle));
+      assert.isNull((await program.account.invoice.fetch(first.invoice)).bundle);
+      await createBundle([first.invoice, second.invoice]);
+    });
+  });
+
+  describe("protocol config", () => {
+    const updateConfig = async (config: any) =>
+      program.methods
+        .updateConfig(config)
+        .accountsPartial({ globalState, adminLog: await adminLog(), paramHistory: await paramHistory(), authority: authority.publicKey })
+        .rpc();
+
+    it("starts from the previously hard-coded parameters", async () => {
+      const { config } = await program.account.globalState.fetch(globalState);
+      assert.equal(config.maxInvoiceAmount.toString(), "10000000000");
+      assert.equal(config.maxTermDays, 365);
+      assert.equal(config.gracePeriodDays, 30);
+      assert.equal(config.lateFeeBpsPerDay, 5);
+      assert.deepEqual(config.coverage, { low: 90, medium: 80, high: 70, veryHigh: 60 });
+    });
+
+    it("applies a lower invoice cap to new listings", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { config } = await program.account.globalState.fetch(globalState);
+
+      await updateConfig({ ...config, maxInvoiceAmount: new anchor.BN(50_000_000) });
+      try {
+        await expectError(createInvoice(owner, { amount: 50_000_001 }), "AmountTooLarge");
+      } finally {
+        await updateConfig(config);
+      }
+    });
+
+    it("rejects out-of-range values", async () => {
+      const { config } = await program.account.globalState.fetch(globalState);
+      await expectError(updateConfig({ ...config, lateFeeBpsPerDay: 101 }), "InvalidConfig");
+      await expectError(
+        updateConfig({ ...config, coverage: { ...config.coverage, veryHigh: 95 } }),
+        "InvalidConfig"
+      );
+    });
+  });
+
+  describe("listing proofs", () => {
+    const owner = Keypair.generate();
+    const DOMAIN = Buffer.from("sureinv:listing-proof:v1");
+
+    // Mirror of verify_listing_proof, as a partner site would run it
+    const verifyListingProof = (proof: Buffer, invoiceKey: PublicKey, invoice: any) => {
+      const u64 = (at: number) => proof.readBigUInt64LE(at).toString();
+      const i64 = (at: number) => proof.readBigInt64LE(at).toString();
+      const yieldAmount = invoice.expectedReturn.sub(invoice.amount);
+      return (
+        proof.length === 106 &&
+        proof.subarray(0, 24).equals(DOMAIN) &&
+        new PublicKey(proof.subarray(24, 56)).equals(invoiceKey) &&
+        i64(56) === invoice.createdAt.toString() &&
+        u64(64) === invoice.amount.toString() &&
+        u64(72) === yieldAmount.toString() &&
+        proof[80] === invoice.riskScore &&
+        proof[81] === invoice.coveragePercentage &&
+        i64(82) === invoice.dueDate.toString() &&
+        u64(90) === yieldAmount.muln(10_000).div(invoice.amount).toString()
+      );
+    };
+
+    const snapshot = (invoice: PublicKey) =>
+      program.methods.getListingProof().accountsPartial({ invoice }).view();
+
+    before(async () => {
+      await airdrop(owner.publicKey);
+    });
+
+    it("verifies a proof and catches a doctored yield", async () => {
+      const { invoice } = await createInvoice(owner);
+      const { proof } = await snapshot(invoice);
+      const account = await program.account.invoice.fetch(invoice);
+
+      assert.isTrue(verifyListingProof(Buffer.from(proof), invoice, account));
+
+      const doctored = Buffer.from(proof);
+      doctored.writeBigUInt64LE(doctored.readBigUInt64LE(72) * 2n, 72);
+      assert.isFalse(verifyListingProof(doctored, invoice, account));
+    });
+
+    it("anchors the published terms on the invoice", async () => {
+      const { invoice } = await createInvoice(owner);
+      assert.isNull((await snapshot(invoice)).matchesPublished);
+
+      await program.methods
+        .publishListingProof()
+        .accountsPartial({ invoice, businessOwner: owner.publicKey })
+        .signers([owner])
+        .rpc();
+
+      const { termsHash, matchesPublished } = await snapshot(invoice);
+      assert.isTrue(matchesPublished);
+      const account = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(account.publishedListingHash, termsHash);
+    });
+  });
+
+  describe("pause flags", () => {
+    const PAUSE_CREATE = 1 << 0;
+    const PAUSE_FUND = 1 << 1;
+    const PAUSE_CLAIM = 1 << 2;
+    const owner = Keypair.generate();
+
+    const setPaused = async (method: "pause" | "unpause", flags: number) =>
+      program.methods[method](flags)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    before(async () => {
+      await airdrop(owner.publicKey);
+    });
+
+    it("blocks listings while creation is paused and restores them on unpause", async () => {
+      await setPaused("pause", PAUSE_CREATE);
+      try {
+        await expectError(createInvoice(owner), "ProtocolPaused");
+      } finally {
+        await setPaused("unpause", PAUSE_CREATE);
+      }
+      await createInvoice(owner);
+    });
+
+    it("pauses each kind of activity independently", async () => {
+      await setPaused("pause", PAUSE_FUND | PAUSE_CLAIM);
+      try {
+        const state = await program.account.globalState.fetch(globalState);
+        assert.equal(state.paused, PAUSE_FUND | PAUSE_CLAIM);
+        // Listing is unaffected by a funding or claims pause
+        await createInvoice(owner);
+
+        const health = await program.methods
+          .getHealth()
+          .accountsPartial({ globalState, insurancePoolAccount: null })
+          .view();
+        assert.equal(health.paused, PAUSE_FUND | PAUSE_CLAIM);
+      } finally {
+        await setPaused("unpause", PAUSE_FUND | PAUSE_CLAIM);
+      }
+      assert.equal((await program.account.globalState.fetch(globalState)).paused, 0);
+    });
+
+    it("is restricted to the protocol authority", async () => {
+      const intruder = Keypair.generate();
+      await airdrop(intruder.publicKey);
+      await expectError(
+        program.methods
+          .pause(PAUSE_CREATE)
+          .accountsPartial({ globalState, adminLog: await adminLog(), authority: intruder.publicKey })
+          .signers([intruder])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("business reputation", () => {
+    it("opens a clean profile with the first listing", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      await createInvoice(owner);
+
+      const [profile] = PublicKey.findProgramAddressSync(
+        [Buffer.from("business_profile"), owner.publicKey.toBuffer()],
+        program.programId
+      );
+      const { businessOwner, history } = await program.account.businessProfile.fetch(profile);
+      assert.ok(businessOwner.equals(owner.publicKey));
+      assert.equal(history.lifetimeDefaults, 0);
+      assert.equal(history.lifetimeLateRepayments, 0);
+
+      // A second listing reuses the same profile
+      await createInvoice(owner);
+    });
+
+    it("prices and records the declared industry", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { riskParams } = await program.account.globalState.fetch(globalState);
+      const listed = async (industry: Record<string, object>) =>
+        program.account.invoice.fetch((await createInvoice(owner, { amount: 10_000_000 }).industry(industry)).invoice);
+
+      const construction = await listed({ construction: {} });
+      const healthcare = await listed({ healthcare: {} });
+      assert.deepEqual(construction.industry, { construction: {} });
+      assert.equal(construction.industryRisk, riskParams.industryPoints[0]);
+      assert.equal(healthcare.industryRisk, riskParams.industryPoints[2]);
+      assert.equal(construction.riskScore - healthcare.riskScore, construction.industryRisk - healthcare.industryRisk);
+    });
+
+    it("configures the history half-life within the ring's window", async () => {
+      const { config } = await program.account.globalState.fetch(globalState);
+      assert.deepEqual(config.risk, {
+        defaultPoints: 10,
+        lateRepaymentPoints: 3,
+        halfLifeMonths: 6,
+        maxHistoryPoints: 20,
+      });
+
+      for (const halfLifeMonths of [0, 25]) {
+        await expectError(
+          program.methods
+            .updateConfig({ ...config, risk: { ...config.risk, halfLifeMonths } })
+            .accountsPartial({ globalState, adminLog: await adminLog(), paramHistory: await paramHistory(), authority: authority.publicKey })
+            .rpc(),
+          "InvalidConfig"
+        );
+      }
+    });
+  });
+
+  describe("insurance claims", () => {
+    const owner = Keypair.generate();
+    const investor = Keypair.generate();
+    const lp = Keypair.generate();
+    const PAUSE_CLAIM = 4;
+    let investorAta: PublicKey;
+    let businessAta: PublicKey;
+
+    const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+    const fund = async (invoice: PublicKey, maxPremium: anchor.BN) =>
+      program.methods
+        .fundInvoice(
+          new anchor.BN(100_000_000),
+          false,
+          maxPremium,
+          (await program.account.invoice.fetch(invoice)).riskScore,
+          (await program.account.invoice.fetch(invoice)).dueDate
+        )
+        .accountsPartial({
+          invoice,
+          debtor: await env.debtorOf(invoice),
+          globalState,
+          investor: investor.publicKey,
+          investorTokenAccount: investorAta,
+          businessTokenAccount: businessAta,
+          insurancePoolAccount: insurancePool,
+          experiment: null,
+          payoutProcessor: null,
+          outboxPage: null,
+          outboxEscrow: null,
+          investorBalance: null,
+          investorWhitelist: null,
+          investorCustody: null,
+          originatorTokenAccount: null,
+          originatorStats: null,
+          mint: usdcMint,
+          pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+          portfolio: portfolio(investor.publicKey),
+        })
+        .signers([investor])
+        .rpc();
+
+    const claim = (invoice: PublicKey) =>
+      program.methods
+        .claimInsurance()
+        .accountsPartial({
+          invoice,
+          globalState,
+          investor: investor.publicKey,
+          investorTokenAccount: investorAta,
+          insurancePoolAccount: insurancePool,
+          insurancePoolAuthority,
+          fundingShare: null,
+          invoiceVault: null,
+          pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+          portfolio: portfolio(investor.publicKey),
+          receiptMint: env.receiptMintPda(invoice),
+          investorReceipt: env.receiptAccount(invoice, investor.publicKey),
+        })
+        .signers([investor])
+        .rpc();
+
+    before(async () => {
+      for (const key of [owner, investor, lp]) {
+        await airdrop(key.publicKey);
+      }
+      investorAta = await env.usdcAccount(investor.publicKey);
+      businessAta = await env.usdcAccount(owner.publicKey);
+      const lpAta = await env.usdcAccount(lp.publicKey);
+      await mintTo(provider.connection, authority.payer, usdcMint, investorAta, authority.publicKey, 1_000_000_000);
+      await mintTo(provider.connection, authority.payer, usdcMint, lpAta, authority.publicKey, 1_000_000_000);
+
+      await program.methods
+        .depositInsuranceLiquidity(new anchor.BN(500_000_000))
+        .accountsPartial({
+          globalState,
+          provider: lp.publicKey,
+          providerTokenAccount: lpAta,
+          insurancePoolAccount: insurancePool,
+        })
+        .signers([lp])
+        .rpc();
+    });
+
+    it("pays a defaulted invoice's claim out of the pool", async () => {
+      const { config } = await program.account.globalState.fetch(globalState);
+      const updateConfig = async (next: any) =>
+        program.methods
+          .updateConfig(next)
+          .accountsPartial({ globalState, adminLog: await adminLog(), paramHistory: await paramHistory(), authority: authority.publicKey })
+          .rpc();
+
+      // No grace period so the invoice can default a few seconds after it is due
+      await updateConfig({ ...config, gracePeriodDays: 0 });
+      try {
+        const dueAt = now() + 4;
+        const { invoice } = await createInvoice(owner, { dueAt });
+        const { insurancePremium } = await program.account.invoice.fetch(invoice);
+        await fund(invoice, insurancePremium);
+
+        await sleep((dueAt - now() + 2) * 1000);
+        await program.methods
+          .markDefaulted()
+          .accountsPartial({
+            invoice,
+            debtor: await env.debtorOf(invoice),
+            investorStats: await env.investorStatsOf(invoice),
+            globalState,
+            experiment: null,
+            pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+            portfolio: await env.portfolioOf(invoice),
+          })
+          .rpc();
+
+        // A claims pause holds the payout back until lifted
+        await program.methods
+          .pause(PAUSE_CLAIM)
+          .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+          .rpc();
+        await expectError(claim(invoice), "ProtocolPaused");
+        await program.methods
+          .unpause(PAUSE_CLAIM)
+          .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+          .rpc();
+
+        const investorBefore = await env.tokenBalance(investorAta);
+        const poolBefore = await env.tokenBalance(insurancePool);
+        const stateBefore = await program.account.globalState.fetch(globalState);
+        await claim(invoice);
+
+        const { insurancePayout, coveragePercentage } = await program.account.invoice.fetch(invoice);
+        assert.equal(insurancePayout.toNumber(), coveragePercentage * 1_000_000);
+        const payout = insurancePayout.toNumber();
+        assert.equal(await env.tokenBalance(investorAta), investorBefore + payout);
+        assert.equal(await env.tokenBalance(insurancePool), poolBefore - payout);
+        // The claim settles the position receipt
+        assert.isTrue((await program.account.invoice.fetch(invoice)).receiptRedeemed);
+        assert.equal((await getMint(provider.connection, env.receiptMintPda(invoice))).supply, BigInt(0));
+
+        const stateAfter = await program.account.globalState.fetch(globalState);
+        assert.equal(
+          stateAfter.insurancePoolBalance.toString(),
+          stateBefore.insurancePoolBalance.sub(insurancePayout).toString()
+        );
+        await expectError(claim(invoice), "InsuranceAlreadyClaimed");
+      } finally {
+        await updateConfig(config);
+      }
+    });
+
+    it("funds at the simulated premium only while it stays within max_premium", async () => {
+      const { invoice } = await createInvoice(owner);
+      const { insurancePremium } = await program.account.invoice.fetch(invoice);
+      assert.isAbove(insurancePremium.toNumber(), 0);
+
+      // A bound under the quoted premium fails cleanly and leaves the listing open
+      await expectError(fund(invoice, insurancePremium.subn(1)), "SlippageExceeded");
+      assert.deepEqual((await program.account.invoice.fetch(invoice)).status, { pendingFunding: {} });
+
+      await fund(invoice, insurancePremium.addn(1_000));
+      assert.deepEqual((await program.account.invoice.fetch(invoice)).status, { funded: {} });
+    });
+  });
+
+  describe("parameter history", () => {
+    const owner = Keypair.generate();
+    const investor = Keypair.generate();
+    let investorAta: PublicKey;
+    let businessAta: PublicKey;
+
+    const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+    const setGrace = async (gracePeriodDays: number) => {
+      const { config } = await program.account.globalState.fetch(globalState);
+      await program.methods
+        .updateConfig({ ...config, gracePeriodDays })
+        .accountsPartial({
+          globalState,
+          adminLog: await adminLog(),
+          paramHistory: await paramHistory(),
+          authority: authority.publicKey,
+        })
+        .rpc();
+      // Keep each regime in its own second so lookups by timestamp are unambiguous
+      await sleep(1_100);
+    };
+
+    const graceAt = async (timestamp: anchor.BN) => {
+      const { paramVersion } = await program.account.globalState.fetch(globalState);
+      const pages = Array.from({ length: Math.ceil(paramVersion / PARAM_HISTORY_PAGE_SIZE) }, (_, page) => ({
+        pubkey: paramHistoryPage(page),
+        isSigner: false,
+        isWritable: false,
+      }));
+      return program.methods
+        .getParamAt({ gracePeriodDays: {} }, timestamp)
+        .accountsPartial({ globalState })
+        .remainingAccounts(pages)
+        .view();
+    };
+
+    const listAndFund = async (dueAt?: number) => {
+      const { invoice } = await createInvoice(owner, { dueAt });
+      const { insurancePremium, riskScore, dueDate } = await program.account.invoice.fetch(invoice);
+      await program.methods
+        .fundInvoice(new anchor.BN(100_000_000), false, insurancePremium, riskScore, dueDate)
+        .accountsPartial({
+          invoice,
+          debtor: await env.debtorOf(invoice),
+          globalState,
+          investor: investor.publicKey,
+          investorTokenAccount: investorAta,
+          businessTokenAccount: businessAta,
+          insurancePoolAccount: insurancePool,
+          experiment: null,
+          payoutProcessor: null,
+          outboxPage: null,
+          outboxEscrow: null,
+          investorBalance: null,
+          investorWhitelist: null,
+          investorCustody: null,
+          originatorTokenAccount: null,
+          originatorStats: null,
+          mint: usdcMint,
+          pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+          portfolio: portfolio(investor.publicKey),
+        })
+        .signers([investor])
+        .rpc();
+      return invoice;
+    };
+
+    before(async () => {
+      await airdrop(owner.publicKey);
+      await airdrop(investor.publicKey);
+      investorAta = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, authority.payer, usdcMint, investor.publicKey)
+      ).address;
+      businessAta = (
+        await getOrCreateAssociatedTokenAccount(provider.connection, authority.payer, usdcMint, owner.publicKey)
+      ).address;
+      await mintTo(provider.connection, authority.payer, usdcMint, investorAta, authority.publicKey, 1_000_000_000);
+    });
+
+    it("agrees with each invoice's records on which grace period applied", async () => {
+      const { config } = await program.account.globalState.fetch(globalState);
+      try {
+        // Regime 1: no grace period; this invoice is funded and defaults under it
+        await setGrace(0);
+        const first = await listAndFund(now() + 3);
+        await sleep(5_000);
+        await program.methods
+          .markDefaulted()
+          .accountsPartial({
+            invoice: first,
+            debtor: await env.debtorOf(first),
+            investorStats: await env.investorStatsOf(first),
+            globalState,
+            experiment: null,
+            pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+            portfolio: await env.portfolioOf(first),
+          })
+          .rpc();
+
+        // Regime 2: a week of grace; this invoice is funded under it
+        await setGrace(7);
+        const second = await listAndFund();
+
+        const defaulted = await program.account.invoice.fetch(first);
+        const atDefault = await graceAt(defaulted.defaultedAt);
+        assert.equal(atDefault.value.toNumber(), 0);
+        assert.equal(atDefault.version, defaulted.paramVersions.settled);
+        assert.equal(defaulted.paramVersions.funded, defaulted.paramVersions.settled);
+
+        const funded = await program.account.invoice.fetch(second);
+        const atFunding = await graceAt(funded.fundingDate);
+        assert.equal(atFunding.value.toNumber(), 7);
+        assert.equal(atFunding.version, funded.paramVersions.funded);
+        assert.equal(funded.paramVersions.funded, defaulted.paramVersions.settled + 1);
+
+        // Before the first regime the original value still reads back
+        const beforeBoth = await graceAt(defaulted.createdAt.subn(3_600));
+        assert.equal(beforeBoth.value.toNumber(), config.gracePeriodDays);
+      } finally {
+        await program.methods
+          .updateConfig(config)
+          .accountsPartial({
+            globalState,
+            adminLog: await adminLog(),
+            paramHistory: await paramHistory(),
+            authority: authority.publicKey,
+          })
+          .rpc();
+      }
+    });
+
+    it("refuses a lookup without every history page", async () => {
+      await expectError(
+        program.methods.getParamAt({ gracePeriodDays: {} }, new anchor.BN(now())).accountsPartial({ globalState }).view(),
+        "ParamHistoryIncomplete"
+      );
+    });
+  });
+
+  describe("invoice repayment", () => {
+    const owner = Keypair.generate();
+    const investor = Keypair.generate();
+    let investorAta: PublicKey;
+    let businessAta: PublicKey;
+    let invoice: PublicKey;
+
+    const repay = async (
+      signer: Keypair,
+      businessTokenAccount: PublicKey,
+      maxTotal: anchor.BN | null,
+      investorTokenAccount = investorAta
+    ) =>
+      program.methods
+        .repayInvoice(new anchor.BN(100_000_000), maxTotal)
+        .accountsPartial({
+          invoice,
+          debtor: await env.debtorOf(invoice),
+          businessOwner: signer.publicKey,
+          globalState,
+          businessTokenAccount,
+          investorTokenAccount,
+          invoiceVault: invoiceVault(invoice),
+          experiment: null,
+          investorStats: await env.investorStatsOf(invoice),
+          pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+          portfolio: await env.portfolioOf(invoice),
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      await airdrop(owner.publicKey);
+      await airdrop(investor.publicKey);
+      investorAta = await env.tokenAccount(usdcMint, investor.publicKey);
+      businessAta = await env.tokenAccount(usdcMint, owner.publicKey);
+      await mintTo(provider.connection, authority.payer, usdcMint, investorAta, authority.publicKey, 1_000_000_000);
+      // Headroom for the interest owed on top of the principal received
+      await mintTo(provider.connection, authority.payer, usdcMint, businessAta, authority.publicKey, 50_000_000);
+
+      ({ invoice } = await createInvoice(owner));
+      const { insurancePremium, riskScore, dueDate } = await program.account.invoice.fetch(invoice);
+      await program.methods
+        .fundInvoice(new anchor.BN(100_000_000), false, insurancePremium, riskScore, dueDate)
+        .accountsPartial({
+          invoice,
+          debtor: await env.debtorOf(invoice),
+          globalState,
+          investor: investor.publicKey,
+          investorTokenAccount: investorAta,
+          businessTokenAccount: businessAta,
+          insurancePoolAccount: insurancePool,
+          experiment: null,
+          payoutProcessor: null,
+          outboxPage: null,
+          outboxEscrow: null,
+          investorBalance: null,
+          investorWhitelist: null,
+          investorCustody: null,
+          originatorTokenAccount: null,
+          originatorStats: null,
+          mint: usdcMint,
+          pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+          portfolio: portfolio(investor.publicKey),
+        })
+        .signers([investor])
+        .rpc();
+    });
+
+    it("rejects a token account for another mint", async () => {
+      const otherMint = await createMint(provider.connection, authority.payer, authority.publicKey, null, 6);
+      const otherAta = await env.tokenAccount(otherMint, owner.publicKey);
+      await mintTo(provider.connection, authority.payer, otherMint, otherAta, authority.publicKey, 500_000_000);
+
+      await expectError(repay(owner, otherAta, null), "TokenMintMismatch");
+      assert.deepEqual((await program.account.invoice.fetch(invoice)).status, { funded: {} });
+    });
+
+    it("only lets the business owner repay", async () => {
+      const stranger = Keypair.generate();
+      await airdrop(stranger.publicKey);
+      const strangerAta = await env.tokenAccount(usdcMint, stranger.publicKey);
+      await mintTo(provider.connection, authority.payer, usdcMint, strangerAta, authority.publicKey, 200_000_000);
+      await expectError(repay(stranger, strangerAta, null), "RepayerNotBusinessOwner");
+      assert.deepEqual((await program.account.invoice.fetch(invoice)).status, { funded: {} });
+    });
+
+    it("only pays the investor's own token account", async () => {
+      const stranger = Keypair.generate();
+      await expectError(
+        repay(owner, businessAta, null, await env.tokenAccount(usdcMint, stranger.publicKey)),
+        "RepaymentDestinationMismatch"
+      );
+      await expectError(repay(owner, businessAta, null, businessAta), "RepaymentDestinationMismatch");
+      assert.deepEqual((await program.account.invoice.fetch(invoice)).status, { funded: {} });
+    });
+
+    it("will not settle at par", async () => {
+      // The yield accrued so far is owed on top of the 100 USDC principal
+      await expectError(repay(owner, businessAta, new anchor.BN(100_000_000)), "SlippageExceeded");
+      assert.deepEqual((await program.account.invoice.fetch(invoice)).status, { funded: {} });
+    });
+
+    it("pays the investor off in USDC against their receipt", async () => {
+      const investorBefore = (await getAccount(provider.connection, investorAta)).amount;
+      await repay(owner, businessAta, new anchor.BN(150_000_000));
+
+      const repaid = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(repaid.status, { repaid: {} });
+      assert.isAbove(repaid.interestPaid.toNumber(), 0);
+      assert.equal(repaid.finalRepaymentAmount.toNumber(), repaid.fundedAmount.add(repaid.interestPaid).toNumber());
+      assert.equal(
+        (await getAccount(provider.connection, invoiceVault(invoice))).amount,
+        BigInt(repaid.finalRepaymentAmount.toString())
+      );
+
+      await program.methods
+        .redeemReceipt()
+        .accountsPartial({
+          invoice,
+          holderReceipt: env.receiptAccount(invoice, investor.publicKey),
+          holder: investor.publicKey,
+          holderTokenAccount: investorAta,
+        })
+        .signers([investor])
+        .rpc();
+      assert.isTrue((await program.account.invoice.fetch(invoice)).receiptRedeemed);
+      assert.equal(
+        (await getAccount(provider.connection, investorAta)).amount,
+        investorBefore + BigInt(repaid.finalRepaymentAmount.toString())
+      );
+      const { paramVersion } = await program.account.globalState.fetch(globalState);
+      assert.equal(repaid.paramVersions.settled, paramVersion);
+
+      // The settlement goes on the business's credit history
+      const { creditHistory } = await program.account.businessProfile.fetch(env.businessProfilePda(owner.publicKey));
+      assert.equal(creditHistory.repaidOnTime, 1);
+      assert.equal(creditHistory.repaidLate + creditHistory.defaulted, 0);
+      assert.equal(creditHistory.volumeFinanced.toString(), repaid.fundedAmount.toString());
+    });
+
+    it("only sweeps repayments left unredeemed for two years", async () => {
+      await expectError(
+        program.methods
+          .sweepUnclaimedRepayment()
+          .accountsPartial({
+            invoice,
+            invoiceVault: invoiceVault(invoice),
+            insurancePoolAccount: insurancePool,
+            globalState,
+            authority: authority.publicKey,
+            payer: authority.publicKey,
+          })
+          .rpc(),
+        "UnclaimedSweepNotOpen"
+      );
+    });
+  });
+
+  describe("funding account checks", () => {
+    const owner = Keypair.generate();
+    const investor = Keypair.generate();
+    const attacker = Keypair.generate();
+    let investorAta: PublicKey;
+    let businessAta: PublicKey;
+    let invoice: PublicKey;
+
+    const fund = async (overrides: Record<string, PublicKey> = {}) =>
+      program.methods
+        .fundInvoice(
+          new anchor.BN(100_000_000),
+          false,
+          new anchor.BN(100_000_000),
+          (await program.account.invoice.fetch(invoice)).riskScore,
+          (await program.account.invoice.fetch(invoice)).dueDate
+        )
+        .accountsPartial({
+          invoice,
+          debtor: await env.debtorOf(invoice),
+          globalState,
+          investor: investor.publicKey,
+          investorTokenAccount: investorAta,
+          businessTokenAccount: businessAta,
+          insurancePoolAccount: insurancePool,
+          experiment: null,
+          payoutProcessor: null,
+          outboxPage: null,
+          outboxEscrow: null,
+          investorBalance: null,
+          investorWhitelist: null,
+          investorCustody: null,
+          originatorTokenAccount: null,
+          originatorStats: null,
+          mint: usdcMint,
+          pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+          portfolio: portfolio(investor.publicKey),
+          ...overrides,
+        })
+        .signers([investor])
+        .rpc();
+
+    before(async () => {
+      for (const key of [owner, investor, attacker]) {
+        await airdrop(key.publicKey);
+      }
+      investorAta = await env.usdcAccount(investor.publicKey);
+      businessAta = await env.usdcAccount(owner.publicKey);
+      await mintTo(provider.connection, authority.payer, usdcMint, investorAta, authority.publicKey, 1_000_000_000);
+      ({ invoice } = await createInvoice(owner));
+    });
+
+    it("refuses to pay the principal to anyone but the business owner", async () => {
+      await expectError(fund({ businessTokenAccount: await env.usdcAccount(attacker.publicKey) }), "ConstraintTokenOwner");
+    });
+
+    it("refuses a premium destination other than the insurance pool", async () => {
+      const fakePool = await createAccount(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        attacker.publicKey,
+        Keypair.generate()
+      );
+      await expectError(fund({ insurancePoolAccount: fakePool }), "ConstraintSeeds");
+    });
+
+    it("refuses accounts that are not the program's global state or invoice", async () => {
+      // A program-owned account at the wrong address never passes for either
+      await expectError(fund({ globalState: invoice }), "AccountDiscriminatorMismatch");
+      await expectError(fund({ invoice: globalState }), "AccountDiscriminatorMismatch");
+    });
+
+    it("funds through the genuine accounts", async () => {
+      const businessBefore = (await getAccount(provider.connection, businessAta)).amount;
+      await fund();
+
+      const funded = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(funded.status, { funded: {} });
+      assert.ok(funded.investor.equals(investor.publicKey));
+      assert.isTrue((await getAccount(provider.connection, businessAta)).amount > businessBefore);
+    });
+  });
+
+  describe("invoice addresses", () => {
+    it("numbers invoices per business so ids never collide across owners", async () => {
+      const alice = await env.createBusiness();
+      const bob = await env.createBusiness();
+
+      const a1 = await env.createInvoice(alice);
+      const b1 = await env.createInvoice(bob);
+      const a2 = await env.createInvoice(alice);
+
+      assert.equal(a1.invoiceId.toNumber(), 1);
+      assert.equal(b1.invoiceId.toNumber(), 1);
+      assert.equal(a2.invoiceId.toNumber(), 2);
+      assert.ok(a1.invoice.equals(invoicePda(alice.publicKey, new anchor.BN(1))));
+      assert.ok(b1.invoice.equals(invoicePda(bob.publicKey, new anchor.BN(1))));
+      assert.notOk(a1.invoice.equals(b1.invoice));
+
+      const fetched = await program.account.invoice.fetch(b1.invoice);
+      assert.ok(fetched.businessOwner.equals(bob.publicKey));
+      assert.equal(
+        (await program.account.businessProfile.fetch(env.businessProfilePda(alice.publicKey))).invoicesCreated.toNumber(),
+        2
+      );
+    });
+  });
+
+  describe("vault integrity", () => {
+    const [payoutProcessor] = PublicKey.findProgramAddressSync([Buffer.from("payout_processor")], program.programId);
+    const [outboxAuthority] = PublicKey.findProgramAddressSync([Buffer.from("outbox_authority")], program.programId);
+    const outboxPage0 = PublicKey.findProgramAddressSync(
+      [Buffer.from("outbox"), Buffer.alloc(4)],
+      program.programId
+    )[0];
+    let attacker: Keypair;
+    let poisoned: PublicKey;
+
+    type Kind = Parameters<typeof program.methods.verifyVaultIntegrity>[0];
+    const verify = (kind: Kind, vault: PublicKey, extra: Record<string, PublicKey> = {}) =>
+      program.methods.verifyVaultIntegrity(kind).accountsPartial({
+        globalState,
+        vault,
+        payoutProcessor: null,
+        investorBalance: null,
+        invoice: null,
+        ...extra,
+      });
+
+    before(async () => {
+      attacker = Keypair.generate();
+      await airdrop(attacker.publicKey);
+      // Looks like an escrow, was built outside the program, and has a delegate
+      // standing by to drain whatever lands in it
+      poisoned = await createAccount(provider.connection, authority.payer, usdcMint, attacker.publicKey, Keypair.generate());
+      await approve(provider.connection, authority.payer, poisoned, Keypair.generate().publicKey, attacker, 1_000_000_000);
+      assert.isNotNull((await getAccount(provider.connection, poisoned)).delegate);
+    });
+
+    it("reports the insurance pool as owned by its authority with no delegate", async () => {
+      const report = await verify({ insurancePool: {} }, insurancePool).view();
+      assert.deepEqual(report.integrity, { ownerOk: true, delegateClear: true, closeAuthorityOk: true });
+      assert.equal(report.amount.toString(), (await getAccount(provider.connection, insurancePool)).amount.toString());
+      assert.equal(
+        report.expectedAmount.toString(),
+        (await program.account.globalState.fetch(globalState)).insurancePoolBalance.toString()
+      );
+    });
+
+    it("will not vouch for a poisoned account in place of a vault", async () => {
+      await expectError(verify({ insurancePool: {} }, poisoned).rpc(), "VaultAddressMismatch");
+      await expectError(verify({ outboxEscrow: {} }, poisoned, { payoutProcessor }).rpc(), "VaultAddressMismatch");
+      await expectError(verify({ outboxEscrow: {} }, poisoned).rpc(), "VaultContextMissing");
+    });
+
+    it("refuses a poisoned escrow for an off-ramp payout", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const investor = await env.createInvestor();
+      const { invoice } = await createInvoice(owner, { offramp: true });
+
+      await expectError(
+        program.methods
+          .fundInvoice(
+          new anchor.BN(100_000_000),
+          false,
+          new anchor.BN(100_000_000),
+          (await program.account.invoice.fetch(invoice)).riskScore,
+          (await program.account.invoice.fetch(invoice)).dueDate
+        )
+          .accountsPartial({
+            invoice,
+            debtor: await env.debtorOf(invoice),
+            globalState,
+            investor: investor.publicKey,
+            investorTokenAccount: investor.usdc,
+            businessTokenAccount: await env.usdcAccount(owner.publicKey),
+            insurancePoolAccount: insurancePool,
+            experiment: null,
+            payoutProcessor,
+            outboxPage: outboxPage0,
+            outboxEscrow: poisoned,
+            investorBalance: null,
+            investorWhitelist: null,
+            investorCustody: null,
+            originatorTokenAccount: null,
+            originatorStats: null,
+            mint: usdcMint,
+            pairLedger: pairLedger(owner.publicKey, investor.publicKey),
+            portfolio: portfolio(investor.publicKey),
+          })
+          .signers([investor.keypair])
+          .rpc(),
+        "InvalidOutboxEscrow"
+      );
+    });
+
+    it("refuses a poisoned escrow when the business cancels an outbox entry", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner, { offramp: true });
+      // Even one owned by the escrow authority, which anyone can create
+      const ownedByAuthority = await createAccount(
+        provider.connection,
+        authority.payer,
+        usdcMint,
+        outboxAuthority,
+        Keypair.generate()
+      );
+
+      for (const outboxEscrow of [poisoned, ownedByAuthority]) {
+        await expectError(
+          program.methods
+            .cancelOutboxEntry(new anchor.BN(0))
+            .accountsPartial({
+              payoutProcessor,
+              outboxPage: outboxPage0,
+              invoice,
+              outboxEscrow,
+              globalState,
+              businessTokenAccount: await env.usdcAccount(owner.publicKey),
+              outboxAuthority,
+              businessOwner: owner.publicKey,
+            })
+            .signers([owner])
+            .rpc(),
+          "InvalidOutboxEscrow"
+        );
+      }
+    });
+  });
+
+  describe("stray fund recovery", () => {
+    type Kind = Parameters<typeof program.methods.recoverForeignTokens>[0];
+    const [lostAndFound] = PublicKey.findProgramAddressSync(
+      [Buffer.from("allowlist"), Buffer.from([2])],
+      program.programId
+    );
+    const recover = async (kind: Kind, source: PublicKey, destination: PublicKey, amount: number) =>
+      program.methods.recoverForeignTokens(kind, new anchor.BN(amount)).accountsPartial({
+        globalState,
+        adminLog: await adminLog(),
+        source,
+        destination,
+        owner: insurancePoolAuthority,
+        payoutProcessor: null,
+        investorBalance: null,
+        invoice: null,
+        allowlist: lostAndFound,
+        authority: authority.publicKey,
+      });
+
+    // The authority's wallet goes on the lost-and-found allowlist, where it stays
+    // pending for the length of the timelock
+    before(async () => {
+      await program.methods
+        .registerDestination({ lostAndFound: {} }, Array.from(Buffer.alloc(32, "ops wallet")))
+        .accountsPartial({
+          allowlist: lostAndFound,
+          globalState,
+          adminLog: await adminLog(),
+          destination: authority.publicKey,
+          authority: authority.publicKey,
+        })
+        .rpc();
+    });
+
+    it("refuses to pay stray tokens anywhere but the lost-and-found allowlist", async () => {
+      const stray = await createMint(provider.connection, authority.payer, authority.publicKey, null, 6);
+      const held = await createAccount(provider.connection, authority.payer, stray, insurancePoolAuthority, Keypair.generate());
+      await mintTo(provider.connection, authority.payer, stray, held, authority.publicKey, 5_000_000);
+      const destination = await env.tokenAccount(stray, authority.publicKey);
+
+      await expectError(recover({ insurancePool: {} }, held, destination, 5_000_000).rpc(), "DestinationNotAllowlisted");
+      assert.equal((await getAccount(provider.connection, held)).amount, BigInt(5_000_000));
+    });
+
+    it("will not recover the USDC pool balance", async () => {
+      const destination = await env.usdcAccount(authority.publicKey);
+      await expectError(recover({ insurancePool: {} }, insurancePool, destination, 1).rpc(), "RecoveryProtected");
+    });
+
+    it("holds excess lamports until their destination is active on the allowlist", async () => {
+      const extra = 2_000_000;
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(
+          anchor.web3.SystemProgram.transfer({ fromPubkey: authority.publicKey, toPubkey: globalState, lamports: extra })
+        )
+      );
+      const before = (await provider.connection.getAccountInfo(globalState))!.lamports;
+
+      await expectError(
+        program.methods
+          .recoverExcessLamports()
+          .accountsPartial({
+            globalState,
+            adminLog: await adminLog(),
+            account: globalState,
+            destination: authority.publicKey,
+            allowlist: lostAndFound,
+            authority: authority.publicKey,
+          })
+          .rpc(),
+        "DestinationNotAllowlisted"
+      );
+      assert.equal((await provider.connection.getAccountInfo(globalState))!.lamports, before);
+    });
+  });
+
+  describe("listing review", () => {
+    it("reports every problem with a bad listing at once", async () => {
+      const { config } = await program.account.globalState.fetch(globalState);
+      const problems = await program.methods
+        .validateListing(
+          config.maxInvoiceAmount.addn(1),
+          new anchor.BN(now() + (config.maxTermDays + 30) * DAY),
+          new Array(32).fill(0),
+          null,
+          false,
+          false,
+          null
+        )
+        .accountsPartial({ globalState, businessProfile: null })
+        .view();
+
+      assert.deepEqual(problems, [
+        { reason: { amountTooLarge: {} }, hint: { reduceAmount: {} } },
+        { reason: { tenorTooLong: {} }, hint: { shortenTenor: {} } },
+        { reason: { debtorInfoTooShort: {} }, hint: { provideDebtorDetails: {} } },
+      ]);
+    });
+
+    it("passes a listing create_invoice would accept", async () => {
+      const problems = await program.methods
+        .validateListing(
+          new anchor.BN(10_000_000),
+          new anchor.BN(now() + 30 * DAY),
+          debtorInfoHash("Acme Corp, net 30"),
+          "ar://bNbA3TEQVL60xlgCcqdz4ZPHFZ711cZ3hmkpGttDt_U",
+          false,
+          false,
+          null
+        )
+        .accountsPartial({ globalState, businessProfile: null })
+        .view();
+      assert.lengthOf(problems, 0);
+    });
+
+    it("delists with a review reason the business can act on", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const { invoice } = await createInvoice(owner);
+      const delist = (reason: Parameters<typeof program.methods.delistInvoice>[0]) =>
+        program.methods
+          .delistInvoice(reason)
+          .accountsPartial({ invoice, globalState, authority: authority.publicKey, payer: authority.publicKey })
+          .rpc();
+
+      await expectError(delist({ amountTooLarge: {} }), "NotAReviewReason");
+      await delist({ debtorUnconfirmed: {} });
+
+      const delisted = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(delisted.status, { delisted: {} });
+      assert.deepEqual(delisted.rejectionReason, { debtorUnconfirmed: {} });
+
+      // The business can still withdraw the listing and resubmit
+      await program.methods
+        .cancelInvoice()
+        .accountsPartial({ invoice, globalState, businessOwner: owner.publicKey })
+        .signers([owner])
+        .rpc();
+      assert.isNull(await program.account.invoice.fetchNullable(invoice));
+    });
+  });
+
+  describe("pair statement", () => {
+    it("nets everything between one business and one investor", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const investor = await env.createInvestor();
+      const statement = () =>
+        program.methods.getPairStatement(business.publicKey, investor.publicKey).accountsPartial({}).view();
+      const closeLedger = () =>
+        program.methods
+          .closePairLedger()
+          .accountsPartial({
+            pairLedger: pairLedger(business.publicKey, investor.publicKey),
+            business: business.publicKey,
+            investor: investor.publicKey,
+          })
+          .signers([business.keypair, investor.keypair])
+          .rpc();
+
+      await env.withConfig({ gracePeriodDays: 0 }, async () => {
+        const { invoice: repaid } = await env.createInvoice(business).amount(60_000_000).listed();
+        const { invoice: defaulted } = await env.createInvoice(business).amount(40_000_000).dueInSeconds(5).listed();
+        await env.fund(repaid).by(investor);
+        await env.fund(defaulted).by(investor);
+
+        await program.methods
+          .repayInvoice(new anchor.BN(1), new anchor.BN(1_000_000_000))
+          .accountsPartial({
+            invoice: repaid,
+            debtor: await env.debtorOf(repaid),
+            businessOwner: business.publicKey,
+            globalState,
+            businessTokenAccount: business.usdc,
+            investorTokenAccount: investor.usdc,
+            invoiceVault: invoiceVault(repaid),
+            experiment: null,
+            investorStats: await env.investorStatsOf(repaid),
+            pairLedger: pairLedger(business.publicKey, investor.publicKey),
+            portfolio: await env.portfolioOf(repaid),
+          })
+          .signers([business.keypair])
+          .rpc();
+
+        let view = await statement();
+        assert.equal(view.invoicesFinanced.toNumber(), 2);
+        assert.equal(view.openInvoices.toNumber(), 1);
+        assert.equal(view.totalFinanced.toNumber(), 100_000_000);
+        assert.equal(view.outstanding.toNumber(), 40_000_000);
+        assert.isAtLeast(view.totalRepaid.toNumber(), 60_000_000);
+        assert.isNotNull(view.averageDaysToPay);
+        await expectError(closeLedger(), "PairExposureOutstanding");
+
+        await env.warpTo(defaulted, "defaultable");
+        await env.markDefaulted(defaulted);
+        view = await statement();
+        assert.equal(view.openInvoices.toNumber(), 0);
+        assert.equal(view.invoicesDefaulted.toNumber(), 1);
+        assert.equal(view.totalDefaulted.toNumber(), 40_000_000);
+        assert.equal(view.outstanding.toNumber(), 0);
+        assert.deepEqual(
+          view.recent.map((entry) => Object.keys(entry.kind)[0]),
+          ["funding", "funding", "repayment", "default"]
+        );
+      });
+
+      // Nothing left open between them, so the pair can settle up and close
+      await closeLedger();
+      assert.isNull(
+        await program.account.pairLedger.fetchNullable(pairLedger(business.publicKey, investor.publicKey))
+      );
+    });
+  });
+
+  describe("investor portfolio", () => {
+    it("keeps its counters reconciled through repayment, default and clawback", async () => {
+      const business = await env.createBusiness().withUsdc(150_000_000);
+      const investor = await env.createInvestor();
+      const view = () => program.methods.getInvestorPortfolio(investor.publicKey).accountsPartial({}).view();
+      const reconciled = async () => {
+        const totals = await view();
+        assert.equal(
+          totals.principalDeployed.toNumber(),
+          totals.outstanding.toNumber() +
+            totals.principalRepaid.toNumber() +
+            totals.principalWrittenOff.toNumber() +
+            totals.principalSold.toNumber()
+        );
+        return totals;
+      };
+      const repay = async (invoice: PublicKey, amount: number, maxTotal: number | null) =>
+        program.methods
+          .repayInvoice(new anchor.BN(amount), maxTotal === null ? null : new anchor.BN(maxTotal))
+          .accountsPartial({
+            invoice,
+            debtor: await env.debtorOf(invoice),
+            businessOwner: business.publicKey,
+            globalState,
+            businessTokenAccount: business.usdc,
+            investorTokenAccount: null,
+            invoiceVault: invoiceVault(invoice),
+            experiment: null,
+            investorStats: await env.investorStatsOf(invoice),
+            pairLedger: pairLedger(business.publicKey, investor.publicKey),
+            portfolio: await env.portfolioOf(invoice),
+          })
+          .signers([business.keypair])
+          .rpc();
+
+      await env.withConfig({ gracePeriodDays: 0 }, async () => {
+        const { invoice: repaid } = await env.createInvoice(business).amount(60_000_000).listed();
+        const { invoice: defaulted } = await env.createInvoice(business).amount(40_000_000).dueInSeconds(5).listed();
+        let premiums = 0;
+        for (const invoice of [repaid, defaulted]) {
+          premiums += (await program.account.invoice.fetch(invoice)).insurancePremium.toNumber();
+          await env.fund(invoice).by(investor);
+        }
+
+        let totals = await reconciled();
+        assert.equal(totals.invoicesFunded, 2);
+        assert.equal(totals.principalDeployed.toNumber(), 100_000_000);
+        assert.equal(totals.premiumsPaid.toNumber(), premiums);
+        assert.equal(totals.outstanding.toNumber(), 100_000_000);
+
+        // A partial repayment moves only what it paid of the principal
+        await repay(repaid, 20_000_000, null);
+        totals = await reconciled();
+        const paidOff = totals.principalRepaid.toNumber();
+        assert.isAtMost(paidOff, 20_000_000);
+        assert.equal(totals.outstanding.toNumber(), 100_000_000 - paidOff);
+        await repay(repaid, 1, 1_000_000_000);
+        totals = await reconciled();
+        assert.equal(totals.principalRepaid.toNumber(), 60_000_000);
+        assert.equal(totals.outstanding.toNumber(), 40_000_000);
+        assert.isAbove(totals.realizedYield.toNumber(), 0);
+
+        await env.warpTo(defaulted, "defaultable");
+        await env.markDefaulted(defaulted);
+        totals = await reconciled();
+        assert.equal(totals.outstanding.toNumber(), 0);
+        assert.equal(totals.principalWrittenOff.toNumber(), 40_000_000);
+        assert.equal(totals.realizedLosses.toNumber(), 40_000_000);
+
+        await env.claimInsurance(defaulted, investor);
+        const payout = (await program.account.invoice.fetch(defaulted)).insurancePayout.toNumber();
+        totals = await reconciled();
+        assert.equal(totals.recovered.toNumber(), payout);
+        assert.equal(totals.realizedLosses.toNumber(), 40_000_000 - payout);
+
+        // The debtor pays after all: the pool claws its payout back, and only the
+        // uncovered rest reaches the investor's portfolio
+        await env.settleRecovery(defaulted, 40_000_000, business);
+        totals = await reconciled();
+        assert.equal(totals.recovered.toNumber(), 40_000_000);
+        assert.equal(totals.realizedLosses.toNumber(), 0);
+        assert.equal(
+          totals.netRealized.toNumber(),
+          totals.realizedYield.toNumber() - totals.premiumsPaid.toNumber()
+        );
+      });
+    });
+  });
+
+  describe("business stats", () => {
+    it("returns the business's track record from its profile", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const investor = await env.createInvestor();
+      const stats = () => program.methods.getBusinessStats(business.publicKey).accountsPartial({}).view();
+
+      await env.withConfig({ gracePeriodDays: 0 }, async () => {
+        const { invoice: repaid } = await env.createInvoice(business).amount(60_000_000).listed();
+        const { invoice: defaulted } = await env.createInvoice(business).amount(40_000_000).dueInSeconds(5).listed();
+        await env.createInvoice(business).amount(10_000_000).listed();
+        await env.fund(repaid).by(investor);
+        await env.fund(defaulted).by(investor);
+
+        let view = await stats();
+        assert.equal(view.invoicesCreated.toNumber(), 3);
+        assert.equal(view.invoicesFunded.toNumber(), 2);
+        assert.equal(view.volumeFunded.toNumber(), 100_000_000);
+        assert.equal(view.outstanding.toNumber(), 100_000_000);
+
+        await program.methods
+          .repayInvoice(new anchor.BN(1), new anchor.BN(1_000_000_000))
+          .accountsPartial({
+            invoice: repaid,
+            debtor: await env.debtorOf(repaid),
+            businessOwner: business.publicKey,
+            globalState,
+            businessTokenAccount: business.usdc,
+            investorTokenAccount: null,
+            invoiceVault: invoiceVault(repaid),
+            experiment: null,
+            investorStats: await env.investorStatsOf(repaid),
+            pairLedger: pairLedger(business.publicKey, investor.publicKey),
+            portfolio: await env.portfolioOf(repaid),
+          })
+          .signers([business.keypair])
+          .rpc();
+        await env.warpTo(defaulted, "defaultable");
+        await env.markDefaulted(defaulted);
+
+        view = await stats();
+        assert.equal(view.repaidOnTime + view.repaidLate, 1);
+        assert.equal(view.defaulted, 1);
+        assert.equal(view.invoicesFunded.toNumber(), 2);
+        assert.equal(view.outstanding.toNumber(), 0);
+      });
+    });
+
+    it("exports the whole history across pages, adding up to the profile", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const investor = await env.createInvestor();
+      const invoices: PublicKey[] = [];
+      for (let i = 0; i < 10; i++) {
+        invoices.push((await env.createInvoice(business).amount(5_000_000).listed()).invoice);
+      }
+      for (const invoice of invoices.slice(0, 4)) {
+        await env.fund(invoice).by(investor);
+      }
+      await env.repayInvoicesBatch(business, invoices.slice(0, 2), 100_000_000);
+      await program.methods
+        .cancelInvoice()
+        .accountsPartial({ invoice: invoices[9], globalState, businessOwner: business.publicKey })
+        .signers([business.keypair])
+        .rpc();
+
+      const created = (await program.account.businessProfile.fetch(business.profile)).invoicesCreated.toNumber();
+      const records = [];
+      let profile = null;
+      let cursor: number | null = 1;
+      let pages = 0;
+      while (cursor !== null) {
+        const start = cursor;
+        const accounts = Array.from({ length: Math.min(8, created - start + 1) }, (_, i) => {
+          const invoice = env.invoicePda(business.publicKey, new anchor.BN(start + i));
+          const audit = env.pda([Buffer.from("invoice_audit"), invoice.toBuffer()]);
+          return [invoice, audit].map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }));
+        }).flat();
+        const page = await program.methods
+          .exportBusinessHistory(business.publicKey, new anchor.BN(start))
+          .accountsPartial({})
+          .remainingAccounts(accounts)
+          .view();
+        assert.equal(page.version, 1);
+        assert.equal(page.cursor.toNumber(), start);
+        profile ??= page.profile;
+        records.push(...page.records);
+        cursor = page.nextCursor === null ? null : page.nextCursor.toNumber();
+        pages++;
+      }
+
+      assert.isAbove(pages, 1);
+      assert.deepEqual(
+        records.map((record) => record.invoiceId.toNumber()),
+        Array.from({ length: created }, (_, i) => i + 1)
+      );
+      const live = records.filter((record) => record.invoice !== null).map((record) => record.invoice);
+      const funded = live.filter((invoice) => invoice.fundingDate !== null);
+      const repaid = live.filter((invoice) => invoice.status.repaid !== undefined);
+      assert.equal(records.length - live.length, 1);
+      assert.equal(funded.length, profile.stats.invoicesFunded.toNumber());
+      assert.equal(
+        funded.reduce((sum, invoice) => sum + invoice.fundedAmount.toNumber(), 0),
+        profile.stats.volumeFunded.toNumber()
+      );
+      assert.equal(repaid.length, profile.stats.repaidOnTime + profile.stats.repaidLate);
+      assert.equal(live.length - repaid.length, profile.stats.openInvoices);
+    });
+  });
+
+  describe("open invoice cap", () => {
+    const setCap = async (cap: number) =>
+      program.methods
+        .setMaxOpenInvoicesPerBusiness(cap)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("holds each business to the cap until its open invoices settle", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const investor = await env.createInvestor();
+      const openInvoices = async () => (await program.account.businessProfile.fetch(business.profile)).openInvoices;
+      const listAtCap = () =>
+        expectError(env.createInvoice(business).amount(10_000_000), "OpenInvoiceLimitReached");
+      const cancel = (invoice: PublicKey) =>
+        program.methods
+          .cancelInvoice()
+          .accountsPartial({ invoice, globalState, businessOwner: business.publicKey })
+          .signers([business.keypair])
+          .rpc();
+
+      await setCap(1);
+      try {
+        await env.withConfig({ gracePeriodDays: 0 }, async () => {
+          // Repaid: open while listed and while funded
+          const { invoice: repaid } = await env.createInvoice(business).amount(10_000_000).listed();
+          assert.equal(await openInvoices(), 1);
+          await listAtCap();
+          const [problem] = await program.methods
+            .validateListing(
+              new anchor.BN(10_000_000),
+              new anchor.BN(now() + 30 * DAY),
+              debtorInfoHash("Acme Corp"),
+              null,
+              false,
+              false,
+              null
+            )
+            .accountsPartial({ globalState, businessProfile: business.profile })
+            .view();
+          assert.deepEqual(problem, { reason: { openInvoiceLimitReached: {} }, hint: { settleOpenInvoices: {} } });
+          await env.fund(repaid).by(investor);
+          await listAtCap();
+          await env.repayInvoicesBatch(business, [repaid], 100_000_000);
+          assert.equal(await openInvoices(), 0);
+
+          // Cancelled
+          const { invoice: cancelled } = await env.createInvoice(business).amount(10_000_000);
+          await listAtCap();
+          await cancel(cancelled);
+          assert.equal(await openInvoices(), 0);
+
+          // Delisted, and cancelling it afterwards frees nothing twice
+          const { invoice: delisted } = await env.createInvoice(business).amount(10_000_000);
+          await program.methods
+            .delistInvoice({ policyViolation: {} })
+            .accountsPartial({ invoice: delisted, globalState, authority: authority.publicKey, payer: authority.publicKey })
+            .rpc();
+          assert.equal(await openInvoices(), 0);
+          await cancel(delisted);
+          assert.equal(await openInvoices(), 0);
+
+          // Expired
+          const deadline = now() + 3;
+          const { invoice: expired } = await env.createInvoice(business).amount(10_000_000).fundingDeadline(deadline);
+          await listAtCap();
+          await sleep((deadline - now() + 2) * 1000);
+          await env.expireInvoice(expired);
+          assert.equal(await openInvoices(), 0);
+
+          // Defaulted
+          const { invoice: defaulted } = await env.createInvoice(business).amount(10_000_000).dueInSeconds(5).listed();
+          await env.fund(defaulted).by(investor);
+          await listAtCap();
+          await env.warpTo(defaulted, "defaultable");
+          await env.markDefaulted(defaulted);
+          assert.equal(await openInvoices(), 0);
+
+          await env.createInvoice(business).amount(10_000_000);
+          assert.equal(await openInvoices(), 1);
+        });
+      } finally {
+        await setCap(0);
+      }
+    });
+
+    it("only lets the authority set the cap", async () => {
+      const stranger = Keypair.generate();
+      await expectError(
+        program.methods
+          .setMaxOpenInvoicesPerBusiness(5)
+          .accountsPartial({ globalState, adminLog: await adminLog(), authority: stranger.publicKey })
+          .signers([stranger])
+          .rpc(),
+        "Unauthorized"
+      );
+      assert.equal((await program.account.globalState.fetch(globalState)).maxOpenInvoicesPerBusiness, 0);
+    });
+  });
+
+  describe("blacklist", () => {
+    const blacklist = async (party: PublicKey, role: object, stranger?: Keypair) =>
+      program.methods
+        .blacklist(party, role as any, { confirmedFraud: {} })
+        .accountsPartial({
+          globalState,
+          adminLog: await adminLog(),
+          authority: (stranger ?? authority).publicKey,
+          payer: authority.publicKey,
+        })
+        .signers(stranger ? [stranger] : [])
+        .rpc();
+    const unblacklist = async (party: PublicKey, role: object, reason: object = { appealUpheld: {} }) =>
+      program.methods
+        .unblacklist(role as any, reason as any)
+        .accountsPartial({
+          blacklistEntry: env.blacklistPda(party),
+          globalState,
+          adminLog: await adminLog(),
+          authority: authority.publicKey,
+        })
+        .rpc();
+
+    it("bars an investor from funding until cleared, leaving its role as a business alone", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const investor = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).amount(10_000_000).listed();
+
+      await blacklist(investor.publicKey, { investor: {} });
+      await expectError(env.fund(invoice).by(investor), "PartyBlacklisted");
+      // A wrong reason is refused either way
+      await expectError(unblacklist(investor.publicKey, { investor: {} }, { sanctionsMatch: {} }), "InvalidBlacklistReason");
+
+      await unblacklist(investor.publicKey, { investor: {} });
+      await env.fund(invoice).by(investor);
+      const entry = await program.account.blacklistEntry.fetch(env.blacklistPda(investor.publicKey));
+      assert.isFalse(entry.investor);
+      assert.deepEqual(entry.reason, { appealUpheld: {} });
+    });
+
+    it("stops a business listing while its funded invoices still settle", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const investor = await env.createInvestor();
+      const { invoice: open } = await env.createInvoice(business).amount(10_000_000).listed();
+      const { invoice: funded } = await env.createInvoice(business).amount(10_000_000).listed();
+      await env.fund(funded).by(investor);
+
+      await blacklist(business.publicKey, { business: {} });
+      try {
+        await expectError(env.createInvoice(business).amount(10_000_000), "PartyBlacklisted");
+        await expectError(env.fund(open).by(investor), "PartyBlacklisted");
+        await env.repayInvoicesBatch(business, [funded], 100_000_000);
+        assert.deepEqual((await program.account.invoice.fetch(funded)).status, { repaid: {} });
+      } finally {
+        await unblacklist(business.publicKey, { business: {} }, { blacklistedInError: {} });
+      }
+      await env.createInvoice(business).amount(10_000_000);
+    });
+
+    it("only lets the authority blacklist, and only for cause", async () => {
+      const stranger = Keypair.generate();
+      const party = Keypair.generate().publicKey;
+      await expectError(blacklist(party, { investor: {} }, stranger), "Unauthorized");
+      await expectError(
+        program.methods
+          .blacklist(party, { investor: {} }, { appealUpheld: {} })
+          .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey, payer: authority.publicKey })
+          .rpc(),
+        "InvalidBlacklistReason"
+      );
+    });
+  });
+
+  describe("protocol totals", () => {
+    it("counts premiums, repayments and insurance payouts in the global state", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const investor = await env.createInvestor();
+      const totals = async () => {
+        const state = await program.account.globalState.fetch(globalState);
+        return {
+          repaid: state.totalRepaid.toNumber(),
+          repaidAmount: state.totalRepaidAmount.toNumber(),
+          premiums: state.totalPremiums.toNumber(),
+          payouts: state.totalInsurancePayouts.toNumber(),
+        };
+      };
+
+      await env.withConfig({ gracePeriodDays: 0 }, async () => {
+        const { invoice: repaid } = await env.createInvoice(business).amount(60_000_000).listed();
+        const { invoice: defaulted } = await env.createInvoice(business).amount(40_000_000).dueInSeconds(5).listed();
+        const before = await totals();
+        let premiums = 0;
+        for (const invoice of [repaid, defaulted]) {
+          premiums += (await program.account.invoice.fetch(invoice)).insurancePremium.toNumber();
+          await env.fund(invoice).by(investor);
+        }
+        assert.equal((await totals()).premiums - before.premiums, premiums);
+
+        await program.methods
+          .repayInvoice(new anchor.BN(1), new anchor.BN(1_000_000_000))
+          .accountsPartial({
+            invoice: repaid,
+            debtor: await env.debtorOf(repaid),
+            businessOwner: business.publicKey,
+            globalState,
+            businessTokenAccount: business.usdc,
+            investorTokenAccount: null,
+            invoiceVault: invoiceVault(repaid),
+            experiment: null,
+            investorStats: await env.investorStatsOf(repaid),
+            pairLedger: pairLedger(business.publicKey, investor.publicKey),
+            portfolio: await env.portfolioOf(repaid),
+          })
+          .signers([business.keypair])
+          .rpc();
+        const { amountRepaid } = await program.account.invoice.fetch(repaid);
+        let after = await totals();
+        assert.equal(after.repaid - before.repaid, 1);
+        assert.equal(after.repaidAmount - before.repaidAmount, amountRepaid.toNumber());
+
+        await env.warpTo(defaulted, "defaultable");
+        await env.markDefaulted(defaulted);
+        await env.claimInsurance(defaulted, investor);
+        const { insurancePayout } = await program.account.invoice.fetch(defaulted);
+        after = await totals();
+        assert.equal(after.payouts - before.payouts, insurancePayout.toNumber());
+        assert.equal(after.repaid - before.repaid, 1);
+      });
+    });
+
+    it("reports them with the pool's token balance in one view", async () => {
+      const state = await program.account.globalState.fetch(globalState);
+      const pool = await getAccount(provider.connection, env.insurancePool);
+      const stats = await program.methods
+        .getGlobalStats()
+        .accountsPartial({ globalState, insurancePoolAccount: env.insurancePool })
+        .view();
+      assert.equal(stats.totalRepaid.toNumber(), state.totalRepaid.toNumber());
+      assert.equal(stats.totalPremiums.toNumber(), state.totalPremiums.toNumber());
+      assert.equal(stats.insurancePoolBalance.toNumber(), state.insurancePoolBalance.toNumber());
+      assert.equal(stats.poolTokenBalance.toString(), pool.amount.toString());
+      assert.equal(
+        stats.poolBalanceDrift.toNumber(),
+        Number(pool.amount) - state.insurancePoolBalance.toNumber()
+      );
+
+      const withoutPool = await program.methods
+        .getGlobalStats()
+        .accountsPartial({ globalState, insurancePoolAccount: null })
+        .view();
+      assert.isNull(withoutPool.poolTokenBalance);
+      assert.isNull(withoutPool.poolBalanceDrift);
+    });
+  });
+
+  describe("position transfer", () => {
+    it("sells a funded position and pays the buyer from then on", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const seller = await env.createInvestor();
+      const buyer = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).amount(60_000_000).listed();
+      const listing = env.pda([Buffer.from("position_listing"), invoice.toBuffer()]);
+      const list = async (by = seller) =>
+        program.methods
+          .listPosition(new anchor.BN(59_000_000))
+          .accountsPartial({
+            invoice,
+            debtor: await env.debtorOf(invoice),
+            listing,
+            seller: by.publicKey,
+            sellerReceipt: (await program.account.invoice.fetch(invoice)).receiptRedeemed
+              ? null
+              : env.receiptAccount(invoice, by.publicKey),
+          })
+          .signers([by.keypair])
+          .rpc();
+      const buy = async (price: number, by: Party = buyer) =>
+        program.methods
+          .transferPosition(new anchor.BN(price))
+          .accountsPartial({
+            invoice,
+            listing,
+            globalState,
+            buyer: by.publicKey,
+            seller: seller.publicKey,
+            buyerTokenAccount: by.usdc,
+            sellerTokenAccount: seller.usdc,
+            sellerLedger: pairLedger(business.publicKey, seller.publicKey),
+            sellerPortfolio: await env.portfolioOf(invoice),
+            sellerStats: env.investorStatsPda(seller.publicKey),
+            buyerLedger: pairLedger(business.publicKey, by.publicKey),
+            sellerReceipt: env.receiptAccount(invoice, seller.publicKey),
+            buyerReceipt: env.receiptAccount(invoice, by.publicKey),
+            buyerWhitelist: null,
+          })
+          .signers([by.keypair])
+          .rpc();
+
+      // Nothing to sell before it is funded
+      await expectError(
+        program.methods
+          .listPosition(new anchor.BN(59_000_000))
+          .accountsPartial({ invoice, listing, seller: seller.publicKey, sellerReceipt: null })
+          .signers([seller.keypair])
+          .rpc(),
+        "Unauthorized"
+      );
+      await env.fund(invoice).by(seller);
+      await list();
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        authority.payer,
+        env.receiptMintPda(invoice),
+        buyer.publicKey
+      );
+
+      // The business can't buy back into its own receivable
+      await expectError(buy(59_000_000, business), "SelfFundingNotAllowed");
+      await expectError(buy(58_000_000), "SlippageExceeded");
+      const sellerBefore = (await getAccount(provider.connection, seller.usdc)).amount;
+      const events: any[] = [];
+      const listener = program.addEventListener("positionTransferred", (event) => events.push(event));
+      await buy(59_000_000);
+      await program.removeEventListener(listener);
+
+      assert.equal((await program.account.invoice.fetch(invoice)).investor.toBase58(), buyer.publicKey.toBase58());
+      assert.equal((await getAccount(provider.connection, seller.usdc)).amount - sellerBefore, BigInt(59_000_000));
+      assert.equal((await getAccount(provider.connection, env.receiptAccount(invoice, buyer.publicKey))).amount, BigInt(1));
+      assert.isNull(await program.account.positionListing.fetchNullable(listing));
+      assert.lengthOf(events, 1);
+      assert.equal(events[0].from.toBase58(), seller.publicKey.toBase58());
+      assert.equal(events[0].to.toBase58(), buyer.publicKey.toBase58());
+      assert.equal(events[0].price.toNumber(), 59_000_000);
+      assert.equal(events[0].invoice.toBase58(), invoice.toBase58());
+      assert.equal(events[0].businessOwner.toBase58(), business.publicKey.toBase58());
+      assert.equal(events[0].investor.toBase58(), buyer.publicKey.toBase58());
+
+      const sold = await program.methods.getPairStatement(business.publicKey, seller.publicKey).accountsPartial({}).view();
+      assert.equal(sold.openInvoices.toNumber(), 0);
+      assert.equal(sold.invoicesSold.toNumber(), 1);
+
+      // Repayment now goes to the buyer, who holds the receipt
+      await program.methods
+        .repayInvoice(new anchor.BN(1), new anchor.BN(1_000_000_000))
+        .accountsPartial({
+          invoice,
+          debtor: await env.debtorOf(invoice),
+          businessOwner: business.publicKey,
+          globalState,
+          businessTokenAccount: business.usdc,
+          investorTokenAccount: null,
+          invoiceVault: invoiceVault(invoice),
+          experiment: null,
+          investorStats: await env.investorStatsOf(invoice),
+          pairLedger: pairLedger(business.publicKey, buyer.publicKey),
+          portfolio: await env.portfolioOf(invoice),
+        })
+        .signers([business.keypair])
+        .rpc();
+      const redeem = (holder: typeof buyer) =>
+        program.methods
+          .redeemReceipt()
+          .accountsPartial({
+            invoice,
+            holderReceipt: env.receiptAccount(invoice, holder.publicKey),
+            holder: holder.publicKey,
+            holderTokenAccount: holder.usdc,
+          })
+          .signers([holder.keypair])
+          .rpc();
+      await expectError(redeem(seller), "InvalidPositionReceipt");
+      const buyerBefore = (await getAccount(provider.connection, buyer.usdc)).amount;
+      await redeem(buyer);
+      assert.isAtLeast(Number((await getAccount(provider.connection, buyer.usdc)).amount - buyerBefore), 60_000_000);
+
+      // A repaid position is no longer for sale
+      await expectError(list(buyer), "PositionNotTransferable");
+    });
+  });
+
+  describe("position receipts", () => {
+    it("pays whoever holds the receipt, wherever it was sent", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const funder = await env.createInvestor();
+      const holder = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).amount(40_000_000).listed();
+      const receiptMint = env.receiptMintPda(invoice);
+
+      let minted: any;
+      const listener = program.addEventListener("receiptMinted", (event) => (minted = event));
+      await env.fund(invoice).by(funder);
+      await program.removeEventListener(listener);
+      assert.equal(minted.mint.toBase58(), receiptMint.toBase58());
+      assert.equal(minted.holder.toBase58(), funder.publicKey.toBase58());
+      assert.equal(minted.invoice.toBase58(), invoice.toBase58());
+      assert.equal(minted.businessOwner.toBase58(), business.publicKey.toBase58());
+
+      // One indivisible token, and nobody can mint another
+      const mint = await getMint(provider.connection, receiptMint);
+      assert.equal(mint.decimals, 0);
+      assert.equal(mint.supply, BigInt(1));
+      assert.isNull(mint.mintAuthority);
+
+      // Sold off-program to an account that is not the holder's associated one
+      const holderReceipt = await createAccount(
+        provider.connection,
+        authority.payer,
+        receiptMint,
+        holder.publicKey,
+        Keypair.generate()
+      );
+      await transfer(
+        provider.connection,
+        authority.payer,
+        env.receiptAccount(invoice, funder.publicKey),
+        holderReceipt,
+        funder.keypair,
+        1
+      );
+
+      await program.methods
+        .repayInvoice(new anchor.BN(1), new anchor.BN(1_000_000_000))
+        .accountsPartial({
+          invoice,
+          debtor: await env.debtorOf(invoice),
+          businessOwner: business.publicKey,
+          globalState,
+          businessTokenAccount: business.usdc,
+          investorTokenAccount: null,
+          invoiceVault: invoiceVault(invoice),
+          experiment: null,
+          investorStats: await env.investorStatsOf(invoice),
+          pairLedger: pairLedger(business.publicKey, funder.publicKey),
+          portfolio: await env.portfolioOf(invoice),
+        })
+        .signers([business.keypair])
+        .rpc();
+
+      // The funder no longer holds the position, even though they are still recorded
+      await expectError(
+        program.methods
+          .redeemReceipt()
+          .accountsPartial({
+            invoice,
+            holderReceipt: env.receiptAccount(invoice, funder.publicKey),
+            holder: funder.publicKey,
+            holderTokenAccount: funder.usdc,
+          })
+          .signers([funder.keypair])
+          .rpc(),
+        "InvalidPositionReceipt"
+      );
+
+      let burned: any;
+      const burnListener = program.addEventListener("receiptBurned", (event) => (burned = event));
+      const before = (await getAccount(provider.connection, holder.usdc)).amount;
+      await program.methods
+        .redeemReceipt()
+        .accountsPartial({ invoice, holderReceipt, holder: holder.publicKey, holderTokenAccount: holder.usdc })
+        .signers([holder.keypair])
+        .rpc();
+      await program.removeEventListener(burnListener);
+
+      const settled = await program.account.invoice.fetch(invoice);
+      assert.equal(
+        (await getAccount(provider.connection, holder.usdc)).amount - before,
+        BigInt(settled.finalRepaymentAmount.toString())
+      );
+      assert.equal(settled.investor.toBase58(), holder.publicKey.toBase58());
+      assert.equal((await getMint(provider.connection, receiptMint)).supply, BigInt(0));
+      assert.equal(burned.holder.toBase58(), holder.publicKey.toBase58());
+      assert.equal(burned.invoiceId.toString(), settled.invoiceId.toString());
+      assert.equal(burned.invoice.toBase58(), invoice.toBase58());
+      assert.equal(burned.investor.toBase58(), holder.publicKey.toBase58());
+    });
+  });
+
+  describe("governance migration", () => {
+    const governanceProgram = Keypair.generate().publicKey;
+    const realm = Keypair.generate().publicKey;
+
+    const governanceCall = async (method: any, signer: Keypair = authority) =>
+      method
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: signer.publicKey })
+        .signers(signer === authority ? [] : [signer])
+        .rpc();
+
+    it("records the governance realm and schedules enablement behind the timelock", async () => {
+      await governanceCall(program.methods.configureGovernance(governanceProgram, realm));
+      let state = await program.account.globalState.fetch(globalState);
+      assert.ok(state.governanceProgram.equals(governanceProgram));
+      assert.ok(state.governanceRealm.equals(realm));
+      assert.isFalse(state.governanceEnabled);
+
+      await governanceCall(program.methods.proposeGovernanceEnabled(true));
+      state = await program.account.globalState.fetch(globalState);
+      assert.isTrue(state.pendingGovernanceEnabled);
+
+      await expectError(
+        governanceCall(program.methods.applyGovernanceEnabled()),
+        "TimelockNotElapsed"
+      );
+    });
+
+    it("keeps governed instructions on the keypair until enablement applies", async () => {
+      await governanceCall(program.methods.setRetentionPeriod(new anchor.BN(30 * DAY)));
+      assert.isFalse((await program.account.globalState.fetch(globalState)).governanceEnabled);
+
+      // Withdraw the pending enablement so later runs start from the keypair
+      await governanceCall(program.methods.proposeGovernanceEnabled(false));
+    });
+
+    it("refuses governance changes from anyone else", async () => {
+      const intruder = Keypair.generate();
+      await airdrop(intruder.publicKey);
+      await expectError(
+        governanceCall(program.methods.configureGovernance(intruder.publicKey, realm), intruder),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("admin approvals", () => {
+    const PAUSE_CREATE = 1 << 0;
+    const [approvals] = PublicKey.findProgramAddressSync([Buffer.from("admin_approvals")], program.programId);
+    const second = Keypair.generate();
+    const third = Keypair.generate();
+
+    const proposalPda = (id: anchor.BN) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("admin_proposal"), id.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    // Propose a guarded instruction built for the approvals PDA as its authority
+    const propose = async (method: any, proposer: Keypair = authority.payer) => {
+      const ix = await method.instruction();
+      const { adminProposalCount } = await program.account.globalState.fetch(globalState);
+      await program.methods
+        .proposeAdminAction(
+          ix.keys.map(({ pubkey, isSigner, isWritable }) => ({ pubkey, isSigner, isWritable })),
+          ix.data
+        )
+        .accountsPartial({ globalState, proposal: proposalPda(adminProposalCount), adminLog: await adminLog(), proposer: proposer.publicKey })
+        .signers(proposer === authority.payer ? [] : [proposer])
+        .rpc();
+      return { proposal: proposalPda(adminProposalCount), ix };
+    };
+
+    const approveAs = async (proposal: PublicKey, approver: Keypair) =>
+      program.methods
+        .approveAdminAction()
+        .accountsPartial({ globalState, proposal, adminLog: await adminLog(), approver: approver.publicKey })
+        .signers([approver])
+        .rpc();
+
+    // The PDA signs inside the self-CPI, so it goes in as a plain account here
+    const execute = async (proposal: PublicKey, ix: any) =>
+      program.methods
+        .executeAdminAction()
+        .accountsPartial({ globalState, proposal, adminLog: await adminLog(), executor: authority.publicKey })
+        .remainingAccounts(ix.keys.map(({ pubkey, isWritable }) => ({ pubkey, isWritable, isSigner: false })))
+        .rpc({ commitment: "confirmed" });
+
+    const pauseAs = async (signer: PublicKey, flags: number, unpause = false) =>
+      program.methods[unpause ? "unpause" : "pause"](flags).accountsPartial({
+        globalState,
+        adminLog: await adminLog(),
+        authority: signer,
+      });
+
+    before(async () => {
+      await airdrop(second.publicKey);
+      await airdrop(third.publicKey);
+    });
+
+    it("takes guarded instructions from the keypair once an approver set is configured", async () => {
+      await program.methods
+        .setAdminApprovers([authority.publicKey, second.publicKey, third.publicKey], 2, new anchor.BN(DAY))
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+      const state = await program.account.globalState.fetch(globalState);
+      assert.equal(state.approvalThreshold, 2);
+      assert.equal(state.adminApprovers.length, 3);
+
+      await expectError((await pauseAs(authority.publicKey, PAUSE_CREATE)).rpc(), "ApprovalRequired");
+      // Including the ones that pick where funds go
+      const [treasuryAllowlist] = PublicKey.findProgramAddressSync(
+        [Buffer.from("allowlist"), Buffer.from([0])],
+        program.programId
+      );
+      const destination = await createAccount(provider.connection, authority.payer, usdcMint, Keypair.generate().publicKey);
+      await expectError(
+        program.methods
+          .registerDestination({ treasury: {} }, Array.from(Buffer.alloc(32, "unapproved")))
+          .accountsPartial({
+            allowlist: treasuryAllowlist,
+            globalState,
+            adminLog: await adminLog(),
+            destination,
+            authority: authority.publicKey,
+            payer: authority.publicKey,
+          })
+          .rpc(),
+        "ApprovalRequired"
+      );
+      await expectError(
+        program.methods
+          .applyPayoutCustody()
+          .accountsPartial({
+            payoutProcessor: env.pda([Buffer.from("payout_processor")]),
+            allowlist: env.pda([Buffer.from("allowlist"), Buffer.from([3])]),
+            globalState,
+            adminLog: await adminLog(),
+            authority: authority.publicKey,
+          })
+          .rpc(),
+        "ApprovalRequired"
+      );
+      // Unguarded instructions stay with the keypair
+      await program.methods
+        .setRetentionPeriod(new anchor.BN(30 * DAY))
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+    });
+
+    it("runs a proposal once the threshold approves it", async () => {
+      const { proposal, ix } = await propose(await pauseAs(approvals, PAUSE_CREATE));
+      await expectError(execute(proposal, ix), "ApprovalThresholdNotMet");
+      await expectError(approveAs(proposal, Keypair.generate()), "NotAnApprover");
+
+      await approveAs(proposal, third);
+      await expectError(approveAs(proposal, third), "AlreadyApproved");
+      const signature = await execute(proposal, ix);
+
+      assert.equal((await program.account.globalState.fetch(globalState)).paused, PAUSE_CREATE);
+      assert.isNull(await program.account.adminProposal.fetchNullable(proposal));
+
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const events = [...parser.parseLogs(tx.meta.logMessages)];
+      assert.deepEqual(events.map((e) => e.name), ["pauseFlagsUpdated", "adminActionExecuted"]);
+      assert.equal(events[1].data.approvals, 2);
+
+      const unpause = await propose(await pauseAs(approvals, PAUSE_CREATE, true), second);
+      await approveAs(unpause.proposal, third);
+      await execute(unpause.proposal, unpause.ix);
+      assert.equal((await program.account.globalState.fetch(globalState)).paused, 0);
+    });
+
+    it("only proposes guarded instructions", async () => {
+      const retention = program.methods
+        .setRetentionPeriod(new anchor.BN(30 * DAY))
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: approvals });
+      await expectError(propose(retention), "InvalidAdminAction");
+
+      const outsider = Keypair.generate();
+      await airdrop(outsider.publicKey);
+      await expectError(propose(await pauseAs(approvals, PAUSE_CREATE), outsider), "NotAnApprover");
+    });
+
+    it("voids pending proposals when the set changes and can hand control back", async () => {
+      const stale = await propose(await pauseAs(approvals, PAUSE_CREATE));
+
+      const clear = await propose(
+        program.methods
+          .setAdminApprovers([], 0, new anchor.BN(0))
+          .accountsPartial({ globalState, adminLog: await adminLog(), authority: approvals })
+      );
+      await approveAs(clear.proposal, second);
+      await execute(clear.proposal, clear.ix);
+      assert.equal((await program.account.globalState.fetch(globalState)).approvalThreshold, 0);
+
+      await expectError(approveAs(stale.proposal, second), "ApproverSetChanged");
+      await program.methods
+        .cancelAdminAction()
+        .accountsPartial({ globalState, proposal: stale.proposal, canceller: third.publicKey })
+        .signers([third])
+        .rpc();
+
+      // Back on the keypair alone
+      await (await pauseAs(authority.publicKey, PAUSE_CREATE)).rpc();
+      await (await pauseAs(authority.publicKey, PAUSE_CREATE, true)).rpc();
+    });
+  });
+
+  describe("book commitment", () => {
+    const PAGE = 10;
+    const bookCommitmentPda = (index: anchor.BN) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("book_commitment"), index.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    it("commits the whole book across crank pages", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      while ((await program.account.globalState.fetch(globalState)).invoicesCreated.toNumber() < 20) {
+        await createInvoice(owner);
+      }
+
+      const state = await program.account.globalState.fetch(globalState);
+      const book = (await program.account.invoice.all())
+        .filter(({ account }) => account.bookNonce.gtn(0))
+        .sort((a, b) => a.account.bookNonce.cmp(b.account.bookNonce));
+      const commitment = bookCommitmentPda(state.bookCommitmentCount);
+
+      for (let start = 0; start < book.length; start += PAGE) {
+        const page = book.slice(start, start + PAGE);
+        await program.methods
+          .commitBookRoot(start + PAGE >= book.length)
+          .accountsPartial({ globalState, bookCommitment: commitment, cranker: authority.publicKey })
+          .remainingAccounts(page.map(({ publicKey }) => ({ pubkey: publicKey, isSigner: false, isWritable: false })))
+          .rpc();
+      }
+
+      const committed = await program.account.bookCommitment.fetch(commitment);
+      assert.isTrue(committed.finalized);
+      assert.equal(committed.frontier.leafCount.toNumber(), book.length);
+      assert.ok(committed.lastNonce.eq(book[book.length - 1].account.bookNonce));
+      assert.ok(committed.throughNonce.eq(state.invoicesCreated));
+      assert.isTrue(committed.committedSlot.gtn(0));
+
+      // The next commitment waits out the interval
+      await expectError(
+        program.methods
+          .commitBookRoot(true)
+          .accountsPartial({ globalState, bookCommitment: bookCommitmentPda(state.bookCommitmentCount.addn(1)), cranker: authority.publicKey })
+          .rpc(),
+        "BookCommitTooSoon"
+      );
+    });
+  });
+
+  describe("investor whitelist", () => {
+    const approve = async (investor: PublicKey, expiresAt: number | null = null) =>
+      program.methods
+        .approveInvestor(investor, expiresAt === null ? null : new anchor.BN(expiresAt))
+        .accountsPartial({
+          globalState,
+          adminLog: await adminLog(),
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .rpc();
+    const revoke = async (investor: PublicKey) =>
+      program.methods
+        .revokeInvestor()
+        .accountsPartial({
+          investorWhitelist: env.investorWhitelistPda(investor),
+          globalState,
+          adminLog: await adminLog(),
+          authority: authority.publicKey,
+        })
+        .rpc();
+    const requireWhitelist = async (enabled: boolean) =>
+      program.methods
+        .setRequireWhitelist(enabled)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("only funds approved, unexpired investors while required", async () => {
+      const business = await env.createBusiness();
+      const approved = await env.createInvestor();
+      const revoked = await env.createInvestor();
+      const expiring = await env.createInvestor();
+      const unknown = await env.createInvestor();
+
+      await approve(approved.publicKey);
+      await approve(revoked.publicKey);
+      await revoke(revoked.publicKey);
+      await approve(expiring.publicKey, now() + 2);
+      const entry = await program.account.investorWhitelist.fetch(env.investorWhitelistPda(revoked.publicKey));
+      assert.isFalse(entry.approved);
+
+      await requireWhitelist(true);
+      try {
+        const listed = async () => (await env.createInvoice(business).amount(10_000_000).listed()).invoice;
+        await expectError(env.fund(await listed()).by(unknown), "InvestorNotWhitelisted");
+        await expectError(env.fund(await listed()).by(revoked), "InvestorNotWhitelisted");
+
+        await sleep(4_000);
+        await expectError(env.fund(await listed()).by(expiring), "InvestorNotWhitelisted");
+
+        const invoice = await listed();
+        await env.fund(invoice).by(approved);
+        assert.deepEqual((await program.account.invoice.fetch(invoice)).status, { funded: {} });
+      } finally {
+        await requireWhitelist(false);
+      }
+
+      // With the requirement off anyone funds again, approved or not
+      await env.fund((await env.createInvoice(business).amount(10_000_000).listed()).invoice).by(revoked);
+    });
+
+    it("keeps expiry in the future and approvals with the authority", async () => {
+      const investor = Keypair.generate();
+      await expectError(approve(investor.publicKey, now() - 60), "InvalidWhitelistExpiry");
+
+      const intruder = Keypair.generate();
+      await airdrop(intruder.publicKey);
+      await expectError(
+        program.methods
+          .approveInvestor(intruder.publicKey, null)
+          .accountsPartial({
+            globalState,
+            adminLog: await adminLog(),
+            authority: intruder.publicKey,
+            payer: intruder.publicKey,
+          })
+          .signers([intruder])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("business verification", () => {
+    const documents = Array.from(Buffer.alloc(32, 7));
+    const jurisdiction = Array.from(Buffer.from("US"));
+    const register = async (owner: Keypair, industryCode = 4_240) =>
+      program.methods
+        .registerBusiness(jurisdiction, industryCode, documents)
+        .accountsPartial({ businessOwner: owner.publicKey })
+        .signers([owner])
+        .rpc();
+    const verify = async (owner: PublicKey, creditScore: number, hash = documents) =>
+      program.methods
+        .verifyBusiness(owner, hash, creditScore, true)
+        .accountsPartial({
+          businessProfile: env.businessProfilePda(owner),
+          globalState,
+          adminLog: await adminLog(),
+          authority: authority.publicKey,
+        })
+        .rpc();
+    const requireVerification = async (enabled: boolean) =>
+      program.methods
+        .setRequireBusinessVerification(enabled)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("only lists for verified businesses while required", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      await register(owner);
+
+      await requireVerification(true);
+      try {
+        await expectError(createInvoice(owner), "BusinessNotVerified");
+        await expectError(verify(owner.publicKey, 780, Array.from(Buffer.alloc(32, 8))), "BusinessProfileChanged");
+        await expectError(verify(owner.publicKey, 900), "InvalidCreditScore");
+
+        await verify(owner.publicKey, 780);
+        const profile = await program.account.businessProfile.fetch(env.businessProfilePda(owner.publicKey));
+        assert.isTrue(profile.verified);
+        assert.equal(profile.creditScore, 780);
+
+        const { invoice } = await createInvoice(owner);
+        assert.deepEqual((await program.account.invoice.fetch(invoice)).status, { pendingFunding: {} });
+
+        // New documents send the profile back for review
+        await register(owner, 2_361);
+        await expectError(createInvoice(owner), "BusinessNotVerified");
+      } finally {
+        await requireVerification(false);
+      }
+    });
+
+    it("keeps verification with the authority", async () => {
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      await register(owner);
+      await expectError(
+        program.methods
+          .verifyBusiness(owner.publicKey, documents, 800, true)
+          .accountsPartial({
+            businessProfile: env.businessProfilePda(owner.publicKey),
+            globalState,
+            adminLog: await adminLog(),
+            authority: owner.publicKey,
+          })
+          .signers([owner])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("credit attestation", () => {
+    const oracle = Keypair.generate();
+    const setOracle = async (key: PublicKey | null) =>
+      program.methods
+        .setOracleAuthority(key)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+    const attestation = (score: number, nonce: number, expiry = now() + 600) => ({
+      score,
+      expiry: new anchor.BN(expiry),
+      nonce: new anchor.BN(nonce),
+    });
+    const listed = async (business: Keypair, signer: Keypair, score: number, nonce: number, expiry?: number) =>
+      (await env.createInvoice(business).amount(10_000_000).creditAttestation(signer, attestation(score, nonce, expiry)))
+        .invoice;
+
+    before(() => setOracle(oracle.publicKey));
+    after(() => setOracle(null));
+
+    it("prices from a fresh attested score", async () => {
+      const business = Keypair.generate();
+      await airdrop(business.publicKey);
+      const strong = await program.account.invoice.fetch(await listed(business, oracle, 820, 1));
+      const weak = await program.account.invoice.fetch(await listed(business, oracle, 640, 2));
+      assert.isBelow(strong.riskScore, weak.riskScore);
+
+      const profile = await program.account.businessProfile.fetch(env.businessProfilePda(business.publicKey));
+      assert.equal(profile.creditAttestationNonce.toNumber(), 2);
+    });
+
+    it("rejects stale, superseded and mis-signed attestations", async () => {
+      const business = Keypair.generate();
+      await airdrop(business.publicKey);
+      await listed(business, oracle, 700, 5);
+
+      await expectError(listed(business, oracle, 820, 4), "CreditAttestationSuperseded");
+      await expectError(listed(business, oracle, 820, 6, now() - 60), "CreditAttestationExpired");
+      await expectError(listed(business, Keypair.generate(), 820, 6), "SignerMismatch");
+    });
+
+    it("keeps the oracle key with the authority", async () => {
+      const intruder = Keypair.generate();
+      await airdrop(intruder.publicKey);
+      await expectError(
+        program.methods
+          .setOracleAuthority(intruder.publicKey)
+          .accountsPartial({ globalState, adminLog: await adminLog(), authority: intruder.publicKey })
+          .signers([intruder])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("risk params", () => {
+    const setRiskParams = async (params: any) =>
+      program.methods
+        .setRiskParams(params)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("retunes the risk score without a redeploy", async () => {
+      const { riskParams } = await program.account.globalState.fetch(globalState);
+      assert.equal(riskParams.baseScore, 10);
+      assert.equal(riskParams.maxScore, 50);
+
+      const owner = Keypair.generate();
+      await airdrop(owner.publicKey);
+      const before = await program.account.invoice.fetch((await createInvoice(owner, { amount: 10_000_000 })).invoice);
+
+      await setRiskParams({ ...riskParams, baseScore: 0 });
+      try {
+        const after = await program.account.invoice.fetch((await createInvoice(owner, { amount: 10_000_000 })).invoice);
+        assert.equal(after.riskScore, before.riskScore - 10);
+      } finally {
+        await setRiskParams(riskParams);
+      }
+    });
+
+    it("rejects unordered bands", async () => {
+      const { riskParams } = await program.account.globalState.fetch(globalState);
+      const creditFloors = [...riskParams.creditFloors].reverse();
+      await expectError(setRiskParams({ ...riskParams, creditFloors }), "InvalidRiskParams");
+    });
+  });
+
+  describe("debtor concentration", () => {
+    const setCap = async (cap: number) =>
+      program.methods
+        .setDebtorExposureCap(new anchor.BN(cap))
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("caps funded exposure to one debtor across businesses", async () => {
+      const debtor = `Globex Ltd ${Keypair.generate().publicKey.toBase58()}`;
+      const [first, second] = [await env.createBusiness(), await env.createBusiness()];
+      const investor = await env.createInvestor();
+      const list = async (business: typeof first) =>
+        (await env.createInvoice(business).amount(10_000_000).debtor(debtor).listed()).invoice;
+      const a = await list(first);
+      const b = await list(second);
+      const c = await list(first);
+      const entry = env.debtorPda(debtorId(debtor));
+      assert.ok((await program.account.invoice.fetch(c)).debtor.equals(entry));
+
+      await setCap(25_000_000);
+      try {
+        await env.fund(a).by(investor);
+        await env.fund(b).by(investor);
+        await expectError(env.fund(c).by(investor), "DebtorConcentrationExceeded");
+      } finally {
+        await setCap(0);
+      }
+
+      const registry = await program.account.debtor.fetch(entry);
+      assert.equal(registry.invoicesListed, 3);
+      assert.equal(registry.invoicesFinanced, 2);
+      assert.equal(registry.outstanding.toNumber(), 20_000_000);
+    });
+  });
+
+  describe("exposure caps", () => {
+    const setCaps = async (investorCap: number, businessCap: number) =>
+      program.methods
+        .setExposureCaps(new anchor.BN(investorCap), new anchor.BN(businessCap))
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("caps outstanding funding per investor and per business", async () => {
+      const [first, second] = [await env.createBusiness(), await env.createBusiness()];
+      const [investor, other] = [await env.createInvestor(), await env.createInvestor()];
+      const list = async (business: typeof first) =>
+        (await env.createInvoice(business).amount(10_000_000).listed()).invoice;
+
+      await setCaps(15_000_000, 15_000_000);
+      try {
+        await env.fund(await list(first)).by(investor);
+        await expectError(env.fund(await list(second)).by(investor), "InvestorExposureExceeded");
+        await expectError(env.fund(await list(first)).by(other), "BusinessExposureExceeded");
+        await env.fund(await list(second)).by(other);
+      } finally {
+        await setCaps(0, 0);
+      }
+
+      const stats = await program.account.investorStats.fetch(env.investorStatsPda(investor.publicKey));
+      assert.equal(stats.outstanding.toNumber(), 10_000_000);
+      const profile = await program.account.businessProfile.fetch(first.profile);
+      assert.equal(profile.outstandingFinanced.toNumber(), 10_000_000);
+    });
+  });
+
+  describe("stablecoin mints", () => {
+    let usdt: PublicKey;
+    const setMintApproval = async (mint: PublicKey, approved: boolean) =>
+      program.methods
+        .setMintApproval(approved)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey, mint, primaryMint: usdcMint })
+        .rpc();
+
+    before(async () => {
+      usdt = await createMint(provider.connection, authority.payer, authority.publicKey, null, 6);
+    });
+
+    it("funds a USDC invoice and a USDT invoice side by side", async () => {
+      await expectError(env.createInvoice(await env.createBusiness()).mint(usdt), "MintNotApproved");
+      await setMintApproval(usdt, true);
+      try {
+        const business = await env.createBusiness();
+        const investor = await env.createInvestor();
+        await env.tokenAccount(usdt, investor.publicKey, 1_000_000_000);
+        const poolBefore = (await getAccount(provider.connection, insurancePool)).amount;
+
+        const { invoice: inUsdc } = await env.createInvoice(business).amount(40_000_000).listed();
+        const { invoice: inUsdt } = await env.createInvoice(business).amount(30_000_000).mint(usdt).listed();
+        await env.fund(inUsdc).by(investor);
+        await env.fund(inUsdt).by(investor);
+
+        const usdcInvoice = await program.account.invoice.fetch(inUsdc);
+        const usdtInvoice = await program.account.invoice.fetch(inUsdt);
+        assert.ok(usdcInvoice.mint.equals(usdcMint));
+        assert.ok(usdtInvoice.mint.equals(usdt));
+        // Only the primary mint is insured
+        assert.isAbove(usdcInvoice.insurancePremium.toNumber(), 0);
+        assert.equal(usdtInvoice.insurancePremium.toNumber(), 0);
+        assert.equal(usdtInvoice.coveragePercentage, 0);
+
+        assert.equal(await env.tokenBalance(business.usdc), 40_000_000);
+        assert.equal(await env.tokenBalance(await env.tokenAccount(usdt, business.publicKey)), 30_000_000);
+        assert.equal(await env.tokenBalance(await env.tokenAccount(usdt, investor.publicKey)), 970_000_000);
+        const poolAfter = (await getAccount(provider.connection, insurancePool)).amount;
+        assert.equal(Number(poolAfter - poolBefore), usdcInvoice.insurancePremium.toNumber());
+
+        await expectError(env.createInvoice(business).mint(usdt).partial(), "PrimaryMintOnly");
+        // and the pool, holding only the primary mint, takes no unclaimed USDT
+        await expectError(
+          program.methods
+            .sweepUnclaimedRepayment()
+            .accountsPartial({
+              invoice: inUsdt,
+              invoiceVault: invoiceVault(inUsdt),
+              insurancePoolAccount: insurancePool,
+              globalState,
+              authority: authority.publicKey,
+              payer: authority.publicKey,
+            })
+            .rpc(),
+          "PrimaryMintOnly"
+        );
+      } finally {
+        await setMintApproval(usdt, false);
+      }
+    });
+
+    it("funds a wrapped SOL invoice straight from lamports", async () => {
+      await setMintApproval(NATIVE_MINT, true);
+      try {
+        const business = await env.createBusiness();
+        const investor = await env.createInvestor();
+        const investorWsol = await env.tokenAccount(NATIVE_MINT, investor.publicKey);
+        const lamportsBefore = await provider.connection.getBalance(investor.publicKey);
+
+        const { invoice } = await env.createInvoice(business).amount(500_000_000).mint(NATIVE_MINT).listed();
+        await env.fund(invoice).by(investor).inSol();
+
+        const funded = await program.account.invoice.fetch(invoice);
+        assert.equal(funded.insurancePremium.toNumber(), 0);
+        assert.ok(funded.investor.equals(investor.publicKey));
+        // Exactly the funding amount is wrapped and passed on, nothing is left behind
+        assert.equal(Number((await getAccount(provider.connection, investorWsol)).amount), 0);
+        const businessWsol = await env.tokenAccount(NATIVE_MINT, business.publicKey);
+        assert.equal(Number((await getAccount(provider.connection, businessWsol)).amount), 500_000_000);
+        assert.isAtMost(await provider.connection.getBalance(investor.publicKey), lamportsBefore - 500_000_000);
+
+        const { invoice: inUsdc } = await env.createInvoice(business).amount(20_000_000).listed();
+        await expectError(env.fund(inUsdc).by(investor).inSol(), "NativeMintOnly");
+      } finally {
+        await setMintApproval(NATIVE_MINT, false);
+      }
+    });
+  });
+
+  describe("escrowed funding", () => {
+    const setWindow = async (seconds: number) =>
+      program.methods
+        .setFundingAcceptanceWindow(new anchor.BN(seconds))
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("holds funding until the business accepts, or returns it after the deadline", async () => {
+      const business = await env.createBusiness();
+      const [investor, patient] = [await env.createInvestor(), await env.createInvestor()];
+      const { invoice: accepted } = await env.createInvoice(business).amount(20_000_000).listed();
+      const { invoice: ignored } = await env.createInvoice(business).amount(20_000_000).listed();
+
+      await expectError(setWindow(8 * DAY), "InvalidAcceptanceWindow");
+      await setWindow(10);
+      try {
+        await env.fund(accepted).by(investor);
+        await env.fund(ignored).by(investor);
+        const escrowed = await program.account.invoice.fetch(accepted);
+        assert.deepEqual(escrowed.status, { fundedPendingAcceptance: {} });
+        assert.isNull(escrowed.releasedAt);
+        assert.equal(await env.tokenBalance(business.usdc), 0);
+        // Terms are locked while the funding waits
+        await expectError(
+          program.methods
+            .cancelInvoice()
+            .accountsPartial({ invoice: accepted, globalState, businessOwner: business.publicKey })
+            .signers([business.keypair])
+            .rpc(),
+          "InvoiceNotAvailable"
+        );
+        await expectError(env.reclaimEscrow(accepted, investor), "AcceptanceWindowOpen");
+
+        await env.acceptFunding(accepted, business);
+        const funded = await program.account.invoice.fetch(accepted);
+        assert.deepEqual(funded.status, { funded: {} });
+        assert.isNull(funded.escrow);
+        assert.equal(await env.tokenBalance(business.usdc), 20_000_000);
+        const receipt = await getAccount(provider.connection, env.receiptAccount(accepted, investor.publicKey));
+        assert.equal(Number(receipt.amount), 1);
+
+        await sleep(11_000);
+        await expectError(env.acceptFunding(ignored, business), "AcceptanceWindowClosed");
+        const before = await env.tokenBalance(investor.usdc);
+        const { insurancePremium } = await program.account.invoice.fetch(ignored);
+        await env.reclaimEscrow(ignored, investor);
+        assert.equal(await env.tokenBalance(investor.usdc), before + 20_000_000 + insurancePremium.toNumber());
+        const relisted = await program.account.invoice.fetch(ignored);
+        assert.deepEqual(relisted.status, { pendingFunding: {} });
+        assert.ok(relisted.investor.equals(PublicKey.default));
+        const stats = await program.account.investorStats.fetch(env.investorStatsPda(investor.publicKey));
+        assert.equal(stats.outstanding.toNumber(), 20_000_000);
+      } finally {
+        await setWindow(0);
+      }
+
+      // Back on the market, for anyone
+      await env.fund(ignored).by(patient);
+      assert.deepEqual((await program.account.invoice.fetch(ignored)).status, { funded: {} });
+    });
+  });
+
+  describe("self-dealing", () => {
+    it("refuses a business funding its own invoice", async () => {
+      const business = await env.createBusiness();
+      const { invoice } = await env.createInvoice(business).amount(20_000_000);
+      await expectError(env.fund(invoice).by(business), "SelfFundingNotAllowed");
+    });
+
+    it("refuses funding from a token account the business owns", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).amount(20_000_000);
+      const businessAccount = await env.tokenAccount(usdcMint, business.publicKey);
+      await expectError(env.fund(invoice).by(investor).from(businessAccount), "ConstraintAssociated");
+      assert.isTrue((await program.account.invoice.fetch(invoice)).investor.equals(PublicKey.default));
+    });
+
+    it("keeps the debtor wallet apart from both parties", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).amount(20_000_000).debtorWallet(investor.publicKey);
+      await expectError(env.fund(invoice).by(investor), "DebtorWalletIsParty");
+
+      await expectError(
+        env.createInvoice(business).amount(20_000_000).debtorWallet(business.publicKey),
+        "DebtorWalletIsParty"
+      );
+      const problems = await program.methods
+        .validateListing(
+          new anchor.BN(20_000_000),
+          new anchor.BN(now() + 30 * DAY),
+          debtorInfoHash("Acme Corp, net 30"),
+          null,
+          false,
+          false,
+          business.publicKey
+        )
+        .accountsPartial({ globalState, businessProfile: business.profile })
+        .view();
+      assert.deepEqual(problems, [{ reason: { debtorWalletIsBusiness: {} }, hint: { nameDebtorWallet: {} } }]);
+    });
+  });
+
+  describe("factoring", () => {
+    it("advances a share of face value and collects all of it at repayment", async () => {
+      const investor = await env.createInvestor();
+      const business = await env.createBusiness().withUsdc(10_000_000);
+      await expectError(env.createInvoice(business).amount(20_000_000).factored(4_000), "InvalidAdvanceRate");
+      await expectError(env.createInvoice(business).amount(20_000_000).factored(9_000).partial(), "FactoringUnsupported");
+
+      const { invoice } = await env.createInvoice(business).amount(20_000_000).factored(9_000).listed();
+      const listed = await program.account.invoice.fetch(invoice);
+      assert.equal(listed.advanceAmount.toNumber(), 18_000_000);
+      assert.equal(listed.expectedReturn.toNumber(), 20_000_000);
+      assert.equal(listed.aprBps, 0);
+      await expectError(env.fund(invoice).by(investor).withAmount(20_000_000), "InvalidFundingAmount");
+
+      const [funded] = await env.cpiEvents(await env.fund(invoice).by(investor));
+      assert.deepEqual(funded.data.fundingMode, { factoring: {} });
+      assert.equal(funded.data.amount.toNumber(), 18_000_000);
+      assert.equal(funded.data.faceValue.toNumber(), 20_000_000);
+      assert.equal((await program.account.invoice.fetch(invoice)).fundedAmount.toNumber(), 18_000_000);
+
+      // Repaid straight away, the investor still collects the whole face value
+      await env.repayInvoicesBatch(business, [invoice], 100_000_000);
+      const repaid = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(repaid.status, { repaid: {} });
+      assert.equal(repaid.amountRepaid.toNumber(), 20_000_000);
+    });
+
+    it("keeps par funding as it was", async () => {
+      const investor = await env.createInvestor();
+      const business = await env.createBusiness();
+      const { invoice } = await env.createInvoice(business).amount(20_000_000).listed();
+      const [funded] = await env.cpiEvents(await env.fund(invoice).by(investor));
+      assert.deepEqual(funded.data.fundingMode, { par: {} });
+      assert.equal(funded.data.amount.toNumber(), funded.data.faceValue.toNumber());
+    });
+  });
+
+  describe("debtor acknowledgment", () => {
+    const setPolicy = async (policy: Record<string, object>) =>
+      program.methods
+        .setAcknowledgmentPolicy(policy as any)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("prices a missing acknowledgment, then can require one", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const debtorWallet = Keypair.generate();
+
+      await expectError(setPolicy({ penalized: { riskPoints: 0 } }), "InvalidAcknowledgmentPolicy");
+      await setPolicy({ penalized: { riskPoints: 10 } });
+      let acknowledged!: PublicKey;
+      let unacknowledged!: PublicKey;
+      try {
+        ({ invoice: acknowledged } = await env.createInvoice(business).amount(20_000_000).debtorWallet(debtorWallet.publicKey));
+        ({ invoice: unacknowledged } = await env.createInvoice(business).amount(20_000_000));
+        const listed = await program.account.invoice.fetch(acknowledged);
+        assert.equal(listed.acknowledgmentPenalty, 10);
+
+        await expectError(env.acknowledge(acknowledged, Keypair.generate()), "DebtorWalletMismatch");
+        await env.acknowledge(acknowledged, debtorWallet);
+        const repriced = await program.account.invoice.fetch(acknowledged);
+        assert.isTrue(repriced.debtorAcknowledged);
+        assert.equal(repriced.riskScore, listed.riskScore - 10);
+        assert.isBelow(repriced.insurancePremium.toNumber(), listed.insurancePremium.toNumber());
+        await expectError(env.acknowledge(acknowledged, debtorWallet), "InvoiceAlreadyAcknowledged");
+      } finally {
+        await setPolicy({ optional: {} });
+      }
+
+      await setPolicy({ required: {} });
+      try {
+        await expectError(env.fund(unacknowledged).by(investor), "DebtorAcknowledgmentRequired");
+        await env.fund(acknowledged).by(investor);
+      } finally {
+        await setPolicy({ optional: {} });
+      }
+    });
+  });
+
+  describe("disputes", () => {
+    const setArbiter = async (arbiter: PublicKey | null) =>
+      program.methods
+        .setDisputeArbiter(arbiter)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("freezes an invoice until the authority or arbiter resolves it", async () => {
+      const business = await env.createBusiness();
+      const [investor, outsider] = [await env.createInvestor(), await env.createInvestor()];
+      const arbiter = Keypair.generate();
+      await airdrop(arbiter.publicKey);
+      const { invoice: adjusted } = await env.createInvoice(business).amount(30_000_000).listed();
+      const { invoice: defaulted } = await env.createInvoice(business).amount(30_000_000).listed();
+      await env.fund(adjusted).by(investor);
+      await env.fund(defaulted).by(investor);
+
+      await expectError(env.raiseDispute(adjusted, outsider), "Unauthorized");
+      await env.raiseDispute(adjusted, business);
+      await env.raiseDispute(defaulted, investor);
+      await expectError(env.raiseDispute(adjusted, investor), "InvoiceDisputed");
+      const disputed = await program.account.invoice.fetch(adjusted);
+      assert.ok(disputed.dispute.raisedBy.equals(business.publicKey));
+      assert.isNull(disputed.dispute.resolvedAt);
+      assert.deepEqual(disputed.status, { disputed: {} });
+
+      await expectError(env.resolveDispute(adjusted, { dismissed: {} }, arbiter), "Unauthorized");
+      await setArbiter(arbiter.publicKey);
+      try {
+        await expectError(
+          env.resolveDispute(adjusted, { adjusted: { remainingBalance: new anchor.BN(30_000_000) } }, arbiter),
+          "InvalidDisputeAdjustment"
+        );
+        await env.resolveDispute(adjusted, { adjusted: { remainingBalance: new anchor.BN(20_000_000) } }, arbiter);
+      } finally {
+        await setArbiter(null);
+      }
+      const written = await program.account.invoice.fetch(adjusted);
+      assert.deepEqual(written.status, { funded: {} });
+      assert.equal(written.remainingBalance.toNumber(), 20_000_000);
+      assert.isNotNull(written.dispute.resolvedAt);
+
+      await env.resolveDispute(defaulted, { defaulted: {} });
+      assert.deepEqual((await program.account.invoice.fetch(defaulted)).status, { defaulted: {} });
+      await expectError(env.resolveDispute(defaulted, { dismissed: {} }), "NoActiveDispute");
+      const stats = await program.account.investorStats.fetch(env.investorStatsPda(investor.publicKey));
+      assert.equal(stats.outstanding.toNumber(), 20_000_000);
+    });
+  });
+
+  describe("recovery settlement", () => {
+    it("pays a defaulted invoice back in deposits, returning the excess", async () => {
+      const business = await env.createBusiness();
+      const [investor, outsider] = [await env.createInvestor(), await env.createInvestor()];
+      const { invoice } = await env.createInvoice(business).amount(30_000_000).listed();
+      await env.fund(invoice).by(investor);
+
+      await expectError(env.settleRecovery(invoice, 1_000_000, business), "InvoiceNotDefaulted");
+      await env.raiseDispute(invoice, business);
+      await env.resolveDispute(invoice, { defaulted: {} });
+      await expectError(env.settleRecovery(invoice, 1_000_000, outsider), "Unauthorized");
+
+      const { remainingBalance, expectedReturn, fundedAmount, interestPaid } = await program.account.invoice.fetch(invoice);
+      // The investor is owed the contractual yield on top of the principal
+      const owed = remainingBalance.toNumber() + expectedReturn.sub(fundedAmount).sub(interestPaid).toNumber();
+      await env.settleRecovery(invoice, 10_000_000, business);
+      let settled = await program.account.invoice.fetch(invoice);
+      assert.equal(settled.recoveredAmount.toNumber(), 10_000_000);
+      assert.equal(settled.remainingBalance.toNumber(), remainingBalance.toNumber() - 10_000_000);
+
+      // The business keeps whatever is left over once the investor is whole
+      const before = (await getAccount(provider.connection, business.usdc)).amount;
+      await env.settleRecovery(invoice, owed, business);
+      settled = await program.account.invoice.fetch(invoice);
+      assert.equal(settled.recoveredAmount.toNumber(), owed);
+      assert.equal(settled.remainingBalance.toNumber(), 0);
+      assert.deepEqual(settled.status, { repaidAfterDefault: {} });
+      const after = (await getAccount(provider.connection, business.usdc)).amount;
+      assert.equal(Number(before - after), owed - 10_000_000);
+    });
+
+    it("takes the assigned collections agency's fee off a settlement", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const agency = Keypair.generate();
+      const collectionsAgency = env.pda([Buffer.from("collections_agency"), agency.publicKey.toBuffer()]);
+      const { invoice } = await env.createInvoice(business).amount(30_000_000).listed();
+      await env.fund(invoice).by(investor);
+      await env.raiseDispute(invoice, business);
+      await env.resolveDispute(invoice, { defaulted: {} });
+
+      await program.methods
+        .registerCollectionsAgency(agency.publicKey, 1_500)
+        .accountsPartial({ collectionsAgency, globalState, adminLog: await adminLog(), authority: authority.publicKey, payer: authority.publicKey })
+        .rpc();
+      await program.methods
+        .assignCollections()
+        .accountsPartial({
+          invoice,
+          collectionsAgency,
+          collectionsAssignment: env.pda([Buffer.from("collections"), invoice.toBuffer()]),
+          globalState,
+          authority: authority.publicKey,
+          payer: authority.publicKey,
+        })
+        .rpc();
+
+      const agencyUsdc = await env.usdcAccount(agency.publicKey);
+      await env.settleRecovery(invoice, 10_000_000, business, agency.publicKey);
+      assert.equal((await getAccount(provider.connection, agencyUsdc)).amount, BigInt(1_500_000));
+      const settled = await program.account.invoice.fetch(invoice);
+      assert.equal(settled.recoveredAmount.toNumber(), 8_500_000);
+      const stats = await program.methods.getCollectionsAgencyStats().accountsPartial({ collectionsAgency }).view();
+      assert.equal(stats.recoveredTotal.toNumber(), 10_000_000);
+      assert.equal(stats.feesEarned.toNumber(), 1_500_000);
+    });
+
+    it("claws an insurance payout back before the investor is paid", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).amount(30_000_000).listed();
+      await env.fund(invoice).by(investor);
+      await env.raiseDispute(invoice, business);
+      await env.resolveDispute(invoice, { defaulted: {} });
+      await env.claimInsurance(invoice, investor);
+
+      const claimed = await program.account.invoice.fetch(invoice);
+      const payout = claimed.insurancePayout.toNumber();
+      const uncovered =
+        claimed.remainingBalance.toNumber() - payout + claimed.expectedReturn.sub(claimed.fundedAmount).toNumber();
+      assert.isAbove(uncovered, 0);
+      // A pool that covers every claim pays this one in full
+      assert.equal(claimed.insurancePayoutOutstanding.toNumber(), 0);
+      await expectError(env.claimRemainingInsurance(invoice), "NoInsuranceOutstanding");
+      const poolBefore = (await program.account.globalState.fetch(globalState)).insurancePoolBalance;
+      const investorBefore = (await getAccount(provider.connection, investor.usdc)).amount;
+
+      // Smaller than the payout, then up to it exactly: the pool only
+      await env.settleRecovery(invoice, 1_000_000, business);
+      await env.settleRecovery(invoice, payout - 1_000_000, business);
+      let settled = await program.account.invoice.fetch(invoice);
+      assert.equal(settled.poolRecovered.toNumber(), payout);
+      assert.deepEqual(settled.status, { defaulted: {} });
+      assert.equal((await getAccount(provider.connection, investor.usdc)).amount, investorBefore);
+
+      // Larger than what is left: the investor's uncovered principal and yield, excess kept
+      await env.settleRecovery(invoice, uncovered + 5_000_000, business);
+      settled = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(settled.status, { repaidAfterDefault: {} });
+      assert.equal(settled.recoveredAmount.toNumber(), payout + uncovered);
+      assert.equal((await getAccount(provider.connection, investor.usdc)).amount, investorBefore + BigInt(uncovered));
+      const { insurancePoolBalance } = await program.account.globalState.fetch(globalState);
+      assert.equal(insurancePoolBalance.sub(poolBefore).toNumber(), payout);
+      await expectError(env.settleRecovery(invoice, 1_000_000, business), "InvoiceNotDefaulted");
+    });
+  });
+
+  describe("business-paid premium", () => {
+    const setPremiumPayer = async (businessPays: boolean) =>
+      program.methods
+        .setPremiumPayer(businessPays)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+    const poolBalance = async () => (await program.account.globalState.fetch(globalState)).insurancePoolBalance;
+
+    it("charges the business at listing and the investor only the principal", async () => {
+      const business = await env.createBusiness().withUsdc(50_000_000);
+      const investor = await env.createInvestor();
+
+      await setPremiumPayer(true);
+      let funded!: PublicKey;
+      let cancelled!: PublicKey;
+      try {
+        await expectError(env.createInvoice(business).amount(40_000_000), "BusinessAccountMissing");
+        const before = await env.tokenBalance(business.usdc);
+        const poolBefore = await poolBalance();
+        ({ invoice: funded } = await env.createInvoice(business).amount(40_000_000).prepaysPremium().listed());
+        ({ invoice: cancelled } = await env.createInvoice(business).amount(40_000_000).prepaysPremium());
+        const listed = await program.account.invoice.fetch(funded);
+        assert.isTrue(listed.premiumPrepaid);
+        assert.isAbove(listed.insurancePremium.toNumber(), 0);
+        assert.equal(before - (await env.tokenBalance(business.usdc)), listed.insurancePremium.muln(2).toNumber());
+        assert.equal((await poolBalance()).sub(poolBefore).toString(), listed.insurancePremium.muln(2).toString());
+      } finally {
+        await setPremiumPayer(false);
+      }
+
+      // The flag only applies at listing: funding moves the principal alone
+      const investorBefore = await env.tokenBalance(investor.usdc);
+      await env.fund(funded).by(investor);
+      assert.equal(investorBefore - (await env.tokenBalance(investor.usdc)), 40_000_000);
+
+      // Cancelling refunds the prepaid premium
+      const { insurancePremium } = await program.account.invoice.fetch(cancelled);
+      const before = await env.tokenBalance(business.usdc);
+      await program.methods
+        .cancelInvoice()
+        .accountsPartial({
+          invoice: cancelled,
+          globalState,
+          businessOwner: business.publicKey,
+          businessTokenAccount: business.usdc,
+          insurancePoolAccount: insurancePool,
+          insurancePoolAuthority,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([business.keypair])
+        .rpc();
+      assert.equal((await env.tokenBalance(business.usdc)) - before, insurancePremium.toNumber());
+    });
+
+    it("keeps charging the investor by default", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).amount(40_000_000).listed();
+      const { insurancePremium, premiumPrepaid } = await program.account.invoice.fetch(invoice);
+      assert.isFalse(premiumPrepaid);
+
+      const before = await env.tokenBalance(investor.usdc);
+      await env.fund(invoice).by(investor);
+      assert.equal(before - (await env.tokenBalance(investor.usdc)), insurancePremium.addn(40_000_000).toNumber());
+    });
+  });
+
+  describe("investment pools", () => {
+
+    it("funds invoices out of pooled deposits and pays settlements back to share holders", async () => {
+      const manager = await env.createParty();
+      const [early, late] = [await env.createInvestor(), await env.createInvestor()];
+      const pool = await env.createInvestmentPool(manager);
+      const vault = env.poolVault(pool);
+      const sharesOf = async (holder: Party) =>
+        env.tokenBalance(getAssociatedTokenAddressSync(env.poolSharesPda(pool), holder.publicKey));
+
+      await env.depositToPool(pool, early, 100_000_000);
+      assert.equal(await sharesOf(early), 100_000_000);
+
+      const business = await env.createBusiness();
+      const { invoice } = await env.createInvoice(business).amount(30_000_000).listed();
+      await expectError(env.settlePoolPosition(pool, invoice), "AccountNotInitialized");
+      await env.poolFundInvoice(pool, manager, invoice);
+      const funded = await program.account.invoice.fetch(invoice);
+      assert.isTrue(funded.investor.equals(env.poolAuthorityPda(pool)));
+      const cost = funded.fundedAmount.add(funded.insurancePremium).toNumber();
+      let state = await program.account.investmentPool.fetch(pool);
+      assert.equal(state.deployed.toNumber(), cost);
+      assert.equal(await env.tokenBalance(vault), 100_000_000 - cost);
+
+      // The position is held at cost, so a share is still worth one USDC
+      await env.depositToPool(pool, late, 10_000_000);
+      assert.equal(await sharesOf(late), 10_000_000);
+      await expectError(env.redeemFromPool(pool, early, 100_000_000), "PoolIlliquid");
+      await expectError(env.settlePoolPosition(pool, invoice), "PoolPositionOpen");
+
+      await env.raiseDispute(invoice, business);
+      await env.resolveDispute(invoice, { defaulted: {} });
+      await env.settlePoolPosition(pool, invoice);
+      const { insurancePayout } = await program.account.invoice.fetch(invoice);
+      state = await program.account.investmentPool.fetch(pool);
+      assert.equal(state.deployed.toNumber(), 0);
+      assert.equal(state.openPositions, 0);
+      assert.isNull(await provider.connection.getAccountInfo(env.poolPositionPda(pool, invoice)));
+      const nav = 110_000_000 - cost + insurancePayout.toNumber();
+      assert.equal(await env.tokenBalance(vault), nav);
+
+      // Shares redeem for their slice of what came back
+      const before = await env.tokenBalance(early.usdc);
+      await env.redeemFromPool(pool, early, 50_000_000);
+      assert.equal((await env.tokenBalance(early.usdc)) - before, Math.floor((50_000_000 * nav) / 110_000_000));
+    });
+
+    it("keeps invoices scored above its mandate out of the pool", async () => {
+      const manager = await env.createParty();
+      const pool = await env.createInvestmentPool(manager, 0);
+      await env.depositToPool(pool, await env.createInvestor(), 50_000_000);
+
+      const { invoice } = await env.createInvoice(await env.createBusiness()).amount(30_000_000).listed();
+      assert.isAbove((await program.account.invoice.fetch(invoice)).riskScore, 0);
+      await expectError(env.poolFundInvoice(pool, manager, invoice), "PoolMandateExceeded");
+    });
+  });
+
+  describe("auto-invest mandates", () => {
+    const mandateOf = (investor: Party) => env.autoInvestMandatePda(investor.publicKey);
+    const manage = (investor: Party) => ({ mandate: mandateOf(investor), investor: investor.publicKey });
+
+    it("funds a matching invoice out of the allowance without the investor signing", async () => {
+      const [investor, cranker] = [await env.createInvestor(), await env.createParty()];
+      await env.createAutoInvestMandate(investor, 100_000_000);
+      const business = await env.createBusiness();
+      const { invoice } = await env.createInvoice(business).amount(30_000_000).listed();
+
+      await env.executeAutoInvest(invoice, investor.publicKey, cranker);
+      const funded = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(funded.status, { funded: {} });
+      assert.isTrue(funded.investor.equals(investor.publicKey));
+      const account = await getAccount(provider.connection, investor.usdc);
+      assert.equal(account.delegatedAmount, BigInt(100_000_000 - 30_000_000 - funded.insurancePremium.toNumber()));
+      assert.equal((await getAccount(provider.connection, env.receiptAccount(invoice, investor.publicKey))).amount, BigInt(1));
+      const mandate = await program.account.autoInvestMandate.fetch(mandateOf(investor));
+      assert.equal(mandate.invoicesFunded, 1);
+      assert.equal(mandate.totalFunded.toNumber(), 30_000_000);
+    });
+
+    it("skips invoices outside the mandate and stops once paused or revoked", async () => {
+      const [investor, cranker] = [await env.createInvestor(), await env.createParty()];
+      await env.createAutoInvestMandate(investor, 100_000_000, { exposureCap: new anchor.BN(40_000_000) });
+      const business = await env.createBusiness();
+      const { invoice: large } = await env.createInvoice(business).amount(60_000_000).listed();
+      await expectError(env.executeAutoInvest(large, investor.publicKey, cranker), "AutoInvestMismatch");
+
+      const { invoice: first } = await env.createInvoice(business).amount(30_000_000).listed();
+      const { invoice: second } = await env.createInvoice(business).amount(20_000_000).listed();
+      await env.executeAutoInvest(first, investor.publicKey, cranker);
+      await expectError(env.executeAutoInvest(second, investor.publicKey, cranker), "AutoInvestExposureExceeded");
+
+      await program.methods.setAutoInvestPaused(true).accountsPartial(manage(investor)).signers([investor.keypair]).rpc();
+      await expectError(env.executeAutoInvest(second, investor.publicKey, cranker), "AutoInvestPaused");
+
+      await program.methods
+        .revokeAutoInvestMandate()
+        .accountsPartial({ ...manage(investor), investorTokenAccount: investor.usdc })
+        .signers([investor.keypair])
+        .rpc();
+      assert.isNull(await provider.connection.getAccountInfo(mandateOf(investor)));
+      assert.isNull((await getAccount(provider.connection, investor.usdc)).delegate);
+    });
+  });
+
+  describe("batch repayment", () => {
+    it("repays several invoices in one transaction", async () => {
+      const investor = await env.createInvestor();
+      const business = await env.createBusiness().withUsdc(10_000_000);
+      const { invoice: first } = await env.createInvoice(business).amount(30_000_000).listed();
+      const { invoice: second } = await env.createInvoice(business).amount(20_000_000).listed();
+      await env.fund(first).by(investor);
+      await env.fund(second).by(investor);
+
+      await expectError(env.repayInvoicesBatch(business, [first, second], 1), "SlippageExceeded");
+      await env.repayInvoicesBatch(business, [first, second], 100_000_000);
+      for (const invoice of [first, second]) {
+        const repaid = await program.account.invoice.fetch(invoice);
+        assert.deepEqual(repaid.status, { repaid: {} });
+        assert.equal(repaid.remainingBalance.toNumber(), 0);
+      }
+      const stats = await program.account.investorStats.fetch(env.investorStatsPda(investor.publicKey));
+      assert.equal(stats.outstanding.toNumber(), 0);
+    });
+
+    it("stops the whole batch at an invoice the signer does not own", async () => {
+      const investor = await env.createInvestor();
+      const [business, other] = [await env.createBusiness().withUsdc(10_000_000), await env.createBusiness()];
+      const { invoice: own } = await env.createInvoice(business).amount(10_000_000).listed();
+      const { invoice: foreign } = await env.createInvoice(other).amount(10_000_000).listed();
+      await env.fund(own).by(investor);
+      await env.fund(foreign).by(investor);
+
+      await expectError(env.repayInvoicesBatch(business, [own, foreign], 100_000_000), "RepayerNotBusinessOwner");
+      assert.deepEqual((await program.account.invoice.fetch(own)).status, { funded: {} });
+    });
+  });
+
+  describe("invoice closing", () => {
+    const close = (invoice: PublicKey, business: Party) =>
+      program.methods
+        .closeInvoice()
+        .accountsPartial({ invoice, globalState, businessOwner: business.publicKey, invoiceVault: null })
+        .signers([business.keypair])
+        .rpc();
+    const setRetention = async (secs: number) =>
+      program.methods
+        .setRetentionPeriod(new anchor.BN(secs))
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("closes a repaid invoice after the retention period and refunds its rent", async () => {
+      const investor = await env.createInvestor();
+      const business = await env.createBusiness().withUsdc(10_000_000);
+      const { invoice } = await env.createInvoice(business).amount(20_000_000).listed();
+      await env.fund(invoice).by(investor);
+      await expectError(close(invoice, business), "InvoiceNotClosable");
+
+      await env.repayInvoicesBatch(business, [invoice], 100_000_000);
+      await expectError(close(invoice, business), "RetentionPeriodActive");
+
+      await setRetention(0);
+      try {
+        await expectError(close(invoice, business), "InvoicePositionOpen");
+        await program.methods
+          .redeemReceipt()
+          .accountsPartial({
+            invoice,
+            holderReceipt: env.receiptAccount(invoice, investor.publicKey),
+            holder: investor.publicKey,
+            holderTokenAccount: investor.usdc,
+          })
+          .signers([investor.keypair])
+          .rpc();
+
+        const rent = await provider.connection.getBalance(invoice);
+        const balance = await provider.connection.getBalance(business.publicKey);
+        await close(invoice, business);
+        assert.isNull(await program.account.invoice.fetchNullable(invoice));
+        assert.isAbove(await provider.connection.getBalance(business.publicKey), balance + rent - 10_000);
+      } finally {
+        await setRetention(30 * DAY);
+      }
+    });
+
+    it("refunds a sponsored invoice's rent to the platform that paid it", async () => {
+      const business = await env.createBusiness();
+      const platform = Keypair.generate();
+      await airdrop(platform.publicKey);
+      const { invoice } = await env.createInvoice(business).sponsoredBy(platform);
+      const account = await program.account.invoice.fetch(invoice);
+      assert.ok(account.rentPayer.equals(platform.publicKey));
+      const cancel = (rentPayer: PublicKey) =>
+        program.methods
+          .cancelInvoice()
+          .accountsPartial({ invoice, globalState, businessOwner: business.publicKey, rentPayer })
+          .signers([business.keypair])
+          .rpc();
+
+      await expectError(cancel(business.publicKey), "RentPayerMismatch");
+      const rent = await provider.connection.getBalance(invoice);
+      const balance = await provider.connection.getBalance(platform.publicKey);
+      await cancel(platform.publicKey);
+      assert.equal(await provider.connection.getBalance(platform.publicKey), balance + rent);
+    });
+  });
+
+  describe("event context", () => {
+    it("names the invoice, both counterparties and the time on every lifecycle event", async () => {
+      const investor = await env.createInvestor();
+      const business = await env.createBusiness().withUsdc(10_000_000);
+      const { invoice } = await env.createInvoice(business).amount(20_000_000).listed();
+
+      // InvoiceFunded and InvoiceRepaid go out through the event authority
+      const events: Record<string, any> = {};
+      const listener = program.addEventListener("repaymentReceived", (event) => (events.repaymentReceived = event));
+      const fundedThrough = await env.fund(invoice).by(investor);
+      const repaidThrough = await env.repayInvoicesBatch(business, [invoice], 100_000_000);
+      for (const { name, data } of [...(await env.cpiEvents(fundedThrough)), ...(await env.cpiEvents(repaidThrough))]) {
+        events[name] = data;
+      }
+      await program.removeEventListener(listener);
+
+      for (const name of ["invoiceFunded", "repaymentReceived", "invoiceRepaid"]) {
+        const event = events[name];
+        assert.ok(event, name);
+        assert.equal(event.invoice.toBase58(), invoice.toBase58(), name);
+        assert.equal(event.businessOwner.toBase58(), business.publicKey.toBase58(), name);
+        assert.equal(event.investor.toBase58(), investor.publicKey.toBase58(), name);
+        assert.isAtMost(Math.abs(event.timestamp.toNumber() - now()), 60, name);
+      }
+      assert.deepEqual(events.repaymentReceived.status, { repaid: {} });
+    });
+  });
+
+  describe("overdue pings", () => {
+    it("signals a past-due invoice once a day to anyone who asks", async () => {
+      const investor = await env.createInvestor();
+      const business = await env.createBusiness();
+      const { invoice } = await env.createInvoice(business).amount(20_000_000).dueInSeconds(5).listed();
+      const ping = () => program.methods.pingOverdue().accountsPartial({ invoice, globalState }).rpc();
+
+      await expectError(ping(), "InvoiceNotFunded");
+      await env.fund(invoice).by(investor);
+      await expectError(ping(), "InvoiceNotOverdue");
+
+      await env.warpTo(invoice, "due");
+      let event: any;
+      const listener = program.addEventListener("invoiceOverdue", (e) => (event = e));
+      await ping();
+      await program.removeEventListener(listener);
+      assert.ok(event.invoice.equals(invoice));
+      assert.equal(event.daysOverdue.toNumber(), 0);
+      assert.equal(event.accruedLateFee.toNumber(), 0);
+      const { lastOverduePing } = await program.account.invoice.fetch(invoice);
+      assert.equal(lastOverduePing.toNumber(), event.timestamp.toNumber());
+
+      await expectError(ping(), "OverduePingTooSoon");
+    });
+  });
+
+  describe("funding deadline", () => {
+    it("closes funding at the deadline and lets anyone expire the listing", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+
+      // By default funding closes a day before the due date
+      const { invoice: standard } = await env.createInvoice(business).tenorDays(10);
+      const { dueDate, fundingDeadline } = await program.account.invoice.fetch(standard);
+      assert.equal(fundingDeadline.toNumber(), dueDate.toNumber() - DAY);
+      await expectError(
+        env.createInvoice(business).tenorDays(10).fundingDeadline(now() + 11 * DAY),
+        "InvalidFundingDeadline"
+      );
+
+      const deadline = now() + 4;
+      const { invoice: funded } = await env.createInvoice(business).amount(10_000_000).fundingDeadline(deadline);
+      const { invoice: stale } = await env.createInvoice(business).amount(10_000_000).fundingDeadline(deadline);
+      await expectError(env.expireInvoice(stale), "FundingWindowOpen");
+      // The deadline itself is still open (the exact second is covered by the unit tests)
+      await env.fund(funded).by(investor);
+
+      await sleep((deadline - now() + 2) * 1000);
+      await expectError(env.fund(stale).by(investor), "FundingWindowClosed");
+
+      let expired: any = null;
+      const listener = program.addEventListener("invoiceExpired", (event) => (expired = event));
+      try {
+        await env.expireInvoice(stale);
+        await sleep(1_000);
+      } finally {
+        await program.removeEventListener(listener);
+      }
+      assert.ok(expired.invoice.equals(stale));
+      assert.equal(expired.fundingDeadline.toNumber(), deadline);
+      assert.deepEqual((await program.account.invoice.fetch(stale)).status, { expired: {} });
+      await expectError(env.expireInvoice(stale), "InvoiceNotAvailable");
+      await expectError(env.expireInvoice(funded), "InvoiceNotAvailable");
+
+      // The business cancels the expired listing and lists the receivable again
+      await program.methods
+        .cancelInvoice()
+        .accountsPartial({ invoice: stale, globalState, businessOwner: business.publicKey })
+        .signers([business.keypair])
+        .rpc();
+      assert.isNull(await program.account.invoice.fetchNullable(stale));
+      const { invoice: relisted } = await env.createInvoice(business).amount(10_000_000);
+      await env.fund(relisted).by(investor);
+      assert.deepEqual((await program.account.invoice.fetch(relisted)).status, { funded: {} });
+    });
+  });
+
+  describe("coverage capacity", () => {
+    const setCapacity = async (capacityBps: number) =>
+      program.methods
+        .setCoverageCapacity(capacityBps)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("rejects funding that would stretch the pool past its capacity", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).amount(100_000_000);
+
+      await expectError(setCapacity(5_000), "InvalidCoverageCapacity");
+
+      // Set the cap between the pool's utilization now and after this funding
+      const { insuredExposure, insurancePoolBalance } = await program.account.globalState.fetch(globalState);
+      const { insurancePremium, coveragePercentage } = await program.account.invoice.fetch(invoice);
+      const exposure = insuredExposure.toNumber();
+      const pool = insurancePoolBalance.toNumber();
+      const before = Math.floor((exposure * 10_000) / pool);
+      const after = Math.floor(((exposure + coveragePercentage * 1_000_000) * 10_000) / (pool + insurancePremium.toNumber()));
+      assert.isAbove(after, Math.max(before, 10_000), "the pool is too deep to reach a cap with one funding");
+      const capacity = Math.max(10_000, Math.ceil((before + after) / 2));
+
+      let funded: any = null;
+      try {
+        await setCapacity(capacity);
+        await expectError(env.fund(invoice).by(investor), "CoverageCapacityExceeded");
+        await setCapacity(0);
+        [{ data: funded }] = await env.cpiEvents(await env.fund(invoice).by(investor));
+      } finally {
+        await setCapacity(0);
+      }
+      assert.ok(funded.invoice.equals(invoice));
+      assert.equal(funded.coverageUtilizationBps.toNumber(), after);
+    });
+  });
+
+  describe("originator fees", () => {
+    const setFeePolicy = async (capBps: number, fromPrincipal: boolean) =>
+      program.methods
+        .setOriginatorFeePolicy(capBps, fromPrincipal)
+        .accountsPartial({ globalState, adminLog: await adminLog(), authority: authority.publicKey })
+        .rpc();
+
+    it("pays the originator from the side the policy names", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const platform = await env.createParty();
+      const stranger = await env.createParty();
+      await env.registerOriginator(platform);
+      await expectError(setFeePolicy(5_000, false), "InvalidOriginatorFeeCap");
+
+      try {
+        await setFeePolicy(200, false);
+        await expectError(env.createInvoice(business).originator(platform.publicKey, 300), "OriginatorFeeTooHigh");
+        await expectError(env.createInvoice(business).originator(stranger.publicKey, 100), "AccountNotInitialized");
+        await expectError(
+          env.createInvoice(business).originator(platform.publicKey, 100).partial(),
+          "OriginationUnsupported"
+        );
+
+        // On top of the investor's cost
+        const { invoice: onTop } = await env
+          .createInvoice(business)
+          .amount(100_000_000)
+          .originator(platform.publicKey, 150);
+        const businessBefore = await env.tokenBalance(business.usdc);
+        const [{ data: funded }] = await env.cpiEvents(await env.fund(onTop).by(investor));
+        assert.equal(await env.tokenBalance(platform.usdc), 1_500_000);
+        assert.equal((await env.tokenBalance(business.usdc)) - businessBefore, 100_000_000);
+        assert.ok(funded.invoice.equals(onTop));
+        assert.equal(funded.originatorFee.toNumber(), 1_500_000);
+
+        // Out of the principal, for invoices listed after the switch
+        await setFeePolicy(200, true);
+        const { invoice: fromPrincipal } = await env
+          .createInvoice(business)
+          .amount(100_000_000)
+          .originator(platform.publicKey, 100);
+        const before = await env.tokenBalance(business.usdc);
+        await env.fund(fromPrincipal).by(investor);
+        assert.equal(await env.tokenBalance(platform.usdc), 2_500_000);
+        assert.equal((await env.tokenBalance(business.usdc)) - before, 99_000_000);
+        assert.equal((await program.account.invoice.fetch(fromPrincipal)).originatorFeePaid.toNumber(), 1_000_000);
+      } finally {
+        await setFeePolicy(0, false);
+      }
+
+      const stats = await program.account.originatorStats.fetch(env.originatorStatsPda(platform.publicKey));
+      assert.equal(stats.invoicesOriginated.toNumber(), 2);
+      assert.equal(stats.invoicesFunded.toNumber(), 2);
+      assert.equal(stats.volumeFunded.toNumber(), 200_000_000);
+      assert.equal(stats.feesEarned.toNumber(), 2_500_000);
+    });
+  });
+
+  describe("debtor info commitment", () => {
+    it("commits to the invoice document by hash, with an off-chain pointer", async () => {
+      const business = await env.createBusiness();
+      const document = "Acme Corp, net 45 invoice #42";
+      const uri = "ar://bNbA3TEQVL60xlgCcqdz4ZPHFZ711cZ3hmkpGttDt_U";
+
+      await expectError(env.createInvoice(business).debtorInfoUri("x".repeat(65)), "DebtorInfoTooLong");
+      const { invoice } = await env.createInvoice(business).debtor(document).debtorInfoUri(uri);
+      const account = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(account.debtorInfoHash, debtorInfoHash(document));
+      assert.equal(account.debtorInfoUri, uri);
+      assert.equal(account.version, 6);
+
+      const verify = (preimage: string) =>
+        program.methods.verifyDebtorInfo(Buffer.from(preimage)).accountsPartial({ invoice }).view();
+      assert.isTrue(await verify(document));
+      assert.isFalse(await verify("Acme Corp, net 30 invoice #42"));
+    });
+  });
+
+  describe("account versioning", () => {
+    it("leaves accounts already on the current version as they are", async () => {
+      const business = await env.createBusiness();
+      const { invoice } = await env.createInvoice(business);
+      const before = await provider.connection.getAccountInfo(invoice);
+
+      // Permissionless, and a no-op as often as it is run
+      const stranger = await env.createParty();
+      for (let i = 0; i < 2; i++) {
+        await program.methods
+          .migrateInvoice()
+          .accountsPartial({ invoice, businessOwner: business.publicKey, payer: stranger.publicKey })
+          .signers([stranger.keypair])
+          .rpc();
+        await program.methods
+          .migrateGlobalState()
+          .accountsPartial({ globalState, authority: authority.publicKey, payer: stranger.publicKey })
+          .signers([stranger.keypair])
+          .rpc();
+      }
+
+      const after = await provider.connection.getAccountInfo(invoice);
+      assert.isTrue(after.data.equals(before.data));
+      assert.equal(after.lamports, before.lamports);
+      assert.equal((await program.account.invoice.fetch(invoice)).version, 6);
+      assert.equal((await program.account.globalState.fetch(globalState)).version, 3);
+    });
+
+    it("keeps the filter fields at fixed offsets for getProgramAccounts", async () => {
+      // INVOICE_BUSINESS_OWNER_OFFSET and INVOICE_STATUS_OFFSET
+      const businessOwnerAt = 16;
+      const statusAt = 105;
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const { invoice: funded } = await env.createInvoice(business);
+      const { invoice: listed } = await env.createInvoice(business);
+      await env.fund(funded).by(investor);
+
+      const pending = await program.account.invoice.all([
+        { memcmp: { offset: businessOwnerAt, bytes: business.publicKey.toBase58() } },
+        { memcmp: { offset: statusAt, bytes: anchor.utils.bytes.bs58.encode([0]) } },
+      ]);
+      assert.deepEqual(pending.map(({ publicKey }) => publicKey.toBase58()), [listed.toBase58()]);
+    });
+
+    it("checks the authority passed in against the global state", async () => {
+      const stranger = await env.createParty();
+      await expectError(
+        program.methods
+          .migrateGlobalState()
+          .accountsPartial({ globalState, authority: stranger.publicKey, payer: stranger.publicKey })
+          .signers([stranger.keypair])
+          .rpc(),
+        "Unauthorized"
+      );
+    });
+  });
+
+  describe("invoice amendments", () => {
+    const amend = (invoice: PublicKey, business: Party, amount: number, dueAt: number, document = "Acme Corp") =>
+      program.methods
+        .amendInvoice(new anchor.BN(amount), new anchor.BN(dueAt), debtorInfoHash(document), null, null)
+        .accountsPartial({
+          invoice,
+          globalState,
+          businessOwner: business.publicKey,
+          experiment: null,
+          microTier: null,
+          instructions: null,
+        })
+        .signers([business.keypair]);
+
+    it("reprices an unfunded invoice and refuses funding on the terms it replaced", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).amount(10_000_000).tenorDays(30);
+      const listed = await program.account.invoice.fetch(invoice);
+
+      // Only its owner amends it, and only within the listing rules
+      const stranger = await env.createBusiness();
+      await env.createInvoice(stranger);
+      await expectError(amend(invoice, stranger, 60_000_000, listed.dueDate.toNumber()).rpc(), "InvoiceOwnerMismatch");
+      await expectError(amend(invoice, business, 0, listed.dueDate.toNumber()).rpc(), "InvalidAmount");
+
+      const events: any[] = [];
+      const listener = program.addEventListener("invoiceAmended", (event) => events.push(event));
+      await amend(invoice, business, 60_000_000, listed.dueDate.toNumber(), "Acme Corp, corrected").rpc();
+      await program.removeEventListener(listener);
+
+      const amended = await program.account.invoice.fetch(invoice);
+      assert.equal(amended.amount.toNumber(), 60_000_000);
+      assert.deepEqual(amended.debtorInfoHash, debtorInfoHash("Acme Corp, corrected"));
+      assert.isAbove(amended.riskScore, listed.riskScore);
+      assert.isTrue(amended.insurancePremium.gt(listed.insurancePremium));
+      assert.lengthOf(events, 1);
+      assert.equal(events[0].oldAmount.toNumber(), 10_000_000);
+      assert.equal(events[0].amount.toNumber(), 60_000_000);
+      assert.equal(events[0].oldRiskScore, listed.riskScore);
+      assert.equal(events[0].riskScore, amended.riskScore);
+      assert.equal(events[0].invoice.toBase58(), invoice.toBase58());
+
+      // An investor still quoting the listed terms is turned away
+      await expectError(
+        env.fund(invoice).by(investor).withAmount(10_000_000).expectingRiskScore(amended.riskScore),
+        "InvalidFundingAmount"
+      );
+      await expectError(env.fund(invoice).by(investor).expectingRiskScore(listed.riskScore), "InvoiceTermsChanged");
+      await env.fund(invoice).by(investor);
+
+      // Funded terms are fixed
+      await expectError(amend(invoice, business, 20_000_000, listed.dueDate.toNumber()).rpc(), "InvoiceNotAvailable");
+    });
+
+    it("turns away a funding signed before the due date moved", async () => {
+      const business = await env.createBusiness();
+      const investor = await env.createInvestor();
+      const { invoice } = await env.createInvoice(business).tenorDays(30).listed();
+      const listed = await program.account.invoice.fetch(invoice);
+
+      await amend(invoice, business, listed.amount.toNumber(), listed.dueDate.toNumber() + 30 * DAY).rpc();
+      const amended = await program.account.invoice.fetch(invoice);
+      // Everything else the investor checks may still match
+      await expectError(
+        env
+          .fund(invoice)
+          .by(investor)
+          .expectingRiskScore(amended.riskScore)
+          .withMaxPremium(amended.insurancePremium)
+          .expectingDueDate(listed.dueDate),
+        "InvoiceTermsChanged"
+      );
+      await env.fund(invoice).by(investor);
+      assert.ok((await program.account.invoice.fetch(invoice)).investor.equals(investor.publicKey));
+    });
+
+    it("keeps the funding deadline before the new due date", async () => {
+      const business = await env.createBusiness();
+      const { invoice } = await env.createInvoice(business).tenorDays(30).fundingDeadline(now() + 20 * DAY);
+      await expectError(amend(invoice, business, 100_000_000, now() + 10 * DAY).rpc(), "InvalidFundingDeadline");
+      await amend(invoice, business, 100_000_000, now() + 60 * DAY).rpc();
+      assert.isAtLeast((await program.account.invoice.fetch(invoice)).paymentTermsDays, 59);
+    });
   });
 });